@@ -0,0 +1,276 @@
+//! インストール済み Gem と現在の Ruby との互換性チェック。
+//!
+//! Ruby のバージョンを切り替えた後や、別の API バージョン向けにビルドされた Gem を
+//! キャッシュから復元した後は、ネイティブ拡張が読み込めなくなる。
+//! `extensions/<platform>/<abi>/<gem>` の `<abi>` と現在の API バージョンを比べ、
+//! 現在の API 向けのビルドがない Gem を不一致として報告する。
+//! あわせて gemspec の `required_ruby_version` も確認する。
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::version_req::Constraint;
+
+/// 不一致の理由。
+#[derive(Debug, Clone, PartialEq)]
+pub enum MismatchKind {
+    /// ネイティブ拡張が別の API バージョン向けにしかビルドされていない
+    Abi { built_for: Vec<String> },
+    /// gemspec の `required_ruby_version` を満たさない
+    RequiredRuby { requirement: String },
+}
+
+/// 現在の Ruby と互換性のない Gem。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    /// `gems/` 直下のディレクトリ名 (例: `nokogiri-1.16.0`)
+    pub gem: String,
+    pub kind: MismatchKind,
+}
+
+impl Mismatch {
+    /// ネイティブ拡張の再ビルドで解消できるか
+    pub fn rebuildable(&self) -> bool {
+        matches!(self.kind, MismatchKind::Abi { .. })
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            MismatchKind::Abi { built_for } => {
+                format!("{} (native extension built for {})", self.gem, built_for.join(", "))
+            }
+            MismatchKind::RequiredRuby { requirement } => {
+                format!("{} (requires ruby {})", self.gem, requirement)
+            }
+        }
+    }
+}
+
+/// `gem_base` (`.arc/env/ruby/<api>`) 以下を調べ、Gem 名順に不一致を返す。
+pub fn scan(gem_base: &Path, api_version: &str, ruby_version: &str) -> Vec<Mismatch> {
+    let mut mismatches: Vec<Mismatch> = abi_mismatches(gem_base, api_version)
+        .into_iter()
+        .map(|(gem, built_for)| Mismatch { gem, kind: MismatchKind::Abi { built_for } })
+        .collect();
+    mismatches.extend(required_ruby_mismatches(gem_base, ruby_version));
+    mismatches.sort_by(|a, b| a.gem.cmp(&b.gem));
+    mismatches
+}
+
+/// 現在の API 向けのビルドがなく、別の API 向けのビルドだけがある Gem。
+/// 古い API 向けのディレクトリが残っていても、現在の API 向けがあれば問題にしない。
+fn abi_mismatches(gem_base: &Path, api_version: &str) -> BTreeMap<String, Vec<String>> {
+    let mut current: BTreeSet<String> = BTreeSet::new();
+    let mut others: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for platform in read_dir_names(&gem_base.join("extensions")) {
+        let platform_dir = gem_base.join("extensions").join(&platform);
+        for abi in read_dir_names(&platform_dir) {
+            // `3.3.0-static` のような接尾辞は同じ API とみなす
+            let matches = abi.split('-').next() == Some(api_version);
+            for gem in read_dir_names(&platform_dir.join(&abi)) {
+                if matches {
+                    current.insert(gem);
+                } else {
+                    let built_for = others.entry(gem).or_default();
+                    if !built_for.contains(&abi) {
+                        built_for.push(abi.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    others.retain(|gem, _| !current.contains(gem));
+    for built_for in others.values_mut() {
+        built_for.sort();
+    }
+    others
+}
+
+fn required_ruby_mismatches(gem_base: &Path, ruby_version: &str) -> Vec<Mismatch> {
+    read_dir_names(&gem_base.join("specifications"))
+        .into_iter()
+        .filter_map(|file| {
+            let gem = file.strip_suffix(".gemspec")?.to_string();
+            let spec = fs::read_to_string(gem_base.join("specifications").join(&file)).ok()?;
+            let constraints = required_ruby_version(&spec)?;
+            if constraints.iter().all(|c| satisfies(ruby_version, c)) {
+                return None;
+            }
+            Some(Mismatch { gem, kind: MismatchKind::RequiredRuby { requirement: constraints.join(", ") } })
+        })
+        .collect()
+}
+
+fn read_dir_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_dir() || e.path().extension().is_some_and(|x| x == "gemspec"))
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+// ─────────────────────────────────────────────
+// required_ruby_version
+// ─────────────────────────────────────────────
+
+/// gemspec から `required_ruby_version` の制約 (例: `[">= 2.7", "< 3.4.dev"]`) を取り出す。
+/// `Gem::Requirement.new(">= 2.7".freeze)` と配列の両方の書き方に対応する。
+pub fn required_ruby_version(spec: &str) -> Option<Vec<String>> {
+    let line = spec.lines().find(|l| l.contains(".required_ruby_version"))?;
+    let rhs = line.split_once('=')?.1;
+    let constraints: Vec<String> = rhs
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    (!constraints.is_empty()).then_some(constraints)
+}
+
+/// `version` が制約 1 つ (`>= 3.0` / `~> 3.1` / `3.3.0` など) を満たすか。
+/// 解釈できない制約は満たすものとして扱う (誤検出で再ビルドさせないため)。
+fn satisfies(version: &str, constraint: &str) -> bool {
+    Constraint::parse(constraint).map_or(true, |c| c.satisfied_by(version))
+}
+
+// ─────────────────────────────────────────────
+// 再ビルド
+// ─────────────────────────────────────────────
+
+/// bundler が再インストールするよう、指定した Gem の拡張・gemspec・本体を取り除く。
+/// 取り除いた Gem の数を返す。
+pub fn remove_for_rebuild(gem_base: &Path, gems: &[&str]) -> Result<usize> {
+    let mut removed = 0;
+    for gem in gems {
+        for platform in read_dir_names(&gem_base.join("extensions")) {
+            let platform_dir = gem_base.join("extensions").join(&platform);
+            for abi in read_dir_names(&platform_dir) {
+                remove_if_exists(&platform_dir.join(&abi).join(gem))?;
+            }
+        }
+        remove_if_exists(&gem_base.join("specifications").join(format!("{}.gemspec", gem)))?;
+        remove_if_exists(&gem_base.join("gems").join(gem))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        crate::link::remove_tree(path)?;
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// `.arc/env/ruby/3.3.0` 相当のフィクスチャ
+    fn fixture(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&base);
+        let ext = base.join("extensions").join("x86_64-linux");
+        // 現在の API 向けのビルドがある
+        fs::create_dir_all(ext.join("3.3.0").join("json-2.7.1")).unwrap();
+        // 古い API 向けのビルドだけがある
+        fs::create_dir_all(ext.join("3.2.0").join("nokogiri-1.16.0")).unwrap();
+        fs::create_dir_all(ext.join("3.1.0").join("nokogiri-1.16.0")).unwrap();
+        // 古い API 向けも残っているが、現在の API 向けもある
+        fs::create_dir_all(ext.join("3.2.0").join("bigdecimal-3.1.6")).unwrap();
+        fs::create_dir_all(ext.join("3.3.0-static").join("bigdecimal-3.1.6")).unwrap();
+
+        let specs = base.join("specifications");
+        fs::create_dir_all(&specs).unwrap();
+        fs::write(
+            specs.join("json-2.7.1.gemspec"),
+            "  s.required_ruby_version = Gem::Requirement.new(\">= 2.3\".freeze)\n",
+        )
+        .unwrap();
+        fs::write(
+            specs.join("legacy-0.1.0.gemspec"),
+            "  s.required_ruby_version = Gem::Requirement.new([\">= 2.5\".freeze, \"< 3.3.dev\".freeze])\n",
+        )
+        .unwrap();
+        fs::write(specs.join("rake-13.1.0.gemspec"), "  s.name = \"rake\".freeze\n").unwrap();
+        for gem in ["json-2.7.1", "nokogiri-1.16.0", "bigdecimal-3.1.6"] {
+            fs::create_dir_all(base.join("gems").join(gem)).unwrap();
+        }
+        fs::write(specs.join("nokogiri-1.16.0.gemspec"), "").unwrap();
+        base
+    }
+
+    #[test]
+    fn test_scan_fixture() {
+        let base = fixture("arc_abi_scan_test");
+        let mismatches = scan(&base, "3.3.0", "3.3.6");
+        assert_eq!(
+            mismatches,
+            [
+                Mismatch {
+                    gem: "legacy-0.1.0".to_string(),
+                    kind: MismatchKind::RequiredRuby { requirement: ">= 2.5, < 3.3.dev".to_string() },
+                },
+                Mismatch {
+                    gem: "nokogiri-1.16.0".to_string(),
+                    kind: MismatchKind::Abi { built_for: vec!["3.1.0".to_string(), "3.2.0".to_string()] },
+                },
+            ]
+        );
+        assert!(!mismatches[0].rebuildable());
+        assert!(mismatches[1].rebuildable());
+        assert_eq!(mismatches[1].describe(), "nokogiri-1.16.0 (native extension built for 3.1.0, 3.2.0)");
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_scan_missing_dirs() {
+        assert!(scan(&std::env::temp_dir().join("arc_abi_missing"), "3.3.0", "3.3.6").is_empty());
+    }
+
+    #[test]
+    fn test_satisfies() {
+        assert!(satisfies("3.3.6", ">= 3.0"));
+        assert!(!satisfies("2.7.8", ">= 3.0"));
+        assert!(satisfies("3.3.6", "< 3.4.dev"));
+        assert!(!satisfies("3.4.0", "< 3.4.dev"));
+        assert!(satisfies("3.3.6", "~> 3.1"));
+        assert!(!satisfies("4.0.0", "~> 3.1"));
+        assert!(satisfies("3.1.9", "~> 3.1.2"));
+        assert!(!satisfies("3.2.0", "~> 3.1.2"));
+        assert!(satisfies("3.3.0", "3.3"));
+        assert!(satisfies("3.3.6", "unparseable"));
+    }
+
+    #[test]
+    fn test_remove_for_rebuild() {
+        let base = fixture("arc_abi_rebuild_test");
+        assert_eq!(remove_for_rebuild(&base, &["nokogiri-1.16.0"]).unwrap(), 1);
+        let ext = base.join("extensions").join("x86_64-linux");
+        assert!(!ext.join("3.2.0").join("nokogiri-1.16.0").exists());
+        assert!(!ext.join("3.1.0").join("nokogiri-1.16.0").exists());
+        assert!(!base.join("specifications").join("nokogiri-1.16.0.gemspec").exists());
+        assert!(!base.join("gems").join("nokogiri-1.16.0").exists());
+        // 他の Gem には触れない
+        assert!(ext.join("3.2.0").join("bigdecimal-3.1.6").exists());
+        assert!(base.join("gems").join("json-2.7.1").exists());
+        assert!(scan(&base, "3.3.0", "3.3.6").iter().all(|m| !m.rebuildable()));
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
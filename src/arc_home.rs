@@ -0,0 +1,166 @@
+//! グローバルな arc ディレクトリ (キャッシュ・プロジェクトのレジストリ・bundler のユーザーディレクトリなど) の解決。
+//!
+//! コンテナでは `HOME` が未設定だったり `/` だったりする。以前は `/tmp` に落ちて、消えうる・誰でも書ける場所に
+//! キャッシュを置いていた。キャッシュ (`cache`) とそれ以外 (`root`) をそれぞれ次の順で決め、
+//! どれも使えなければ `ARC_HOME` の設定を促すエラーにする:
+//!
+//! 1. `ARC_HOME` (`$ARC_HOME`, キャッシュは `$ARC_HOME/cache`)
+//! 2. `XDG_CACHE_HOME` / `XDG_CONFIG_HOME` (`$XDG_CACHE_HOME/arc`, `$XDG_CONFIG_HOME/arc`)
+//! 3. `HOME` (`~/.arc`, キャッシュは `~/.arc/cache`)
+//!
+//! 空の値と相対パスは未設定とみなす (XDG Base Directory の仕様と同じ)。`HOME=/` も未設定とみなす。
+//! 解決はプロセスで 1 度だけ行い、`--verbose` のときに結果を表示する。
+
+use anyhow::{Result, anyhow};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::signals::{ARC_CACHE_DIR, ARC_GLOBAL_ROOT};
+
+/// XDG のディレクトリの下に作るディレクトリ名
+const XDG_APP_DIR: &str = "arc";
+
+/// ディレクトリをどの環境変数から決めたか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    ArcHome,
+    XdgCache,
+    XdgConfig,
+    Home,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::ArcHome => "ARC_HOME",
+            Source::XdgCache => "XDG_CACHE_HOME",
+            Source::XdgConfig => "XDG_CONFIG_HOME",
+            Source::Home => "HOME",
+        })
+    }
+}
+
+/// 解決したディレクトリと、その根拠
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub source: Source,
+}
+
+impl Location {
+    /// 表示用 (`arc env` / `arc doctor`): `/home/u/.arc (from HOME)`
+    pub fn describe(&self) -> String {
+        format!("{} (from {})", self.path.display(), self.source)
+    }
+}
+
+/// グローバルな arc ディレクトリ
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArcHome {
+    /// レジストリ (`projects.toml`)・bundler のユーザーディレクトリ・worktree 間の共有先
+    pub root: Location,
+    /// Ruby・Gem のキャッシュ
+    pub cache: Location,
+}
+
+impl ArcHome {
+    /// `var` で環境変数を引いて解決する (テストでは環境変数を差し替える)。
+    pub fn resolve(var: impl Fn(&str) -> Option<OsString>) -> Result<ArcHome> {
+        let dir = |name: &str| var(name).map(PathBuf::from).filter(|p| p.is_absolute());
+        let home = dir("HOME").filter(|p| p.parent().is_some()).map(|p| p.join(ARC_GLOBAL_ROOT));
+        let arc_home = dir("ARC_HOME");
+        let location = |path: PathBuf, source| Location { path, source };
+
+        let root = arc_home.clone().map(|p| location(p, Source::ArcHome))
+            .or_else(|| dir("XDG_CONFIG_HOME").map(|p| location(p.join(XDG_APP_DIR), Source::XdgConfig)))
+            .or_else(|| home.clone().map(|p| location(p, Source::Home)));
+        let cache = arc_home.map(|p| location(p.join(ARC_CACHE_DIR), Source::ArcHome))
+            .or_else(|| dir("XDG_CACHE_HOME").map(|p| location(p.join(XDG_APP_DIR), Source::XdgCache)))
+            .or_else(|| home.map(|p| location(p.join(ARC_CACHE_DIR), Source::Home)));
+
+        match (root, cache) {
+            (Some(root), Some(cache)) => Ok(ArcHome { root, cache }),
+            (root, _) => Err(anyhow!(
+                "cannot locate the arc {} directory: HOME is unset or '/'. Set ARC_HOME to a writable directory (e.g. ARC_HOME=/var/lib/arc){}",
+                if root.is_none() { "config" } else { "cache" },
+                if root.is_none() { " or XDG_CONFIG_HOME" } else { " or XDG_CACHE_HOME" },
+            )),
+        }
+    }
+}
+
+static RESOLVED: OnceLock<Result<ArcHome, String>> = OnceLock::new();
+
+/// プロセスの環境変数から解決した arc のディレクトリ (初回だけ解決する)
+pub fn get() -> Result<&'static ArcHome> {
+    RESOLVED
+        .get_or_init(|| {
+            let resolved = ArcHome::resolve(|name| std::env::var_os(name)).map_err(|e| e.to_string());
+            if let Ok(home) = &resolved {
+                crate::display::verbose(&format!("🏠 arc home: {}; cache: {}", home.root.describe(), home.cache.describe()));
+            }
+            resolved
+        })
+        .as_ref()
+        .map_err(|e| anyhow!("{}", e))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// `vars` だけが設定された環境で解決する
+    fn resolve(vars: &[(&str, &str)]) -> Result<ArcHome> {
+        ArcHome::resolve(|name| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| OsString::from(v)))
+    }
+
+    fn paths(home: &ArcHome) -> (&Path, Source, &Path, Source) {
+        (&home.root.path, home.root.source, &home.cache.path, home.cache.source)
+    }
+
+    #[test]
+    fn test_arc_home_wins() {
+        let home = resolve(&[("ARC_HOME", "/srv/arc"), ("XDG_CACHE_HOME", "/xdg/cache"), ("HOME", "/home/u")]).unwrap();
+        assert_eq!(paths(&home), (Path::new("/srv/arc"), Source::ArcHome, Path::new("/srv/arc/cache"), Source::ArcHome));
+    }
+
+    #[test]
+    fn test_xdg_split() {
+        let home = resolve(&[("XDG_CACHE_HOME", "/xdg/cache"), ("XDG_CONFIG_HOME", "/xdg/config"), ("HOME", "/home/u")]).unwrap();
+        assert_eq!(paths(&home), (Path::new("/xdg/config/arc"), Source::XdgConfig, Path::new("/xdg/cache/arc"), Source::XdgCache));
+        // 片方だけなら、もう片方は HOME から決める
+        let home = resolve(&[("XDG_CACHE_HOME", "/xdg/cache"), ("HOME", "/home/u")]).unwrap();
+        assert_eq!(paths(&home), (Path::new("/home/u/.arc"), Source::Home, Path::new("/xdg/cache/arc"), Source::XdgCache));
+        // 相対パスの XDG は無視する
+        let home = resolve(&[("XDG_CONFIG_HOME", "config"), ("HOME", "/home/u")]).unwrap();
+        assert_eq!(home.root.source, Source::Home);
+    }
+
+    #[test]
+    fn test_home_fallback() {
+        let home = resolve(&[("HOME", "/home/u")]).unwrap();
+        assert_eq!(paths(&home), (Path::new("/home/u/.arc"), Source::Home, Path::new("/home/u/.arc/cache"), Source::Home));
+        assert_eq!(home.cache.describe(), "/home/u/.arc/cache (from HOME)");
+    }
+
+    #[test]
+    fn test_no_home_is_an_error() {
+        for vars in [&[][..], &[("HOME", "/")], &[("HOME", "")], &[("ARC_HOME", "")], &[("HOME", "relative")]] {
+            let err = resolve(vars).unwrap_err().to_string();
+            assert!(err.contains("Set ARC_HOME"), "{:?}: {}", vars, err);
+            assert!(!err.contains("/tmp"));
+        }
+        // キャッシュだけ決まっても、設定側が決まらなければエラー
+        let err = resolve(&[("XDG_CACHE_HOME", "/xdg/cache")]).unwrap_err().to_string();
+        assert!(err.contains("config directory") && err.contains("XDG_CONFIG_HOME"), "{}", err);
+        let err = resolve(&[("XDG_CONFIG_HOME", "/xdg/config")]).unwrap_err().to_string();
+        assert!(err.contains("cache directory") && err.contains("XDG_CACHE_HOME"), "{}", err);
+    }
+}
@@ -0,0 +1,259 @@
+/// `.arc/env/bin` への Gem 実行ファイル用ラッパー (binstub) の生成。
+///
+/// `bundle install` は実行ファイルを `.arc/env/ruby/<api>/bin/` に置くが、
+/// そのシバンはビルドマシンの Ruby を指していることがある。
+/// arc は `.arc/env/bin/` に、プロジェクトの Ruby で実体を起動する薄いラッパーを生成する。
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+
+/// arc が生成した binstub であることを示すマーカー。
+/// このマーカーを含まないファイルは削除・上書きしない。
+const STUB_MARKER: &str = "# arc binstub";
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// `sync` の結果。install_end の payload に記録する。
+#[derive(Debug, Default, PartialEq)]
+pub struct StubReport {
+    /// 新規作成または更新した binstub の数
+    pub created: usize,
+    /// 対応する Gem がなくなったため削除した binstub の数
+    pub removed: usize,
+}
+
+/// 生成済みの binstub。
+#[derive(Debug, Clone)]
+pub struct Stub {
+    /// 実行ファイル名
+    pub name: String,
+    /// 提供元の Gem 名
+    pub gem: String,
+}
+
+// ─────────────────────────────────────────────
+// パス
+// ─────────────────────────────────────────────
+
+/// binstub の出力先 (`.arc/env/bin`)
+pub fn stub_dir(env_path: &Path) -> PathBuf {
+    env_path.join("bin")
+}
+
+/// Gem の実行ファイルが置かれるディレクトリ (`.arc/env/ruby/<api>/bin`)
+pub fn gem_bin_dir(env_path: &Path, ruby_api_ver: &str) -> PathBuf {
+    env_path.join("ruby").join(ruby_api_ver).join("bin")
+}
+
+// ─────────────────────────────────────────────
+// 生成・掃除
+// ─────────────────────────────────────────────
+
+/// Gem の実行ファイルを走査し、`lock_gems` に含まれる Gem の binstub を生成する。
+/// 既存の binstub は上書きし、対応する Gem がなくなった binstub は削除する。
+pub fn sync(env_path: &Path, ruby_api_ver: &str, lock_gems: &[String]) -> Result<StubReport> {
+    let source_dir = gem_bin_dir(env_path, ruby_api_ver);
+    let dest_dir = stub_dir(env_path);
+    crate::perms::create_dir_all(&dest_dir)
+        .with_context(|| format!("Failed to create {:?}", dest_dir))?;
+
+    let lock_gems: HashSet<&str> = lock_gems.iter().map(String::as_str).collect();
+    let mut report = StubReport::default();
+    let mut generated: HashSet<String> = HashSet::new();
+
+    if source_dir.exists() {
+        for entry in fs::read_dir(&source_dir)? {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(String::from) else {
+                continue;
+            };
+            let Some(gem) = fs::read_to_string(entry.path()).ok().and_then(|c| gem_for_executable(&c))
+            else {
+                continue;
+            };
+            if !lock_gems.contains(gem.as_str()) {
+                continue;
+            }
+
+            let dest = dest_dir.join(&name);
+            // ユーザーが置いたファイルは上書きしない
+            if dest.exists() && !is_stub(&dest) {
+                continue;
+            }
+
+            let content = render_stub(&name, &gem, ruby_api_ver);
+            if fs::read_to_string(&dest).ok().as_deref() != Some(content.as_str()) {
+                fs::write(&dest, &content)
+                    .with_context(|| format!("binstub の書き込みに失敗しました: {:?}", dest))?;
+                fs::set_permissions(&dest, fs::Permissions::from_mode(0o755))?;
+                report.created += 1;
+            }
+            generated.insert(name);
+        }
+    }
+
+    for stub in list(env_path)? {
+        if !generated.contains(&stub.name) {
+            fs::remove_file(dest_dir.join(&stub.name))?;
+            report.removed += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+/// `.arc/env/bin` 内の arc 生成 binstub を名前順で返す。
+pub fn list(env_path: &Path) -> Result<Vec<Stub>> {
+    let dir = stub_dir(env_path);
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut stubs: Vec<Stub> = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        if let Some(gem) = stub_gem(&content) {
+            stubs.push(Stub {
+                name: entry.file_name().to_string_lossy().to_string(),
+                gem,
+            });
+        }
+    }
+
+    stubs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(stubs)
+}
+
+fn is_stub(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|c| stub_gem(&c).is_some())
+}
+
+/// binstub のマーカー行から Gem 名を取り出す。
+fn stub_gem(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|l| l.strip_prefix(STUB_MARKER))
+        .and_then(|rest| rest.trim().strip_prefix("(gem: "))
+        .and_then(|rest| rest.strip_suffix(')'))
+        .map(String::from)
+}
+
+/// RubyGems が生成したラッパーから提供元の Gem 名を取り出す。
+/// 例: `load Gem.activate_bin_path('rubocop', 'rubocop', version)` → `rubocop`
+pub(crate) fn gem_for_executable(content: &str) -> Option<String> {
+    for marker in ["activate_bin_path(", "bin_path("] {
+        if let Some(pos) = content.find(marker) {
+            let rest = content[pos + marker.len()..].trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+            let inner = &rest[1..];
+            return inner.find(quote).map(|end| inner[..end].to_string());
+        }
+    }
+    None
+}
+
+/// binstub の内容を生成する。
+/// パスはスクリプト自身の位置から解決するため、プロジェクトを移動しても動作する。
+fn render_stub(name: &str, gem: &str, ruby_api_ver: &str) -> String {
+    format!(
+        "#!/bin/sh\n\
+         {STUB_MARKER} (gem: {gem})\n\
+         # Generated by `arc sync`. Do not edit.\n\
+         env_dir=\"$(cd \"$(dirname \"$0\")/..\" && pwd)\"\n\
+         exec \"$env_dir/ruby_runtime/bin/ruby\" \"$env_dir/ruby/{ruby_api_ver}/bin/{name}\" \"$@\"\n"
+    )
+}
+
+/// シングルクォートでシェル用にクォートする (arc が生成するシェルスクリプト・スニペット用)。
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/tmp/my dir"), "'/tmp/my dir'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    fn rubygems_wrapper(gem: &str, exe: &str) -> String {
+        format!(
+            "#!/opt/hostedtoolcache/Ruby/3.3.6/x64/bin/ruby\n\
+             # This file was generated by RubyGems.\n\
+             require 'rubygems'\n\
+             version = \">= 0.a\"\n\
+             load Gem.activate_bin_path('{gem}', '{exe}', version)\n"
+        )
+    }
+
+    /// フィクスチャの GEM_HOME を作る
+    fn fixture(name: &str) -> PathBuf {
+        let env = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&env);
+        let bin = gem_bin_dir(&env, "3.3.0");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("rubocop"), rubygems_wrapper("rubocop", "rubocop")).unwrap();
+        fs::write(bin.join("rspec"), rubygems_wrapper("rspec-core", "rspec")).unwrap();
+        env
+    }
+
+    #[test]
+    fn test_gem_for_executable() {
+        assert_eq!(
+            gem_for_executable(&rubygems_wrapper("rspec-core", "rspec")).as_deref(),
+            Some("rspec-core")
+        );
+        assert!(gem_for_executable("#!/bin/sh\necho hi\n").is_none());
+    }
+
+    #[test]
+    fn test_stub_creation() {
+        let env = fixture("arc_binstubs_create_test");
+        let gems = vec!["rubocop".to_string(), "rspec-core".to_string()];
+
+        let report = sync(&env, "3.3.0", &gems).unwrap();
+        assert_eq!(report, StubReport { created: 2, removed: 0 });
+
+        let stub = stub_dir(&env).join("rubocop");
+        let content = fs::read_to_string(&stub).unwrap();
+        assert!(content.contains("ruby_runtime/bin/ruby"));
+        assert!(content.contains("ruby/3.3.0/bin/rubocop"));
+        assert_ne!(fs::metadata(&stub).unwrap().permissions().mode() & 0o111, 0);
+
+        // 2 回目は変更なし
+        assert_eq!(sync(&env, "3.3.0", &gems).unwrap(), StubReport::default());
+
+        let names: Vec<String> = list(&env).unwrap().into_iter().map(|s| s.gem).collect();
+        assert_eq!(names, ["rspec-core", "rubocop"]);
+        fs::remove_dir_all(&env).unwrap();
+    }
+
+    #[test]
+    fn test_stale_stub_removed() {
+        let env = fixture("arc_binstubs_stale_test");
+        sync(&env, "3.3.0", &["rubocop".to_string(), "rspec-core".to_string()]).unwrap();
+
+        // rspec-core がロックファイルから消えた
+        fs::write(stub_dir(&env).join("mytool"), "#!/bin/sh\necho mine\n").unwrap();
+        let report = sync(&env, "3.3.0", &["rubocop".to_string()]).unwrap();
+        assert_eq!(report, StubReport { created: 0, removed: 1 });
+        assert!(!stub_dir(&env).join("rspec").exists());
+        // arc が生成していないファイルは残る
+        assert!(stub_dir(&env).join("mytool").exists());
+        fs::remove_dir_all(&env).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! Signal payload の大きなフィールドを `.flux/blobs/<sha256>` へ逃がす仕組み。
+//!
+//! payload をシリアライズした長さが上限を超える場合、大きいフィールドから順に
+//! `{ "$blob": "<sha256>", "bytes": N }` という参照に置き換える。
+//! blob の中身はフィールドの値をシリアライズした JSON そのもの。
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+
+/// blob 参照のキー
+pub const BLOB_KEY: &str = "$blob";
+/// payload サイズ上限のデフォルト (16 KB)
+pub const DEFAULT_PAYLOAD_BUDGET: usize = 16 * 1024;
+
+// ─────────────────────────────────────────────
+// 参照
+// ─────────────────────────────────────────────
+
+/// `value` が blob 参照であればハッシュを返す。
+pub fn blob_ref(value: &Value) -> Option<&str> {
+    let fields = value.as_object()?;
+    if fields.len() != 2 || !fields.contains_key("bytes") {
+        return None;
+    }
+    fields.get(BLOB_KEY)?.as_str()
+}
+
+/// `value` 内のすべての blob 参照のハッシュを集める。
+pub fn collect_refs<'a>(value: &'a Value, refs: &mut BTreeSet<&'a str>) {
+    if let Some(hash) = blob_ref(value) {
+        refs.insert(hash);
+        return;
+    }
+    match value {
+        Value::Array(items) => items.iter().for_each(|v| collect_refs(v, refs)),
+        Value::Object(fields) => fields.values().for_each(|v| collect_refs(v, refs)),
+        _ => {}
+    }
+}
+
+/// blob 参照を `load` で読み込んだ値に再帰的に置き換える。
+pub fn inline(value: &Value, load: &mut impl FnMut(&str) -> Result<Value>) -> Result<Value> {
+    if let Some(hash) = blob_ref(value) {
+        return load(hash);
+    }
+    Ok(match value {
+        Value::Array(items) => Value::Array(items.iter().map(|v| inline(v, load)).collect::<Result<_>>()?),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), inline(v, load)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+// ─────────────────────────────────────────────
+// 退避
+// ─────────────────────────────────────────────
+
+/// payload が `budget` バイトを超える場合、大きいトップレベルフィールドから順に
+/// `store` で blob に書き出して参照に置き換える。
+/// `store` はシリアライズ済みの内容を受け取り、ハッシュを返す。
+pub fn spill(payload: Value, budget: usize, store: &mut impl FnMut(&[u8]) -> Result<String>) -> Result<Value> {
+    let size = serde_json::to_string(&payload)?.len();
+    if size <= budget {
+        return Ok(payload);
+    }
+
+    let Value::Object(mut fields) = payload else {
+        // オブジェクト以外は丸ごと退避する
+        let bytes = serde_json::to_vec(&payload)?;
+        return Ok(reference(store(&bytes)?, bytes.len()));
+    };
+
+    let mut sized: Vec<(String, usize)> = fields
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), serde_json::to_string(v)?.len())))
+        .collect::<Result<_>>()?;
+    sized.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let mut size = size;
+    for (key, field_size) in sized {
+        if size <= budget {
+            break;
+        }
+        let bytes = serde_json::to_vec(&fields[&key])?;
+        // ハッシュは常に 64 文字なので、書き出す前に参照のサイズが分かる
+        let replacement_size = serde_json::to_string(&reference("0".repeat(64), bytes.len()))?.len();
+        if replacement_size >= field_size {
+            // 参照の方が大きくなる小さなフィールドは退避しない
+            continue;
+        }
+        size -= field_size - replacement_size;
+        fields.insert(key, reference(store(&bytes)?, bytes.len()));
+    }
+    Ok(Value::Object(fields))
+}
+
+fn reference(hash: String, bytes: usize) -> Value {
+    json!({ BLOB_KEY: hash, "bytes": bytes })
+}
+
+// ─────────────────────────────────────────────
+// SHA-256
+// ─────────────────────────────────────────────
+
+/// SHA-256 の 16 進表記。blob のファイル名に使う。
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_sha256_known_values() {
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // 2 ブロックにまたがる入力
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_spill_and_inline_round_trip() {
+        let mut store: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+        let gemfile = "gem 'rails'\n".repeat(200);
+        let payload = json!({ "gem": "rails", "gemfile": gemfile });
+
+        let spilled = spill(payload.clone(), 256, &mut |bytes: &[u8]| {
+            let hash = sha256_hex(bytes);
+            store.insert(hash.clone(), bytes.to_vec());
+            Ok(hash)
+        })
+        .unwrap();
+
+        // 小さなフィールドはそのまま、大きなフィールドだけが参照になる
+        assert_eq!(spilled["gem"], "rails");
+        let hash = blob_ref(&spilled["gemfile"]).unwrap().to_string();
+        assert_eq!(spilled["gemfile"]["bytes"], store[&hash].len());
+        assert!(serde_json::to_string(&spilled).unwrap().len() <= 256);
+
+        let mut refs = BTreeSet::new();
+        collect_refs(&spilled, &mut refs);
+        assert_eq!(refs.into_iter().collect::<Vec<_>>(), [hash.as_str()]);
+
+        let restored = inline(&spilled, &mut |h| Ok(serde_json::from_slice(&store[h])?)).unwrap();
+        assert_eq!(restored, payload);
+    }
+
+    #[test]
+    fn test_spill_under_budget_is_untouched() {
+        let payload = json!({ "gem": "rails" });
+        let spilled = spill(payload.clone(), 1024, &mut |_: &[u8]| panic!("should not store")).unwrap();
+        assert_eq!(spilled, payload);
+    }
+
+    #[test]
+    fn test_blob_ref_requires_exact_shape() {
+        assert_eq!(blob_ref(&json!({ "$blob": "abc", "bytes": 3 })), Some("abc"));
+        assert_eq!(blob_ref(&json!({ "$blob": "abc" })), None);
+        assert_eq!(blob_ref(&json!({ "$blob": "abc", "bytes": 3, "x": 1 })), None);
+    }
+}
@@ -0,0 +1,155 @@
+//! arc が隔離する bundler の設定 (`BUNDLE_APP_CONFIG` / `BUNDLE_USER_HOME`) と、
+//! `bundle install` の終了コードの分類。
+//!
+//! bundler は `~/.bundle/config` やプロジェクトの `.bundle/config` を読むため、
+//! 何もしなければ `BUNDLE_PATH` や `BUNDLE_FROZEN` が arc の外から紛れ込む。
+//! 隔離環境では設定の置き場所を `.arc/bundle-config/` と `~/.arc/bundler/` に向ける。
+//!
+//! 設定ファイルは bundler と同じ形式 (`BUNDLE_<KEY>: "value"` の平坦な YAML)。
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// プロジェクトの bundler 設定ディレクトリ (`BUNDLE_APP_CONFIG`)
+pub const APP_CONFIG_DIR: &str = ".arc/bundle-config";
+/// ユーザー単位の bundler ディレクトリ (`BUNDLE_USER_HOME`, `~/.arc/bundler`)
+pub const USER_HOME_DIR: &str = "bundler";
+/// `BUNDLE_APP_CONFIG` 内の設定ファイル名
+const CONFIG_FILE: &str = "config";
+
+pub fn app_config_dir(cwd: &Path) -> PathBuf {
+    cwd.join(APP_CONFIG_DIR)
+}
+
+pub fn user_home_dir() -> Result<PathBuf> {
+    Ok(crate::signals::get_global_arc_dir()?.join(USER_HOME_DIR))
+}
+
+// ─────────────────────────────────────────────
+// 設定ファイル
+// ─────────────────────────────────────────────
+
+/// `jobs` → `BUNDLE_JOBS`、`build.nokogiri` → `BUNDLE_BUILD__NOKOGIRI` (bundler と同じ変換)。
+pub fn env_key(key: &str) -> String {
+    if key.starts_with("BUNDLE_") {
+        return key.to_string();
+    }
+    format!("BUNDLE_{}", key.replace('.', "__").replace('-', "___").to_uppercase())
+}
+
+/// 設定ファイルを読み込む。存在しなければ空。
+pub fn load(cwd: &Path) -> Result<BTreeMap<String, String>> {
+    let path = app_config_dir(cwd).join(CONFIG_FILE);
+    let Ok(content) = fs::read_to_string(&path) else { return Ok(BTreeMap::new()) };
+    let mut settings = BTreeMap::new();
+    for line in content.lines() {
+        if line.trim() == "---" || line.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .with_context(|| format!("bundler の設定を解釈できません: {:?}: {}", path, line))?;
+        settings.insert(key.trim().to_string(), unquote(value.trim()));
+    }
+    Ok(settings)
+}
+
+/// 設定を 1 つ書き換える (`value` が `None` なら削除)。
+pub fn set(cwd: &Path, key: &str, value: Option<&str>) -> Result<()> {
+    let mut settings = load(cwd)?;
+    match value {
+        Some(v) => settings.insert(env_key(key), v.to_string()),
+        None => settings.remove(&env_key(key)),
+    };
+    let dir = app_config_dir(cwd);
+    crate::perms::create_dir_all(&dir)?;
+    let mut content = String::from("---\n");
+    for (k, v) in &settings {
+        content.push_str(&format!("{}: {}\n", k, serde_json::to_string(v)?));
+    }
+    let path = dir.join(CONFIG_FILE);
+    fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))
+}
+
+fn unquote(value: &str) -> String {
+    serde_json::from_str::<String>(value).unwrap_or_else(|_| value.trim_matches('\'').to_string())
+}
+
+// ─────────────────────────────────────────────
+// 終了コード
+// ─────────────────────────────────────────────
+
+/// bundler の終了コード (`Bundler::BundlerError#status_code`) → (`failure_kind`, ヒント)
+const EXIT_CODES: [(i32, &str, &str); 12] = [
+    (4, "gemfile_error", "The Gemfile has an error; check the line bundler reported."),
+    (5, "install_error", "A gem failed to install (often a native extension); check the build log above."),
+    (6, "version_conflict", "Dependencies conflict; relax a version constraint or run `bundle update <gem>`."),
+    (7, "gem_not_found", "A gem or version could not be found; check the name, version and sources."),
+    (10, "gemfile_not_found", "No Gemfile was found in the project."),
+    (11, "git_error", "A git-sourced gem could not be fetched; check the repository URL and credentials."),
+    (16, "frozen_lockfile", "Gemfile.lock is out of date while frozen/deployment mode is on; run `arc sync` without it."),
+    (17, "network_error", "Could not reach the gem server; check the network or proxy and retry."),
+    (18, "ruby_version_mismatch", "The Gemfile requires a different Ruby; run `arc bootstrap <version>`."),
+    (20, "lockfile_error", "Gemfile.lock is corrupt; restore it from version control or delete it."),
+    (23, "permission_error", "Permission denied while writing gems; check ownership of .arc/env."),
+    (31, "no_space", "The disk is full; free space (e.g. `arc gc`) and retry."),
+];
+
+/// 終了コードを分類する。既知のコードでなければ `unknown`。
+pub fn classify_exit(code: i32) -> (&'static str, Option<&'static str>) {
+    match EXIT_CODES.iter().find(|(c, _, _)| *c == code) {
+        Some((_, kind, hint)) => (kind, Some(hint)),
+        None => ("unknown", None),
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_key() {
+        assert_eq!(env_key("jobs"), "BUNDLE_JOBS");
+        assert_eq!(env_key("build.nokogiri"), "BUNDLE_BUILD__NOKOGIRI");
+        assert_eq!(env_key("BUNDLE_FROZEN"), "BUNDLE_FROZEN");
+    }
+
+    #[test]
+    fn test_set_get_roundtrip() {
+        let cwd = std::env::temp_dir().join("arc_bundler_config_test");
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        assert!(load(&cwd).unwrap().is_empty());
+
+        set(&cwd, "jobs", Some("4")).unwrap();
+        set(&cwd, "build.nokogiri", Some("--use-system-libraries")).unwrap();
+        let content = fs::read_to_string(cwd.join(".arc/bundle-config/config")).unwrap();
+        assert_eq!(content, "---\nBUNDLE_BUILD__NOKOGIRI: \"--use-system-libraries\"\nBUNDLE_JOBS: \"4\"\n");
+        assert_eq!(load(&cwd).unwrap()["BUNDLE_JOBS"], "4");
+
+        set(&cwd, "jobs", None).unwrap();
+        assert_eq!(load(&cwd).unwrap().keys().collect::<Vec<_>>(), ["BUNDLE_BUILD__NOKOGIRI"]);
+        // bundler が書いた引用符なしの値も読める
+        fs::write(cwd.join(".arc/bundle-config/config"), "---\nBUNDLE_DEPLOYMENT: true\n").unwrap();
+        assert_eq!(load(&cwd).unwrap()["BUNDLE_DEPLOYMENT"], "true");
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_classify_exit() {
+        assert_eq!(classify_exit(6).0, "version_conflict");
+        assert_eq!(classify_exit(17).0, "network_error");
+        assert_eq!(classify_exit(16).0, "frozen_lockfile");
+        assert_eq!(classify_exit(1), ("unknown", None));
+        // 表のコードと種類は重複しない
+        let mut codes: Vec<i32> = EXIT_CODES.iter().map(|(c, _, _)| *c).collect();
+        codes.dedup();
+        assert_eq!(codes.len(), EXIT_CODES.len());
+    }
+}
@@ -0,0 +1,232 @@
+//! グローバルキャッシュ (`~/.arc/cache`) のレイアウトのバージョンと移行。
+//!
+//! `.layout-version` にレイアウトのバージョンを書いておき、キャッシュを使うコマンドの開始時に
+//! このバイナリが期待するバージョン (`LAYOUT_VERSION`) と比べる。古ければ `MIGRATIONS` を
+//! 順に適用し、1 段ごとにバージョンを書き換える (途中で中断されても、次回はその段からやり直す)。
+//! 新しいバイナリが作ったキャッシュなど移行できない場合は、`arc cache clean --all` の案内を 1 度だけ表示する。
+//!
+//! | バージョン | レイアウト |
+//! |---|---|
+//! | 0 (ファイルなし) | `rubies/<id>/`・`gems/{gems,specifications,extensions}/` |
+//! | 1 | 展開を終えた Ruby に `rubies/<id>/.complete` を置く |
+
+use anyhow::{Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// このバイナリが期待するレイアウトのバージョン
+pub const LAYOUT_VERSION: u32 = 1;
+/// バージョンを書くファイル (`~/.arc/cache/` 内)
+const VERSION_FILE: &str = ".layout-version";
+/// 移行できないことを案内済みのバージョン
+const WARNED_FILE: &str = ".layout-warned";
+/// 移行の記録
+pub const MIGRATION_LOG: &str = "migration.log";
+/// 展開を終えた Ruby の目印 (`rubies/<id>/.complete`)
+pub const COMPLETE_MARKER: &str = ".complete";
+/// Ruby の展開先 (`~/.arc/cache/rubies`)
+pub const RUBIES_DIR: &str = "rubies";
+
+/// `from` のレイアウトを `from + 1` に移す処理。何度実行しても同じ結果になること。
+struct Migration {
+    from: u32,
+    description: &'static str,
+    run: fn(&Path) -> Result<usize>,
+}
+
+const MIGRATIONS: [Migration; 1] = [Migration {
+    from: 0,
+    description: "mark fully extracted rubies with .complete",
+    run: mark_complete_rubies,
+}];
+
+/// `ensure` の結果
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// 既に現在のレイアウト (または空のキャッシュ)
+    Current,
+    /// 移行した (各段の説明)
+    Migrated(Vec<String>),
+    /// 移行できない。`warn` は今回案内を表示すべきか
+    Unsupported { found: u32, warn: bool },
+}
+
+/// キャッシュのレイアウトのバージョン。ファイルがなければ 0。
+pub fn read_version(cache: &Path) -> Result<u32> {
+    let path = cache.join(VERSION_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => content.trim().parse().with_context(|| format!("{:?} を解釈できません", path)),
+        Err(_) => Ok(0),
+    }
+}
+
+/// レイアウトを現在のバージョンにそろえる。
+pub fn ensure(cache: &Path) -> Result<Outcome> {
+    // キャッシュがまだなければ、現在のレイアウトで始める
+    if fs::read_dir(cache).map_or(true, |mut entries| entries.next().is_none()) {
+        crate::perms::create_dir_all(cache)?;
+        write_version(cache, LAYOUT_VERSION)?;
+        return Ok(Outcome::Current);
+    }
+
+    let mut version = read_version(cache)?;
+    if version > LAYOUT_VERSION || (version < LAYOUT_VERSION && !MIGRATIONS.iter().any(|m| m.from == version)) {
+        let warned = fs::read_to_string(cache.join(WARNED_FILE)).ok();
+        let warn = warned.as_deref().map(str::trim) != Some(&version.to_string());
+        if warn {
+            fs::write(cache.join(WARNED_FILE), version.to_string())?;
+        }
+        return Ok(Outcome::Unsupported { found: version, warn });
+    }
+
+    let mut applied = Vec::new();
+    while let Some(migration) = MIGRATIONS.iter().find(|m| m.from == version) {
+        let count = (migration.run)(cache)?;
+        version += 1;
+        write_version(cache, version)?;
+        let line = format!("{} → {}: {} ({} changed)", migration.from, version, migration.description, count);
+        log(cache, &line)?;
+        applied.push(line);
+    }
+    Ok(if applied.is_empty() { Outcome::Current } else { Outcome::Migrated(applied) })
+}
+
+/// 一時ファイルに書いてから置き換える (中断されても壊れたファイルを残さない)。
+fn write_version(cache: &Path, version: u32) -> Result<()> {
+    let tmp = cache.join(format!("{}.tmp", VERSION_FILE));
+    fs::write(&tmp, format!("{}\n", version))?;
+    fs::rename(&tmp, cache.join(VERSION_FILE)).context(".layout-version の更新に失敗しました")
+}
+
+fn log(cache: &Path, line: &str) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(cache.join(MIGRATION_LOG))?;
+    writeln!(file, "{} {}", chrono::Local::now().to_rfc3339(), line)?;
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// Ruby の完了マーカー
+// ─────────────────────────────────────────────
+
+/// 展開を終えた Ruby か (`.complete` がある)。
+pub fn is_complete(ruby_dir: &Path) -> bool {
+    ruby_dir.join(COMPLETE_MARKER).is_file()
+}
+
+pub fn mark_complete(ruby_dir: &Path) -> Result<()> {
+    fs::write(ruby_dir.join(COMPLETE_MARKER), "").with_context(|| format!("Failed to mark {:?}", ruby_dir))
+}
+
+/// 0 → 1: `bin/ruby` まで展開されている Ruby に `.complete` を置く。
+/// 展開の途中で止まったものは印を付けず、次の bootstrap でダウンロードし直す。
+fn mark_complete_rubies(cache: &Path) -> Result<usize> {
+    let Ok(entries) = fs::read_dir(cache.join(RUBIES_DIR)) else { return Ok(0) };
+    let mut marked = 0;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if dir.join("bin/ruby").is_file() && !is_complete(&dir) {
+            mark_complete(&dir)?;
+            marked += 1;
+        }
+    }
+    Ok(marked)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ディレクトリ以下のファイルの一覧 (相対パス、ログを除く)
+    fn tree(root: &Path) -> Vec<String> {
+        let mut files = Vec::new();
+        let mut stack = vec![root.to_path_buf()];
+        while let Some(dir) = stack.pop() {
+            for entry in fs::read_dir(&dir).unwrap().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else if !path.ends_with(MIGRATION_LOG) {
+                    files.push(path.strip_prefix(root).unwrap().display().to_string());
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    /// バージョン 0 のキャッシュ: 完全な Ruby・展開途中の Ruby・Gem
+    fn layout_v0(name: &str) -> std::path::PathBuf {
+        let cache = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&cache);
+        for (path, content) in [
+            ("rubies/3.3.6-linux-x86_64/bin/ruby", "elf"),
+            ("rubies/3.4.1-linux-x86_64/download.tar.gz", "partial"),
+            ("gems/gems/rake-13.1.0/lib/rake.rb", ""),
+            ("gems/specifications/rake-13.1.0.gemspec", ""),
+        ] {
+            fs::create_dir_all(cache.join(path).parent().unwrap()).unwrap();
+            fs::write(cache.join(path), content).unwrap();
+        }
+        cache
+    }
+
+    #[test]
+    fn test_migrates_v0_to_current() {
+        let cache = layout_v0("arc_cache_layout_v0_test");
+        match ensure(&cache).unwrap() {
+            Outcome::Migrated(steps) => assert_eq!(steps, ["0 → 1: mark fully extracted rubies with .complete (1 changed)"]),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(
+            tree(&cache),
+            [
+                ".layout-version",
+                "gems/gems/rake-13.1.0/lib/rake.rb",
+                "gems/specifications/rake-13.1.0.gemspec",
+                "rubies/3.3.6-linux-x86_64/.complete",
+                "rubies/3.3.6-linux-x86_64/bin/ruby",
+                "rubies/3.4.1-linux-x86_64/download.tar.gz",
+            ]
+        );
+        assert_eq!(read_version(&cache).unwrap(), LAYOUT_VERSION);
+        assert!(fs::read_to_string(cache.join(MIGRATION_LOG)).unwrap().contains("0 → 1"));
+        // 2 度目は何もしない
+        assert_eq!(ensure(&cache).unwrap(), Outcome::Current);
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_migration_is_resumed() {
+        let cache = layout_v0("arc_cache_layout_resume_test");
+        // 印を付けた後、バージョンを書く前に中断された状態
+        mark_complete_rubies(&cache).unwrap();
+        assert_eq!(read_version(&cache).unwrap(), 0);
+        assert!(matches!(ensure(&cache).unwrap(), Outcome::Migrated(_)));
+        assert_eq!(read_version(&cache).unwrap(), LAYOUT_VERSION);
+        assert!(is_complete(&cache.join("rubies/3.3.6-linux-x86_64")));
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    fn test_newer_layout_warns_once() {
+        let cache = layout_v0("arc_cache_layout_newer_test");
+        fs::write(cache.join(VERSION_FILE), "99\n").unwrap();
+        assert_eq!(ensure(&cache).unwrap(), Outcome::Unsupported { found: 99, warn: true });
+        assert_eq!(ensure(&cache).unwrap(), Outcome::Unsupported { found: 99, warn: false });
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    fn test_fresh_cache_starts_current() {
+        let cache = std::env::temp_dir().join("arc_cache_layout_fresh_test");
+        let _ = fs::remove_dir_all(&cache);
+        assert_eq!(ensure(&cache).unwrap(), Outcome::Current);
+        assert_eq!(read_version(&cache).unwrap(), LAYOUT_VERSION);
+        fs::remove_dir_all(&cache).unwrap();
+    }
+}
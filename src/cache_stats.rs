@@ -0,0 +1,187 @@
+//! キャッシュの効果の集計 (`arc stats --cache`)。
+//!
+//! bootstrap / install_end の payload に記録されたキャッシュ指標だけを使う純粋な集計。
+//! 節約できた時間はログ上の実測値からの推定であり、正確な値ではない。
+
+use serde_json::Value;
+
+use crate::commands::phases;
+use crate::signals::{Signal, SignalType};
+
+/// ログ全体でのキャッシュの効果。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheStats {
+    /// キャッシュ指標を持つ bootstrap の回数 (adopt による記録は含まない)
+    pub bootstraps: usize,
+    pub bootstrap_hits: usize,
+    pub bytes_linked: u64,
+    pub bytes_downloaded: u64,
+    /// キャッシュ指標を持つ sync の回数
+    pub syncs: usize,
+    pub gems_restored: u64,
+    pub gems_fetched: u64,
+    /// sync ごとの「復元した Gem / 新たに入った Gem」の平均
+    pub avg_restored_ratio: Option<f64>,
+    /// 推定した節約時間 (マイクロ秒)
+    pub est_saved_us: Option<u64>,
+}
+
+impl CacheStats {
+    pub fn from_signals(signals: &[Signal]) -> Self {
+        let mut stats = Self::default();
+
+        // bootstrap: ヒット時とミス時の所要時間の差を節約時間とみなす
+        let (mut hit_us, mut miss_us) = (Vec::new(), Vec::new());
+        for signal in signals.iter().filter(|s| s.r_type == SignalType::Bootstrap.to_string()) {
+            let Some(hit) = signal.payload["cache_hit"].as_bool() else { continue };
+            stats.bootstraps += 1;
+            if hit {
+                stats.bootstrap_hits += 1;
+            }
+            stats.bytes_linked += signal.payload["bytes_linked"].as_u64().unwrap_or(0);
+            stats.bytes_downloaded += signal.payload["bytes_downloaded"].as_u64().unwrap_or(0);
+            if let Some(us) = signal.payload["duration_us"].as_u64() {
+                if hit { hit_us.push(us) } else { miss_us.push(us) }
+            }
+        }
+
+        // install: bundler が 1 Gem を取得するのにかかった平均時間を復元した Gem 数に掛ける
+        let mut ratios = Vec::new();
+        let (mut bundler_us, mut restore_us) = (0u64, 0u64);
+        for signal in signals.iter().filter(|s| s.r_type == SignalType::InstallEnd.to_string()) {
+            let cache = &signal.payload["cache"];
+            let (Some(restored), Some(fetched)) = (cache["restored"].as_u64(), cache["fetched"].as_u64()) else {
+                continue;
+            };
+            stats.syncs += 1;
+            stats.gems_restored += restored;
+            stats.gems_fetched += fetched;
+            if restored + fetched > 0 {
+                ratios.push(restored as f64 / (restored + fetched) as f64);
+            }
+            bundler_us += phase_us(&signal.payload, phases::BUNDLER).unwrap_or(0);
+            restore_us += cache["restore_us"].as_u64().unwrap_or(0);
+        }
+        if !ratios.is_empty() {
+            stats.avg_restored_ratio = Some(ratios.iter().sum::<f64>() / ratios.len() as f64);
+        }
+
+        let install_saved = (stats.gems_fetched > 0).then(|| {
+            let per_gem = bundler_us / stats.gems_fetched;
+            (stats.gems_restored * per_gem).saturating_sub(restore_us)
+        });
+        let bootstrap_saved = match (mean(&hit_us), mean(&miss_us)) {
+            (Some(hit), Some(miss)) => Some(miss.saturating_sub(hit) * stats.bootstrap_hits as u64),
+            _ => None,
+        };
+        stats.est_saved_us = match (install_saved, bootstrap_saved) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+
+        stats
+    }
+
+    /// bootstrap のキャッシュヒット率
+    pub fn hit_rate(&self) -> Option<f64> {
+        (self.bootstraps > 0).then(|| self.bootstrap_hits as f64 / self.bootstraps as f64)
+    }
+}
+
+/// install_end の `phases` から指定したフェーズの所要時間を取り出す。
+fn phase_us(payload: &Value, name: &str) -> Option<u64> {
+    payload["phases"]
+        .as_array()?
+        .iter()
+        .find(|p| p["name"] == name)?["duration_us"]
+        .as_u64()
+}
+
+fn mean(values: &[u64]) -> Option<u64> {
+    (!values.is_empty()).then(|| values.iter().sum::<u64>() / values.len() as u64)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn signal(r_type: SignalType, payload: Value) -> Signal {
+        Signal {
+            id: "id".to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: "2026-01-01T00:00:00Z".to_string(),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    fn install_end(restored: u64, fetched: u64, bundler_us: u64, restore_us: u64) -> Signal {
+        signal(
+            SignalType::InstallEnd,
+            json!({
+                "phases": [
+                    { "name": "restore_cache", "duration_us": restore_us },
+                    { "name": "bundler", "duration_us": bundler_us },
+                ],
+                "cache": { "restored": restored, "fetched": fetched, "restore_us": restore_us },
+            }),
+        )
+    }
+
+    #[test]
+    fn test_empty_log() {
+        let stats = CacheStats::from_signals(&[]);
+        assert_eq!(stats, CacheStats::default());
+        assert_eq!(stats.hit_rate(), None);
+    }
+
+    #[test]
+    fn test_bootstrap_hit_rate() {
+        let signals = vec![
+            signal(SignalType::Bootstrap, json!({ "cache_hit": false, "bytes_downloaded": 30, "bytes_linked": 100, "duration_us": 9_000 })),
+            signal(SignalType::Bootstrap, json!({ "cache_hit": true, "bytes_downloaded": 0, "bytes_linked": 100, "duration_us": 1_000 })),
+            signal(SignalType::Bootstrap, json!({ "cache_hit": true, "bytes_linked": 100, "duration_us": 3_000 })),
+            // adopt による bootstrap は cache_hit を持たないため数えない
+            signal(SignalType::Bootstrap, json!({ "ruby_version": "3.3.0", "adopted": true })),
+        ];
+        let stats = CacheStats::from_signals(&signals);
+        assert_eq!(stats.bootstraps, 3);
+        assert_eq!(stats.bootstrap_hits, 2);
+        assert_eq!(stats.bytes_linked, 300);
+        assert_eq!(stats.bytes_downloaded, 30);
+        assert!((stats.hit_rate().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        // (9000 - 平均 2000) × ヒット 2 回
+        assert_eq!(stats.est_saved_us, Some(14_000));
+    }
+
+    #[test]
+    fn test_install_restored_ratio_and_estimate() {
+        let signals = vec![
+            install_end(0, 10, 10_000, 0),
+            install_end(8, 2, 2_000, 500),
+            // キャッシュ指標のない古い記録は無視する
+            signal(SignalType::InstallEnd, json!({ "exit_code": 0 })),
+        ];
+        let stats = CacheStats::from_signals(&signals);
+        assert_eq!(stats.syncs, 2);
+        assert_eq!(stats.gems_restored, 8);
+        assert_eq!(stats.gems_fetched, 12);
+        assert!((stats.avg_restored_ratio.unwrap() - 0.4).abs() < 1e-9);
+        // 1 Gem あたり 1000µs × 復元 8 Gem - 復元にかかった 500µs
+        assert_eq!(stats.est_saved_us, Some(7_500));
+    }
+
+    #[test]
+    fn test_no_estimate_without_baseline() {
+        // すべてヒットしていると比較対象がないため推定しない
+        let signals = vec![signal(SignalType::Bootstrap, json!({ "cache_hit": true, "duration_us": 1_000 }))];
+        let stats = CacheStats::from_signals(&signals);
+        assert_eq!(stats.est_saved_us, None);
+    }
+}
@@ -1,15 +1,78 @@
+use clap::error::{ContextKind, ContextValue, ErrorKind};
 use clap::{Parser, Subcommand};
+use std::ffi::OsString;
 use std::path::PathBuf;
 
+use crate::commands::{ChangelogFormat, PromptShell};
+use crate::display::{Layout, StatsGroup};
+use crate::stats_export::ExportFormat;
+use crate::transcript::TranscriptFormat;
+
 /// arc — Flux Core / Ruby 版 uv
 #[derive(Parser)]
 #[command(name = "arc")]
 #[command(about = "Flux Core — Ruby 版 uv / 操作ログ記録・再生エンジン", long_about = None)]
 pub struct Cli {
+    /// 詳細を表示する (Signal を記録しなかった理由など)
+    #[arg(long, global = true)]
+    pub verbose: bool,
+    /// 進捗を 1 行 1 JSON のイベントとして stderr に書く (人間向けの出力は止める)
+    #[arg(long, global = true)]
+    pub progress_json: bool,
+    /// 進捗イベントを stderr ではなく指定した fd に書く
+    #[arg(long, global = true, value_name = "N", requires = "progress_json")]
+    pub progress_fd: Option<i32>,
+    /// .flux に書き込まない (変更するコマンドは失敗し、exec / run は記録せずに実行する)。
+    /// .flux に書き込めなければ自動で有効になる
+    #[arg(long, global = true)]
+    pub read_only: bool,
+    /// 終了時に arc 自身のオーバーヘッドの内訳を表示する (子プロセスの時間を除く。--verbose でも表示)
+    #[arg(long, global = true)]
+    pub timings: bool,
+    /// 件数の 3 桁区切りを選ぶ言語 (例: de → 182.403、fr → 182 403)。config.toml の [display] lang より優先
+    #[arg(long, global = true, value_name = "LANG")]
+    pub lang: Option<String>,
+    /// 罫線・絵文字・色を使わず、1 行 1 レコードの key=value で表示する (TERM=dumb や [display] plain = true でも有効)
+    #[arg(long, global = true)]
+    pub plain: bool,
+    /// config.toml の [signal_hooks] を実行しない (環境変数 ARC_NO_SIGNAL_HOOKS=1 でも無効になる)
+    #[arg(long, global = true)]
+    pub no_signal_hooks: bool,
+    /// 外部コマンド・ダウンロード・.flux 外への書き込みを行わず、実行する内容だけを表示する
+    #[arg(long)]
+    pub dry_run: bool,
+    /// 他の arc がプロジェクトを変更中のとき、ロックの解放を待つ最大秒数
+    #[arg(long, global = true, value_name = "SECS", default_value_t = crate::project_lock::DEFAULT_TIMEOUT_SECS)]
+    pub lock_timeout: u64,
+    /// 他の arc がプロジェクトを変更中なら待たずに失敗する
+    #[arg(long, global = true)]
+    pub no_wait: bool,
     #[command(subcommand)]
     pub command: Commands,
 }
 
+impl Cli {
+    /// `Cli::try_parse_from` と同じだが、打ち間違えたサブコマンドには clap の候補のうち
+    /// 編集距離が最も近いものだけを提案する (`remve` → `remove`。clap のままだと `recent` / `r` / `tree` も並ぶ)。
+    pub fn try_parse_suggesting<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        Self::try_parse_from(args).map_err(|mut e| {
+            if e.kind() == ErrorKind::InvalidSubcommand
+                && let (Some(ContextValue::String(typed)), Some(ContextValue::Strings(candidates))) =
+                    (e.get(ContextKind::InvalidSubcommand), e.get(ContextKind::SuggestedSubcommand))
+                && let Some(best) = crate::suggest::closest(typed, candidates.iter().map(String::as_str))
+            {
+                let best = best.to_string();
+                e.insert(ContextKind::SuggestedSubcommand, ContextValue::Strings(vec![best]));
+            }
+            e
+        })
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// 新しい Flux プロジェクトを初期化する
@@ -17,7 +80,22 @@ pub enum Commands {
         /// プロジェクトパス（省略時はカレントディレクトリ）
         #[arg(default_value = ".")]
         path: PathBuf,
+        /// プロジェクト名（省略時はディレクトリ名）
+        #[arg(long)]
+        name: Option<String>,
+        /// プロジェクトの説明
+        #[arg(long)]
+        description: Option<String>,
+        /// 名前・Ruby バージョン・Gemfile 作成・bootstrap を対話的に選ぶ
+        /// (stdin が TTY でオプション指定がなければ自動で有効)
+        #[arg(long, overrides_with = "no_interactive")]
+        interactive: bool,
+        /// 対話モードを無効にする
+        #[arg(long, overrides_with = "interactive")]
+        no_interactive: bool,
     },
+    /// 既存の Gemfile / Ruby バージョンを取り込んで Flux プロジェクトにする
+    Adopt,
     /// 現在のプロジェクト状態を表示する（Flux State）
     State {
         /// JSON 形式で出力する
@@ -29,17 +107,142 @@ pub enum Commands {
         /// 直近の操作による差分を表示する
         #[arg(short, long)]
         diff: bool,
-        /// 指定した種別の Signal のみを抽出する (例: add, exec_start)
+        /// 指定した種別の Signal のみを抽出する (例: add, exec_start)。
+        /// 繰り返し指定・カンマ区切りが可能で、`!exec_start` のように `!` を付けると除外する
         #[arg(short, long, name = "TYPE")]
-        r#type: Option<String>,
+        r#type: Vec<String>,
+        /// stats.ignore を無視してすべての実行を集計する
+        #[arg(long)]
+        all: bool,
+        /// 統計テーブルのレイアウト (省略時は端末幅から選択)
+        #[arg(long, value_enum)]
+        layout: Option<Layout>,
+        /// 直近 12 週間の日別の実行数を 1 日 1 文字で表示する (--json で日別の配列)
+        #[arg(long, conflicts_with_all = ["raw", "diff"])]
+        activity: bool,
+        /// Signal 種別ごとの件数を表示し、名前空間のない古い独自種別に印を付ける
+        #[arg(long, conflicts_with_all = ["raw", "diff", "activity", "json", "stream"])]
+        summary: bool,
+        /// bootstrap で入れた Ruby のバージョンの履歴を表示する (--json で配列)
+        #[arg(long, conflicts_with_all = ["raw", "diff", "activity", "summary", "stream"])]
+        ruby_history: bool,
+        /// --activity をブロック文字ではなく数字で表示する
+        #[arg(long, requires = "activity")]
+        ascii: bool,
+        /// 失敗した操作を原因ごとにまとめずに、1 件ずつすべて表示する
+        #[arg(long, conflicts_with_all = ["raw", "diff", "activity", "json", "stream"])]
+        failures_verbose: bool,
+        /// 指定したユーザーが記録した Signal・実行のみを対象にする (記録のない古い Signal は `unknown`)
+        #[arg(long, value_name = "NAME")]
+        user: Option<String>,
+        /// Signal を 1 行 1 件の NDJSON で逐次出力する (最後の行はページの要約)
+        #[arg(long, conflicts_with_all = ["json", "raw", "diff"])]
+        stream: bool,
+        /// --json / --stream で出力する最大件数
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+        /// --json / --stream で先頭から飛ばす件数 (--type / --user で絞り込んだ後に数える)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        offset: usize,
+        /// --json / --stream でこの ID の Signal より後だけを出力する (前回の next_cursor を渡す)
+        #[arg(long, value_name = "SIGNAL_ID")]
+        cursor: Option<String>,
+        /// プロジェクトではなく指定した NDJSON の Signal ログを読む (`-` は stdin)
+        #[arg(long, value_name = "PATH|-")]
+        from: Option<PathBuf>,
+    },
+    /// コマンドごとの実行統計を表示する
+    Stats {
+        /// キャッシュの効果 (ヒット率・復元率・推定節約時間) を表示する
+        #[arg(long)]
+        cache: bool,
+        /// .arc/env のサイズの推移と、最も大きく増えた操作を表示する
+        #[arg(long, conflicts_with_all = ["cache", "export"])]
+        disk: bool,
+        /// stats.ignore を無視してすべての実行を集計する
+        #[arg(long)]
+        all: bool,
+        /// 統計テーブルのレイアウト (省略時は端末幅から選択)
+        #[arg(long, value_enum)]
+        layout: Option<Layout>,
+        /// 集計の単位 (コマンドごと・ユーザーごと・実行した場所ごと)
+        #[arg(long, value_enum, default_value = "command", conflicts_with_all = ["cache", "export"])]
+        by: StatsGroup,
+        /// 指定したユーザーの実行のみを集計する
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["cache", "export"])]
+        user: Option<String>,
+        /// 1 つのコマンド (タスク名・エイリアス名) に絞り、日別の推移も表示する
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["cache", "export"])]
+        command: Option<String>,
+        /// 表示名かコマンドラインが一致する実行の履歴を時系列で表示する (既定は部分一致)
+        #[arg(long, value_name = "PATTERN", conflicts_with_all = ["cache", "disk", "export", "command"])]
+        follow: Option<String>,
+        /// --follow のパターンを正規表現として扱う
+        #[arg(long, requires = "follow")]
+        regex: bool,
+        /// --follow の表示の後もログを監視し、新しく終わった実行を表示し続ける (Ctrl-C で終了)
+        #[arg(long, requires = "follow", conflicts_with = "from")]
+        watch: bool,
+        /// 日別・コマンド別の統計を CSV / JSON で書き出す
+        #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "cache")]
+        export: Option<ExportFormat>,
+        /// 書き出し先のファイル (省略時は標準出力)
+        #[arg(short, long, value_name = "FILE", requires = "export")]
+        output: Option<PathBuf>,
+        /// 実行回数の上位に限らず、すべてのコマンドを書き出す
+        #[arg(long, requires = "export")]
+        all_commands: bool,
+        /// 2 つの期間 (--before / --after) のコマンド統計を並べて比べる
+        #[arg(long, requires = "before", requires = "after", conflicts_with_all = ["cache", "disk", "export", "follow"])]
+        compare: bool,
+        /// --compare の比較元の期間 (2026-01-01..2026-01-31、2026-02-01..、<signal-id>..<signal-id>)
+        #[arg(long, value_name = "RANGE", requires = "compare")]
+        before: Option<String>,
+        /// --compare の比較先の期間 (--before と同じ書式)
+        #[arg(long, value_name = "RANGE", requires = "compare")]
+        after: Option<String>,
+        /// Signal の前と後を比べる (<id>、または <id>..<id> の前と後)
+        #[arg(long, value_name = "ID[..ID]", conflicts_with_all = ["compare", "cache", "disk", "export", "follow"])]
+        compare_signals: Option<String>,
+        /// 成功と失敗を行き来するコマンドを一覧にする ([stats] flaky_threshold / flaky_min_runs)
+        #[arg(long, conflicts_with_all = ["cache", "disk", "export", "follow", "compare", "compare_signals", "command"])]
+        flaky: bool,
+        /// --flaky で集計する期間 (--before と同じ書式。省略時はすべて)
+        #[arg(long, value_name = "RANGE", requires = "flaky")]
+        window: Option<String>,
+        /// 悪化として強調する変化 (平均・p95 は %、失敗率はポイント)
+        #[arg(long, value_name = "PCT", default_value_t = crate::stats_compare::DEFAULT_THRESHOLD)]
+        threshold: f64,
+        /// プロジェクトではなく指定した NDJSON の Signal ログを読む (`-` は stdin)
+        #[arg(long, value_name = "PATH|-")]
+        from: Option<PathBuf>,
     },
     /// 任意のコマンドを実行し、結果を Flux ログに記録する
     Exec {
+        /// 引数をつなげたコマンドラインをシェル (`sh -c`) で実行する (パイプや && を使う場合)
+        #[arg(short, long)]
+        shell: bool,
+        /// [safety] confirm_patterns の確認を省略する ([safety] allow_yes_bypass = true の場合のみ)
+        #[arg(short, long)]
+        yes: bool,
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         command: Vec<String>,
     },
     /// Gemfile.lock と環境を同期する (bundle install のラップ)
-    Sync,
+    Sync {
+        /// 同期済みかどうかの判定のみ行う (同期済みなら 0、そうでなければ 1 で終了)
+        #[arg(long)]
+        check: bool,
+        /// 同期済みでも bundle install を実行する
+        #[arg(long)]
+        force: bool,
+        /// 現在の Ruby と ABI が合わないネイティブ拡張を削除してから再インストールする
+        #[arg(long, conflicts_with = "check")]
+        force_rebuild: bool,
+        /// 事前確認 (ruby / bundler / Gemfile / 空き容量 / ロック) を省略する
+        #[arg(long, conflicts_with = "check")]
+        no_preflight: bool,
+    },
     /// Gem を追加する
     Add {
         /// 追加する Gem 名
@@ -47,26 +250,552 @@ pub enum Commands {
         /// バージョン指定 (オプション)
         #[arg(short, long)]
         version: Option<String>,
+        /// 確認をスキップする
+        #[arg(short, long)]
+        yes: bool,
+        /// Gemfile を変更せずに差分を表示する
+        #[arg(long)]
+        dry_run: bool,
     },
     /// Gem を削除する
     Remove {
-        /// 削除する Gem 名
-        gem: String,
+        /// 削除する Gem 名（省略時は Gemfile から対話的に選択）
+        gem: Option<String>,
+        /// 確認をスキップする
+        #[arg(short, long)]
+        yes: bool,
+        /// Gemfile.lock から外れた Gem を .arc/env から削除する
+        #[arg(long)]
+        purge: bool,
+        /// Gemfile を変更せずに差分を表示する
+        #[arg(long, conflicts_with = "purge")]
+        dry_run: bool,
+    },
+    /// Gemfile.lock から外れた Gem を .arc/env から削除する
+    #[command(name = "prune-gems")]
+    PruneGems {
+        /// 削除せずに対象を表示する
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// インストール済みの Gem のライセンスを一覧表示する
+    Licenses {
+        /// Gem ごと (既定) かライセンスごとにまとめる
+        #[arg(long, value_enum, default_value = "gem")]
+        group_by: crate::licenses::LicenseGroup,
+        /// JSON 形式で出力する
+        #[arg(long)]
+        json: bool,
+        /// このライセンスの Gem があれば失敗する (繰り返し指定・カンマ区切り、例: GPL-3.0,AGPL-3.0)
+        #[arg(long, value_name = "LICENSE", value_delimiter = ',')]
+        fail_on: Vec<String>,
     },
     /// 直前の Add/Remove 操作を取り消す
-    Undo,
+    Undo {
+        /// 確認をスキップする
+        #[arg(short, long)]
+        yes: bool,
+        /// Gemfile を変更せずに差分を表示する
+        #[arg(long)]
+        dry_run: bool,
+        /// 取り消す操作の後で対象の Gem の行が手で編集されていても確認せずに進める
+        #[arg(long)]
+        force: bool,
+        /// 対象の Gem の行を、記録しておいた操作の前の内容 (オプション・グループを含む) にそのまま戻す
+        #[arg(long)]
+        exact: bool,
+        /// 基準点 (Signal ID・RFC 3339 の時刻・init) より後の add / remove をまとめて取り消す
+        #[arg(long, value_name = "SIGNAL_ID|TIMESTAMP|init", conflicts_with_all = ["force", "exact"])]
+        all_since: Option<String>,
+    },
     /// プリコンパイル済み Ruby をプロジェクトに導入する
     Bootstrap {
         /// 使用する Ruby バージョン (例: 3.4.0)。省略時は .arc/config.toml の値を使用。
-        version: Option<String>,
+        /// --cache-only の場合は複数指定できる。
+        #[arg(required_if_eq("cache_only", "true"))]
+        versions: Vec<String>,
+        /// プロジェクトなしで、グローバルキャッシュに Ruby を入れるだけにする (CI イメージの準備など)
+        #[arg(long)]
+        cache_only: bool,
+        /// グローバルキャッシュにある Ruby を一覧表示する (ネットワークを使わない)
+        #[arg(long, conflicts_with_all = ["versions", "cache_only"])]
+        installed: bool,
+        /// キャッシュ済みの Ruby にプロジェクトを切り替える (ネットワークを使わない)
+        #[arg(long = "use", value_name = "VERSION", conflicts_with_all = ["versions", "cache_only", "installed"])]
+        use_version: Option<String>,
+        /// 最後に bootstrap した Ruby より古いバージョンへの変更を確認なしで許可する
+        #[arg(long, conflicts_with_all = ["cache_only", "installed"])]
+        allow_downgrade: bool,
+        /// Gemfile の `ruby` 指定や Gem の `required_ruby_version` を満たさない Ruby でも続行する
+        #[arg(long, conflicts_with_all = ["cache_only", "installed"])]
+        ignore_ruby_constraints: bool,
     },
     /// Flux 管理下の環境でコマンドを実行する
     Run {
+        /// バックグラウンドで起動し、すぐにプロンプトへ戻る
+        #[arg(short, long)]
+        detach: bool,
+        /// 隔離環境で利用できる実行ファイルの一覧を表示する
+        #[arg(long)]
+        list_bins: bool,
+        /// config.toml の [aliases] を展開結果とともに表示する
+        #[arg(long)]
+        list_aliases: bool,
+        /// 引数をつなげたコマンドラインをシェル (`sh -c`) で実行する
+        #[arg(short, long, conflicts_with = "detach")]
+        shell: bool,
+        /// [safety] confirm_patterns の確認を省略する ([safety] allow_yes_bypass = true の場合のみ)
+        #[arg(short, long)]
+        yes: bool,
+        /// Spring を止めず、arc の環境専用のサーバーを使う (SPRING_APPLICATION_ID を環境ごとにする)
+        #[arg(long)]
+        spring: bool,
+        /// HOME を .arc/env/home にして実行する ([run] sandbox_home を今回だけ有効にする)
+        #[arg(long)]
+        sandbox_home: bool,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// エイリアスを実行する (`arc run <alias>` の短縮形。エイリアス以外はエラー)
+    R {
+        /// エイリアス名と追加の引数
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+    /// プロジェクトのテストを実行する ([commands] test。未定義なら bin/rspec・rspec・rake test から推測)
+    Test {
+        /// テストコマンドに追加する引数
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// config.toml の [commands] に定義したタスクを実行し、タスク名で記録する
+    Task {
+        /// タスク名
+        name: String,
+        /// タスクのコマンドに追加する引数
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// `arc run --detach` で起動したプロセスの一覧を表示する
+    Ps,
+    /// `arc run --detach` で起動したプロセスを停止する
+    Stop {
+        /// run_start Signal の ID
+        id: String,
+    },
+    /// `arc run --detach` で起動したプロセスの出力、または `arc shell --transcript` の記録を再生する
+    Output {
+        /// run_start Signal (記録の場合は shell_enter Signal) の ID
+        id: String,
+        /// stdout の代わりに stderr を表示する
+        #[arg(long)]
+        stderr: bool,
+    },
+    /// (内部用) デタッチ実行の reaper
+    #[command(name = "__reap", hide = true)]
+    Reap {
+        /// 展開元のエイリアス名
+        #[arg(long)]
+        alias: Option<String>,
+        /// `arc run --spring`
+        #[arg(long)]
+        spring: bool,
+        /// `arc run --sandbox-home`
+        #[arg(long)]
+        sandbox_home: bool,
+        /// run_start の payload に加えるフィールド (JSON。`[safety]` の確認結果など)
+        #[arg(long)]
+        fields: Option<String>,
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         command: Vec<String>,
     },
+    /// 状態・履歴・詳細を表示し続ける端末のダッシュボード (`tui` feature でビルドした場合のみ)
+    Ui,
+    /// どの Signal からも参照されていない blob (.flux/blobs/) を削除する
+    Gc {
+        /// [signals] retention_days より古い Signal と、それだけが参照していた blob・出力を削除する
+        #[arg(long)]
+        retention: bool,
+        /// 削除するものを表示するだけにする
+        #[arg(long, requires = "retention")]
+        dry_run: bool,
+    },
+    /// signals.jsonl の古い形式 (v1) の Signal を現在の形式に書き換える
+    UpgradeLog,
+    /// プロジェクトの環境の一部を削除する
+    #[command(group(clap::ArgGroup::new("target").required(true).args(["runtime", "bootsnap", "home", "all"]).multiple(true)))]
+    Clean {
+        /// .arc/env/ruby_runtime を削除する (他の worktree と共有していれば警告する)
+        #[arg(long)]
+        runtime: bool,
+        /// bootsnap のキャッシュ (.arc/env/bootsnap) を削除する
+        #[arg(long)]
+        bootsnap: bool,
+        /// sandbox の HOME (.arc/env/home) を削除する
+        #[arg(long)]
+        home: bool,
+        /// --runtime・--bootsnap・--home をすべて行う
+        #[arg(long)]
+        all: bool,
+        /// 確認せずに削除する
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Gemfile.lock の依存関係を木構造で表示する
+    Tree {
+        /// この Gem を起点に表示する（省略時は Gemfile の直接依存すべて）
+        #[arg(conflicts_with = "invert")]
+        gem: Option<String>,
+        /// 指定した Gem に依存している Gem を表示する (辺を反転)
+        #[arg(short, long, value_name = "GEM")]
+        invert: Option<String>,
+        /// 表示する深さの上限
+        #[arg(long, value_name = "N")]
+        depth: Option<usize>,
+        /// 隣接リストを JSON 形式で出力する
+        #[arg(long)]
+        json: bool,
+    },
     /// 現在の arc 環境情報を表示する (Ruby パス・GEM_HOME 等)
-    Env,
+    Env {
+        /// ruby / gem / bundle / rake が隔離環境の PATH で正しく解決されるか確認する
+        #[arg(long)]
+        check_path: bool,
+        /// .arc/env.lock を現在の環境から作り直して比較し、ずれていれば失敗する (CI 向け)
+        #[arg(long, conflicts_with = "check_path")]
+        verify_lock: bool,
+        /// --check-path で追加で確認するコマンド
+        #[arg(requires = "check_path")]
+        names: Vec<String>,
+        #[command(subcommand)]
+        command: Option<EnvCommand>,
+    },
+    /// 実行を名前付きの基準として記録し、その後の実行と比べる
+    Baseline {
+        #[command(subcommand)]
+        command: BaselineCommand,
+    },
+    /// .arc/config.toml の設定を表示する
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Gemfile を操作する
+    Gemfile {
+        #[command(subcommand)]
+        command: GemfileCommand,
+    },
+    /// arc が隔離した bundler の設定 (.arc/bundle-config) を操作する
+    BundleConfig {
+        #[command(subcommand)]
+        command: BundleConfigCommand,
+    },
+    /// グローバルキャッシュを操作する
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// 登録済みのすべてのプロジェクトの直近の操作を新しい順に表示する
+    Recent {
+        /// 表示する件数
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// プロジェクトのパス付きの JSON で出力する
+        #[arg(long)]
+        json: bool,
+    },
+    /// 不具合報告に添付する環境情報をファイルにまとめる (匿名化済み)
+    Report {
+        /// Markdown の代わりに JSON で書き出す
+        #[arg(long)]
+        json: bool,
+        /// 出力先 (省略時は ./arc-report-<時刻>.md)
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Flux 履歴を Gem を含まないバンドル (tar.gz) に書き出す
+    Export {
+        /// 出力先のファイル (例: history.tar.gz)
+        #[arg(long, value_name = "FILE")]
+        bundle: PathBuf,
+        /// ユーザー名・ホスト名・環境変数の値を匿名化する
+        #[arg(long)]
+        redact: bool,
+        /// 出力などを含める合計サイズの上限 (MB)
+        #[arg(long, value_name = "MB", default_value_t = 64)]
+        max_size: u64,
+    },
+    /// `arc export --bundle` のバンドルから新しいプロジェクトを作成する
+    Import {
+        /// バンドルファイル
+        file: PathBuf,
+        /// 展開先のプロジェクトパス（省略時はカレントディレクトリ）
+        #[arg(default_value = ".")]
+        path: PathBuf,
+    },
     /// arc 管理下の隔離環境でインタラクティブシェルを起動する
-    Shell,
+    Shell {
+        /// セッション内で実行したコマンドを Flux ログに記録する (bash / zsh / fish)
+        #[arg(long)]
+        record_history: bool,
+        /// 端末の入出力を pty で中継し、`.flux/output/<shell_enter の ID>.transcript` に記録する
+        #[arg(long)]
+        transcript: bool,
+        /// 記録の形式 (`cast` は asciinema v2 互換。`arc output` が時間どおりに再生する)
+        #[arg(long, value_enum, value_name = "FORMAT", default_value = "text", requires = "transcript")]
+        transcript_format: TranscriptFormat,
+    },
+    /// `bin/` や `script/` の Ruby スクリプトを、直接実行しても arc の Ruby と隔離環境で動く binstub にする
+    Binstub {
+        /// 対象のスクリプト
+        #[arg(required_unless_present_any = ["all", "remove"], conflicts_with = "all")]
+        scripts: Vec<PathBuf>,
+        /// bin/ のすべての Ruby スクリプトを対象にする
+        #[arg(long)]
+        all: bool,
+        /// binstub を元のスクリプトに戻す (対象を省略すると bin/ と script/ のすべて)
+        #[arg(long)]
+        remove: bool,
+    },
+    /// シェルのプロンプト用に、プロジェクト名・Ruby・直近の実行結果を 1 行で出す (プロジェクトの外では何も出さない)
+    Prompt {
+        /// 出力の書式 (既定: '{project} {ruby} {last_status}')。
+        /// {project} {ruby} {last_status} {last_command} {failed_count_today} が使える
+        #[arg(long, value_name = "FORMAT", conflicts_with = "init")]
+        format: Option<String>,
+        /// プロンプトに組み込むスニペットを出力する
+        #[arg(long, value_enum, value_name = "SHELL")]
+        init: Option<PromptShell>,
+    },
+    /// 操作ログから依存の変更 (add / remove / Ruby の切り替え) を CHANGELOG にまとめる
+    Changelog {
+        /// この点より後の変更から (Signal ID / YYYY-MM-DD / RFC 3339 / Git のタグ)
+        #[arg(long, value_name = "POINT")]
+        since: Option<String>,
+        /// この点までの変更 (Signal ID / YYYY-MM-DD / RFC 3339 / Git のタグ)
+        #[arg(long, value_name = "POINT")]
+        until: Option<String>,
+        /// 出力の形式
+        #[arg(long, value_enum, default_value = "md")]
+        format: ChangelogFormat,
+        /// 取り消した操作と undo も載せる
+        #[arg(long)]
+        include_reverted: bool,
+        /// 出力先のファイル (省略時は標準出力)
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// 完了していない操作 (arc が途中で落ちた add / remove / undo) を調べる
+    Doctor {
+        /// Gemfile の状態に合わせて、記録を完了させるか破棄する
+        #[arg(long)]
+        resolve_intent: bool,
+    },
+    /// `.arc/workspace.toml` に並べた複数の arc プロジェクト (モノレポ) をまとめて操作する
+    Ws {
+        #[command(subcommand)]
+        command: WsCommand,
+    },
+    /// プロジェクトの外で使う Ruby の CLI ツール (rubocop など) を ~/.arc/tools に入れる
+    Tool {
+        #[command(subcommand)]
+        command: ToolCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WsCommand {
+    /// メンバーごとの Ruby バージョン・Gem 数・最後の失敗を表示する
+    State,
+    /// 各メンバーで `arc sync` を実行する
+    Sync {
+        /// 同時に実行するメンバー数 (既定は [parallel] jobs、なければ CPU 数)
+        #[arg(long, value_name = "N", alias = "parallel")]
+        max_parallel: Option<usize>,
+        /// 失敗したメンバーがあっても残りを実行する
+        #[arg(long)]
+        keep_going: bool,
+    },
+    /// 各メンバーの隔離環境でコマンドを実行する (`arc exec` と同じ)
+    Exec {
+        /// 同時に実行するメンバー数 (既定は [parallel] jobs、なければ CPU 数)
+        #[arg(long, value_name = "N", alias = "parallel")]
+        max_parallel: Option<usize>,
+        /// 失敗したメンバーがあっても残りを実行する
+        #[arg(long)]
+        keep_going: bool,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ToolCommand {
+    /// Gem を専用の GEM_HOME に入れ、~/.arc/bin にランチャーを作る
+    Install {
+        gem: String,
+        /// 入れるバージョン (省略時は最新)
+        #[arg(long, value_name = "VERSION")]
+        version: Option<String>,
+    },
+    /// インストール済みのツールを表示する (`*` はランチャーが指すバージョン)
+    List,
+    /// ツールとそのランチャーを削除する
+    Uninstall {
+        gem: String,
+        /// 削除するバージョン (省略時はすべて)
+        #[arg(long, value_name = "VERSION")]
+        version: Option<String>,
+    },
+    /// ランチャーを作らずにツールを実行する (入っていなければ一時的に入れる)
+    Run {
+        gem: String,
+        /// 使うバージョン (省略時はランチャーが指すもの、なければ最新)
+        #[arg(long, value_name = "VERSION")]
+        version: Option<String>,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// Gemfile.lock の Gem を、プロジェクトなしでグローバルキャッシュに入れておく
+    Warm {
+        /// 読み込む Gemfile.lock
+        #[arg(long, value_name = "PATH")]
+        from_lockfile: PathBuf,
+        /// Gem のインストールに使う Ruby バージョン (省略時は既定のバージョン)
+        #[arg(long, value_name = "VERSION")]
+        ruby: Option<String>,
+    },
+    /// グローバルキャッシュを削除する (レイアウトを移行できない場合など)
+    Clean {
+        /// Ruby と Gem を含むキャッシュ全体を削除する
+        #[arg(long, required = true)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum EnvCommand {
+    /// 別のプロジェクト (または保存した env.lock) と Ruby・bundler・Gem・設定を比べる。違いがあれば失敗する
+    Diff {
+        /// 比べる相手のプロジェクトのディレクトリか env.lock
+        other: PathBuf,
+        /// 比べる元 (省略時はカレントディレクトリのプロジェクト)
+        #[arg(long, value_name = "PATH")]
+        base: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BaselineCommand {
+    /// 実行を基準として記録する (既定は <name> と同じタスク・コマンドの最後の成功した実行)
+    Set {
+        name: String,
+        /// 基準にするタスク・コマンド (省略時は <name>)
+        #[arg(long, conflicts_with = "signal")]
+        command: Option<String>,
+        /// 基準にする実行の start / end Signal の ID (一意に決まる先頭部分でもよい)
+        #[arg(long, value_name = "ID")]
+        signal: Option<String>,
+    },
+    /// 最新の実行を基準と比べ、--max-regression を超えて遅いか失敗していれば失敗する
+    Compare {
+        name: String,
+        /// 比べるタスク・コマンド (省略時は基準のもの)
+        #[arg(long)]
+        command: Option<String>,
+        /// 許容する遅れ (10% / 10)
+        #[arg(long, value_name = "PCT", value_parser = crate::commands::baseline::parse_percent,
+              default_value_t = crate::commands::baseline::DEFAULT_MAX_REGRESSION)]
+        max_regression: f64,
+    },
+    /// 基準の一覧と、基準の実行がまだログにあるか
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// すべての設定を `section.key = value` の形式で表示する
+    List {
+        /// `${VAR}` を展開した値を表示する (既定ではファイルに書かれたまま)
+        #[arg(long)]
+        resolved: bool,
+    },
+    /// 1 つの設定を書き換え、変更を `config_set` Signal に記録する (例: `arc config set project.name shop`)
+    Set {
+        /// `section.key`
+        key: String,
+        /// 値 (TOML の値として読めればその型、読めなければ文字列)
+        value: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum GemfileCommand {
+    /// bundler に渡す前に Gemfile の書き間違い (閉じていない引用符・対応しない end・重複など) を調べる
+    Check {
+        /// 調べる Gemfile (省略時はカレントディレクトリの Gemfile)
+        #[arg(long, value_name = "PATH")]
+        path: Option<PathBuf>,
+    },
+    /// gem 行を各スコープの中で名前順に並べ、引用符とカンマの前後の空白をそろえる (既定は差分の表示だけ)
+    Sort {
+        /// 整形が必要なら差分を表示して失敗する (CI 用)
+        #[arg(long, conflicts_with = "write")]
+        check: bool,
+        /// 整形した内容を書き込み、記録する
+        #[arg(long)]
+        write: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BundleConfigCommand {
+    /// 設定する (例: jobs 4 → BUNDLE_JOBS: "4")
+    Set { key: String, value: String },
+    /// 設定を削除する
+    Unset { key: String },
+    /// 値を表示する
+    Get { key: String },
+    /// すべての設定を表示する
+    List,
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(args: &[&str]) -> String {
+        match Cli::try_parse_suggesting(args) {
+            Ok(_) => panic!("{:?} should not parse", args),
+            Err(e) => e.render().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_mistyped_subcommand_suggests_closest() {
+        assert!(parse_error(&["arc", "remve", "nokogiri"]).contains("tip: a similar subcommand exists: 'remove'\n"));
+        assert!(parse_error(&["arc", "boostrap"]).contains("tip: a similar subcommand exists: 'bootstrap'\n"));
+        assert!(parse_error(&["arc", "ws", "snyc"]).contains("tip: a similar subcommand exists: 'sync'\n"));
+        // 同じ距離なら clap の候補の順 (state / stats)
+        assert!(parse_error(&["arc", "stat"]).contains("tip: a similar subcommand exists: 'state'\n"));
+        // 近い候補がなければ clap の表示のまま
+        assert!(parse_error(&["arc", "deploy"]).contains("unrecognized subcommand 'deploy'"));
+    }
+
+    #[test]
+    fn test_clean_accepts_home_and_all() {
+        for flag in ["--home", "--all"] {
+            let cli = Cli::try_parse_from(["arc", "clean", flag, "--yes"]).unwrap();
+            assert!(matches!(cli.command, Commands::Clean { home, all, .. } if home || all), "{}", flag);
+        }
+        assert!(parse_error(&["arc", "clean", "--yes"]).contains("required arguments were not provided"));
+    }
 }
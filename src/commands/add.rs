@@ -0,0 +1,225 @@
+//! `arc add`: Gemfile に Gem を加えて記録し、bundle install を実行する。
+
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use crate::gemfile;
+use crate::gemfile_hash;
+use crate::signals::SignalType;
+
+use super::context::CommandContext;
+use super::exec::merge_fields;
+use super::gemfile::{check_gemfile, gemfile_issue_fields};
+use super::guard_mutation;
+use super::remove::{edit_and_record, print_gemfile_diff};
+use super::sync::install_after_edit;
+
+// ─────────────────────────────────────────────
+// arc add
+// ─────────────────────────────────────────────
+
+pub fn add(ctx: &CommandContext, gem_name: &str, version: Option<&str>, yes: bool, dry_run: bool) -> Result<()> {
+    add_at(ctx, gem_name, version, yes, dry_run || ctx.dry_run)
+}
+
+pub(super) fn add_at(ctx: &CommandContext, gem_name: &str, version: Option<&str>, yes: bool, dry_run: bool) -> Result<()> {
+    let project = ctx.project().context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    let cwd = ctx.root()?;
+    let gemfile_path = cwd.join("Gemfile");
+    if dry_run {
+        let edit = gemfile::Edit::InsertGem {
+            name: gem_name.to_string(),
+            version: version.map(String::from),
+            group: None,
+        };
+        print_gemfile_diff(&gemfile_path, &[edit])?;
+        crate::dry_run::note("would record `add` and run: bundle install");
+        return Ok(());
+    }
+    guard_mutation(project, cwd, yes)?;
+
+    let external_edit = gemfile_hash::warn_if_modified(&project.read_signals()?, &gemfile_path);
+    let edit = gemfile::Edit::InsertGem { name: gem_name.to_string(), version: version.map(String::from), group: None };
+    let Some(after) = gemfile::edited(&gemfile_path, &[edit])? else {
+        eprintln!("ℹ️  '{}' は既に Gemfile に存在します。スキップします。", gem_name);
+        return Ok(()); // 変更なし → install 不要
+    };
+
+    let issues = check_gemfile(&after)?;
+    let mut payload = json!({
+        "gem": gem_name,
+        "version": version,
+        super::undo_check::LINES_KEY: super::undo_check::lines_value(&gemfile::GemfileDoc::parse(&after).gem_lines(gem_name)),
+    });
+    merge_fields(&mut payload, gemfile_issue_fields(&issues));
+    edit_and_record(project, &gemfile_path, &after, format!("arc add {}", gem_name), SignalType::Add, payload, external_edit)?;
+    eprintln!("➕ Added '{}' to Gemfile", gem_name);
+
+    install_after_edit(project, cwd)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::commands::doctor::doctor_at;
+    use crate::commands::init::init_with;
+    use crate::commands::state_json;
+    use crate::commands::tests::{fake_bundle, preflight_ready, synced_project};
+    use crate::config::ArcConfig;
+    use crate::intent;
+    use crate::signals::FluxProject;
+
+    #[test]
+    fn test_add_detects_external_gemfile_edit() {
+        let cwd = synced_project("arc_external_edit_test");
+        fake_bundle(&cwd, "exit 0");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let _lock = preflight_ready(&cwd);
+        let last_add = || project.read_signals().unwrap().into_iter().rfind(|s| s.r_type == "add").unwrap();
+
+        add_at(&CommandContext::at(&cwd, false), "rake", None, true, false).unwrap();
+        let first = last_add();
+        assert_eq!(
+            first.payload[gemfile_hash::PAYLOAD_KEY],
+            gemfile_hash::hash(&cwd.join("Gemfile")).unwrap()
+        );
+        assert!(first.payload.get(gemfile_hash::EXTERNAL_EDIT_KEY).is_none());
+
+        // arc を通さずに Gemfile を編集する
+        let mut content = fs::read_to_string(cwd.join("Gemfile")).unwrap();
+        content.push_str("gem 'puma'\n");
+        fs::write(cwd.join("Gemfile"), content).unwrap();
+        let signals = project.read_signals().unwrap();
+        assert!(gemfile_hash::modified_since(&signals, &cwd.join("Gemfile")).is_some());
+
+        add_at(&CommandContext::at(&cwd, false), "rails", None, true, false).unwrap();
+        assert_eq!(last_add().payload[gemfile_hash::EXTERNAL_EDIT_KEY], true);
+
+        // arc 経由の変更だけなら検出しない
+        add_at(&CommandContext::at(&cwd, false), "sidekiq", None, true, false).unwrap();
+        assert!(last_add().payload.get(gemfile_hash::EXTERNAL_EDIT_KEY).is_none());
+
+        // --dry-run は Gemfile も Signal も変えない
+        let before = (fs::read_to_string(cwd.join("Gemfile")).unwrap(), project.read_signals().unwrap().len());
+        add_at(&CommandContext::at(&cwd, false), "puma", Some("~> 6.4"), true, true).unwrap();
+        assert_eq!((fs::read_to_string(cwd.join("Gemfile")).unwrap(), project.read_signals().unwrap().len()), before);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_add_checks_gemfile_before_bundler() {
+        let cwd = synced_project("arc_add_gemfile_check_test");
+        fake_bundle(&cwd, "exit 0");
+        let project = FluxProject::init(&cwd, &Default::default(), json!({})).unwrap().0;
+        let _lock = preflight_ready(&cwd);
+        let adds = || project.read_signals().unwrap().into_iter().filter(|s| s.r_type == "add").collect::<Vec<_>>();
+
+        // 重複は警告として記録して続ける
+        let content = fs::read_to_string(cwd.join("Gemfile")).unwrap();
+        fs::write(cwd.join("Gemfile"), format!("{}gem 'puma'\ngem 'puma'\n", content)).unwrap();
+        add_at(&CommandContext::at(&cwd, false), "rake", None, true, false).unwrap();
+        let issues = &adds()[0].payload["gemfile_issues"];
+        assert_eq!(issues[0]["severity"], "warning");
+        assert!(issues[0]["message"].as_str().unwrap().contains("'puma'"), "{}", issues);
+
+        // 閉じていないブロックがあれば Gemfile を変えず、Signal も記録しない
+        let broken = format!("{}group :test do\n", fs::read_to_string(cwd.join("Gemfile")).unwrap());
+        fs::write(cwd.join("Gemfile"), &broken).unwrap();
+        let err = add_at(&CommandContext::at(&cwd, false), "rails", None, true, false).unwrap_err().to_string();
+        assert!(err.contains("arc gemfile check"), "{}", err);
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), broken);
+        assert_eq!(adds().len(), 1);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_deterministic_mode_reproduces_log_byte_for_byte() {
+        // init → add → state --json を 2 回実行して、ログと出力を比べる
+        let run = || {
+            let cwd = synced_project("arc_deterministic_test");
+            fake_bundle(&cwd, "exit 0");
+            crate::deterministic::scoped(42, || {
+                init_with(&cwd, &ArcConfig::default(), json!({})).unwrap();
+                let _lock = preflight_ready(&cwd);
+                add_at(&CommandContext::at(&cwd, false), "rake", None, true, false).unwrap();
+                let project = FluxProject::open(&cwd).unwrap();
+                let mut state = Vec::new();
+                state_json::write_json(&mut state, project.iter_signals().unwrap(), |_| true, &Default::default()).unwrap();
+                (fs::read_to_string(&project.signal_file).unwrap(), String::from_utf8(state).unwrap(), cwd)
+            })
+        };
+        let (log, state, _) = run();
+        let (again, state_again, cwd) = run();
+        assert_eq!(log, again);
+        assert_eq!(state, state_again);
+
+        let signals = FluxProject::open(&cwd).unwrap().read_signals().unwrap();
+        let types: Vec<&str> = signals.iter().map(|s| s.r_type.as_str()).collect();
+        assert_eq!(types[..2], ["init", "add"]);
+        for (i, signal) in signals.iter().enumerate() {
+            assert_eq!(signal.id, crate::deterministic::signal_id(42, i as u64));
+            assert_eq!(signal.timestamp, crate::deterministic::timestamp(i as u64).to_rfc3339());
+        }
+
+        // 通常のモードで記録したログには書き足さない
+        let project = FluxProject::open(&cwd).unwrap();
+        project.record(SignalType::Add, json!({})).unwrap();
+        let err = crate::deterministic::scoped(42, || project.record(SignalType::Add, json!({}))).unwrap_err().to_string();
+        assert!(err.contains("ARC_DETERMINISTIC") && err.contains("記録しません"), "{}", err);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_add_is_resolved_from_intent() {
+        let cwd = synced_project("arc_intent_add_test");
+        fake_bundle(&cwd, "exit 0");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let _lock = preflight_ready(&cwd);
+        let adds = || project.read_signals().unwrap().into_iter().filter(|s| s.r_type == "add").collect::<Vec<_>>();
+
+        // Gemfile を書き換えた後、記録する前に落ちる → 記録を完了させる
+        intent::fail_at(Some(intent::AFTER_EDIT));
+        assert!(add_at(&CommandContext::at(&cwd, false), "rake", None, true, false).is_err());
+        intent::fail_at(None);
+        assert!(fs::read_to_string(cwd.join("Gemfile")).unwrap().contains("gem 'rake'"));
+        assert!(adds().is_empty());
+        let pending = intent::read(&project.flux_dir).unwrap().unwrap();
+        assert_eq!(pending.command, "arc add rake");
+
+        // 残っている間は次の変更を始めない
+        let err = add_at(&CommandContext::at(&cwd, false), "rails", None, true, false).unwrap_err().to_string();
+        assert!(err.contains("`arc add rake` が完了していない"), "{}", err);
+
+        doctor_at(&project, &cwd, false).unwrap();
+        assert!(intent::read(&project.flux_dir).unwrap().is_some());
+        doctor_at(&project, &cwd, true).unwrap();
+        assert_eq!(intent::read(&project.flux_dir).unwrap(), None);
+        let recorded = adds();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].id, pending.signal_id);
+        assert_eq!(recorded[0].payload["gem"], "rake");
+        assert_eq!(recorded[0].payload["resolved_intent"], true);
+        assert_eq!(recorded[0].payload[gemfile_hash::PAYLOAD_KEY], gemfile_hash::hash(&cwd.join("Gemfile")).unwrap());
+
+        // Gemfile を書き換える前に落ちる → 破棄する
+        let before = fs::read_to_string(cwd.join("Gemfile")).unwrap();
+        intent::fail_at(Some(intent::BEFORE_EDIT));
+        assert!(add_at(&CommandContext::at(&cwd, false), "puma", None, true, false).is_err());
+        intent::fail_at(None);
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), before);
+        doctor_at(&project, &cwd, true).unwrap();
+        assert_eq!(intent::read(&project.flux_dir).unwrap(), None);
+        assert_eq!(adds().len(), 1);
+
+        // 途中で落ちなければ intent は残らない
+        add_at(&CommandContext::at(&cwd, false), "puma", None, true, false).unwrap();
+        assert_eq!(intent::read(&project.flux_dir).unwrap(), None);
+        assert_eq!(adds().len(), 2);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
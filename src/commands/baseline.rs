@@ -0,0 +1,404 @@
+//! `arc baseline`: 実行を名前付きの基準として記録し、その後の実行と比べる (性能改善の CI ゲート)。
+//!
+//! `arc baseline set <name>` は基準にする実行 (既定は `<name>` と同じ名前のタスク・コマンドの最後の成功) を指す
+//! `x-baseline-set` Signal を記録する。実行時間と結果も Signal に写しておくため、`arc gc --retention` で
+//! 元の実行が消えても比べられる。基準の Signal 自体は名前ごとに最新のものが保存期間を過ぎても残る。
+//!
+//! `arc baseline compare <name>` は、同じコマンドの最新の実行 (基準の実行以外) を基準と比べ、
+//! `--max-regression` を超えて遅いか、基準は成功したのに失敗していれば 0 以外で終了する。
+
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use super::context::CommandContext;
+use crate::display;
+use crate::signals::{Signal, SignalType};
+use crate::state::{Execution, FluxState};
+
+/// 基準を記録する Signal の種別 (`x-baseline-set`) の component と name
+const COMPONENT: &str = "baseline";
+const NAME: &str = "set";
+/// `--max-regression` の既定値 (%)
+pub const DEFAULT_MAX_REGRESSION: f64 = 10.0;
+
+pub fn signal_type() -> SignalType {
+    SignalType::custom(COMPONENT, NAME).expect("valid custom type")
+}
+
+/// `x-baseline-set` Signal か
+pub fn is_baseline(signal: &Signal) -> bool {
+    signal.r_type == format!("x-{}-{}", COMPONENT, NAME)
+}
+
+/// `10%` / `10` を % の数値にする (`--max-regression`)
+pub fn parse_percent(s: &str) -> Result<f64, String> {
+    let value: f64 = s.trim().trim_end_matches('%').parse().map_err(|_| format!("'{}' is not a percentage (e.g. 10%)", s))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("'{}' must be a non-negative percentage", s));
+    }
+    Ok(value)
+}
+
+/// 記録した基準
+#[derive(Debug, Clone, PartialEq)]
+pub struct Baseline {
+    pub name: String,
+    /// 基準の実行の start Signal の ID
+    pub target_id: String,
+    /// 基準の実行の表示名 (タスク・エイリアス・コマンド)
+    pub command: String,
+    pub command_line: String,
+    /// 記録したときに写した実行時間
+    pub duration: Option<Duration>,
+    pub success: bool,
+    /// 基準を記録した時刻
+    pub set_at: String,
+}
+
+impl Baseline {
+    pub fn from_signal(signal: &Signal) -> Option<Self> {
+        if !is_baseline(signal) {
+            return None;
+        }
+        let payload = &signal.payload;
+        Some(Self {
+            name: payload["name"].as_str()?.to_string(),
+            target_id: payload["target_id"].as_str()?.to_string(),
+            command: payload["command"].as_str().unwrap_or_default().to_string(),
+            command_line: payload["command_line"].as_str().unwrap_or_default().to_string(),
+            duration: payload["duration_us"].as_u64().map(Duration::from_micros),
+            success: payload["success"].as_bool().unwrap_or(false),
+            set_at: signal.timestamp.clone(),
+        })
+    }
+
+    /// `x-baseline-set` Signal の payload
+    pub fn payload(name: &str, execution: &Execution) -> Value {
+        json!({
+            "name": name,
+            "target_id": execution.start_id,
+            "command": execution.display_name(),
+            "command_line": execution.command_line(),
+            "duration_us": execution.duration.map(|d| d.as_micros() as u64),
+            "success": execution.success,
+            "exit_code": execution.exit_code,
+            "ended_at": execution.ended_at.map(|t| t.to_rfc3339()),
+        })
+    }
+}
+
+/// 名前ごとの最新の基準 (名前順)
+pub fn defined(signals: &[Signal]) -> BTreeMap<String, Baseline> {
+    signals.iter().filter_map(Baseline::from_signal).map(|b| (b.name.clone(), b)).collect()
+}
+
+/// 名前で基準を探す。なければ定義されている名前を案内する
+pub fn find<'a>(baselines: &'a BTreeMap<String, Baseline>, name: &str) -> Result<&'a Baseline> {
+    if let Some(baseline) = baselines.get(name) {
+        return Ok(baseline);
+    }
+    let names: Vec<&str> = baselines.keys().map(String::as_str).collect();
+    match names.is_empty() {
+        true => bail!("No baseline named '{}'. Set one with `arc baseline set {}`", name, name),
+        false => bail!("No baseline named '{}' (defined: {})", name, names.join(", ")),
+    }
+}
+
+/// 名前ごとに最新の `x-baseline-set` Signal の位置 (保存期間を過ぎても残す)
+pub fn latest_positions(signals: &[Signal]) -> Vec<usize> {
+    let mut latest: BTreeMap<&str, usize> = BTreeMap::new();
+    for (i, signal) in signals.iter().enumerate() {
+        if is_baseline(signal)
+            && let Some(name) = signal.payload["name"].as_str()
+        {
+            latest.insert(name, i);
+        }
+    }
+    latest.into_values().collect()
+}
+
+/// 基準にする実行。`signal` (start / end の ID、一意に決まる先頭部分) を指定しなければ、
+/// `command` (省略時は `name`) の最後の成功した実行。
+pub fn resolve_target<'a>(
+    signals: &[Signal],
+    state: &'a FluxState,
+    name: &str,
+    command: Option<&str>,
+    signal: Option<&str>,
+) -> Result<&'a Execution> {
+    if let Some(id) = signal {
+        let target = &signals[crate::stats_compare::position_of(signals, id)?];
+        let start_id = target.payload["ref_id"].as_str().unwrap_or(&target.id);
+        return match state.executions.iter().find(|e| e.start_id == start_id) {
+            Some(execution) => Ok(execution),
+            None => bail!("Signal {} is not a finished execution", target.id),
+        };
+    }
+    let command = command.unwrap_or(name);
+    match state.executions.iter().rfind(|e| e.success && e.display_name() == command) {
+        Some(execution) => Ok(execution),
+        None => bail!("No successful run of '{}' to use as the baseline (use --command or --signal)", command),
+    }
+}
+
+/// 基準と最新の実行の比較
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub baseline: Duration,
+    pub baseline_success: bool,
+    /// 基準の実行がログに残っているか (なければ Signal に写した値で比べる)
+    pub target_present: bool,
+    pub current: Duration,
+    pub current_success: bool,
+    pub current_id: String,
+}
+
+impl Comparison {
+    /// 基準からの変化 (%)。遅くなれば正
+    pub fn delta_pct(&self) -> f64 {
+        match self.baseline.as_secs_f64() {
+            0.0 => 0.0,
+            base => (self.current.as_secs_f64() - base) / base * 100.0,
+        }
+    }
+
+    /// 悪化していればその理由
+    pub fn regression(&self, max_regression: f64) -> Option<String> {
+        if self.baseline_success && !self.current_success {
+            return Some("the latest run failed but the baseline succeeded".to_string());
+        }
+        let delta = self.delta_pct();
+        (delta > max_regression).then(|| format!("{:+.1}% is beyond the allowed {:.1}%", delta, max_regression))
+    }
+}
+
+/// `baseline` と、`command` (省略時は基準のコマンド) の最新の実行 (基準の実行以外) を比べる。
+pub fn compare(baseline: &Baseline, state: &FluxState, command: Option<&str>) -> Result<Comparison> {
+    let target = state.executions.iter().find(|e| e.start_id == baseline.target_id);
+    let Some(base_duration) = target.and_then(|e| e.duration).or(baseline.duration) else {
+        bail!("Baseline '{}' has no recorded duration", baseline.name);
+    };
+    let command = command.unwrap_or(&baseline.command);
+    let Some(current) = state.executions.iter().rfind(|e| e.display_name() == command && e.start_id != baseline.target_id) else {
+        bail!("No run of '{}' to compare with baseline '{}'", command, baseline.name);
+    };
+    let Some(current_duration) = current.duration else {
+        bail!("The latest run of '{}' ({}) has no duration", command, current.start_id);
+    };
+    Ok(Comparison {
+        baseline: base_duration,
+        baseline_success: target.map_or(baseline.success, |e| e.success),
+        target_present: target.is_some(),
+        current: current_duration,
+        current_success: current.success,
+        current_id: current.start_id.clone(),
+    })
+}
+
+fn status(success: bool) -> &'static str {
+    if success { "ok" } else { "failed" }
+}
+
+// ─────────────────────────────────────────────
+// エントリポイント
+// ─────────────────────────────────────────────
+
+/// `arc baseline set <name> [--command X] [--signal ID]`
+pub fn set(ctx: &CommandContext, name: &str, command: Option<&str>, signal: Option<&str>) -> Result<()> {
+    let project = ctx.project()?;
+    let signals = project.read_signals()?;
+    let state = FluxState::from_signals(&signals);
+    let execution = resolve_target(&signals, &state, name, command, signal)?;
+    project.record(signal_type(), Baseline::payload(name, execution))?;
+    println!(
+        "📌 Baseline '{}': {} ({}, {}) at {}",
+        name,
+        execution.command_line(),
+        execution.duration.map(display::fmt_duration).unwrap_or_else(|| "—".to_string()),
+        status(execution.success),
+        execution.start_id
+    );
+    Ok(())
+}
+
+/// `arc baseline compare <name> [--command X] [--max-regression PCT]`: 悪化していれば失敗する
+pub fn compare_cmd(ctx: &CommandContext, name: &str, command: Option<&str>, max_regression: f64) -> Result<()> {
+    let project = ctx.project()?;
+    let signals = project.read_signals()?;
+    let baselines = defined(&signals);
+    let baseline = find(&baselines, name)?;
+    let comparison = compare(baseline, &FluxState::from_signals(&signals), command)?;
+    let stored = if comparison.target_present { "" } else { " (stored; the run is no longer in the log)" };
+    println!("📌 Baseline '{}': {}", name, baseline.command_line);
+    println!("   baseline {:>10}  {}{}", display::fmt_duration(comparison.baseline), status(comparison.baseline_success), stored);
+    println!(
+        "   latest   {:>10}  {}  ({:+.1}%)",
+        display::fmt_duration(comparison.current),
+        status(comparison.current_success),
+        comparison.delta_pct()
+    );
+    if let Some(reason) = comparison.regression(max_regression) {
+        bail!("Regression against baseline '{}': {}", name, reason);
+    }
+    println!("✅ Within {:.1}% of the baseline", max_regression);
+    Ok(())
+}
+
+/// `arc baseline list`
+pub fn list(ctx: &CommandContext) -> Result<()> {
+    let project = ctx.project()?;
+    let signals = project.read_signals()?;
+    let baselines = defined(&signals);
+    if baselines.is_empty() {
+        println!("No baselines. Set one with `arc baseline set <name>`.");
+        return Ok(());
+    }
+    let state = FluxState::from_signals(&signals);
+    for line in list_lines(&baselines, &state) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+fn list_lines(baselines: &BTreeMap<String, Baseline>, state: &FluxState) -> Vec<String> {
+    let width = baselines.keys().map(|n| n.chars().count()).max().unwrap_or(0);
+    baselines
+        .values()
+        .map(|b| {
+            let present = state.executions.iter().any(|e| e.start_id == b.target_id);
+            format!(
+                "{:<width$}  {:>10}  {:<6}  {}  set {}  {}",
+                b.name,
+                b.duration.map(display::fmt_duration).unwrap_or_else(|| "—".to_string()),
+                status(b.success),
+                b.command_line,
+                display::fmt_timestamp(&b.set_at),
+                if present { "target present" } else { "target gone (stored duration)" },
+                width = width
+            )
+        })
+        .collect()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(id: &str, r_type: &str, second: u32, payload: Value) -> Signal {
+        Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: format!("2026-03-01T10:00:{:02}+09:00", second),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    /// rspec の実行 (`id`s / `id`e) を `seconds` 秒で
+    fn run(log: &mut Vec<Signal>, id: &str, seconds: u64, success: bool) {
+        let at = log.len() as u32;
+        log.push(signal(&format!("{}s", id), "run_start", at, json!({ "command": "rspec", "args": [], "cwd": "/p" })));
+        log.push(signal(
+            &format!("{}e", id),
+            "run_end",
+            at + 1,
+            json!({ "ref_id": format!("{}s", id), "exit_code": if success { 0 } else { 1 }, "success": success, "duration_us": seconds * 1_000_000 }),
+        ));
+    }
+
+    fn set_baseline(log: &mut Vec<Signal>, name: &str, command: Option<&str>, id: Option<&str>) -> Result<()> {
+        let state = FluxState::from_signals(log);
+        let payload = Baseline::payload(name, resolve_target(log, &state, name, command, id)?);
+        let at = log.len() as u32;
+        log.push(signal(&format!("b{}", at), "x-baseline-set", at, payload));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_defaults_to_last_success() {
+        let mut log = Vec::new();
+        run(&mut log, "r1", 140, true);
+        run(&mut log, "r2", 150, true);
+        run(&mut log, "r3", 90, false);
+        set_baseline(&mut log, "rspec", None, None).unwrap();
+        set_baseline(&mut log, "first", Some("rspec"), Some("r1e")).unwrap();
+        assert!(set_baseline(&mut log, "rake", None, None).unwrap_err().to_string().contains("'rake'"));
+
+        let baselines = defined(&log);
+        assert_eq!(baselines.keys().collect::<Vec<_>>(), ["first", "rspec"]);
+        assert_eq!(baselines["rspec"].target_id, "r2s");
+        assert_eq!(baselines["rspec"].duration, Some(Duration::from_secs(150)));
+        assert_eq!(baselines["first"].target_id, "r1s");
+
+        // 同じ名前で設定し直すと新しいほうが使われる
+        set_baseline(&mut log, "rspec", None, Some("r1s")).unwrap();
+        assert_eq!(defined(&log)["rspec"].target_id, "r1s");
+        assert_eq!(latest_positions(&log), [7, 8]);
+
+        // 保存期間を過ぎても名前ごとに最新の基準は残る
+        let horizon = chrono::DateTime::parse_from_rfc3339("2026-03-02T00:00:00+09:00").unwrap().to_utc();
+        let keep = super::super::retention::keep_mask(&log, horizon, &Default::default());
+        let kept: Vec<&str> = log.iter().zip(&keep).filter(|(_, k)| **k).map(|(s, _)| s.id.as_str()).collect();
+        assert_eq!(kept, ["b7", "b8"]);
+    }
+
+    #[test]
+    fn test_compare_within_and_beyond_tolerance() {
+        let mut log = Vec::new();
+        run(&mut log, "r1", 100, true);
+        set_baseline(&mut log, "suite", Some("rspec"), None).unwrap();
+        // 基準の実行しかなければ比べられない
+        let baseline = defined(&log)["suite"].clone();
+        assert!(compare(&baseline, &FluxState::from_signals(&log), None).is_err());
+
+        run(&mut log, "r2", 108, true);
+        let comparison = compare(&baseline, &FluxState::from_signals(&log), None).unwrap();
+        assert_eq!((comparison.current_id.as_str(), comparison.target_present), ("r2s", true));
+        assert!((comparison.delta_pct() - 8.0).abs() < 1e-9);
+        assert_eq!(comparison.regression(10.0), None);
+        assert_eq!(comparison.regression(5.0).unwrap(), "+8.0% is beyond the allowed 5.0%");
+
+        run(&mut log, "r3", 60, false);
+        let comparison = compare(&baseline, &FluxState::from_signals(&log), None).unwrap();
+        assert!(comparison.regression(10.0).unwrap().contains("failed"));
+        // 別のコマンドは比べる相手がない
+        assert!(compare(&baseline, &FluxState::from_signals(&log), Some("rake")).unwrap_err().to_string().contains("'rake'"));
+    }
+
+    #[test]
+    fn test_compare_uses_stored_duration_after_gc() {
+        let mut log = Vec::new();
+        run(&mut log, "r1", 100, true);
+        set_baseline(&mut log, "suite", Some("rspec"), None).unwrap();
+        run(&mut log, "r2", 125, true);
+        // 保存期間を過ぎて基準の実行が消えても、基準の Signal に写した値で比べる
+        let log: Vec<Signal> = log.into_iter().filter(|s| !s.id.starts_with("r1")).collect();
+        let baseline = defined(&log)["suite"].clone();
+        let state = FluxState::from_signals(&log);
+        let comparison = compare(&baseline, &state, None).unwrap();
+        assert!(!comparison.target_present);
+        assert_eq!(comparison.baseline, Duration::from_secs(100));
+        assert!(comparison.regression(20.0).is_some());
+        assert!(list_lines(&defined(&log), &state)[0].ends_with("target gone (stored duration)"));
+    }
+
+    #[test]
+    fn test_missing_baseline_and_percent() {
+        assert!(find(&defined(&[]), "suite").unwrap_err().to_string().contains("arc baseline set suite"));
+        let mut log = Vec::new();
+        run(&mut log, "r1", 100, true);
+        set_baseline(&mut log, "suite", Some("rspec"), None).unwrap();
+        assert_eq!(find(&defined(&log), "nightly").unwrap_err().to_string(), "No baseline named 'nightly' (defined: suite)");
+        assert_eq!(parse_percent("10%"), Ok(10.0));
+        assert_eq!(parse_percent("2.5"), Ok(2.5));
+        assert!(parse_percent("-1%").is_err());
+        assert!(parse_percent("fast").is_err());
+    }
+}
@@ -0,0 +1,429 @@
+//! `arc binstub`: プロジェクトのスクリプト (`bin/` / `script/`) を arc の Ruby で起動する binstub にする。
+//!
+//! `#!/usr/bin/env ruby` のスクリプトは `arc run` の中では隔離環境の Ruby で動くが、直接実行すると
+//! システムの Ruby で動いてしまう。binstub はスクリプト自身の位置から上へプロジェクトのルート
+//! (`.flux` のあるディレクトリ) を探し、`runner::isolated_env` と同じ環境変数を設定して arc の Ruby で
+//! 元のスクリプトを起動する。`.arc/env` がなければ警告を出して PATH の `ruby` で起動する。
+//!
+//! 元のスクリプトは `[exec] binstub_style` に従って残す:
+//!
+//! | style | `<name>` | `<name>.orig` |
+//! |---|---|---|
+//! | `wrap` (既定) | 元のスクリプトを起動するラッパー | 元のスクリプト (移動) |
+//! | `backup` | 起動用のヘッダー + 元の内容 (`ruby -x` で実行) | 元のスクリプトの複製 |
+//!
+//! `arc binstub --remove` は `<name>.orig` を戻す。
+
+use anyhow::{Context, Result, bail};
+use serde_json::json;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::BinstubStyle;
+use crate::dry_run;
+use crate::safe_path;
+use crate::signals::{ARC_ENV_DIR, FLUX_DIR, SignalType};
+
+use super::context::CommandContext;
+use super::runner::{self, EnvValue, ruby_bin};
+
+/// arc が生成した binstub であることを示すマーカー。これを含まないファイルは戻さない。
+const SHIM_MARKER: &str = "# arc script binstub";
+/// 元のスクリプトを残すファイル名の接尾辞
+const BACKUP_SUFFIX: &str = ".orig";
+/// `--all` で binstub にするスクリプトのディレクトリ
+const ALL_DIR: &str = "bin";
+/// `--remove` を対象なしで実行したときに戻すディレクトリ
+const SCRIPT_DIRS: [&str; 2] = ["bin", "script"];
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// スクリプトを binstub にしなかった理由
+#[derive(Debug, PartialEq)]
+pub enum Skip {
+    /// すでに binstub になっている
+    Installed,
+    /// 先頭行が Ruby のシバンではない
+    NotRuby,
+    /// `<name>.orig` がすでにある (上書きしない)
+    BackupExists,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Skip::Installed => "already a binstub",
+            Skip::NotRuby => "not a Ruby script (no ruby shebang)",
+            Skip::BackupExists => "a .orig file already exists",
+        })
+    }
+}
+
+// ─────────────────────────────────────────────
+// arc binstub
+// ─────────────────────────────────────────────
+
+/// `scripts` (`all` なら `bin/` のすべての Ruby スクリプト) を binstub にする。
+/// `remove` なら binstub を元に戻す (対象を省略すると、`all` なら `bin/`、それ以外は `bin/` と `script/` のすべて)。
+pub fn binstub(ctx: &CommandContext, scripts: &[PathBuf], all: bool, remove: bool) -> Result<()> {
+    let project = ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    let root = ctx.root()?.canonicalize()?;
+
+    let explicit = !scripts.is_empty();
+    let targets = if explicit {
+        scripts.iter().map(|s| safe_path::join_within(&root, s)).collect::<Result<Vec<_>>>()?
+    } else {
+        let dirs: &[&str] = if remove && !all { &SCRIPT_DIRS } else { &[ALL_DIR] };
+        // 戻すときは、名前を指定して作ったサブディレクトリの binstub も探す
+        let files = dirs.iter().flat_map(|dir| files_in(&root.join(dir), remove));
+        if remove { files.filter(|p| is_shim_file(p)).collect() } else { files.collect() }
+    };
+
+    let style = ctx.config()?.exec.binstub_style;
+    let mut touched = Vec::new();
+    for path in targets {
+        let display = path.strip_prefix(&root).unwrap_or(&path).display().to_string();
+        if remove {
+            if !is_shim_file(&path) {
+                eprintln!("⚠️  Skipped {}: not a binstub created by arc", display);
+                continue;
+            }
+            if ctx.dry_run {
+                dry_run::note(&format!("would restore {}", display));
+            } else {
+                restore(&path)?;
+                println!("↩️  {}: restored the original script", display);
+            }
+        } else {
+            if let Some(skip) = check(&path)? {
+                // --all ではシェルスクリプトなどを黙って飛ばす
+                if explicit || skip != Skip::NotRuby {
+                    eprintln!("⚠️  Skipped {}: {}", display, skip);
+                }
+                continue;
+            }
+            let shim = render(&root, &file_name(&path), style)?;
+            if ctx.dry_run {
+                dry_run::note(&format!("would write a binstub to {} ({})", display, style.as_str()));
+            } else {
+                install(&path, style, &shim)?;
+                println!("🔗 {}: runs with the arc Ruby (original kept in {}{})", display, display, BACKUP_SUFFIX);
+            }
+        }
+        touched.push(display);
+    }
+
+    if touched.is_empty() {
+        println!("Nothing to do.");
+        return Ok(());
+    }
+    let action = if remove { "remove" } else { "install" };
+    project.record(SignalType::Binstub, json!({ "action": action, "style": style.as_str(), "scripts": touched }))?;
+    Ok(())
+}
+
+/// `dir` 直下 (`recursive` ならサブディレクトリも) のファイル (`.orig` を除く、名前順)
+fn files_in(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+    paths.sort();
+    let mut files = Vec::new();
+    for path in paths {
+        let Ok(meta) = fs::symlink_metadata(&path) else { continue };
+        if meta.is_dir() && recursive {
+            files.extend(files_in(&path, true));
+        } else if path.is_file() && !path.to_string_lossy().ends_with(BACKUP_SUFFIX) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(BACKUP_SUFFIX);
+    PathBuf::from(backup)
+}
+
+// ─────────────────────────────────────────────
+// 生成・復元
+// ─────────────────────────────────────────────
+
+/// `path` を binstub にできるか調べる。できなければその理由。
+pub fn check(path: &Path) -> Result<Option<Skip>> {
+    let content = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let content = String::from_utf8_lossy(&content);
+    if shim_style(&content).is_some() {
+        return Ok(Some(Skip::Installed));
+    }
+    if !content.lines().next().is_some_and(is_ruby_shebang) {
+        return Ok(Some(Skip::NotRuby));
+    }
+    if fs::symlink_metadata(backup_path(path)).is_ok() {
+        return Ok(Some(Skip::BackupExists));
+    }
+    Ok(None)
+}
+
+/// `render` で作った `shim` で `path` を置き換え、元のスクリプトを `<name>.orig` に残す (`check` の後に呼ぶ)。
+pub fn install(path: &Path, style: BinstubStyle, shim: &str) -> Result<()> {
+    let backup = backup_path(path);
+    match style {
+        BinstubStyle::Wrap => {
+            fs::rename(path, &backup).with_context(|| format!("Failed to move {:?} to {:?}", path, backup))?;
+            fs::write(path, shim).with_context(|| format!("Failed to write {:?}", path))?;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        }
+        BinstubStyle::Backup => {
+            let original = fs::read(path)?;
+            fs::copy(path, &backup).with_context(|| format!("Failed to copy {:?} to {:?}", path, backup))?;
+            fs::write(path, [shim.as_bytes(), &original].concat()).with_context(|| format!("Failed to write {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+/// binstub の `path` を元のスクリプトに戻す。`backup` 形式で `.orig` が消えていれば、ヘッダーを取り除く。
+pub fn restore(path: &Path) -> Result<()> {
+    let backup = backup_path(path);
+    if backup.is_file() {
+        return fs::rename(&backup, path).with_context(|| format!("Failed to restore {:?}", path));
+    }
+    let content = fs::read_to_string(path)?;
+    match shim_style(&content) {
+        Some(BinstubStyle::Backup) => {
+            // 2 行目以降で最初の Ruby のシバンからが元の内容 (ruby -x と同じ規則)
+            let start = content.match_indices('\n').map(|(i, _)| i + 1).find(|&i| is_ruby_shebang(&content[i..]));
+            let Some(start) = start else { bail!("{:?} has no original script after the binstub header", path) };
+            fs::write(path, &content[start..]).with_context(|| format!("Failed to write {:?}", path))
+        }
+        _ => bail!("Cannot restore {:?}: {:?} is missing", path, backup),
+    }
+}
+
+/// `#!` で始まり `ruby` を含む行か
+fn is_ruby_shebang(line: &str) -> bool {
+    let line = line.lines().next().unwrap_or_default();
+    line.starts_with("#!") && line.contains("ruby")
+}
+
+fn is_shim_file(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|c| shim_style(&String::from_utf8_lossy(&c)).is_some())
+}
+
+/// binstub のマーカー行から形式を取り出す
+fn shim_style(content: &str) -> Option<BinstubStyle> {
+    let rest = content.lines().take(2).find_map(|l| l.strip_prefix(SHIM_MARKER))?;
+    match rest.trim().strip_prefix("(style: ")?.strip_suffix(')')? {
+        "wrap" => Some(BinstubStyle::Wrap),
+        "backup" => Some(BinstubStyle::Backup),
+        _ => None,
+    }
+}
+
+/// スクリプト `name` の binstub (`backup` 形式ではヘッダー部分)。
+/// 環境変数は `runner::isolated_env` から作り、プロジェクト内のパスは実行時に見つけたルート (`$root`) からにする。
+pub fn render(root: &Path, name: &str, style: BinstubStyle) -> Result<String> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let path = |p: &Path| shell_path(p, root, home.as_deref());
+    let ruby = path(&ruby_bin(&root.join(ARC_ENV_DIR)));
+    let (target, ruby_args) = match style {
+        BinstubStyle::Wrap => (format!("{}{}", name, BACKUP_SUFFIX), ""),
+        BinstubStyle::Backup => (name.to_string(), "-x "),
+    };
+
+    let mut script = format!(
+        "#!/bin/sh\n\
+         {SHIM_MARKER} (style: {style})\n\
+         # Generated by `arc binstub`. Do not edit; `arc binstub --remove` restores the original script.\n\
+         script_dir=\"$(cd \"$(dirname \"$0\")\" && pwd)\"\n\
+         script=\"$script_dir/{target}\"\n\
+         root=\"$script_dir\"\n\
+         while [ ! -d \"$root/{FLUX_DIR}\" ] && [ \"$root\" != / ]; do\n\
+         \x20   root=\"$(dirname \"$root\")\"\n\
+         done\n\
+         if [ ! -x \"{ruby}\" ]; then\n\
+         \x20   echo \"arc: {ARC_ENV_DIR} not found for $0; running it with the ruby on PATH (run \\`arc bootstrap\\`)\" >&2\n\
+         \x20   exec /usr/bin/env ruby {ruby_args}\"$script\" \"$@\"\n\
+         fi\n",
+        style = style.as_str(),
+        target = escape(&target),
+    );
+    for (var, value) in runner::isolated_env(root)? {
+        let value = match value {
+            EnvValue::Set(p) => path(&p),
+            EnvValue::Prepend(dirs) => {
+                let dirs: Vec<String> = dirs.iter().map(|d| path(d)).collect();
+                format!("{}${{{var}:+:${var}}}", dirs.join(":"))
+            }
+        };
+        script.push_str(&format!("{var}=\"{value}\"\nexport {var}\n"));
+    }
+    script.push_str(&format!("exec \"{ruby}\" {ruby_args}\"$script\" \"$@\"\n"));
+    Ok(script)
+}
+
+/// binstub の中で使うパス (二重引用符の中に置く形)。プロジェクト内は `$root/…`、ホーム内は `$HOME/…` にする。
+fn shell_path(path: &Path, root: &Path, home: Option<&Path>) -> String {
+    let prefixed = |var: &str, rel: &Path| match rel.as_os_str().is_empty() {
+        true => var.to_string(),
+        false => format!("{}/{}", var, escape(&rel.to_string_lossy())),
+    };
+    if let Ok(rel) = path.strip_prefix(root) {
+        return prefixed("$root", rel);
+    }
+    if let Some(rel) = home.and_then(|home| path.strip_prefix(home).ok()) {
+        return prefixed("$HOME", rel);
+    }
+    escape(&path.to_string_lossy())
+}
+
+/// 二重引用符の中で特別な意味を持つ文字をエスケープする
+fn escape(s: &str) -> String {
+    s.chars().fold(String::with_capacity(s.len()), |mut out, c| {
+        if matches!(c, '\\' | '"' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    const SCRIPT: &str = "#!/usr/bin/env ruby\nputs ARGV.inspect\n";
+
+    /// `.flux` と、受け取った GEM_HOME と引数を表示する偽の Ruby を持つプロジェクト
+    fn fixture(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(FLUX_DIR)).unwrap();
+        let env_dir = root.join(ARC_ENV_DIR);
+        fs::create_dir_all(runner::ruby_runtime_bin(&env_dir)).unwrap();
+        executable(&ruby_bin(&env_dir), "#!/bin/sh\necho \"arc-ruby GEM_HOME=$GEM_HOME $*\"\n");
+        let root = root.canonicalize().unwrap();
+        executable(&root.join("bin/console"), SCRIPT);
+        root
+    }
+
+    fn executable(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn stub(root: &Path, path: &Path, style: BinstubStyle) {
+        assert_eq!(check(path).unwrap(), None);
+        install(path, style, &render(root, &file_name(path), style).unwrap()).unwrap();
+    }
+
+    /// 別のディレクトリから binstub を実行し、(stdout, stderr) を返す
+    fn run(path: &Path, path_env: Option<&Path>) -> (String, String) {
+        let mut command = Command::new("/bin/sh");
+        command.arg(path).arg("a b").current_dir(std::env::temp_dir());
+        if let Some(dir) = path_env {
+            command.env("PATH", env::join_paths([dir.to_path_buf(), PathBuf::from("/usr/bin"), PathBuf::from("/bin")]).unwrap());
+        }
+        let output = command.output().unwrap();
+        (String::from_utf8_lossy(&output.stdout).trim().to_string(), String::from_utf8_lossy(&output.stderr).to_string())
+    }
+
+    #[test]
+    fn test_render_uses_isolated_env() {
+        let root = fixture("arc_binstub_render_test");
+        let shim = render(&root, "console", BinstubStyle::Wrap).unwrap();
+        assert!(shim.starts_with("#!/bin/sh\n# arc script binstub (style: wrap)\n"));
+        assert!(shim.contains("script=\"$script_dir/console.orig\"\n"));
+        assert!(shim.contains("GEM_HOME=\"$root/.arc/env\"\nexport GEM_HOME\n"));
+        assert!(shim.contains("PATH=\"$root/.arc/env/ruby_runtime/bin:$root/.arc/env/bin${PATH:+:$PATH}\"\n"));
+        assert!(shim.ends_with("exec \"$root/.arc/env/ruby_runtime/bin/ruby\" \"$script\" \"$@\"\n"));
+        assert!(!shim.contains(&root.to_string_lossy().to_string()));
+        assert_eq!(shim_style(&shim), Some(BinstubStyle::Wrap));
+
+        let backup = render(&root, "console", BinstubStyle::Backup).unwrap();
+        assert!(backup.contains("script=\"$script_dir/console\"\n"));
+        assert!(backup.ends_with("ruby\" -x \"$script\" \"$@\"\n"));
+        assert_eq!(escape("a\"$`\\b"), "a\\\"\\$\\`\\\\b");
+
+        // ランタイムがなければ作らない (runner と同じエラー)
+        fs::remove_dir_all(root.join(ARC_ENV_DIR)).unwrap();
+        assert!(render(&root, "console", BinstubStyle::Wrap).unwrap_err().to_string().contains("arc bootstrap"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_wrap_finds_root_from_any_depth() {
+        let root = fixture("arc_binstub_depth_test");
+        let deep = root.join("script/tools/db/seed");
+        executable(&deep, SCRIPT);
+        stub(&root, &deep, BinstubStyle::Wrap);
+        stub(&root, &root.join("bin/console"), BinstubStyle::Wrap);
+
+        assert_eq!(fs::read_to_string(backup_path(&deep)).unwrap(), SCRIPT);
+        let (out, _) = run(&deep, None);
+        assert_eq!(out, format!("arc-ruby GEM_HOME={}/.arc/env {}.orig a b", root.display(), deep.display()));
+        let (out, _) = run(&root.join("bin/console"), None);
+        assert!(out.ends_with("bin/console.orig a b"), "{}", out);
+        assert_eq!(check(&deep).unwrap(), Some(Skip::Installed));
+
+        restore(&deep).unwrap();
+        assert_eq!(fs::read_to_string(&deep).unwrap(), SCRIPT);
+        assert!(!backup_path(&deep).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_falls_back_to_path_ruby_without_env() {
+        let root = fixture("arc_binstub_fallback_test");
+        let console = root.join("bin/console");
+        stub(&root, &console, BinstubStyle::Backup);
+        assert_eq!(fs::read_to_string(backup_path(&console)).unwrap(), SCRIPT);
+        assert!(fs::read_to_string(&console).unwrap().ends_with(&format!("\"$@\"\n{}", SCRIPT)));
+
+        fs::remove_dir_all(root.join(ARC_ENV_DIR)).unwrap();
+        let system = root.join("system-bin");
+        executable(&system.join("ruby"), "#!/bin/sh\necho \"system-ruby $*\"\n");
+        let (out, err) = run(&console, Some(&system));
+        assert_eq!(out, format!("system-ruby -x {} a b", console.display()));
+        assert!(err.contains("arc: .arc/env not found") && err.contains("arc bootstrap"), "{}", err);
+
+        // .orig が消えていても、ヘッダーを取り除いて戻せる
+        fs::remove_file(backup_path(&console)).unwrap();
+        restore(&console).unwrap();
+        assert_eq!(fs::read_to_string(&console).unwrap(), SCRIPT);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_check_skips() {
+        let root = fixture("arc_binstub_check_test");
+        executable(&root.join("bin/setup"), "#!/bin/bash\necho setup\n");
+        assert_eq!(check(&root.join("bin/setup")).unwrap(), Some(Skip::NotRuby));
+        fs::write(root.join("bin/console.orig"), "mine").unwrap();
+        assert_eq!(check(&root.join("bin/console")).unwrap(), Some(Skip::BackupExists));
+        executable(&root.join("bin/sub/deeper"), SCRIPT);
+        assert_eq!(files_in(&root.join("bin"), false), [root.join("bin/console"), root.join("bin/setup")]);
+        assert_eq!(files_in(&root.join("bin"), true).len(), 3);
+        // wrap で .orig がなければ戻せない
+        fs::write(root.join("bin/other"), render(&root, "other", BinstubStyle::Wrap).unwrap()).unwrap();
+        assert!(restore(&root.join("bin/other")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
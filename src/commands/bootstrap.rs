@@ -0,0 +1,411 @@
+//! `arc bootstrap`: Ruby をグローバルキャッシュに用意し、プロジェクトの `.arc/env/ruby_runtime` にリンクする。
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::{env, fs};
+use std::path::Path;
+
+use crate::cache_layout;
+use crate::config::ArcConfig;
+use crate::display;
+use crate::perms;
+use crate::progress::{self, ProgressEvent};
+use crate::signals::SignalType;
+use crate::worktree;
+
+use super::context::CommandContext;
+use super::fsops::{fmt_link_report, path_str};
+use super::gem_cache::check_cache_layout;
+use super::sync::measure_env;
+
+// ─────────────────────────────────────────────
+// arc bootstrap (Global Cache 対応)
+// ─────────────────────────────────────────────
+
+pub(super) fn resolve_ruby_id(version: &str) -> String {
+    format!("{}-{}-{}", version, env::consts::OS, env::consts::ARCH)
+}
+
+/// ruby-builder のアセット名の接尾辞を上書きする環境変数 (config.toml の `platform_suffix` より優先)
+pub(super) const RUBY_PLATFORM_ENV: &str = "ARC_RUBY_PLATFORM";
+
+/// OS / アーキテクチャごとの ruby-builder のアセット名の接尾辞。
+/// 先頭から順に試す (新しい Ubuntu 向けのアセットがなければ古いものにフォールバックする)。
+const PLATFORM_SUFFIXES: [(&str, &str, &[&str]); 2] = [
+    ("linux", "x86_64", &["ubuntu-24.04", "ubuntu-22.04"]),
+    ("linux", "aarch64", &["ubuntu-24.04-arm64", "ubuntu-22.04-arm64"]),
+];
+
+/// 試す接尾辞の一覧 (先頭から順に試す)。`override_suffix` があればそれだけを使う。
+pub(super) fn resolve_platform(os: &str, arch: &str, override_suffix: Option<&str>) -> Result<Vec<String>> {
+    if let Some(suffix) = override_suffix {
+        return Ok(vec![suffix.to_string()]);
+    }
+    match PLATFORM_SUFFIXES.iter().find(|(o, a, _)| *o == os && *a == arch) {
+        Some((_, _, suffixes)) => Ok(suffixes.iter().map(|s| s.to_string()).collect()),
+        None => anyhow::bail!(
+            "未対応のプラットフォームです: {} / {}\n\
+             ruby-builder のアセットを指定してください (動作は保証されません):\n\
+             \x20 - .arc/config.toml の [ruby] に platform_suffix = \"ubuntu-22.04\"\n\
+             \x20 - 環境変数 {}=ubuntu-22.04",
+            os, arch, RUBY_PLATFORM_ENV
+        ),
+    }
+}
+
+/// この環境で試す接尾辞。`ARC_RUBY_PLATFORM` > config.toml の `platform_suffix` > 自動選択。
+pub(super) fn ruby_platforms(config_suffix: Option<&str>) -> Result<Vec<String>> {
+    let from_env = env::var(RUBY_PLATFORM_ENV).ok().filter(|s| !s.is_empty());
+    let override_suffix = from_env.as_deref().or(config_suffix);
+    if let Some(suffix) = override_suffix {
+        progress::human(&format!(
+            "⚠️  Using the ruby-builder asset for '{}' (overridden); compatibility is not guaranteed.",
+            suffix
+        ));
+    }
+    resolve_platform(env::consts::OS, env::consts::ARCH, override_suffix)
+}
+
+pub(super) fn resolve_ruby_url(version: &str, suffix: &str) -> String {
+    format!(
+        "https://github.com/ruby/ruby-builder/releases/download/toolcache/ruby-{}-{}.tar.gz",
+        version, suffix
+    )
+}
+
+/// `versions`: CLI 引数で指定されたバージョン。空の場合は config.toml を参照する。
+/// `cache_only` の場合はプロジェクトなしで、各バージョンをグローバルキャッシュに入れるだけ。
+/// `installed` / `use_version` はキャッシュ済みの Ruby の一覧と切り替え (ネットワークを使わない)。
+pub fn bootstrap(
+    ctx: &CommandContext,
+    versions: &[String],
+    cache_only: bool,
+    installed: bool,
+    use_version: Option<&str>,
+    allow_downgrade: bool,
+    ignore_ruby_constraints: bool,
+) -> Result<()> {
+    let rubies = crate::signals::get_global_cache_dir()?.join(cache_layout::RUBIES_DIR);
+    if installed {
+        return super::rubies::list_installed(ctx, &rubies);
+    }
+    if let Some(version) = use_version {
+        check_cache_layout()?;
+        return super::rubies::use_cached(ctx, version, &rubies, allow_downgrade, ignore_ruby_constraints);
+    }
+    if cache_only {
+        check_cache_layout()?;
+        let suffixes = ruby_platforms(ctx.config().ok().and_then(|c| c.ruby.platform_suffix.as_deref()))?;
+        return warm_rubies(&rubies, versions, &|dir, v| Ok(download_ruby_to_cache(dir, v, &suffixes)?.bytes));
+    }
+    if versions.len() > 1 {
+        anyhow::bail!("複数のバージョンを指定できるのは --cache-only の場合だけです。");
+    }
+    bootstrap_at(ctx, versions.first().map(String::as_str), allow_downgrade, ignore_ruby_constraints)
+}
+
+/// 各バージョンの Ruby を `rubies_root` (`~/.arc/cache/rubies`) に用意する。
+/// プロジェクトへのリンクも Signal の記録もしない。失敗したバージョンがあれば最後にエラーを返す。
+pub(super) fn warm_rubies(rubies_root: &Path, versions: &[String], download: &dyn Fn(&Path, &str) -> Result<u64>) -> Result<()> {
+    let mut failed = Vec::new();
+    for version in versions {
+        let cache_dir = rubies_root.join(resolve_ruby_id(version));
+        if cache_layout::is_complete(&cache_dir) {
+            progress::human(&format!("✨ Cache Hit: Ruby {} found in global cache.", version));
+            continue;
+        }
+        discard_incomplete_ruby(&cache_dir)?;
+        match download(&cache_dir, version).and_then(|bytes| cache_layout::mark_complete(&cache_dir).map(|_| bytes)) {
+            Ok(bytes) => progress::human(&format!("📦 Ruby {} cached ({})", version, display::fmt_bytes(bytes))),
+            Err(e) => {
+                progress::human(&format!("❌ Ruby {}: {:#}", version, e));
+                failed.push(version.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!("{} / {} バージョンの取得に失敗しました: {}", failed.len(), versions.len(), failed.join(", "));
+    }
+    Ok(())
+}
+
+/// `.complete` のない (展開の途中で止まった) Ruby を消す。dry-run では何もしない。
+fn discard_incomplete_ruby(cache_dir: &Path) -> Result<()> {
+    if crate::dry_run::is_enabled() || fs::symlink_metadata(cache_dir).is_err() {
+        return Ok(());
+    }
+    progress::human(&format!("🧹 Removing an incomplete Ruby in the cache: {}", cache_dir.display()));
+    fs::remove_dir_all(cache_dir).with_context(|| format!("Failed to remove {:?}", cache_dir))
+}
+
+pub(super) fn bootstrap_at(ctx: &CommandContext, version_arg: Option<&str>, allow_downgrade: bool, ignore_ruby_constraints: bool) -> Result<()> {
+    let project = ctx.project().context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    let cwd = ctx.root()?;
+    check_cache_layout()?;
+
+    // バージョン解決: 引数 > config.toml の順で優先
+    let config = ctx.config()?;
+    let ruby_version = version_arg.map_or_else(|| config.ruby.version.clone(), String::from);
+    // 最後に入れた Ruby より古ければ、config.toml を変える前に確認する
+    let downgraded_from = super::rubies::guard_downgrade_on_terminal(project, &ruby_version, allow_downgrade)?;
+    // Gemfile の `ruby` 指定とロックされた Gem の required_ruby_version を満たすか
+    let ruby_constraints = super::ruby_constraints::guard(cwd, &ruby_version, ignore_ruby_constraints)?;
+    if let Some(v) = version_arg {
+        // 引数で指定された場合は config.toml を更新して永続化
+        if ctx.dry_run {
+            crate::dry_run::note(&format!("would set the Ruby version to {} in .arc/config.toml", v));
+        } else {
+            ArcConfig::update(&project.flux_dir, |c| c.ruby.version = v.to_string())?;
+            progress::human(&format!("📝 Ruby version set to {} in .arc/config.toml", v));
+        }
+    }
+
+    let cache_dir = crate::signals::get_global_cache_dir()?
+        .join(cache_layout::RUBIES_DIR)
+        .join(resolve_ruby_id(&ruby_version));
+    let ruby_dest = cwd.join(crate::signals::ARC_ENV_DIR).join("ruby_runtime");
+
+    project.ensure_env_usable()?;
+    if let Some(ref target) = project.env_storage.symlink_target {
+        progress::human(&format!("ℹ️  {} → {}", crate::signals::ARC_ENV_DIR, target.display()));
+    }
+    // exists() はリンクを辿るため、リンク切れの ruby_runtime も「存在する」とみなす
+    if fs::symlink_metadata(&ruby_dest).is_ok() {
+        progress::human(&format!("ℹ️  Ruby 実行環境は既にプロジェクト内に存在します: {:?}", ruby_dest));
+        progress::human("   キャッシュ済みのバージョンに切り替える場合は `arc bootstrap --use <version>` を実行してください。");
+        progress::emit(&ProgressEvent::Summary { operation: "bootstrap".to_string(), success: true, duration_us: 0 });
+        return Ok(());
+    }
+
+    let timer = std::time::Instant::now();
+    // [env] share_runtime = "per-repo" なら worktree 間で共有する配置先
+    let shared_base = worktree::shared_dir(&config.env, cwd, &crate::signals::get_global_arc_dir()?);
+    let shared = shared_base.as_ref().map(|base| worktree::shared_runtime(base, &resolve_ruby_id(&ruby_version)));
+    let reuse_shared = shared.as_ref().is_some_and(|s| s.exists());
+
+    // 1. グローバルキャッシュにあるか確認 (他の worktree が共有先に配置済みなら不要)
+    let cache_hit = cache_layout::is_complete(&cache_dir);
+    let download = if reuse_shared {
+        progress::human(&format!("🔗 Ruby {} is already shared by another worktree.", ruby_version));
+        None
+    } else if cache_hit {
+        progress::human(&format!("✨ Cache Hit: Ruby {} found in global cache.", ruby_version));
+        None
+    } else {
+        let suffixes = ruby_platforms(config.ruby.platform_suffix.as_deref())?;
+        discard_incomplete_ruby(&cache_dir)?;
+        let download = progress::phase("download_ruby", || download_ruby_to_cache(&cache_dir, &ruby_version, &suffixes))?;
+        if !ctx.dry_run {
+            cache_layout::mark_complete(&cache_dir)?;
+        }
+        Some(download)
+    };
+
+    // 2. キャッシュからプロジェクト (または共有先) へリンク/コピー
+    progress::human("⚡ Linking Ruby to project environment...");
+    let link_mode = config.cache.link_mode;
+    let linked = if ctx.dry_run {
+        let target = shared.as_deref().unwrap_or(&ruby_dest);
+        crate::dry_run::note(&format!("would link {} → {}", cache_dir.display(), target.display()));
+        None
+    } else {
+        progress::phase("link_ruby", || worktree::place_runtime(&cache_dir, &ruby_dest, shared.as_deref(), link_mode))?
+    };
+    match (&linked, &shared) {
+        (Some(linked), _) => progress::human(&fmt_link_report(linked, link_mode)),
+        (None, Some(shared)) => progress::human(&format!("🔗 ruby_runtime → {}", shared.display())),
+        (None, None) => {}
+    }
+    if config.env.share_gems
+        && !ctx.dry_run
+        && let Some(base) = &shared_base
+        && !worktree::share_gem_dir(&cwd.join(crate::signals::ARC_ENV_DIR).join("ruby"), base)?
+    {
+        progress::human("⚠️  .arc/env/ruby already exists in this worktree; gems are not shared (remove it to share)");
+    }
+
+    project.record(
+        SignalType::Bootstrap,
+        json!({
+            "ruby_version": ruby_version,
+            "downgraded_from": downgraded_from,
+            "ruby_constraints": ruby_constraints,
+            "cache_hit":    cache_hit || reuse_shared,
+            "dest":         ruby_dest.to_string_lossy(),
+            "shared":       shared.as_ref().map(|s| s.to_string_lossy()),
+            "link":         linked.as_ref().map(|l| l.to_json(link_mode)),
+            "bytes_linked":     linked.as_ref().map_or(0, |l| l.bytes),
+            "bytes_downloaded": download.as_ref().map_or(0, |d| d.bytes),
+            "download_attempts": download.as_ref().map(|d| &d.attempts),
+            "duration_us":  timer.elapsed().as_micros() as u64,
+            "disk":         measure_env(cwd, config).map(|size| size.to_json()),
+        }),
+    )?;
+
+    progress::human(&format!("✨ Ruby {} bootstrap complete!", ruby_version));
+    progress::emit(&ProgressEvent::Summary {
+        operation: "bootstrap".to_string(),
+        success: true,
+        duration_us: timer.elapsed().as_micros() as u64,
+    });
+    Ok(())
+}
+
+/// `download_ruby_to_cache` の結果。
+pub(super) struct RubyDownload {
+    pub(super) bytes: u64,
+    /// 試した接尾辞 (順に、最後のものが成功)
+    attempts: Vec<String>,
+}
+
+/// `suffixes` を順に `fetch` し、最初に成功した接尾辞と試した接尾辞の一覧を返す。
+pub(super) fn fetch_first(suffixes: &[String], fetch: &mut dyn FnMut(&str) -> Result<bool>) -> Result<(String, Vec<String>)> {
+    let mut attempts = Vec::new();
+    for suffix in suffixes {
+        attempts.push(suffix.clone());
+        if fetch(suffix)? {
+            return Ok((suffix.clone(), attempts));
+        }
+    }
+    anyhow::bail!("Ruby バイナリのダウンロードに失敗しました (試したアセット: {})。", attempts.join(", "))
+}
+
+/// Ruby バイナリをダウンロードしてキャッシュディレクトリに展開する。
+/// `suffixes` のアセットを順に試す。失敗した場合はキャッシュディレクトリを削除してエラーを返す。
+pub(super) fn download_ruby_to_cache(cache_dir: &Path, ruby_version: &str, suffixes: &[String]) -> Result<RubyDownload> {
+    progress::human(&format!("🚀 Cache Miss: Downloading Ruby {} from ruby-builder...", ruby_version));
+    if !crate::dry_run::is_enabled() {
+        perms::create_dir_all(cache_dir).context("キャッシュディレクトリの作成に失敗しました")?;
+    }
+
+    let tmp_archive = cache_dir.join("download.tar.gz");
+
+    // --progress-json の間は curl の進捗バーの代わりに、書き込まれたバイト数を定期的に出す
+    let progress_flag = if progress::is_json() { "--silent" } else { "--progress-bar" };
+    let mut fetch = |suffix: &str| -> Result<bool> {
+        let ruby_url = resolve_ruby_url(ruby_version, suffix);
+        let mut curl = std::process::Command::new("curl");
+        curl.args(["-fSL", progress_flag, "-o", path_str(&tmp_archive)?, &ruby_url]);
+        let mut tick = || {
+            let bytes = fs::metadata(&tmp_archive).map(|m| m.len()).unwrap_or(0);
+            progress::emit(&ProgressEvent::Download { bytes, total: None });
+        };
+        let tick: Option<&mut dyn FnMut()> = if progress::is_json() { Some(&mut tick) } else { None };
+        let ok = progress::run_child(&mut curl, &format!("curl {}", ruby_url), tick)
+            .context("curl の起動に失敗しました")?
+            .success();
+        if !ok && suffixes.last().is_some_and(|last| last != suffix) {
+            progress::human(&format!("   {} is not available; trying the next asset...", ruby_url));
+        }
+        Ok(ok)
+    };
+    let attempts = match fetch_first(suffixes, &mut fetch) {
+        Ok((_, attempts)) => attempts,
+        Err(e) => {
+            let _ = fs::remove_dir_all(cache_dir);
+            return Err(e);
+        }
+    };
+
+    let bytes = fs::metadata(&tmp_archive).map(|m| m.len()).unwrap_or(0);
+    progress::emit(&ProgressEvent::Download { bytes, total: Some(bytes) });
+
+    let mut tar = std::process::Command::new("tar");
+    tar.args([
+        "-xzf", path_str(&tmp_archive)?,
+        "-C",   path_str(cache_dir)?,
+        "--strip-components=1",
+    ]);
+    let tar_ok = progress::run_child(&mut tar, "tar -xzf", None)
+        .context("tar の起動に失敗しました")?
+        .success();
+
+    let _ = fs::remove_file(&tmp_archive);
+
+    if !tar_ok {
+        let _ = fs::remove_dir_all(cache_dir);
+        anyhow::bail!("アーカイブの展開に失敗しました。");
+    }
+
+    Ok(RubyDownload { bytes, attempts })
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bootstrap_cache_only_multiple_versions() {
+        let root = std::env::temp_dir().join("arc_cache_only_test");
+        let _ = fs::remove_dir_all(&root);
+        let rubies = root.join("cache/rubies");
+        fs::create_dir_all(&rubies).unwrap();
+        // 偽のダウンロード: 9.9.9 だけ失敗する
+        let download = |dir: &Path, version: &str| -> Result<u64> {
+            if version == "9.9.9" {
+                anyhow::bail!("404");
+            }
+            fs::create_dir_all(dir.join("bin")).unwrap();
+            Ok(100)
+        };
+        let versions = ["3.3.6", "9.9.9", "3.4.1"].map(String::from);
+
+        let err = warm_rubies(&rubies, &versions, &download).unwrap_err();
+        assert!(err.to_string().contains("1 / 3"), "{}", err);
+        assert!(rubies.join(resolve_ruby_id("3.3.6")).is_dir());
+        assert!(rubies.join(resolve_ruby_id("3.4.1")).is_dir());
+        assert!(!rubies.join(resolve_ruby_id("9.9.9")).exists());
+        // プロジェクトのファイルは作らない
+        let mut entries: Vec<String> = fs::read_dir(&root).unwrap().flatten().map(|e| e.file_name().to_string_lossy().into()).collect();
+        entries.sort();
+        assert_eq!(entries, ["cache"]);
+
+        // 取得済みのバージョンはダウンロードしない
+        let no_download = |_: &Path, _: &str| -> Result<u64> { panic!("should be cached") };
+        warm_rubies(&rubies, &versions[..1], &no_download).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_platform() {
+        assert_eq!(resolve_platform("linux", "x86_64", None).unwrap(), ["ubuntu-24.04", "ubuntu-22.04"]);
+        assert_eq!(resolve_platform("linux", "aarch64", None).unwrap(), ["ubuntu-24.04-arm64", "ubuntu-22.04-arm64"]);
+        // 上書きした場合はフォールバックしない
+        assert_eq!(resolve_platform("linux", "x86_64", Some("ubuntu-20.04")).unwrap(), ["ubuntu-20.04"]);
+        assert_eq!(resolve_platform("freebsd", "x86_64", Some("ubuntu-22.04")).unwrap(), ["ubuntu-22.04"]);
+
+        let err = resolve_platform("freebsd", "x86_64", None).unwrap_err().to_string();
+        assert!(err.contains("freebsd / x86_64"), "{}", err);
+        assert!(err.contains("platform_suffix = \"ubuntu-22.04\""), "{}", err);
+        assert!(err.contains(RUBY_PLATFORM_ENV), "{}", err);
+        assert_eq!(
+            resolve_ruby_url("3.3.6", "ubuntu-22.04"),
+            "https://github.com/ruby/ruby-builder/releases/download/toolcache/ruby-3.3.6-ubuntu-22.04.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_download_falls_back_in_order() {
+        let suffixes = resolve_platform("linux", "x86_64", None).unwrap();
+        let mut tried = Vec::new();
+        // 24.04 のアセットが 404 の場合
+        let (used, attempts) = fetch_first(&suffixes, &mut |s| {
+            tried.push(s.to_string());
+            Ok(s == "ubuntu-22.04")
+        })
+        .unwrap();
+        assert_eq!(used, "ubuntu-22.04");
+        assert_eq!(attempts, ["ubuntu-24.04", "ubuntu-22.04"]);
+        assert_eq!(tried, attempts);
+
+        let (_, attempts) = fetch_first(&suffixes, &mut |_| Ok(true)).unwrap();
+        assert_eq!(attempts, ["ubuntu-24.04"]);
+
+        let err = fetch_first(&suffixes, &mut |_| Ok(false)).unwrap_err();
+        assert!(err.to_string().contains("ubuntu-24.04, ubuntu-22.04"), "{}", err);
+    }
+}
@@ -0,0 +1,520 @@
+//! `arc export --bundle` / `arc import` — Gem を含まない Flux 履歴の持ち運び。
+//!
+//! バンドルは次の構成の tar.gz (展開・圧縮はシステムの `tar` に任せる):
+//!
+//! ```text
+//! manifest.json     arc バージョン・プロジェクト名・収録ファイル一覧
+//! signals.jsonl     Signal ログ (--redact 時は匿名化済み)
+//! config.toml       .flux/config.toml
+//! blobs/...         .flux/blobs/ (Signal から参照される退避済みフィールド)
+//! snapshots/...     .flux/snapshots/ (存在する場合)
+//! output/...        .flux/output/ (デタッチ実行の出力。サイズ上限まで)
+//! ```
+//!
+//! `.arc/env` は含めないため、インポート先では `arc bootstrap` / `arc sync` が必要になる。
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::detach;
+use crate::config::ArcConfig;
+use crate::signals::{self, FluxProject, Signal, SignalMeta, SignalType};
+use crate::snapshot::SNAPSHOTS_DIR;
+
+/// マニフェストのファイル名
+const MANIFEST_FILE: &str = "manifest.json";
+/// バンドル内の Signal ログのファイル名
+const SIGNALS_FILE: &str = "signals.jsonl";
+/// バンドル内の設定ファイル名
+const CONFIG_FILE: &str = "config.toml";
+/// バンドル内の blob のディレクトリ名
+const BLOBS_DIR: &str = "blobs";
+/// バンドル内の出力ディレクトリ名
+const OUTPUT_DIR: &str = "output";
+/// マニフェストのフォーマットバージョン
+const BUNDLE_FORMAT: u32 = 1;
+/// 匿名化した値の置き換え文字列
+const REDACTED: &str = "<redacted>";
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// バンドルに同梱されるマニフェスト。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub arc_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_name: Option<String>,
+    pub exported_at: String,
+    pub signal_count: usize,
+    pub redacted: bool,
+    /// バンドルに含めたファイル (バンドルルートからの相対パス)
+    pub files: Vec<String>,
+    /// サイズ上限などで除外したファイル
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<String>,
+}
+
+/// `arc export --bundle` のオプション。
+pub struct ExportOptions {
+    /// usernames / hostname / 環境変数の値を匿名化する
+    pub redact: bool,
+    /// バンドルに含めるファイルの合計サイズの上限 (バイト)
+    pub max_bytes: u64,
+}
+
+// ─────────────────────────────────────────────
+// 匿名化
+// ─────────────────────────────────────────────
+
+/// Signal ログと出力からユーザー名・ホスト名・ホームディレクトリ・環境変数の値を取り除く。
+pub struct Redactor {
+    home: Option<String>,
+    words: Vec<(String, &'static str)>,
+}
+
+impl Redactor {
+    pub fn new(home: Option<String>, user: Option<String>, host: Option<String>) -> Self {
+        // 1 文字の名前は通常の単語を壊しやすいため対象外にする
+        let usable = |s: Option<String>| s.filter(|s| s.chars().count() >= 2);
+        let mut words = Vec::new();
+        if let Some(user) = usable(user) {
+            words.push((user, "<user>"));
+        }
+        if let Some(host) = usable(host) {
+            words.push((host, "<host>"));
+        }
+        Self {
+            home: usable(home).map(|h| h.trim_end_matches('/').to_string()),
+            words,
+        }
+    }
+
+    /// 現在の環境 (`$HOME`, `$USER`, ホスト名) から作成する。
+    pub fn from_env() -> Self {
+        let user = std::env::var("USER").or_else(|_| std::env::var("LOGNAME")).ok();
+        Self::new(std::env::var("HOME").ok(), user, signals::hostname())
+    }
+
+    pub fn redact_str(&self, s: &str) -> String {
+        let mut out = match &self.home {
+            Some(home) => s.replace(home.as_str(), "~"),
+            None => s.to_string(),
+        };
+        for (word, replacement) in &self.words {
+            out = replace_word(&out, word, replacement);
+        }
+        out
+    }
+
+    /// JSON 値を再帰的に匿名化する。
+    /// `env` / `environment` オブジェクトの値と、`KEY=value` 形式の文字列の値は伏せる。
+    pub fn redact_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(s) => Value::String(self.redact_str(&redact_assignment(s))),
+            Value::Array(items) => Value::Array(items.iter().map(|v| self.redact_value(v)).collect()),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| {
+                        let v = match (k.as_str(), v) {
+                            ("env" | "environment", Value::Object(vars)) => Value::Object(
+                                vars.keys().map(|name| (name.clone(), json!(REDACTED))).collect(),
+                            ),
+                            _ => self.redact_value(v),
+                        };
+                        (self.redact_str(k), v)
+                    })
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    pub fn redact_signal(&self, signal: &Signal) -> Signal {
+        Signal {
+            payload: self.redact_value(&signal.payload),
+            meta: signal.meta.as_ref().map(|m| SignalMeta {
+                user: m.user.as_ref().map(|u| self.redact_str(u)),
+                host: m.host.as_ref().map(|h| self.redact_str(h)),
+            }),
+            ..signal.clone()
+        }
+    }
+}
+
+/// `word` を単語境界 (英数字・`_`・`-` 以外に挟まれた位置) でのみ置き換える。
+fn replace_word(s: &str, word: &str, replacement: &str) -> String {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(pos) = rest.find(word) {
+        let before = rest[..pos].chars().last().or_else(|| out.chars().last());
+        let after = rest[pos + word.len()..].chars().next();
+        out.push_str(&rest[..pos]);
+        if before.is_some_and(is_word_char) || after.is_some_and(is_word_char) {
+            out.push_str(word);
+        } else {
+            out.push_str(replacement);
+        }
+        rest = &rest[pos + word.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `NAME=value` (NAME は大文字の環境変数名) の値を伏せる。それ以外はそのまま返す。
+fn redact_assignment(s: &str) -> String {
+    match s.split_once('=') {
+        Some((name, _))
+            if !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') =>
+        {
+            format!("{}={}", name, REDACTED)
+        }
+        _ => s.to_string(),
+    }
+}
+
+// ─────────────────────────────────────────────
+// エクスポート
+// ─────────────────────────────────────────────
+
+/// バンドルの内容を `staging` ディレクトリに書き出し、マニフェストを返す。
+///
+/// Signal ログと設定は常に含める。スナップショットと出力は新しいものから順に、
+/// 合計が `max_bytes` を超えない範囲で含める。
+pub fn stage(project: &FluxProject, staging: &Path, opts: &ExportOptions) -> Result<Manifest> {
+    fs::create_dir_all(staging).with_context(|| format!("Failed to create {:?}", staging))?;
+    let redactor = opts.redact.then(Redactor::from_env);
+
+    let mut signals = project.read_signals()?;
+    if let Some(r) = &redactor {
+        // blob の内容も匿名化するため、参照を展開してから処理する (ハッシュが変わるため)
+        signals = signals
+            .iter()
+            .map(|s| {
+                let payload = project.resolve_blob(&s.payload)?;
+                Ok(r.redact_signal(&Signal { payload, ..s.clone() }))
+            })
+            .collect::<Result<_>>()?;
+    }
+    let mut log = String::new();
+    for signal in &signals {
+        log.push_str(&serde_json::to_string(signal)?);
+        log.push('\n');
+    }
+    fs::write(staging.join(SIGNALS_FILE), &log)?;
+
+    let mut files = vec![SIGNALS_FILE.to_string()];
+    let mut used = log.len() as u64;
+
+    let config_src = project.flux_dir.join(CONFIG_FILE);
+    if config_src.exists() {
+        used += fs::copy(&config_src, staging.join(CONFIG_FILE))?;
+        files.push(CONFIG_FILE.to_string());
+    }
+
+    // 参照されている blob は Signal ログの一部として常に含める
+    let blobs_src = project.blobs_dir();
+    if !opts.redact && blobs_src.exists() {
+        for path in newest_first(&blobs_src)? {
+            let rel = format!("{}/{}", BLOBS_DIR, path.file_name().unwrap_or_default().to_string_lossy());
+            let dest = staging.join(&rel);
+            fs::create_dir_all(dest.parent().unwrap_or(staging))?;
+            used += fs::copy(&path, &dest)?;
+            files.push(rel);
+        }
+    }
+
+    let mut skipped = Vec::new();
+    let optional = [
+        (SNAPSHOTS_DIR, project.flux_dir.join(SNAPSHOTS_DIR)),
+        (OUTPUT_DIR, detach::output_dir(&project.flux_dir)),
+    ];
+    for (name, src_dir) in optional {
+        for path in newest_first(&src_dir)? {
+            let rel = format!("{}/{}", name, path.file_name().unwrap_or_default().to_string_lossy());
+            let content = fs::read(&path)?;
+            let content = match &redactor {
+                // 匿名化できないバイナリは含めない
+                Some(r) => match String::from_utf8(content) {
+                    Ok(text) => r.redact_str(&text).into_bytes(),
+                    Err(_) => {
+                        skipped.push(rel);
+                        continue;
+                    }
+                },
+                None => content,
+            };
+            if used + content.len() as u64 > opts.max_bytes {
+                skipped.push(rel);
+                continue;
+            }
+            let dest = staging.join(&rel);
+            fs::create_dir_all(dest.parent().unwrap_or(staging))?;
+            fs::write(&dest, &content)?;
+            used += content.len() as u64;
+            files.push(rel);
+        }
+    }
+
+    let config = ArcConfig::load(&project.flux_dir)?;
+    let manifest = Manifest {
+        format: BUNDLE_FORMAT,
+        arc_version: env!("CARGO_PKG_VERSION").to_string(),
+        project_name: config.project.name,
+        exported_at: Local::now().to_rfc3339(),
+        signal_count: signals.len(),
+        redacted: opts.redact,
+        files,
+        skipped,
+    };
+    fs::write(staging.join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)?;
+    Ok(manifest)
+}
+
+/// ディレクトリ直下のファイルを名前の降順 (Signal ID = UUID v7 なので新しい順) で返す。
+fn newest_first(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    paths.sort();
+    paths.reverse();
+    Ok(paths)
+}
+
+/// プロジェクトの履歴を `file` (tar.gz) に書き出す。
+pub fn export(project: &FluxProject, file: &Path, opts: &ExportOptions) -> Result<Manifest> {
+    let staging = scratch_dir("export");
+    let result = stage(project, &staging, opts).and_then(|manifest| {
+        run_tar(Command::new("tar").arg("-czf").arg(file).arg("-C").arg(&staging).arg("."))?;
+        Ok(manifest)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+// ─────────────────────────────────────────────
+// インポート
+// ─────────────────────────────────────────────
+
+/// 展開済みのバンドル (`staging`) から `root` に新しい Flux プロジェクトを作成する。
+/// 既に `.flux` が存在する場合は上書きせずにエラーを返す。
+pub fn restore(staging: &Path, root: &Path) -> Result<(FluxProject, Manifest)> {
+    let manifest_path = staging.join(MANIFEST_FILE);
+    let manifest: Manifest = serde_json::from_str(
+        &fs::read_to_string(&manifest_path)
+            .with_context(|| format!("バンドルに {} がありません", MANIFEST_FILE))?,
+    )
+    .context("manifest.json のパースに失敗しました")?;
+
+    // マニフェストに書かれたパスでバンドル外のファイルを指せないようにする
+    if let Some(rel) = manifest.files.iter().find(|rel| {
+        Path::new(rel).components().any(|c| !matches!(c, std::path::Component::Normal(_)))
+    }) {
+        anyhow::bail!("不正なパスがマニフェストに含まれています: {}", rel);
+    }
+    // バンドル内のシンボリックリンクでバンドルの外のファイルを取り込まない
+    let sources = manifest
+        .files
+        .iter()
+        .map(|rel| crate::safe_path::join_within(staging, rel))
+        .collect::<Result<Vec<_>>>()?;
+    if FluxProject::open(root).is_ok() {
+        anyhow::bail!(
+            "{:?} は既に Flux プロジェクトです。空のディレクトリにインポートしてください。",
+            root.join(".flux")
+        );
+    }
+    fs::create_dir_all(root)?;
+    // init Signal と config.toml は直後にバンドルの内容で置き換わる
+    let (project, _) = FluxProject::init(
+        root,
+        &crate::config::ArcConfig::default(),
+        json!({ "path": root, "version": env!("CARGO_PKG_VERSION") }),
+    )?;
+
+    for (rel, src) in manifest.files.iter().zip(&sources) {
+        let dest = match rel.as_str() {
+            SIGNALS_FILE => project.signal_file.clone(),
+            CONFIG_FILE => project.flux_dir.join(CONFIG_FILE),
+            _ if rel.starts_with(&format!("{}/", OUTPUT_DIR)) => {
+                detach::output_dir(&project.flux_dir).join(&rel[OUTPUT_DIR.len() + 1..])
+            }
+            _ => project.flux_dir.join(rel),
+        };
+        fs::create_dir_all(dest.parent().unwrap_or(&project.flux_dir))?;
+        fs::copy(src, &dest).with_context(|| format!("{} の展開に失敗しました", rel))?;
+    }
+
+    project.record(
+        SignalType::Import,
+        json!({
+            "source": manifest,
+        }),
+    )?;
+    Ok((project, manifest))
+}
+
+/// バンドル `file` を `root` にインポートする。
+pub fn import(file: &Path, root: &Path) -> Result<(FluxProject, Manifest)> {
+    let staging = scratch_dir("import");
+    fs::create_dir_all(&staging)?;
+    let result = run_tar(Command::new("tar").arg("-xzf").arg(file).arg("-C").arg(&staging))
+        .and_then(|_| restore(&staging, root));
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn scratch_dir(kind: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("arc-{}-{}", kind, signals::new_signal_id()))
+}
+
+fn run_tar(command: &mut Command) -> Result<()> {
+    let status = command.status().context("tar の起動に失敗しました")?;
+    if !status.success() {
+        anyhow::bail!("tar が失敗しました ({})", status);
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FluxState;
+
+    fn fixture(name: &str) -> (PathBuf, FluxProject) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut config = ArcConfig::default();
+        config.project.name = Some("shop".to_string());
+        let (project, _) =
+            FluxProject::init(&root, &config, json!({ "path": "/home/alice/shop", "version": "0.1.0" })).unwrap();
+        let start = project
+            .record(SignalType::ExecStart, json!({ "command": "rake", "args": ["test"], "cwd": "/home/alice/shop" }))
+            .unwrap();
+        project
+            .record(SignalType::ExecEnd, json!({ "ref_id": start.id, "exit_code": 0, "success": true, "duration_us": 1500 }))
+            .unwrap();
+        let output = detach::output_dir(&project.flux_dir);
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("0001.out"), "old\n").unwrap();
+        fs::write(output.join("0002.out"), "new output\n").unwrap();
+        (root, project)
+    }
+
+    fn summary(project: &FluxProject) -> String {
+        let state = FluxState::from_signals(&project.read_signals().unwrap());
+        format!("{:?} {:?} {:?}", state.project_path, state.version, state.executions)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (root, project) = fixture("arc_bundle_round_trip_test");
+        let bundle = root.join("history.tar.gz");
+        let opts = ExportOptions { redact: false, max_bytes: u64::MAX };
+        let manifest = export(&project, &bundle, &opts).unwrap();
+        assert_eq!(manifest.project_name.as_deref(), Some("shop"));
+        assert_eq!(manifest.signal_count, 3);
+
+        let dest = root.join("imported");
+        let (imported, _) = import(&bundle, &dest).unwrap();
+        assert_eq!(summary(&imported), summary(&project));
+
+        let signals = imported.read_signals().unwrap();
+        assert_eq!(signals.len(), 4);
+        assert_eq!(signals[3].r_type, "import");
+        assert_eq!(signals[3].payload["source"]["project_name"], "shop");
+        assert_eq!(ArcConfig::load(&imported.flux_dir).unwrap().project.name.as_deref(), Some("shop"));
+        assert!(detach::output_dir(&imported.flux_dir).join("0002.out").exists());
+        // 環境はブートストラップしない
+        assert!(!dest.join(".arc").exists());
+
+        // 既存のプロジェクトには上書きしない
+        assert!(import(&bundle, &dest).is_err());
+        assert_eq!(imported.read_signals().unwrap().len(), 4);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_size_cap_keeps_newest_outputs() {
+        let (root, project) = fixture("arc_bundle_size_cap_test");
+        let staging = root.join("staging");
+        let log_len = fs::metadata(&project.signal_file).unwrap().len();
+        let config_len = fs::metadata(project.flux_dir.join(CONFIG_FILE)).unwrap().len();
+        let opts = ExportOptions { redact: false, max_bytes: log_len + config_len + 11 };
+
+        let manifest = stage(&project, &staging, &opts).unwrap();
+        assert!(manifest.files.contains(&"output/0002.out".to_string()));
+        assert_eq!(manifest.skipped, ["output/0001.out"]);
+        assert!(!staging.join("output/0001.out").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_redact_value() {
+        let r = Redactor::new(Some("/home/alice".into()), Some("alice".into()), Some("devbox".into()));
+        let payload = json!({
+            "cwd": "/home/alice/shop",
+            "args": ["ssh", "alice@devbox", "SECRET_TOKEN=abc123", "--user=malice"],
+            "env": { "DATABASE_URL": "postgres://alice:pw@db" },
+            "host": "devbox.local",
+        });
+        let redacted = r.redact_value(&payload);
+        assert_eq!(redacted["cwd"], "~/shop");
+        assert_eq!(redacted["args"], json!(["ssh", "<user>@<host>", "SECRET_TOKEN=<redacted>", "--user=malice"]));
+        assert_eq!(redacted["env"]["DATABASE_URL"], "<redacted>");
+        assert_eq!(redacted["host"], "<host>.local");
+    }
+
+    #[test]
+    fn test_restore_rejects_path_traversal() {
+        let root = std::env::temp_dir().join("arc_bundle_traversal_test");
+        let _ = fs::remove_dir_all(&root);
+        let staging = root.join("staging");
+        fs::create_dir_all(&staging).unwrap();
+        let manifest = Manifest {
+            format: BUNDLE_FORMAT,
+            arc_version: "0.0.0".into(),
+            project_name: None,
+            exported_at: String::new(),
+            signal_count: 0,
+            redacted: false,
+            files: vec!["../escape".into()],
+            skipped: vec![],
+        };
+        fs::write(staging.join(MANIFEST_FILE), serde_json::to_string(&manifest).unwrap()).unwrap();
+        assert!(restore(&staging, &root.join("dest")).is_err());
+
+        // マニフェストのパスは正しくても、バンドル外を指すシンボリックリンクは取り込まない
+        fs::write(root.join("passwd"), "root:x:0:0").unwrap();
+        std::os::unix::fs::symlink(root.join("passwd"), staging.join(SIGNALS_FILE)).unwrap();
+        let manifest = Manifest { files: vec![SIGNALS_FILE.into()], ..manifest };
+        fs::write(staging.join(MANIFEST_FILE), serde_json::to_string(&manifest).unwrap()).unwrap();
+        let Err(err) = restore(&staging, &root.join("dest")) else { panic!("imported a symlink outside the bundle") };
+        let err = err.to_string();
+        assert!(err.contains("refusing to read path outside"), "{}", err);
+        assert!(!root.join("dest/.flux").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
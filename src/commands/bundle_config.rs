@@ -0,0 +1,65 @@
+//! `arc bundle-config`: arc が隔離した bundler の設定 (`.arc/bundle-config/config`) を読み書きする。
+
+use anyhow::Result;
+
+use crate::bundler_config;
+use crate::cli::BundleConfigCommand;
+
+use super::context::CommandContext;
+
+pub fn bundle_config(ctx: &CommandContext, command: BundleConfigCommand) -> Result<()> {
+    ctx.project()?;
+    let cwd = ctx.root()?;
+    match command {
+        BundleConfigCommand::Set { key, value } if ctx.dry_run => {
+            crate::dry_run::note(&format!("would set {} = {:?} in .arc/bundle-config/config", bundler_config::env_key(&key), value));
+        }
+        BundleConfigCommand::Unset { key } if ctx.dry_run => {
+            crate::dry_run::note(&format!("would unset {} in .arc/bundle-config/config", bundler_config::env_key(&key)));
+        }
+        BundleConfigCommand::Set { key, value } => {
+            bundler_config::set(cwd, &key, Some(&value))?;
+            eprintln!("✅ {} = {:?}", bundler_config::env_key(&key), value);
+        }
+        BundleConfigCommand::Unset { key } => bundler_config::set(cwd, &key, None)?,
+        BundleConfigCommand::Get { key } => {
+            let settings = bundler_config::load(cwd)?;
+            let key = bundler_config::env_key(&key);
+            match settings.get(&key) {
+                Some(value) => println!("{}", value),
+                None => anyhow::bail!("{} is not set", key),
+            }
+        }
+        BundleConfigCommand::List => {
+            for (key, value) in bundler_config::load(cwd)? {
+                println!("{}={}", key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dry_run_bundle_config_writes_nothing() {
+        let (cwd, _) = crate::signals::temp_project("arc_bundle_config_dry_run_test");
+        let set = |ctx: &CommandContext, value: &str| {
+            bundle_config(ctx, BundleConfigCommand::Set { key: "jobs".to_string(), value: value.to_string() }).unwrap()
+        };
+        set(&CommandContext::at(&cwd, true), "4");
+        assert!(bundler_config::load(&cwd).unwrap().is_empty());
+
+        set(&CommandContext::at(&cwd, false), "4");
+        bundle_config(&CommandContext::at(&cwd, true), BundleConfigCommand::Unset { key: "jobs".to_string() }).unwrap();
+        set(&CommandContext::at(&cwd, true), "8");
+        assert_eq!(bundler_config::load(&cwd).unwrap().get("BUNDLE_JOBS").map(String::as_str), Some("4"));
+        std::fs::remove_dir_all(&cwd).unwrap();
+    }
+}
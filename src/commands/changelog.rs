@@ -0,0 +1,598 @@
+//! `arc changelog`: 操作ログから依存の変更 (add / remove / undo / Ruby の切り替え) だけを拾い、
+//! 人が読める CHANGELOG (Markdown) にする。
+//!
+//! - 取り消した操作 (`undo` の `target_id`) と、それを取り消した undo は打ち消し合って消える。
+//!   `--include-reverted` なら両方を残し、取り消した側に印を付ける
+//! - 1 つの節 (日付またはリリース) の中では Gem ごとに正味の変化にまとめる。削除して別の要件で
+//!   追加し直した Gem は「Upgraded」「Downgraded」「Pinned」になり、追加して削除した Gem は消える
+//! - Ruby は `bootstrap` の `ruby_version` を追い、変わったときだけ載せる (`arc adopt` の取り込みは変更ではない)
+//!
+//! 範囲 (`--since` / `--until`) は Signal ID、日付、RFC 3339 の時刻、Git のタグで指定する。
+//! どちらかがタグなら、日付の代わりにその間のタグ (リリース) ごとに節を分ける。
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::process::Command;
+
+use super::CommandContext;
+use super::undo_check::LINES_KEY;
+use crate::gemfile;
+use crate::ruby_version;
+use crate::signals::{self, Signal};
+use crate::version_req::Requirement;
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ChangelogFormat {
+    /// Markdown
+    Md,
+}
+
+/// 1 つの依存の変更
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added { gem: String, version: Option<String> },
+    Removed { gem: String, version: Option<String> },
+    /// 同じ節の中で要件が変わった Gem (`None` は要件なし)
+    Changed { gem: String, from: Option<String>, to: Option<String> },
+    Ruby { from: Option<String>, to: String },
+    /// 範囲の前の操作を取り消した undo、または `--include-reverted` で残した undo
+    Reverted(Box<Change>),
+}
+
+/// CHANGELOG の 1 行
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// ログ上の位置
+    pub position: usize,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub change: Change,
+    /// 後で取り消された操作 (`--include-reverted` のときだけ残る)
+    pub reverted: bool,
+}
+
+/// 節の区切りになるリリース (Git のタグ)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Release {
+    pub name: String,
+    /// タグが指すコミットの時刻
+    pub time: DateTime<Utc>,
+}
+
+/// CHANGELOG の 1 節 (日付またはリリース)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub title: String,
+    /// 古い順
+    pub entries: Vec<Entry>,
+}
+
+/// タグより後の変更を集める節の見出し
+pub const UNRELEASED: &str = "Unreleased";
+
+// ─────────────────────────────────────────────
+// 選択
+// ─────────────────────────────────────────────
+
+/// `signals[range]` の依存の変更を古い順に返す。
+/// 取り消しの判定と Ruby の直前の版を知るため、範囲の前のログも読む。
+pub fn select(signals: &[Signal], range: Range<usize>, include_reverted: bool) -> Vec<Entry> {
+    let range = range.start.min(signals.len())..range.end.min(signals.len());
+    let positions = crate::stats_compare::positions(signals);
+    // 範囲内の undo が取り消した、範囲内の操作
+    let cancelled: HashSet<&str> = signals[range.clone()]
+        .iter()
+        .filter(|s| s.r_type == "undo")
+        .filter_map(|s| s.payload["target_id"].as_str())
+        .filter(|id| positions.get(id).is_some_and(|p| range.contains(p)))
+        .collect();
+
+    let mut ruby: Option<String> = None;
+    let mut entries = Vec::new();
+    for (position, signal) in signals[..range.end].iter().enumerate() {
+        let in_range = position >= range.start;
+        let change = match signal.r_type.as_str() {
+            "bootstrap" => {
+                let Some(to) = signal.payload["ruby_version"].as_str() else { continue };
+                let from = signal.payload["switched_from"].as_str().map(String::from).or(ruby.replace(to.to_string()));
+                if !in_range || signal.payload["adopted"].as_bool() == Some(true) || from.as_deref() == Some(to) {
+                    continue;
+                }
+                Change::Ruby { from, to: to.to_string() }
+            }
+            _ if !in_range => continue,
+            "add" | "remove" => {
+                if signal.payload["adopted"].as_bool() == Some(true) {
+                    continue;
+                }
+                let Some(change) = change_of(signal) else { continue };
+                change
+            }
+            "undo" => {
+                let target = signal.payload["target_id"].as_str().unwrap_or_default();
+                if cancelled.contains(target) && !include_reverted {
+                    continue;
+                }
+                let Some(change) = positions.get(target).and_then(|&p| change_of(&signals[p])) else { continue };
+                Change::Reverted(Box::new(change))
+            }
+            _ => continue,
+        };
+        if cancelled.contains(signal.id.as_str()) && !include_reverted {
+            continue;
+        }
+        let Ok(timestamp) = DateTime::parse_from_rfc3339(&signal.timestamp).map(|t| t.to_utc()) else { continue };
+        entries.push(Entry {
+            position,
+            timestamp,
+            user: signal.user().to_string(),
+            change,
+            reverted: cancelled.contains(signal.id.as_str()),
+        });
+    }
+    entries
+}
+
+/// add / remove の Signal が表す変更。削除した Gem の要件は記録した `gem` 行から読む。
+fn change_of(signal: &Signal) -> Option<Change> {
+    let gem = signal.payload["gem"].as_str()?.to_string();
+    match signal.r_type.as_str() {
+        "add" => Some(Change::Added { gem, version: signal.payload["version"].as_str().map(String::from) }),
+        "remove" => {
+            let line = signal.payload[LINES_KEY][0]["text"].as_str().unwrap_or_default();
+            let version = gemfile::parse_content(line).into_iter().next().and_then(|e| e.version);
+            Some(Change::Removed { gem, version })
+        }
+        _ => None,
+    }
+}
+
+// ─────────────────────────────────────────────
+// 節へのまとめ
+// ─────────────────────────────────────────────
+
+/// `entries` を節に分ける (新しい節が先)。`releases` があればタグごと、なければ記録した日付ごと。
+pub fn sections(entries: Vec<Entry>, releases: Option<&[Release]>) -> Vec<Section> {
+    let mut sections: Vec<Section> = Vec::new();
+    for entry in entries {
+        let title = match releases {
+            Some(releases) => release_of(&entry.timestamp, releases),
+            None => entry.timestamp.with_timezone(&Local).date_naive().to_string(),
+        };
+        match sections.iter_mut().find(|s| s.title == title) {
+            Some(section) => section.entries.push(entry),
+            None => sections.push(Section { title, entries: vec![entry] }),
+        }
+    }
+    for section in &mut sections {
+        section.entries = net(std::mem::take(&mut section.entries));
+    }
+    sections.retain(|s| !s.entries.is_empty());
+    sections.sort_by_key(|s| std::cmp::Reverse(s.entries.last().map(|e| e.position)));
+    sections
+}
+
+/// `time` を含むリリース: その時刻以降で最初のタグ。どのタグより後なら `UNRELEASED`。
+fn release_of(time: &DateTime<Utc>, releases: &[Release]) -> String {
+    let mut sorted: Vec<&Release> = releases.iter().collect();
+    sorted.sort_by_key(|r| r.time);
+    let release = sorted.into_iter().find(|r| r.time >= *time);
+    release.map_or_else(|| UNRELEASED.to_string(), |r| format!("{} ({})", r.name, r.time.with_timezone(&Local).date_naive()))
+}
+
+/// 節の中の Gem と Ruby の変更を正味の変化にまとめる。取り消しに関わる行はそのまま残す。
+fn net(entries: Vec<Entry>) -> Vec<Entry> {
+    // Gem ごと (Ruby は "" とする) の最初と最後の行
+    let mut spans: HashMap<String, (usize, usize)> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if let Some(key) = net_key(entry) {
+            spans.entry(key).and_modify(|span| span.1 = i).or_insert((i, i));
+        }
+    }
+    let mut netted = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let Some(key) = net_key(entry) else {
+            netted.push(entry.clone());
+            continue;
+        };
+        let (first, last) = spans[&key];
+        if i != last {
+            continue;
+        }
+        if let Some(change) = net_change(&entries[first].change, &entry.change) {
+            netted.push(Entry { change, ..entry.clone() });
+        }
+    }
+    netted
+}
+
+fn net_key(entry: &Entry) -> Option<String> {
+    if entry.reverted {
+        return None;
+    }
+    match &entry.change {
+        Change::Added { gem, .. } | Change::Removed { gem, .. } => Some(gem.clone()),
+        Change::Ruby { .. } => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// 最初の変更の前と最後の変更の後を比べる。変化がなければ `None`。
+fn net_change(first: &Change, last: &Change) -> Option<Change> {
+    match (first, last) {
+        (Change::Ruby { from, .. }, Change::Ruby { to, .. }) => {
+            (from.as_deref() != Some(to)).then(|| Change::Ruby { from: from.clone(), to: to.clone() })
+        }
+        // 前はなく、後もない
+        (Change::Added { .. }, Change::Removed { .. }) => None,
+        (Change::Added { .. }, Change::Added { .. }) | (Change::Removed { .. }, Change::Removed { .. }) => Some(last.clone()),
+        (Change::Removed { gem, version: from }, Change::Added { version: to, .. }) => {
+            (from != to).then(|| Change::Changed { gem: gem.clone(), from: from.clone(), to: to.clone() })
+        }
+        _ => Some(last.clone()),
+    }
+}
+
+// ─────────────────────────────────────────────
+// 出力
+// ─────────────────────────────────────────────
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { gem, version: Some(v) } => write!(f, "Added {} ({})", gem, v),
+            Change::Added { gem, version: None } => write!(f, "Added {}", gem),
+            Change::Removed { gem, .. } => write!(f, "Removed {}", gem),
+            Change::Changed { gem, from: None, to: Some(to) } => write!(f, "Pinned {} to {}", gem, to),
+            Change::Changed { gem, from: Some(from), to: None } => write!(f, "Unpinned {} (was {})", gem, from),
+            Change::Changed { gem, from, to } => {
+                let (from, to) = (from.as_deref().unwrap_or("any"), to.as_deref().unwrap_or("any"));
+                let verb = match (floor(from), floor(to)) {
+                    (Some(a), Some(b)) if ruby_version::compare(&b, &a).is_gt() => "Upgraded",
+                    (Some(a), Some(b)) if ruby_version::compare(&b, &a).is_lt() => "Downgraded",
+                    _ => "Changed",
+                };
+                write!(f, "{} {} {} → {}", verb, gem, from, to)
+            }
+            Change::Ruby { from: Some(from), to } => write!(f, "Ruby {} → {}", from, to),
+            Change::Ruby { from: None, to } => write!(f, "Ruby {}", to),
+            Change::Reverted(change) => write!(f, "Reverted: {}", change),
+        }
+    }
+}
+
+/// 要件の下限の版 (`~> 1.16` → `1.16`)
+fn floor(requirement: &str) -> Option<String> {
+    Requirement::parse(requirement).ok()?.lower().map(|b| b.version)
+}
+
+/// Markdown の CHANGELOG。`show_user` なら行に操作したユーザーを添える。
+pub fn render_markdown(sections: &[Section], show_user: bool) -> String {
+    let mut out = String::from("# Changelog\n");
+    if sections.is_empty() {
+        out.push_str("\nNo dependency changes.\n");
+    }
+    for section in sections {
+        out.push_str(&format!("\n## {}\n\n", section.title));
+        for entry in &section.entries {
+            out.push_str(&format!("- {}", entry.change));
+            if entry.reverted {
+                out.push_str(" (reverted)");
+            }
+            if show_user {
+                out.push_str(&format!(" — {}", entry.user));
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// ─────────────────────────────────────────────
+// 範囲
+// ─────────────────────────────────────────────
+
+/// `--since` / `--until` の解決結果
+#[derive(Debug, Clone, PartialEq)]
+enum Point {
+    /// この時刻までを含む
+    Time(DateTime<Utc>),
+    /// この位置の Signal までを含む
+    Signal(usize),
+    Tag(Release),
+}
+
+impl Point {
+    /// 日付は `until` ならその日の終わり、`since` ならその日の始まり (ローカル時刻)。
+    fn parse(s: &str, signals: &[Signal], tags: &[Release], until: bool) -> Result<Self> {
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Point::Time(time.to_utc()));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            let date = if until { date.succ_opt().unwrap_or(date) } else { date };
+            let start = date.and_hms_opt(0, 0, 0).and_then(|t| Local.from_local_datetime(&t).earliest());
+            let Some(start) = start else { bail!("Invalid date '{}'", s) };
+            // 時刻は「ここまでを含む」ので、翌日の始まりの直前にする
+            let start = if until { start - chrono::Duration::nanoseconds(1) } else { start };
+            return Ok(Point::Time(start.to_utc()));
+        }
+        if let Some(tag) = tags.iter().find(|t| t.name == s) {
+            return Ok(Point::Tag(tag.clone()));
+        }
+        crate::stats_compare::position_of(signals, s)
+            .map(Point::Signal)
+            .with_context(|| format!("'{}' is not a signal ID, date (YYYY-MM-DD), RFC 3339 time or git tag", s))
+    }
+
+    /// この点までを含めたときの、範囲の端 (その次の位置)
+    fn end(&self, signals: &[Signal]) -> usize {
+        let time = match self {
+            Point::Signal(position) => return position + 1,
+            Point::Time(time) => time,
+            Point::Tag(tag) => &tag.time,
+        };
+        signals
+            .iter()
+            .position(|s| DateTime::parse_from_rfc3339(&s.timestamp).is_ok_and(|ts| ts > *time))
+            .unwrap_or(signals.len())
+    }
+
+    fn time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Point::Time(time) => Some(*time),
+            Point::Tag(tag) => Some(tag.time),
+            Point::Signal(_) => None,
+        }
+    }
+}
+
+/// `root` の Git リポジトリのタグと、それが指すコミットの時刻。Git がない、リポジトリでない場合は空。
+fn git_tags(root: &Path) -> Vec<Release> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["for-each-ref", "--format=%(refname:short)%09%(committerdate:iso-strict)%09%(*committerdate:iso-strict)", "refs/tags"])
+        .output();
+    let Some(output) = output.ok().filter(|o| o.status.success()) else { return Vec::new() };
+    String::from_utf8_lossy(&output.stdout).lines().filter_map(parse_tag_line).collect()
+}
+
+/// `git for-each-ref` の 1 行。注釈付きタグはタグ自体ではなく、指すコミットの時刻 (3 列目) を使う。
+fn parse_tag_line(line: &str) -> Option<Release> {
+    let mut fields = line.split('\t');
+    let name = fields.next()?.to_string();
+    let (direct, peeled) = (fields.next().unwrap_or_default(), fields.next().unwrap_or_default());
+    let time = DateTime::parse_from_rfc3339(if peeled.is_empty() { direct } else { peeled }).ok()?.to_utc();
+    Some(Release { name, time })
+}
+
+// ─────────────────────────────────────────────
+// arc changelog
+// ─────────────────────────────────────────────
+
+pub fn changelog(
+    ctx: &CommandContext,
+    since: Option<&str>,
+    until: Option<&str>,
+    _format: ChangelogFormat,
+    include_reverted: bool,
+    out: Option<&Path>,
+) -> Result<()> {
+    let signals = ctx.project()?.read_signals()?;
+    let tags = if since.is_some() || until.is_some() { git_tags(ctx.root()?) } else { Vec::new() };
+    let since = since.map(|s| Point::parse(s, &signals, &tags, false)).transpose()?;
+    let until = until.map(|s| Point::parse(s, &signals, &tags, true)).transpose()?;
+
+    let start = since.as_ref().map_or(0, |p| p.end(&signals));
+    let end = until.as_ref().map_or(signals.len(), |p| p.end(&signals)).max(start);
+    let by_release = matches!(since, Some(Point::Tag(_))) || matches!(until, Some(Point::Tag(_)));
+    let releases: Vec<Release> = tags
+        .into_iter()
+        .filter(|t| since.as_ref().and_then(Point::time).is_none_or(|from| t.time > from))
+        .filter(|t| until.as_ref().and_then(Point::time).is_none_or(|to| t.time <= to))
+        .collect();
+
+    let entries = select(&signals, start..end, include_reverted);
+    let sections = sections(entries, by_release.then_some(releases.as_slice()));
+    let markdown = render_markdown(&sections, signals::distinct_users(&signals[start..end]) > 1);
+    match out {
+        Some(path) => {
+            std::fs::write(path, markdown).with_context(|| format!("Failed to write {:?}", path))?;
+            eprintln!("📝 Wrote changelog to {}", path.display());
+        }
+        None => print!("{}", markdown),
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::SignalMeta;
+    use serde_json::{Value, json};
+
+    fn signal(id: &str, r_type: &str, at: &str, user: &str, payload: Value) -> Signal {
+        Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            // 日付はローカル時刻で分けるので、どのタイムゾーンでも同じ日になるよう UTC で書く
+            timestamp: format!("2026-03-{}:00Z", at),
+            meta: Some(SignalMeta { user: Some(user.to_string()), host: None }),
+            v: 2,
+        }
+    }
+
+    fn op(id: &str, r_type: &str, at: &str, gem: &str, version: Option<&str>) -> Signal {
+        let line = match version {
+            Some(v) => format!("gem '{}', '{}'", gem, v),
+            None => format!("gem '{}'", gem),
+        };
+        signal(id, r_type, at, "alice", json!({ "gem": gem, "version": version, LINES_KEY: [{ "text": line }] }))
+    }
+
+    /// 3 週間分のログ
+    fn log() -> Vec<Signal> {
+        vec![
+            signal("a0", "init", "02T09:00", "alice", json!({})),
+            signal("a1", "add", "02T09:01", "alice", json!({ "gem": "nokogiri", "version": "1.16.0", "adopted": true })),
+            signal("a2", "bootstrap", "02T09:02", "alice", json!({ "ruby_version": "3.3.6" })),
+            op("a3", "add", "02T10:00", "rails", Some("~> 7.1")),
+            op("a4", "add", "02T11:00", "pry", None),
+            op("a5", "remove", "03T09:00", "pry", None),
+            signal("a6", "exec_end", "03T09:30", "alice", json!({ "command": "rspec", "exit_code": 0 })),
+            // 2 週目: 要件の更新と、取り消した add
+            op("b1", "remove", "09T10:00", "nokogiri", Some("1.16.0")),
+            op("b2", "add", "09T10:01", "nokogiri", Some("1.16.2")),
+            op("b3", "add", "09T11:00", "sidekiq", Some("~> 7.0")),
+            signal("b4", "undo", "09T11:05", "alice", json!({ "target_id": "b3", "target_type": "add", "gem": "sidekiq" })),
+            signal("b5", "bootstrap", "10T09:00", "alice", json!({ "ruby_version": "3.3.6", "cache_hit": true })),
+            op("b6", "add", "10T10:00", "debug", None),
+            op("b7", "remove", "10T10:30", "debug", None),
+            // 3 週目: Ruby の切り替えと、要件を付け外した Gem
+            op("c1", "remove", "16T10:00", "rack", None),
+            op("c2", "add", "16T10:01", "rack", Some("~> 3.0")),
+            op("c3", "remove", "16T11:00", "puma", Some("~> 6.4")),
+            op("c4", "add", "16T11:01", "puma", None),
+            signal("c5", "bootstrap", "17T09:00", "bob", json!({ "ruby_version": "3.4.1", "switched_from": "3.3.6" })),
+            op("c6", "remove", "17T10:00", "rails", Some("~> 7.1")),
+            op("c7", "add", "17T10:01", "rails", Some("~> 7.0")),
+        ]
+    }
+
+    fn render(signals: &[Signal], range: Range<usize>, include_reverted: bool, releases: Option<&[Release]>) -> String {
+        let show_user = signals::distinct_users(&signals[range.clone()]) > 1;
+        render_markdown(&sections(select(signals, range, include_reverted), releases), show_user)
+    }
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_changelog_by_day() {
+        let signals = log();
+        let expected = "\
+# Changelog
+
+## 2026-03-17
+
+- Ruby 3.3.6 → 3.4.1 — bob
+- Downgraded rails ~> 7.1 → ~> 7.0 — alice
+
+## 2026-03-16
+
+- Pinned rack to ~> 3.0 — alice
+- Unpinned puma (was ~> 6.4) — alice
+
+## 2026-03-09
+
+- Upgraded nokogiri 1.16.0 → 1.16.2 — alice
+
+## 2026-03-03
+
+- Removed pry — alice
+
+## 2026-03-02
+
+- Ruby 3.3.6 — alice
+- Added rails (~> 7.1) — alice
+- Added pry — alice
+";
+        assert_eq!(render(&signals, 0..signals.len(), false, None), expected);
+    }
+
+    #[test]
+    fn test_changelog_include_reverted() {
+        let signals = log();
+        // 1 人分の範囲ではユーザーを書かない
+        let start = Point::Signal(4).end(&signals);
+        let end = Point::Time(time("2026-03-09T23:59:59Z")).end(&signals);
+        assert_eq!(
+            render(&signals, start..end, true, None),
+            "\
+# Changelog
+
+## 2026-03-09
+
+- Upgraded nokogiri 1.16.0 → 1.16.2
+- Added sidekiq (~> 7.0) (reverted)
+- Reverted: Added sidekiq (~> 7.0)
+
+## 2026-03-03
+
+- Removed pry
+"
+        );
+    }
+
+    #[test]
+    fn test_undo_of_earlier_operation_is_listed() {
+        let mut signals = log();
+        signals.push(signal("d1", "undo", "20T09:00", "alice", json!({ "target_id": "a3", "target_type": "add", "gem": "rails" })));
+        let start = Point::Signal(signals.len() - 2).end(&signals);
+        assert_eq!(render(&signals, start..signals.len(), false, None), "# Changelog\n\n## 2026-03-20\n\n- Reverted: Added rails (~> 7.1)\n");
+        // 範囲に何もなければそう書く
+        assert_eq!(render(&signals, 6..7, false, None), "# Changelog\n\nNo dependency changes.\n");
+    }
+
+    #[test]
+    fn test_changelog_by_release() {
+        let signals = log();
+        let releases = [
+            Release { name: "v1.0.0".to_string(), time: time("2026-03-05T12:00:00Z") },
+            Release { name: "v1.1.0".to_string(), time: time("2026-03-16T12:00:00Z") },
+        ];
+        let start = Point::Tag(releases[0].clone()).end(&signals);
+        assert_eq!(
+            render(&signals, start..signals.len(), false, Some(&releases[1..])),
+            "\
+# Changelog
+
+## Unreleased
+
+- Ruby 3.3.6 → 3.4.1 — bob
+- Downgraded rails ~> 7.1 → ~> 7.0 — alice
+
+## v1.1.0 (2026-03-16)
+
+- Upgraded nokogiri 1.16.0 → 1.16.2 — alice
+- Pinned rack to ~> 3.0 — alice
+- Unpinned puma (was ~> 6.4) — alice
+"
+        );
+    }
+
+    #[test]
+    fn test_point_parse() {
+        let signals = log();
+        let tags = [Release { name: "v1.0.0".to_string(), time: time("2026-03-05T12:00:00Z") }];
+        assert_eq!(Point::parse("b4", &signals, &tags, false).unwrap(), Point::Signal(10));
+        assert_eq!(Point::parse("v1.0.0", &signals, &tags, false).unwrap(), Point::Tag(tags[0].clone()));
+        assert_eq!(Point::parse("2026-03-09T10:00:30Z", &signals, &tags, true).unwrap().end(&signals), 8);
+        assert!(matches!(Point::parse("2026-03-09", &signals, &tags, false).unwrap(), Point::Time(_)));
+        assert!(Point::parse("v9", &signals, &tags, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_tag_line() {
+        let light = parse_tag_line("v1.0.0\t2026-03-05T12:00:00+09:00\t").unwrap();
+        assert_eq!(light.time, time("2026-03-05T12:00:00+09:00"));
+        // 注釈付きタグは指すコミットの時刻
+        let annotated = parse_tag_line("v1.1.0\t2026-03-20T00:00:00+09:00\t2026-03-16T12:00:00+09:00").unwrap();
+        assert_eq!((annotated.name.as_str(), annotated.time), ("v1.1.0", time("2026-03-16T12:00:00+09:00")));
+        assert!(parse_tag_line("broken").is_none());
+    }
+}
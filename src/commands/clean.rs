@@ -0,0 +1,198 @@
+//! `arc clean`: `.arc/env` の中の作り直せるもの (ruby_runtime・bootsnap のキャッシュ・隔離した HOME) を削除する。
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::link;
+use crate::prompt;
+use crate::worktree;
+
+use super::context::CommandContext;
+use super::{preloader, sandbox_home};
+
+pub fn clean(ctx: &CommandContext, runtime: bool, bootsnap: bool, home: bool, yes: bool) -> Result<()> {
+    ctx.project()?;
+    let cwd = ctx.root()?;
+    if ctx.dry_run {
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        let targets = [
+            (runtime, env_dir.join("ruby_runtime")),
+            (bootsnap, env_dir.join(preloader::BOOTSNAP_DIR)),
+            (home, sandbox_home::dir(&env_dir)),
+        ];
+        for (_, dir) in targets.iter().filter(|(selected, dir)| *selected && fs::symlink_metadata(dir).is_ok()) {
+            crate::dry_run::note(&format!("would remove {}", dir.display()));
+        }
+        return Ok(());
+    }
+    if runtime {
+        clean_runtime_at(cwd, yes, &mut std::io::stdin().lock(), &mut std::io::stderr())?;
+    }
+    if bootsnap {
+        clean_bootsnap_at(cwd, &mut std::io::stderr())?;
+    }
+    if home {
+        clean_home_at(cwd, &mut std::io::stderr())?;
+    }
+    Ok(())
+}
+
+/// `.arc/env/home` (`[run] sandbox_home` の HOME) を削除する。passthrough のリンク先は消さない。削除したら `true`。
+fn clean_home_at<W: std::io::Write>(cwd: &Path, out: &mut W) -> Result<bool> {
+    let dir = sandbox_home::dir(&cwd.join(crate::signals::ARC_ENV_DIR));
+    if !dir.exists() {
+        writeln!(out, "ℹ️  No sandbox HOME in this project")?;
+        return Ok(false);
+    }
+    link::remove_tree(&dir).with_context(|| format!("Failed to remove {:?}", dir))?;
+    writeln!(out, "🧹 Removed {}", dir.display())?;
+    Ok(true)
+}
+
+/// `.arc/env/bootsnap` (隔離環境で実行したときの `BOOTSNAP_CACHE_DIR`) を削除する。削除したら `true`。
+fn clean_bootsnap_at<W: std::io::Write>(cwd: &Path, out: &mut W) -> Result<bool> {
+    let dir = cwd.join(crate::signals::ARC_ENV_DIR).join(preloader::BOOTSNAP_DIR);
+    if !dir.exists() {
+        writeln!(out, "ℹ️  No bootsnap cache in this project")?;
+        return Ok(false);
+    }
+    fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {:?}", dir))?;
+    writeln!(out, "🧹 Removed {}", dir.display())?;
+    Ok(true)
+}
+
+/// `.arc/env/ruby_runtime` を削除する。共有された実行環境へのリンクであれば共有先も削除するため、
+/// 他の worktree が参照している場合は警告し、`yes` でなければ確認を求める。削除したら `true`。
+fn clean_runtime_at<R: std::io::BufRead, W: std::io::Write>(cwd: &Path, yes: bool, input: &mut R, out: &mut W) -> Result<bool> {
+    let dest = cwd.join(crate::signals::ARC_ENV_DIR).join("ruby_runtime");
+    if fs::symlink_metadata(&dest).is_err() {
+        writeln!(out, "ℹ️  No ruby_runtime in this project")?;
+        return Ok(false);
+    }
+
+    let shared = fs::read_link(&dest).ok().map(|target| dest.parent().map_or(target.clone(), |p| p.join(&target)));
+    if shared.is_some() {
+        let siblings = worktree::siblings_sharing(cwd, &dest, crate::signals::ARC_ENV_DIR);
+        if let Some(warning) = worktree::sharing_warning(&siblings) {
+            writeln!(out, "{}", warning)?;
+            if !yes && !prompt::confirm(input, out, "Remove the shared ruby_runtime?")? {
+                writeln!(out, "Cancelled (pass --yes to remove it without asking)")?;
+                return Ok(false);
+            }
+        }
+    }
+
+    if let Some(target) = shared.as_ref().filter(|t| fs::symlink_metadata(t).is_ok()) {
+        link::remove_tree(target).with_context(|| format!("Failed to remove {:?}", target))?;
+    }
+    link::remove_tree(&dest).with_context(|| format!("Failed to remove {:?}", dest))?;
+    writeln!(out, "🧹 Removed {}", shared.as_deref().unwrap_or(&dest).display())?;
+    Ok(true)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::LinkMode;
+
+    #[test]
+    fn test_dry_run_clean_keeps_everything() {
+        let cwd = crate::commands::tests::synced_project("arc_clean_dry_run_test");
+        crate::signals::FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap();
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        fs::create_dir_all(env_dir.join(preloader::BOOTSNAP_DIR)).unwrap();
+        fs::create_dir_all(sandbox_home::dir(&env_dir)).unwrap();
+
+        clean(&CommandContext::at(&cwd, true), true, true, true, true).unwrap();
+        assert!(env_dir.join("ruby_runtime").exists());
+        assert!(env_dir.join(preloader::BOOTSNAP_DIR).exists());
+        assert!(sandbox_home::dir(&env_dir).exists());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_clean_runtime_warns_about_sibling_worktrees() {
+        let root = std::env::temp_dir().join("arc_clean_runtime_test");
+        let _ = fs::remove_dir_all(&root);
+        let (main, feature) = (root.join("main"), root.join("feature"));
+        let admin = main.join(".git/worktrees/feature");
+        fs::create_dir_all(&admin).unwrap();
+        fs::create_dir_all(&feature).unwrap();
+        fs::write(admin.join("commondir"), "../..").unwrap();
+        fs::write(admin.join("gitdir"), feature.join(".git").display().to_string()).unwrap();
+        fs::write(feature.join(".git"), format!("gitdir: {}", admin.display())).unwrap();
+        fs::create_dir_all(root.join("cache/bin")).unwrap();
+        fs::write(root.join("cache/bin/ruby"), "").unwrap();
+        let shared = root.join("shared/ruby_runtime/ruby-3.3.6");
+        let dest = |wt: &Path| wt.join(crate::signals::ARC_ENV_DIR).join("ruby_runtime");
+        for wt in [&main, &feature] {
+            worktree::place_runtime(&root.join("cache"), &dest(wt), Some(&shared), LinkMode::Copy).unwrap();
+        }
+
+        let mut out = Vec::new();
+        assert!(!clean_runtime_at(&main, false, &mut std::io::Cursor::new("n\n"), &mut out).unwrap());
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("shared with 1 other worktree"), "{}", text);
+        assert!(text.contains(&fs::canonicalize(&feature).unwrap().display().to_string()));
+        assert!(shared.exists());
+
+        assert!(clean_runtime_at(&main, false, &mut std::io::Cursor::new("y\n"), &mut Vec::new()).unwrap());
+        assert!(!shared.exists());
+        assert!(fs::symlink_metadata(dest(&main)).is_err());
+        // もう片方の worktree のリンクは切れる (bootstrap で作り直す)
+        assert!(fs::symlink_metadata(dest(&feature)).is_ok() && !dest(&feature).exists());
+
+        // 共有していない実行環境は確認なしで削除する
+        let solo = root.join("solo");
+        worktree::place_runtime(&root.join("cache"), &dest(&solo), None, LinkMode::Copy).unwrap();
+        let mut out = Vec::new();
+        assert!(clean_runtime_at(&solo, false, &mut std::io::Cursor::new(""), &mut out).unwrap());
+        assert!(!String::from_utf8(out).unwrap().contains("⚠️"));
+        assert!(!dest(&solo).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_clean_bootsnap() {
+        let cwd = std::env::temp_dir().join("arc_clean_bootsnap_test");
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        let mut out = Vec::new();
+        assert!(!clean_bootsnap_at(&cwd, &mut out).unwrap());
+        assert!(String::from_utf8(out).unwrap().contains("No bootsnap cache"));
+
+        let cache = cwd.join(".arc/env/bootsnap/bootsnap/compile-cache-iseq/00");
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(cache.join("1a2b"), "iseq").unwrap();
+        assert!(clean_bootsnap_at(&cwd, &mut Vec::new()).unwrap());
+        assert!(!cwd.join(".arc/env/bootsnap").exists());
+        assert!(cwd.join(".arc/env").exists());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_clean_home_keeps_passthrough_targets() {
+        let root = std::env::temp_dir().join("arc_clean_home_test");
+        let _ = fs::remove_dir_all(&root);
+        let (cwd, real) = (root.join("project"), root.join("real"));
+        fs::create_dir_all(real.join(".ssh")).unwrap();
+        fs::write(real.join(".ssh/config"), "Host *\n").unwrap();
+        fs::create_dir_all(&cwd).unwrap();
+        let mut out = Vec::new();
+        assert!(!clean_home_at(&cwd, &mut out).unwrap());
+        assert!(String::from_utf8(out).unwrap().contains("No sandbox HOME"));
+
+        let env_path = cwd.join(crate::signals::ARC_ENV_DIR);
+        sandbox_home::prepare(&env_path, Some(&real), &[".ssh".to_string()]).unwrap();
+        assert!(clean_home_at(&cwd, &mut Vec::new()).unwrap());
+        assert!(!env_path.join("home").exists());
+        // リンク先 (本当のホーム) は消さない
+        assert_eq!(fs::read_to_string(real.join(".ssh/config")).unwrap(), "Host *\n");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
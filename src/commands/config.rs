@@ -0,0 +1,91 @@
+//! `arc config list`: 設定の一覧。
+//!
+//! `arc config set`: `.arc/config.toml` の 1 つの値を書き換え、`config_set` Signal に変更前と変更後の値を残す
+//! (`project.name` を変えた時期などを履歴から追えるようにする)。
+
+use anyhow::Result;
+use serde_json::json;
+
+use super::CommandContext;
+use crate::config::ArcConfig;
+use crate::signals::{FluxProject, SignalType};
+
+/// 設定を変更した結果
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// 変更して記録した (変更前の値)
+    Changed(Option<String>),
+    /// 既に同じ値
+    Unchanged,
+}
+
+/// `arc config list`: 設定の一覧。`resolved` なら `${VAR}` を展開した値。
+pub fn config_list(ctx: &CommandContext, resolved: bool) -> Result<()> {
+    let flux_dir = &ctx.project()?.flux_dir;
+    let config = if resolved { ArcConfig::load(flux_dir)? } else { ArcConfig::load_raw(flux_dir)? };
+    for (key, value) in config.list()? {
+        println!("{} = {}", key, value);
+    }
+    Ok(())
+}
+
+pub fn config_set(ctx: &CommandContext, key: &str, value: &str) -> Result<()> {
+    let project = ctx.project()?;
+    if ctx.dry_run {
+        let mut config = ArcConfig::load_raw(&project.flux_dir)?;
+        config.set(key, value)?;
+        crate::dry_run::note(&format!("would set {} = {:?} and record `config_set`", key, value));
+        return Ok(());
+    }
+    match set(project, key, value)? {
+        Outcome::Changed(Some(old)) => eprintln!("✅ {} = {:?} (was {:?})", key, value, old),
+        Outcome::Changed(None) => eprintln!("✅ {} = {:?}", key, value),
+        Outcome::Unchanged => eprintln!("ℹ️  {} is already {:?}", key, value),
+    }
+    Ok(())
+}
+
+/// config.toml を書き換え、値が変わったときだけ `config_set` を記録する。
+pub fn set(project: &FluxProject, key: &str, value: &str) -> Result<Outcome> {
+    let mut config = ArcConfig::load_raw(&project.flux_dir)?;
+    let before = config.list()?;
+    let old = config.set(key, value)?;
+    if config.list()? == before {
+        return Ok(Outcome::Unchanged);
+    }
+    config.save(&project.flux_dir)?;
+    project.record(SignalType::ConfigSet, json!({ "key": key, "old": old, "new": value }))?;
+    Ok(Outcome::Changed(old))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::temp_project;
+    use std::fs;
+
+    #[test]
+    fn test_rename_is_recorded() {
+        let (root, project) = temp_project("arc_config_set_test");
+        fs::write(project.flux_dir.join("config.toml"), "[project]\nname = \"shop\"\n\n[ruby]\nversion = \"3.3.6\"\n").unwrap();
+
+        assert_eq!(set(&project, "project.name", "storefront").unwrap(), Outcome::Changed(Some("shop".to_string())));
+        assert_eq!(ArcConfig::load(&project.flux_dir).unwrap().project.name.as_deref(), Some("storefront"));
+        // 同じ値なら記録しない
+        assert_eq!(set(&project, "project.name", "storefront").unwrap(), Outcome::Unchanged);
+
+        let signals = project.read_signals().unwrap();
+        let renames: Vec<_> = signals.iter().filter(|s| s.r_type == "config_set").collect();
+        assert_eq!(renames.len(), 1);
+        assert_eq!(renames[0].payload, json!({ "key": "project.name", "old": "shop", "new": "storefront" }));
+        assert_eq!(super::super::recent::summary(renames[0]), "project.name: shop → storefront");
+
+        assert!(set(&project, "project.nickname", "x").is_err());
+        assert!(set(&project, "ruby.version", "[1]").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+//! コマンド間で共有する実行コンテキスト。`main` で 1 度だけ作り、各コマンドに `&CommandContext` で渡す。
+//!
+//! プロジェクトと config.toml は最初に要求されたときに読み込む (`FluxProject::open` は表示設定を
+//! 切り替える副作用があるため、プロジェクトを使わないコマンドでは開かない)。
+
+use anyhow::Result;
+use std::cell::OnceCell;
+use std::path::{Path, PathBuf};
+
+use crate::config::ArcConfig;
+use crate::progress;
+use crate::signals::FluxProject;
+
+/// 人間向けの進捗表示。`--progress-json` のときは stderr に何も出さない。
+#[derive(Debug, Clone, Copy)]
+pub struct Reporter {
+    json: bool,
+}
+
+impl Reporter {
+    /// 現在のグローバルな出力設定から作る (`main` でフラグを反映した後に呼ぶ)。
+    pub fn from_flags() -> Self {
+        Self { json: progress::is_json() }
+    }
+
+    /// 進捗の 1 行を stderr に出す。
+    pub fn human(&self, line: &str) {
+        if !self.json {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// コマンドの実行コンテキスト。
+pub struct CommandContext {
+    /// 起動したディレクトリ (取得に失敗した場合はそのエラー)
+    root: std::io::Result<PathBuf>,
+    project: OnceCell<FluxProject>,
+    config: OnceCell<ArcConfig>,
+    pub reporter: Reporter,
+    /// グローバルな `--dry-run`
+    pub dry_run: bool,
+}
+
+impl CommandContext {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            root: std::env::current_dir(),
+            project: OnceCell::new(),
+            config: OnceCell::new(),
+            reporter: Reporter::from_flags(),
+            dry_run,
+        }
+    }
+
+    /// `root` で起動したものとして作る (`arc init <path>` の bootstrap や、作業ディレクトリを変えられないテスト)。
+    pub fn at(root: &Path, dry_run: bool) -> Self {
+        Self { root: Ok(root.to_path_buf()), ..Self::new(dry_run) }
+    }
+
+    /// 起動したディレクトリ。取得できなかった場合は `std::env::current_dir` と同じエラーを返す。
+    pub fn root(&self) -> Result<&Path> {
+        match &self.root {
+            Ok(root) => Ok(root),
+            Err(e) => Err(std::io::Error::new(e.kind(), e.to_string()).into()),
+        }
+    }
+
+    /// 起動したディレクトリの Flux プロジェクト。開けなかった場合は次の呼び出しで開き直す。
+    pub fn project(&self) -> Result<&FluxProject> {
+        if let Some(project) = self.project.get() {
+            return Ok(project);
+        }
+        let project = FluxProject::open(self.root()?)?;
+        Ok(self.project.get_or_init(|| project))
+    }
+
+    /// プロジェクトの config.toml。コマンドの途中で書き換える場合は `ArcConfig::load` で読み直すこと。
+    pub fn config(&self) -> Result<&ArcConfig> {
+        if let Some(config) = self.config.get() {
+            return Ok(config);
+        }
+        let config = ArcConfig::load(&self.project()?.flux_dir)?;
+        Ok(self.config.get_or_init(|| config))
+    }
+}
@@ -0,0 +1,627 @@
+//! `arc run --detach` / `arc ps` / `arc stop` のプロセス管理。
+//!
+//! デタッチ実行は次の 2 段構成で行う:
+//!
+//! ```text
+//! arc run --detach puma
+//!   └─ arc __reap -- puma     (setsid で新しいセッションを作る reaper)
+//!        └─ puma              (新しいプロセスグループ。出力は .flux/output/ へ)
+//! ```
+//!
+//! reaper は子プロセスを `wait` して終了ステータスを `run_end` に記録する。
+//! 実行中のプロセスは `.flux/running/<signal-id>` の pidfile で追跡する。
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use super::preloader::{self, Preloaders};
+use super::runner::inject_isolated_env;
+use super::sandbox_home::Sandbox;
+use crate::config::ArcConfig;
+use crate::output_store::{self, GcReport, Policy};
+use crate::signals::{self, ARC_ENV_DIR, FluxProject, SignalType};
+
+/// pidfile を置くディレクトリ名 (`.flux/running/`)
+const RUNNING_DIR: &str = "running";
+/// デタッチしたプロセスの出力先ディレクトリ名 (`.flux/output/`)
+const OUTPUT_DIR: &str = "output";
+/// `arc stop` が reaper による `run_end` の記録を待つ最大時間
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+// ─────────────────────────────────────────────
+// pidfile
+// ─────────────────────────────────────────────
+
+/// `.flux/running/<signal-id>` に保存されるデタッチプロセスの情報。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunningEntry {
+    /// run_start Signal の ID
+    pub id: String,
+    /// 実行中のコマンドの pid
+    pub pid: u32,
+    /// pid 再利用を見分けるための起動時刻 (`/proc/<pid>/stat` の starttime)
+    pub start_time: u64,
+    /// reaper の pid
+    pub reaper_pid: u32,
+    /// reaper の起動時刻
+    pub reaper_start_time: u64,
+    pub command: String,
+    pub args: Vec<String>,
+    pub started_at: String,
+}
+
+impl RunningEntry {
+    /// コマンドが (同じプロセスとして) まだ生きているか。
+    pub fn is_alive(&self) -> bool {
+        is_same_process(self.pid, self.start_time)
+    }
+
+    /// reaper がまだ生きているか。
+    pub fn reaper_alive(&self) -> bool {
+        is_same_process(self.reaper_pid, self.reaper_start_time)
+    }
+}
+
+pub fn running_dir(flux_dir: &Path) -> PathBuf {
+    flux_dir.join(RUNNING_DIR)
+}
+
+pub fn output_dir(flux_dir: &Path) -> PathBuf {
+    flux_dir.join(OUTPUT_DIR)
+}
+
+fn write_entry(dir: &Path, entry: &RunningEntry) -> Result<()> {
+    crate::perms::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    let path = dir.join(&entry.id);
+    // 途中まで書かれた pidfile を読まれないよう、一時ファイル経由で置き換える
+    let tmp = dir.join(format!(".{}.tmp", entry.id));
+    fs::write(&tmp, serde_json::to_string(entry)?)
+        .with_context(|| format!("Failed to write {:?}", tmp))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+fn read_entry(dir: &Path, id: &str) -> Option<RunningEntry> {
+    let content = fs::read_to_string(dir.join(id)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// pidfile をすべて読み込む。壊れた pidfile は削除してスキップする。
+fn read_entries(dir: &Path) -> Result<Vec<RunningEntry>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut entries = Vec::new();
+    for dirent in fs::read_dir(dir)? {
+        let path = dirent?.path();
+        let is_tmp = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+        if is_tmp || !path.is_file() {
+            continue;
+        }
+
+        match fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str(&c).ok()) {
+            Some(entry) => entries.push(entry),
+            None => {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    entries.sort_by(|a: &RunningEntry, b| a.id.cmp(&b.id));
+    Ok(entries)
+}
+
+/// 実行中 (pidfile がある) のデタッチプロセスの run_start ID
+fn running_ids(flux_dir: &Path) -> Result<BTreeSet<String>> {
+    Ok(read_entries(&running_dir(flux_dir))?.into_iter().map(|e| e.id).collect())
+}
+
+fn remove_entry(dir: &Path, id: &str) {
+    let _ = fs::remove_file(dir.join(id));
+}
+
+// ─────────────────────────────────────────────
+// /proc ヘルパー
+// ─────────────────────────────────────────────
+
+/// `/proc/<pid>/stat` から必要なフィールドだけを取り出したもの。
+#[derive(Debug)]
+struct ProcStat {
+    state: char,
+    /// セッション ID (テストで setsid の確認に使用する)
+    #[cfg_attr(not(test), allow(dead_code))]
+    session: i64,
+    start_time: u64,
+}
+
+fn read_proc_stat(pid: u32) -> Option<ProcStat> {
+    let content = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // comm (2 番目のフィールド) は空白や括弧を含みうるため、最後の ')' 以降を分割する
+    let (_, rest) = content.rsplit_once(')')?;
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    // fields[0] = state (3), fields[3] = session (6), fields[19] = starttime (22)
+    Some(ProcStat {
+        state: fields.first()?.chars().next()?,
+        session: fields.get(3)?.parse().ok()?,
+        start_time: fields.get(19)?.parse().ok()?,
+    })
+}
+
+/// プロセスの起動時刻を返す。プロセスが存在しなければ `None`。
+fn process_start_time(pid: u32) -> Option<u64> {
+    read_proc_stat(pid).map(|s| s.start_time)
+}
+
+/// `pid` が `start_time` に起動したプロセスとしてまだ生きているか。
+/// ゾンビは終了済みとして扱う。
+fn is_same_process(pid: u32, start_time: u64) -> bool {
+    matches!(read_proc_stat(pid), Some(s) if s.start_time == start_time && s.state != 'Z')
+}
+
+/// 子プロセスを新しいセッションで起動するよう `Command` を設定する。
+fn new_session(command: &mut Command) {
+    // SAFETY: pre_exec 内では async-signal-safe な setsid(2) のみを呼ぶ
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+// ─────────────────────────────────────────────
+// 起動 (親側)
+// ─────────────────────────────────────────────
+
+/// reaper を新しいセッションで起動し、記録された run_start の Signal ID と pid を返す。
+/// reaper が run_start を記録して pidfile を書き終えるまで待ってから戻る。
+/// `fields` は `arc __reap --fields` で reaper に渡し、run_start の payload に加える。
+#[allow(clippy::too_many_arguments)]
+pub fn spawn(
+    cwd: &Path,
+    cmd: &str,
+    args: &[String],
+    alias: Option<&str>,
+    env: &BTreeMap<String, String>,
+    spring: bool,
+    sandbox_home: bool,
+    fields: &serde_json::Value,
+) -> Result<(String, u32)> {
+    let exe = std::env::current_exe().context("arc 実行ファイルのパスを取得できません")?;
+
+    let mut command = Command::new(exe);
+    command.arg("__reap");
+    if let Some(alias) = alias {
+        command.arg("--alias").arg(alias);
+    }
+    if spring {
+        command.arg("--spring");
+    }
+    if sandbox_home {
+        command.arg("--sandbox-home");
+    }
+    if fields.as_object().is_some_and(|f| !f.is_empty()) {
+        command.arg("--fields").arg(fields.to_string());
+    }
+    command
+        .arg("--")
+        .arg(cmd)
+        .args(args)
+        .current_dir(cwd)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        // 起動した端末から切り離す。コマンドの出力は reaper が .out / .err に書く
+        .stderr(Stdio::null());
+    new_session(&mut command);
+
+    let mut reaper = command
+        .spawn()
+        .context("デタッチ実行用プロセスの起動に失敗しました")?;
+
+    // ハンドシェイク: reaper は "<signal-id> <pid>" を 1 行だけ出力する
+    let stdout = reaper.stdout.take().context("reaper の stdout を取得できません")?;
+    let mut line = String::new();
+    BufReader::new(stdout).read_line(&mut line)?;
+
+    let parsed = line
+        .split_once(' ')
+        .and_then(|(id, pid)| Some((id.to_string(), pid.trim().parse::<u32>().ok()?)));
+
+    match parsed {
+        Some(handshake) => Ok(handshake),
+        None => {
+            let _ = reaper.wait();
+            anyhow::bail!("コマンド '{}' をデタッチ実行できませんでした。--detach を付けずに実行すると原因を確認できます。", cmd)
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// reaper (子側)
+// ─────────────────────────────────────────────
+
+/// `arc __reap` の本体。コマンドを起動して終了まで待ち、run_end を記録する。
+/// `spring` は `arc run --spring`、`sandbox_home` は `arc run --sandbox-home`。
+/// `fields` (`[safety]` の確認結果など) は run_start の payload に加える。
+#[allow(clippy::too_many_arguments)]
+pub fn reap(
+    project: &FluxProject,
+    cwd: &Path,
+    cmd: &str,
+    args: &[String],
+    alias: Option<&str>,
+    spring: bool,
+    sandbox_home: bool,
+    fields: serde_json::Value,
+) -> Result<()> {
+    let id = signals::new_signal_id();
+    let running = running_dir(&project.flux_dir);
+    let output = output_dir(&project.flux_dir);
+    crate::perms::create_dir_all(&output).with_context(|| format!("Failed to create {:?}", output))?;
+    // 新しい出力を作る前に [output] の上限を適用する (新しい出力は削除の対象にならない)
+    let config = ArcConfig::load(&project.flux_dir)?;
+    gc_output(project, &config)?;
+
+    let out_path = output.join(format!("{}.out", id));
+    let err_path = output.join(format!("{}.err", id));
+
+    let mut command = Command::new(cmd);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(File::create(&out_path)?)
+        .stderr(File::create(&err_path)?)
+        // arc stop でサーバーのワーカーごと止められるよう、独立したプロセスグループにする
+        .process_group(0);
+    inject_isolated_env(&mut command, cwd)?;
+    let preloaders = Preloaders::decide(&project.root, &config.run, spring, || preloader::processes_if_locked(&project.root));
+    command.envs(preloaders.env());
+    let mut env_context = json!({ "mode": "isolated", "GEM_HOME": ARC_ENV_DIR });
+    if let (Some(context), serde_json::Value::Object(fields)) = (env_context.as_object_mut(), preloaders.context_fields()) {
+        context.extend(fields);
+    }
+    if let Some(sandbox) = Sandbox::decide(cwd, &config.run, sandbox_home)? {
+        command.envs(sandbox.env());
+        if let Some(context) = env_context.as_object_mut() {
+            context.extend(sandbox.context_fields());
+        }
+    }
+
+    let timer = Instant::now();
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("コマンド '{}' の起動に失敗しました: {}", cmd, e))?;
+    let pid = child.id();
+    let reaper_pid = std::process::id();
+
+    let mut payload = json!({
+        "command": cmd,
+        "args": args,
+        "cwd": cwd.to_string_lossy(),
+        "env_context": env_context,
+        crate::exec_context::PAYLOAD_KEY: crate::exec_context::ExecContext::current(),
+        "detached": true,
+        "pid": pid,
+        "stdout": out_path.to_string_lossy(),
+        "stderr": err_path.to_string_lossy(),
+    });
+    if let Some(alias) = alias {
+        payload["alias"] = json!(alias);
+    }
+    super::exec::merge_fields(&mut payload, fields);
+    let start_signal = project.record_with_id(id.clone(), SignalType::RunStart, payload)?;
+
+    write_entry(
+        &running,
+        &RunningEntry {
+            id: id.clone(),
+            pid,
+            start_time: process_start_time(pid).unwrap_or(0),
+            reaper_pid,
+            reaper_start_time: process_start_time(reaper_pid).unwrap_or(0),
+            command: cmd.to_string(),
+            args: args.to_vec(),
+            started_at: start_signal.timestamp.clone(),
+        },
+    )?;
+
+    // ハンドシェイク。親が終了した後は stdout に書き込まない
+    {
+        let mut stdout = std::io::stdout();
+        writeln!(stdout, "{} {}", id, pid)?;
+        stdout.flush()?;
+    }
+
+    let status = child.wait()?;
+    let elapsed = timer.elapsed();
+
+    project.record(
+        SignalType::RunEnd,
+        json!({
+            "ref_id": id,
+            "exit_code": status.code(),
+            "signal": status.signal(),
+            "success": status.success(),
+            "duration_ms": elapsed.as_millis() as u64,
+            "duration_us": elapsed.as_micros() as u64,
+            "detached": true,
+        }),
+    )?;
+    remove_entry(&running, &id);
+
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// 出力の容量管理
+// ─────────────────────────────────────────────
+
+/// `[output]` の上限を出力ディレクトリに適用する。実行中のプロセスの出力には触れない。
+/// 期限内の出力を容量のために削除した場合は `x-output-evicted` に記録する。
+pub fn gc_output(project: &FluxProject, config: &ArcConfig) -> Result<GcReport> {
+    let keep = running_ids(&project.flux_dir)?;
+    let report = output_store::enforce(
+        &output_dir(&project.flux_dir),
+        &Policy::from_config(&config.output),
+        &keep,
+        std::time::SystemTime::now(),
+    )?;
+    let early: Vec<_> = report
+        .early_evictions()
+        .map(|e| json!({ "ref_id": e.id, "bytes": e.bytes, "reason": e.reason.as_str() }))
+        .collect();
+    if !early.is_empty() {
+        project.record(SignalType::custom("output", "evicted")?, json!({ "evicted": early }))?;
+    }
+    Ok(report)
+}
+
+// ─────────────────────────────────────────────
+// 一覧・停止
+// ─────────────────────────────────────────────
+
+/// reaper ごと消えてしまったエントリを abandoned として閉じる。
+fn abandon(project: &FluxProject, entry: &RunningEntry) -> Result<()> {
+    project.record(
+        SignalType::RunEnd,
+        json!({
+            "ref_id": entry.id,
+            "exit_code": null,
+            "success": false,
+            "detached": true,
+            "abandoned": true,
+        }),
+    )?;
+    remove_entry(&running_dir(&project.flux_dir), &entry.id);
+    Ok(())
+}
+
+/// 実行中のデタッチプロセスを返す。
+/// コマンドも reaper も終了している古い pidfile は abandoned として記録して掃除する。
+pub fn list(project: &FluxProject) -> Result<Vec<RunningEntry>> {
+    let mut alive = Vec::new();
+
+    for entry in read_entries(&running_dir(&project.flux_dir))? {
+        if entry.is_alive() {
+            alive.push(entry);
+        } else if !entry.reaper_alive() {
+            abandon(project, &entry)?;
+        }
+        // コマンドは終了済みだが reaper が生きている場合は、reaper が run_end を書くのを待つ
+    }
+
+    Ok(alive)
+}
+
+/// 停止処理の結果。
+#[derive(Debug, PartialEq)]
+pub enum StopOutcome {
+    /// reaper が終了ステータスを記録した
+    Reaped,
+    /// reaper がいないため abandoned として記録した
+    Abandoned,
+    /// SIGTERM を送ったが、待機時間内に終了しなかった
+    StillRunning,
+}
+
+/// デタッチプロセスのプロセスグループに SIGTERM を送り、終了を待つ。
+pub fn stop(project: &FluxProject, id: &str) -> Result<StopOutcome> {
+    let running = running_dir(&project.flux_dir);
+    let entry = read_entry(&running, id)
+        .with_context(|| format!("実行中のプロセスが見つかりません: {}", id))?;
+
+    if entry.is_alive() {
+        // SAFETY: kill(2) はメモリ安全性に影響しない。負の pid はプロセスグループを指す
+        let rc = unsafe { libc::kill(-(entry.pid as libc::pid_t), libc::SIGTERM) };
+        if rc == -1 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("pid {} への SIGTERM に失敗しました", entry.pid));
+        }
+    }
+
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while Instant::now() < deadline {
+        if !running.join(id).exists() {
+            return Ok(StopOutcome::Reaped);
+        }
+        if !entry.reaper_alive() {
+            abandon(project, &entry)?;
+            return Ok(StopOutcome::Abandoned);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    Ok(StopOutcome::StillRunning)
+}
+
+/// `started_at` (RFC 3339) から現在までの経過時間を返す。
+pub fn uptime_since(started_at: &str) -> Option<std::time::Duration> {
+    let started = chrono::DateTime::parse_from_rfc3339(started_at).ok()?;
+    Local::now().signed_duration_since(started).to_std().ok()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::temp_project;
+
+    fn entry_for(id: &str, pid: u32, start_time: u64) -> RunningEntry {
+        RunningEntry {
+            id: id.to_string(),
+            pid,
+            start_time,
+            reaper_pid: pid,
+            reaper_start_time: start_time,
+            command: "puma".to_string(),
+            args: vec![],
+            started_at: Local::now().to_rfc3339(),
+        }
+    }
+
+    /// 終了済みのプロセスの pid を得る
+    fn dead_pid() -> u32 {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    #[test]
+    fn test_new_session() {
+        let mut command = Command::new("sleep");
+        command.arg("5");
+        new_session(&mut command);
+        let mut child = command.spawn().unwrap();
+
+        // setsid 済みのプロセスは自身がセッションリーダーになる
+        let stat = read_proc_stat(child.id()).unwrap();
+        assert_eq!(stat.session, child.id() as i64);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_pid_reuse_detected() {
+        let me = std::process::id();
+        let start = process_start_time(me).unwrap();
+        assert!(is_same_process(me, start));
+        // 同じ pid でも起動時刻が違えば別プロセス
+        assert!(!is_same_process(me, start + 1));
+        assert!(!is_same_process(dead_pid(), start));
+    }
+
+    #[test]
+    fn test_pidfile_roundtrip() {
+        let dir = std::env::temp_dir().join("arc_detach_pidfile_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let entry = entry_for("0001", 42, 7);
+        write_entry(&dir, &entry).unwrap();
+
+        let entries = read_entries(&dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].pid, 42);
+        assert_eq!(read_entry(&dir, "0001").unwrap().start_time, 7);
+
+        remove_entry(&dir, "0001");
+        assert!(read_entries(&dir).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_pidfile_removed() {
+        let dir = std::env::temp_dir().join("arc_detach_corrupt_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("garbage"), "not json").unwrap();
+
+        assert!(read_entries(&dir).unwrap().is_empty());
+        assert!(!dir.join("garbage").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stale_entry_cleanup() {
+        let (dir, project) = temp_project("arc_detach_stale_test");
+        let running = running_dir(&project.flux_dir);
+        write_entry(&running, &entry_for("0001", dead_pid(), 1)).unwrap();
+
+        assert!(list(&project).unwrap().is_empty());
+        assert!(!running.join("0001").exists());
+
+        let signals = project.read_signals().unwrap();
+        let end = signals.iter().find(|s| s.r_type == "run_end").unwrap();
+        assert_eq!(end.payload["ref_id"], "0001");
+        assert_eq!(end.payload["abandoned"], true);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_live_entry_listed() {
+        let (dir, project) = temp_project("arc_detach_live_test");
+        let me = std::process::id();
+        let start = process_start_time(me).unwrap();
+        write_entry(&running_dir(&project.flux_dir), &entry_for("0001", me, start)).unwrap();
+
+        let alive = list(&project).unwrap();
+        assert_eq!(alive.len(), 1);
+        assert_eq!(alive[0].id, "0001");
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_gc_output_records_early_evictions() {
+        let (dir, project) = temp_project("arc_detach_gc_output_test");
+        let output = output_dir(&project.flux_dir);
+        fs::create_dir_all(&output).unwrap();
+        for id in ["0001", "0002", "0003"] {
+            fs::write(output.join(format!("{}.out", id)), vec![b'x'; 400 * 1024]).unwrap();
+        }
+        // 実行中の 0001 は上限を超えていても残す
+        let me = std::process::id();
+        write_entry(&running_dir(&project.flux_dir), &entry_for("0001", me, process_start_time(me).unwrap())).unwrap();
+        let mut config = ArcConfig::default();
+        config.output.max_total_mb = Some(1);
+
+        let report = gc_output(&project, &config).unwrap();
+        assert_eq!(report.evicted.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), ["0002"]);
+        assert!(output.join("0001.out").exists() && output.join("0003.out").exists());
+
+        let evicted = project.read_signals().unwrap().into_iter().find(|s| s.r_type == "x-output-evicted").unwrap();
+        assert_eq!(evicted.payload["evicted"][0]["ref_id"], "0002");
+        assert_eq!(evicted.payload["evicted"][0]["reason"], "max_total_mb");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stop_without_reaper_abandons() {
+        let (dir, project) = temp_project("arc_detach_stop_test");
+        write_entry(&running_dir(&project.flux_dir), &entry_for("0001", dead_pid(), 1)).unwrap();
+
+        assert_eq!(stop(&project, "0001").unwrap(), StopOutcome::Abandoned);
+        assert!(stop(&project, "0001").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! `arc doctor`: プロジェクトの状態を点検し、完了していない操作 (`.flux/intent.json`) を調べる。
+
+use anyhow::Result;
+use serde_json::json;
+use std::path::Path;
+
+use crate::display;
+use crate::gemfile_hash;
+use crate::intent;
+use crate::signals::{FluxProject, LogState, SignalType};
+
+use super::context::CommandContext;
+
+/// 完了していない操作 (`.flux/intent.json`) を調べる。`resolve_intent` なら記録を完了させるか破棄する。
+pub fn doctor(ctx: &CommandContext, resolve_intent: bool) -> Result<()> {
+    doctor_at(ctx.project()?, ctx.root()?, resolve_intent)
+}
+
+pub(super) fn doctor_at(project: &FluxProject, cwd: &Path, resolve_intent: bool) -> Result<()> {
+    if project.log_state() == LogState::Empty {
+        eprintln!(
+            "⚠️  {:?} is missing or empty: no operations recorded yet (not even `init`). It is created on the next recorded operation",
+            project.signal_file
+        );
+    }
+    match crate::arc_home::get() {
+        Ok(home) => eprintln!("🏠 Arc home: {}; cache: {}", home.root.describe(), home.cache.describe()),
+        Err(e) => eprintln!("⚠️  {}", e),
+    }
+    let unsafe_paths = crate::safe_path::audit(project, &project.read_signals()?);
+    if !unsafe_paths.is_empty() {
+        eprintln!("⚠️  {} recorded path(s) point outside .flux / .arc (arc refuses to read them):", unsafe_paths.len());
+        for issue in &unsafe_paths {
+            eprintln!("   {}", issue);
+        }
+    }
+    let Some(pending) = intent::read(&project.flux_dir)? else {
+        eprintln!("✅ No unfinished operations");
+        return Ok(());
+    };
+    let resolution = intent::resolve(&pending, &project.read_signals()?, gemfile_hash::hash(&cwd.join("Gemfile")).as_deref());
+    eprintln!("⚠️  Unfinished `{}` (started {})", pending.command, display::fmt_timestamp(&pending.started_at));
+    eprintln!("   {}", resolution.describe());
+    if !resolve_intent {
+        eprintln!("   Run `arc doctor --resolve-intent` to apply this");
+        return Ok(());
+    }
+
+    if resolution == intent::Resolution::Complete {
+        let mut payload = pending.payload.clone();
+        payload["resolved_intent"] = json!(true);
+        project.record_with_id(pending.signal_id.clone(), SignalType::parse(&pending.signal_type)?, payload)?;
+        eprintln!("📝 Recorded `{}`", pending.signal_type);
+    }
+    intent::clear(&project.flux_dir)?;
+    eprintln!("✅ Resolved");
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! `arc env`: 隔離環境の情報・PATH の確認・env.lock の検証と、`arc env diff`。
+
+use anyhow::Result;
+use std::env;
+use std::path::Path;
+
+use crate::display;
+use crate::link;
+use crate::signals::SignalType;
+
+use super::context::CommandContext;
+use super::runner::{build_ld_library_path, ruby_bin};
+use super::sync::{current_env_lock, measure_env};
+
+// ─────────────────────────────────────────────
+// arc env
+// ─────────────────────────────────────────────
+
+pub fn env(ctx: &CommandContext, check_path: bool, verify_lock: bool, names: &[String]) -> Result<()> {
+    let cwd = ctx.root()?;
+    if verify_lock {
+        return verify_env_lock(ctx);
+    }
+    let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+    let ruby_bin_path = ruby_bin(&env_dir);
+
+    // プロジェクト名は Flux プロジェクト外でも env を表示できるようベストエフォートで取得する
+    let config = ctx.config().ok();
+
+    if check_path {
+        let inherited = env::var_os("PATH");
+        let dirs = super::runner::isolated_path_dirs(&env_dir, inherited.as_deref());
+        let expected = config.map(|c| c.ruby.version.as_str());
+        return super::path_check::report(&super::path_check::check(&env_dir, &dirs, names, expected));
+    }
+
+    eprintln!("⚡ arc env");
+    eprintln!();
+    if let Some(name) = config.and_then(|c| c.project.name.as_deref()) {
+        eprintln!("  Name:      {}", name);
+    }
+    eprintln!("  Project:   {}", cwd.display());
+    eprintln!("  ARC_ENV:   {}", env_dir.display());
+    eprintln!("  Storage:   {}",
+        link::EnvStorage::inspect(&env_dir, crate::signals::get_global_cache_dir().ok().as_deref()).describe()
+    );
+    eprintln!("  GEM_HOME:  {}", env_dir.display());
+    match config.map(|c| &c.run).filter(|run| run.sandbox_home) {
+        Some(run) if run.sandbox_home_passthrough.is_empty() => {
+            eprintln!("  HOME:      {}", super::sandbox_home::dir(&env_dir).display());
+        }
+        Some(run) => eprintln!("  HOME:      {} (passthrough: {})",
+            super::sandbox_home::dir(&env_dir).display(), run.sandbox_home_passthrough.join(", ")),
+        None => eprintln!("  HOME:      {} (not sandboxed)", env::var("HOME").unwrap_or_default()),
+    }
+    if let Some(line) = env_disk_line(ctx) {
+        eprintln!("  Disk:      {}", line);
+    }
+    // bundler の設定も隔離している (~/.bundle・.bundle/config は読まれない)
+    eprintln!("  BUNDLE_APP_CONFIG: {}", crate::bundler_config::app_config_dir(cwd).display());
+    match crate::arc_home::get() {
+        Ok(home) => {
+            eprintln!("  BUNDLE_USER_HOME:  {}", home.root.path.join(crate::bundler_config::USER_HOME_DIR).display());
+            eprintln!("  Arc home:  {}", home.root.describe());
+            eprintln!("  Cache:     {}", home.cache.describe());
+        }
+        Err(e) => eprintln!("  Arc home:  ⚠️  {}", e),
+    }
+    eprintln!("  Ruby:      {}",
+        if ruby_bin_path.exists() { ruby_bin_path.display().to_string() }
+        else { "(not bootstrapped — run `arc bootstrap`)".to_string() }
+    );
+
+    // Ruby バージョンを実際に走らせて表示（共有ライブラリを解決してから実行）
+    if ruby_bin_path.exists() {
+        let mut cmd = std::process::Command::new(&ruby_bin_path);
+        cmd.arg("--version");
+
+        // LD_LIBRARY_PATH を設定 (runner と同じロジックを共有)
+        if let Some(ld_path) = build_ld_library_path(&env_dir) {
+            cmd.env("LD_LIBRARY_PATH", ld_path);
+        }
+
+        if let Ok(o) = cmd.output() {
+            let ver = if !o.stdout.is_empty() {
+                String::from_utf8_lossy(&o.stdout).to_string()
+            } else {
+                String::from_utf8_lossy(&o.stderr).to_string()
+            };
+            eprintln!("  Version:   {}", ver.trim());
+        }
+    }
+
+    eprintln!();
+    Ok(())
+}
+
+/// `arc env` の Disk 行: 現在のサイズと、直前の sync で記録したサイズからの増減。
+pub(super) fn env_disk_line(ctx: &CommandContext) -> Option<String> {
+    let size = measure_env(ctx.root().ok()?, ctx.config().ok()?)?;
+    let mut line = format!("{}{}", if size.partial { "≥" } else { "" }, display::fmt_bytes(size.bytes));
+    let previous = ctx.project().ok()?.read_signals().ok()?.into_iter().rev().find_map(|s| {
+        (s.r_type == SignalType::InstallEnd.to_string()).then(|| s.payload["disk"]["bytes"].as_u64()).flatten()
+    });
+    if let Some(previous) = previous {
+        let (sign, delta) = if size.bytes >= previous { ('+', size.bytes - previous) } else { ('-', previous - size.bytes) };
+        line.push_str(&format!(" ({}{} since the last sync)", sign, display::fmt_bytes(delta)));
+    }
+    Some(line)
+}
+
+/// `arc env diff`: `base` (省略時はカレントディレクトリ) と `other` の環境を比べる。違いがあれば失敗する。
+pub fn env_diff(ctx: &CommandContext, other: &Path, base: Option<&Path>) -> Result<()> {
+    let cwd = ctx.root()?;
+    let base = base.unwrap_or(cwd);
+    let diff = crate::env_diff::compare(&crate::env_diff::Manifest::load(base)?, &crate::env_diff::Manifest::load(other)?);
+    for line in diff.lines(&base.display().to_string(), &other.display().to_string()) {
+        println!("{}", line);
+    }
+    if diff.differences() > 0 {
+        anyhow::bail!("The environments differ ({} difference(s))", diff.differences());
+    }
+    Ok(())
+}
+
+/// `.arc/env.lock` を現在の環境から組み立て直し、ずれていれば差分を表示してエラーにする。
+pub(super) fn verify_env_lock(ctx: &CommandContext) -> Result<()> {
+    let cwd = ctx.root()?;
+    match current_env_lock(cwd, ctx.config()?)?.diff_against_file(cwd)? {
+        None => {
+            eprintln!("✅ {} matches the environment", crate::env_lock::ENV_LOCK_FILE);
+            Ok(())
+        }
+        Some(diff) => {
+            print!("{}", diff);
+            anyhow::bail!("{} does not match the environment (run `arc sync` to regenerate it)", crate::env_lock::ENV_LOCK_FILE)
+        }
+    }
+}
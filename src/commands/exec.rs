@@ -0,0 +1,571 @@
+//! `arc exec` / `arc run` / `arc task`: コマンドを記録しながら実行する。
+//! `arc ps` / `arc stop` / `arc output` はバックグラウンドで起動した実行を扱う。
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+
+use crate::binstubs;
+use crate::config::{ArcConfig, CommandSpec};
+use crate::display;
+use crate::signals::{FluxProject, SignalType};
+
+use super::context::CommandContext;
+use super::pipeline::ShellInvocation;
+use super::runner::{ArcEnv, ruby_runtime_bin};
+
+// ─────────────────────────────────────────────
+// arc exec
+// ─────────────────────────────────────────────
+
+pub fn exec(ctx: &CommandContext, args: &[String], shell: bool, yes: bool) -> Result<()> {
+    if args.is_empty() {
+        anyhow::bail!("コマンドを指定してください。Usage: arc exec <command> [args...]");
+    }
+    let cwd = ctx.root()?;
+    let project = ctx.project()?;
+    let config = ctx.config()?;
+
+    if shell {
+        let shell = ShellInvocation::new(config.run.shell.as_deref(), args);
+        let confirmation = super::safety::confirm_on_terminal(project, config, &args[0], &shell.text, yes)?;
+        eprintln!("🚀 arc exec: {}", shell.text);
+        let mut extra = shell.signal_fields();
+        merge_fields(&mut extra, confirmation.signal_fields());
+        merge_fields(&mut extra, super::runner::env_fields(&config.env.set));
+        let executed = super::runner::execute_recorded(
+            project,
+            SignalType::ExecStart,
+            &shell.program,
+            &shell.args,
+            cwd,
+            ArcEnv::System,
+            extra,
+        )?;
+        return super::runner::finish_recorded(project, SignalType::ExecEnd, &executed, json!({}));
+    }
+
+    let (cmd, cmd_args) = (&args[0], &args[1..]);
+    let line = display::fmt_cmd(cmd, cmd_args);
+    let confirmation = super::safety::confirm_on_terminal(project, config, cmd, &line, yes)?;
+    if let Some(hint) = super::pipeline::hint("exec", args) {
+        eprintln!("{}", hint);
+    }
+    eprintln!("🚀 arc exec: {}", line);
+
+    let mut extra = confirmation.signal_fields();
+    merge_fields(&mut extra, super::runner::env_fields(&config.env.set));
+    super::runner::run_with_flux(
+        project,
+        SignalType::ExecStart,
+        SignalType::ExecEnd,
+        cmd,
+        cmd_args,
+        cwd,
+        ArcEnv::System,
+        extra,
+    )
+}
+
+/// `extra` のフィールドを `fields` に追加する (start Signal の payload 用)
+pub(super) fn merge_fields(fields: &mut Value, extra: Value) {
+    if let (Some(fields), Value::Object(extra)) = (fields.as_object_mut(), extra) {
+        fields.extend(extra);
+    }
+}
+
+// ─────────────────────────────────────────────
+// arc run
+// ─────────────────────────────────────────────
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(ctx: &CommandContext, args: &[String], detach: bool, require_alias: bool, shell: bool, yes: bool, spring: bool, sandbox_home: bool) -> Result<()> {
+    if args.is_empty() {
+        anyhow::bail!("実行するコマンドを指定してください。");
+    }
+    ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    run_at(ctx, args, detach, require_alias, shell, yes, spring, sandbox_home)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn run_at(
+    ctx: &CommandContext,
+    args: &[String],
+    detach: bool,
+    require_alias: bool,
+    shell: bool,
+    yes: bool,
+    spring: bool,
+    sandbox_home: bool,
+) -> Result<()> {
+    // reaper は別のプロセスで run_start / run_end と出力 (.flux/output) を書くため、読み取り専用では起動しない
+    if detach {
+        crate::read_only::refuse("run --detach")?;
+    }
+    let (project, cwd) = (ctx.project()?, ctx.root()?);
+    let config = ctx.config()?;
+
+    // 先頭の KEY=value はコマンドの環境変数 (エイリアス・[env] set より優先)
+    let (assignments, args) = leading_assignments(args);
+    if args.is_empty() {
+        anyhow::bail!("実行するコマンドを指定してください。");
+    }
+    let (alias, spec) = resolve_run_args(config, args, require_alias)?;
+    let env = config.command_env(&spec.env, &assignments);
+    let env_mode = if spec.isolated_or_default() { ArcEnv::Isolated } else { ArcEnv::System };
+    let argv = spec.cmd;
+    let (cmd, cmd_args) = (&argv[0], &argv[1..]);
+    if let Some(ref name) = alias {
+        eprintln!("🏷  {} → {}", name, display::fmt_cmd(cmd, cmd_args));
+    }
+
+    // エイリアス展開後のコマンドラインで確認する
+    let line = if shell { argv.join(" ") } else { display::fmt_cmd(cmd, cmd_args) };
+    let confirmation = super::safety::confirm_on_terminal(project, config, cmd, &line, yes)?;
+
+    if detach {
+        // reaper は別のプロセスでコマンドを実行して記録するため、dry-run では起動しない
+        if ctx.dry_run {
+            crate::dry_run::note(&format!("would run detached: {}", display::fmt_cmd(cmd, cmd_args)));
+            return Ok(());
+        }
+        let (id, pid) = super::detach::spawn(cwd, cmd, cmd_args, alias.as_deref(), &env, spring, sandbox_home, &confirmation.signal_fields())?;
+        eprintln!("🌙 Detached: {} (pid {})", display::fmt_cmd(cmd, cmd_args), pid);
+        eprintln!(
+            "   Output: {}.{{out,err}}",
+            super::detach::output_dir(&project.flux_dir).join(&id).display()
+        );
+        eprintln!("   Stop with `arc stop {}`", id);
+        println!("{}", id);
+        return Ok(());
+    }
+
+    let mut extra = match alias {
+        Some(name) => json!({ "alias": name }),
+        None => json!({}),
+    };
+    merge_fields(&mut extra, confirmation.signal_fields());
+    merge_fields(&mut extra, super::runner::env_fields(&env));
+    if spring {
+        extra["spring"] = json!(true);
+    }
+    if sandbox_home {
+        extra["sandbox_home"] = json!(true);
+    }
+    // --shell: エイリアス展開後のコマンドラインをシェルに渡し、元のコマンドラインを記録する
+    let shell = shell.then(|| ShellInvocation::new(config.run.shell.as_deref(), &argv));
+    let (cmd, cmd_args) = match &shell {
+        Some(shell) => {
+            merge_fields(&mut extra, shell.signal_fields());
+            (&shell.program, shell.args.as_slice())
+        }
+        None => {
+            if let Some(hint) = super::pipeline::hint("run", &argv) {
+                eprintln!("{}", hint);
+            }
+            (cmd, cmd_args)
+        }
+    };
+    let executed = super::runner::execute_recorded(project, SignalType::RunStart, cmd, cmd_args, cwd, env_mode, extra)?;
+    super::runner::finish_recorded(project, SignalType::RunEnd, &executed, json!({}))
+}
+
+/// 先頭の `KEY=value` の並びと、残りの引数に分ける。
+pub(super) fn leading_assignments(args: &[String]) -> (Vec<(String, String)>, &[String]) {
+    let is_name = |name: &str| {
+        name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let assignments: Vec<(String, String)> = args
+        .iter()
+        .map_while(|arg| arg.split_once('=').filter(|(name, _)| is_name(name)))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    let rest = &args[assignments.len()..];
+    (assignments, rest)
+}
+
+// ─────────────────────────────────────────────
+// arc task / arc test
+// ─────────────────────────────────────────────
+
+/// `arc task <name>` / `arc test`: `[commands]` のタスクを実行し (既定は隔離環境)、タスク名で記録する。
+pub fn task(ctx: &CommandContext, name: &str, extra: &[String]) -> Result<()> {
+    let cwd = ctx.root()?;
+    let project = ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    task_at(project, cwd, name, extra)
+}
+
+pub(super) fn task_at(project: &FluxProject, cwd: &Path, name: &str, extra: &[String]) -> Result<()> {
+    let config = ArcConfig::load(&project.flux_dir)?;
+    let (spec, source) = super::task::resolve(&config, cwd, name, extra)?;
+    let env_mode = if spec.isolated_or_default() { ArcEnv::Isolated } else { ArcEnv::System };
+    let mut fields = json!({ "task": name });
+    merge_fields(&mut fields, super::runner::env_fields(&config.command_env(&spec.env, &[])));
+    let (cmd, cmd_args) = (&spec.cmd[0], &spec.cmd[1..]);
+    match source {
+        super::task::Source::Config => eprintln!("🏷  {} → {}", name, display::fmt_cmd(cmd, cmd_args)),
+        super::task::Source::Detected(reason) => {
+            eprintln!("🏷  {} → {} (detected: {})", name, display::fmt_cmd(cmd, cmd_args), reason)
+        }
+    }
+
+    let executed = super::runner::execute_recorded(
+        project,
+        SignalType::RunStart,
+        cmd,
+        cmd_args,
+        cwd,
+        env_mode,
+        fields,
+    )?;
+    super::runner::finish_recorded(project, SignalType::RunEnd, &executed, json!({}))
+}
+
+/// `arc run` の引数の先頭がエイリアスであれば展開し、(エイリアス名, コマンド) を返す。
+/// `require_alias` (`arc r`) の場合、エイリアスでなければエラーにする。
+pub(super) fn resolve_run_args(
+    config: &ArcConfig,
+    args: &[String],
+    require_alias: bool,
+) -> Result<(Option<String>, CommandSpec)> {
+    let (name, extra) = (&args[0], &args[1..]);
+    match config.expand_alias(name, extra) {
+        Some(spec) => Ok((Some(name.clone()), spec)),
+        None if require_alias => {
+            if config.aliases.is_empty() {
+                anyhow::bail!(
+                    "エイリアス '{}' は定義されていません。config.toml の [aliases] に追加してください。",
+                    name
+                );
+            }
+            let known: Vec<&str> = config.aliases.keys().map(String::as_str).collect();
+            anyhow::bail!(
+                "エイリアス '{}' は定義されていません。利用できるエイリアス: {}",
+                name,
+                known.join(", ")
+            )
+        }
+        None => Ok((None, CommandSpec::plain(args.to_vec()))),
+    }
+}
+
+/// `arc run --list-aliases`: config.toml の [aliases] を展開結果とともに表示する。
+pub fn list_aliases(ctx: &CommandContext) -> Result<()> {
+    let config = ctx.config()?;
+
+    if config.aliases.is_empty() {
+        eprintln!("ℹ️  エイリアスは定義されていません (config.toml の [aliases])。");
+        return Ok(());
+    }
+    let width = config.aliases.keys().map(|k| k.chars().count()).max().unwrap_or(0);
+    for name in config.aliases.keys() {
+        let spec = config.expand_alias(name, &[]).unwrap_or_default();
+        println!("{:<width$}  {}", name, alias_line(&spec), width = width);
+    }
+    Ok(())
+}
+
+/// `--list-aliases` の 1 行: 環境変数は `KEY=value` としてコマンドの前に、システムの環境なら末尾に示す。
+pub(super) fn alias_line(spec: &CommandSpec) -> String {
+    let mut words: Vec<String> = spec.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    words.extend(spec.cmd.iter().cloned());
+    let mut line = words.join(" ");
+    if !spec.isolated_or_default() {
+        line.push_str("  (system env)");
+    }
+    line
+}
+
+/// `arc run --list-bins`: 隔離環境の PATH で見える実行ファイルをグループごとに表示する。
+pub fn list_bins(ctx: &CommandContext) -> Result<()> {
+    let cwd = ctx.root()?;
+    let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+
+    let mut runtime: Vec<String> = match fs::read_dir(ruby_runtime_bin(&env_dir)) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => vec![],
+    };
+    runtime.sort();
+    let stubs = binstubs::list(&env_dir)?;
+
+    eprintln!("⚡ arc run: available executables");
+    eprintln!();
+    eprintln!("  ruby runtime ({}):", runtime.len());
+    for name in &runtime {
+        println!("    {}", name);
+    }
+    eprintln!();
+    eprintln!("  gem binstubs ({}):", stubs.len());
+    for stub in &stubs {
+        println!("    {:<24} ({})", stub.name, stub.gem);
+    }
+    Ok(())
+}
+
+/// `arc __reap` (内部用): `arc run --detach` から新しいセッションで起動される。
+pub fn reap(ctx: &CommandContext, args: &[String], alias: Option<&str>, spring: bool, sandbox_home: bool, fields: Option<&str>) -> Result<()> {
+    if args.is_empty() {
+        anyhow::bail!("実行するコマンドを指定してください。");
+    }
+    let fields = match fields {
+        Some(fields) => serde_json::from_str(fields).context("--fields は JSON のオブジェクトで指定してください")?,
+        None => json!({}),
+    };
+    let cwd = ctx.root()?;
+    let project = ctx.project()?;
+    super::detach::reap(project, cwd, &args[0], &args[1..], alias, spring, sandbox_home, fields)
+}
+
+// ─────────────────────────────────────────────
+// arc ps / arc stop
+// ─────────────────────────────────────────────
+
+pub fn ps(ctx: &CommandContext) -> Result<()> {
+    let project = ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+
+    let running = super::detach::list(project)?;
+    if running.is_empty() {
+        eprintln!("No detached processes running.");
+        return Ok(());
+    }
+
+    eprintln!("🌙 Detached processes ({}):", running.len());
+    for entry in &running {
+        let uptime = super::detach::uptime_since(&entry.started_at)
+            .map(display::fmt_duration)
+            .unwrap_or_else(|| "?".to_string());
+        eprintln!(
+            "   {}  pid {:<7} {} (up {})",
+            entry.id,
+            entry.pid,
+            display::fmt_cmd(&entry.command, &entry.args),
+            uptime
+        );
+    }
+    Ok(())
+}
+
+pub fn stop(ctx: &CommandContext, id: &str) -> Result<()> {
+    let project = ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+
+    match super::detach::stop(project, id)? {
+        super::detach::StopOutcome::Reaped => eprintln!("🛑 Stopped {}", id),
+        super::detach::StopOutcome::Abandoned => {
+            eprintln!("🛑 Stopped {} (exit status unavailable — marked as abandoned)", id)
+        }
+        super::detach::StopOutcome::StillRunning => {
+            eprintln!("⏳ SIGTERM sent to {}, but it has not exited yet.", id)
+        }
+    }
+    Ok(())
+}
+
+/// `arc output <id>`: デタッチ実行の出力を表示する (圧縮済みなら展開する)。
+/// `arc shell --transcript` の記録なら再生する (cast は記録した間隔どおりに出す)。
+pub fn output(ctx: &CommandContext, id: &str, stderr: bool) -> Result<()> {
+    let project = ctx.project()?;
+    let stream = if stderr { "err" } else { "out" };
+    let dir = crate::safe_path::safe_join(&project.root, super::detach::output_dir(&project.flux_dir))?;
+    if !stderr && let Some((format, content)) = crate::transcript::find(&dir, id)? {
+        return crate::transcript::replay(format, &content, &mut std::io::stdout(), &mut std::thread::sleep);
+    }
+    let content = crate::output_store::read(&dir, id, stream)?
+        .with_context(|| format!("{} の出力が見つかりません (削除されたか、デタッチ実行ではありません)", id))?;
+    print!("{}", content);
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::task;
+    use crate::commands::tests::synced_project;
+
+    #[test]
+    fn test_resolve_run_args() {
+        let config: ArcConfig = toml::from_str(
+            "[ruby]\nversion = \"3.3.6\"\n\n[aliases]\nspec = [\"bundle\", \"exec\", \"rspec\"]\n",
+        ).unwrap();
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let (alias, spec) = resolve_run_args(&config, &args(&["spec", "--seed", "1"]), false).unwrap();
+        assert_eq!(alias.as_deref(), Some("spec"));
+        assert_eq!(spec.cmd, ["bundle", "exec", "rspec", "--seed", "1"]);
+
+        // arc run は通常のコマンドをそのまま実行する
+        let (alias, spec) = resolve_run_args(&config, &args(&["rake", "db:migrate"]), false).unwrap();
+        assert!(alias.is_none());
+        assert_eq!(spec, CommandSpec::plain(args(&["rake", "db:migrate"])));
+
+        // arc r はエイリアス以外を受け付けない
+        let err = resolve_run_args(&config, &args(&["rake"]), true).unwrap_err();
+        assert_eq!(err.to_string(), "エイリアス 'rake' は定義されていません。利用できるエイリアス: spec");
+        let err = resolve_run_args(&ArcConfig::default(), &args(&["rake"]), true).unwrap_err();
+        assert!(err.to_string().contains("[aliases] に追加してください"));
+    }
+
+    #[test]
+    fn test_task_runs_detected_rspec_under_task_name() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let cwd = synced_project("arc_task_e2e_test");
+        // 引数を書き出すだけの rspec
+        let rspec = cwd.join("bin/rspec");
+        fs::create_dir_all(rspec.parent().unwrap()).unwrap();
+        fs::write(&rspec, "#!/bin/sh\necho \"$@\" > rspec.args\n").unwrap();
+        fs::set_permissions(&rspec, fs::Permissions::from_mode(0o755)).unwrap();
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+
+        let extra = ["spec/models".to_string()];
+        task_at(&project, &cwd, task::TEST_TASK, &extra).unwrap();
+        task_at(&project, &cwd, task::TEST_TASK, &[]).unwrap();
+        assert_eq!(fs::read_to_string(cwd.join("rspec.args")).unwrap(), "\n");
+
+        let signals = project.read_signals().unwrap();
+        let start = signals.iter().find(|s| s.r_type == "run_start").unwrap();
+        assert_eq!(start.payload["command"], "bin/rspec");
+        assert_eq!(start.payload["args"], serde_json::json!(["spec/models"]));
+        assert_eq!(start.payload["task"], "test");
+
+        let stats = crate::state::FluxState::from_signals(&signals).command_stats(&[]);
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].command.as_str(), stats[0].total_runs), ("test", 2));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_task_receives_expanded_config_value() {
+        let cwd = synced_project("arc_task_template_test");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let home = std::env::var("HOME").unwrap();
+        ArcConfig::update(&project.flux_dir, |c| {
+            let argv = ["sh", "-c", "printf %s \"$1|$2\" > task.out", "_", "${HOME}/cache", "$${HOME}"];
+            c.commands.insert("show".into(), argv.map(String::from).to_vec().into());
+        })
+        .unwrap();
+
+        task_at(&project, &cwd, "show", &[]).unwrap();
+        assert_eq!(fs::read_to_string(cwd.join("task.out")).unwrap(), format!("{}/cache|${{HOME}}", home));
+        // ファイルには展開前の形が残る
+        let raw = fs::read_to_string(project.flux_dir.join("config.toml")).unwrap();
+        assert!(raw.contains("\"${HOME}/cache\""), "{}", raw);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_leading_assignments() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let argv = args(&["RAILS_ENV=test", "_X=a=b", "rails", "FOO=1"]);
+        let (assignments, rest) = leading_assignments(&argv);
+        assert_eq!(assignments, [("RAILS_ENV".to_string(), "test".to_string()), ("_X".to_string(), "a=b".to_string())]);
+        assert_eq!(rest, ["rails", "FOO=1"]);
+        // 変数名にならないものはコマンドとして扱う
+        let argv = args(&["--opt=1", "ls"]);
+        assert!(leading_assignments(&argv).0.is_empty());
+        let argv = args(&["1X=y", "ls"]);
+        assert!(leading_assignments(&argv).0.is_empty());
+    }
+
+    #[test]
+    fn test_run_cooperates_with_spring() {
+        let cwd = synced_project("arc_run_spring_test");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        fs::create_dir_all(cwd.join("bin")).unwrap();
+        fs::write(cwd.join("bin/spring"), "#!/usr/bin/env ruby\n").unwrap();
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let show = args(&["sh", "-c", "echo \"$DISABLE_SPRING|$SPRING_APPLICATION_ID|$BOOTSNAP_CACHE_DIR\" > env.out"]);
+        let last_start = || project.read_signals().unwrap().into_iter().rev().find(|s| s.r_type == "run_start").unwrap();
+
+        run_at(&CommandContext::at(&cwd, false), &show, false, false, false, false, false, false).unwrap();
+        let bootsnap = cwd.join(".arc/env/bootsnap").display().to_string();
+        assert_eq!(fs::read_to_string(cwd.join("env.out")).unwrap(), format!("1||{}\n", bootsnap));
+        let context = last_start().payload["env_context"].clone();
+        assert_eq!(context["spring"], serde_json::json!({ "mode": "disabled", "detected": "bin/spring" }));
+        assert_eq!(context["BOOTSNAP_CACHE_DIR"], ".arc/env/bootsnap");
+
+        run_at(&CommandContext::at(&cwd, false), &show, false, false, false, false, true, false).unwrap();
+        let out = fs::read_to_string(cwd.join("env.out")).unwrap();
+        let id = last_start().payload["env_context"]["spring"]["application_id"].as_str().unwrap().to_string();
+        assert_eq!(out, format!("|{}|{}\n", id, bootsnap));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run_detach_starts_nothing() {
+        let cwd = synced_project("arc_run_detach_dry_run_test");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let argv = ["touch".to_string(), "DRYRAN".to_string()];
+        let (result, signals) = crate::dry_run::scoped(|| run_at(&CommandContext::at(&cwd, true), &argv, true, false, false, true, false, false));
+        result.unwrap();
+        assert!(signals.is_empty());
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!cwd.join("DRYRAN").exists());
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        assert!(!super::super::detach::output_dir(&project.flux_dir).exists());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_read_only_refuses_detach() {
+        let cwd = synced_project("arc_run_detach_read_only_test");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let argv = ["touch".to_string(), "RAN".to_string()];
+        let err = crate::read_only::scoped(|| run_at(&CommandContext::at(&cwd, false), &argv, true, false, false, true, false, false))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`arc run --detach`"), "{}", err);
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        assert!(!super::super::detach::output_dir(&project.flux_dir).exists());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_run_applies_merged_env() {
+        let cwd = synced_project("arc_run_env_test");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        fs::write(
+            project.flux_dir.join("config.toml"),
+            "[ruby]\nversion = \"3.3.6\"\n\n[env]\nset = { A = \"global\", B = \"global\", C = \"global\" }\n\n\
+             [aliases]\nshow = { cmd = [\"sh\", \"-c\", \"echo $A $B $C > env.out\"], env = { B = \"alias\", C = \"alias\" }, isolated = false }\n",
+        )
+        .unwrap();
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        run_at(&CommandContext::at(&cwd, false), &args(&["C=cli", "show"]), false, false, false, false, false, false).unwrap();
+        assert_eq!(fs::read_to_string(cwd.join("env.out")).unwrap(), "global alias cli\n");
+
+        let start = project.read_signals().unwrap().into_iter().find(|s| s.r_type == "run_start").unwrap();
+        assert_eq!(start.payload["env"], serde_json::json!({ "A": "global", "B": "alias", "C": "cli" }));
+        assert_eq!(start.payload["env_context"]["mode"], "system");
+        let redacted = crate::commands::bundle::Redactor::new(None, None, None).redact_signal(&start);
+        assert_eq!(redacted.payload["env"]["C"], "<redacted>");
+
+        // KEY=value だけではコマンドにならない
+        assert!(run_at(&CommandContext::at(&cwd, false), &args(&["C=cli"]), false, false, false, false, false, false).is_err());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_alias_line_shows_env() {
+        let spec = CommandSpec {
+            cmd: vec!["rails".into(), "server".into()],
+            env: [("RAILS_ENV".to_string(), "production".to_string())].into(),
+            isolated: Some(false),
+        };
+        assert_eq!(alias_line(&spec), "RAILS_ENV=production rails server  (system env)");
+        assert_eq!(alias_line(&CommandSpec::plain(vec!["rspec".into()])), "rspec");
+    }
+}
@@ -0,0 +1,48 @@
+//! `arc export` / `arc import`: プロジェクトの Signal と関連ファイルを 1 つのバンドルにまとめる・展開する。
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::display;
+
+use super::bundle;
+use super::context::CommandContext;
+
+/// 相対パスは起動したディレクトリからのパスとして扱う。
+pub fn export(ctx: &CommandContext, file: &Path, redact: bool, max_size_mb: u64) -> Result<()> {
+    let project = ctx.project()?;
+
+    let opts = bundle::ExportOptions {
+        redact,
+        max_bytes: max_size_mb.saturating_mul(1024 * 1024),
+    };
+    let manifest = bundle::export(project, &ctx.root()?.join(file), &opts)?;
+
+    eprintln!("📦 Exported {} signals to {}", display::fmt_count(manifest.signal_count as u64), file.display());
+    eprintln!("   Files:   {}", manifest.files.len());
+    if redact {
+        eprintln!("   Redacted usernames, hostnames and environment values");
+    }
+    if !manifest.skipped.is_empty() {
+        eprintln!(
+            "   Skipped: {} file(s) over the {} MB limit or not redactable",
+            manifest.skipped.len(),
+            max_size_mb
+        );
+    }
+    Ok(())
+}
+
+/// `path` に新しいプロジェクトとして展開する。相対パスは起動したディレクトリからのパスとして扱う。
+pub fn import(ctx: &CommandContext, file: &Path, path: &Path) -> Result<()> {
+    let root = ctx.root()?;
+    let (project, manifest) = bundle::import(&root.join(file), &root.join(path))?;
+
+    eprintln!("📥 Imported {} signals into {:?}", display::fmt_count(manifest.signal_count as u64), project.flux_dir);
+    if let Some(ref name) = manifest.project_name {
+        eprintln!("   Name:    {}", name);
+    }
+    eprintln!("   Source:  arc {} ({})", manifest.arc_version, manifest.exported_at);
+    eprintln!("   The environment is not bootstrapped — run `arc bootstrap` and `arc sync` to use it.");
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! Gem ディレクトリのリンク・コピーまわりのヘルパー。実際の配置方法 (reflink / hardlink / copy) は `crate::link` が決める。
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::display;
+use crate::link::{self, LinkMode, LinkReport};
+use crate::perms;
+
+/// `Path` を UTF-8 文字列に変換する。非 UTF-8 パスでは `Err` を返す。
+pub(super) fn path_str(p: &Path) -> Result<&str> {
+    p.to_str().context("パスが UTF-8 ではありません")
+}
+
+/// `src_root` 内の各エントリを `dest_root` へ `mode` に従って配置する。
+/// 既に存在するエントリはスキップする（べき等）。
+pub(super) fn sync_gem_dirs(src_root: &Path, dest_root: &Path, mode: LinkMode) -> Result<LinkReport> {
+    let mut report = LinkReport::default();
+    if !src_root.exists() {
+        return Ok(report);
+    }
+    perms::create_dir_all(dest_root)?;
+
+    for entry in fs::read_dir(src_root)? {
+        let entry = entry?;
+        let dest = dest_root.join(entry.file_name());
+        // リンク切れのシンボリックリンクも「存在する」とみなし、上書きしない
+        if fs::symlink_metadata(&dest).is_err() {
+            // ベストエフォート: 個別エントリの失敗は無視して続行
+            if let Ok(r) = link::link_tree(&entry.path(), &dest, mode) {
+                report.merge(&r);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// 配置方法ごとのファイル数を 1 行にまとめる。
+pub(super) fn fmt_link_report(report: &LinkReport, mode: LinkMode) -> String {
+    format!(
+        "🔗 {} files linked [{}] (reflink: {}, hardlink: {}, copy: {})",
+        display::fmt_count(report.total() as u64),
+        mode.as_str(),
+        display::fmt_count(report.reflink as u64),
+        display::fmt_count(report.hardlink as u64),
+        display::fmt_count(report.copy as u64)
+    )
+}
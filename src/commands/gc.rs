@@ -0,0 +1,127 @@
+//! `arc gc`: 参照されていない blob と古い出力を消す。`--retention` では保存期間を過ぎた Signal を消す。
+
+use anyhow::Result;
+
+use crate::config::ArcConfig;
+use crate::display;
+use crate::signals::FluxProject;
+
+use super::context::CommandContext;
+use super::{detach, retention};
+
+pub fn gc(ctx: &CommandContext, retention: bool, dry_run: bool) -> Result<()> {
+    let project = ctx.project()?;
+    if retention {
+        return gc_retention(project, dry_run || ctx.dry_run);
+    }
+    if ctx.dry_run {
+        crate::dry_run::note(&format!(
+            "would remove unreferenced blobs in {} and apply [output] limits to {}",
+            project.blobs_dir().display(),
+            detach::output_dir(&project.flux_dir).display()
+        ));
+        return Ok(());
+    }
+    let report = project.gc_blobs()?;
+
+    eprintln!(
+        "🧹 Removed {} unreferenced blob(s) ({} bytes), kept {}",
+        report.removed, report.freed_bytes, report.kept
+    );
+
+    let output = detach::gc_output(project, ctx.config()?)?;
+    if !output.evicted.is_empty() || output.truncated > 0 || output.compressed > 0 {
+        eprintln!(
+            "🧹 Output: removed {} capture(s) ({}), truncated {} file(s), compressed {} file(s)",
+            output.evicted.len(),
+            display::fmt_bytes(output.evicted.iter().map(|e| e.bytes).sum()),
+            output.truncated,
+            output.compressed
+        );
+    }
+    Ok(())
+}
+
+/// `arc gc --retention`: 前回の確認の日付に関係なく、保存期間を過ぎた Signal を消す
+fn gc_retention(project: &FluxProject, dry_run: bool) -> Result<()> {
+    let Some(days) = ArcConfig::load(&project.flux_dir)?.signals.retention_days else {
+        anyhow::bail!("[signals] retention_days is not set in .flux/config.toml");
+    };
+    let horizon = retention::horizon(retention::now(project)?, days);
+    let purge = retention::prepare(project, days, horizon)?;
+    if purge.dropped_total() == 0 {
+        eprintln!("🧹 No signals older than {} days (before {})", days, purge.horizon);
+        return Ok(());
+    }
+    let purge = match dry_run {
+        true => purge,
+        false => retention::apply(project, purge)?,
+    };
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    eprintln!(
+        "🧹 {} {} signal(s) older than {} days (before {}), kept {}",
+        verb,
+        display::fmt_count(purge.dropped_total() as u64),
+        days,
+        purge.horizon,
+        display::fmt_count(purge.kept as u64)
+    );
+    for (r_type, count) in &purge.dropped {
+        eprintln!("   {:<16} {}", r_type, display::fmt_count(*count as u64));
+    }
+    eprintln!(
+        "   {} {} blob(s) ({}) and {} output file(s) ({})",
+        verb,
+        purge.blobs.len(),
+        display::fmt_bytes(purge.blob_bytes),
+        purge.outputs.len(),
+        display::fmt_bytes(purge.output_bytes)
+    );
+    Ok(())
+}
+
+/// 変更するコマンドの前に、`[signals] retention_days` を過ぎた Signal を消す (1 日に 1 回)。
+/// 失敗してもコマンドは続ける。
+pub fn auto_retention(project: &FluxProject) {
+    let result = ArcConfig::load(&project.flux_dir).and_then(|config| {
+        let now = retention::now(project)?;
+        retention::auto(project, config.signals.retention_days, now)
+    });
+    match result {
+        Ok(Some(purge)) => eprintln!(
+            "🧹 Removed {} signal(s) older than {} days ([signals] retention_days)",
+            display::fmt_count(purge.dropped_total() as u64),
+            purge.retention_days
+        ),
+        Ok(None) => {}
+        Err(e) => eprintln!("⚠️  Could not apply [signals] retention_days: {:#}", e),
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_dry_run_gc_removes_nothing() {
+        let (cwd, project) = crate::signals::temp_project("arc_gc_dry_run_test");
+        fs::create_dir_all(project.blobs_dir()).unwrap();
+        let orphan = project.blobs_dir().join("orphan");
+        fs::write(&orphan, "unreferenced").unwrap();
+        let output = detach::output_dir(&project.flux_dir);
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join("old.out"), "x".repeat(64)).unwrap();
+        ArcConfig::update(&project.flux_dir, |c| c.output.max_total_mb = Some(0)).unwrap();
+
+        gc(&CommandContext::at(&cwd, true), false, false).unwrap();
+        assert!(orphan.exists());
+        assert!(output.join("old.out").exists());
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
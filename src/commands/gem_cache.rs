@@ -0,0 +1,232 @@
+//! グローバルな Gem キャッシュ (`~/.arc/cache/gems`) への収穫 (harvest) と復元 (restore)、
+//! `arc cache warm` / `arc cache clean`。
+
+use anyhow::{Context, Result};
+use std::{env, fs};
+use std::path::Path;
+
+use crate::cache_layout;
+use crate::config::ArcConfig;
+use crate::link::{LinkMode, LinkReport};
+use crate::lockfile;
+use crate::perms;
+use crate::progress;
+
+use super::bootstrap::{download_ruby_to_cache, resolve_ruby_id, ruby_platforms, warm_rubies};
+use super::context::CommandContext;
+use super::fsops::sync_gem_dirs;
+use super::runner::inject_isolated_env;
+
+// ─────────────────────────────────────────────
+// 定数
+// ─────────────────────────────────────────────
+
+/// Gem が格納されるサブディレクトリ名。
+/// `gems/`: ソース本体, `specifications/`: メタデータ, `extensions/`: C拡張バイナリ
+const GEM_SUBDIRS: [&str; 3] = ["gems", "specifications", "extensions"];
+
+// ─────────────────────────────────────────────
+// Gem キャッシュ (Harvest & Restore)
+// ─────────────────────────────────────────────
+
+/// プロジェクト内の Gem をグローバルキャッシュに保存する（ベストエフォート）。
+pub(super) fn harvest_gems(cwd: &Path, gem_cache: &Path, ruby_api_ver: &str, mode: LinkMode) -> Result<LinkReport> {
+    let local_base = cwd
+        .join(crate::signals::ARC_ENV_DIR)
+        .join("ruby")
+        .join(ruby_api_ver);
+
+    let mut report = LinkReport::default();
+    if !local_base.exists() {
+        return Ok(report);
+    }
+
+    for subdir in GEM_SUBDIRS {
+        if let Ok(r) = sync_gem_dirs(&local_base.join(subdir), &gem_cache.join(subdir), mode) {
+            report.merge(&r);
+        }
+    }
+    Ok(report)
+}
+
+/// グローバルキャッシュからプロジェクト内へ Gem を復元する（ベストエフォート）。
+pub(super) fn restore_gems(cwd: &Path, gem_cache: &Path, ruby_api_ver: &str, mode: LinkMode) -> Result<LinkReport> {
+    let _timer = crate::overhead::scope(crate::overhead::RESTORE);
+    let mut report = LinkReport::default();
+    if !gem_cache.exists() {
+        return Ok(report);
+    }
+
+    let local_base = cwd
+        .join(crate::signals::ARC_ENV_DIR)
+        .join("ruby")
+        .join(ruby_api_ver);
+
+    for subdir in GEM_SUBDIRS {
+        if let Ok(r) = sync_gem_dirs(&gem_cache.join(subdir), &local_base.join(subdir), mode) {
+            report.merge(&r);
+        }
+    }
+    Ok(report)
+}
+
+// ─────────────────────────────────────────────
+// arc cache warm
+// ─────────────────────────────────────────────
+
+/// グローバルキャッシュのレイアウトを確認し、古ければ移行する。
+/// 移行できない場合は `arc cache clean --all` を 1 度だけ案内し、そのまま続ける。
+pub(super) fn check_cache_layout() -> Result<()> {
+    if crate::dry_run::is_enabled() {
+        return Ok(());
+    }
+    let cache = crate::signals::get_global_cache_dir()?;
+    match cache_layout::ensure(&cache)? {
+        cache_layout::Outcome::Current => {}
+        cache_layout::Outcome::Migrated(steps) => {
+            for step in steps {
+                progress::human(&format!("🔧 Cache layout migrated: {}", step));
+            }
+        }
+        cache_layout::Outcome::Unsupported { found, warn } => {
+            if warn {
+                progress::human(&format!(
+                    "⚠️  The cache at {} uses layout version {} (this arc expects {}) and cannot be migrated.",
+                    cache.display(),
+                    found,
+                    cache_layout::LAYOUT_VERSION
+                ));
+                progress::human("   Run `arc cache clean --all` to start over with an empty cache.");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// グローバルキャッシュ (`~/.arc/cache`) を丸ごと削除する。
+pub fn cache_clean(ctx: &CommandContext) -> Result<()> {
+    let cache = crate::signals::get_global_cache_dir()?;
+    if !cache.exists() {
+        println!("ℹ️  {} does not exist.", cache.display());
+        return Ok(());
+    }
+    if ctx.dry_run {
+        crate::dry_run::note(&format!("would remove {}", cache.display()));
+        return Ok(());
+    }
+    fs::remove_dir_all(&cache).with_context(|| format!("Failed to remove {:?}", cache))?;
+    println!("🗑️  Removed {}", cache.display());
+    Ok(())
+}
+
+/// Gemfile.lock の Gem をグローバルキャッシュ (`~/.arc/cache/gems`) に先に入れておく。
+/// `ruby` を省略した場合は既定のバージョンを使う (キャッシュになければ取得する)。
+pub fn cache_warm(ctx: &CommandContext, lockfile_path: &Path, ruby: Option<&str>) -> Result<()> {
+    let version = ruby.map_or_else(|| ArcConfig::default().ruby.version, String::from);
+    let runtime = cached_ruby(&version)?;
+    let fetched = warm_gems(lockfile_path, &runtime, &version, &crate::signals::get_global_gems_dir()?)?;
+    ctx.reporter.human(&format!("✨ {} gem(s) added to the cache", fetched));
+    Ok(())
+}
+
+/// グローバルキャッシュの Ruby (`~/.arc/cache/rubies/<id>`)。キャッシュになければ取得する。
+pub(super) fn cached_ruby(version: &str) -> Result<std::path::PathBuf> {
+    check_cache_layout()?;
+    let rubies = crate::signals::get_global_cache_dir()?.join(cache_layout::RUBIES_DIR);
+    let suffixes = ruby_platforms(None)?;
+    warm_rubies(&rubies, &[version.to_string()], &|dir, v| Ok(download_ruby_to_cache(dir, v, &suffixes)?.bytes))?;
+    Ok(rubies.join(resolve_ruby_id(version)))
+}
+
+/// キャッシュにない Gem を使い捨ての GEM_HOME に `gem install` し、キャッシュに取り込む。
+/// 取り込んだ Gem の数を返す。インストールに失敗した Gem があれば、残りを取り込んでからエラーを返す。
+pub(super) fn warm_gems(lockfile_path: &Path, ruby_runtime: &Path, ruby_version: &str, gem_cache: &Path) -> Result<usize> {
+    let content = fs::read_to_string(lockfile_path)
+        .with_context(|| format!("Gemfile.lock の読み込みに失敗しました: {:?}", lockfile_path))?;
+    let missing = crate::snapshot::missing_from_cache(&content, gem_cache);
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    // プロジェクトと同じ配置の一時ディレクトリ (.arc/env/ruby_runtime → キャッシュの Ruby)
+    let scratch = env::temp_dir().join(format!("arc-cache-warm-{}", std::process::id()));
+    let env_dir = scratch.join(crate::signals::ARC_ENV_DIR);
+    perms::create_dir_all(&env_dir)?;
+    let result = (|| {
+        std::os::unix::fs::symlink(ruby_runtime, super::runner::ruby_runtime_root(&env_dir))?;
+        let api = crate::config::ruby_api_version(ruby_version);
+        let install_dir = env_dir.join("ruby").join(&api);
+        let lock = lockfile::parse_content(&content);
+        let mut failed = Vec::new();
+        for spec in lock.specs.iter().filter(|s| missing.contains(&format!("{}-{}", s.name, s.version))) {
+            let dir_name = format!("{}-{}", spec.name, spec.version);
+            let (version, platform) = spec.version_and_platform();
+            let mut gem = std::process::Command::new(super::runner::ruby_runtime_bin(&env_dir).join("gem"));
+            gem.args(["install", &spec.name, "-v", version, "--ignore-dependencies", "--no-document"]);
+            if let Some(platform) = platform {
+                gem.args(["--platform", platform]);
+            }
+            gem.arg("--install-dir").arg(&install_dir);
+            inject_isolated_env(&mut gem, &scratch)?;
+            let ok = progress::run_child(&mut gem, &format!("gem install {}", dir_name), None)
+                .context("gem の起動に失敗しました")?
+                .success();
+            if !ok {
+                failed.push(dir_name);
+            }
+        }
+        harvest_gems(&scratch, gem_cache, &api, LinkMode::Copy)?;
+        if !failed.is_empty() {
+            anyhow::bail!("{} 個の Gem の取得に失敗しました: {}", failed.len(), failed.join(", "));
+        }
+        Ok(missing.len())
+    })();
+    let _ = fs::remove_dir_all(&scratch);
+    result
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_warm_from_lockfile() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join("arc_cache_warm_test");
+        let _ = fs::remove_dir_all(&root);
+        let runtime = root.join("rubies/3.3.6");
+        fs::create_dir_all(runtime.join("bin")).unwrap();
+        fs::write(runtime.join("bin/ruby"), "").unwrap();
+        // 偽の gem: --install-dir に Gem を「インストール」する (broken だけ失敗する)
+        let gem = runtime.join("bin/gem");
+        let script = "#!/bin/sh\nname=$2; ver=$4; plat=\"\"\n\
+                      while [ $# -gt 0 ]; do case $1 in --install-dir) dir=$2;; --platform) plat=-$2;; esac; shift; done\n\
+                      [ $name = broken ] && exit 1\n\
+                      mkdir -p $dir/gems/$name-$ver$plat $dir/specifications && touch $dir/specifications/$name-$ver$plat.gemspec\n";
+        fs::write(&gem, script).unwrap();
+        fs::set_permissions(&gem, fs::Permissions::from_mode(0o755)).unwrap();
+        let lock = root.join("Gemfile.lock");
+        fs::write(&lock, "GEM\n  specs:\n    json (2.7.1)\n    nokogiri (1.16.0-x86_64-linux)\n    rake (13.1.0)\n").unwrap();
+        let cache = root.join("gems");
+        fs::create_dir_all(cache.join("gems/rake-13.1.0")).unwrap();
+        fs::create_dir_all(cache.join("specifications")).unwrap();
+        fs::write(cache.join("specifications/rake-13.1.0.gemspec"), "").unwrap();
+
+        assert_eq!(warm_gems(&lock, &runtime, "3.3.6", &cache).unwrap(), 2);
+        assert!(cache.join("gems/nokogiri-1.16.0-x86_64-linux").is_dir());
+        assert!(cache.join("specifications/json-2.7.1.gemspec").is_file());
+        assert_eq!(warm_gems(&lock, &runtime, "3.3.6", &cache).unwrap(), 0);
+
+        fs::write(&lock, "GEM\n  specs:\n    broken (1.0)\n    racc (1.7.3)\n").unwrap();
+        let err = warm_gems(&lock, &runtime, "3.3.6", &cache).unwrap_err();
+        assert!(err.to_string().contains("broken-1.0"), "{}", err);
+        // 成功した Gem は取り込む
+        assert!(cache.join("gems/racc-1.7.3").is_dir());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! `arc gemfile check` / `arc gemfile sort` と、Gemfile を変更するコマンドが bundler の前に行う検査。
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+
+use crate::display;
+use crate::gemfile;
+use crate::gemfile_hash;
+use crate::progress;
+use crate::signals::SignalType;
+
+use super::context::CommandContext;
+use super::remove::edit_and_record;
+
+/// bundler に渡す前に Gemfile の内容を検査する。問題を表示し、誤り (Error) があれば中止する。
+/// 警告は Signal に記録できるよう返す。
+pub(super) fn check_gemfile(content: &str) -> Result<Vec<gemfile::ValidationIssue>> {
+    let issues = gemfile::validate(content);
+    display::render_gemfile_issues("Gemfile", &issues);
+    let errors = issues.iter().filter(|i| i.severity == gemfile::Severity::Error).count();
+    if errors > 0 {
+        anyhow::bail!("Gemfile に誤りが {} 件あります。修正してから再実行してください (`arc gemfile check` で確認できます)。", errors);
+    }
+    Ok(issues)
+}
+
+/// Signal の payload に加える `gemfile_issues` (問題がなければ何も加えない)
+pub(super) fn gemfile_issue_fields(issues: &[gemfile::ValidationIssue]) -> Value {
+    if issues.is_empty() { json!({}) } else { json!({ "gemfile_issues": issues }) }
+}
+
+/// `arc gemfile check`: Gemfile を検査して問題を表示する。誤りがあれば失敗する。
+pub fn gemfile_check(ctx: &CommandContext, path: Option<&Path>) -> Result<()> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => ctx.root()?.join("Gemfile"),
+    };
+    let content = fs::read_to_string(&path).with_context(|| format!("Gemfile の読み込みに失敗しました: {:?}", path))?;
+    let issues = check_gemfile(&content)?;
+    if issues.is_empty() && !display::is_plain() {
+        eprintln!("✅ Gemfile: no issues found");
+    }
+    Ok(())
+}
+
+/// `arc gemfile sort`: 各スコープの `gem` 行を名前順に並べ、引用符 (`[gemfile] quote`) とカンマの前後の空白をそろえる。
+/// 既定では差分を表示するだけ。`--check` は整形が必要なら差分を表示して失敗し、`--write` は書き込んで記録する。
+pub fn gemfile_sort(ctx: &CommandContext, check: bool, write: bool) -> Result<()> {
+    if !gemfile_sort_at(ctx, check, write)? {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `gemfile_sort` の本体。`--check` で整形が必要だった場合だけ `false`。
+fn gemfile_sort_at(ctx: &CommandContext, check: bool, write: bool) -> Result<bool> {
+    let gemfile_path = ctx.root()?.join("Gemfile");
+    let project = ctx.project().ok();
+    let quote = match project {
+        Some(_) => ctx.config()?.gemfile.quote,
+        None => crate::config::QuoteStyle::default(),
+    };
+    let before = fs::read_to_string(&gemfile_path).with_context(|| format!("Gemfile の読み込みに失敗しました: {:?}", gemfile_path))?;
+    check_gemfile(&before)?;
+    let after = gemfile::GemfileDoc::parse(&before).sorted(quote.char()).render();
+    let diff = gemfile::unified_diff("Gemfile", &before, &after);
+    if diff.is_empty() {
+        progress::human("✅ Gemfile is already sorted");
+        return Ok(true);
+    }
+    print!("{}", diff);
+    if check {
+        eprintln!("❌ Gemfile is not sorted — run `arc gemfile sort --write`");
+        return Ok(false);
+    }
+    if !write {
+        eprintln!("ℹ️  Run `arc gemfile sort --write` to apply");
+        return Ok(true);
+    }
+    if ctx.dry_run {
+        crate::dry_run::note("would write Gemfile and record `x-gemfile-format`");
+        return Ok(true);
+    }
+
+    let project = project.context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    let external_edit = gemfile_hash::warn_if_modified(&project.read_signals()?, &gemfile_path);
+    let payload = json!({ "diff": diff, "quote": quote });
+    edit_and_record(project, &gemfile_path, &after, "arc gemfile sort".to_string(), SignalType::custom("gemfile", "format")?, payload, external_edit)?;
+    eprintln!("📝 Sorted Gemfile");
+    Ok(true)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ArcConfig;
+
+    const MESSY: &str = "source \"https://rubygems.org\"\n\ngem \"rake\"\ngem 'puma' ,'~> 6.4'\n";
+
+    #[test]
+    fn test_gemfile_sort_without_project() {
+        let cwd = std::env::temp_dir().join("arc_gemfile_sort_plain_test");
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        fs::write(cwd.join("Gemfile"), MESSY).unwrap();
+        let sort = |check, write| gemfile_sort_at(&CommandContext::at(&cwd, false), check, write);
+        // プロジェクトがなくても --check はできる (引用符は既定の single)
+        assert!(!sort(true, false).unwrap());
+        assert!(sort(false, false).unwrap());
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), MESSY);
+        assert!(sort(false, true).is_err());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_gemfile_sort_check_and_write() {
+        let (cwd, project) = crate::signals::temp_project("arc_gemfile_sort_test");
+        fs::write(cwd.join("Gemfile"), MESSY).unwrap();
+        let sort = |check, write| gemfile_sort_at(&CommandContext::at(&cwd, false), check, write);
+        ArcConfig::update(&project.flux_dir, |c| c.gemfile.quote = crate::config::QuoteStyle::Double).unwrap();
+        // --dry-run では差分を表示するだけ
+        assert!(gemfile_sort_at(&CommandContext::at(&cwd, true), false, true).unwrap());
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), MESSY);
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        assert!(sort(false, true).unwrap());
+        let sorted = "source \"https://rubygems.org\"\n\ngem \"puma\", \"~> 6.4\"\ngem \"rake\"\n";
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), sorted);
+        let signal = project.read_signals().unwrap().into_iter().last().unwrap();
+        assert_eq!(signal.r_type, "x-gemfile-format");
+        assert_eq!(signal.payload["quote"], "double");
+        assert!(signal.payload["diff"].as_str().unwrap().contains("+gem \"puma\", \"~> 6.4\""), "{}", signal.payload);
+        assert_eq!(signal.payload[gemfile_hash::PAYLOAD_KEY], gemfile_hash::hash(&cwd.join("Gemfile")).unwrap());
+
+        // 2 回目は何もしない
+        let count = project.read_signals().unwrap().len();
+        assert!(sort(true, false).unwrap());
+        assert!(sort(false, true).unwrap());
+        assert_eq!(project.read_signals().unwrap().len(), count);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! `arc init` / `arc adopt`: Flux プロジェクトの作成と、既存の Gemfile・Ruby バージョンの取り込み。
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::{env, fs};
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::config::ArcConfig;
+use crate::gemfile;
+use crate::gemfile_hash;
+use crate::prompt;
+use crate::signals::{FluxProject, SignalType};
+
+use super::bootstrap::bootstrap_at;
+use super::context::CommandContext;
+
+// ─────────────────────────────────────────────
+// arc init
+// ─────────────────────────────────────────────
+
+/// `interactive` を省略した場合は、stdin が TTY で名前・説明が指定されていなければ対話モードにする。
+pub fn init(
+    ctx: &CommandContext,
+    path: &Path,
+    name: Option<String>,
+    description: Option<String>,
+    interactive: Option<bool>,
+) -> Result<()> {
+    let interactive = interactive
+        .unwrap_or_else(|| name.is_none() && description.is_none() && std::io::stdin().is_terminal());
+
+    // デフォルト config.toml を生成 (プロジェクト名はディレクトリ名から)
+    let mut config = ArcConfig::default();
+    config.project.name = name.or_else(|| crate::config::default_project_name(path));
+    config.project.description = description;
+
+    let (signal, bootstrap_now) = if interactive {
+        let mut prompter = prompt::IoPrompter { input: std::io::stdin().lock(), out: std::io::stderr() };
+        let (_, signal, answers) = init_interactive(path, &mut config, &mut prompter)?;
+        if answers.create_gemfile {
+            eprintln!("📝 Created Gemfile");
+        }
+        (signal, answers.bootstrap)
+    } else {
+        (init_with(path, &config, json!({}))?.1, false)
+    };
+
+    eprintln!("✨ Flux project initialized at {:?}", path);
+    if let Some(ref name) = config.project.name {
+        eprintln!("   Name:   {}", name);
+    }
+    eprintln!("   Signal: {} ({})", signal.id, signal.r_type);
+    eprintln!("   Ruby:   {} (change with `arc bootstrap <version>`)", config.ruby.version);
+
+    if bootstrap_now {
+        eprintln!();
+        bootstrap_at(&CommandContext::at(path, ctx.dry_run), None, false, false)?;
+    }
+    Ok(())
+}
+
+/// 対話的に質問してからプロジェクトを作成する。質問が終わるまでは何も作成しない。
+/// 回答は `config` に反映する。bootstrap (ネットワークを使う) は呼び出し側で行う。
+pub(super) fn init_interactive(
+    path: &Path,
+    config: &mut ArcConfig,
+    prompter: &mut dyn prompt::Prompter,
+) -> Result<(FluxProject, crate::signals::Signal, super::wizard::InitAnswers)> {
+    let ctx = super::wizard::InitContext {
+        default_name: config.project.name.clone(),
+        default_ruby: config.ruby.version.clone(),
+        ruby_version_file: fs::read_to_string(path.join(".ruby-version"))
+            .ok()
+            .and_then(|c| parse_ruby_version_file(&c)),
+        latest_cached: super::wizard::latest_cached_ruby(&crate::signals::get_global_cache_dir()?.join("rubies")),
+        gemfile_exists: path.join("Gemfile").exists(),
+    };
+    let answers = super::wizard::ask(prompter, &ctx)?;
+
+    config.project.name = answers.name.clone();
+    config.ruby.version = answers.ruby_version.clone();
+    let (project, signal) = init_with(path, config, answers.to_json())?;
+    if answers.create_gemfile {
+        fs::write(path.join("Gemfile"), gemfile::SCAFFOLD).context("Gemfile の作成に失敗しました")?;
+    }
+    Ok((project, signal, answers))
+}
+
+/// `.flux/` を作成して `config` を保存し、init Signal を記録する。`init` と `adopt` で共有する。
+/// `extra` は init Signal の payload に追加するフィールド。
+pub(super) fn init_with(path: &Path, config: &ArcConfig, extra: serde_json::Value) -> Result<(FluxProject, crate::signals::Signal)> {
+    if !path.exists() {
+        fs::create_dir_all(path).context("プロジェクトディレクトリの作成に失敗しました")?;
+    }
+
+    let mut payload = json!({
+        "path": path,
+        "name": config.project.name,
+        "version": env!("CARGO_PKG_VERSION"),
+        "ruby_version": config.ruby.version,
+    });
+    if let (Some(fields), serde_json::Value::Object(extra)) = (payload.as_object_mut(), extra) {
+        fields.extend(extra);
+    }
+    FluxProject::init(path, config, payload)
+}
+
+// ─────────────────────────────────────────────
+// arc adopt
+// ─────────────────────────────────────────────
+
+pub fn adopt(ctx: &CommandContext) -> Result<()> {
+    let cwd = ctx.root()?;
+    let adopted = adopt_at(cwd)?;
+
+    eprintln!("🧲 Adopted existing project at {:?}", cwd);
+    if adopted.initialized {
+        eprintln!("   Initialized a new Flux project");
+    }
+    eprintln!("   Dependencies: {} tracked from Gemfile", adopted.dependencies);
+    match adopted.ruby {
+        Some((ref version, source)) => eprintln!("   Ruby:         {} (from {})", version, source),
+        None => eprintln!("   Ruby:         not detected (keeping {})", adopted.config_ruby),
+    }
+    eprintln!("   Signal:       {} (adopt)", adopted.signal_id);
+    Ok(())
+}
+
+/// `arc adopt` の結果。
+pub(super) struct Adopted {
+    signal_id: String,
+    pub(super) initialized: bool,
+    pub(super) dependencies: usize,
+    ruby: Option<(String, &'static str)>,
+    config_ruby: String,
+}
+
+/// 既存の Gemfile / Ruby バージョンから基準となる Signal を合成する。
+///
+/// adopt → add (直接依存ごと、`adopted: true`) → bootstrap (検出した Ruby) の順に記録する。
+/// adopted な add は `arc undo` の対象にならない。
+pub(super) fn adopt_at(cwd: &Path) -> Result<Adopted> {
+    let gemfile_path = cwd.join("Gemfile");
+    if !gemfile_path.exists() {
+        anyhow::bail!("Gemfile が見つかりません。`arc init` で新規プロジェクトを作成してください。");
+    }
+    let ruby = detect_ruby_version(cwd);
+
+    let (project, initialized) = match FluxProject::open(cwd) {
+        Ok(project) => {
+            if project.read_signals()?.iter().any(|s| s.r_type == "adopt") {
+                anyhow::bail!("このプロジェクトは既に adopt 済みです。");
+            }
+            (project, false)
+        }
+        Err(_) => {
+            let mut config = ArcConfig::default();
+            config.project.name = crate::config::default_project_name(cwd);
+            if let Some((ref version, _)) = ruby {
+                config.ruby.version = version.clone();
+            }
+            (init_with(cwd, &config, json!({}))?.0, true)
+        }
+    };
+
+    // 既存プロジェクトの場合も config.toml を検出結果に合わせる
+    let config = ArcConfig::load(&project.flux_dir)?;
+    if let Some((ref version, _)) = ruby
+        && config.ruby.version != *version {
+            ArcConfig::update(&project.flux_dir, |c| c.ruby.version = version.clone())?;
+        }
+
+    let gems = gemfile::parse(&gemfile_path)?;
+
+    let mut payload = json!({
+        "lockfile": cwd.join("Gemfile.lock").exists(),
+        "dependencies": gems.len(),
+        "ruby_version": ruby.as_ref().map(|(v, _)| v),
+        "ruby_source": ruby.as_ref().map(|(_, s)| s),
+    });
+    gemfile_hash::stamp(&mut payload, &gemfile_path, false);
+    let adopt_signal = project.record(SignalType::Adopt, payload)?;
+
+    for gem in &gems {
+        project.record(
+            SignalType::Add,
+            json!({
+                "gem": gem.name,
+                "version": gem.version,
+                "group": gem.group,
+                "adopted": true,
+                "adopt_id": adopt_signal.id,
+            }),
+        )?;
+    }
+
+    if let Some((ref version, source)) = ruby {
+        project.record(
+            SignalType::Bootstrap,
+            json!({
+                "ruby_version": version,
+                "source": source,
+                "adopted": true,
+                "adopt_id": adopt_signal.id,
+            }),
+        )?;
+    }
+
+    Ok(Adopted {
+        signal_id: adopt_signal.id,
+        initialized,
+        dependencies: gems.len(),
+        ruby,
+        config_ruby: config.ruby.version,
+    })
+}
+
+/// プロジェクトで使われている Ruby バージョンを `.ruby-version` → `ruby --version` の順に探す。
+/// 見つかった場合は (バージョン, 検出元) を返す。
+fn detect_ruby_version(cwd: &Path) -> Option<(String, &'static str)> {
+    if let Ok(content) = fs::read_to_string(cwd.join(".ruby-version"))
+        && let Some(version) = parse_ruby_version_file(&content) {
+            return Some((version, ".ruby-version"));
+        }
+
+    let output = std::process::Command::new("ruby").arg("--version").output().ok()?;
+    parse_ruby_version_output(&String::from_utf8_lossy(&output.stdout)).map(|v| (v, "ruby --version"))
+}
+
+/// `.ruby-version` の内容 (例: `3.3.6`, `ruby-3.3.6`) からバージョンを取り出す。
+pub(crate) fn parse_ruby_version_file(content: &str) -> Option<String> {
+    let line = content.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with('#'))?;
+    let version = line.strip_prefix("ruby-").unwrap_or(line);
+    version.starts_with(|c: char| c.is_ascii_digit()).then(|| version.to_string())
+}
+
+/// `ruby --version` の出力 (例: `ruby 3.3.6 (2024-11-05 revision 75015d4c1f) [x86_64-linux]`,
+/// `ruby 2.7.8p225 (...)`) からバージョンを取り出す。
+pub(super) fn parse_ruby_version_output(output: &str) -> Option<String> {
+    let token = output.strip_prefix("ruby ")?.split_whitespace().next()?;
+    let version = token.split('p').next()?;
+    version.starts_with(|c: char| c.is_ascii_digit()).then(|| version.to_string())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::undo::find_undo_target;
+
+    #[test]
+    fn test_init_interactive() {
+        use crate::commands::wizard::tests::Canned;
+
+        let cwd = std::env::temp_dir().join("arc_init_interactive_test");
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        fs::write(cwd.join(".ruby-version"), "3.2.2\n").unwrap();
+
+        // 質問の途中で中断すると何も作成されない
+        let mut config = ArcConfig::default();
+        assert!(init_interactive(&cwd, &mut config, &mut Canned::new(&["shop", "2"])).is_err());
+        assert!(!cwd.join(".flux").exists());
+        assert!(!cwd.join("Gemfile").exists());
+
+        let (project, signal, answers) =
+            init_interactive(&cwd, &mut config, &mut Canned::new(&["shop", "", "y", "n"])).unwrap();
+        assert_eq!(answers.ruby_version, "3.2.2");
+        assert_eq!(config.ruby.version, "3.2.2");
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), gemfile::SCAFFOLD);
+
+        let saved = ArcConfig::load(&project.flux_dir).unwrap();
+        assert_eq!(saved.project.name.as_deref(), Some("shop"));
+        assert_eq!(saved.ruby.version, "3.2.2");
+        assert_eq!(signal.payload["ruby_version"], "3.2.2");
+        assert_eq!(signal.payload["interactive"], true);
+        assert_eq!(signal.payload["ruby_source"], ".ruby-version");
+        assert_eq!(signal.payload["gemfile_created"], true);
+        assert_eq!(signal.payload["bootstrap"], false);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_adopt_synthesizes_baseline() {
+        let cwd = std::env::temp_dir().join("arc_adopt_test");
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        fs::write(cwd.join("Gemfile"), "source 'https://rubygems.org'\ngem 'rails', '~> 7.1'\ngroup :test do\n  gem 'rspec'\nend\n").unwrap();
+        fs::write(cwd.join(".ruby-version"), "ruby-3.2.2\n").unwrap();
+
+        let adopted = adopt_at(&cwd).unwrap();
+        assert!(adopted.initialized);
+        assert_eq!(adopted.dependencies, 2);
+
+        let project = FluxProject::open(&cwd).unwrap();
+        let signals = project.read_signals().unwrap();
+        let types: Vec<&str> = signals.iter().map(|s| s.r_type.as_str()).collect();
+        assert_eq!(types, ["init", "adopt", "add", "add", "bootstrap"]);
+
+        let adopt = &signals[1];
+        let gemfile = fs::read(cwd.join("Gemfile")).unwrap();
+        // Gemfile のハッシュは gemfile_hash::stamp の SHA-256 だけ
+        assert_eq!(adopt.payload[gemfile_hash::PAYLOAD_KEY], crate::blobs::sha256_hex(&gemfile));
+        assert!(adopt.payload.get("gemfile_hash").is_none());
+        assert_eq!(adopt.payload["ruby_source"], ".ruby-version");
+        assert_eq!(signals[2].payload["gem"], "rails");
+        assert_eq!(signals[2].payload["version"], "~> 7.1");
+        assert_eq!(signals[3].payload["group"], "test");
+        assert_eq!(signals[3].payload["adopted"], true);
+        assert_eq!(signals[3].payload["adopt_id"], adopt.id.as_str());
+        assert_eq!(signals[4].payload["ruby_version"], "3.2.2");
+        assert_eq!(ArcConfig::load(&project.flux_dir).unwrap().ruby.version, "3.2.2");
+
+        // adopted な add は undo できない
+        let err = find_undo_target(&signals).unwrap_err();
+        assert!(err.to_string().contains("rspec"));
+        // 2 回目の adopt は拒否する
+        assert!(adopt_at(&cwd).is_err());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_parse_ruby_versions() {
+        assert_eq!(parse_ruby_version_file("3.3.6\n").as_deref(), Some("3.3.6"));
+        assert_eq!(parse_ruby_version_file("ruby-3.2.2").as_deref(), Some("3.2.2"));
+        assert_eq!(parse_ruby_version_file("system\n"), None);
+        assert_eq!(
+            parse_ruby_version_output("ruby 3.3.6 (2024-11-05 revision 75015d4c1f) [x86_64-linux]\n").as_deref(),
+            Some("3.3.6")
+        );
+        assert_eq!(parse_ruby_version_output("ruby 2.7.8p225 (2023-03-30 revision 1f4d455848)").as_deref(), Some("2.7.8"));
+        assert_eq!(parse_ruby_version_output("jruby 9.4"), None);
+    }
+}
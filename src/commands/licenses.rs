@@ -0,0 +1,40 @@
+//! `arc licenses`: `.arc/env` にインストールされた Gem のライセンス。
+
+use anyhow::{Context, Result};
+
+use crate::display;
+use crate::licenses::{self, LicenseGroup};
+
+use super::context::CommandContext;
+use super::runner::ruby_bin;
+
+/// `arc licenses`: `.arc/env` にインストールされた Gem のライセンス。
+/// `fail_on` のライセンスを持つ Gem があれば、一覧を出した後で失敗する。
+pub fn licenses(ctx: &CommandContext, group: LicenseGroup, json_output: bool, fail_on: &[String]) -> Result<()> {
+    ctx.project().context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    let config = ctx.config()?;
+    let env_dir = ctx.root()?.join(crate::signals::ARC_ENV_DIR);
+    let gem_base = env_dir.join("ruby").join(crate::config::ruby_api_version(&config.ruby.version));
+    if !gem_base.join("specifications").is_dir() {
+        anyhow::bail!("インストール済みの Gem がありません ({:?})。先に `arc sync` を実行してください。", gem_base);
+    }
+    let ruby = ruby_bin(&env_dir);
+    let gems = licenses::scan(&gem_base, ruby.exists().then_some(ruby.as_path()));
+
+    if json_output {
+        let value = match group {
+            LicenseGroup::Gem => serde_json::to_value(&gems)?,
+            LicenseGroup::License => serde_json::to_value(licenses::by_license(&gems))?,
+        };
+        println!("{}", serde_json::to_string_pretty(&value)?);
+    } else {
+        display::render_licenses(&gems, group);
+    }
+
+    let denied = licenses::forbidden(&gems, fail_on);
+    if !denied.is_empty() {
+        let list: Vec<String> = denied.iter().map(|(gem, license)| format!("{} {} ({})", gem.name, gem.version, license)).collect();
+        anyhow::bail!("禁止されたライセンスの Gem が {} 件あります: {}", denied.len(), list.join(", "));
+    }
+    Ok(())
+}
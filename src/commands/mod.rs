@@ -1,591 +1,286 @@
-mod runner;
-
-use anyhow::{Context, Result};
-use serde_json::json;
+mod add;
+pub mod baseline;
+mod binstub;
+mod bootstrap;
+mod bundle;
+mod bundle_config;
+mod changelog;
+mod clean;
+mod config;
+mod context;
+mod detach;
+mod doctor;
+mod env;
+mod exec;
+mod export;
+mod fsops;
+mod gc;
+mod gem_cache;
+mod gemfile;
+mod init;
+mod licenses;
+mod path_check;
+pub(crate) mod phases;
+mod pipeline;
+mod preflight;
+mod preloader;
+mod prune_gems;
+mod recent;
+mod remove;
+mod report;
+pub mod retention;
+mod rubies;
+mod ruby_constraints;
+pub(crate) mod runner;
+mod safety;
+mod sandbox_home;
+mod shell;
+mod shell_history;
+mod shell_prompt;
+mod signal_input;
+mod state;
+mod state_json;
+mod stats;
+mod sync;
+mod task;
+pub mod tool;
+mod tree;
+mod ui;
+mod undo;
+mod undo_check;
+mod undo_since;
+mod upgrade_log;
+mod wizard;
+pub mod workspace;
+
+pub use add::add;
+pub use binstub::binstub;
+pub use bootstrap::bootstrap;
+pub use bundle_config::bundle_config;
+pub use changelog::{ChangelogFormat, changelog};
+pub use clean::clean;
+pub use config::{config_list, config_set};
+pub use context::CommandContext;
+pub use doctor::doctor;
+pub use env::{env, env_diff};
+pub use exec::{exec, list_aliases, list_bins, output, ps, reap, run, stop, task};
+pub use export::{export, import};
+pub use gc::{auto_retention, gc};
+pub use gem_cache::{cache_clean, cache_warm};
+pub use gemfile::{gemfile_check, gemfile_sort};
+pub use init::{adopt, init};
+pub use licenses::licenses;
+pub use prune_gems::prune_gems;
+pub use recent::recent;
+pub use remove::remove;
+pub use report::report;
+pub use shell::shell;
+pub use shell_prompt::{PromptShell, prompt};
+pub use state::{activity, ruby_history, state, state_json, state_summary};
+pub use stats::{CompareWindows, compare_stats, disk_stats, export_stats, flaky_stats, follow, stats};
+pub use sync::sync;
+pub use tree::tree;
+pub use ui::ui;
+pub use undo::{UndoOptions, undo};
+pub use upgrade_log::upgrade_log;
+
+use anyhow::Result;
+use std::io::IsTerminal;
 use std::path::Path;
-use std::{env, fs};
 
 use crate::config::ArcConfig;
-use crate::display;
-use crate::gemfile;
-use crate::signals::{FluxProject, SignalType};
-use runner::{ArcEnv, build_ld_library_path, inject_isolated_env, ruby_bin};
-
-// ─────────────────────────────────────────────
-// 定数
-// ─────────────────────────────────────────────
-
-/// Gem が格納されるサブディレクトリ名。
-/// `gems/`: ソース本体, `specifications/`: メタデータ, `extensions/`: C拡張バイナリ
-const GEM_SUBDIRS: [&str; 3] = ["gems", "specifications", "extensions"];
+use crate::prompt;
+use crate::registry::{self, Registry};
+use crate::signals::FluxProject;
+#[cfg(feature = "tui")]
+pub use detach::output_dir;
 
 // ─────────────────────────────────────────────
 // 低レベルヘルパー
 // ─────────────────────────────────────────────
 
-/// `Path` を UTF-8 文字列に変換する。非 UTF-8 パスでは `Err` を返す。
-fn path_str(p: &Path) -> Result<&str> {
-    p.to_str().context("パスが UTF-8 ではありません")
-}
-
-/// `src` を `dest` へハードリンク優先でコピーする。
-/// `cp -al` が失敗した場合（ファイルシステムが異なる等）は `cp -r` にフォールバックする。
-fn cp_link_or_copy(src: &Path, dest: &Path) -> Result<()> {
-    let ok = matches!(
-        std::process::Command::new("cp")
-            .args(["-al", path_str(src)?, path_str(dest)?])
-            .status(),
-        Ok(s) if s.success()
-    );
-
-    if !ok {
-        let _ = fs::remove_dir_all(dest); // 失敗した残骸を掃除
-        let status = std::process::Command::new("cp")
-            .args(["-r", path_str(src)?, path_str(dest)?])
-            .status()
-            .context("cp -r の起動に失敗しました")?;
-
-        if !status.success() {
-            anyhow::bail!("コピーに失敗しました: {:?} → {:?}", src, dest);
-        }
-    }
-
-    Ok(())
-}
-
-/// `src_root` 内の各エントリを `dest_root` へハードリンク優先でコピーする。
-/// 既に存在するエントリはスキップする（べき等）。
-fn sync_gem_dirs(src_root: &Path, dest_root: &Path) -> Result<()> {
-    if !src_root.exists() {
-        return Ok(());
-    }
-    fs::create_dir_all(dest_root)?;
-
-    for entry in fs::read_dir(src_root)? {
-        let entry = entry?;
-        let dest = dest_root.join(entry.file_name());
-        if !dest.exists() {
-            // ベストエフォート: 個別エントリの失敗は無視して続行
-            let _ = cp_link_or_copy(&entry.path(), &dest);
-        }
-    }
-    Ok(())
-}
-
-// ─────────────────────────────────────────────
-// arc init
-// ─────────────────────────────────────────────
-
-pub fn init(path: &Path) -> Result<()> {
-    if !path.exists() {
-        fs::create_dir_all(path).context("プロジェクトディレクトリの作成に失敗しました")?;
-    }
-
-    let project = FluxProject::init(path)?;
-
-    // デフォルト config.toml を生成
-    let config = ArcConfig::default();
-    config.save(&project.flux_dir)
-        .context("config.toml の初期化に失敗しました")?;
-
-    let signal = project.record(
-        SignalType::Init,
-        json!({
-            "path": path,
-            "version": env!("CARGO_PKG_VERSION"),
-            "ruby_version": config.ruby.version,
-        }),
-    )?;
-
-    eprintln!("✨ Flux project initialized at {:?}", path);
-    eprintln!("   Signal: {} ({})", signal.id, signal.r_type);
-    eprintln!("   Ruby:   {} (change with `arc bootstrap <version>`)", config.ruby.version);
-
-    Ok(())
-}
-
-// ─────────────────────────────────────────────
-// arc state
-// ─────────────────────────────────────────────
-
-pub fn state(json_output: bool, raw: bool, diff: bool, type_filter: Option<String>) -> Result<()> {
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)?;
-    let signals = project.read_signals()?;
-
-    let filtered: Vec<_> = match &type_filter {
-        Some(t) => signals.iter().filter(|s| s.r_type == *t).collect(),
-        None    => signals.iter().collect(),
-    };
-
-    if json_output {
-        println!("{}", serde_json::to_string_pretty(&filtered)?);
+/// Gemfile を変更するコマンドの前に、対象プロジェクトが意図したものか確認する。
+/// 最後に使ったプロジェクトと異なる場合 (または `[safety] confirm_mutations`) のみ確認を求める。
+/// `--yes` 指定時と、stdin が TTY でない場合は確認しない。
+fn guard_mutation(project: &FluxProject, cwd: &Path, yes: bool) -> Result<()> {
+    if yes || !std::io::stdin().is_terminal() {
         return Ok(());
     }
 
-    if raw {
-        return display::render_raw(&filtered, &project.flux_dir);
-    }
-
-    if diff {
-        return display::render_diff(&signals);
-    }
-
-    display::render_full(&signals, &cwd)
-}
-
-// ─────────────────────────────────────────────
-// arc exec
-// ─────────────────────────────────────────────
-
-pub fn exec(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        anyhow::bail!("コマンドを指定してください。Usage: arc exec <command> [args...]");
-    }
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)?;
-    let (cmd, cmd_args) = (&args[0], &args[1..]);
-
-    eprintln!("🚀 arc exec: {}", display::fmt_cmd(cmd, cmd_args));
-
-    runner::run_with_flux(
-        &project,
-        SignalType::ExecStart,
-        SignalType::ExecEnd,
-        cmd,
-        cmd_args,
-        &cwd,
-        ArcEnv::System,
-    )
-}
-
-// ─────────────────────────────────────────────
-// arc sync
-// ─────────────────────────────────────────────
-
-pub fn sync() -> Result<()> {
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)
-        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
-    install_with(&project, &cwd)
-}
-
-/// `FluxProject` を受け取って bundle install を実行する内部ヘルパー。
-/// `add`/`remove`/`undo` から再利用することで `FluxProject::open()` の二重呼び出しを防ぐ。
-/// 実行前にキャッシュから Gem を復元し、実行後にキャッシュへ保存する。
-fn install_with(project: &FluxProject, cwd: &Path) -> Result<()> {
-    if !cwd.join("Gemfile").exists() {
-        anyhow::bail!("Gemfile が見つかりません。");
-    }
-
-    // config.toml から Ruby API バージョンを取得
     let config = ArcConfig::load(&project.flux_dir)?;
-    let ruby_api_ver = crate::config::ruby_api_version(&config.ruby.version);
-
-    // 1. キャッシュから既存の Gem を復元 (Binary Install 相当)
-    let _ = restore_gems(cwd, &ruby_api_ver);
-
-    eprintln!("⚡ arc: bundle install → {}", crate::signals::ARC_ENV_DIR);
-
-    let args = vec!["install".to_string()];
-    runner::run_with_flux(
-        project,
-        SignalType::InstallStart,
-        SignalType::InstallEnd,
-        "bundle",
-        &args,
-        cwd,
-        ArcEnv::Isolated,
+    let registry = Registry::load_from(&registry::registry_path()?).unwrap_or_default();
+    let root = cwd.canonicalize().unwrap_or_else(|_| cwd.to_path_buf());
+
+    let proceed = prompt::confirm_target(
+        &registry,
+        &root,
+        config.project.name.as_deref(),
+        config.safety.confirm_mutations,
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr(),
     )?;
-
-    // 2. 新しく入った Gem をキャッシュに保存 (将来のプロジェクト用)
-    let _ = harvest_gems(cwd, &ruby_api_ver);
-
-    Ok(())
-}
-
-// ─────────────────────────────────────────────
-// Gem キャッシュ (Harvest & Restore)
-// ─────────────────────────────────────────────
-
-/// プロジェクト内の Gem をグローバルキャッシュに保存する（ベストエフォート）。
-fn harvest_gems(cwd: &Path, ruby_api_ver: &str) -> Result<()> {
-    let gem_cache = crate::signals::get_global_gems_dir();
-    let local_base = cwd
-        .join(crate::signals::ARC_ENV_DIR)
-        .join("ruby")
-        .join(ruby_api_ver);
-
-    if !local_base.exists() {
-        return Ok(());
-    }
-
-    for subdir in GEM_SUBDIRS {
-        let _ = sync_gem_dirs(&local_base.join(subdir), &gem_cache.join(subdir));
-    }
-    Ok(())
-}
-
-/// グローバルキャッシュからプロジェクト内へ Gem を復元する（ベストエフォート）。
-fn restore_gems(cwd: &Path, ruby_api_ver: &str) -> Result<()> {
-    let gem_cache = crate::signals::get_global_gems_dir();
-    if !gem_cache.exists() {
-        return Ok(());
-    }
-
-    let local_base = cwd
-        .join(crate::signals::ARC_ENV_DIR)
-        .join("ruby")
-        .join(ruby_api_ver);
-
-    for subdir in GEM_SUBDIRS {
-        let _ = sync_gem_dirs(&gem_cache.join(subdir), &local_base.join(subdir));
-    }
-    Ok(())
-}
-
-// ─────────────────────────────────────────────
-// arc run
-// ─────────────────────────────────────────────
-
-pub fn run(args: &[String]) -> Result<()> {
-    if args.is_empty() {
-        anyhow::bail!("実行するコマンドを指定してください。");
-    }
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)
-        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
-
-    let (cmd, cmd_args) = (&args[0], &args[1..]);
-    runner::run_with_flux(
-        &project,
-        SignalType::RunStart,
-        SignalType::RunEnd,
-        cmd,
-        cmd_args,
-        &cwd,
-        ArcEnv::Isolated,
-    )
-}
-
-// ─────────────────────────────────────────────
-// arc env
-// ─────────────────────────────────────────────
-
-pub fn env() -> Result<()> {
-    let cwd = env::current_dir()?;
-    let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
-    let ruby_bin_path = ruby_bin(&env_dir);
-
-    eprintln!("⚡ arc env");
-    eprintln!();
-    eprintln!("  Project:   {}", cwd.display());
-    eprintln!("  ARC_ENV:   {}", env_dir.display());
-    eprintln!("  GEM_HOME:  {}", env_dir.display());
-    eprintln!("  Ruby:      {}",
-        if ruby_bin_path.exists() { ruby_bin_path.display().to_string() }
-        else { "(not bootstrapped — run `arc bootstrap`)".to_string() }
-    );
-
-    // Ruby バージョンを実際に走らせて表示（共有ライブラリを解決してから実行）
-    if ruby_bin_path.exists() {
-        let mut cmd = std::process::Command::new(&ruby_bin_path);
-        cmd.arg("--version");
-
-        // LD_LIBRARY_PATH を設定 (runner と同じロジックを共有)
-        if let Some(ld_path) = build_ld_library_path(&env_dir) {
-            cmd.env("LD_LIBRARY_PATH", ld_path);
-        }
-
-        if let Ok(o) = cmd.output() {
-            let ver = if !o.stdout.is_empty() {
-                String::from_utf8_lossy(&o.stdout).to_string()
-            } else {
-                String::from_utf8_lossy(&o.stderr).to_string()
-            };
-            eprintln!("  Version:   {}", ver.trim());
-        }
+    if !proceed {
+        anyhow::bail!("中止しました。");
     }
-
-    eprintln!();
-    Ok(())
-}
-
-// ─────────────────────────────────────────────
-// arc shell
-// ─────────────────────────────────────────────
-
-pub fn shell() -> Result<()> {
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)
-        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
-
-    // 起動するシェルを決定: $SHELL > /bin/bash
-    let shell_bin = env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string());
-
-    eprintln!("🐚 arc shell: entering isolated environment");
-    eprintln!("   Shell:   {}", shell_bin);
-    eprintln!("   GEM_HOME: {}", cwd.join(crate::signals::ARC_ENV_DIR).display());
-    eprintln!("   Type 'exit' to leave the arc environment.");
-    eprintln!();
-
-    let mut command = std::process::Command::new(&shell_bin);
-    inject_isolated_env(&mut command, &cwd)?;
-
-    // ARC_SHELL=1 をセットしておくと、PS1 等でカスタマイズできる
-    command.env("ARC_SHELL", "1");
-
-    project.record(
-        SignalType::Custom("shell_enter".to_string()),
-        json!({ "shell": &shell_bin }),
-    )?;
-
-    // インタラクティブシェルを起動。ユーザーが exit するまでブロック。
-    let status = command
-        .status()
-        .map_err(|e| anyhow::anyhow!("シェル '{}' の起動に失敗しました: {}", shell_bin, e))?;
-
-    let exit_code = status.code().unwrap_or(0);
-    project.record(
-        SignalType::Custom("shell_exit".to_string()),
-        json!({ "exit_code": exit_code }),
-    )?;
-
-    eprintln!();
-    eprintln!("🐚 arc shell: exited (code: {})", exit_code);
-
     Ok(())
 }
 
 // ─────────────────────────────────────────────
-// arc add
-// ─────────────────────────────────────────────
-
-pub fn add(gem_name: &str, version: Option<&str>) -> Result<()> {
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)
-        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
-
-    let gemfile_path = cwd.join("Gemfile");
-    let added = gemfile::add_gem(&gemfile_path, gem_name, version)?;
-
-    if added {
-        eprintln!("➕ Added '{}' to Gemfile", gem_name);
-    } else {
-        eprintln!("ℹ️  '{}' は既に Gemfile に存在します。スキップします。", gem_name);
-        return Ok(()); // 変更なし → install 不要
-    }
-
-    project.record(
-        SignalType::Add,
-        json!({ "gem": gem_name, "version": version }),
-    )?;
-
-    install_with(&project, &cwd)
-}
-
-// ─────────────────────────────────────────────
-// arc remove
+// テスト
 // ─────────────────────────────────────────────
 
-pub fn remove(gem_name: &str) -> Result<()> {
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)
-        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
-
-    let gemfile_path = cwd.join("Gemfile");
-    if !gemfile_path.exists() {
-        anyhow::bail!("Gemfile が見つかりません。");
-    }
-
-    let removed = gemfile::remove_gem(&gemfile_path, gem_name)?;
-
-    if removed {
-        eprintln!("➖ Removed '{}' from Gemfile", gem_name);
-    } else {
-        eprintln!("ℹ️  '{}' は Gemfile に見つかりませんでした。スキップします。", gem_name);
-        return Ok(()); // 変更なし → install 不要
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sync_state;
+    use super::add::add_at;
+    use super::bootstrap::{bootstrap_at, resolve_ruby_id};
+    use super::runner::ruby_bin;
+    use super::sync::install_with_cache;
+    use std::fs;
+
+    /// ruby_runtime と Gem ディレクトリを持つ、sync 済みのプロジェクトを作る
+    pub(crate) fn synced_project(name: &str) -> std::path::PathBuf {
+        let cwd = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&cwd);
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        fs::create_dir_all(runner::ruby_runtime_bin(&env_dir)).unwrap();
+        fs::write(ruby_bin(&env_dir), "").unwrap();
+        fs::create_dir_all(env_dir.join("ruby").join("3.3.0")).unwrap();
+        fs::write(cwd.join("Gemfile"), "gem 'json'\n").unwrap();
+
+        let digest = sync_state::compute_digest(&cwd, "3.3.6").unwrap();
+        sync_state::save(&env_dir, &digest).unwrap();
+        cwd
     }
 
-    project.record(
-        SignalType::Remove,
-        json!({ "gem": gem_name }),
-    )?;
-
-    install_with(&project, &cwd)
-}
-
-// ─────────────────────────────────────────────
-// arc undo (Time Machine)
-// ─────────────────────────────────────────────
-
-pub fn undo() -> Result<()> {
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)?;
-    let signals = project.read_signals()?;
-
-    // 既に取り消し済みのシグナル ID を収集する（所有型 String で保持）
-    let already_undone: std::collections::HashSet<String> = signals.iter()
-        .filter(|s| s.r_type == "undo")
-        .filter_map(|s| s.payload["target_id"].as_str().map(String::from))
-        .collect();
-
-    // 最新の「未取り消し」の add/remove を探す
-    let target = signals.iter().rev().find(|s| {
-        (s.r_type == "add" || s.r_type == "remove")
-            && !already_undone.contains(&s.id)
-    });
+    /// 隔離環境の PATH で最初に見つかる偽の bundle (`#!/bin/sh` の後に `body` を実行する) を置く
+    pub(crate) fn fake_bundle(cwd: &Path, body: &str) {
+        use std::os::unix::fs::PermissionsExt;
 
-    let target = match target {
-        Some(s) => s,
-        None    => anyhow::bail!("取り消し可能な操作（add/remove）が見つかりません。"),
-    };
-
-    let gem_name = target.payload["gem"].as_str()
-        .context("シグナルに gem 名が含まれていません。")?;
-
-    eprintln!("⏪ Undo: {}", target.r_type);
-
-    let gemfile_path = cwd.join("Gemfile");
-    match target.r_type.as_str() {
-        "add" => {
-            eprintln!("   Removing '{}' from Gemfile...", gem_name);
-            gemfile::remove_gem(&gemfile_path, gem_name)?;
-        }
-        "remove" => {
-            let version = target.payload["version"].as_str();
-            eprintln!("   Restoring '{}' to Gemfile...", gem_name);
-            gemfile::add_gem(&gemfile_path, gem_name, version)?;
-        }
-        _ => unreachable!(),
+        let bundle = runner::ruby_runtime_bin(&cwd.join(crate::signals::ARC_ENV_DIR)).join("bundle");
+        fs::write(&bundle, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&bundle, fs::Permissions::from_mode(0o755)).unwrap();
     }
 
-    project.record(
-        SignalType::Undo,
-        json!({
-            "target_id":   target.id,
-            "target_type": target.r_type,
-            "gem":         gem_name,
-        }),
-    )?;
-
-    install_with(&project, &cwd)
-}
-
-// ─────────────────────────────────────────────
-// arc bootstrap (Global Cache 対応)
-// ─────────────────────────────────────────────
-
-fn resolve_ruby_id(version: &str) -> String {
-    format!("{}-{}-{}", version, env::consts::OS, env::consts::ARCH)
-}
-
-fn resolve_ruby_url(version: &str) -> Result<String> {
-    let suffix = match (env::consts::OS, env::consts::ARCH) {
-        ("linux", "x86_64")  => "ubuntu-24.04",
-        ("linux", "aarch64") => "ubuntu-24.04-arm64",
-        (os, arch) => anyhow::bail!("未対応のプラットフォームです: {} / {}", os, arch),
-    };
-
-    Ok(format!(
-        "https://github.com/ruby/ruby-builder/releases/download/toolcache/ruby-{}-{}.tar.gz",
-        version, suffix
-    ))
-}
-
-/// `version`: CLI 引数で指定されたバージョン。None の場合は config.toml を参照する。
-pub fn bootstrap(version_arg: Option<&str>) -> Result<()> {
-    let cwd = env::current_dir()?;
-    let project = FluxProject::open(&cwd)
-        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
-
-    // バージョン解決: 引数 > config.toml の順で優先
-    let mut config = ArcConfig::load(&project.flux_dir)?;
-    let ruby_version = if let Some(v) = version_arg {
-        // 引数で指定された場合は config.toml を更新して永続化
-        config.ruby.version = v.to_string();
-        config.save(&project.flux_dir)?;
-        eprintln!("📝 Ruby version set to {} in .arc/config.toml", v);
-        v.to_string()
-    } else {
-        config.ruby.version.clone()
-    };
-
-    let cache_dir = crate::signals::get_global_cache_dir()
-        .join("rubies")
-        .join(resolve_ruby_id(&ruby_version));
-    let ruby_dest = cwd.join(crate::signals::ARC_ENV_DIR).join("ruby_runtime");
+    /// `synced_project` を sync の事前確認が通る状態にする: ruby が 3.3.6 と答え、ロックを保持する
+    pub(crate) fn preflight_ready(cwd: &Path) -> crate::project_lock::ProjectLock {
+        use std::os::unix::fs::PermissionsExt;
 
-    if ruby_dest.exists() {
-        eprintln!("ℹ️  Ruby 実行環境は既にプロジェクト内に存在します: {:?}", ruby_dest);
-        eprintln!("   バージョンを変更する場合は ruby_runtime を削除してから再実行してください。");
-        return Ok(());
-    }
-
-    // 1. グローバルキャッシュにあるか確認
-    let cache_hit = cache_dir.exists();
-    if cache_hit {
-        eprintln!("✨ Cache Hit: Ruby {} found in global cache.", ruby_version);
-    } else {
-        download_ruby_to_cache(&cache_dir, &ruby_version)?;
+        let ruby = ruby_bin(&cwd.join(crate::signals::ARC_ENV_DIR));
+        fs::write(&ruby, "#!/bin/sh\ncase \"$1\" in -e) printf 3.3.6 ;; esac\n").unwrap();
+        fs::set_permissions(&ruby, fs::Permissions::from_mode(0o755)).unwrap();
+        crate::project_lock::acquire(&cwd.join(crate::signals::FLUX_DIR), "arc add", std::time::Duration::ZERO).unwrap()
     }
 
-    // 2. キャッシュからプロジェクトへリンク/コピー
-    eprintln!("⚡ Linking Ruby to project environment...");
-    let ruby_env_dir = ruby_dest.parent()
-        .context("ruby_dest の親ディレクトリが取得できません")?;
-    fs::create_dir_all(ruby_env_dir)?;
-    cp_link_or_copy(&cache_dir, &ruby_dest)?;
-
-    project.record(
-        SignalType::Bootstrap,
-        json!({
-            "ruby_version": ruby_version,
-            "cache_hit":    cache_hit,
-            "dest":         ruby_dest.to_string_lossy(),
-        }),
-    )?;
-
-    eprintln!("✨ Ruby {} bootstrap complete!", ruby_version);
-    Ok(())
-}
-
-/// Ruby バイナリをダウンロードしてキャッシュディレクトリに展開する。
-/// 失敗した場合はキャッシュディレクトリを削除してエラーを返す。
-fn download_ruby_to_cache(cache_dir: &Path, ruby_version: &str) -> Result<()> {
-    eprintln!("🚀 Cache Miss: Downloading Ruby {} from ruby-builder...", ruby_version);
-    fs::create_dir_all(cache_dir).context("キャッシュディレクトリの作成に失敗しました")?;
-
-    let ruby_url = resolve_ruby_url(ruby_version)?;
-    let tmp_archive = cache_dir.join("download.tar.gz");
-
-    let curl_ok = std::process::Command::new("curl")
-        .args(["-fL", "--progress-bar", "-o", path_str(&tmp_archive)?, &ruby_url])
-        .status()
-        .context("curl の起動に失敗しました")?
-        .success();
-
-    if !curl_ok {
-        let _ = fs::remove_dir_all(cache_dir);
-        anyhow::bail!("Ruby バイナリのダウンロードに失敗しました。");
+    #[test]
+    fn test_dry_run_sync_add_bootstrap() {
+        use crate::executor::{self, RecordingExecutor};
+        use std::rc::Rc;
+
+        let cwd = synced_project("arc_dry_run_test");
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let logged = project.read_signals().unwrap().len();
+        let gemfile = fs::read_to_string(cwd.join("Gemfile")).unwrap();
+        let types = |signals: &[crate::signals::Signal]| signals.iter().map(|s| s.r_type.clone()).collect::<Vec<_>>();
+
+        // sync: bundler は起動せず、.arc/env もキャッシュも変えない
+        let fake = Rc::new(RecordingExecutor::default());
+        let (result, signals) = executor::with(fake.clone(), || {
+            crate::dry_run::scoped(|| install_with_cache(&project, &cwd, &cwd.join("gem-cache"), false, false, true))
+        });
+        result.unwrap();
+        assert_eq!(fake.calls.borrow()[0].argv, ["bundle", "install"]);
+        assert_eq!(fake.calls.borrow()[0].cwd.as_deref(), Some(cwd.as_path()));
+        assert_eq!(types(&signals), ["install_start", "install_end"]);
+        assert!(sync_state::load(&env_dir).is_some());
+        assert!(!cwd.join("gem-cache").exists() && !crate::env_lock::path(&cwd).exists());
+
+        // add: Gemfile を変えず、何も起動しない
+        let fake = Rc::new(RecordingExecutor::default());
+        let (result, signals) = executor::with(fake.clone(), || crate::dry_run::scoped(|| add_at(&CommandContext::at(&cwd, true), "rake", None, true, true)));
+        result.unwrap();
+        assert!(fake.calls.borrow().is_empty() && signals.is_empty());
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), gemfile);
+
+        // bootstrap: ダウンロードと展開のコマンドだけを表示し、リンクも config の更新もしない
+        fs::remove_dir_all(runner::ruby_runtime_root(&env_dir)).unwrap();
+        let fake = Rc::new(RecordingExecutor::default());
+        let (result, signals) =
+            executor::with(fake.clone(), || crate::dry_run::scoped(|| bootstrap_at(&CommandContext::at(&cwd, true), Some("0.0.1-dry-run"), false, false)));
+        result.unwrap();
+        assert_eq!(fake.programs(), ["curl", "tar"]);
+        assert!(fake.calls.borrow()[0].argv.last().unwrap().contains("ruby-0.0.1-dry-run-"));
+        assert_eq!(types(&signals), ["bootstrap"]);
+        assert!(fs::symlink_metadata(runner::ruby_runtime_root(&env_dir)).is_err());
+        assert!(!crate::signals::get_global_cache_dir().unwrap().join("rubies").join(resolve_ruby_id("0.0.1-dry-run")).exists());
+        assert_eq!(ArcConfig::load(&project.flux_dir).unwrap().ruby.version, "3.3.6");
+
+        assert_eq!(project.read_signals().unwrap().len(), logged);
+        fs::remove_dir_all(&cwd).unwrap();
     }
 
-    let tar_ok = std::process::Command::new("tar")
-        .args([
-            "-xzf", path_str(&tmp_archive)?,
-            "-C",   path_str(cache_dir)?,
-            "--strip-components=1",
-        ])
-        .status()
-        .context("tar の起動に失敗しました")?
-        .success();
-
-    let _ = fs::remove_file(&tmp_archive);
-
-    if !tar_ok {
-        let _ = fs::remove_dir_all(cache_dir);
-        anyhow::bail!("アーカイブの展開に失敗しました。");
+    /// `test_scripted_session_snapshot` の Signal ログ (種類と payload)
+    const SESSION_SNAPSHOT: &str = r#"
+init {"name":null,"path":"<root>","ruby_version":"3.3.6","version":"<version>"}
+add {"gem":"rake","gemfile_lines":[{"text":"gem 'rake'"}],"gemfile_sha256":"0ef2fea8e89f0c75cce2181be9f9910c2eaafc391c305b1419c0cbfa6163cd68","version":null}
+install_start {"args":["install"],"command":"bundle","cwd":"<root>"}
+install_end {"arc_overhead_ms":0,"binstubs":{"created":0,"removed":0},"cache":{"fetched":0,"restore_us":0,"restored":0},"disk":{"bytes":130,"files":3,"partial":false},"duration_ms":0,"duration_us":0,"env_lock":"b0f5389748425288f9aaab62443492ea503e9ab5789291ffc10a8c40efeaac59","exit_code":0,"gemfile_sha256":"0ef2fea8e89f0c75cce2181be9f9910c2eaafc391c305b1419c0cbfa6163cd68","link":{"bytes":0,"copy":0,"cross_device":false,"hardlink":0,"mode":"auto","reflink":0},"phases":[{"duration_us":0,"name":"restore_cache"},{"duration_us":0,"name":"bundler"},{"duration_us":0,"name":"generate_binstubs"},{"duration_us":0,"name":"harvest_cache"},{"duration_us":0,"name":"write_sync_state"}],"ref_id":"018cc251-fbd0-76c4-9d18-8009454ff88b","success":true}
+add {"gem":"activesupport","gemfile_lines":[{"text":"gem 'activesupport', '~> 7.1'"}],"gemfile_sha256":"6044ad6b1cbada7f96ec8ad47bd40e5dd77833c10e2a022686e77c43e3eb8785","version":"~> 7.1"}
+install_start {"args":["install"],"command":"bundle","cwd":"<root>"}
+install_end {"arc_overhead_ms":0,"binstubs":{"created":0,"removed":0},"cache":{"fetched":0,"restore_us":0,"restored":0},"disk":{"bytes":130,"files":3,"partial":false},"duration_ms":0,"duration_us":0,"env_lock":"b0f5389748425288f9aaab62443492ea503e9ab5789291ffc10a8c40efeaac59","exit_code":0,"gemfile_sha256":"6044ad6b1cbada7f96ec8ad47bd40e5dd77833c10e2a022686e77c43e3eb8785","link":{"bytes":0,"copy":0,"cross_device":false,"hardlink":0,"mode":"auto","reflink":0},"phases":[{"duration_us":0,"name":"restore_cache"},{"duration_us":0,"name":"bundler"},{"duration_us":0,"name":"generate_binstubs"},{"duration_us":0,"name":"harvest_cache"},{"duration_us":0,"name":"write_sync_state"}],"ref_id":"018cc252-0788-73cb-9f0c-747ea2ea2c82","success":true}
+config_set {"key":"project.name","new":"shop","old":null}
+x-gemfile-format {"diff":"--- a/Gemfile\n+++ b/Gemfile\n@@ -1,3 +1,3 @@\n+gem 'activesupport', '~> 7.1'\n gem 'json'\n gem 'rake'\n-gem 'activesupport', '~> 7.1'\n","gemfile_sha256":"d5d2e99880af6c8b54e1244fb4ad97bdb0c6d922b4bda8ac7dd5513501c7586f","quote":"single"}
+exec_start {"args":[],"command":"true","cwd":"<root>"}
+exec_end {"arc_overhead_ms":0,"duration_ms":0,"duration_us":0,"exit_code":0,"ref_id":"018cc252-1728-73b8-888c-368cb0a6657e","success":true}
+x-baseline-set {"command":"true","command_line":"true","duration_us":0,"ended_at":"2024-01-01T00:00:10+00:00","exit_code":0,"name":"smoke","success":true,"target_id":"018cc252-1728-73b8-888c-368cb0a6657e"}
+remove {"gem":"rake","gemfile_lines":[{"text":"gem 'rake'"}],"gemfile_sha256":"17ffb8c657af307e531f7045400d0d0228bf37963abfea06e1b211fbc26928b7"}
+install_start {"args":["install"],"command":"bundle","cwd":"<root>"}
+install_end {"arc_overhead_ms":0,"binstubs":{"created":0,"removed":0},"cache":{"fetched":0,"restore_us":0,"restored":0},"disk":{"bytes":130,"files":3,"partial":false},"duration_ms":0,"duration_us":0,"env_lock":"b0f5389748425288f9aaab62443492ea503e9ab5789291ffc10a8c40efeaac59","exit_code":0,"gemfile_sha256":"17ffb8c657af307e531f7045400d0d0228bf37963abfea06e1b211fbc26928b7","link":{"bytes":0,"copy":0,"cross_device":false,"hardlink":0,"mode":"auto","reflink":0},"phases":[{"duration_us":0,"name":"restore_cache"},{"duration_us":0,"name":"bundler"},{"duration_us":0,"name":"generate_binstubs"},{"duration_us":0,"name":"harvest_cache"},{"duration_us":0,"name":"write_sync_state"}],"ref_id":"018cc252-26c8-7e1f-b555-983aa92fb54e","success":true}
+"#;
+
+    #[test]
+    fn test_scripted_session_snapshot() {
+        let cwd = synced_project("arc_e2e_snapshot_test");
+        fake_bundle(&cwd, "exit 0");
+
+        let log = crate::deterministic::scoped(0, || {
+            super::init::init_with(&cwd, &ArcConfig::default(), serde_json::json!({})).unwrap();
+            let _lock = preflight_ready(&cwd);
+            let ctx = CommandContext::at(&cwd, false);
+            add(&ctx, "rake", None, true, false).unwrap();
+            add(&ctx, "activesupport", Some("~> 7.1"), true, false).unwrap();
+            config_set(&ctx, "project.name", "shop").unwrap();
+            gemfile_check(&ctx, None).unwrap();
+            gemfile_sort(&ctx, false, true).unwrap();
+            exec(&ctx, &["true".to_string()], false, true).unwrap();
+            baseline::set(&ctx, "smoke", Some("true"), None).unwrap();
+            remove(&ctx, Some("rake"), true, false, false).unwrap();
+            upgrade_log(&ctx).unwrap();
+            doctor(&ctx, false).unwrap();
+            gc(&ctx, false, false).unwrap();
+            // --dry-run では何も記録しない
+            let dry = CommandContext::at(&cwd, true);
+            remove(&dry, Some("json"), true, false, false).unwrap();
+            undo(&dry, true, UndoOptions { dry_run: false, force: false, exact: false, interactive: false }, None).unwrap();
+            ctx.project().unwrap().read_signals().unwrap()
+        });
+
+        // 端末・シェル・PATH によって変わる値と、パッケージのバージョンを除く
+        let root = cwd.display().to_string();
+        let lines: Vec<String> = log
+            .into_iter()
+            .map(|mut s| {
+                if let Some(payload) = s.payload.as_object_mut() {
+                    payload.remove("context");
+                    payload.remove("env_context");
+                }
+                let line = format!("{} {}", s.r_type, s.payload);
+                line.replace(&root, "<root>").replace(env!("CARGO_PKG_VERSION"), "<version>")
+            })
+            .collect();
+        assert_eq!(lines.join("\n"), SESSION_SNAPSHOT.trim(), "{}", lines.join("\n"));
+        fs::remove_dir_all(&cwd).unwrap();
     }
-
-    Ok(())
 }
@@ -0,0 +1,277 @@
+//! `arc env --check-path`: 隔離環境の PATH で重要なコマンドがどこに解決されるかを調べる。
+//!
+//! `.arc/env/bin` に古いラッパーが残っていたり、`ruby_runtime` のリンクが切れていたりすると、
+//! `arc run ruby` でも意図しない ruby が選ばれる。実際の PATH 探索 (`runner::which_in`) と
+//! 同じ規則で解決し、環境のレイヤー外で見つかったもの・リンク切れ・バージョンの不一致を報告する。
+
+use anyhow::Result;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use super::runner;
+
+/// 常に確認するコマンド
+pub const CRITICAL: [&str; 4] = ["ruby", "gem", "bundle", "rake"];
+/// `--version` を実行して確認するコマンド
+const VERSIONED: [&str; 2] = ["ruby", "bundle"];
+/// `--version` の待ち時間の上限
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+    NotFound,
+    /// `ruby_runtime/bin` / `.arc/env/bin` 以外のディレクトリで見つかった
+    OutsideEnv,
+    /// レイヤー内のリンク切れのシンボリックリンク (PATH 探索では飛ばされる)
+    Dangling(PathBuf),
+    /// `ruby --version` が `[ruby] version` と一致しない
+    VersionMismatch { expected: String, actual: String },
+    /// `--version` が失敗した・時間内に終わらなかった
+    VersionFailed(String),
+}
+
+impl Anomaly {
+    pub fn describe(&self) -> String {
+        match self {
+            Anomaly::NotFound => "not found in PATH".to_string(),
+            Anomaly::OutsideEnv => "resolved outside the arc env layers".to_string(),
+            Anomaly::Dangling(path) => format!("dangling symlink {}", path.display()),
+            Anomaly::VersionMismatch { expected, actual } => {
+                format!("version {} does not match config ({})", actual, expected)
+            }
+            Anomaly::VersionFailed(reason) => format!("--version failed: {}", reason),
+        }
+    }
+}
+
+/// 1 つのコマンドの解決結果。
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    pub name: String,
+    /// PATH 探索で選ばれたパス
+    pub path: Option<PathBuf>,
+    /// シンボリックリンクを辿った先
+    pub target: Option<PathBuf>,
+    /// `--version` の出力 (1 行目)
+    pub version: Option<String>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// `CRITICAL` と `extra` の各コマンドを `dirs` (隔離環境の PATH) で解決する。
+/// `expected_ruby` は `[ruby] version`。
+pub fn check(env_path: &Path, dirs: &[PathBuf], extra: &[String], expected_ruby: Option<&str>) -> Vec<Resolution> {
+    let layers = runner::env_path_layers(env_path);
+    let mut names: Vec<String> = CRITICAL.iter().map(|n| n.to_string()).collect();
+    names.extend(extra.iter().filter(|n| !CRITICAL.contains(&n.as_str())).cloned());
+
+    names
+        .into_iter()
+        .map(|name| {
+            let mut anomalies: Vec<Anomaly> = layers
+                .iter()
+                .map(|dir| dir.join(&name))
+                .filter(|p| fs::symlink_metadata(p).is_ok_and(|m| m.file_type().is_symlink()) && !p.exists())
+                .map(Anomaly::Dangling)
+                .collect();
+
+            let path = runner::which_in(&name, dirs);
+            let mut version = None;
+            match &path {
+                None => anomalies.push(Anomaly::NotFound),
+                Some(p) => {
+                    if !p.parent().is_some_and(|dir| layers.iter().any(|l| l == dir)) {
+                        anomalies.push(Anomaly::OutsideEnv);
+                    }
+                    if VERSIONED.contains(&name.as_str()) {
+                        match run_version(p, env_path, dirs) {
+                            Ok(v) => version = Some(v),
+                            Err(reason) => anomalies.push(Anomaly::VersionFailed(reason)),
+                        }
+                    }
+                }
+            }
+            if name == "ruby"
+                && let (Some(expected), Some(v)) = (expected_ruby, &version)
+            {
+                let actual = ruby_version_number(v);
+                if actual != expected {
+                    anomalies.push(Anomaly::VersionMismatch { expected: expected.to_string(), actual: actual.to_string() });
+                }
+            }
+
+            Resolution {
+                target: path.as_ref().and_then(|p| fs::canonicalize(p).ok()),
+                name,
+                path,
+                version,
+                anomalies,
+            }
+        })
+        .collect()
+}
+
+/// `ruby 3.3.6 (2024-11-05 revision ...) [x86_64-linux]` → `3.3.6` (`2.7.8p225` の patchlevel は除く)
+fn ruby_version_number(output: &str) -> &str {
+    let token = output.split_whitespace().nth(1).unwrap_or("");
+    token.split('p').next().unwrap_or(token)
+}
+
+/// 隔離環境の PATH・共有ライブラリで `<path> --version` を実行し、出力の 1 行目を返す。
+fn run_version(path: &Path, env_path: &Path, dirs: &[PathBuf]) -> std::result::Result<String, String> {
+    let mut command = Command::new(path);
+    command.arg("--version").stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    if let Ok(joined) = std::env::join_paths(dirs) {
+        command.env("PATH", joined);
+    }
+    if let Some(ld_path) = runner::build_ld_library_path(env_path) {
+        command.env("LD_LIBRARY_PATH", ld_path);
+    }
+    let mut child = command.spawn().map_err(|e| e.to_string())?;
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if started.elapsed() > VERSION_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("timed out after {}s", VERSION_TIMEOUT.as_secs()));
+            }
+            None => std::thread::sleep(Duration::from_millis(20)),
+        }
+    };
+
+    // --version の出力は小さいため、終了後にまとめて読んでもパイプは詰まらない
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut output);
+    }
+    if output.trim().is_empty()
+        && let Some(mut stderr) = child.stderr.take()
+    {
+        let _ = stderr.read_to_string(&mut output);
+    }
+    if !status.success() {
+        return Err(format!("exited with {}", status.code().map_or("a signal".to_string(), |c| c.to_string())));
+    }
+    output
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty())
+        .map(String::from)
+        .ok_or_else(|| "no output".to_string())
+}
+
+/// 結果を表示し、問題があればエラーを返す (CI で終了コードを判定できるように)。
+pub fn report(results: &[Resolution]) -> Result<()> {
+    eprintln!("🔎 PATH check (isolated env)");
+    eprintln!();
+    for r in results {
+        let mark = if r.anomalies.is_empty() { "✅" } else { "❌" };
+        let location = match (&r.path, &r.target) {
+            (Some(path), Some(target)) if path != target => format!("{} → {}", path.display(), target.display()),
+            (Some(path), _) => path.display().to_string(),
+            (None, _) => "-".to_string(),
+        };
+        eprintln!("  {} {:<8} {}", mark, r.name, location);
+        if let Some(version) = &r.version {
+            eprintln!("     {:<8} {}", "", version);
+        }
+        for anomaly in &r.anomalies {
+            eprintln!("     {:<8} ⚠️  {}", "", anomaly.describe());
+        }
+    }
+    eprintln!();
+
+    let failed = results.iter().filter(|r| !r.anomalies.is_empty()).count();
+    if failed > 0 {
+        anyhow::bail!("{} 個のコマンドが隔離環境で正しく解決されません", failed);
+    }
+    eprintln!("✅ All binaries resolve inside the arc env");
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script(path: &Path, body: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// `.arc/env` と、PATH の後ろに続くシステム側のディレクトリ (おとり) を作る。
+    fn fixture(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let env_path = root.join(".arc/env");
+        let system = root.join("usr/bin");
+        script(&runner::ruby_bin(&env_path), "echo 'ruby 3.3.6 (2024-11-05 revision 75015d4c1f) [x86_64-linux]'");
+        script(&runner::ruby_runtime_bin(&env_path).join("gem"), "echo 3.5.22");
+        script(&crate::binstubs::stub_dir(&env_path).join("bundle"), "echo 'Bundler version 2.5.22'");
+        script(&crate::binstubs::stub_dir(&env_path).join("rake"), "echo 'rake, version 13.2.1'");
+        // システム側のおとり
+        script(&system.join("ruby"), "echo 'ruby 2.6.10p210'");
+        script(&system.join("rspec"), "exit 0");
+        (root, env_path, system)
+    }
+
+    fn find<'a>(results: &'a [Resolution], name: &str) -> &'a Resolution {
+        results.iter().find(|r| r.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_healthy_env() {
+        let (root, env_path, system) = fixture("arc_path_check_ok_test");
+        let dirs = runner::isolated_path_dirs(&env_path, Some(system.as_os_str()));
+        let results = check(&env_path, &dirs, &[], Some("3.3.6"));
+        assert!(results.iter().all(|r| r.anomalies.is_empty()), "{:?}", results);
+        assert_eq!(find(&results, "ruby").path.as_deref(), Some(runner::ruby_bin(&env_path).as_path()));
+        assert_eq!(find(&results, "bundle").version.as_deref(), Some("Bundler version 2.5.22"));
+        assert!(report(&results).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_detects_shadowing_and_dangling_links() {
+        let (root, env_path, system) = fixture("arc_path_check_bad_test");
+        // ruby_runtime の ruby が消え、.arc/env/bin には古いリンクが残っている
+        fs::remove_file(runner::ruby_bin(&env_path)).unwrap();
+        let stale = crate::binstubs::stub_dir(&env_path).join("ruby");
+        std::os::unix::fs::symlink(root.join("gone/ruby"), &stale).unwrap();
+
+        let dirs = runner::isolated_path_dirs(&env_path, Some(system.as_os_str()));
+        let extra = ["rspec".to_string(), "missing-tool".to_string()];
+        let results = check(&env_path, &dirs, &extra, Some("3.3.6"));
+
+        let ruby = find(&results, "ruby");
+        assert_eq!(ruby.path.as_deref(), Some(system.join("ruby").as_path()));
+        assert!(ruby.anomalies.contains(&Anomaly::Dangling(stale)));
+        assert!(ruby.anomalies.contains(&Anomaly::OutsideEnv));
+        assert!(ruby.anomalies.contains(&Anomaly::VersionMismatch { expected: "3.3.6".into(), actual: "2.6.10".into() }));
+        assert_eq!(find(&results, "rspec").anomalies, [Anomaly::OutsideEnv]);
+        assert_eq!(find(&results, "missing-tool").anomalies, [Anomaly::NotFound]);
+        assert!(find(&results, "gem").anomalies.is_empty());
+        assert!(report(&results).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_version_failure() {
+        let root = std::env::temp_dir().join("arc_path_check_version_test");
+        let _ = fs::remove_dir_all(&root);
+        script(&root.join("fails"), "echo boom >&2; exit 2");
+        assert_eq!(run_version(&root.join("fails"), &root, &[]), Err("exited with 2".to_string()));
+        assert_eq!(ruby_version_number("ruby 2.7.8p225 (2023-03-30 revision 1f4d455848) [x86_64-linux]"), "2.7.8");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+//! `arc sync` (install) の各フェーズの所要時間の計測。
+//!
+//! フェーズ名は install_end の payload (`phases`) にそのまま記録されるため、
+//! 変更すると過去のログとの集計が途切れる点に注意。
+
+use serde_json::{Value, json};
+use std::time::{Duration, Instant};
+
+use crate::display;
+use crate::progress;
+
+/// キャッシュからの Gem の復元
+pub const RESTORE_CACHE: &str = "restore_cache";
+/// `bundle install` の実行
+pub const BUNDLER: &str = "bundler";
+/// binstub の生成
+pub const GENERATE_BINSTUBS: &str = "generate_binstubs";
+/// キャッシュへの Gem の保存
+pub const HARVEST_CACHE: &str = "harvest_cache";
+/// sync ダイジェストの保存
+pub const WRITE_SYNC_STATE: &str = "write_sync_state";
+
+/// 実行したフェーズを順に記録する。
+#[derive(Debug, Default)]
+pub struct Phases {
+    done: Vec<(&'static str, Duration)>,
+}
+
+impl Phases {
+    /// `f` を 1 つのフェーズとして実行し、所要時間を記録する。進捗イベントも出す。
+    pub fn time<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let timer = Instant::now();
+        let result = progress::phase(name, f);
+        self.done.push((name, timer.elapsed()));
+        result
+    }
+
+    /// 記録済みのフェーズの所要時間。
+    pub fn duration_of(&self, name: &str) -> Option<Duration> {
+        self.done.iter().find(|(n, _)| *n == name).map(|(_, d)| *d)
+    }
+
+    /// install_end の payload に記録する形式: `[{"name": ..., "duration_us": ...}, ...]`
+    pub fn to_json(&self) -> Value {
+        self.done
+            .iter()
+            .map(|(name, d)| json!({ "name": name, "duration_us": d.as_micros() as u64 }))
+            .collect()
+    }
+
+    /// 完了時に表示する 1 行の要約。例: `restore 412ms · bundler 1m 11s · harvest 2.1s`
+    pub fn summary(&self) -> String {
+        self.done
+            .iter()
+            .map(|(name, d)| format!("{} {}", label(name), display::fmt_duration_us(d.as_micros() as u64)))
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+}
+
+/// 要約で使う短い表示名。
+fn label(name: &str) -> &str {
+    match name {
+        RESTORE_CACHE => "restore",
+        GENERATE_BINSTUBS => "binstubs",
+        HARVEST_CACHE => "harvest",
+        WRITE_SYNC_STATE => "sync-state",
+        other => other,
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phases_json_and_summary() {
+        let mut phases = Phases::default();
+        let value = phases.time(RESTORE_CACHE, || 42);
+        assert_eq!(value, 42);
+        phases.time(BUNDLER, || std::thread::sleep(Duration::from_millis(2)));
+
+        let json = phases.to_json();
+        assert_eq!(json[0]["name"], RESTORE_CACHE);
+        assert_eq!(json[1]["name"], BUNDLER);
+        assert!(json[1]["duration_us"].as_u64().unwrap() >= 2_000);
+
+        let summary = phases.summary();
+        assert!(summary.starts_with("restore "));
+        assert!(summary.contains(" · bundler "));
+    }
+}
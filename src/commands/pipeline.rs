@@ -0,0 +1,143 @@
+//! `arc exec --shell` / `arc run --shell`: 引数をシェルのコマンドラインとして実行する。
+//!
+//! `arc exec grep foo log | wc -l` のパイプは外側のシェルが解釈してしまうため、
+//! パイプラインごと記録したい場合は `arc exec --shell 'grep foo log | wc -l'` とする。
+//! Signal には `sh -c ...` ではなく元のコマンドラインを `command` として記録する。
+
+use serde_json::{Value, json};
+
+/// `[run] shell` が未設定のときに使うシェル
+pub const DEFAULT_SHELL: &str = "sh";
+
+/// シェルの呼び出し方と、記録するコマンドライン。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellInvocation {
+    pub program: String,
+    pub args: Vec<String>,
+    /// 引数を空白で連結したコマンドライン
+    pub text: String,
+}
+
+impl ShellInvocation {
+    pub fn new(shell: Option<&str>, argv: &[String]) -> Self {
+        let text = argv.join(" ");
+        Self {
+            program: shell.unwrap_or(DEFAULT_SHELL).to_string(),
+            args: vec!["-c".to_string(), text.clone()],
+            text,
+        }
+    }
+
+    /// start Signal の payload を上書きするフィールド
+    pub fn signal_fields(&self) -> Value {
+        json!({ "command": self.text, "args": [], "via_shell": true })
+    }
+}
+
+/// シェルの構文とみなす記号
+const SHELL_METACHARS: [&str; 9] = ["|", "&&", ";", ">", "<", "$(", "`", "*", "~/"];
+
+/// `arc exec "grep foo | wc -l"` のように、シェルのコマンドラインを 1 つの引数で渡していそうか。
+/// 空白を含まない引数 (`./a|b` のような実在しうるファイル名) や、記号を含まない
+/// 空白入りの引数 (`"my tool"`) は正当なコマンドとみなす。
+pub fn looks_like_shell(argv: &[String]) -> bool {
+    let [only] = argv else { return false };
+    only.contains(char::is_whitespace) && SHELL_METACHARS.iter().any(|m| only.contains(m))
+}
+
+/// `--shell` を付け忘れていそうな場合のヒント。
+pub fn hint(subcommand: &str, argv: &[String]) -> Option<String> {
+    looks_like_shell(argv).then(|| {
+        format!(
+            "💡 シェルのコマンドラインとして実行するには `--shell` を付けてください: arc {} --shell '{}'",
+            subcommand, argv[0]
+        )
+    })
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::runner::{self, ArcEnv};
+    use crate::signals::{FluxProject, SignalType};
+    use std::fs;
+
+    fn args(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// シェル経由で実行し、(終了コード, start Signal の payload) を返す。
+    fn run_shell(name: &str, argv: &[&str]) -> (i32, Value) {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let project = FluxProject::init(&dir, &Default::default(), json!({})).unwrap().0;
+
+        let shell = ShellInvocation::new(None, &args(argv));
+        let executed = runner::execute_recorded(
+            &project,
+            SignalType::ExecStart,
+            &shell.program,
+            &shell.args,
+            &dir,
+            ArcEnv::System,
+            shell.signal_fields(),
+        )
+        .unwrap();
+        let start = project.read_signals().unwrap().pop().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        (executed.exit_code(), start.payload)
+    }
+
+    #[test]
+    fn test_pipeline_is_recorded_as_written() {
+        let (code, payload) = run_shell("arc_pipeline_pipe_test", &["printf 'a\\nb\\n'", "|", "grep -q b"]);
+        assert_eq!(code, 0);
+        assert_eq!(payload["command"], "printf 'a\\nb\\n' | grep -q b");
+        assert_eq!(payload["args"], json!([]));
+        assert_eq!(payload["via_shell"], true);
+    }
+
+    #[test]
+    fn test_and_list() {
+        let (code, payload) = run_shell("arc_pipeline_and_test", &["true && false"]);
+        assert_eq!(code, 1);
+        assert_eq!(payload["command"], "true && false");
+    }
+
+    #[test]
+    fn test_exit_status_propagates() {
+        let (code, _) = run_shell("arc_pipeline_exit_test", &["exit 3"]);
+        assert_eq!(code, 3);
+        // パイプラインの終了コードは最後のコマンドのもの
+        let (code, _) = run_shell("arc_pipeline_last_test", &["true | (exit 7)"]);
+        assert_eq!(code, 7);
+    }
+
+    #[test]
+    fn test_configured_shell() {
+        let shell = ShellInvocation::new(Some("bash"), &args(&["echo", "hi"]));
+        assert_eq!(shell.program, "bash");
+        assert_eq!(shell.args, ["-c", "echo hi"]);
+    }
+
+    #[test]
+    fn test_hint_heuristic() {
+        assert!(looks_like_shell(&args(&["grep foo | wc -l"])));
+        assert!(looks_like_shell(&args(&["make && make install"])));
+        assert!(looks_like_shell(&args(&["echo hi > out.txt"])));
+        // 正当な単一引数のコマンド
+        assert!(!looks_like_shell(&args(&["rake"])));
+        assert!(!looks_like_shell(&args(&["./bin/setup"])));
+        assert!(!looks_like_shell(&args(&["my tool"])));
+        assert!(!looks_like_shell(&args(&["./weird|name"])));
+        // 複数引数は外側のシェルで分割済み
+        assert!(!looks_like_shell(&args(&["grep", "a|b", "log"])));
+        assert_eq!(hint("exec", &args(&["rake"])), None);
+        assert!(hint("exec", &args(&["grep foo | wc -l"])).unwrap().contains("arc exec --shell 'grep foo | wc -l'"));
+    }
+}
@@ -0,0 +1,295 @@
+//! `arc sync` の事前確認 (pre-flight)。
+//!
+//! sync の失敗の多くは、途中で見つかる実行環境の問題 (ランタイムがない、共有ライブラリが壊れている、bundler がない) である。
+//! Gem キャッシュの復元や bundler の実行より前に、次を順に確かめる:
+//!
+//! 1. このプロセスがプロジェクトのロック (`.flux/lock`) を保持している
+//! 2. `.arc/env` のファイルシステムに `[sync] min_free_mb` 以上の空きがある
+//! 3. `ruby_runtime` の ruby が実行でき、config.toml のバージョンと一致する
+//! 4. bundler が `bundle --version` に応答する
+//! 5. ruby が Gemfile を解釈できる (`ruby -c`)
+//!
+//! 問題が見つかれば、何が問題でどのコマンドで直せるかを示して中止する。
+//! install_start / install_end の組は記録せず、代わりに `preflight_failed` を記録する。
+//! `arc sync --no-preflight` で省略できる。
+
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+use super::rubies::probe_version;
+use super::runner::{inject_isolated_env, ruby_bin};
+use crate::config::ArcConfig;
+use crate::display;
+use crate::signals::{ARC_ENV_DIR, FluxProject, SignalType};
+
+pub const LOCK: &str = "lock";
+pub const DISK: &str = "disk";
+pub const RUBY: &str = "ruby";
+pub const BUNDLER: &str = "bundler";
+pub const GEMFILE: &str = "gemfile";
+
+/// 見つかった問題
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// 確認の名前 (`LOCK` / `DISK` / `RUBY` / `BUNDLER` / `GEMFILE`)
+    pub check: &'static str,
+    pub problem: String,
+    /// 直すためのコマンド
+    pub fix: String,
+}
+
+impl Failure {
+    fn new(check: &'static str, problem: String, fix: &str) -> Self {
+        Self { check, problem, fix: fix.to_string() }
+    }
+
+    /// `preflight_failed` の payload
+    fn to_json(&self) -> Value {
+        json!({ "check": self.check, "problem": self.problem, "fix": self.fix })
+    }
+}
+
+/// 事前確認を行い、問題があれば `preflight_failed` を記録してエラーにする。
+pub fn run(project: &FluxProject, cwd: &Path, config: &ArcConfig) -> Result<()> {
+    let timer = Instant::now();
+    let Err(failure) = check(project, cwd, config) else {
+        display::verbose(&format!("✅ preflight passed ({})", display::fmt_duration_us(timer.elapsed().as_micros() as u64)));
+        return Ok(());
+    };
+    project.record(SignalType::PreflightFailed, failure.to_json())?;
+    bail!(
+        "sync の事前確認で問題が見つかりました ({}): {}\n  直すには: {}\n  (確認を省略するには `arc sync --no-preflight`)",
+        failure.check,
+        failure.problem,
+        failure.fix
+    );
+}
+
+/// 確認を順に行い、最初に見つかった問題を返す。
+pub fn check(project: &FluxProject, cwd: &Path, config: &ArcConfig) -> Result<(), Failure> {
+    let env_dir = cwd.join(ARC_ENV_DIR);
+    check_lock(&project.flux_dir)?;
+    check_disk(if env_dir.exists() { &env_dir } else { cwd }, config.sync.min_free_mb)?;
+    check_ruby(&env_dir, &config.ruby.version)?;
+    check_bundler(cwd)?;
+    check_gemfile(cwd, &env_dir)
+}
+
+fn check_lock(flux_dir: &Path) -> Result<(), Failure> {
+    if crate::project_lock::held_by_current_process(flux_dir) {
+        return Ok(());
+    }
+    Err(Failure::new(
+        LOCK,
+        "プロジェクトのロック (.flux/lock) を保持していません".to_string(),
+        "他の arc の終了を待ってから `arc sync` を実行してください",
+    ))
+}
+
+fn check_disk(path: &Path, min_free_mb: u64) -> Result<(), Failure> {
+    // 空き容量を取得できないファイルシステムでは確認しない
+    let Ok(free) = free_bytes(path) else { return Ok(()) };
+    let required = min_free_mb * 1024 * 1024;
+    if free >= required {
+        return Ok(());
+    }
+    Err(Failure::new(
+        DISK,
+        format!(
+            "{} の空き容量が {} しかありません (必要: {}、`[sync] min_free_mb`)",
+            path.display(),
+            display::fmt_bytes(free),
+            display::fmt_bytes(required)
+        ),
+        "`arc cache clean` で Gem キャッシュを整理するか、ディスクの空きを増やしてください",
+    ))
+}
+
+fn check_ruby(env_dir: &Path, version: &str) -> Result<(), Failure> {
+    let ruby = ruby_bin(env_dir);
+    let bootstrap = format!("`arc bootstrap {}`", version);
+    if !ruby.exists() {
+        return Err(Failure::new(RUBY, format!("Ruby のランタイムがありません ({})", ruby.display()), &bootstrap));
+    }
+    match probe_version(&ruby) {
+        None => Err(Failure::new(
+            RUBY,
+            format!("{} を実行できません (壊れているか、共有ライブラリが見つかりません)", ruby.display()),
+            &bootstrap,
+        )),
+        Some(actual) if actual != version => Err(Failure::new(
+            RUBY,
+            format!("ruby_runtime の Ruby は {} ですが、config.toml は {} です", actual, version),
+            &bootstrap,
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+fn check_bundler(cwd: &Path) -> Result<(), Failure> {
+    let mut command = Command::new("bundle");
+    command.arg("--version").current_dir(cwd).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    let responded = inject_isolated_env(&mut command, cwd).is_ok() && command.status().is_ok_and(|s| s.success());
+    if responded {
+        return Ok(());
+    }
+    Err(Failure::new(
+        BUNDLER,
+        "bundler が応答しません (`bundle --version` が失敗しました)".to_string(),
+        "`arc exec gem install bundler`",
+    ))
+}
+
+fn check_gemfile(cwd: &Path, env_dir: &Path) -> Result<(), Failure> {
+    // Gemfile がないことは install 自体が報告する
+    if !cwd.join("Gemfile").exists() {
+        return Ok(());
+    }
+    let mut command = Command::new(ruby_bin(env_dir));
+    command.args(["-c", "Gemfile"]).current_dir(cwd).stdin(Stdio::null());
+    let output = match inject_isolated_env(&mut command, cwd).ok().and_then(|_| command.output().ok()) {
+        Some(output) if output.status.success() => return Ok(()),
+        Some(output) => output,
+        None => return Ok(()),
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let detail = stderr.lines().find(|l| !l.trim().is_empty()).unwrap_or("ruby -c が失敗しました");
+    Err(Failure::new(GEMFILE, format!("Gemfile を解釈できません: {}", detail.trim()), "`arc exec ruby -c Gemfile`"))
+}
+
+#[cfg(test)]
+thread_local! {
+    static FREE_BYTES: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
+/// テスト用: 空き容量を `bytes` として扱う (小さな tmpfs の再現)。
+#[cfg(test)]
+pub fn set_free_bytes(bytes: Option<u64>) {
+    FREE_BYTES.with(|f| f.set(bytes));
+}
+
+fn free_bytes(path: &Path) -> std::io::Result<u64> {
+    #[cfg(test)]
+    if let Some(bytes) = FREE_BYTES.with(|f| f.get()) {
+        return Ok(bytes);
+    }
+    crate::fs_util::free_space(path)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::runner::ruby_runtime_bin;
+    use crate::commands::tests::fake_bundle;
+    use crate::project_lock::{self, ProjectLock};
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn write_script(path: &Path, body: &str) {
+        fs::write(path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// ロックを保持し、3.3.6 の ruby と応答する bundler を置いた、確認をすべて通るプロジェクト
+    fn healthy(name: &str) -> (PathBuf, FluxProject, ProjectLock) {
+        let cwd = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        let project = FluxProject::init(&cwd, &Default::default(), json!({})).unwrap().0;
+        let lock = project_lock::acquire(&project.flux_dir, "arc sync", Duration::ZERO).unwrap();
+        let env_dir = cwd.join(ARC_ENV_DIR);
+        fs::create_dir_all(ruby_runtime_bin(&env_dir)).unwrap();
+        write_script(&ruby_bin(&env_dir), "case \"$1\" in -e) printf 3.3.6 ;; -c) echo 'Syntax OK' ;; esac");
+        fake_bundle(&cwd, "echo 'Bundler version 2.5.0'");
+        fs::write(cwd.join("Gemfile"), "gem 'rack'\n").unwrap();
+        (cwd, project, lock)
+    }
+
+    fn failed_check(project: &FluxProject, cwd: &Path) -> Failure {
+        check(project, cwd, &ArcConfig::default()).unwrap_err()
+    }
+
+    #[test]
+    fn test_healthy_project_passes() {
+        let (cwd, project, _lock) = healthy("arc_preflight_ok_test");
+        assert_eq!(check(&project, &cwd, &ArcConfig::default()), Ok(()));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_lock_not_held() {
+        let (cwd, project, lock) = healthy("arc_preflight_lock_test");
+        drop(lock);
+        assert_eq!(failed_check(&project, &cwd).check, LOCK);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_low_disk_space() {
+        let (cwd, project, _lock) = healthy("arc_preflight_disk_test");
+        set_free_bytes(Some(10 * 1024 * 1024));
+        let failure = failed_check(&project, &cwd);
+        set_free_bytes(None);
+        assert_eq!(failure.check, DISK);
+        assert!(failure.problem.contains("10.0 MiB") && failure.problem.contains("256.0 MiB"), "{}", failure.problem);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_ruby_missing_broken_or_mismatched() {
+        let (cwd, project, _lock) = healthy("arc_preflight_ruby_test");
+        let ruby = ruby_bin(&cwd.join(ARC_ENV_DIR));
+
+        write_script(&ruby, "printf 3.4.1");
+        let failure = failed_check(&project, &cwd);
+        assert_eq!((failure.check, failure.fix.as_str()), (RUBY, "`arc bootstrap 3.3.6`"));
+        assert!(failure.problem.contains("3.4.1"), "{}", failure.problem);
+
+        write_script(&ruby, "echo 'error while loading shared libraries: libruby.so.3.3' >&2; exit 127");
+        assert!(failed_check(&project, &cwd).problem.contains("実行できません"));
+
+        fs::remove_file(&ruby).unwrap();
+        assert!(failed_check(&project, &cwd).problem.contains("ランタイムがありません"));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_bundler_not_responding() {
+        let (cwd, project, _lock) = healthy("arc_preflight_bundler_test");
+        fake_bundle(&cwd, "exit 1");
+        assert_eq!(failed_check(&project, &cwd).check, BUNDLER);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_gemfile_syntax_error() {
+        let (cwd, project, _lock) = healthy("arc_preflight_gemfile_test");
+        let ruby = ruby_bin(&cwd.join(ARC_ENV_DIR));
+        write_script(&ruby, "case \"$1\" in -e) printf 3.3.6 ;; -c) echo 'Gemfile:1: unterminated string meets end of file' >&2; exit 1 ;; esac");
+        let failure = failed_check(&project, &cwd);
+        assert_eq!(failure.check, GEMFILE);
+        assert!(failure.problem.contains("Gemfile:1: unterminated string"), "{}", failure.problem);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_failure_is_recorded() {
+        let (cwd, project, lock) = healthy("arc_preflight_record_test");
+        drop(lock);
+        let err = run(&project, &cwd, &ArcConfig::default()).unwrap_err().to_string();
+        assert!(err.contains("--no-preflight") && err.contains("直すには"), "{}", err);
+        let last = project.read_signals().unwrap().pop().unwrap();
+        assert_eq!(last.r_type, "preflight_failed");
+        assert_eq!(last.payload["check"], LOCK);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+//! Spring / bootsnap など、Ruby のプリローダーとの協調。
+//!
+//! Spring のサーバーは起動したときの環境 (GEM_HOME・PATH) を持ち続けるため、`arc run` とシェルを行き来すると
+//! 別の環境のサーバーに繋がり、違う Gem が使われる。隔離環境で実行するとき、`bin/spring` があるか
+//! このプロジェクトの Spring が動いていれば:
+//!
+//! - 既定 (`[run] disable_spring = true`) は `DISABLE_SPRING=1` を設定する
+//! - `--spring` の場合は `SPRING_APPLICATION_ID` を arc の環境ごとの値にし、他の環境のサーバーと混ざらないようにする
+//!
+//! あわせて `BOOTSNAP_CACHE_DIR` を `.arc/env/bootsnap` にし、bootsnap のキャッシュを管理下の環境に置く。
+//! 決めた内容は start Signal の `env_context` に記録する。
+
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::RunConfig;
+use crate::signals::ARC_ENV_DIR;
+
+/// bootsnap のキャッシュの置き場所 (`.arc/env` からの相対パス)
+pub const BOOTSNAP_DIR: &str = "bootsnap";
+
+/// 実行中のプロセス (`/proc` から読む)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Process {
+    pub pid: u32,
+    /// NUL を空白に置き換えたコマンドライン (Spring は `$0` を `spring server | app | ...` に書き換える)
+    pub cmdline: String,
+    pub cwd: Option<PathBuf>,
+}
+
+/// Spring を見つけた根拠
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpringFound {
+    Binstub,
+    Process,
+}
+
+/// Spring の扱い
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spring {
+    /// Spring を使っていない
+    NotDetected,
+    /// `DISABLE_SPRING=1`
+    Disabled(SpringFound),
+    /// `SPRING_APPLICATION_ID` を arc の環境ごとにする (`--spring`)
+    Namespaced(SpringFound, String),
+    /// `[run] disable_spring = false`: 何もしない
+    Inherited(SpringFound),
+}
+
+/// 隔離環境で実行するときのプリローダーの設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preloaders {
+    pub spring: Spring,
+    pub bootsnap_cache_dir: PathBuf,
+}
+
+impl Preloaders {
+    /// `root` のプロジェクトについて決める。`keep_spring` は `--spring`。
+    /// `processes` (プロセスの一覧) は `bin/spring` がない場合だけ呼ぶ。
+    pub fn decide(root: &Path, config: &RunConfig, keep_spring: bool, processes: impl FnOnce() -> Vec<Process>) -> Self {
+        let env_dir = root.join(ARC_ENV_DIR);
+        let found = if root.join("bin/spring").is_file() {
+            Some(SpringFound::Binstub)
+        } else if spring_running(&processes(), root) {
+            Some(SpringFound::Process)
+        } else {
+            None
+        };
+        let spring = match found {
+            None => Spring::NotDetected,
+            Some(found) if keep_spring => Spring::Namespaced(found, application_id(&env_dir)),
+            Some(found) if config.disable_spring => Spring::Disabled(found),
+            Some(found) => Spring::Inherited(found),
+        };
+        Self { spring, bootsnap_cache_dir: env_dir.join(BOOTSNAP_DIR) }
+    }
+
+    /// コマンドに設定する環境変数
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![("BOOTSNAP_CACHE_DIR", self.bootsnap_cache_dir.to_string_lossy().to_string())];
+        match &self.spring {
+            Spring::Disabled(_) => vars.push(("DISABLE_SPRING", "1".to_string())),
+            Spring::Namespaced(_, id) => vars.push(("SPRING_APPLICATION_ID", id.clone())),
+            Spring::NotDetected | Spring::Inherited(_) => {}
+        }
+        vars
+    }
+
+    /// start Signal の `env_context` に加えるフィールド
+    pub fn context_fields(&self) -> Value {
+        let found = |found: &SpringFound| match found {
+            SpringFound::Binstub => "bin/spring",
+            SpringFound::Process => "process",
+        };
+        let spring = match &self.spring {
+            Spring::NotDetected => json!({ "mode": "not_detected" }),
+            Spring::Disabled(f) => json!({ "mode": "disabled", "detected": found(f) }),
+            Spring::Namespaced(f, id) => json!({ "mode": "namespaced", "detected": found(f), "application_id": id }),
+            Spring::Inherited(f) => json!({ "mode": "inherited", "detected": found(f) }),
+        };
+        json!({ "spring": spring, "BOOTSNAP_CACHE_DIR": format!("{}/{}", ARC_ENV_DIR, BOOTSNAP_DIR) })
+    }
+}
+
+/// arc の環境ごとの Spring のアプリケーション ID (環境ディレクトリの絶対パスから作る)
+fn application_id(env_dir: &Path) -> String {
+    format!("arc-{}", &crate::blobs::sha256_hex(env_dir.to_string_lossy().as_bytes())[..16])
+}
+
+/// `root` で動いている Spring のサーバー・アプリケーションのプロセスがあるか
+pub fn spring_running(processes: &[Process], root: &Path) -> bool {
+    processes.iter().any(|p| {
+        (p.cmdline.starts_with("spring server") || p.cmdline.starts_with("spring app")) && p.cwd.as_deref() == Some(root)
+    })
+}
+
+/// Gemfile.lock に spring があるプロジェクトだけ、プロセスの一覧を読む (それ以外では `/proc` を走査しない)。
+pub fn processes_if_locked(root: &Path) -> Vec<Process> {
+    let locked = fs::read_to_string(root.join("Gemfile.lock"))
+        .is_ok_and(|lock| lock.lines().any(|l| l.trim_start().starts_with("spring (")));
+    if locked { process_table() } else { Vec::new() }
+}
+
+/// `/proc` から現在のプロセスを読む。読めないプロセス (他のユーザーなど) は cwd を `None` にする。
+pub fn process_table() -> Vec<Process> {
+    let Ok(entries) = fs::read_dir("/proc") else { return vec![] };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let cmdline = fs::read(entry.path().join("cmdline")).ok()?;
+            let cmdline = String::from_utf8_lossy(&cmdline).replace('\0', " ").trim().to_string();
+            Some(Process { pid, cmdline, cwd: fs::read_link(entry.path().join("cwd")).ok() })
+        })
+        .collect()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(name: &str, binstub: bool) -> PathBuf {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("bin")).unwrap();
+        if binstub {
+            fs::write(root.join("bin/spring"), "#!/usr/bin/env ruby\n").unwrap();
+        }
+        root
+    }
+
+    fn process(pid: u32, cmdline: &str, cwd: &Path) -> Process {
+        Process { pid, cmdline: cmdline.to_string(), cwd: Some(cwd.to_path_buf()) }
+    }
+
+    fn env(preloaders: &Preloaders) -> Vec<(&'static str, String)> {
+        preloaders.env().into_iter().filter(|(k, _)| *k != "BOOTSNAP_CACHE_DIR").collect()
+    }
+
+    #[test]
+    fn test_spring_running_matches_project_cwd() {
+        let root = Path::new("/srv/shop");
+        let table = [
+            process(10, "spring server | shop | started 3 mins ago", Path::new("/srv/other")),
+            process(11, "ruby bin/rails server", root),
+            Process { pid: 12, cmdline: "spring app    | shop | started 1 min ago | development mode".to_string(), cwd: None },
+        ];
+        assert!(!spring_running(&table, root));
+        let table = [process(13, "spring app    | shop | started 1 min ago | development mode", root)];
+        assert!(spring_running(&table, root));
+        assert!(!spring_running(&[], root));
+    }
+
+    #[test]
+    fn test_env_for_each_config() {
+        let root = project("arc_preloader_binstub_test", true);
+        let mut config = RunConfig::default();
+
+        // 既定: bin/spring があれば無効にする
+        let decided = Preloaders::decide(&root, &config, false, Vec::new);
+        assert_eq!(decided.spring, Spring::Disabled(SpringFound::Binstub));
+        assert_eq!(env(&decided), [("DISABLE_SPRING", "1".to_string())]);
+        assert_eq!(decided.env()[0], ("BOOTSNAP_CACHE_DIR", root.join(".arc/env/bootsnap").to_string_lossy().to_string()));
+        assert_eq!(decided.context_fields()["spring"], json!({ "mode": "disabled", "detected": "bin/spring" }));
+        assert_eq!(decided.context_fields()["BOOTSNAP_CACHE_DIR"], ".arc/env/bootsnap");
+
+        // --spring: 環境ごとの ID。同じ環境なら同じ値
+        let namespaced = Preloaders::decide(&root, &config, true, Vec::new);
+        let Spring::Namespaced(SpringFound::Binstub, id) = &namespaced.spring else { panic!("{:?}", namespaced.spring) };
+        assert!(id.starts_with("arc-") && id.len() == 20, "{}", id);
+        assert_eq!(env(&namespaced), [("SPRING_APPLICATION_ID", id.clone())]);
+        assert_eq!(Preloaders::decide(&root, &config, true, Vec::new), namespaced);
+        let other = project("arc_preloader_other_test", true);
+        assert_ne!(Preloaders::decide(&other, &config, true, Vec::new).spring, namespaced.spring);
+
+        // disable_spring = false: 触らない
+        config.disable_spring = false;
+        let inherited = Preloaders::decide(&root, &config, false, Vec::new);
+        assert_eq!(inherited.spring, Spring::Inherited(SpringFound::Binstub));
+        assert!(env(&inherited).is_empty());
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_dir_all(&other).unwrap();
+    }
+
+    #[test]
+    fn test_spring_detected_from_process_only() {
+        let root = project("arc_preloader_process_test", false);
+        let config = RunConfig::default();
+        let none = Preloaders::decide(&root, &config, false, Vec::new);
+        assert_eq!(none.spring, Spring::NotDetected);
+        assert!(env(&none).is_empty());
+        assert_eq!(none.context_fields()["spring"]["mode"], "not_detected");
+
+        let table = [process(42, "spring server | app | started 1 hour ago", &root)];
+        let decided = Preloaders::decide(&root, &config, false, || table.to_vec());
+        assert_eq!(decided.spring, Spring::Disabled(SpringFound::Process));
+        assert_eq!(decided.context_fields()["spring"]["detected"], "process");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
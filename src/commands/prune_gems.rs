@@ -0,0 +1,59 @@
+//! `arc prune-gems`: Gemfile.lock から外れた Gem を `.arc/env` から削除する。
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::path::Path;
+
+use crate::config::ArcConfig;
+use crate::display;
+use crate::lockfile;
+use crate::prune;
+use crate::signals::{FluxProject, SignalType};
+
+use super::context::CommandContext;
+use super::runner;
+
+pub fn prune_gems(ctx: &CommandContext, dry_run: bool) -> Result<()> {
+    let project = ctx.project().context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    prune_at(project, ctx.root()?, dry_run || ctx.dry_run)
+}
+
+/// Gemfile.lock から外れた Gem を `.arc/env` から削除し、prune Signal を記録する。
+pub(super) fn prune_at(project: &FluxProject, cwd: &Path, dry_run: bool) -> Result<()> {
+    let lock_path = cwd.join("Gemfile.lock");
+    if !lock_path.exists() {
+        anyhow::bail!("Gemfile.lock が見つかりません。先に `arc sync` を実行してください。");
+    }
+    let config = ArcConfig::load(&project.flux_dir)?;
+    let api = crate::config::ruby_api_version(&config.ruby.version);
+    let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+    let gem_base = env_dir.join("ruby").join(&api);
+
+    let required = prune::required_set(&lockfile::parse(&lock_path)?);
+    let protected = prune::default_gem_names(&runner::ruby_runtime_root(&env_dir), &api);
+    let orphans = prune::find_orphans(&gem_base, &required, &protected);
+    if orphans.is_empty() {
+        eprintln!("✅ No unused gems in {}", crate::signals::ARC_ENV_DIR);
+        return Ok(());
+    }
+
+    let total: u64 = orphans.iter().map(|o| o.bytes).sum();
+    for orphan in &orphans {
+        eprintln!("   - {} ({})", orphan.dir_name, display::fmt_bytes(orphan.bytes));
+    }
+    if dry_run {
+        eprintln!("🔍 dry-run: {} gem(s) would be purged, reclaiming {}", display::fmt_count(orphans.len() as u64), display::fmt_bytes(total));
+        return Ok(());
+    }
+
+    let reclaimed = prune::purge(&gem_base, &orphans)?;
+    project.record(
+        SignalType::Prune,
+        json!({
+            "gems": orphans.iter().map(|o| &o.dir_name).collect::<Vec<_>>(),
+            "bytes": reclaimed,
+        }),
+    )?;
+    eprintln!("🧹 Purged {} gem(s), reclaimed {}", display::fmt_count(orphans.len() as u64), display::fmt_bytes(reclaimed));
+    Ok(())
+}
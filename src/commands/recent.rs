@@ -0,0 +1,170 @@
+//! `arc recent`: `~/.arc/projects.toml` に登録されたすべてのプロジェクトの直近の Signal を
+//! 1 つの時系列にまとめる。
+//!
+//! 各プロジェクトのログは末尾の数 KB (`signals::TAIL_READ_BYTES`) だけを読むため、
+//! ログが大きくなっても、登録数が増えても遅くならない。
+
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::display;
+use crate::registry::{self, Registry};
+use crate::signals::{self, FluxProject, Signal};
+
+use super::context::CommandContext;
+
+pub fn recent(ctx: &CommandContext, limit: usize, json: bool) -> Result<()> {
+    let gathered = gather(&Registry::load_from(&registry::registry_path()?)?, limit);
+    for path in &gathered.missing {
+        ctx.reporter.human(&format!("⚠️  Skipped {} (project no longer exists)", path.display()));
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&gathered.entries)?);
+    } else if gathered.entries.is_empty() {
+        ctx.reporter.human("No recent activity in registered projects.");
+    } else {
+        for line in lines(&gathered.entries) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// プロジェクト名付きの Signal
+#[derive(Debug, Serialize)]
+pub struct RecentEntry {
+    pub project: String,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub signal: Signal,
+}
+
+/// 集めた Signal と、読めなかったプロジェクト
+#[derive(Debug, Default)]
+pub struct Recent {
+    /// 新しい順
+    pub entries: Vec<RecentEntry>,
+    /// ディレクトリ (または `.flux/`) がなくなったプロジェクト
+    pub missing: Vec<PathBuf>,
+}
+
+/// 登録されたプロジェクトの末尾の Signal を新しい順に並べ、`limit` 件に切り詰める。
+pub fn gather(registry: &Registry, limit: usize) -> Recent {
+    let mut recent = Recent::default();
+    for entry in &registry.projects {
+        let Ok(project) = FluxProject::open(&entry.path) else {
+            recent.missing.push(entry.path.clone());
+            continue;
+        };
+        let name = entry.name.clone().unwrap_or_else(|| dir_name(&entry.path));
+        let signals = project.read_tail(signals::TAIL_READ_BYTES).unwrap_or_default();
+        recent.entries.extend(signals.into_iter().rev().take(limit).map(|signal| RecentEntry {
+            project: name.clone(),
+            path: entry.path.clone(),
+            signal,
+        }));
+    }
+    // 時刻の書式はすべて RFC 3339 だが、タイムゾーンが違いうるので時刻として比べる
+    recent.entries.sort_by_key(|e| std::cmp::Reverse(chrono::DateTime::parse_from_rfc3339(&e.signal.timestamp).ok()));
+    recent.entries.truncate(limit);
+    recent
+}
+
+/// 1 Signal 1 行で整形する: 時刻・プロジェクト・種類・内容。
+pub fn lines(entries: &[RecentEntry]) -> Vec<String> {
+    let width = entries.iter().map(|e| e.project.chars().count()).max().unwrap_or(0);
+    entries
+        .iter()
+        .map(|e| {
+            let line = format!(
+                "{}  {:<width$}  {:<11}  {}",
+                display::fmt_timestamp(&e.signal.timestamp),
+                e.project,
+                e.signal.r_type,
+                summary(&e.signal),
+            );
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Signal の内容の要約 (Gem 名・コマンド・タスク名・設定の変更)。
+pub(super) fn summary(signal: &Signal) -> String {
+    let p = &signal.payload;
+    if signal.r_type == "config_set" {
+        let old = p["old"].as_str().unwrap_or("(unset)");
+        return format!("{}: {} → {}", p["key"].as_str().unwrap_or("?"), old, p["new"].as_str().unwrap_or("?"));
+    }
+    if let Some(gem) = p["gem"].as_str() {
+        return p["version"].as_str().map_or(gem.to_string(), |v| format!("{} ({})", gem, v));
+    }
+    if let Some(task) = p["task"].as_str() {
+        return format!("task {}", task);
+    }
+    if let Some(command) = p["command"].as_str() {
+        let args: Vec<String> = p["args"]
+            .as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+        return signals::truncate_display(&display::fmt_cmd(command, &args), 60);
+    }
+    String::new()
+}
+
+pub(super) fn dir_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().to_string())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::{RecordOptions, SignalType};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_merges_projects_newest_first() {
+        let root = std::env::temp_dir().join("arc_recent_test");
+        let _ = fs::remove_dir_all(&root);
+        let mut registry = Registry::default();
+        // 3 つのプロジェクトに、ずらした時刻で交互に記録する (init の時刻より後)
+        for (i, name) in ["api", "web", "worker"].iter().enumerate() {
+            let dir = root.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            let project = FluxProject::init(&dir, &Default::default(), json!({})).unwrap().0;
+            for minute in 0..3 {
+                let ts = format!("2099-01-0{}T10:{:02}:00+09:00", minute + 1, i * 10);
+                project
+                    .record_with(
+                        SignalType::Add,
+                        json!({ "gem": format!("{}{}", name, minute) }),
+                        RecordOptions { timestamp: Some(ts), ..Default::default() },
+                    )
+                    .unwrap();
+            }
+            registry.touch(&dir, (i == 0).then(|| "API".to_string()));
+        }
+        registry.touch(&root.join("gone"), None);
+
+        let recent = gather(&registry, 4);
+        let gems: Vec<&str> = recent.entries.iter().map(|e| e.signal.payload["gem"].as_str().unwrap()).collect();
+        assert_eq!(gems, ["worker2", "web2", "api2", "worker1"]);
+        assert_eq!(recent.entries[2].project, "API");
+        assert_eq!(recent.entries[0].project, "worker");
+        assert_eq!(recent.missing, [root.join("gone")]);
+
+        // init を含め、全件でも各プロジェクト 4 件ずつ
+        assert_eq!(gather(&registry, 100).entries.len(), 12);
+        assert!(gather(&registry, 0).entries.is_empty());
+        let printed = lines(&recent.entries);
+        assert_eq!(printed[0], "2099-01-03 10:20  worker  add          worker2");
+        assert_eq!(printed[2], "2099-01-03 10:00  API     add          api2");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+//! `arc remove`: Gemfile から Gem を外して記録する。
+//! Gemfile を書き換えて Signal を記録する処理 (`edit_and_record` など) は add / undo からも使う。
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::gemfile;
+use crate::gemfile_hash;
+use crate::intent;
+use crate::prompt;
+use crate::signals::{FluxProject, SignalType};
+
+use super::context::CommandContext;
+use super::exec::merge_fields;
+use super::gemfile::{check_gemfile, gemfile_issue_fields};
+use super::guard_mutation;
+use super::prune_gems::prune_at;
+use super::sync::install_after_edit;
+
+// ─────────────────────────────────────────────
+// arc remove
+// ─────────────────────────────────────────────
+
+pub fn remove(ctx: &CommandContext, gem_name: Option<&str>, yes: bool, purge: bool, dry_run: bool) -> Result<()> {
+    let dry_run = dry_run || ctx.dry_run;
+    let cwd = ctx.root()?;
+    let project = ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+
+    let gemfile_path = cwd.join("Gemfile");
+    if !gemfile_path.exists() {
+        anyhow::bail!("Gemfile が見つかりません。");
+    }
+    if !dry_run {
+        guard_mutation(project, cwd, yes)?;
+    }
+
+    let targets = match gem_name {
+        Some(name) => vec![name.to_string()],
+        None => {
+            // スクリプトから呼ばれた場合にハングしないよう、TTY でなければ従来通りエラーにする
+            if !std::io::stdin().is_terminal() {
+                anyhow::bail!("削除する Gem 名を指定してください。Usage: arc remove <gem>");
+            }
+            let selected = pick_gems_to_remove(&gemfile_path, yes)?;
+            if selected.is_empty() {
+                eprintln!("ℹ️  キャンセルしました。");
+                return Ok(());
+            }
+            selected
+        }
+    };
+
+    for gem in &targets {
+        ensure_declared(&gemfile_path, gem)?;
+    }
+
+    if dry_run {
+        let edits: Vec<gemfile::Edit> =
+            targets.iter().map(|gem| gemfile::Edit::RemoveGem { name: gem.clone() }).collect();
+        return print_gemfile_diff(&gemfile_path, &edits);
+    }
+
+    let external_edit = gemfile_hash::warn_if_modified(&project.read_signals()?, &gemfile_path);
+    let mut removed_any = false;
+    for gem in &targets {
+        removed_any |= remove_one(project, &gemfile_path, gem, external_edit)?;
+    }
+
+    if !removed_any {
+        return Ok(()); // 変更なし → install 不要
+    }
+
+    install_after_edit(project, cwd)?;
+    if purge {
+        prune_at(project, cwd, false)?;
+    }
+    Ok(())
+}
+
+/// `--dry-run`: 編集後の Gemfile との差分を stdout に出す。
+pub(super) fn print_gemfile_diff(gemfile_path: &Path, edits: &[gemfile::Edit]) -> Result<()> {
+    let diff = gemfile::preview(gemfile_path, edits)?;
+    if diff.is_empty() {
+        eprintln!("ℹ️  Gemfile に変更はありません。");
+    } else {
+        print!("{}", diff);
+    }
+    Ok(())
+}
+
+/// Gemfile の Gem を一覧表示して削除対象を選ばせる。`yes` でなければ最後に確認する。
+fn pick_gems_to_remove(gemfile_path: &Path, yes: bool) -> Result<Vec<String>> {
+    let entries = gemfile::parse(gemfile_path)?;
+    if entries.is_empty() {
+        anyhow::bail!("Gemfile に Gem が宣言されていません。");
+    }
+
+    let mut input = std::io::stdin().lock();
+    let mut out = std::io::stderr();
+    let selected = prompt::pick_gems(&entries, &mut input, &mut out)?;
+
+    if selected.is_empty() || yes {
+        return Ok(selected);
+    }
+    let question = format!("Remove {}?", selected.join(", "));
+    if prompt::confirm(&mut input, &mut out, &question)? {
+        Ok(selected)
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Gemfile から 1 つの Gem を削除し、remove Signal を記録する。削除できた場合は `true`。
+pub(super) fn remove_one(project: &FluxProject, gemfile_path: &Path, gem_name: &str, external_edit: bool) -> Result<bool> {
+    let lines = gem_lines(gemfile_path, gem_name);
+    let Some(after) = gemfile::edited(gemfile_path, &[gemfile::Edit::RemoveGem { name: gem_name.to_string() }])? else {
+        eprintln!("ℹ️  '{}' は Gemfile に見つかりませんでした。スキップします。", gem_name);
+        return Ok(false);
+    };
+
+    let issues = check_gemfile(&after)?;
+    let mut payload = json!({ "gem": gem_name, super::undo_check::LINES_KEY: super::undo_check::lines_value(&lines) });
+    merge_fields(&mut payload, gemfile_issue_fields(&issues));
+    edit_and_record(project, gemfile_path, &after, format!("arc remove {}", gem_name), SignalType::Remove, payload, external_edit)?;
+    eprintln!("➖ Removed '{}' from Gemfile", gem_name);
+
+    Ok(true)
+}
+
+/// Gemfile を `after` に書き換え、`signal_type` の Signal を記録する。
+/// 書き換えの前に `.flux/intent.json` を書き、記録できたら消す。途中で落ちても
+/// `arc doctor --resolve-intent` で Gemfile と Signal ログを揃えられる。
+pub(super) fn edit_and_record(
+    project: &FluxProject,
+    gemfile_path: &Path,
+    after: &str,
+    command: String,
+    signal_type: SignalType,
+    mut payload: Value,
+    external_edit: bool,
+) -> Result<()> {
+    let after_sha256 = crate::blobs::sha256_hex(after.as_bytes());
+    gemfile_hash::stamp_hash(&mut payload, Some(after_sha256.clone()), external_edit);
+    let id = project.next_signal_id()?;
+    let pending = intent::begin(
+        &project.flux_dir,
+        &intent::Intent::new(command, id.clone(), signal_type.to_string(), payload.clone(), gemfile_hash::hash(gemfile_path), after_sha256),
+    )?;
+
+    intent::fail_point(intent::BEFORE_EDIT)?;
+    write_gemfile(gemfile_path, after)?;
+    intent::fail_point(intent::AFTER_EDIT)?;
+    project.record_with_id(id, signal_type, payload)?;
+    pending.finish()
+}
+
+/// Gemfile を書き換える。途中で失敗しても壊れた Gemfile が残らないよう、同じディレクトリの一時ファイルに
+/// 書いてから rename する (パーミッションは元のファイルに合わせる)。
+fn write_gemfile(gemfile_path: &Path, content: &str) -> Result<()> {
+    let tmp = gemfile_path.with_file_name(format!(".Gemfile.arc-tmp-{}", std::process::id()));
+    let written = fs::write(&tmp, content)
+        .and_then(|_| match fs::metadata(gemfile_path) {
+            Ok(meta) => fs::set_permissions(&tmp, meta.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|_| fs::rename(&tmp, gemfile_path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written.with_context(|| format!("Gemfile の書き込みに失敗しました: {:?}", gemfile_path))
+}
+
+/// Gemfile の `gem_name` の行 (undo で手での編集を検出するために記録する)
+fn gem_lines(gemfile_path: &Path, gem_name: &str) -> Vec<gemfile::GemLine> {
+    let content = fs::read_to_string(gemfile_path).unwrap_or_default();
+    gemfile::GemfileDoc::parse(&content).gem_lines(gem_name)
+}
+
+/// `gem_name` が Gemfile に宣言されていなければ、宣言されている Gem から近い名前を添えてエラーにする。
+pub(super) fn ensure_declared(gemfile_path: &Path, gem_name: &str) -> Result<()> {
+    let content = fs::read_to_string(gemfile_path).context("Gemfile の読み込みに失敗しました")?;
+    if gemfile::GemfileDoc::parse(&content).contains(gem_name) {
+        return Ok(());
+    }
+    let declared = gemfile::parse_content(&content);
+    anyhow::bail!(
+        "'{}' は Gemfile に宣言されていません{}",
+        gem_name,
+        crate::suggest::hint(gem_name, declared.iter().map(|e| e.name.as_str()))
+    )
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_gem_names_suggest_closest() {
+        let cwd = std::env::temp_dir().join("arc_suggest_gem_test");
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        let gemfile_path = cwd.join("Gemfile");
+        fs::write(&gemfile_path, "source 'https://rubygems.org'\ngem 'nokogiri'\ngem 'rails'\n").unwrap();
+
+        ensure_declared(&gemfile_path, "rails").unwrap();
+        let err = ensure_declared(&gemfile_path, "nokigiri").unwrap_err().to_string();
+        assert_eq!(err, "'nokigiri' は Gemfile に宣言されていません (did you mean 'nokogiri'?)");
+        let err = ensure_declared(&gemfile_path, "sidekiq").unwrap_err().to_string();
+        assert_eq!(err, "'sidekiq' は Gemfile に宣言されていません");
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+//! `arc report`: arc 自体の不具合報告に添付する環境情報を 1 つのファイルにまとめる。
+//!
+//! バージョン・OS・設定・診断結果・直近の Signal・ディレクトリの状態・最後に失敗した
+//! install の出力を集め、Markdown (または JSON) で書き出す。ユーザー名・ホスト名・
+//! ホームディレクトリは `arc export --redact` と同じ `Redactor` で取り除く。
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::bundle::Redactor;
+use super::context::CommandContext;
+use super::{detach, path_check, runner};
+use crate::config::ArcConfig;
+use crate::display::fmt_bytes;
+use crate::gemfile_hash;
+use crate::link;
+use crate::perms;
+use crate::signals::{self, ARC_ENV_DIR, FluxProject, Signal};
+use crate::state::{FailureGroup, FluxState};
+
+/// `arc report`: 環境情報を集めて書き出す (既定では `default_path`)。
+pub fn report(ctx: &CommandContext, json: bool, output: Option<&Path>) -> Result<()> {
+    let cwd = ctx.root()?;
+    let gathered = gather(ctx.project()?, cwd, &signals::get_global_cache_dir()?, &Redactor::from_env())?;
+    let content = if json {
+        serde_json::to_string_pretty(&gathered)? + "\n"
+    } else {
+        to_markdown(&gathered)
+    };
+
+    let path = output.map(|p| cwd.join(p)).unwrap_or_else(|| default_path(cwd, json));
+    fs::write(&path, content).with_context(|| format!("Failed to write {:?}", path))?;
+    eprintln!("📝 Wrote report to {}", path.display());
+    eprintln!("⚠️  Usernames, hostnames and home paths were removed, but review the file before sharing it.");
+    Ok(())
+}
+
+/// 含める Signal の数
+pub const SIGNAL_LIMIT: usize = 50;
+/// 失敗した install の出力の末尾の行数
+const OUTPUT_TAIL_LINES: usize = 40;
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub arc: BuildInfo,
+    pub config: Option<Value>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub layout: Vec<LayoutEntry>,
+    pub failed_install: Option<FailedInstall>,
+    /// 失敗を原因とコマンドごとにまとめたもの (`FluxState::failed_summary`)
+    pub failures: Vec<FailureGroup>,
+    pub signals: Vec<Signal>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub profile: String,
+    pub os: String,
+    pub arch: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub check: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LayoutEntry {
+    pub path: String,
+    pub exists: bool,
+    /// シンボリックリンクの場合はリンク先
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedInstall {
+    pub id: String,
+    pub timestamp: String,
+    pub exit_code: Option<i64>,
+    /// 出力が残っていれば末尾
+    pub output_tail: Option<String>,
+}
+
+/// プロジェクトの情報を集める。文字列はすべて `redactor` を通してから格納する。
+pub fn gather(project: &FluxProject, cwd: &Path, cache_dir: &Path, redactor: &Redactor) -> Result<Report> {
+    let signals = project.read_signals()?;
+    let env_dir = cwd.join(ARC_ENV_DIR);
+    let config = ArcConfig::load(&project.flux_dir);
+
+    let mut diagnostics = Vec::new();
+    let mut diagnose = |check: &str, ok: bool, detail: String| {
+        diagnostics.push(Diagnostic { check: check.to_string(), ok, detail: redactor.redact_str(&detail) });
+    };
+    diagnose(
+        "config.toml",
+        config.is_ok(),
+        config.as_ref().err().map_or("ok".to_string(), |e| format!("{:#}", e)),
+    );
+    diagnose(
+        "signals.jsonl writable",
+        perms::writable(&project.signal_file),
+        project.signal_file.display().to_string(),
+    );
+    let modified = gemfile_hash::modified_since(&signals, &cwd.join("Gemfile"));
+    diagnose(
+        "Gemfile unchanged outside arc",
+        modified.is_none(),
+        modified.map_or("ok".to_string(), |since| format!("modified since {}", since)),
+    );
+    let regressed = signals::timestamp_regressions(&signals, signals::TIMESTAMP_REGRESSION_THRESHOLD_SECS);
+    diagnose("signal timestamps monotonic", regressed.is_empty(), format!("{} regression(s)", regressed.len()));
+    let inherited = std::env::var_os("PATH");
+    let dirs = runner::isolated_path_dirs(&env_dir, inherited.as_deref());
+    let expected = config.as_ref().ok().map(|c| c.ruby.version.as_str());
+    for r in path_check::check(&env_dir, &dirs, &[], expected) {
+        let detail = if r.anomalies.is_empty() {
+            r.path.as_ref().map_or("-".to_string(), |p| p.display().to_string())
+        } else {
+            r.anomalies.iter().map(|a| a.describe()).collect::<Vec<_>>().join("; ")
+        };
+        diagnose(&format!("PATH: {}", r.name), r.anomalies.is_empty(), detail);
+    }
+
+    let layout = [env_dir.clone(), runner::ruby_runtime_root(&env_dir), crate::binstubs::stub_dir(&env_dir), cache_dir.to_path_buf()]
+        .iter()
+        .map(|path| layout_entry(path, redactor))
+        .collect();
+
+    let failed_install = signals
+        .iter()
+        .rev()
+        .find(|s| s.r_type == "install_end" && s.payload["success"] == false)
+        .map(|end| {
+            let start_id = end.payload["ref_id"].as_str().unwrap_or(&end.id);
+            FailedInstall {
+                id: end.id.clone(),
+                timestamp: end.timestamp.clone(),
+                exit_code: end.payload["exit_code"].as_i64(),
+                output_tail: output_tail(&detach::output_dir(&project.flux_dir), start_id)
+                    .map(|t| redactor.redact_str(&t)),
+            }
+        });
+
+    let failures = FluxState::from_signals(&signals)
+        .failed_summary()
+        .into_iter()
+        .map(|g| FailureGroup {
+            command: redactor.redact_str(&g.command),
+            last_command_line: redactor.redact_str(&g.last_command_line),
+            ..g
+        })
+        .collect();
+
+    let recent = signals.len().saturating_sub(SIGNAL_LIMIT);
+    Ok(Report {
+        arc: BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            profile: if cfg!(debug_assertions) { "debug" } else { "release" }.to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        },
+        config: config.ok().and_then(|c| serde_json::to_value(&c).ok()).map(|v| redactor.redact_value(&v)),
+        diagnostics,
+        layout,
+        failed_install,
+        failures,
+        signals: signals[recent..].iter().map(|s| redactor.redact_signal(s)).collect(),
+    })
+}
+
+fn layout_entry(path: &Path, redactor: &Redactor) -> LayoutEntry {
+    LayoutEntry {
+        path: redactor.redact_str(&path.display().to_string()),
+        exists: path.exists(),
+        link: fs::read_link(path).ok().map(|t| redactor.redact_str(&t.display().to_string())),
+        bytes: link::tree_size(&fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())),
+    }
+}
+
+/// 出力ファイル (`.flux/output/<start_id>.{out,err}`、圧縮済みを含む) の末尾。
+fn output_tail(output_dir: &Path, start_id: &str) -> Option<String> {
+    let mut tail = String::new();
+    for ext in crate::output_store::STREAMS {
+        let Ok(Some(content)) = crate::output_store::read(output_dir, start_id, ext) else { continue };
+        let lines: Vec<&str> = content.lines().collect();
+        let from = lines.len().saturating_sub(OUTPUT_TAIL_LINES);
+        tail.push_str(&format!("==> {}.{} <==\n{}\n", start_id, ext, lines[from..].join("\n")));
+    }
+    (!tail.is_empty()).then_some(tail)
+}
+
+/// Markdown に整形する。
+pub fn to_markdown(report: &Report) -> String {
+    let mut md = String::from("# arc report\n\n");
+
+    md.push_str("## Environment\n\n");
+    md.push_str(&format!("- arc: {} ({})\n", report.arc.version, report.arc.profile));
+    md.push_str(&format!("- OS: {} / {}\n\n", report.arc.os, report.arc.arch));
+
+    md.push_str("## Config\n\n");
+    match report.config.as_ref().and_then(|c| toml::to_string_pretty(c).ok()) {
+        Some(toml) => md.push_str(&format!("```toml\n{}```\n\n", toml)),
+        None => md.push_str("(config.toml could not be read)\n\n"),
+    }
+
+    md.push_str("## Diagnostics\n\n| Check | Result | Detail |\n|---|---|---|\n");
+    for d in &report.diagnostics {
+        md.push_str(&format!("| {} | {} | {} |\n", d.check, if d.ok { "ok" } else { "FAIL" }, d.detail.replace('|', "\\|")));
+    }
+
+    md.push_str("\n## Layout\n\n| Path | Exists | Size | Link |\n|---|---|---|---|\n");
+    for entry in &report.layout {
+        md.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            entry.path,
+            if entry.exists { "yes" } else { "no" },
+            fmt_bytes(entry.bytes),
+            entry.link.as_deref().unwrap_or("")
+        ));
+    }
+
+    md.push_str("\n## Last failed install\n\n");
+    match &report.failed_install {
+        None => md.push_str("(none)\n"),
+        Some(f) => {
+            let code = f.exit_code.map_or("?".to_string(), |c| c.to_string());
+            md.push_str(&format!("- {} at {} (exit code {})\n", f.id, f.timestamp, code));
+            match &f.output_tail {
+                Some(tail) => md.push_str(&format!("\n```\n{}```\n", tail)),
+                None => md.push_str("- output: not captured\n"),
+            }
+        }
+    }
+
+    md.push_str("\n## Failures\n\n");
+    if report.failures.is_empty() {
+        md.push_str("(none)\n");
+    } else {
+        md.push_str("| Kind | Command | Count | Last | Last exit |\n|---|---|---|---|---|\n");
+        for g in &report.failures {
+            md.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                g.kind,
+                g.command.replace('|', "\\|"),
+                g.count,
+                g.last_at.map_or("?".to_string(), |ts| ts.to_rfc3339()),
+                g.last_exit_code.map_or("?".to_string(), |c| c.to_string())
+            ));
+        }
+    }
+
+    md.push_str(&format!("\n## Recent signals (last {})\n\n```jsonl\n", SIGNAL_LIMIT));
+    for signal in &report.signals {
+        if let Ok(line) = serde_json::to_string(signal) {
+            md.push_str(&line);
+            md.push('\n');
+        }
+    }
+    md.push_str("```\n");
+    md
+}
+
+/// 出力先を決める。指定がなければカレントディレクトリの `arc-report-<時刻>.{md,json}`。
+pub fn default_path(cwd: &Path, json: bool) -> PathBuf {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    cwd.join(format!("arc-report-{}.{}", stamp, if json { "json" } else { "md" }))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::SignalType;
+    use serde_json::json;
+
+    #[test]
+    fn test_report_sections_and_redaction() {
+        // フィクスチャのホームディレクトリ
+        let home = std::env::temp_dir().join("arc_report_home_test");
+        let _ = fs::remove_dir_all(&home);
+        let cwd = home.join("work/shop");
+        let cache = home.join(".arc/cache");
+        fs::create_dir_all(&cwd).unwrap();
+        fs::create_dir_all(cache.join("gems")).unwrap();
+        fs::write(cache.join("gems/a"), "x".repeat(10)).unwrap();
+
+        let project = FluxProject::init(&cwd, &Default::default(), json!({ "cwd": cwd })).unwrap().0;
+        project
+            .record(SignalType::InstallStart, json!({ "command": "bundle", "cwd": cwd, "env": { "SECRET": "s3cret" } }))
+            .unwrap();
+        let start = project.read_signals().unwrap().pop().unwrap();
+        project
+            .record(SignalType::InstallEnd, json!({ "ref_id": start.id, "exit_code": 5, "success": false }))
+            .unwrap();
+        let output = detach::output_dir(&project.flux_dir);
+        fs::create_dir_all(&output).unwrap();
+        fs::write(output.join(format!("{}.err", start.id)), format!("Errno::EACCES {}/work/shop/Gemfile.lock\n", home.display())).unwrap();
+
+        let redactor = Redactor::new(Some(home.display().to_string()), Some("alice".into()), None);
+        let report = gather(&project, &cwd, &cache, &redactor).unwrap();
+        let md = to_markdown(&report);
+
+        for section in ["## Environment", "## Config", "## Diagnostics", "## Layout", "## Last failed install", "## Failures", "## Recent signals"] {
+            assert!(md.contains(section), "{} missing", section);
+        }
+        assert!(md.contains("| PATH: ruby | FAIL |"));
+        assert!(md.contains("exit code 5"));
+        assert!(md.contains("Errno::EACCES ~/work/shop/Gemfile.lock"));
+        assert!(md.contains("| ~/.arc/cache | yes | 10 B |"), "{}", md);
+        assert!(!md.contains("s3cret"));
+        let json = serde_json::to_string(&report).unwrap();
+        for text in [&md, &json] {
+            assert!(!text.contains(&home.display().to_string()), "home path leaked");
+        }
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
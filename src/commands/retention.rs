@@ -0,0 +1,391 @@
+//! `[signals] retention_days`: 保存期間を過ぎた Signal をログから消す (プライバシーのため)。
+//!
+//! 変更するコマンド (sync / add など) を実行するたびに、1 日に 1 回だけ (`.flux/.last-retention-check`)
+//! ログの先頭の Signal の時刻を保存期間の境界と比べ、古ければログを書き換える。`arc gc --retention` は
+//! 日付の印に関係なく同じことをし、`--dry-run` なら消すものを表示するだけにする。
+//!
+//! 境界より古くても、State を組み立てるのに必要な Signal は残す:
+//!
+//! - `init`
+//! - 最後の `bootstrap` (今の Ruby)
+//! - 名前ごとに最新の `x-baseline-set` (`arc baseline`)
+//! - まだ `.flux/snapshots/` にあるスナップショットを使った `undo`
+//! - 残す end の start (境界をまたいだ実行の記録を半端にしない)
+//!
+//! 消した Signal だけが参照していた blob と、消した run_start の出力 (`.flux/output/`) も消し、
+//! 消した数を `retention_purge` Signal に記録する。
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::baseline;
+use super::detach;
+use crate::blobs;
+use crate::output_store;
+use crate::signals::{FluxProject, Signal, SignalType};
+use crate::snapshot;
+
+/// 最後に確認した時刻を書く印 (`.flux/.last-retention-check`)
+pub const MARKER: &str = ".last-retention-check";
+/// 自動の確認の間隔
+const CHECK_INTERVAL_HOURS: i64 = 24;
+
+/// 現在の時刻。`ARC_DETERMINISTIC=1` では次に記録する Signal の時刻 (止まった時計)
+pub fn now(project: &FluxProject) -> Result<DateTime<Utc>> {
+    if crate::deterministic::is_enabled() {
+        return Ok(crate::deterministic::timestamp(project.read_signals()?.len() as u64).to_utc());
+    }
+    Ok(Utc::now())
+}
+
+/// `now` から `days` 日前。これより前に記録された Signal が消す対象になる
+pub fn horizon(now: DateTime<Utc>, days: u64) -> DateTime<Utc> {
+    now - chrono::Duration::days(days as i64)
+}
+
+/// 境界より古くても残す Signal の種別 (最後のものだけを残すかは `keep_mask` で決める)
+fn is_structural(signal: &Signal) -> bool {
+    matches!(signal.r_type.as_str(), "init" | "bootstrap") || baseline::is_baseline(signal)
+}
+
+/// 消すかどうかを時刻で決められない (時刻を解釈できない) Signal は残す
+fn is_old(signal: &Signal, horizon: DateTime<Utc>) -> bool {
+    DateTime::parse_from_rfc3339(&signal.timestamp).is_ok_and(|ts| ts < horizon)
+}
+
+/// 各 Signal を残すかどうか。`snapshots` は `.flux/snapshots/` にあるスナップショットのダイジェスト。
+pub fn keep_mask(signals: &[Signal], horizon: DateTime<Utc>, snapshots: &HashSet<String>) -> Vec<bool> {
+    let last_bootstrap = signals.iter().rposition(|s| s.r_type == "bootstrap");
+    let mut keep: Vec<bool> = signals
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            !is_old(s, horizon)
+                || s.r_type == "init"
+                || Some(i) == last_bootstrap
+                || (s.r_type == "undo"
+                    && s.payload["restore"]["snapshot"].as_str().is_some_and(|digest| snapshots.contains(digest)))
+        })
+        .collect();
+    for position in baseline::latest_positions(signals) {
+        keep[position] = true;
+    }
+    // 残す end が参照する start は残す
+    let starts: HashSet<&str> = signals
+        .iter()
+        .zip(&keep)
+        .filter(|(s, kept)| **kept && s.r_type.ends_with("_end"))
+        .filter_map(|(s, _)| s.payload["ref_id"].as_str())
+        .collect();
+    for (signal, kept) in signals.iter().zip(keep.iter_mut()) {
+        if starts.contains(signal.id.as_str()) {
+            *kept = true;
+        }
+    }
+    keep
+}
+
+/// 消す内容 (`prepare`) と、消した結果
+#[derive(Debug, Clone, Default)]
+pub struct Purge {
+    pub retention_days: u64,
+    pub horizon: String,
+    /// 書き換えた後のログ
+    content: String,
+    /// 書き換える前のログの長さ (追記されていないかの確認用)
+    original_len: u64,
+    pub kept: usize,
+    /// 消す Signal の数 (種別ごと)
+    pub dropped: BTreeMap<String, usize>,
+    /// 消す blob (ハッシュ)
+    pub blobs: Vec<String>,
+    pub blob_bytes: u64,
+    /// 消す出力のファイル
+    pub outputs: Vec<PathBuf>,
+    pub output_bytes: u64,
+}
+
+impl Purge {
+    pub fn dropped_total(&self) -> usize {
+        self.dropped.values().sum()
+    }
+
+    /// `retention_purge` Signal の payload
+    pub fn to_json(&self) -> Value {
+        json!({
+            "retention_days": self.retention_days,
+            "horizon": self.horizon,
+            "dropped": self.dropped_total(),
+            "dropped_by_type": self.dropped,
+            "kept": self.kept,
+            "blobs_removed": self.blobs.len(),
+            "blob_bytes": self.blob_bytes,
+            "outputs_removed": self.outputs.len(),
+            "output_bytes": self.output_bytes,
+        })
+    }
+}
+
+/// ログを読み、`horizon` より古い Signal を消した場合の内容を求める (まだ何も変更しない)。
+/// JSON として読めない行は判断できないため残す。
+pub fn prepare(project: &FluxProject, retention_days: u64, horizon: DateTime<Utc>) -> Result<Purge> {
+    let content = match fs::read_to_string(&project.signal_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("{:?} を読み込めません", project.signal_file)),
+    };
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let parsed: Vec<Option<Signal>> = lines.iter().map(|l| serde_json::from_str(l.trim_end()).ok()).collect();
+    let signals: Vec<Signal> = parsed.iter().flatten().cloned().collect();
+    let snapshots = snapshot_digests(&project.flux_dir);
+    let mut keep = keep_mask(&signals, horizon, &snapshots).into_iter();
+
+    let mut purge = Purge {
+        retention_days,
+        horizon: horizon.to_rfc3339(),
+        original_len: content.len() as u64,
+        ..Default::default()
+    };
+    let mut kept_refs = BTreeSet::new();
+    let mut dropped_refs = BTreeSet::new();
+    let mut dropped_ids = HashSet::new();
+    for (line, signal) in lines.iter().zip(&parsed) {
+        let Some(signal) = signal else {
+            purge.content.push_str(line);
+            continue;
+        };
+        if keep.next().unwrap_or(true) {
+            purge.content.push_str(line);
+            purge.kept += 1;
+            blobs::collect_refs(&signal.payload, &mut kept_refs);
+        } else {
+            *purge.dropped.entry(signal.r_type.clone()).or_default() += 1;
+            blobs::collect_refs(&signal.payload, &mut dropped_refs);
+            dropped_ids.insert(signal.id.as_str());
+        }
+    }
+
+    for hash in dropped_refs.difference(&kept_refs) {
+        let path = project.blobs_dir().join(hash);
+        if let Ok(meta) = fs::metadata(&path) {
+            purge.blob_bytes += meta.len();
+            purge.blobs.push(hash.to_string());
+        }
+    }
+    for capture in output_store::scan(&detach::output_dir(&project.flux_dir))? {
+        if dropped_ids.contains(capture.id.as_str()) {
+            purge.output_bytes += capture.bytes;
+            purge.outputs.extend(capture.files);
+        }
+    }
+    Ok(purge)
+}
+
+fn snapshot_digests(flux_dir: &Path) -> HashSet<String> {
+    let Ok(entries) = fs::read_dir(snapshot::snapshots_dir(flux_dir)) else { return HashSet::new() };
+    entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_string_lossy().split('.').next().map(String::from))
+        .collect()
+}
+
+/// `prepare` の内容でログを書き換え、参照されなくなった blob と出力を消して `retention_purge` を記録する。
+/// ロックを取らない exec / run が途中で追記した場合は、その行を失わないよう読み直してやり直す。
+pub fn apply(project: &FluxProject, purge: Purge) -> Result<Purge> {
+    let (retention_days, horizon) = (purge.retention_days, DateTime::parse_from_rfc3339(&purge.horizon)?.to_utc());
+    let mut prepared = Some(purge);
+    crate::fs_util::rewrite_with_retry(&project.signal_file, || {
+        let purge = match prepared.take() {
+            Some(purge) => purge,
+            None => prepare(project, retention_days, horizon)?,
+        };
+        if purge.dropped_total() == 0 {
+            return Ok(Some(purge));
+        }
+        let original_len = purge.original_len;
+        if !crate::fs_util::replace_unless_appended(&project.signal_file, &purge.content, original_len, "retention")? {
+            return Ok(None);
+        }
+        for hash in &purge.blobs {
+            let path = project.blobs_dir().join(hash);
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+        }
+        for path in &purge.outputs {
+            fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))?;
+        }
+        project.record(SignalType::RetentionPurge, purge.to_json())?;
+        Ok(Some(purge))
+    })
+}
+
+/// ログの先頭の (残す種別ではない) Signal の時刻。先頭だけを読むため、ログが大きくても安い
+pub fn oldest_timestamp(signal_file: &Path) -> Result<Option<DateTime<Utc>>> {
+    let Ok(file) = fs::File::open(signal_file) else { return Ok(None) };
+    for line in BufReader::new(file).lines() {
+        let Ok(signal) = serde_json::from_str::<Signal>(&line?) else { continue };
+        if !is_structural(&signal) {
+            return Ok(DateTime::parse_from_rfc3339(&signal.timestamp).ok().map(|ts| ts.to_utc()));
+        }
+    }
+    Ok(None)
+}
+
+/// 前回の確認から `CHECK_INTERVAL_HOURS` 以上たっていれば、印を `now` に更新して `true`
+pub fn check_due(flux_dir: &Path, now: DateTime<Utc>) -> Result<bool> {
+    let marker = flux_dir.join(MARKER);
+    let last = fs::read_to_string(&marker).ok().and_then(|s| DateTime::parse_from_rfc3339(s.trim()).ok()).map(|last| last.to_utc());
+    if last.is_some_and(|last| now - last < chrono::Duration::hours(CHECK_INTERVAL_HOURS)) {
+        return Ok(false);
+    }
+    fs::write(&marker, format!("{}\n", now.to_rfc3339())).with_context(|| format!("Failed to write {:?}", marker))?;
+    Ok(true)
+}
+
+/// 変更するコマンドの前に呼ぶ。`retention_days` が設定され、その日まだ確認しておらず、
+/// 先頭の Signal が境界より古ければ消す。消したときだけ結果を返す。
+pub fn auto(project: &FluxProject, retention_days: Option<u64>, now: DateTime<Utc>) -> Result<Option<Purge>> {
+    let Some(days) = retention_days else { return Ok(None) };
+    if !check_due(&project.flux_dir, now)? {
+        return Ok(None);
+    }
+    let horizon = horizon(now, days);
+    if oldest_timestamp(&project.signal_file)?.is_none_or(|ts| ts >= horizon) {
+        return Ok(None);
+    }
+    let purge = apply(project, prepare(project, days, horizon)?)?;
+    Ok((purge.dropped_total() > 0).then_some(purge))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deterministic;
+    use crate::signals::RecordOptions;
+    use crate::state::FluxState;
+
+    /// 止まった時計で `day` 日目の時刻
+    fn day(day: i64) -> DateTime<Utc> {
+        deterministic::timestamp(0).to_utc() + chrono::Duration::days(day)
+    }
+
+    /// Signal が 1 件もないプロジェクト (各テストが時刻を指定して init から記録する)
+    fn scratch(name: &str) -> (PathBuf, FluxProject) {
+        let (root, project) = crate::signals::temp_project(name);
+        fs::write(&project.signal_file, "").unwrap();
+        (root, project)
+    }
+
+    fn record(project: &FluxProject, r_type: SignalType, at: i64, payload: Value) -> Signal {
+        let options = RecordOptions { timestamp: Some(day(at).to_rfc3339()), ..Default::default() };
+        project.record_with(r_type, payload, options).unwrap()
+    }
+
+    fn run(project: &FluxProject, at: i64, ended: i64, command: &str) -> String {
+        let start = record(project, SignalType::RunStart, at, json!({ "command": command, "args": [], "cwd": "/p" }));
+        record(project, SignalType::RunEnd, ended, json!({ "ref_id": start.id, "exit_code": 0, "success": true }));
+        start.id
+    }
+
+    #[test]
+    fn test_purge_drops_old_signals_and_artifacts() {
+        let (root, project) = scratch("arc_retention_purge_test");
+        record(&project, SignalType::Init, 0, json!({ "path": "/p", "version": "0.1.0" }));
+        record(&project, SignalType::Bootstrap, 1, json!({ "ruby_version": "3.2.0" }));
+        let old_run = run(&project, 2, 2, "rake");
+        record(&project, SignalType::Bootstrap, 3, json!({ "ruby_version": "3.3.0" }));
+        record(&project, SignalType::Add, 4, json!({ "gem": "rack" }));
+        // 境界をまたいだ実行は start ごと残す
+        let spanning = run(&project, 9, 11, "sidekiq");
+        record(&project, SignalType::Undo, 5, json!({ "target_id": "x", "restore": { "path": "snapshot", "snapshot": "abc" } }));
+        record(&project, SignalType::Undo, 6, json!({ "target_id": "y", "restore": { "path": "snapshot", "snapshot": "gone" } }));
+        let recent_run = run(&project, 12, 12, "rspec");
+        let output = detach::output_dir(&project.flux_dir);
+        fs::create_dir_all(&output).unwrap();
+        for id in [&old_run, &spanning, &recent_run] {
+            fs::write(output.join(format!("{}.out", id)), "log\n").unwrap();
+        }
+        let snapshots = snapshot::snapshots_dir(&project.flux_dir);
+        fs::create_dir_all(&snapshots).unwrap();
+        fs::write(snapshots.join("abc.Gemfile.lock"), "GEM\n").unwrap();
+        // payload_budget を超える payload は blob に退避される
+        let big = "x".repeat(64 * 1024);
+        record(&project, SignalType::custom("test", "note").unwrap(), 3, json!({ "text": big }));
+        record(&project, SignalType::custom("test", "note").unwrap(), 13, json!({ "text": format!("{}y", big) }));
+        let blobs_before = fs::read_dir(project.blobs_dir()).unwrap().count();
+        assert_eq!(blobs_before, 2);
+
+        let purge = prepare(&project, 10, horizon(day(20), 10)).unwrap();
+        let dropped: Vec<(&str, usize)> = purge.dropped.iter().map(|(t, n)| (t.as_str(), *n)).collect();
+        assert_eq!(
+            dropped,
+            [("add", 1), ("bootstrap", 1), ("run_end", 1), ("run_start", 1), ("undo", 1), ("x-test-note", 1)]
+        );
+        assert_eq!((purge.blobs.len(), purge.outputs.len()), (1, 1));
+        // dry-run (prepare だけ) では何も変わらない
+        assert_eq!(project.read_signals().unwrap().len(), 14);
+
+        apply(&project, purge).unwrap();
+        let signals = project.read_signals().unwrap();
+        let types: Vec<&str> = signals.iter().map(|s| s.r_type.as_str()).collect();
+        assert_eq!(
+            types,
+            ["init", "bootstrap", "run_start", "run_end", "undo", "run_start", "run_end", "x-test-note", "retention_purge"]
+        );
+        let last = &signals[signals.len() - 1].payload;
+        assert_eq!((last["dropped"].as_u64(), last["kept"].as_u64()), (Some(6), Some(8)));
+        assert_eq!(last["outputs_removed"], 1);
+        assert_eq!(fs::read_dir(project.blobs_dir()).unwrap().count(), 1);
+        assert!(!output.join(format!("{}.out", old_run)).exists());
+        assert!(output.join(format!("{}.out", spanning)).exists());
+        assert!(output.join(format!("{}.out", recent_run)).exists());
+
+        // 残したログから State を組み立て直せる
+        let state = FluxState::from_signals(&signals);
+        assert_eq!(state.project_path.as_deref(), Some("/p"));
+        assert_eq!(state.ruby_installs.iter().map(|r| r.version.as_str()).collect::<Vec<_>>(), ["3.3.0"]);
+        let commands: Vec<&str> = state.executions.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["sidekiq", "rspec"]);
+
+        // 2 回目は消すものがない
+        assert_eq!(prepare(&project, 10, horizon(day(20), 10)).unwrap().dropped_total(), 0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_auto_checks_once_a_day() {
+        let (root, project) = scratch("arc_retention_auto_test");
+        record(&project, SignalType::Init, 0, json!({ "path": "/p" }));
+        record(&project, SignalType::Add, 1, json!({ "gem": "rack" }));
+        record(&project, SignalType::Add, 30, json!({ "gem": "puma" }));
+        assert_eq!(oldest_timestamp(&project.signal_file).unwrap(), Some(day(1)));
+        // 止まった時計は次に記録する Signal の時刻
+        assert_eq!(deterministic::scoped(0, || now(&project).unwrap()), deterministic::timestamp(3));
+
+        // 設定がなければ確認もしない
+        assert!(auto(&project, None, day(40)).unwrap().is_none());
+        assert!(!project.flux_dir.join(MARKER).exists());
+        // 境界より古い Signal がなければ確認の印だけを残す
+        assert!(auto(&project, Some(20), day(10)).unwrap().is_none());
+        assert!(project.flux_dir.join(MARKER).exists());
+
+        let purge = auto(&project, Some(20), day(40)).unwrap().unwrap();
+        assert_eq!(purge.dropped_total(), 1);
+        assert_eq!(oldest_timestamp(&project.signal_file).unwrap(), Some(day(30)));
+        // 同じ日のうちは確認しない
+        assert!(auto(&project, Some(1), day(40) + chrono::Duration::hours(12)).unwrap().is_none());
+        assert_eq!(auto(&project, Some(1), day(41)).unwrap().unwrap().dropped_total(), 1);
+        // retention_purge は実際の時刻で記録されるため、止まった時計では古くならない
+        let types: Vec<String> = project.read_signals().unwrap().into_iter().map(|s| s.r_type).collect();
+        assert_eq!(types, ["init", "retention_purge", "retention_purge"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
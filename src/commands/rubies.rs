@@ -0,0 +1,416 @@
+//! `arc bootstrap --installed` / `arc bootstrap --use <version>`: グローバルキャッシュにある Ruby の一覧と切り替え。
+//!
+//! キャッシュの `rubies/<version>-<os>-<arch>` のうち、この環境の OS / アーキテクチャのものを一覧にする。
+//! プロジェクトが使っている Ruby は、`ruby_runtime/bin/ruby` に `RUBY_VERSION` を問い合わせて判断する。
+//!
+//! `--use` はネットワークを使わずに、キャッシュ済みの Ruby へ ruby_runtime を差し替える。
+//! 新しい ruby_runtime を `ruby_runtime.new` に用意して実行できることを確かめてから入れ替え、
+//! 古いものはその後で消す。
+//!
+//! どちらの bootstrap も、最後に記録した Ruby より古いバージョンへの変更は `--allow-downgrade` か確認がなければ中止する。
+
+use anyhow::{Context, Result, bail};
+use serde_json::json;
+use std::fs;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use super::runner::{ruby_bin, ruby_runtime_root};
+use super::bootstrap::resolve_ruby_id;
+use super::context::CommandContext;
+use super::fsops::fmt_link_report;
+use super::sync::report_abi_mismatches;
+use crate::abi;
+use crate::cache_layout;
+use crate::config::{self, ArcConfig};
+use crate::display;
+use crate::link;
+use crate::progress;
+use crate::prompt;
+use crate::ruby_version;
+use crate::signals::{ARC_ENV_DIR, FluxProject, SignalType};
+use crate::state::{FluxState, RubyInstall};
+use crate::worktree;
+
+/// グローバルキャッシュにある Ruby
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedRuby {
+    pub version: String,
+    pub dir: PathBuf,
+    /// `.complete` がある (展開が最後まで終わっている)
+    pub complete: bool,
+    pub bytes: u64,
+}
+
+/// `rubies_root` (`~/.arc/cache/rubies`) にある、この環境向けの Ruby をバージョンの順に返す。
+pub fn cached_rubies(rubies_root: &Path) -> Vec<CachedRuby> {
+    // `<version>-<os>-<arch>` の `-<os>-<arch>`
+    let suffix = resolve_ruby_id("");
+    let Ok(entries) = fs::read_dir(rubies_root) else { return Vec::new() };
+    let mut rubies: Vec<CachedRuby> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| {
+            let name = e.file_name().to_str()?.to_string();
+            let version = name.strip_suffix(&suffix).filter(|v| !v.is_empty())?.to_string();
+            let dir = e.path();
+            Some(CachedRuby { version, complete: cache_layout::is_complete(&dir), bytes: link::tree_size(&dir), dir })
+        })
+        .collect();
+    rubies.sort_by_key(|r| version_key(&r.version));
+    rubies
+}
+
+/// `3.10.0` が `3.9.0` より後に来るよう、数値として比べるためのキー
+fn version_key(version: &str) -> (Vec<u64>, String) {
+    let numbers = version.split('.').map(|part| part.parse().unwrap_or(0)).collect();
+    (numbers, version.to_string())
+}
+
+/// `ruby` に `RUBY_VERSION` を問い合わせる。実行できなければ `None`。
+pub fn probe_version(ruby: &Path) -> Option<String> {
+    let output = std::process::Command::new(ruby).args(["-e", "print RUBY_VERSION"]).output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !version.is_empty()).then_some(version)
+}
+
+/// `arc bootstrap --installed`
+pub fn list_installed(ctx: &CommandContext, rubies_root: &Path) -> Result<()> {
+    let rubies = cached_rubies(rubies_root);
+    if rubies.is_empty() {
+        println!("No rubies in the global cache ({}).", rubies_root.display());
+        println!("   Run `arc bootstrap --cache-only <version>` to download one.");
+        return Ok(());
+    }
+    // プロジェクトの外では「使用中」を表示しない
+    let current = ctx.project().ok().and_then(|p| probe_version(&ruby_bin(&p.root.join(ARC_ENV_DIR))));
+    println!("💎 Rubies in the global cache ({}):", rubies_root.display());
+    let width = rubies.iter().map(|r| r.version.len()).max().unwrap_or(0);
+    for ruby in &rubies {
+        let in_use = current.as_deref() == Some(ruby.version.as_str());
+        let note = match (ruby.complete, in_use) {
+            (false, _) => "  (incomplete; run `arc bootstrap --cache-only` to fetch it again)",
+            (true, true) => "  ← this project",
+            (true, false) => "",
+        };
+        println!(
+            "  {} {:<width$}  {:>9}{}",
+            if in_use { "*" } else { " " },
+            ruby.version,
+            display::fmt_bytes(ruby.bytes),
+            note,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+/// ファイル・シンボリックリンク・ディレクトリのどれでも消す (なければ何もしない)
+fn remove_path(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return Ok(()),
+    }
+    .with_context(|| format!("Failed to remove {:?}", path))
+}
+
+/// `arc bootstrap --use <version>`: キャッシュ済みの Ruby にプロジェクトを切り替える。
+pub fn use_cached(ctx: &CommandContext, version: &str, rubies_root: &Path, allow_downgrade: bool, ignore_constraints: bool) -> Result<()> {
+    let project = ctx.project().context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    let (cwd, config) = (ctx.root()?, ctx.config()?);
+    let cache_dir = rubies_root.join(resolve_ruby_id(version));
+    if !cache_layout::is_complete(&cache_dir) {
+        bail!(
+            "Ruby {} はグローバルキャッシュにありません ({:?})\n  `arc bootstrap --installed` でキャッシュ済みのバージョンを確認するか、`arc bootstrap {}` で取得してください",
+            version,
+            cache_dir,
+            version
+        );
+    }
+    project.ensure_env_usable()?;
+
+    let env_dir = cwd.join(ARC_ENV_DIR);
+    let ruby_dest = ruby_runtime_root(&env_dir);
+    let previous = probe_version(&ruby_bin(&env_dir));
+    if previous.as_deref() == Some(version) {
+        progress::human(&format!("ℹ️  This project already uses Ruby {}", version));
+        return Ok(());
+    }
+    let downgraded_from = guard_downgrade_on_terminal(project, version, allow_downgrade)?;
+    let ruby_constraints = super::ruby_constraints::guard(cwd, version, ignore_constraints)?;
+    if ctx.dry_run {
+        crate::dry_run::note(&format!(
+            "would switch ruby_runtime from {} to Ruby {} ({})",
+            previous.as_deref().unwrap_or("(none)"),
+            version,
+            cache_dir.display()
+        ));
+        return Ok(());
+    }
+
+    let timer = std::time::Instant::now();
+    let shared_base = worktree::shared_dir(&config.env, cwd, &crate::signals::get_global_arc_dir()?);
+    let shared = shared_base.as_ref().map(|base| worktree::shared_runtime(base, &resolve_ruby_id(version)));
+
+    // 1. 新しい ruby_runtime を隣に用意して、実行できることを確かめる
+    let staged = env_dir.join("ruby_runtime.new");
+    remove_path(&staged)?;
+    let link_mode = config.cache.link_mode;
+    let linked = worktree::place_runtime(&cache_dir, &staged, shared.as_deref(), link_mode)?;
+    let probed = probe_version(&staged.join("bin").join("ruby"));
+    if probed.as_deref() != Some(version) {
+        remove_path(&staged)?;
+        bail!(
+            "キャッシュの Ruby {} を実行できません (RUBY_VERSION: {})。ruby_runtime は変更していません",
+            version,
+            probed.as_deref().unwrap_or("取得できません")
+        );
+    }
+
+    // 2. 入れ替える。古い ruby_runtime は新しいものを置いてから消す
+    let retired = env_dir.join("ruby_runtime.old");
+    remove_path(&retired)?;
+    if fs::symlink_metadata(&ruby_dest).is_ok() {
+        fs::rename(&ruby_dest, &retired).with_context(|| format!("Failed to move {:?}", ruby_dest))?;
+    }
+    if let Err(e) = fs::rename(&staged, &ruby_dest) {
+        let _ = fs::rename(&retired, &ruby_dest);
+        return Err(e).with_context(|| format!("Failed to move {:?} to {:?}", staged, ruby_dest));
+    }
+    remove_path(&retired)?;
+    if let Some(linked) = &linked {
+        progress::human(&fmt_link_report(linked, link_mode));
+    }
+
+    ArcConfig::update(&project.flux_dir, |c| c.ruby.version = version.to_string())?;
+    project.record(
+        SignalType::Bootstrap,
+        json!({
+            "ruby_version":  version,
+            "switched_from": previous,
+            "downgraded_from": downgraded_from,
+            "ruby_constraints": ruby_constraints,
+            "cache_hit":     true,
+            "dest":          ruby_dest.to_string_lossy(),
+            "shared":        shared.as_ref().map(|s| s.to_string_lossy()),
+            "link":          linked.as_ref().map(|l| l.to_json(link_mode)),
+            "bytes_linked":     linked.as_ref().map_or(0, |l| l.bytes),
+            "bytes_downloaded": 0,
+            "duration_us":   timer.elapsed().as_micros() as u64,
+        }),
+    )?;
+    progress::human(&format!(
+        "✨ Switched to Ruby {} (from {})",
+        version,
+        previous.as_deref().unwrap_or("no runtime")
+    ));
+
+    // 3. 切り替えた API バージョン向けの Gem を確認する
+    let api_version = config::ruby_api_version(version);
+    let gem_base = env_dir.join("ruby").join(&api_version);
+    if gem_base.is_dir() {
+        report_abi_mismatches(&abi::scan(&gem_base, &api_version, version), false);
+    } else {
+        progress::human(&format!("   No gems are installed for Ruby {} yet; run `arc sync`.", api_version));
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// ダウングレードの確認
+// ─────────────────────────────────────────────
+
+/// `target` が最後に bootstrap した Ruby より古ければ警告し、`allow` か確認がなければ中止する。
+/// 続ける場合はダウングレード元のバージョン (ダウングレードでなければ `None`) を返す。
+/// `interactive` でなければ確認を求めずに中止する。
+pub fn guard_downgrade<R: BufRead, W: Write>(
+    history: &[RubyInstall],
+    target: &str,
+    allow: bool,
+    interactive: bool,
+    input: &mut R,
+    out: &mut W,
+) -> Result<Option<String>> {
+    let Some(previous) = history.last().map(|r| r.version.as_str()) else { return Ok(None) };
+    if !ruby_version::is_downgrade(previous, target) {
+        return Ok(None);
+    }
+    writeln!(out)?;
+    writeln!(out, "  ⚠️  Ruby {} is OLDER than the last bootstrapped version {}", target, previous)?;
+    writeln!(out, "      (check .arc/config.toml: a merge may have picked the older version)")?;
+    writeln!(out)?;
+    if allow {
+        writeln!(out, "Downgrading because of --allow-downgrade")?;
+        return Ok(Some(previous.to_string()));
+    }
+    if interactive && prompt::confirm(input, out, &format!("Downgrade Ruby {} → {}?", previous, target))? {
+        return Ok(Some(previous.to_string()));
+    }
+    bail!(
+        "Ruby {} から {} へのダウングレードを中止しました。意図した変更であれば --allow-downgrade を付けて再実行してください。",
+        previous,
+        target
+    )
+}
+
+/// 端末の stdin / stderr で `guard_downgrade` を行う。stdin が端末でなければ確認せずに中止する。
+pub fn guard_downgrade_on_terminal(project: &FluxProject, target: &str, allow: bool) -> Result<Option<String>> {
+    let state = FluxState::from_signals(&project.read_signals()?);
+    let interactive = std::io::stdin().is_terminal();
+    guard_downgrade(state.ruby_history(), target, allow, interactive, &mut std::io::stdin().lock(), &mut std::io::stderr())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::FLUX_DIR;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// `RUBY_VERSION` を返すだけの Ruby をキャッシュに置く
+    fn stub_ruby(rubies_root: &Path, version: &str, complete: bool) -> PathBuf {
+        let dir = rubies_root.join(resolve_ruby_id(version));
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        let ruby = dir.join("bin").join("ruby");
+        fs::write(&ruby, format!("#!/bin/sh\nprintf '{}'\n", version)).unwrap();
+        fs::set_permissions(&ruby, fs::Permissions::from_mode(0o755)).unwrap();
+        if complete {
+            cache_layout::mark_complete(&dir).unwrap();
+        }
+        dir
+    }
+
+    fn fixture(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let rubies = root.join("rubies");
+        stub_ruby(&rubies, "3.3.6", true);
+        stub_ruby(&rubies, "3.4.1", true);
+        stub_ruby(&rubies, "3.10.0", false);
+        fs::create_dir_all(rubies.join("3.2.0-other-os")).unwrap();
+
+        let cwd = root.join("app");
+        fs::create_dir_all(&cwd).unwrap();
+        FluxProject::init(&cwd, &Default::default(), json!({})).unwrap();
+        ArcConfig::update(&cwd.join(FLUX_DIR), |c| c.ruby.version = "3.3.6".to_string()).unwrap();
+        let env_dir = cwd.join(ARC_ENV_DIR);
+        fs::create_dir_all(&env_dir).unwrap();
+        link::link_tree(&rubies.join(resolve_ruby_id("3.3.6")), &ruby_runtime_root(&env_dir), Default::default()).unwrap();
+        (root, cwd)
+    }
+
+    #[test]
+    fn test_cached_rubies_for_this_platform() {
+        let (root, cwd) = fixture("arc_rubies_list_test");
+        let rubies = cached_rubies(&root.join("rubies"));
+        let listed: Vec<(&str, bool)> = rubies.iter().map(|r| (r.version.as_str(), r.complete)).collect();
+        assert_eq!(listed, [("3.3.6", true), ("3.4.1", true), ("3.10.0", false)]);
+        assert!(rubies[0].bytes > 0);
+        assert_eq!(probe_version(&ruby_bin(&cwd.join(ARC_ENV_DIR))).as_deref(), Some("3.3.6"));
+        assert_eq!(probe_version(&root.join("missing")), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_use_cached_swaps_runtime() {
+        let (root, cwd) = fixture("arc_rubies_use_test");
+        let rubies = root.join("rubies");
+        let env_dir = cwd.join(ARC_ENV_DIR);
+
+        use_cached(&CommandContext::at(&cwd, false), "3.4.1", &rubies, false, false).unwrap();
+        assert_eq!(probe_version(&ruby_bin(&env_dir)).as_deref(), Some("3.4.1"));
+        assert!(!env_dir.join("ruby_runtime.new").exists() && !env_dir.join("ruby_runtime.old").exists());
+
+        let project = FluxProject::open(&cwd).unwrap();
+        assert_eq!(ArcConfig::load(&project.flux_dir).unwrap().ruby.version, "3.4.1");
+        let signals = project.read_signals().unwrap();
+        let last = signals.last().unwrap();
+        assert_eq!(last.r_type, "bootstrap");
+        assert_eq!(last.payload["ruby_version"], "3.4.1");
+        assert_eq!(last.payload["switched_from"], "3.3.6");
+        assert_eq!(last.payload["cache_hit"], true);
+
+        // 不完全なキャッシュには切り替えない
+        let err = use_cached(&CommandContext::at(&cwd, false), "3.10.0", &rubies, false, false).unwrap_err().to_string();
+        assert!(err.contains("グローバルキャッシュにありません"), "{}", err);
+        assert_eq!(probe_version(&ruby_bin(&env_dir)).as_deref(), Some("3.4.1"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_use_cached_keeps_runtime_when_new_one_fails() {
+        let (root, cwd) = fixture("arc_rubies_broken_test");
+        let rubies = root.join("rubies");
+        let broken = stub_ruby(&rubies, "3.4.1", true);
+        fs::write(broken.join("bin").join("ruby"), "#!/bin/sh\nexit 1\n").unwrap();
+
+        let err = use_cached(&CommandContext::at(&cwd, false), "3.4.1", &rubies, false, false).unwrap_err().to_string();
+        assert!(err.contains("実行できません"), "{}", err);
+        let env_dir = cwd.join(ARC_ENV_DIR);
+        assert_eq!(probe_version(&ruby_bin(&env_dir)).as_deref(), Some("3.3.6"));
+        assert!(!env_dir.join("ruby_runtime.new").exists());
+        let project = FluxProject::open(&cwd).unwrap();
+        assert_eq!(ArcConfig::load(&project.flux_dir).unwrap().ruby.version, "3.3.6");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn installs(versions: &[&str]) -> Vec<RubyInstall> {
+        versions
+            .iter()
+            .enumerate()
+            .map(|(i, v)| RubyInstall { version: v.to_string(), cache_hit: Some(true), timestamp: String::new(), signal_id: i.to_string() })
+            .collect()
+    }
+
+    fn guard(history: &[&str], target: &str, allow: bool, interactive: bool, answer: &str) -> (Result<Option<String>>, String) {
+        let mut out = Vec::new();
+        let result = guard_downgrade(&installs(history), target, allow, interactive, &mut std::io::Cursor::new(answer.as_bytes()), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_downgrade_gate() {
+        // 初回・同じバージョン・アップグレードは何も聞かない
+        for (history, target) in [(vec![], "3.3.6"), (vec!["3.4.1"], "3.4.1"), (vec!["3.4.0-rc1"], "3.4.0")] {
+            let (result, out) = guard(&history, target, false, false, "");
+            assert_eq!(result.unwrap(), None);
+            assert!(out.is_empty(), "{}", out);
+        }
+
+        // 最後に記録したバージョンと比べる (以前のもっと新しいバージョンではない)
+        assert_eq!(guard(&["3.4.1", "3.3.0"], "3.3.6", false, false, "").0.unwrap(), None);
+
+        let (result, out) = guard(&["3.3.6", "3.4.1"], "3.4.1-rc1", false, false, "y\n");
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("--allow-downgrade") && err.contains("3.4.1-rc1"), "{}", err);
+        assert!(out.contains("OLDER than the last bootstrapped version 3.4.1") && !out.contains("[y/N]"), "{}", out);
+
+        assert_eq!(guard(&["3.4.1"], "3.3.6", true, false, "").0.unwrap().as_deref(), Some("3.4.1"));
+        assert_eq!(guard(&["3.4.1"], "3.3.6", false, true, "y\n").0.unwrap().as_deref(), Some("3.4.1"));
+        let (result, out) = guard(&["3.4.1"], "3.3.6", false, true, "n\n");
+        assert!(result.is_err());
+        assert!(out.contains("Downgrade Ruby 3.4.1 → 3.3.6? [y/N]"), "{}", out);
+    }
+
+    #[test]
+    fn test_use_cached_refuses_downgrade() {
+        let (root, cwd) = fixture("arc_rubies_downgrade_test");
+        let rubies = root.join("rubies");
+        let env_dir = cwd.join(ARC_ENV_DIR);
+        use_cached(&CommandContext::at(&cwd, false), "3.4.1", &rubies, false, false).unwrap();
+
+        // stdin は端末ではないので確認せずに中止する
+        let err = use_cached(&CommandContext::at(&cwd, false), "3.3.6", &rubies, false, false).unwrap_err().to_string();
+        assert!(err.contains("ダウングレード"), "{}", err);
+        assert_eq!(probe_version(&ruby_bin(&env_dir)).as_deref(), Some("3.4.1"));
+
+        use_cached(&CommandContext::at(&cwd, false), "3.3.6", &rubies, true, false).unwrap();
+        let signals = FluxProject::open(&cwd).unwrap().read_signals().unwrap();
+        assert_eq!(signals.last().unwrap().payload["downgraded_from"], "3.4.1");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+//! `arc bootstrap` で選んだ Ruby が、プロジェクトの Ruby 要件を満たすかの確認。
+//!
+//! 次の 2 つと照らし合わせる:
+//!
+//! 1. Gemfile の `ruby` 指定 (`ruby "~> 3.2"`, `ruby ">= 3.1", "< 3.4"`, `ruby file: ".ruby-version"`)
+//! 2. Gemfile.lock があれば、ロックされた Gem の `required_ruby_version` の下限のうち最大のもの。
+//!    ロックファイルの spec に `ruby (...)` の行があればそれを、なければインストール済みの gemspec を使う
+//!
+//! 満たさない要件があれば、どの要件がどの版で満たされないかを示して中止する。
+//! `--ignore-ruby-constraints` で続行でき、確認の結果と上書きの有無は bootstrap の Signal に残る。
+
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+
+use crate::lockfile;
+use crate::progress;
+use crate::signals::ARC_ENV_DIR;
+use crate::version_req::{Constraint, Op, Requirement};
+
+/// 要件 1 つと、その出どころ (`Gemfile`, `nokogiri 1.16.0`)。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub origin: String,
+    pub requirement: Requirement,
+}
+
+/// 満たされなかった要件。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub origin: String,
+    pub requirement: String,
+    /// 要件のうち満たさなかった制約 (`>= 3.2`)
+    pub unmet: Vec<String>,
+}
+
+/// 1 つの Ruby バージョンについての確認結果。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub ruby_version: String,
+    pub sources: Vec<Source>,
+    pub violations: Vec<Violation>,
+    /// 要件どうしが矛盾していて、どの版でも満たせない場合の説明
+    pub conflict: Option<String>,
+}
+
+// ─────────────────────────────────────────────
+// 要件の収集
+// ─────────────────────────────────────────────
+
+/// Gemfile の `ruby` 指定から要件を取り出す。指定がなければ `None`。
+/// `file:` は `cwd` からの相対パスの `.ruby-version` 形式のファイルを読み、その版ちょうどを要件にする。
+pub fn gemfile_requirement(content: &str, cwd: &Path) -> Result<Option<Requirement>> {
+    let Some(args) = content.lines().find_map(ruby_directive) else { return Ok(None) };
+    let mut parts = Vec::new();
+    for arg in args.split(',').map(str::trim) {
+        if let Some(quoted) = unquote(arg) {
+            parts.push(quoted.to_string());
+        } else if let Some(path) = arg.strip_prefix("file:").and_then(|p| unquote(p.trim())) {
+            let file = fs::read_to_string(cwd.join(path)).unwrap_or_default();
+            match super::init::parse_ruby_version_file(&file) {
+                Some(version) => parts.push(version),
+                None => bail!("Gemfile の `ruby file: \"{}\"` からバージョンを読み取れません", path),
+            }
+        }
+        // engine: / patchlevel: などは範囲に関係しない
+    }
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+    Requirement::from_parts(&parts).map(Some)
+}
+
+/// `ruby "~> 3.2"` / `ruby(">= 3.1")` の行なら引数部分を返す
+fn ruby_directive(line: &str) -> Option<&str> {
+    let code = line.split('#').next()?.trim();
+    let args = code.strip_prefix("ruby")?;
+    if !args.starts_with([' ', '(']) {
+        return None;
+    }
+    let args = args.trim();
+    Some(args.strip_prefix('(').and_then(|a| a.strip_suffix(')')).unwrap_or(args))
+}
+
+fn unquote(s: &str) -> Option<&str> {
+    ['"', '\''].iter().find_map(|q| s.strip_prefix(*q)?.strip_suffix(*q))
+}
+
+/// ロックされた Gem の `required_ruby_version` の下限のうち、もっとも高いもの。
+/// 同じ下限なら先に見つかった Gem を出どころにする。
+pub fn gem_lower_bound(cwd: &Path) -> Option<Source> {
+    let lock = lockfile::parse(&cwd.join("Gemfile.lock")).ok()?;
+    let gem_bases = installed_gem_bases(cwd);
+    let mut best: Option<(Constraint, String)> = None;
+    for spec in &lock.specs {
+        let requirement = spec
+            .required_ruby_version
+            .as_deref()
+            .and_then(|r| Requirement::parse(r).ok())
+            .or_else(|| installed_requirement(&gem_bases, &spec.name, &spec.version));
+        let Some(lower) = requirement.and_then(|r| r.lower()) else { continue };
+        let constraint = Constraint { op: if lower.inclusive { Op::Ge } else { Op::Gt }, version: lower.version };
+        let higher = best.as_ref().is_none_or(|(current, _)| {
+            crate::ruby_version::compare(&constraint.version, &current.version).is_gt()
+                || (constraint.version == current.version && constraint.op == Op::Gt && current.op == Op::Ge)
+        });
+        if higher {
+            best = Some((constraint, format!("{} {}", spec.name, spec.version)));
+        }
+    }
+    best.map(|(constraint, origin)| Source { origin, requirement: Requirement { constraints: vec![constraint] } })
+}
+
+/// `.arc/env/ruby/<api>` の一覧 (Ruby を入れ替える前は別の API 向けしかないこともある)
+fn installed_gem_bases(cwd: &Path) -> Vec<std::path::PathBuf> {
+    let mut bases: Vec<_> = fs::read_dir(cwd.join(ARC_ENV_DIR).join("ruby"))
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect())
+        .unwrap_or_default();
+    bases.sort();
+    bases
+}
+
+fn installed_requirement(gem_bases: &[std::path::PathBuf], name: &str, version: &str) -> Option<Requirement> {
+    let file = format!("{}-{}.gemspec", name, version);
+    gem_bases.iter().find_map(|base| {
+        let spec = fs::read_to_string(base.join("specifications").join(&file)).ok()?;
+        let constraints = crate::abi::required_ruby_version(&spec)?;
+        let parts: Vec<&str> = constraints.iter().map(String::as_str).collect();
+        Requirement::from_parts(&parts).ok()
+    })
+}
+
+/// プロジェクトの Ruby 要件をすべて集める (Gemfile の指定 → Gem の下限の順)。
+pub fn collect(cwd: &Path) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    if let Ok(content) = fs::read_to_string(cwd.join("Gemfile"))
+        && let Some(requirement) = gemfile_requirement(&content, cwd)?
+    {
+        sources.push(Source { origin: "Gemfile `ruby` directive".to_string(), requirement });
+    }
+    sources.extend(gem_lower_bound(cwd));
+    Ok(sources)
+}
+
+// ─────────────────────────────────────────────
+// 確認
+// ─────────────────────────────────────────────
+
+pub fn check(ruby_version: &str, sources: Vec<Source>) -> Check {
+    let violations = sources
+        .iter()
+        .filter(|source| !source.requirement.satisfied_by(ruby_version))
+        .map(|source| Violation {
+            origin: source.origin.clone(),
+            requirement: source.requirement.to_string(),
+            unmet: source.requirement.violations(ruby_version).iter().map(|c| c.to_string()).collect(),
+        })
+        .collect();
+    let conflict = conflict(&sources);
+    Check { ruby_version: ruby_version.to_string(), sources, violations, conflict }
+}
+
+/// 要件どうしが矛盾していれば、どれとどれかを説明する
+fn conflict(sources: &[Source]) -> Option<String> {
+    let all = sources.iter().fold(Requirement::default(), |acc, s| acc.intersect(&s.requirement));
+    let (a, b) = all.conflict()?;
+    let origin = |c: &Constraint| {
+        sources.iter().find(|s| s.requirement.constraints.contains(c)).map_or("", |s| s.origin.as_str()).to_string()
+    };
+    Some(format!("{} ({}) conflicts with {} ({})", a, origin(a), b, origin(b)))
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![format!("Ruby {} does not satisfy the project's Ruby requirements:", self.ruby_version)];
+        for v in &self.violations {
+            lines.push(format!("  ✗ {} requires {} (fails {})", v.origin, v.requirement, v.unmet.join(", ")));
+        }
+        if let Some(conflict) = &self.conflict {
+            lines.push(format!("  No Ruby version satisfies every requirement: {}", conflict));
+        }
+        lines
+    }
+
+    pub fn to_json(&self, ignored: bool) -> Value {
+        json!({
+            "checked": self.sources.iter().map(|s| json!({
+                "origin":      s.origin,
+                "requirement": s.requirement.to_string(),
+            })).collect::<Vec<_>>(),
+            "violations": self.violations.iter().map(|v| json!({
+                "origin":      v.origin,
+                "requirement": v.requirement,
+                "unmet":       v.unmet,
+            })).collect::<Vec<_>>(),
+            "conflict": self.conflict,
+            "ignored":  ignored,
+        })
+    }
+}
+
+/// `ruby_version` をプロジェクトの要件と照らし合わせる。満たさなければ `ignore` がない限り中止する。
+/// bootstrap の Signal に入れる確認結果を返す。
+pub fn guard(cwd: &Path, ruby_version: &str, ignore: bool) -> Result<Value> {
+    let check = check(ruby_version, collect(cwd)?);
+    if check.passed() {
+        return Ok(check.to_json(false));
+    }
+    let lines = check.describe();
+    if !ignore {
+        bail!("{}\nRe-run with --ignore-ruby-constraints to bootstrap anyway.", lines.join("\n"));
+    }
+    for line in &lines {
+        progress::human(&format!("⚠️  {}", line.trim_start()));
+    }
+    progress::human("   Continuing because --ignore-ruby-constraints was given.");
+    Ok(check.to_json(true))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project(name: &str) -> std::path::PathBuf {
+        crate::signals::temp_project(&format!("arc_ruby_constraints_{}", name)).0
+    }
+
+    fn requirement(content: &str) -> Option<String> {
+        gemfile_requirement(content, Path::new("/nonexistent")).unwrap().map(|r| r.to_string())
+    }
+
+    #[test]
+    fn test_gemfile_directive_forms() {
+        assert_eq!(requirement("source 'https://rubygems.org'\nruby '3.3.0'\n").as_deref(), Some("= 3.3.0"));
+        assert_eq!(requirement("ruby \"~> 3.2\" # pinned\n").as_deref(), Some("~> 3.2"));
+        assert_eq!(requirement("ruby \">= 3.1\", \"< 3.4\"\n").as_deref(), Some(">= 3.1, < 3.4"));
+        assert_eq!(requirement("ruby(\"~> 3.3.0\", engine: \"ruby\")\n").as_deref(), Some("~> 3.3.0"));
+        assert_eq!(requirement("gem 'rubocop'\n# ruby '2.7'\n"), None);
+        assert_eq!(requirement("gem 'ruby-progressbar'\nruby RUBY_VERSION\n"), None);
+    }
+
+    #[test]
+    fn test_gemfile_directive_file() {
+        let dir = temp_project("file");
+        fs::write(dir.join(".ruby-version"), "ruby-3.2.2\n").unwrap();
+        let r = gemfile_requirement("ruby file: \".ruby-version\"\n", &dir).unwrap().unwrap();
+        assert_eq!(r.to_string(), "= 3.2.2");
+        assert!(gemfile_requirement("ruby file: \".missing\"\n", &dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_gem_lower_bound_prefers_lockfile_then_gemspec() {
+        let dir = temp_project("gems");
+        fs::write(
+            dir.join("Gemfile.lock"),
+            "GEM\n  specs:\n    nokogiri (1.16.0-x86_64-linux)\n      ruby (>= 3.0, < 3.4.dev)\n    rails (7.1.3)\n    rake (13.1.0)\n",
+        )
+        .unwrap();
+        let specs = dir.join(ARC_ENV_DIR).join("ruby").join("3.3.0").join("specifications");
+        fs::create_dir_all(&specs).unwrap();
+        fs::write(
+            specs.join("rails-7.1.3.gemspec"),
+            "  s.required_ruby_version = Gem::Requirement.new(\">= 2.7.0\".freeze)\n",
+        )
+        .unwrap();
+        let source = gem_lower_bound(&dir).unwrap();
+        assert_eq!(source.origin, "nokogiri 1.16.0-x86_64-linux");
+        assert_eq!(source.requirement.to_string(), ">= 3.0");
+
+        fs::write(
+            specs.join("rake-13.1.0.gemspec"),
+            "  s.required_ruby_version = Gem::Requirement.new([\">= 3.1\".freeze, \"< 4\".freeze])\n",
+        )
+        .unwrap();
+        let source = gem_lower_bound(&dir).unwrap();
+        assert_eq!((source.origin.as_str(), source.requirement.to_string()), ("rake 13.1.0", ">= 3.1".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_reports_violations_and_conflicts() {
+        let sources = vec![
+            Source { origin: "Gemfile `ruby` directive".to_string(), requirement: Requirement::parse("~> 3.1.0").unwrap() },
+            Source { origin: "rake 13.1.0".to_string(), requirement: Requirement::parse(">= 3.2").unwrap() },
+        ];
+        let check = check("3.1.4", sources.clone());
+        assert!(!check.passed());
+        assert_eq!(check.violations.len(), 1);
+        assert_eq!(check.violations[0].unmet, [">= 3.2"]);
+        assert_eq!(
+            check.conflict.as_deref(),
+            Some("~> 3.1.0 (Gemfile `ruby` directive) conflicts with >= 3.2 (rake 13.1.0)")
+        );
+        let lines = check.describe();
+        assert!(lines[1].contains("rake 13.1.0 requires >= 3.2 (fails >= 3.2)"), "{:?}", lines);
+
+        let json = check.to_json(true);
+        assert_eq!(json["ignored"], true);
+        assert_eq!(json["checked"][0]["requirement"], "~> 3.1.0");
+        assert_eq!(json["violations"][0]["origin"], "rake 13.1.0");
+
+        let ok = super::check("3.3.6", vec![sources[1].clone()]);
+        assert!(ok.passed());
+        assert_eq!(ok.conflict, None);
+    }
+
+    #[test]
+    fn test_guard_requires_override() {
+        let dir = temp_project("guard");
+        fs::write(dir.join("Gemfile"), "source 'https://rubygems.org'\nruby '~> 3.3'\n").unwrap();
+        let err = guard(&dir, "3.2.2", false).unwrap_err().to_string();
+        assert!(err.contains("Gemfile `ruby` directive requires ~> 3.3 (fails ~> 3.3)"), "{}", err);
+        assert!(err.contains("--ignore-ruby-constraints"), "{}", err);
+
+        let json = guard(&dir, "3.2.2", true).unwrap();
+        assert_eq!(json["ignored"], true);
+        assert_eq!(json["violations"].as_array().unwrap().len(), 1);
+
+        let json = guard(&dir, "3.3.6", false).unwrap();
+        assert_eq!(json["ignored"], false);
+        assert!(json["violations"].as_array().unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
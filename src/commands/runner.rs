@@ -1,11 +1,18 @@
 use anyhow::Result;
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::env;
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::time::Instant;
-
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+use super::preloader::{self, Preloaders};
+use super::sandbox_home::Sandbox;
+use crate::config::ArcConfig;
+use crate::interpreter::SystemBinary;
+use crate::display;
+use crate::progress;
 use crate::signals::{ARC_ENV_DIR, FluxProject, SignalType};
 
 /// プロセスの環境モード。
@@ -43,6 +50,29 @@ pub fn ruby_bin(env_path: &Path) -> PathBuf {
     ruby_runtime_bin(env_path).join("ruby")
 }
 
+/// 隔離環境が PATH の先頭に加えるディレクトリ (優先順)。
+pub fn env_path_layers(env_path: &Path) -> [PathBuf; 2] {
+    [ruby_runtime_bin(env_path), crate::binstubs::stub_dir(env_path)]
+}
+
+/// 隔離モードの PATH: `env_path_layers` の後に、引き継いだ PATH を続ける。
+pub fn isolated_path_dirs(env_path: &Path, inherited: Option<&OsStr>) -> Vec<PathBuf> {
+    let mut paths = env_path_layers(env_path).to_vec();
+    if let Some(current) = inherited {
+        paths.extend(env::split_paths(current));
+    }
+    paths
+}
+
+/// `dirs` を順に探し、最初に見つかった実行可能な `name` を返す (シェルの PATH 探索と同じ規則)。
+/// リンク切れのシンボリックリンクや実行権のないファイルは飛ばす。
+pub fn which_in(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    dirs.iter().map(|dir| dir.join(name)).find(|candidate| {
+        std::fs::metadata(candidate).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    })
+}
+
 /// LD_LIBRARY_PATH を構築する。
 /// `ruby_runtime/lib` が存在する場合、それを既存の値の先頭に追加する。
 pub fn build_ld_library_path(env_path: &Path) -> Option<OsString> {
@@ -67,6 +97,16 @@ pub fn build_ld_library_path(env_path: &Path) -> Option<OsString> {
 /// site_ruby / vendor_ruby / standard lib を RUBYLIB にセットする。
 /// ポータブルな GitHub Actions 由来の Ruby バイナリのパス問題を解決する。
 pub fn build_rubylib_path(env_path: &Path) -> Option<OsString> {
+    let mut lib_paths = rubylib_dirs(env_path)?;
+    if let Some(current) = env::var_os("RUBYLIB") {
+        lib_paths.extend(env::split_paths(&current));
+    }
+    env::join_paths(lib_paths).ok()
+}
+
+/// RUBYLIB に加える `ruby_runtime/lib/ruby` 以下のディレクトリ (既存の RUBYLIB を含まない)。
+/// `arc tool` のランチャーはこれをスクリプトに書き込む。
+pub fn rubylib_dirs(env_path: &Path) -> Option<Vec<PathBuf>> {
     let ruby_lib_dir = ruby_runtime_lib(env_path).join("ruby");
     if !ruby_lib_dir.exists() {
         return None;
@@ -112,23 +152,35 @@ pub fn build_rubylib_path(env_path: &Path) -> Option<OsString> {
 
     // 標準ライブラリルート
     lib_paths.push(ver_path);
-
-    let result = match env::var_os("RUBYLIB") {
-        Some(current) => {
-            lib_paths.extend(env::split_paths(&current));
-            env::join_paths(lib_paths).ok()?
-        }
-        None => env::join_paths(lib_paths).ok()?,
-    };
-    Some(result)
+    Some(lib_paths)
 }
 
 // ─────────────────────────────────────────────
 // コマンド実行 (Flux シグナル記録付き)
 // ─────────────────────────────────────────────
 
+/// 記録付きで実行し終えたコマンドの結果。`finish_recorded` に渡して end Signal を記録する。
+pub struct Executed {
+    /// `[signals]` の設定で記録を抑制した場合は `None` (end も記録しない)
+    start_id: Option<String>,
+    status: ExitStatus,
+    duration: Duration,
+}
+
+impl Executed {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    /// シグナルで終了した場合は 1
+    pub fn exit_code(&self) -> i32 {
+        self.status.code().unwrap_or(1)
+    }
+}
+
 /// コマンドを実行し、開始・終了を Flux シグナルとして記録する。
-/// `exec`, `install`, `run` の共通ロジックを一元化する。
+/// `exec`, `install`, `run` の共通ロジックを一元化する。`extra` のフィールドは start の payload に追加される。
+#[allow(clippy::too_many_arguments)]
 pub fn run_with_flux(
     project: &FluxProject,
     start_type: SignalType,
@@ -137,50 +189,164 @@ pub fn run_with_flux(
     args: &[String],
     cwd: &Path,
     env_mode: ArcEnv,
+    extra: serde_json::Value,
 ) -> Result<()> {
+    let executed = execute_recorded(project, start_type, cmd, args, cwd, env_mode, extra)?;
+    finish_recorded(project, end_type, &executed, json!({}))
+}
+
+/// start Signal を記録してコマンドを終了まで実行する。`extra` のフィールドは start の payload に追加される。
+/// `extra` の `env` (`env_fields`) はコマンドの環境変数にも設定する。記録した値と実際の環境が食い違わないよう、ここで一度に扱う。
+/// 隔離モードでは Spring / bootsnap の扱いと sandbox の HOME を決め (`extra` の `spring` は `--spring`、
+/// `sandbox_home` は `--sandbox-home`)、`env_context` に記録する。
+/// end Signal は記録しないため、呼び出し側で `finish_recorded` を呼ぶこと。
+pub fn execute_recorded(
+    project: &FluxProject,
+    start_type: SignalType,
+    cmd: &str,
+    args: &[String],
+    cwd: &Path,
+    env_mode: ArcEnv,
+    extra: serde_json::Value,
+) -> Result<Executed> {
+    let config = ArcConfig::load(&project.flux_dir)?;
+    let keep_spring = extra.get("spring").and_then(|v| v.as_bool()).unwrap_or(false);
+    let sandbox_home = extra.get("sandbox_home").and_then(|v| v.as_bool()).unwrap_or(false);
+    let preloaders = (env_mode == ArcEnv::Isolated)
+        .then(|| Preloaders::decide(&project.root, &config.run, keep_spring, || preloader::processes_if_locked(&project.root)));
+
     // シグナルに記録する環境コンテキスト
-    let env_context = match env_mode {
+    let mut env_context = match env_mode {
         ArcEnv::Isolated => json!({ "mode": "isolated", "GEM_HOME": ARC_ENV_DIR }),
         ArcEnv::System   => json!({ "mode": "system" }),
     };
+    if let (Some(context), Some(preloaders)) = (env_context.as_object_mut(), &preloaders)
+        && let serde_json::Value::Object(fields) = preloaders.context_fields() {
+            context.extend(fields);
+        }
+    let sandbox = match env_mode {
+        ArcEnv::Isolated => Sandbox::decide(cwd, &config.run, sandbox_home)?,
+        ArcEnv::System   => None,
+    };
+    if let (Some(context), Some(sandbox)) = (env_context.as_object_mut(), &sandbox) {
+        context.extend(sandbox.context_fields());
+    }
+    // System モードでは PATH から実際に起動するバイナリを解決して記録する
+    let system_binary = (env_mode == ArcEnv::System).then(|| SystemBinary::decide(cmd, cwd, &config.exec, &project.flux_dir));
+    if let (Some(context), Some(binary)) = (env_context.as_object_mut(), &system_binary)
+        && let serde_json::Value::Object(fields) = binary.context_fields()
+    {
+        context.extend(fields);
+    }
 
-    let start_signal = project.record(
-        start_type,
-        json!({
-            "command": cmd,
-            "args": args,
-            "cwd": cwd.to_string_lossy(),
-            "env_context": env_context,
-        }),
-    )?;
+    let mut payload = json!({
+        "command": cmd,
+        "args": args,
+        "cwd": cwd.to_string_lossy(),
+        "env_context": env_context,
+        crate::exec_context::PAYLOAD_KEY: crate::exec_context::ExecContext::current(),
+    });
+    if let (Some(fields), serde_json::Value::Object(extra)) = (payload.as_object_mut(), extra) {
+        fields.extend(extra);
+    }
 
-    let mut command = Command::new(cmd);
-    command.args(args);
+    // 設定・コマンドラインで指定した環境変数
+    let vars: Vec<(String, String)> = payload["env"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+        .collect();
+
+    // 抑制する場合は start を記録せず、対応する end も記録しない
+    let line = display::fmt_cmd(cmd_name(&payload), &payload_args(&payload));
+    let start_id = match config.signals.skip_reason(&start_type, cmd_name(&payload), &line) {
+        Some(reason) => {
+            display::verbose(&format!("🔇 not recorded: {} ({})", line, reason));
+            None
+        }
+        None => Some(project.record(start_type, payload)?.id),
+    };
+
+    let mut command = Command::new(system_binary.as_ref().map_or_else(|| OsString::from(cmd), |b| b.program.clone().into_os_string()));
+    command.args(args).current_dir(cwd);
 
     // 隔離モードの場合、環境変数を注入する
     if env_mode == ArcEnv::Isolated {
         inject_isolated_env(&mut command, cwd)?;
     }
+    if let Some(preloaders) = &preloaders {
+        command.envs(preloaders.env());
+    }
+    if let Some(sandbox) = &sandbox {
+        command.envs(sandbox.env());
+    }
+    // 指定した環境変数は隔離環境の値より優先する
+    command.envs(vars);
 
     let timer = Instant::now();
-    let status = command
-        .status()
+    let status = progress::run_child(&mut command, &display::fmt_cmd(cmd, args), None)
         .map_err(|e| anyhow::anyhow!("コマンド '{}' の起動に失敗しました: {}", cmd, e))?;
 
-    let duration_ms = timer.elapsed().as_millis() as u64;
-    let exit_code = status.code().unwrap_or(1);
+    Ok(Executed {
+        start_id,
+        status,
+        duration: timer.elapsed(),
+    })
+}
 
-    project.record(
-        end_type,
-        json!({
-            "ref_id": start_signal.id,
-            "exit_code": exit_code,
-            "success": status.success(),
-            "duration_ms": duration_ms,
-        }),
-    )?;
+/// 指定された環境変数を start の payload に記録するフィールド (`execute_recorded` の `extra` 用)。
+/// `arc export --redact` などでは `env` の値は伏せられる。
+pub fn env_fields(env: &BTreeMap<String, String>) -> serde_json::Value {
+    if env.is_empty() { json!({}) } else { json!({ "env": env }) }
+}
+
+/// 記録されるコマンド名 (`--shell` の場合はコマンドライン全体)
+fn cmd_name(payload: &serde_json::Value) -> &str {
+    payload["command"].as_str().unwrap_or_default()
+}
+
+fn payload_args(payload: &serde_json::Value) -> Vec<String> {
+    payload["args"]
+        .as_array()
+        .map(|args| args.iter().filter_map(|a| a.as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+/// end Signal を記録する。`extra` のフィールドは payload に追加される。
+/// コマンドが失敗していた場合は、その終了コードでプロセスを終了する。
+pub fn finish_recorded(
+    project: &FluxProject,
+    end_type: SignalType,
+    executed: &Executed,
+    extra: serde_json::Value,
+) -> Result<()> {
+    let exit_code = executed.exit_code();
+    let Some(start_id) = &executed.start_id else {
+        if !executed.status.success() {
+            crate::overhead::print_footer();
+            std::process::exit(exit_code);
+        }
+        return Ok(());
+    };
 
-    if !status.success() {
+    let mut payload = json!({
+        "ref_id": start_id,
+        "exit_code": exit_code,
+        "success": executed.status.success(),
+        "duration_ms": executed.duration.as_millis() as u64,
+        "duration_us": executed.duration.as_micros() as u64,
+        crate::overhead::PAYLOAD_KEY: crate::overhead::elapsed_ms(),
+    });
+    if let (Some(fields), serde_json::Value::Object(extra)) = (payload.as_object_mut(), extra) {
+        fields.extend(extra);
+    }
+
+    project.record(end_type, payload)?;
+
+    if !executed.status.success() {
+        crate::signal_hooks::wait_pending(crate::signal_hooks::EXIT_WAIT);
+        crate::overhead::print_footer();
         // std::process::exit() は Rust の Drop トレイトを呼び出さずに即座に終了する。
         // 現状すべての Signal 記録は完了しているため問題ないが、
         // 将来バッファリングされた書き込みを導入する場合は要注意。
@@ -190,46 +356,150 @@ pub fn run_with_flux(
     Ok(())
 }
 
-/// 隔離モード用の環境変数を `Command` に注入する。
-/// PATH, GEM_HOME, BUNDLE_PATH, LD_LIBRARY_PATH, RUBYLIB を設定する。
-/// `arc shell` からも再利用できるよう `pub` に公開している。
-pub fn inject_isolated_env(command: &mut Command, cwd: &Path) -> Result<()> {
-    let env_path = cwd.join(ARC_ENV_DIR);
-    let gem_home = env_path.to_string_lossy().to_string();
+/// 隔離モードで設定する環境変数の値。
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvValue {
+    /// そのまま設定する
+    Set(PathBuf),
+    /// 引き継いだ値の前に加える (PATH など)
+    Prepend(Vec<PathBuf>),
+}
 
-    command.env("GEM_HOME",    &gem_home);
-    command.env("BUNDLE_PATH", &gem_home);
+/// 隔離モードで設定する環境変数 (設定する順)。
+/// PATH, GEM_HOME, BUNDLE_PATH, BUNDLE_APP_CONFIG, BUNDLE_USER_HOME, LD_LIBRARY_PATH, RUBYLIB。
+/// `inject_isolated_env` と、プロジェクトのスクリプトの binstub (`arc binstub`) が共有する。
+pub fn isolated_env(cwd: &Path) -> Result<Vec<(&'static str, EnvValue)>> {
+    let env_path = cwd.join(ARC_ENV_DIR);
+    let mut vars = vec![
+        ("GEM_HOME",    EnvValue::Set(env_path.clone())),
+        ("BUNDLE_PATH", EnvValue::Set(env_path.clone())),
+        // ~/.bundle や .bundle/config の設定が紛れ込まないよう、bundler の設定の置き場所も隔離する
+        ("BUNDLE_APP_CONFIG", EnvValue::Set(crate::bundler_config::app_config_dir(cwd))),
+        ("BUNDLE_USER_HOME",  EnvValue::Set(crate::bundler_config::user_home_dir()?)),
+    ];
 
     // LD_LIBRARY_PATH: 共有ライブラリの解決
-    if let Some(ld_path) = build_ld_library_path(&env_path) {
-        command.env("LD_LIBRARY_PATH", ld_path);
+    let lib = ruby_runtime_lib(&env_path);
+    if lib.exists() {
+        vars.push(("LD_LIBRARY_PATH", EnvValue::Prepend(vec![lib])));
     }
 
-    // PATH: ruby_runtime/bin を最優先
-    let bin_path = ruby_bin(&env_path);
-    if !bin_path.exists() {
+    // PATH: ruby_runtime/bin を最優先、次に Gem の binstub (.arc/env/bin)
+    if !ruby_bin(&env_path).exists() {
         anyhow::bail!(
             "Ruby runtime not found in {:?}.\nRun `arc bootstrap` to install it.",
-            bin_path.parent().unwrap()
+            ruby_runtime_bin(&env_path)
         );
     }
-
-    let new_path = {
-        let mut paths = vec![
-            bin_path,
-            env_path.join("bin"),
-        ];
-        if let Some(current) = env::var_os("PATH") {
-            paths.extend(env::split_paths(&current));
-        }
-        env::join_paths(paths)?
-    };
-    command.env("PATH", new_path);
+    vars.push(("PATH", EnvValue::Prepend(env_path_layers(&env_path).to_vec())));
 
     // RUBYLIB: ポータブルRuby環境での標準ライブラリ解決
-    if let Some(rubylib) = build_rubylib_path(&env_path) {
-        command.env("RUBYLIB", rubylib);
+    if let Some(dirs) = rubylib_dirs(&env_path) {
+        vars.push(("RUBYLIB", EnvValue::Prepend(dirs)));
     }
 
+    Ok(vars)
+}
+
+/// 隔離モード用の環境変数 (`isolated_env`) を `Command` に注入する。
+/// `arc shell` からも再利用できるよう `pub` に公開している。
+pub fn inject_isolated_env(command: &mut Command, cwd: &Path) -> Result<()> {
+    for (name, value) in isolated_env(cwd)? {
+        match value {
+            EnvValue::Set(path) => {
+                command.env(name, path);
+            }
+            EnvValue::Prepend(mut dirs) => {
+                dirs.extend(env::var_os(name).iter().flat_map(env::split_paths));
+                command.env(name, env::join_paths(dirs)?);
+            }
+        }
+    }
     Ok(())
 }
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::tests::synced_project;
+    use std::fs;
+
+    #[test]
+    fn test_skipped_execution_records_nothing() {
+        let (cwd, project) = crate::signals::temp_project("arc_skip_signals_test");
+        let run = |cmd: &str| {
+            run_with_flux(&project, SignalType::ExecStart, SignalType::ExecEnd, cmd, &[], &cwd, ArcEnv::System, json!({}))
+                .unwrap();
+            project.read_signals().unwrap().iter().map(|s| s.r_type.clone()).collect::<Vec<_>>()
+        };
+
+        // end だけを指定しても start/end の両方が記録されない
+        let mut config = ArcConfig::default();
+        config.signals.skip_types = vec!["exec_end".to_string()];
+        config.save(&project.flux_dir).unwrap();
+        assert_eq!(run("true"), ["init"]);
+
+        config.signals.skip_types.clear();
+        config.signals.skip_commands = vec!["tr*".to_string()];
+        config.save(&project.flux_dir).unwrap();
+        assert_eq!(run("true"), ["init"]);
+        assert_eq!(run("pwd"), ["init", "exec_start", "exec_end"]);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_read_only_exec_runs_child_without_recording() {
+        let (cwd, project) = crate::signals::temp_project("arc_read_only_exec_test");
+        let before = fs::read_to_string(&project.signal_file).unwrap();
+
+        crate::read_only::scoped(|| {
+            let args = ["-c".to_string(), "touch ran".to_string()];
+            run_with_flux(&project, SignalType::ExecStart, SignalType::ExecEnd, "sh", &args, &cwd, ArcEnv::System, json!({}))
+                .unwrap();
+        });
+        assert!(cwd.join("ran").exists());
+        assert_eq!(fs::read_to_string(&project.signal_file).unwrap(), before);
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_end_signal_records_overhead_without_child_time() {
+        let (cwd, project) = crate::signals::temp_project("arc_overhead_payload_test");
+        crate::overhead::start();
+        run_with_flux(
+            &project,
+            SignalType::ExecStart,
+            SignalType::ExecEnd,
+            "sleep",
+            &["0.3".to_string()],
+            &cwd,
+            ArcEnv::System,
+            json!({}),
+        )
+        .unwrap();
+
+        let end = project.read_signals().unwrap().pop().unwrap();
+        assert!(end.payload["duration_ms"].as_u64().unwrap() >= 300);
+        let overhead = end.payload[crate::overhead::PAYLOAD_KEY].as_u64().unwrap();
+        assert!(overhead < 250, "{}", overhead);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_isolated_env_scopes_bundler_config() {
+        let cwd = synced_project("arc_bundler_env_test");
+        let mut sh = std::process::Command::new("sh");
+        sh.args(["-c", "echo $BUNDLE_APP_CONFIG; echo $BUNDLE_USER_HOME"]);
+        inject_isolated_env(&mut sh, &cwd).unwrap();
+        let out = String::from_utf8(sh.output().unwrap().stdout).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], cwd.join(".arc/bundle-config").display().to_string());
+        assert!(lines[1].ends_with(".arc/bundler"), "{}", lines[1]);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+//! `[safety] confirm_patterns`: 危険なコマンド (デプロイ・DB 削除など) を `arc exec` / `arc run` で実行する前の確認。
+//!
+//! パターンは `stats.ignore` と同じ照合 (`state::matches_ignore`) で、整形したコマンドラインに対して判定する。
+//! 一致した場合はコマンド・対象プロジェクト・一致したパターンを表示し、プロジェクト名の入力を求める。
+//! `--yes` で確認を省略できるのは `[safety] allow_yes_bypass = true` の場合だけ。
+//! 拒否した場合は `x-safety-exec_refused` を記録して実行しない。
+
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use serde_json::{Value, json};
+
+use crate::config::ArcConfig;
+use crate::signals::{FluxProject, SignalType};
+use crate::state;
+
+/// 確認の結果
+#[derive(Debug, PartialEq)]
+pub enum Confirmation {
+    /// どのパターンにも一致しない
+    NotRequired,
+    /// プロジェクト名の入力で確認した
+    Confirmed(String),
+    /// `--yes` で省略した (`allow_yes_bypass` が有効)
+    Bypassed(String),
+}
+
+impl Confirmation {
+    /// start Signal の payload に追加するフィールド
+    pub fn signal_fields(&self) -> Value {
+        match self {
+            Self::NotRequired => json!({}),
+            Self::Confirmed(pattern) => json!({ "confirmation": "confirmed", "confirm_pattern": pattern }),
+            Self::Bypassed(pattern) => json!({ "confirmation": "bypassed", "confirm_pattern": pattern }),
+        }
+    }
+}
+
+/// `command_line` に一致する最初のパターン
+fn matching_pattern<'a>(patterns: &'a [String], program: &str, command_line: &str) -> Option<&'a str> {
+    patterns
+        .iter()
+        .find(|p| state::matches_ignore(p, program, command_line))
+        .map(String::as_str)
+}
+
+/// 確認が必要なコマンドなら、プロジェクト名の入力を求める。拒否した場合は記録してエラーにする。
+pub fn confirm_command<R: BufRead, W: Write>(
+    project: &FluxProject,
+    config: &ArcConfig,
+    program: &str,
+    command_line: &str,
+    yes: bool,
+    input: &mut R,
+    out: &mut W,
+) -> Result<Confirmation> {
+    let Some(pattern) = matching_pattern(&config.safety.confirm_patterns, program, command_line) else {
+        return Ok(Confirmation::NotRequired);
+    };
+    if yes && config.safety.allow_yes_bypass {
+        writeln!(out, "⚠️  `{}` matches confirm pattern '{}'; skipped by --yes", command_line, pattern)?;
+        return Ok(Confirmation::Bypassed(pattern.to_string()));
+    }
+
+    let name = project_name(project, config);
+    writeln!(out)?;
+    writeln!(out, "  ⚠️  This command matches [safety] confirm pattern '{}'", pattern)?;
+    writeln!(out, "      Command: {}", command_line)?;
+    writeln!(out, "      Project: {} ({})", name, project.root.display())?;
+    if yes {
+        writeln!(out, "      (--yes is ignored unless [safety] allow_yes_bypass = true)")?;
+    }
+    writeln!(out)?;
+    write!(out, "Type the project name to continue: ")?;
+    out.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    if answer.trim() == name {
+        return Ok(Confirmation::Confirmed(pattern.to_string()));
+    }
+
+    // 読み取り専用のチェックアウトでは記録せずに中止する
+    if !crate::read_only::is_enabled() {
+        project.record(
+            SignalType::custom("safety", "exec_refused")?,
+            json!({ "command_line": command_line, "confirm_pattern": pattern, "cwd": project.root.to_string_lossy() }),
+        )?;
+    }
+    anyhow::bail!("プロジェクト名が一致しないため中止しました ('{}' を入力してください)。", name)
+}
+
+/// 確認に使うプロジェクト名 (config の名前、なければディレクトリ名)
+fn project_name(project: &FluxProject, config: &ArcConfig) -> String {
+    config
+        .project
+        .name
+        .clone()
+        .or_else(|| crate::config::default_project_name(&project.root))
+        .unwrap_or_default()
+}
+
+/// 端末の stdin / stderr で `confirm_command` を行う。stdin が端末でなければ EOF と同じく拒否になる。
+pub fn confirm_on_terminal(
+    project: &FluxProject,
+    config: &ArcConfig,
+    program: &str,
+    command_line: &str,
+    yes: bool,
+) -> Result<Confirmation> {
+    confirm_command(project, config, program, command_line, yes, &mut std::io::stdin().lock(), &mut std::io::stderr())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn project(name: &str) -> (FluxProject, ArcConfig) {
+        let (_, project) = crate::signals::temp_project(name);
+        let mut config = ArcConfig::default();
+        config.project.name = Some("shop".to_string());
+        config.safety.confirm_patterns = vec!["*deploy*".to_string(), "rake db:drop*".to_string()];
+        (project, config)
+    }
+
+    fn confirm(project: &FluxProject, config: &ArcConfig, line: &str, yes: bool, answer: &str) -> (Result<Confirmation>, String) {
+        let mut out = Vec::new();
+        let program = line.split(' ').next().unwrap();
+        let result = confirm_command(project, config, program, line, yes, &mut Cursor::new(answer.as_bytes()), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    fn refused(project: &FluxProject) -> usize {
+        project.read_signals().unwrap().iter().filter(|s| s.r_type == "x-safety-exec_refused").count()
+    }
+
+    #[test]
+    fn test_unmatched_command_needs_no_confirmation() {
+        let (project, config) = project("arc_safety_unmatched_test");
+        let (result, out) = confirm(&project, &config, "rake db:migrate", false, "");
+        assert_eq!(result.unwrap(), Confirmation::NotRequired);
+        assert!(out.is_empty(), "{}", out);
+        fs::remove_dir_all(&project.root).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_name_refuses_and_records() {
+        let (project, config) = project("arc_safety_wrong_name_test");
+        let (result, out) = confirm(&project, &config, "rake db:drop", false, "shoop\n");
+        assert!(result.unwrap_err().to_string().contains("'shop'"));
+        assert!(out.contains("rake db:drop*") && out.contains("Command: rake db:drop") && out.contains("Project: shop"), "{}", out);
+        assert_eq!(refused(&project), 1);
+
+        // EOF (stdin が端末でない場合) も拒否
+        assert!(confirm(&project, &config, "cap production deploy", false, "").0.is_err());
+        assert_eq!(refused(&project), 2);
+        fs::remove_dir_all(&project.root).unwrap();
+    }
+
+    #[test]
+    fn test_correct_name_confirms() {
+        let (project, config) = project("arc_safety_confirm_test");
+        let (result, _) = confirm(&project, &config, "bin/deploy production", false, " shop \n");
+        let confirmation = result.unwrap();
+        assert_eq!(confirmation, Confirmation::Confirmed("*deploy*".to_string()));
+        assert_eq!(confirmation.signal_fields(), json!({ "confirmation": "confirmed", "confirm_pattern": "*deploy*" }));
+        assert_eq!(refused(&project), 0);
+
+        // run --detach でも reaper が run_start に確認結果を記録する
+        let env_dir = project.root.join(crate::signals::ARC_ENV_DIR);
+        fs::create_dir_all(super::super::runner::ruby_runtime_bin(&env_dir)).unwrap();
+        fs::write(super::super::runner::ruby_bin(&env_dir), "").unwrap();
+        super::super::detach::reap(&project, &project.root, "true", &[], None, false, false, confirmation.signal_fields()).unwrap();
+        let start = project.read_signals().unwrap().into_iter().find(|s| s.r_type == "run_start").unwrap();
+        assert_eq!(start.payload["detached"], true);
+        assert_eq!(start.payload["confirmation"], "confirmed");
+        assert_eq!(start.payload["confirm_pattern"], "*deploy*");
+        fs::remove_dir_all(&project.root).unwrap();
+    }
+
+    #[test]
+    fn test_yes_bypass_requires_policy() {
+        let (project, mut config) = project("arc_safety_bypass_test");
+        // 許可されていなければ --yes でも入力を求める
+        let (result, out) = confirm(&project, &config, "rake db:drop", true, "");
+        assert!(result.is_err());
+        assert!(out.contains("allow_yes_bypass"), "{}", out);
+        assert_eq!(refused(&project), 1);
+
+        config.safety.allow_yes_bypass = true;
+        let (result, _) = confirm(&project, &config, "rake db:drop", true, "");
+        let confirmation = result.unwrap();
+        assert_eq!(confirmation, Confirmation::Bypassed("rake db:drop*".to_string()));
+        assert_eq!(confirmation.signal_fields()["confirmation"], "bypassed");
+        // --yes なしなら許可されていても確認する
+        assert!(confirm(&project, &config, "rake db:drop", false, "").0.is_err());
+        fs::remove_dir_all(&project.root).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+//! `[run] sandbox_home` / `--sandbox-home`: 隔離環境で実行するコマンドの HOME を `.arc/env/home` にする。
+//!
+//! Gem によっては実行中に `~/.gem`・`~/.bundle`・キャッシュなどを HOME に書き込み、本当のホームを汚したり、
+//! マシンごとに結果が変わったりする。sandbox の HOME は初回に作り、ドキュメントを生成しない最小の `.gemrc` を置く。
+//!
+//! git の設定 (`~/.gitconfig`) や認証情報のように本当のホームが必要なものは、`[run] sandbox_home_passthrough` に
+//! HOME からの相対パスで並べると、sandbox の HOME から本当のホームへのシンボリックリンクになる。
+//! 本当のホームにないものはリンクしない。sandbox の HOME は `arc clean --home` (`--all`) で消す。
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::RunConfig;
+use crate::display;
+use crate::perms;
+use crate::signals::ARC_ENV_DIR;
+
+/// sandbox の HOME (`.arc/env/home`)
+pub const HOME_DIR: &str = "home";
+/// sandbox の HOME に置く `.gemrc`
+const GEMRC: &str = "gem: --no-document\n";
+
+pub fn dir(env_path: &Path) -> PathBuf {
+    env_path.join(HOME_DIR)
+}
+
+/// sandbox の HOME を用意する。`passthrough` のうちリンクしたもの (本当のホームにあるもの) を返す。
+/// sandbox の HOME に既にある (リンクではない) ファイルは置き換えない。
+pub fn prepare(env_path: &Path, real_home: Option<&Path>, passthrough: &[String]) -> Result<Vec<String>> {
+    let home = dir(env_path);
+    perms::create_dir_all(&home).with_context(|| format!("Failed to create {:?}", home))?;
+    let gemrc = home.join(".gemrc");
+    if fs::symlink_metadata(&gemrc).is_err() {
+        fs::write(&gemrc, GEMRC).with_context(|| format!("Failed to write {:?}", gemrc))?;
+    }
+
+    let mut linked = Vec::new();
+    for entry in passthrough {
+        let relative = Path::new(entry);
+        if entry.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            bail!("[run] sandbox_home_passthrough: {:?} must be a path relative to HOME", entry);
+        }
+        let Some(target) = real_home.map(|h| h.join(relative)).filter(|t| fs::symlink_metadata(t).is_ok()) else {
+            display::verbose(&format!("🏠 sandbox HOME: {} is not in the real home; not linked", entry));
+            continue;
+        };
+        let link = home.join(relative);
+        match fs::read_link(&link) {
+            Ok(current) if current == target => {}
+            Ok(_) => {
+                fs::remove_file(&link).with_context(|| format!("Failed to remove {:?}", link))?;
+                std::os::unix::fs::symlink(&target, &link)?;
+            }
+            Err(_) if fs::symlink_metadata(&link).is_ok() => {
+                eprintln!("⚠️  {} already exists in the sandbox HOME; {} is not linked", link.display(), entry);
+                continue;
+            }
+            Err(_) => {
+                if let Some(parent) = link.parent() {
+                    perms::create_dir_all(parent)?;
+                }
+                std::os::unix::fs::symlink(&target, &link).with_context(|| format!("Failed to link {:?}", link))?;
+            }
+        }
+        linked.push(entry.clone());
+    }
+    Ok(linked)
+}
+
+/// 実行に使う sandbox の HOME (`Sandbox::decide`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sandbox {
+    pub home: PathBuf,
+    /// 本当のホームへリンクした passthrough
+    pub linked: Vec<String>,
+}
+
+impl Sandbox {
+    /// `[run] sandbox_home` か `requested` (`--sandbox-home`) なら sandbox の HOME を用意する。使わなければ `None`。
+    pub fn decide(cwd: &Path, config: &RunConfig, requested: bool) -> Result<Option<Self>> {
+        if !(config.sandbox_home || requested) {
+            return Ok(None);
+        }
+        let env_path = cwd.join(ARC_ENV_DIR);
+        let real_home = std::env::var_os("HOME").map(PathBuf::from);
+        let linked = prepare(&env_path, real_home.as_deref(), &config.sandbox_home_passthrough)?;
+        Ok(Some(Self { home: dir(&env_path), linked }))
+    }
+
+    /// 子プロセスに設定する環境変数
+    pub fn env(&self) -> [(&'static str, &Path); 1] {
+        [("HOME", &self.home)]
+    }
+
+    /// start Signal の `env_context` に加えるフィールド
+    pub fn context_fields(&self) -> Map<String, Value> {
+        let mut fields = Map::new();
+        fields.insert("HOME".to_string(), json!(format!("{}/{}", ARC_ENV_DIR, HOME_DIR)));
+        if !self.linked.is_empty() {
+            fields.insert("home_passthrough".to_string(), json!(self.linked));
+        }
+        fields
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_child_writes_into_sandbox() {
+        let cwd = scratch("arc_sandbox_home_apply_test");
+        let config = RunConfig { sandbox_home: true, ..Default::default() };
+        let sandbox = Sandbox::decide(&cwd, &config, false).unwrap().unwrap();
+        let status = std::process::Command::new("sh")
+            .args(["-c", "echo junk > \"$HOME/marker\""])
+            .current_dir(&cwd)
+            .envs(sandbox.env())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let home = cwd.join(ARC_ENV_DIR).join(HOME_DIR);
+        assert_eq!(fs::read_to_string(home.join("marker")).unwrap(), "junk\n");
+        assert_eq!(fs::read_to_string(home.join(".gemrc")).unwrap(), GEMRC);
+        assert_eq!(Value::Object(sandbox.context_fields()), json!({ "HOME": ".arc/env/home" }));
+
+        // 無効なら何もしない。--sandbox-home だけでも有効になる
+        assert_eq!(Sandbox::decide(&cwd, &RunConfig::default(), false).unwrap(), None);
+        assert_eq!(Sandbox::decide(&cwd, &RunConfig::default(), true).unwrap().unwrap().home, home);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_passthrough_links() {
+        let root = scratch("arc_sandbox_home_passthrough_test");
+        let real = root.join("real");
+        fs::create_dir_all(real.join(".ssh")).unwrap();
+        fs::create_dir_all(real.join(".config/gh")).unwrap();
+        fs::write(real.join(".netrc"), "machine example.com\n").unwrap();
+        let env_path = root.join("project").join(ARC_ENV_DIR);
+        let home = dir(&env_path);
+        let passthrough = [".netrc", ".ssh", ".config/gh", ".aws"].map(String::from);
+
+        let linked = prepare(&env_path, Some(&real), &passthrough).unwrap();
+        // 本当のホームにない .aws はリンクしない
+        assert_eq!(linked, [".netrc", ".ssh", ".config/gh"]);
+        assert_eq!(fs::read_link(home.join(".ssh")).unwrap(), real.join(".ssh"));
+        assert_eq!(fs::read_link(home.join(".config/gh")).unwrap(), real.join(".config/gh"));
+        assert_eq!(fs::read_to_string(home.join(".netrc")).unwrap(), "machine example.com\n");
+        assert!(fs::symlink_metadata(home.join(".aws")).is_err());
+
+        // 2 回目は同じ結果 (リンクし直さない)。sandbox にある本物のファイルは置き換えない
+        fs::remove_file(home.join(".netrc")).unwrap();
+        fs::write(home.join(".netrc"), "local\n").unwrap();
+        let linked = prepare(&env_path, Some(&real), &passthrough).unwrap();
+        assert_eq!(linked, [".ssh", ".config/gh"]);
+        assert_eq!(fs::read_to_string(home.join(".netrc")).unwrap(), "local\n");
+
+        // HOME の外を指すパスは設定の誤り
+        for entry in ["../etc", "/etc/passwd", ""] {
+            assert!(prepare(&env_path, Some(&real), &[entry.to_string()]).is_err(), "{:?}", entry);
+        }
+        // HOME がなければ何もリンクしない
+        assert!(prepare(&env_path, None, &passthrough[..1]).unwrap().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+//! `arc shell`: 隔離環境を有効にしたサブシェル。
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::env;
+use std::process::{Command, ExitStatus};
+
+use crate::pty::{Pty, RawMode, WinSize};
+use crate::signals::{FluxProject, Signal, SignalType};
+use crate::transcript::{Recorder, TranscriptFormat};
+
+use super::context::CommandContext;
+use super::runner::inject_isolated_env;
+
+// ─────────────────────────────────────────────
+// arc shell
+// ─────────────────────────────────────────────
+
+/// `transcript` を指定すると、シェルを pty で起動して端末の出力を `.flux/output/` に記録する。
+pub fn shell(ctx: &CommandContext, record_history: bool, transcript: Option<TranscriptFormat>) -> Result<()> {
+    let cwd = ctx.root()?;
+    let project = ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+
+    // 起動するシェルを決定: $SHELL > /bin/bash
+    let shell_bin = env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string());
+
+    eprintln!("🐚 arc shell: entering isolated environment");
+    eprintln!("   Shell:   {}", shell_bin);
+    eprintln!("   GEM_HOME: {}", cwd.join(crate::signals::ARC_ENV_DIR).display());
+    eprintln!("   Type 'exit' to leave the arc environment.");
+    eprintln!();
+
+    let mut command = std::process::Command::new(&shell_bin);
+    inject_isolated_env(&mut command, cwd)?;
+
+    // ARC_SHELL=1 / ARC_PROJECT をセットしておくと、PS1 等でカスタマイズできる
+    command.env("ARC_SHELL", "1");
+    let config = ctx.config()?;
+    if let Some(ref name) = config.project.name {
+        command.env("ARC_PROJECT", name);
+    }
+
+    let mut payload = json!({ "shell": &shell_bin, "record_history": record_history });
+    if let Some(format) = transcript {
+        payload["transcript"] = json!(format.as_str());
+    }
+    let enter = project.record(SignalType::ShellEnter, payload)?;
+
+    // --record-history: セッション内のコマンドを履歴ファイル経由で取り込む
+    let history = if record_history {
+        match super::shell_history::ShellKind::detect(&shell_bin) {
+            Some(kind) => Some(super::shell_history::prepare(kind, &project.flux_dir, &enter.id, &mut command)?),
+            None => {
+                eprintln!("⚠️  --record-history は bash / zsh / fish のみ対応しています: {}", shell_bin);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // インタラクティブシェルを起動。ユーザーが exit するまでブロック。
+    let (status, mut exit_payload) = match transcript {
+        Some(format) => run_with_transcript(project, &enter, command, format, &shell_bin)?,
+        None => {
+            let status = command
+                .status()
+                .map_err(|e| anyhow::anyhow!("シェル '{}' の起動に失敗しました: {}", shell_bin, e))?;
+            (status, json!({}))
+        }
+    };
+
+    if let Some(history) = history {
+        let recorded = history.import(project, &enter.id)?;
+        eprintln!("📝 Recorded {} command(s) from the session", recorded);
+    }
+
+    let exit_code = status.code().unwrap_or(0);
+    exit_payload["exit_code"] = json!(exit_code);
+    project.record(SignalType::ShellExit, exit_payload)?;
+
+    eprintln!();
+    eprintln!("🐚 arc shell: exited (code: {})", exit_code);
+
+    Ok(())
+}
+
+/// シェルを pty で起動し、出力を実端末に流しながら `<shell_enter の ID>.<拡張子>` に記録する。
+/// shell_exit に加える `transcript` (ファイル・形式・記録したバイト数) を返す。
+fn run_with_transcript(
+    project: &FluxProject,
+    enter: &Signal,
+    command: Command,
+    format: TranscriptFormat,
+    shell_bin: &str,
+) -> Result<(ExitStatus, Value)> {
+    let dir = super::detach::output_dir(&project.flux_dir);
+    crate::perms::create_dir_all(&dir)?;
+    let file = format!("{}.{}", enter.id, format.extension());
+    let path = crate::safe_path::join_within(&dir, &file)?;
+    eprintln!("⚠️  Recording this session to {}", path.display());
+    eprintln!("   Everything shown in the terminal is saved as-is, including passwords and tokens.");
+    eprintln!();
+
+    let size = WinSize::of(libc::STDOUT_FILENO);
+    let mut recorder = Recorder::create(&path, format, size.unwrap_or(WinSize::DEFAULT), &enter.timestamp, shell_bin)?;
+    let pty = Pty::open(size.unwrap_or(WinSize::DEFAULT))?;
+    let raw = RawMode::enable(libc::STDIN_FILENO);
+    let status = pty.run(
+        command,
+        Some(Box::new(std::io::stdin())),
+        &mut std::io::stdout(),
+        &mut recorder,
+        size.map(|_| libc::STDOUT_FILENO),
+    );
+    drop(raw);
+    let status = status.map_err(|e| anyhow::anyhow!("シェル '{}' の起動に失敗しました: {}", shell_bin, e))?;
+
+    let bytes = recorder.finish(&chrono::Local::now().to_rfc3339(), status.code().unwrap_or(0))?;
+    let relative = path.strip_prefix(&project.root).unwrap_or(&path);
+    Ok((status, json!({ "transcript": { "file": relative, "format": format.as_str(), "bytes": bytes } })))
+}
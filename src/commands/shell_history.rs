@@ -0,0 +1,384 @@
+//! `arc shell --record-history` — シェルセッション内のコマンドを Flux ログに取り込む。
+//!
+//! セッション用の一時ディレクトリ (`.flux/shell/<shell_enter-id>/`) に履歴ファイルを向け、
+//! シェル終了後にそれをパースして `shell_cmd` Signal として記録する。
+//!
+//! | シェル | 仕組み | 履歴フォーマット |
+//! |---|---|---|
+//! | bash | `--rcfile` で HISTFILE / HISTTIMEFORMAT を設定 | `#<epoch>` 行 + コマンド |
+//! | zsh  | `ZDOTDIR` の .zshrc で EXTENDED_HISTORY を有効化 | `: <epoch>:<dur>;cmd` |
+//! | fish | `XDG_DATA_HOME` と `fish_history` でセッション名を指定 | `- cmd: ...` / `  when: ...` |
+
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::binstubs::shell_quote;
+use crate::signals::{FluxProject, SignalType};
+
+/// セッション用一時ディレクトリの親 (`.flux/shell/`)
+const SHELL_DIR: &str = "shell";
+/// fish の履歴セッション名
+const FISH_SESSION: &str = "arc";
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// 履歴の記録に対応しているシェル。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ShellKind {
+    /// シェルのパス (例: `/usr/bin/zsh`) から種別を判定する。
+    pub fn detect(shell_bin: &str) -> Option<Self> {
+        match Path::new(shell_bin).file_name()?.to_str()? {
+            "bash" => Some(ShellKind::Bash),
+            "zsh" => Some(ShellKind::Zsh),
+            "fish" => Some(ShellKind::Fish),
+            _ => None,
+        }
+    }
+}
+
+/// 履歴ファイルの 1 エントリ。
+#[derive(Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub command: String,
+    /// 実行時刻 (UNIX epoch 秒)
+    pub timestamp: Option<i64>,
+}
+
+/// 履歴記録のために準備したセッション。
+pub struct HistorySession {
+    kind: ShellKind,
+    dir: PathBuf,
+}
+
+// ─────────────────────────────────────────────
+// セッションの準備
+// ─────────────────────────────────────────────
+
+/// 一時ディレクトリに rcfile 等を生成し、`command` に引数・環境変数を設定する。
+pub fn prepare(
+    kind: ShellKind,
+    flux_dir: &Path,
+    session_id: &str,
+    command: &mut Command,
+) -> Result<HistorySession> {
+    let dir = flux_dir.join(SHELL_DIR).join(session_id);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    let session = HistorySession { kind, dir };
+    let histfile = shell_quote(&session.history_file().to_string_lossy());
+
+    match kind {
+        ShellKind::Bash => {
+            let rcfile = session.dir.join("bashrc");
+            fs::write(
+                &rcfile,
+                format!(
+                    "[ -f ~/.bashrc ] && . ~/.bashrc\n\
+                     HISTFILE={histfile}\n\
+                     HISTTIMEFORMAT='%F %T '\n\
+                     HISTSIZE=100000\n\
+                     HISTFILESIZE=100000\n\
+                     shopt -s histappend\n\
+                     PROMPT_COMMAND=\"history -a${{PROMPT_COMMAND:+; $PROMPT_COMMAND}}\"\n"
+                ),
+            )?;
+            command.arg("--rcfile").arg(&rcfile).arg("-i");
+        }
+        ShellKind::Zsh => {
+            // ZDOTDIR を差し替えるため、元の起動ファイルを読み込む shim を置く
+            let orig = std::env::var("ZDOTDIR").unwrap_or_else(|_| "$HOME".to_string());
+            fs::write(
+                session.dir.join(".zshenv"),
+                format!("[ -f \"{orig}/.zshenv\" ] && . \"{orig}/.zshenv\"\n"),
+            )?;
+            fs::write(
+                session.dir.join(".zshrc"),
+                format!(
+                    "[ -f \"{orig}/.zshrc\" ] && . \"{orig}/.zshrc\"\n\
+                     HISTFILE={histfile}\n\
+                     HISTSIZE=100000\n\
+                     SAVEHIST=100000\n\
+                     setopt EXTENDED_HISTORY INC_APPEND_HISTORY\n"
+                ),
+            )?;
+            command.env("ZDOTDIR", &session.dir);
+        }
+        ShellKind::Fish => {
+            command.env("XDG_DATA_HOME", &session.dir);
+            command.env("fish_history", FISH_SESSION);
+        }
+    }
+
+    Ok(session)
+}
+
+impl HistorySession {
+    fn history_file(&self) -> PathBuf {
+        match self.kind {
+            ShellKind::Fish => self.dir.join("fish").join(format!("{}_history", FISH_SESSION)),
+            _ => self.dir.join("history"),
+        }
+    }
+
+    /// 履歴ファイルを読み込んでパースする。ファイルがなければ空。
+    pub fn read(&self) -> Vec<HistoryEntry> {
+        let Ok(content) = fs::read_to_string(self.history_file()) else {
+            return vec![];
+        };
+        match self.kind {
+            ShellKind::Bash => parse_bash(&content),
+            ShellKind::Zsh => parse_zsh(&content),
+            ShellKind::Fish => parse_fish(&content),
+        }
+    }
+
+    /// 履歴を `shell_cmd` Signal として記録し、一時ディレクトリを削除する。記録した件数を返す。
+    pub fn import(self, project: &FluxProject, parent_id: &str) -> Result<usize> {
+        let entries = self.read();
+        for entry in &entries {
+            let executed_at = entry
+                .timestamp
+                .and_then(|t| Local.timestamp_opt(t, 0).single())
+                .map(|t| t.to_rfc3339());
+            project.record(
+                SignalType::ShellCmd,
+                json!({
+                    "command": entry.command,
+                    "executed_at": executed_at,
+                    "parent_id": parent_id,
+                }),
+            )?;
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+        Ok(entries.len())
+    }
+}
+
+// ─────────────────────────────────────────────
+// パーサー
+// ─────────────────────────────────────────────
+
+/// bash の拡張履歴 (`HISTTIMEFORMAT` 設定時) をパースする。
+/// `#<epoch>` 行の後、次の `#<epoch>` 行までが 1 コマンド (複数行コマンドを含む)。
+pub fn parse_bash(content: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut pending_ts: Option<i64> = None;
+    let mut continuing = false;
+
+    for line in content.lines() {
+        if let Some(ts) = line.strip_prefix('#').and_then(|t| t.parse::<i64>().ok()) {
+            pending_ts = Some(ts);
+            continuing = false;
+            continue;
+        }
+
+        match entries.last_mut() {
+            // タイムスタンプ行の直後でなければ、直前のコマンドの続き
+            Some(last) if continuing && pending_ts.is_none() => {
+                last.command.push('\n');
+                last.command.push_str(line);
+            }
+            _ => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let timestamp = pending_ts.take();
+                entries.push(HistoryEntry {
+                    command: line.to_string(),
+                    timestamp,
+                });
+                // タイムスタンプのない履歴では 1 行が 1 コマンド
+                continuing = timestamp.is_some();
+            }
+        }
+    }
+
+    entries
+}
+
+/// zsh の EXTENDED_HISTORY (`: <epoch>:<duration>;<command>`) をパースする。
+/// 行末の `\` は複数行コマンドの継続を表す。
+pub fn parse_zsh(content: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    let mut continuing = false;
+
+    for line in content.lines() {
+        if continuing && let Some(last) = entries.last_mut() {
+            last.command.push('\n');
+            last.command.push_str(line.strip_suffix('\\').unwrap_or(line));
+            continuing = line.ends_with('\\');
+            continue;
+        }
+
+        let (timestamp, command) = match line
+            .strip_prefix(": ")
+            .and_then(|rest| rest.split_once(';'))
+        {
+            Some((meta, cmd)) => (meta.split(':').next().and_then(|t| t.parse().ok()), cmd),
+            None => (None, line),
+        };
+        if command.trim().is_empty() {
+            continue;
+        }
+
+        continuing = command.ends_with('\\');
+        entries.push(HistoryEntry {
+            command: command.strip_suffix('\\').unwrap_or(command).to_string(),
+            timestamp,
+        });
+    }
+
+    entries
+}
+
+/// fish の履歴ファイル (`- cmd: ...` / `  when: ...`) をパースする。
+pub fn parse_fish(content: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+
+    for line in content.lines() {
+        if let Some(cmd) = line.strip_prefix("- cmd: ") {
+            entries.push(HistoryEntry {
+                command: unescape_fish(cmd),
+                timestamp: None,
+            });
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ")
+            && let Some(last) = entries.last_mut() {
+                last.timestamp = when.trim().parse().ok();
+            }
+    }
+
+    entries
+}
+
+/// fish の履歴エスケープ (`\n`, `\\`) を戻す。
+fn unescape_fish(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::Stdio;
+
+    fn entry(command: &str, timestamp: Option<i64>) -> HistoryEntry {
+        HistoryEntry { command: command.to_string(), timestamp }
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(ShellKind::detect("/bin/bash"), Some(ShellKind::Bash));
+        assert_eq!(ShellKind::detect("/usr/local/bin/fish"), Some(ShellKind::Fish));
+        assert_eq!(ShellKind::detect("/bin/tcsh"), None);
+    }
+
+    #[test]
+    fn test_parse_bash() {
+        let content = "#1700000000\nls -la\n#1700000005\nfor i in 1 2; do\n  echo $i\ndone\n#1700000009\ngit status\n";
+        assert_eq!(
+            parse_bash(content),
+            vec![
+                entry("ls -la", Some(1700000000)),
+                entry("for i in 1 2; do\n  echo $i\ndone", Some(1700000005)),
+                entry("git status", Some(1700000009)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_bash_without_timestamps() {
+        assert_eq!(parse_bash("ls\npwd\n"), vec![entry("ls", None), entry("pwd", None)]);
+        // "#" で始まるが数字でない行はコメントコマンドとして扱う
+        assert_eq!(parse_bash("#1\n# note\n"), vec![entry("# note", Some(1))]);
+    }
+
+    #[test]
+    fn test_parse_zsh() {
+        let content = ": 1700000000:0;ls -la\n: 1700000005:2;echo one \\\ntwo\n: 1700000009:0;git status\n";
+        assert_eq!(
+            parse_zsh(content),
+            vec![
+                entry("ls -la", Some(1700000000)),
+                entry("echo one \ntwo", Some(1700000005)),
+                entry("git status", Some(1700000009)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_fish() {
+        let content = "- cmd: ls -la\n  when: 1700000000\n- cmd: echo a\\nb\n  when: 1700000005\n  paths:\n    - a\n";
+        assert_eq!(
+            parse_fish(content),
+            vec![entry("ls -la", Some(1700000000)), entry("echo a\nb", Some(1700000005))]
+        );
+    }
+
+    /// 実際の bash を対話モードで起動し、stdin から流したコマンドが記録されることを確認する
+    #[test]
+    fn test_bash_session_recorded() {
+        if Command::new("bash").arg("--version").output().is_err() {
+            return; // bash がない環境ではスキップ
+        }
+
+        let root = std::env::temp_dir().join("arc_shell_history_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let project = FluxProject::init(&root, &Default::default(), serde_json::json!({})).unwrap().0;
+        let enter = project
+            .record(SignalType::ShellEnter, json!({}))
+            .unwrap();
+
+        let mut command = Command::new("bash");
+        // ユーザーの ~/.bashrc に依存しないよう HOME を差し替える
+        command.env("HOME", &root).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null());
+        let session = prepare(ShellKind::Bash, &project.flux_dir, &enter.id, &mut command).unwrap();
+
+        let mut child = command.spawn().unwrap();
+        child.stdin.take().unwrap().write_all(b"echo one\ntrue two\nexit\n").unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(session.import(&project, &enter.id).unwrap(), 3);
+
+        let cmds: Vec<_> = project
+            .read_signals()
+            .unwrap()
+            .into_iter()
+            .filter(|s| s.r_type == "shell_cmd")
+            .collect();
+        assert_eq!(cmds.len(), 3);
+        assert_eq!(cmds[0].payload["command"], "echo one");
+        assert_eq!(cmds[0].payload["parent_id"], enter.id);
+        assert!(cmds[0].payload["executed_at"].is_string());
+        assert!(!project.flux_dir.join(SHELL_DIR).join(&enter.id).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
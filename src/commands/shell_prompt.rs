@@ -0,0 +1,358 @@
+//! `arc prompt`: シェルのプロンプトに arc の状態を 1 行で出す。
+//!
+//! プロンプトを描画するたびに呼ばれるため、状態の再構築 (ログ全体の読み込み) はしない。
+//! 読むのは config.toml・`~/.arc/projects.toml` の該当エントリ・Signal ログの末尾だけ。
+//! カレントディレクトリがプロジェクトでなければ何も出さずに正常終了し、プロンプトを汚さない。
+//!
+//! | プレースホルダ | 内容 |
+//! |---|---|
+//! | `{project}` | プロジェクト名 (config.toml > projects.toml > ディレクトリ名) |
+//! | `{ruby}` | config.toml の Ruby のバージョン |
+//! | `{last_status}` | 直近の exec / run の結果 (`✓` 成功 / `✗` 失敗 / `·` なし) |
+//! | `{last_command}` | 直近の exec / run のコマンド名 |
+//! | `{failed_count_today}` | 今日失敗した exec / run の数 |
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, Local, NaiveDate};
+use std::fs;
+use std::path::Path;
+
+use crate::config::ArcConfig;
+use crate::registry::{self, Registry};
+use crate::signals::{self, FLUX_DIR, SIGNAL_FILE, Signal};
+
+use super::context::CommandContext;
+
+/// `--format` の既定値
+pub const DEFAULT_FORMAT: &str = "{project} {ruby} {last_status}";
+/// 使えるプレースホルダ
+const PLACEHOLDERS: [&str; 5] = ["project", "ruby", "last_status", "last_command", "failed_count_today"];
+/// `{failed_count_today}` のために末尾から読む上限 (これより前の失敗は数えない)
+const MAX_TAIL_BYTES: u64 = 64 * 1024;
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// `arc prompt --init` の対象シェル。
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum PromptShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// プロンプトに出す値。
+#[derive(Debug, Default, PartialEq)]
+pub struct Segment {
+    pub project: String,
+    pub ruby: String,
+    /// 直近の exec / run が成功したか (`None` はまだ実行していない)
+    pub last_success: Option<bool>,
+    /// 直近の exec / run のコマンド名 (start が読んだ範囲にない場合は `None`)
+    pub last_command: Option<String>,
+    pub failed_today: usize,
+}
+
+impl Segment {
+    /// `root` のプロジェクトの値を集める。`root` が Flux プロジェクトでなければ `None`。
+    pub fn gather(root: &Path, registry: Option<&Registry>, today: NaiveDate) -> Option<Self> {
+        let flux_dir = root.join(FLUX_DIR);
+        if !flux_dir.is_dir() {
+            return None;
+        }
+        // 壊れた config.toml でもプロンプトは出す (名前はレジストリかディレクトリ名から)
+        let config = ArcConfig::load(&flux_dir).ok();
+        let registered = registry
+            .and_then(|r| r.projects.iter().find(|p| p.path == root))
+            .and_then(|p| p.name.clone());
+        let project = config
+            .as_ref()
+            .and_then(|c| c.project.name.clone())
+            .or(registered)
+            .unwrap_or_else(|| super::recent::dir_name(root));
+
+        let tail = read_today(&flux_dir.join(SIGNAL_FILE), today);
+        let ends: Vec<&Signal> = tail.iter().filter(|s| is_end(s)).collect();
+        let last = ends.last();
+        let last_command = last.and_then(|end| {
+            let start = tail.iter().rev().find(|s| s.id == end.payload["ref_id"].as_str().unwrap_or_default())?;
+            start.payload["command"].as_str().map(String::from)
+        });
+
+        Some(Self {
+            project,
+            ruby: config.map(|c| c.ruby.version).unwrap_or_default(),
+            last_success: last.map(|end| succeeded(end)),
+            last_command,
+            failed_today: ends.iter().filter(|end| !succeeded(end) && local_date(end) == Some(today)).count(),
+        })
+    }
+
+    /// プレースホルダの値。知らない名前なら `None`。
+    fn value(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "project" => self.project.clone(),
+            "ruby" => self.ruby.clone(),
+            "last_status" => match self.last_success {
+                Some(true) => "✓",
+                Some(false) => "✗",
+                None => "·",
+            }
+            .to_string(),
+            "last_command" => self.last_command.clone().unwrap_or_default(),
+            "failed_count_today" => self.failed_today.to_string(),
+            _ => return None,
+        })
+    }
+}
+
+// ─────────────────────────────────────────────
+// arc prompt
+// ─────────────────────────────────────────────
+
+/// `format` を省略すると `DEFAULT_FORMAT`。`init` を指定すると、プロンプトに組み込むスニペットを出力する。
+pub fn prompt(ctx: &CommandContext, format: Option<&str>, init: Option<PromptShell>) -> Result<()> {
+    if let Some(shell) = init {
+        print!("{}", init_script(shell));
+        return Ok(());
+    }
+    let format = format.unwrap_or(DEFAULT_FORMAT);
+    let root = ctx.root()?;
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let registry = registry::registry_path().ok().and_then(|path| Registry::load_from(&path).ok());
+    match Segment::gather(&root, registry.as_ref(), Local::now().date_naive()) {
+        Some(segment) => println!("{}", render(format, &segment)?),
+        // プロジェクトの外でも書式の誤りには気づけるようにする
+        None => {
+            render(format, &Segment::default())?;
+        }
+    }
+    Ok(())
+}
+
+/// `format` のプレースホルダを置き換える。閉じていない `{` はそのまま残し、前後の空白は削る。
+pub fn render(format: &str, segment: &Segment) -> Result<String> {
+    let mut out = String::with_capacity(format.len());
+    let mut rest = format;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else { break };
+        let name = &rest[open + 1..open + close];
+        let Some(value) = segment.value(name) else {
+            bail!("Unknown placeholder {{{}}} in --format (available: {})", name, PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", "));
+        };
+        out.push_str(&rest[..open]);
+        out.push_str(&value);
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out.trim().to_string())
+}
+
+/// `arc prompt --init <shell>` が出力するスニペット。
+/// 直前のコマンドの終了ステータス (`$?` / `$status`) は元のプロンプトにそのまま渡す。
+pub fn init_script(shell: PromptShell) -> &'static str {
+    match shell {
+        PromptShell::Bash => {
+            "# Add to ~/.bashrc (pass --format to `arc prompt` to change the segment)\n\
+             __arc_prompt() {\n\
+             \x20   local exit_status=$?\n\
+             \x20   local segment\n\
+             \x20   segment=\"$(arc prompt 2>/dev/null)\"\n\
+             \x20   ARC_PROMPT=\"${segment:+$segment }\"\n\
+             \x20   return $exit_status\n\
+             }\n\
+             PROMPT_COMMAND=\"${PROMPT_COMMAND:+$PROMPT_COMMAND; }__arc_prompt\"\n\
+             PS1='${ARC_PROMPT}'\"$PS1\"\n"
+        }
+        PromptShell::Zsh => {
+            "# Add to ~/.zshrc (pass --format to `arc prompt` to change the segment)\n\
+             __arc_prompt() {\n\
+             \x20   local exit_status=$?\n\
+             \x20   local segment=\"$(arc prompt 2>/dev/null)\"\n\
+             \x20   segment=\"${segment//\\%/%%}\"\n\
+             \x20   ARC_PROMPT=\"${segment:+$segment }\"\n\
+             \x20   return $exit_status\n\
+             }\n\
+             autoload -Uz add-zsh-hook\n\
+             add-zsh-hook precmd __arc_prompt\n\
+             setopt PROMPT_SUBST\n\
+             PROMPT='${ARC_PROMPT}'\"$PROMPT\"\n"
+        }
+        PromptShell::Fish => {
+            "# Add to ~/.config/fish/config.fish (pass --format to `arc prompt` to change the segment)\n\
+             functions -q __arc_original_prompt; or functions -c fish_prompt __arc_original_prompt\n\
+             function __arc_return\n\
+             \x20   return $argv[1]\n\
+             end\n\
+             function fish_prompt\n\
+             \x20   set -l last_status $status\n\
+             \x20   set -l segment (arc prompt 2>/dev/null)\n\
+             \x20   test -n \"$segment\"; and printf '%s ' $segment\n\
+             \x20   __arc_return $last_status\n\
+             \x20   __arc_original_prompt\n\
+             end\n"
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// Signal ログの末尾
+// ─────────────────────────────────────────────
+
+/// ログの末尾を読む。今日の Signal がすべて入るまで (最大 `MAX_TAIL_BYTES`)、読む範囲を倍々に広げる。
+fn read_today(signal_file: &Path, today: NaiveDate) -> Vec<Signal> {
+    let len = fs::metadata(signal_file).map_or(0, |m| m.len());
+    let mut max_bytes = signals::TAIL_READ_BYTES;
+    loop {
+        let tail = signals::read_tail_at(signal_file, max_bytes).unwrap_or_default();
+        let complete = max_bytes >= len || tail.first().and_then(local_date).is_none_or(|date| date < today);
+        if complete || max_bytes >= MAX_TAIL_BYTES {
+            return tail;
+        }
+        max_bytes *= 2;
+    }
+}
+
+fn is_end(signal: &Signal) -> bool {
+    matches!(signal.r_type.as_str(), "exec_end" | "run_end")
+}
+
+fn succeeded(end: &Signal) -> bool {
+    end.payload["success"].as_bool().unwrap_or(end.payload["exit_code"].as_i64() == Some(0))
+}
+
+/// Signal を記録した日 (ローカル時刻)
+fn local_date(signal: &Signal) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(&signal.timestamp).ok().map(|t| t.with_timezone(&Local).date_naive())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::{FluxProject, RecordOptions, SignalType};
+    use serde_json::{Value, json};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    /// 「今日」の基準にする時刻
+    const NOW: &str = "2099-01-02T12:00:00+00:00";
+    const LAST_WEEK: &str = "2098-12-26T12:00:00+00:00";
+
+    fn today() -> NaiveDate {
+        DateTime::parse_from_rfc3339(NOW).unwrap().with_timezone(&Local).date_naive()
+    }
+
+    fn project(name: &str) -> (PathBuf, FluxProject) {
+        let (root, project) = crate::signals::temp_project(name);
+        fs::write(project.flux_dir.join("config.toml"), "[project]\nname = \"shop\"\n\n[ruby]\nversion = \"3.3.6\"\n").unwrap();
+        (root, project)
+    }
+
+    fn exec(project: &FluxProject, command: &str, success: bool, timestamp: &str) {
+        let at = |ts: &str| RecordOptions { timestamp: Some(ts.to_string()), ..Default::default() };
+        let start = project.record_with(SignalType::ExecStart, json!({ "command": command, "args": [] }), at(timestamp)).unwrap();
+        let payload = json!({ "ref_id": start.id, "exit_code": if success { 0 } else { 1 }, "success": success });
+        project.record_with(SignalType::ExecEnd, payload, at(timestamp)).unwrap();
+    }
+
+    fn render_all(segment: &Segment) -> String {
+        render("{project}|{ruby}|{last_status}|{last_command}|{failed_count_today}", segment).unwrap()
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let (root, project) = project("arc_prompt_placeholders_test");
+        let segment = Segment::gather(&root, None, today()).unwrap();
+        assert_eq!(render_all(&segment), "shop|3.3.6|·||0");
+        assert_eq!(render(DEFAULT_FORMAT, &segment).unwrap(), "shop 3.3.6 ·");
+
+        exec(&project, "rake", false, LAST_WEEK);
+        exec(&project, "rspec", false, NOW);
+        exec(&project, "rubocop", false, NOW);
+        let segment = Segment::gather(&root, None, today()).unwrap();
+        assert_eq!(render_all(&segment), "shop|3.3.6|✗|rubocop|2");
+
+        exec(&project, "rspec", true, NOW);
+        let segment = Segment::gather(&root, None, today()).unwrap();
+        assert_eq!(render_all(&segment), "shop|3.3.6|✓|rspec|2");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_project_name_fallback() {
+        let (root, project) = project("arc_prompt_name_test");
+        fs::write(project.flux_dir.join("config.toml"), "[ruby]\nversion = \"3.4.1\"\n").unwrap();
+        assert_eq!(Segment::gather(&root, None, today()).unwrap().project, "arc_prompt_name_test");
+
+        let mut registry = Registry::default();
+        registry.touch(&root, Some("registered".to_string()));
+        let segment = Segment::gather(&root, Some(&registry), today()).unwrap();
+        assert_eq!((segment.project.as_str(), segment.ruby.as_str()), ("registered", "3.4.1"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_no_project() {
+        let root = std::env::temp_dir().join("arc_prompt_no_project_test");
+        fs::create_dir_all(&root).unwrap();
+        assert_eq!(Segment::gather(&root, None, today()), None);
+        assert_eq!(render_all(&Segment::default()), "||·||0");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_render_format() {
+        let segment = Segment { project: "shop".into(), ..Default::default() };
+        assert_eq!(render("  [{project}] ", &segment).unwrap(), "[shop]");
+        assert_eq!(render("{project} {", &segment).unwrap(), "shop {");
+        let err = render("{project} {rubby}", &segment).unwrap_err().to_string();
+        assert!(err.contains("{rubby}") && err.contains("{failed_count_today}"), "{}", err);
+    }
+
+    #[test]
+    fn test_init_scripts_call_arc_prompt() {
+        for shell in [PromptShell::Bash, PromptShell::Zsh, PromptShell::Fish] {
+            let script = init_script(shell);
+            assert!(script.contains("arc prompt 2>/dev/null"), "{:?}", shell);
+            assert!(script.ends_with('\n'));
+        }
+        assert!(init_script(PromptShell::Bash).contains("PROMPT_COMMAND="));
+        assert!(init_script(PromptShell::Zsh).contains("add-zsh-hook precmd __arc_prompt"));
+        assert!(init_script(PromptShell::Fish).contains("function fish_prompt"));
+    }
+
+    #[test]
+    fn test_large_log_stays_fast() {
+        let (root, project) = project("arc_prompt_perf_test");
+        let mut file = fs::OpenOptions::new().append(true).open(project.flux_dir.join(SIGNAL_FILE)).unwrap();
+        let mut buf = Vec::new();
+        for i in 0..100_000 {
+            let (id, ts) = (format!("s{}", i), if i < 99_800 { LAST_WEEK } else { NOW });
+            let (r_type, payload): (&str, Value) = match i % 2 {
+                0 => ("exec_start", json!({ "command": "rspec", "args": [] })),
+                _ => ("exec_end", json!({ "ref_id": format!("s{}", i - 1), "exit_code": 1, "success": false })),
+            };
+            let line = json!({ "id": id, "type": r_type, "payload": payload, "timestamp": ts, "v": 2 });
+            writeln!(buf, "{}", line).unwrap();
+        }
+        file.write_all(&buf).unwrap();
+
+        let started = Instant::now();
+        let segment = Segment::gather(&root, None, today()).unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(segment.last_command.as_deref(), Some("rspec"));
+        assert_eq!(segment.last_success, Some(false));
+        assert_eq!(segment.failed_today, 100);
+        assert!(elapsed < Duration::from_millis(200), "{:?}", elapsed);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! `--from <path|->`: `arc state` / `arc stats` をプロジェクトの外の Signal ログに対して実行する。
+//!
+//! 指定した場合は `FluxProject::open` を通さず、ファイル (`-` なら stdin) の NDJSON を
+//! `.flux/signals.jsonl` と同じパーサー (`signals::parse_lines`) で読む。
+//! config.toml は読まず既定値を使い、Gemfile・環境・blob など実際のプロジェクトが必要な表示は省く。
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::config::ArcConfig;
+use crate::signals::{self, FluxProject, Signal};
+
+/// stdin を表す `--from` の値
+pub const STDIN: &str = "-";
+
+/// Signal の読み込み元
+pub enum SignalInput {
+    /// カレントディレクトリのプロジェクト (`--from` なし)
+    Project(FluxProject),
+    /// `--from` で指定したログ。stdin は 1 度しか読めないため、開いた時点ですべて読み込む
+    External { source: PathBuf, signals: Vec<Signal> },
+}
+
+impl SignalInput {
+    /// `from` を省略した場合は `cwd` のプロジェクトを開く。
+    pub fn open(cwd: &Path, from: Option<&Path>) -> Result<Self> {
+        match from {
+            None => Ok(Self::Project(FluxProject::open(cwd)?)),
+            Some(path) if path == Path::new(STDIN) => Self::from_reader(Path::new("<stdin>"), std::io::stdin().lock()),
+            Some(path) => {
+                let file = fs::File::open(path).with_context(|| format!("Signal ログを開けません: {:?}", path))?;
+                Self::from_reader(path, BufReader::new(file))
+            }
+        }
+    }
+
+    /// `reader` の NDJSON を読み込む。`source` は表示に使う。
+    pub fn from_reader(source: &Path, reader: impl BufRead) -> Result<Self> {
+        let signals = signals::parse_lines(reader)
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("{} の Signal を読み込めません", source.display()))?;
+        Ok(Self::External { source: source.to_path_buf(), signals })
+    }
+
+    /// 実際のプロジェクト (`--from` の場合は `None`)
+    pub fn project(&self) -> Option<&FluxProject> {
+        match self {
+            Self::Project(project) => Some(project),
+            Self::External { .. } => None,
+        }
+    }
+
+    /// 表示に使う読み込み元 (`.flux` か `--from` のパス)
+    pub fn source(&self) -> &Path {
+        match self {
+            Self::Project(project) => &project.flux_dir,
+            Self::External { source, .. } => source,
+        }
+    }
+
+    pub fn read_signals(&self) -> Result<Vec<Signal>> {
+        match self {
+            Self::Project(project) => project.read_signals(),
+            Self::External { signals, .. } => Ok(signals.clone()),
+        }
+    }
+
+    /// Signal を時系列順に 1 件ずつ返す。プロジェクトのログはファイルから逐次読む。
+    pub fn iter_signals(&self) -> Result<Box<dyn Iterator<Item = Result<Signal>> + '_>> {
+        Ok(match self {
+            Self::Project(project) => Box::new(project.iter_signals()?),
+            Self::External { signals, .. } => Box::new(signals.iter().cloned().map(Ok)),
+        })
+    }
+
+    /// プロジェクトの config.toml (`--from` の場合は既定値)
+    pub fn config(&self) -> Result<ArcConfig> {
+        match self {
+            Self::Project(project) => ArcConfig::load(&project.flux_dir),
+            Self::External { .. } => Ok(ArcConfig::default()),
+        }
+    }
+
+    /// `--from` の場合、プロジェクトがないため省いた表示を stderr に知らせる。
+    pub fn note_skipped(&self, skipped: &str) {
+        if let Self::External { source, .. } = self {
+            eprintln!("ℹ️  Reading signals from {}; {} (needs a project, config.toml defaults apply)", source.display(), skipped);
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_external_input_matches_project_log() {
+        let root = std::env::temp_dir().join("arc_signal_input_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let project = FluxProject::init(&root, &Default::default(), json!({})).unwrap().0;
+        project.record(crate::signals::SignalType::Add, json!({ "gem": "rake" })).unwrap();
+
+        let log = fs::read_to_string(&project.signal_file).unwrap();
+        // 末尾の空行 (パイプでよく付く) は読み飛ばす
+        let input = SignalInput::from_reader(Path::new("<stdin>"), Cursor::new(format!("{}\n\n", log))).unwrap();
+        let local = SignalInput::open(&root, None).unwrap();
+        let ids = |input: &SignalInput| input.read_signals().unwrap().into_iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(&input), ids(&local));
+        assert_eq!(input.iter_signals().unwrap().count(), 2);
+        assert!(input.project().is_none() && local.project().is_some());
+        assert_eq!(input.source(), Path::new("<stdin>"));
+
+        // `state --json` の出力がプロジェクトから読んだ場合と一致する
+        let render = |input: &SignalInput| {
+            let mut out = Vec::new();
+            super::super::state_json::write_json(&mut out, input.iter_signals().unwrap(), |_| true, &Default::default()).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(render(&input), render(&local));
+        let state = |input: &SignalInput| crate::state::FluxState::from_signals(&input.read_signals().unwrap()).activity(7);
+        assert_eq!(serde_json::to_value(state(&input)).unwrap(), serde_json::to_value(state(&local)).unwrap());
+
+        let from_file = SignalInput::open(Path::new("/nonexistent"), Some(&project.signal_file)).unwrap();
+        assert_eq!(ids(&from_file), ids(&local));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_external_input_reports_bad_line() {
+        let Err(err) = SignalInput::from_reader(Path::new("backup.jsonl"), Cursor::new("\n{not json}\n")) else { panic!() };
+        let message = format!("{:#}", err);
+        assert!(message.contains("backup.jsonl") && message.contains("line 2"), "{}", message);
+    }
+}
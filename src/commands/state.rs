@@ -0,0 +1,161 @@
+//! `arc state`: Signal ログから再構築した状態の表示 (要約・生テーブル・JSON・Ruby の履歴・日別の実行数)。
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::display;
+use crate::gemfile_hash;
+use crate::perms;
+use crate::signals::LogState;
+use crate::type_filter::TypeFilter;
+
+use super::context::CommandContext;
+use super::signal_input::SignalInput;
+
+// ─────────────────────────────────────────────
+// arc state
+// ─────────────────────────────────────────────
+/// `.flux/` はあるが Signal がまだない (ログがない・空) プロジェクトの `arc state` の表示。
+pub(super) fn print_empty_log_banner(out: &mut impl std::io::Write) -> Result<()> {
+    writeln!(out, "ℹ️  Project initialized, but no operations have been recorded yet ({} is missing or empty).", crate::signals::SIGNAL_FILE)?;
+    writeln!(out, "   Next steps:")?;
+    writeln!(out, "     arc bootstrap      # install the project's Ruby")?;
+    writeln!(out, "     arc add <gem>      # add a gem (recorded as a signal)")?;
+    writeln!(out, "     arc exec <cmd>     # run a command with Flux logging")?;
+    Ok(())
+}
+
+
+#[allow(clippy::too_many_arguments)]
+pub fn state(
+    ctx: &CommandContext,
+    raw: bool,
+    diff: bool,
+    types: Vec<String>,
+    all: bool,
+    layout: Option<display::Layout>,
+    user: Option<&str>,
+    failures_verbose: bool,
+    from: Option<&Path>,
+) -> Result<()> {
+    let cwd = ctx.root()?;
+    let input = SignalInput::open(cwd, from)?;
+    if let Some(project) = input.project()
+        && project.log_state() == LogState::Empty && !raw && !diff {
+            return print_empty_log_banner(&mut std::io::stderr());
+        }
+    let signals = input.read_signals()?;
+
+    let filter = TypeFilter::parse(&types, signals.iter().map(|s| s.r_type.as_str()))?;
+    let filtered: Vec<_> = signals
+        .iter()
+        .filter(|s| filter.matches(&s.r_type) && user.is_none_or(|u| s.user() == u))
+        .collect();
+
+    if raw {
+        return display::render_raw(&filtered, input.source());
+    }
+
+    if diff {
+        input.note_skipped("blob references are shown unresolved");
+        return display::render_diff(input.project(), &signals);
+    }
+
+    let config = input.config()?;
+    let Some(project) = input.project() else {
+        input.note_skipped("Gemfile dependencies and project checks are skipped");
+        return display::render_full(&signals, None, &config, all, layout, user, failures_verbose);
+    };
+    display::render_full(&signals, Some(&cwd.join("Gemfile")), &config, all, layout, user, failures_verbose)?;
+    if let Some(since) = gemfile_hash::modified_since(&signals, &cwd.join("Gemfile")) {
+        eprintln!("⚠️  Gemfile was modified outside arc since {}", since);
+    }
+    if !perms::writable(&project.signal_file) {
+        eprintln!(
+            "⚠️  {:?} is not writable by the current user; set `[permissions] group_writable = true` (or {}=1) and fix the group/mode of .flux",
+            project.signal_file, perms::ENV_VAR
+        );
+    }
+    let (captures, bytes) = crate::output_store::usage(&super::detach::output_dir(&project.flux_dir))?;
+    if captures > 0 {
+        eprintln!("📦 Captured output: {} run(s), {} in .flux/output", display::fmt_count(captures as u64), display::fmt_bytes(bytes));
+    }
+    let legacy = crate::upgrade_log::legacy_count(&signals);
+    if legacy > 0 {
+        eprintln!(
+            "ℹ️  This log contains {} v1 signal(s); run `arc upgrade-log` for faster state reconstruction",
+            display::fmt_count(legacy as u64)
+        );
+    }
+    let regressed = crate::signals::timestamp_regressions(&signals, crate::signals::TIMESTAMP_REGRESSION_THRESHOLD_SECS);
+    if let Some(first) = regressed.first() {
+        eprintln!(
+            "⚠️  {} signal(s) have timestamps earlier than preceding signals (first: {} at {}); replay follows file order",
+            regressed.len(), first.id, first.timestamp
+        );
+    }
+    Ok(())
+}
+
+/// `arc state --summary`: Signal 種別ごとの件数。
+pub fn state_summary(ctx: &CommandContext, from: Option<&Path>) -> Result<()> {
+    let input = SignalInput::open(ctx.root()?, from)?;
+    display::render_type_summary(&input.read_signals()?);
+    Ok(())
+}
+
+/// `arc state --json` / `--stream`: Signal を 1 件ずつ読みながら書き出す (ログ全体を読み込まない)。
+#[allow(clippy::too_many_arguments)]
+pub fn state_json(
+    ctx: &CommandContext,
+    types: Vec<String>,
+    user: Option<&str>,
+    cursor: Option<String>,
+    offset: usize,
+    limit: Option<usize>,
+    stream: bool,
+    from: Option<&Path>,
+) -> Result<()> {
+    let page = super::state_json::Page { cursor, offset, limit };
+    let input = SignalInput::open(ctx.root()?, from)?;
+    // ログにだけ現れる種別も --type に指定できるよう、種別の一覧だけを先に集める
+    let present: std::collections::BTreeSet<String> = if types.is_empty() {
+        Default::default()
+    } else {
+        input.iter_signals()?.map(|s| s.map(|s| s.r_type)).collect::<Result<_>>()?
+    };
+    let filter = TypeFilter::parse(&types, present.iter().map(String::as_str))?;
+    let keep = |s: &crate::signals::Signal| filter.matches(&s.r_type) && user.is_none_or(|u| s.user() == u);
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    if stream {
+        super::state_json::write_stream(&mut out, input.iter_signals()?, keep, &page)?;
+    } else {
+        super::state_json::write_json(&mut out, input.iter_signals()?, keep, &page)?;
+    }
+    Ok(())
+}
+
+/// `arc state --ruby-history`: bootstrap で入れた Ruby のバージョンの履歴。
+pub fn ruby_history(ctx: &CommandContext, json_output: bool, from: Option<&Path>) -> Result<()> {
+    let input = SignalInput::open(ctx.root()?, from)?;
+    let state = crate::state::FluxState::from_signals(&input.read_signals()?);
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(state.ruby_history())?);
+    } else {
+        display::render_ruby_history(state.ruby_history());
+    }
+    Ok(())
+}
+
+/// `arc state --activity`: 日別の実行数。
+pub fn activity(ctx: &CommandContext, json_output: bool, ascii: bool, from: Option<&Path>) -> Result<()> {
+    let input = SignalInput::open(ctx.root()?, from)?;
+    let days = crate::state::FluxState::from_signals(&input.read_signals()?).activity(display::ACTIVITY_DAYS);
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&days)?);
+    } else {
+        display::render_activity(&days, ascii);
+    }
+    Ok(())
+}
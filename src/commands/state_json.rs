@@ -0,0 +1,240 @@
+//! `arc state --json` / `--stream` の Signal 出力とページング。
+//!
+//! ログを先頭から 1 件ずつ読み、ページに入る Signal だけを書き出す。
+//! `--stream` はページを溜めずに 1 行 1 Signal の NDJSON で書き、最後の行にページの要約を置く:
+//!
+//! ```text
+//! {"id":"…","type":"add",…}
+//! {"id":"…","type":"exec_end",…}
+//! {"next_cursor":"…","count":2,"has_more":false}
+//! ```
+//!
+//! 要約の行は `id` を持たないことで Signal と区別できる。
+//! `--cursor <id>` はその Signal より後だけを返すので、前回の `next_cursor` を渡せば新しい Signal だけを取得できる。
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::io::Write;
+
+use crate::signals::Signal;
+
+/// 出力する範囲。`cursor` の後から `offset` 件を飛ばし、`limit` 件まで
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Page {
+    pub cursor: Option<String>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Page {
+    /// 範囲の指定がない (ログ全体を従来どおりの配列で出す)
+    pub fn is_unbounded(&self) -> bool {
+        *self == Page::default()
+    }
+}
+
+/// ページの要約 (`--stream` の最終行、`--json` のページング時の `next_cursor` など)
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PageSummary {
+    /// 次の呼び出しで `--cursor` に渡す ID (最後に出力した Signal。出力がなければ渡された cursor)
+    pub next_cursor: Option<String>,
+    pub count: usize,
+    /// ページの後にまだ Signal がある
+    pub has_more: bool,
+}
+
+/// `signals` (時系列順) のうち `keep` を満たすものから `page` の範囲を `emit` に渡す。
+/// `cursor` の Signal がログになければエラー。
+pub fn paginate(
+    signals: impl Iterator<Item = Result<Signal>>,
+    keep: impl Fn(&Signal) -> bool,
+    page: &Page,
+    mut emit: impl FnMut(&Signal) -> Result<()>,
+) -> Result<PageSummary> {
+    let mut after_cursor = page.cursor.is_none();
+    let mut skipped = 0;
+    let mut summary = PageSummary { next_cursor: page.cursor.clone(), count: 0, has_more: false };
+    for signal in signals {
+        let signal = signal?;
+        if !after_cursor {
+            after_cursor = page.cursor.as_deref() == Some(signal.id.as_str());
+            continue;
+        }
+        if !keep(&signal) {
+            continue;
+        }
+        if skipped < page.offset {
+            skipped += 1;
+            continue;
+        }
+        if page.limit.is_some_and(|limit| summary.count >= limit) {
+            summary.has_more = true;
+            break;
+        }
+        emit(&signal)?;
+        summary.count += 1;
+        summary.next_cursor = Some(signal.id.clone());
+    }
+    if !after_cursor {
+        bail!("cursor {:?} が Signal ログにありません", page.cursor.as_deref().unwrap_or_default());
+    }
+    Ok(summary)
+}
+
+/// NDJSON で書き出す (1 行 1 Signal、最後に要約の行)。
+pub fn write_stream(
+    out: &mut impl Write,
+    signals: impl Iterator<Item = Result<Signal>>,
+    keep: impl Fn(&Signal) -> bool,
+    page: &Page,
+) -> Result<PageSummary> {
+    let summary = paginate(signals, keep, page, |signal| {
+        serde_json::to_writer(&mut *out, signal)?;
+        out.write_all(b"\n")?;
+        Ok(())
+    })?;
+    serde_json::to_writer(&mut *out, &summary)?;
+    out.write_all(b"\n")?;
+    out.flush()?;
+    Ok(summary)
+}
+
+/// `--json` の出力。範囲の指定がなければ従来どおり Signal の配列、
+/// あれば `{"signals": [...], "next_cursor": …, "count": …, "has_more": …}`。
+pub fn write_json(
+    out: &mut impl Write,
+    signals: impl Iterator<Item = Result<Signal>>,
+    keep: impl Fn(&Signal) -> bool,
+    page: &Page,
+) -> Result<()> {
+    let mut selected = Vec::new();
+    let summary = paginate(signals, keep, page, |signal| {
+        selected.push(signal.clone());
+        Ok(())
+    })?;
+    let text = if page.is_unbounded() {
+        serde_json::to_string_pretty(&selected)?
+    } else {
+        #[derive(Serialize)]
+        struct Paged<'a> {
+            signals: &'a [Signal],
+            #[serde(flatten)]
+            summary: &'a PageSummary,
+        }
+        serde_json::to_string_pretty(&Paged { signals: &selected, summary: &summary })?
+    };
+    writeln!(out, "{}", text)?;
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    /// `n` 件の Signal (3 件に 1 件が exec_end、残りは add)
+    fn log(n: usize) -> Vec<Signal> {
+        (0..n)
+            .map(|i| Signal {
+                id: format!("{:08}", i),
+                r_type: if i % 3 == 0 { "exec_end" } else { "add" }.to_string(),
+                payload: json!({ "gem": format!("g{}", i) }),
+                timestamp: "2024-01-01T00:00:00+09:00".to_string(),
+                meta: None,
+                v: 2,
+            })
+            .collect()
+    }
+
+    fn ids(signals: &[Signal], page: &Page, keep: impl Fn(&Signal) -> bool) -> (Vec<String>, PageSummary) {
+        let mut ids = Vec::new();
+        let summary = paginate(signals.iter().cloned().map(Ok), keep, page, |s| {
+            ids.push(s.id.clone());
+            Ok(())
+        })
+        .unwrap();
+        (ids, summary)
+    }
+
+    #[test]
+    fn test_offset_limit_windows_are_stable() {
+        let signals = log(10_000);
+        let page = Page { offset: 4_000, limit: Some(3), ..Default::default() };
+        let (first, summary) = ids(&signals, &page, |_| true);
+        assert_eq!(first, ["00004000", "00004001", "00004002"]);
+        assert_eq!(summary, PageSummary { next_cursor: Some("00004002".into()), count: 3, has_more: true });
+        // 同じ指定は同じ範囲を返す
+        assert_eq!(ids(&signals, &page, |_| true).0, first);
+        // 絞り込みの後に offset を数える
+        let (adds, _) = ids(&signals, &Page { offset: 2, limit: Some(2), ..Default::default() }, |s| s.r_type == "add");
+        assert_eq!(adds, ["00000004", "00000005"]);
+        // 最後のページ
+        let (last, summary) = ids(&signals, &Page { offset: 9_998, limit: Some(5), ..Default::default() }, |_| true);
+        assert_eq!(last, ["00009998", "00009999"]);
+        assert!(!summary.has_more);
+    }
+
+    #[test]
+    fn test_cursor_continuity() {
+        let signals = log(2_500);
+        let mut cursor = None;
+        let mut seen = Vec::new();
+        loop {
+            let page = Page { cursor: cursor.clone(), limit: Some(1_000), ..Default::default() };
+            let (batch, summary) = ids(&signals, &page, |_| true);
+            seen.extend(batch);
+            cursor = summary.next_cursor;
+            if !summary.has_more {
+                break;
+            }
+        }
+        let all: Vec<String> = signals.iter().map(|s| s.id.clone()).collect();
+        assert_eq!(seen, all);
+
+        // 新しい Signal がなければ空のページと同じ cursor が返る
+        let page = Page { cursor: cursor.clone(), limit: Some(10), ..Default::default() };
+        let (empty, summary) = ids(&signals, &page, |_| true);
+        assert!(empty.is_empty());
+        assert_eq!(summary.next_cursor, cursor);
+        // 追記された分だけが返る
+        let (new, _) = ids(&log(2_502), &page, |_| true);
+        assert_eq!(new, ["00002500", "00002501"]);
+
+        let unknown = Page { cursor: Some("nope".into()), ..Default::default() };
+        let err = paginate(signals.into_iter().map(Ok), |_| true, &unknown, |_| Ok(())).unwrap_err();
+        assert!(err.to_string().contains("nope"), "{}", err);
+    }
+
+    #[test]
+    fn test_stream_is_ndjson() {
+        let signals = log(1_000);
+        let mut out = Vec::new();
+        let page = Page { limit: Some(500), ..Default::default() };
+        write_stream(&mut out, signals.into_iter().map(Ok), |s| s.r_type == "add", &page).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 501);
+        assert!(lines[..500].iter().all(|l| l["type"] == "add" && l["id"].is_string()));
+        assert_eq!(lines[500], json!({ "next_cursor": "00000749", "count": 500, "has_more": true }));
+        assert!(text.lines().all(|l| !l.starts_with(' ')));
+    }
+
+    #[test]
+    fn test_json_keeps_array_without_page() {
+        let signals = log(3);
+        let mut out = Vec::new();
+        write_json(&mut out, signals.iter().cloned().map(Ok), |_| true, &Page::default()).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&out).unwrap().as_array().unwrap().len(), 3);
+
+        let mut out = Vec::new();
+        write_json(&mut out, signals.into_iter().map(Ok), |_| true, &Page { limit: Some(1), ..Default::default() }).unwrap();
+        let paged: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(paged["signals"].as_array().unwrap().len(), 1);
+        assert_eq!(paged["next_cursor"], "00000000");
+        assert_eq!(paged["has_more"], true);
+    }
+}
@@ -0,0 +1,182 @@
+//! `arc stats`: コマンドごとの実行統計 (追跡・不安定な実行・期間の比較・ディスク使用量・書き出し)。
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::cache_stats::CacheStats;
+use crate::display;
+use crate::signals::FluxProject;
+use crate::stats_export::{self, ExportFormat};
+
+use super::context::CommandContext;
+use super::signal_input::SignalInput;
+
+// ─────────────────────────────────────────────
+// arc stats
+// ─────────────────────────────────────────────
+
+#[allow(clippy::too_many_arguments)]
+pub fn stats(
+    ctx: &CommandContext,
+    cache: bool,
+    all: bool,
+    layout: Option<display::Layout>,
+    group: display::StatsGroup,
+    user: Option<&str>,
+    command: Option<&str>,
+    from: Option<&Path>,
+) -> Result<()> {
+    let input = SignalInput::open(ctx.root()?, from)?;
+    let signals = input.read_signals()?;
+
+    if cache {
+        display::render_cache_stats(&CacheStats::from_signals(&signals));
+        return Ok(());
+    }
+
+    let config = input.config()?;
+    display::render_stats(&signals, &config, all, layout, group, user, command);
+    Ok(())
+}
+
+/// `arc stats --follow`: パターンに一致するコマンドの実行履歴。
+/// `all` でなければ `stats.ignore` に一致する実行を除く。`watch` なら続けてログを監視する。
+pub fn follow(ctx: &CommandContext, pattern: &str, regex: bool, watch: bool, all: bool, user: Option<&str>, from: Option<&Path>) -> Result<()> {
+    let matcher = crate::follow::Matcher::new(pattern, regex)?;
+    let input = SignalInput::open(ctx.root()?, from)?;
+    let config = input.config()?;
+    if let (true, Some(project)) = (watch, input.project()) {
+        let keep = |e: &crate::state::Execution| {
+            user.is_none_or(|u| e.user == u) && (all || !e.is_ignored(&config.stats.ignore)) && matcher.matches(e)
+        };
+        return follow_watch(project, pattern, &keep);
+    }
+    let mut state = crate::state::FluxState::from_signals(&input.read_signals()?);
+    if let Some(user) = user {
+        state.retain_user(user);
+    }
+    if !all {
+        state.executions.retain(|e| !e.is_ignored(&config.stats.ignore));
+    }
+    let runs = crate::follow::runs(&state.executions, &matcher);
+    let lock = input.project().and_then(|project| crate::lockfile::parse(&project.root.join("Gemfile.lock")).ok());
+    display::render_follow(pattern, &runs, &crate::follow::FollowSummary::from_runs(&runs), lock.as_ref());
+    Ok(())
+}
+
+/// `arc stats --follow --watch`: 終わった実行を表示した後、ログに追記された行だけを読んで新しい実行を表示し続ける。
+/// 監視中は end を待っている start を表示せず、終わった時点で 1 行加える。
+fn follow_watch(project: &FluxProject, pattern: &str, keep: &dyn Fn(&crate::state::Execution) -> bool) -> Result<()> {
+    const INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+    let lock = crate::lockfile::parse(&project.root.join("Gemfile.lock")).ok();
+    let mut watch = crate::follow::Watch::default();
+    watch.poll(project)?;
+    let mut runs: Vec<&crate::state::Execution> = watch.executions().iter().filter(|e| keep(e)).collect();
+    runs.sort_by_key(|e| e.started_at);
+    display::render_follow(pattern, &runs, &crate::follow::FollowSummary::from_runs(&runs), lock.as_ref());
+    eprintln!("👀 Watching {} for new runs (Ctrl-C to stop)", project.signal_file.display());
+    loop {
+        std::thread::sleep(INTERVAL);
+        for e in watch.poll(project)?.iter().filter(|e| keep(e)) {
+            display::render_follow_run(e, lock.as_ref());
+        }
+    }
+}
+
+/// `arc stats --flaky`: 成功と失敗を行き来するコマンド。結果によらず終了コードは 0。
+/// `window` は集計する期間 (`--compare` と同じ書式)。`all` でなければ `stats.ignore` に一致する実行を除く。
+pub fn flaky_stats(ctx: &CommandContext, window: Option<&str>, all: bool, user: Option<&str>, from: Option<&Path>) -> Result<()> {
+    let input = SignalInput::open(ctx.root()?, from)?;
+    let config = input.config()?;
+    let signals = input.read_signals()?;
+    let window = window.map(|range| crate::stats_compare::Window::parse(range, &signals)).transpose()?;
+    let positions = crate::stats_compare::positions(&signals);
+    let mut state = crate::state::FluxState::from_signals(&signals);
+    if let Some(user) = user {
+        state.retain_user(user);
+    }
+    let executions: Vec<&crate::state::Execution> = state
+        .executions
+        .iter()
+        .filter(|e| all || !e.is_ignored(&config.stats.ignore))
+        .filter(|e| window.as_ref().is_none_or(|w| w.contains(e, &positions)))
+        .collect();
+    let (threshold, min_runs) = (config.stats.flaky_threshold, config.stats.flaky_min_runs);
+    display::render_flaky(&crate::flaky::analyze(&executions, min_runs, threshold), threshold, min_runs);
+    Ok(())
+}
+
+/// `arc stats --compare` の 2 つの期間
+pub enum CompareWindows {
+    /// `--before` / `--after` の範囲
+    Ranges(String, String),
+    /// `--compare-signals`: Signal (の範囲) の前と後
+    AroundSignals(String),
+}
+
+/// `arc stats --compare` / `--compare-signals`: 2 つの期間のコマンド統計を比べる。
+/// `all` でなければ `stats.ignore` に一致する実行を除く。
+pub fn compare_stats(ctx: &CommandContext, windows: CompareWindows, threshold: f64, all: bool, from: Option<&Path>) -> Result<()> {
+    use crate::stats_compare::{self, Window};
+
+    let input = SignalInput::open(ctx.root()?, from)?;
+    let config = input.config()?;
+    let signals = input.read_signals()?;
+    let (before, after, labels) = match windows {
+        CompareWindows::Ranges(before, after) => {
+            (Window::parse(&before, &signals)?, Window::parse(&after, &signals)?, (before, after))
+        }
+        CompareWindows::AroundSignals(range) => {
+            let (before, after) = Window::around(&range, &signals)?;
+            let (first, last) = range.split_once("..").unwrap_or((&range, &range));
+            (before, after, (format!("..{}", first), format!("{}..", last)))
+        }
+    };
+
+    let state = crate::state::FluxState::from_signals(&signals);
+    let positions = stats_compare::positions(&signals);
+    let ignore: &[String] = if all { &[] } else { &config.stats.ignore };
+    let stats = |window: &Window| state.command_stats_where(ignore, |e| window.contains(e, &positions));
+    let comparison = stats_compare::compare(&stats(&before), &stats(&after), threshold);
+    display::render_compare(&labels.0, &labels.1, &comparison, threshold);
+    Ok(())
+}
+
+/// `arc stats --disk`: `.arc/env` のサイズの推移。
+pub fn disk_stats(ctx: &CommandContext, from: Option<&Path>) -> Result<()> {
+    let input = SignalInput::open(ctx.root()?, from)?;
+    display::render_disk_stats(&crate::disk_stats::DiskStats::from_signals(&input.read_signals()?));
+    Ok(())
+}
+
+/// 日別・コマンド別の統計を `format` で書き出す。`output` を省略した場合は標準出力へ。
+pub fn export_stats(
+    ctx: &CommandContext,
+    format: ExportFormat,
+    output: Option<&Path>,
+    all_commands: bool,
+    all: bool,
+    from: Option<&Path>,
+) -> Result<()> {
+    let input = SignalInput::open(ctx.root()?, from)?;
+    let config = input.config()?;
+    let state = crate::state::FluxState::from_signals(&input.read_signals()?);
+
+    let ignore: &[String] = if all { &[] } else { &config.stats.ignore };
+    let top = (!all_commands).then_some(stats_export::DEFAULT_TOP);
+    let rows = stats_export::rows(&state, ignore, top);
+    let content = match format {
+        ExportFormat::Csv => stats_export::to_csv(&rows),
+        ExportFormat::Json => format!("{}\n", serde_json::to_string_pretty(&stats_export::to_json(&rows))?),
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, content).with_context(|| format!("Failed to write {:?}", path))?;
+            eprintln!("📊 Exported {} row(s) to {}", rows.len(), path.display());
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
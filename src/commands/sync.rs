@@ -0,0 +1,527 @@
+//! `arc sync`: Gemfile.lock に合わせて `.arc/env` に Gem を入れる (キャッシュから復元 → bundle install → キャッシュへ収穫)。
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+
+use crate::abi;
+use crate::binstubs;
+use crate::config::ArcConfig;
+use crate::env_lock::EnvLock;
+use crate::gemfile;
+use crate::gemfile_hash;
+use crate::lockfile;
+use crate::progress::{self, ProgressEvent};
+use crate::signals::{FluxProject, SignalType};
+use crate::sync_state;
+
+use super::context::CommandContext;
+use super::fsops::fmt_link_report;
+use super::gem_cache::{check_cache_layout, harvest_gems, restore_gems};
+use super::gemfile::{check_gemfile, gemfile_issue_fields};
+use super::phases::Phases;
+use super::runner::{ArcEnv, ruby_bin};
+
+// ─────────────────────────────────────────────
+// arc sync
+// ─────────────────────────────────────────────
+
+pub fn sync(ctx: &CommandContext, check: bool, force: bool, force_rebuild: bool, no_preflight: bool) -> Result<()> {
+    let cwd = ctx.root()?;
+    let project = ctx.project()
+        .context("Flux プロジェクトが見つかりません。`arc init` を実行してください。")?;
+    let config = ctx.config()?;
+
+    if check {
+        // CI ゲート用: 比較のみ行い、install は決して実行しない
+        if sync_skippable(cwd, &config.ruby.version, false)? {
+            ctx.reporter.human("✅ already in sync");
+            return Ok(());
+        }
+        ctx.reporter.human("❌ out of sync — run `arc sync`");
+        std::process::exit(1);
+    }
+
+    if sync_skippable(cwd, &config.ruby.version, force || force_rebuild)? {
+        let digest = sync_state::compute_digest(cwd, &config.ruby.version)?;
+        let mut payload = json!({ "digest": digest });
+        gemfile_hash::stamp(&mut payload, &cwd.join("Gemfile"), false);
+        project.record(SignalType::SyncSkipped, payload)?;
+        ctx.reporter.human("✅ already in sync");
+        progress::emit(&ProgressEvent::Summary { operation: "sync".to_string(), success: true, duration_us: 0 });
+        return Ok(());
+    }
+
+    if let Ok(content) = fs::read_to_string(cwd.join("Gemfile")) {
+        check_gemfile(&content)?;
+    }
+    install_with(project, cwd, force_rebuild, !no_preflight, ctx.dry_run)
+}
+
+/// 前回の install から Gemfile / Gemfile.lock / Ruby バージョンが変わっておらず、
+/// 環境ディレクトリも健全であれば `true` を返す。`force` の場合は常に `false`。
+pub(super) fn sync_skippable(cwd: &Path, ruby_version: &str, force: bool) -> Result<bool> {
+    if force {
+        return Ok(false);
+    }
+
+    let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+    let gem_dir = env_dir.join("ruby").join(crate::config::ruby_api_version(ruby_version));
+    let healthy = ruby_bin(&env_dir).exists() && gem_dir.exists();
+
+    let digest = sync_state::compute_digest(cwd, ruby_version)?;
+    Ok(healthy && sync_state::is_current(&env_dir, &digest))
+}
+
+/// `FluxProject` を受け取って bundle install を実行する内部ヘルパー。
+/// `add`/`remove`/`undo` から再利用することで `FluxProject::open()` の二重呼び出しを防ぐ。
+/// 実行前にキャッシュから Gem を復元し、実行後にキャッシュへ保存する。
+/// `preflight` なら、その前に実行環境を確認する (`preflight` モジュール)。
+pub(super) fn install_with(project: &FluxProject, cwd: &Path, force_rebuild: bool, preflight: bool, dry_run: bool) -> Result<()> {
+    check_cache_layout()?;
+    if preflight && !dry_run {
+        super::preflight::run(project, cwd, &ArcConfig::load(&project.flux_dir)?)?;
+    }
+    install_with_cache(project, cwd, &crate::signals::get_global_gems_dir()?, force_rebuild, false, dry_run)
+}
+
+/// Gemfile を変更した後の install。変更前の同期済みの Gemfile.lock をスナップショットに残す。
+/// dry-run の add / remove は Gemfile を変える前に戻るため、ここには来ない。
+pub(super) fn install_after_edit(project: &FluxProject, cwd: &Path) -> Result<()> {
+    crate::snapshot::take(&project.flux_dir, cwd, &cwd.join(crate::signals::ARC_ENV_DIR))?;
+    install_with(project, cwd, false, true, false)
+}
+
+/// `install_with` の Gem キャッシュの場所を指定できる版。
+/// `local` の場合は `bundle install --local` (キャッシュにある Gem だけで解決する) を実行する。
+/// `dry_run` では `.arc/env` とキャッシュを変えない (`dry_run_install`)。
+pub(super) fn install_with_cache(
+    project: &FluxProject,
+    cwd: &Path,
+    gem_cache: &Path,
+    force_rebuild: bool,
+    local: bool,
+    dry_run: bool,
+) -> Result<()> {
+    if !cwd.join("Gemfile").exists() {
+        anyhow::bail!("Gemfile が見つかりません。");
+    }
+
+    // config.toml から Ruby API バージョンを取得
+    let config = ArcConfig::load(&project.flux_dir)?;
+    let ruby_api_ver = crate::config::ruby_api_version(&config.ruby.version);
+    project.ensure_env_usable()?;
+
+    let mut args = vec!["install".to_string()];
+    if local {
+        args.push("--local".to_string());
+    }
+    if dry_run {
+        return dry_run_install(project, cwd, gem_cache, &args);
+    }
+
+    // 途中で中断された install を up-to-date と誤判定しないよう、先にダイジェストを消す
+    let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+    sync_state::clear(&env_dir);
+
+    let timer = std::time::Instant::now();
+    let mut phases = Phases::default();
+    let gems_dir = env_dir.join("ruby").join(&ruby_api_ver).join("gems");
+    let before_restore = installed_gems(&gems_dir);
+
+    // 1. キャッシュから既存の Gem を復元 (Binary Install 相当)
+    let link_mode = config.cache.link_mode;
+    let mut linked = phases
+        .time(super::phases::RESTORE_CACHE, || restore_gems(cwd, gem_cache, &ruby_api_ver, link_mode))
+        .unwrap_or_default();
+
+    // 別の API 向けにビルドされたネイティブ拡張は読み込めないため、bundler の前に確認する
+    let gem_base = env_dir.join("ruby").join(&ruby_api_ver);
+    let mismatches = abi::scan(&gem_base, &ruby_api_ver, &config.ruby.version);
+    let rebuild: Vec<&str> = mismatches
+        .iter()
+        .filter(|m| force_rebuild && m.rebuildable())
+        .map(|m| m.gem.as_str())
+        .collect();
+    abi::remove_for_rebuild(&gem_base, &rebuild)?;
+    report_abi_mismatches(&mismatches, force_rebuild);
+    let after_restore = installed_gems(&gems_dir);
+
+    progress::human(&format!("⚡ arc: bundle install → {}", crate::signals::ARC_ENV_DIR));
+
+    // 誤りは呼び出し元 (sync / add / remove) で止めているので、ここでは警告を記録するだけ
+    let issues = fs::read_to_string(cwd.join("Gemfile")).map(|c| gemfile::validate(&c)).unwrap_or_default();
+    let executed = phases.time(super::phases::BUNDLER, || {
+        super::runner::execute_recorded(
+            project,
+            SignalType::InstallStart,
+            "bundle",
+            &args,
+            cwd,
+            ArcEnv::Isolated,
+            gemfile_issue_fields(&issues),
+        )
+    })?;
+
+    let mut extra = json!({});
+    if executed.success() {
+        // Gem の実行ファイルに対する binstub を .arc/env/bin に生成する
+        let report = phases.time(super::phases::GENERATE_BINSTUBS, || sync_binstubs(cwd, &ruby_api_ver))?;
+        if report.created > 0 || report.removed > 0 {
+            progress::human(&format!("🔗 binstubs: {} created, {} removed", report.created, report.removed));
+        }
+        // キャッシュ効果の計測: 復元で増えた Gem と、bundler が取得した Gem
+        let after_install = installed_gems(&gems_dir);
+        extra = json!({
+            "binstubs": { "created": report.created, "removed": report.removed },
+            "cache": {
+                "restored": after_restore.difference(&before_restore).count(),
+                "fetched": after_install.difference(&after_restore).count(),
+                "restore_us": phases.duration_of(super::phases::RESTORE_CACHE).map(|d| d.as_micros() as u64),
+            },
+        });
+
+        // 2. 新しく入った Gem をキャッシュに保存 (将来のプロジェクト用)
+        linked.merge(
+            &phases
+                .time(super::phases::HARVEST_CACHE, || harvest_gems(cwd, gem_cache, &ruby_api_ver, link_mode))
+                .unwrap_or_default(),
+        );
+        if linked.total() > 0 {
+            progress::human(&fmt_link_report(&linked, link_mode));
+        }
+        extra["link"] = linked.to_json(link_mode);
+
+        // bundle install が Gemfile.lock を更新しうるため、完了後にダイジェストと env.lock を書く
+        let env_lock_hash = phases.time(super::phases::WRITE_SYNC_STATE, || -> Result<String> {
+            let digest = sync_state::compute_digest(cwd, &config.ruby.version)?;
+            sync_state::save(&env_dir, &digest)?;
+            current_env_lock(cwd, &config)?.write(cwd)
+        })?;
+        extra["env_lock"] = json!(env_lock_hash);
+        if let Some(size) = measure_env(cwd, &config) {
+            extra["disk"] = size.to_json();
+        }
+    }
+
+    if !mismatches.is_empty() {
+        extra["abi"] = json!({
+            "mismatches": mismatches.iter().map(|m| m.describe()).collect::<Vec<_>>(),
+            "rebuilt": rebuild,
+        });
+    }
+    if !executed.success() {
+        let (kind, hint) = crate::bundler_config::classify_exit(executed.exit_code());
+        extra["failure_kind"] = json!(kind);
+        progress::human(&format!("❌ bundle install failed (exit {}, {})", executed.exit_code(), kind));
+        if let Some(hint) = hint {
+            progress::human(&format!("   💡 {}", hint));
+        }
+    }
+    progress::human(&format!("⏱  {}", phases.summary()));
+    extra["phases"] = phases.to_json();
+    gemfile_hash::stamp(&mut extra, &cwd.join("Gemfile"), false);
+    progress::emit(&ProgressEvent::Summary {
+        operation: "sync".to_string(),
+        success: executed.success(),
+        duration_us: timer.elapsed().as_micros() as u64,
+    });
+    super::runner::finish_recorded(project, SignalType::InstallEnd, &executed, extra)
+}
+
+/// `--dry-run` の install: `.arc/env` とキャッシュへの書き込みを省き、bundler の起動を表示するだけにする。
+fn dry_run_install(project: &FluxProject, cwd: &Path, gem_cache: &Path, args: &[String]) -> Result<()> {
+    crate::dry_run::note(&format!("would restore cached gems from {}", gem_cache.display()));
+    let executed = super::runner::execute_recorded(project, SignalType::InstallStart, "bundle", args, cwd, ArcEnv::Isolated, json!({}))?;
+    crate::dry_run::note("would generate binstubs, harvest new gems into the cache and write .sync-state / env.lock");
+    super::runner::finish_recorded(project, SignalType::InstallEnd, &executed, json!({}))
+}
+
+/// `[stats] track_disk` が有効なら `.arc/env` のサイズを数える (時間の上限つき)。
+pub(super) fn measure_env(cwd: &Path, config: &ArcConfig) -> Option<crate::fs_util::DirSize> {
+    config.stats.track_disk.then(|| {
+        crate::fs_util::dir_size(&cwd.join(crate::signals::ARC_ENV_DIR), crate::fs_util::DISK_WALK_BUDGET)
+    })
+}
+
+/// 現在の Gemfile.lock と Ruby の実行環境から `.arc/env.lock` の内容を組み立てる。
+pub(super) fn current_env_lock(cwd: &Path, config: &ArcConfig) -> Result<EnvLock> {
+    let lock_path = cwd.join("Gemfile.lock");
+    let lock = if lock_path.exists() { lockfile::parse(&lock_path)? } else { Default::default() };
+    let ruby = fs::canonicalize(ruby_bin(&cwd.join(crate::signals::ARC_ENV_DIR))).unwrap_or_default();
+    Ok(EnvLock::build(&lock, &config.ruby.version, &ruby))
+}
+
+/// 互換性のない Gem を一覧表示する。再ビルドで直せるものには `--force-rebuild` を案内する。
+pub(super) fn report_abi_mismatches(mismatches: &[abi::Mismatch], force_rebuild: bool) {
+    if mismatches.is_empty() {
+        return;
+    }
+    progress::human(&format!("⚠️  {} gem(s) are incompatible with the current Ruby:", mismatches.len()));
+    for m in mismatches {
+        let note = if force_rebuild && m.rebuildable() { " → rebuilding" } else { "" };
+        progress::human(&format!("   - {}{}", m.describe(), note));
+    }
+    if !force_rebuild && mismatches.iter().any(|m| m.rebuildable()) {
+        progress::human("   Run `arc sync --force-rebuild` to rebuild their native extensions.");
+    }
+}
+
+/// `gems/` 直下のディレクトリ名 (例: `json-2.7.1`) の集合。
+fn installed_gems(gems_dir: &Path) -> std::collections::BTreeSet<String> {
+    fs::read_dir(gems_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Gemfile.lock に載っている Gem の binstub を生成し、不要になったものを削除する。
+fn sync_binstubs(cwd: &Path, ruby_api_ver: &str) -> Result<binstubs::StubReport> {
+    let lock_path = cwd.join("Gemfile.lock");
+    let lock_gems: Vec<String> = if lock_path.exists() {
+        lockfile::parse(&lock_path)?.specs.into_iter().map(|s| s.name).collect()
+    } else {
+        vec![]
+    };
+    binstubs::sync(&cwd.join(crate::signals::ARC_ENV_DIR), ruby_api_ver, &lock_gems)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::env::env_disk_line;
+    use crate::commands::env::verify_env_lock;
+    use crate::commands::phases;
+    use crate::commands::runner;
+    use crate::commands::tests::{fake_bundle, synced_project};
+
+    #[test]
+    fn test_sync_skippable() {
+        let cwd = synced_project("arc_sync_skip_test");
+        assert!(sync_skippable(&cwd, "3.3.6", false).unwrap());
+
+        fs::write(cwd.join("Gemfile"), "gem 'rails'\n").unwrap();
+        assert!(!sync_skippable(&cwd, "3.3.6", false).unwrap());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_sync_force_overrides_skip() {
+        let cwd = synced_project("arc_sync_force_test");
+        assert!(!sync_skippable(&cwd, "3.3.6", true).unwrap());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_install_records_phases() {
+        let cwd = synced_project("arc_install_phases_test");
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        fake_bundle(&cwd, "exit 0");
+        sync_state::clear(&env_dir);
+
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        install_with(&project, &cwd, false, false, false).unwrap();
+
+        let signals = project.read_signals().unwrap();
+        let end = signals.iter().find(|s| s.r_type == "install_end").unwrap();
+        let names: Vec<&str> = end.payload["phases"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                phases::RESTORE_CACHE,
+                phases::BUNDLER,
+                phases::GENERATE_BINSTUBS,
+                phases::HARVEST_CACHE,
+                phases::WRITE_SYNC_STATE,
+            ]
+        );
+        assert!(end.payload["phases"][1]["duration_us"].is_u64());
+        assert_eq!(end.payload["cache"]["restored"], 0);
+        assert!(end.payload["cache"]["fetched"].is_u64());
+        assert!(sync_skippable(&cwd, "3.3.6", false).unwrap());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_install_writes_env_lock() {
+        let cwd = synced_project("arc_install_env_lock_test");
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        fake_bundle(&cwd, "exit 0");
+        fs::write(cwd.join("Gemfile.lock"), "GEM\n  specs:\n    json (2.7.1)\n\nBUNDLED WITH\n   2.5.3\n").unwrap();
+
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        install_with_cache(&project, &cwd, &cwd.join("gem-cache"), false, false, false).unwrap();
+
+        let written = fs::read_to_string(crate::env_lock::path(&cwd)).unwrap();
+        assert!(written.contains("name = \"json\""), "{}", written);
+        let end = project.read_signals().unwrap().into_iter().rfind(|s| s.r_type == "install_end").unwrap();
+        assert_eq!(end.payload["env_lock"], crate::blobs::sha256_hex(written.as_bytes()));
+        verify_env_lock(&CommandContext::at(&cwd, false)).unwrap();
+        // .arc/env のサイズも記録する
+        let expected = crate::fs_util::dir_size(&env_dir, crate::fs_util::DISK_WALK_BUDGET);
+        assert_eq!(end.payload["disk"]["bytes"], expected.bytes);
+        assert_eq!(end.payload["disk"]["partial"], false);
+        assert!(env_disk_line(&CommandContext::at(&cwd, false)).unwrap().ends_with("(+0 B since the last sync)"));
+        ArcConfig::update(&project.flux_dir, |c| c.stats.track_disk = false).unwrap();
+        install_with_cache(&project, &cwd, &cwd.join("gem-cache"), false, false, false).unwrap();
+        let end = project.read_signals().unwrap().into_iter().rfind(|s| s.r_type == "install_end").unwrap();
+        assert!(end.payload.get("disk").is_none());
+
+        // bundler を通さずに Gemfile.lock を変えると、env.lock とずれる
+        fs::write(cwd.join("Gemfile.lock"), "GEM\n  specs:\n    json (2.7.2)\n\nBUNDLED WITH\n   2.5.3\n").unwrap();
+        assert!(verify_env_lock(&CommandContext::at(&cwd, false)).is_err());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_install_force_rebuild_abi_mismatch() {
+        let cwd = synced_project("arc_install_rebuild_test");
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        let gem_base = env_dir.join("ruby").join("3.3.0");
+        let stale = gem_base.join("extensions").join("x86_64-linux").join("3.2.0").join("nokogiri-1.16.0");
+        fs::create_dir_all(&stale).unwrap();
+        fs::create_dir_all(gem_base.join("gems").join("nokogiri-1.16.0")).unwrap();
+        // 偽の bundle: Gem が取り除かれていれば、現在の API 向けに「ビルド」する
+        let script = format!(
+            "base={}\n\
+             [ -d $base/gems/nokogiri-1.16.0 ] && exit 0\n\
+             mkdir -p $base/gems/nokogiri-1.16.0 $base/extensions/x86_64-linux/3.3.0/nokogiri-1.16.0\n",
+            gem_base.display()
+        );
+        fake_bundle(&cwd, &script);
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        // 再ビルドした Gem がグローバルキャッシュに入らないよう、テスト専用のキャッシュを使う
+        let gem_cache = cwd.join("gem-cache");
+
+        // 既定では一覧を記録するだけで、何も削除しない
+        install_with_cache(&project, &cwd, &gem_cache, false, false, false).unwrap();
+        assert!(stale.exists());
+        let end = project.read_signals().unwrap().into_iter().rfind(|s| s.r_type == "install_end").unwrap();
+        assert_eq!(
+            end.payload["abi"],
+            serde_json::json!({
+                "mismatches": ["nokogiri-1.16.0 (native extension built for 3.2.0)"],
+                "rebuilt": [],
+            })
+        );
+
+        install_with_cache(&project, &cwd, &gem_cache, true, false, false).unwrap();
+        assert!(!stale.exists());
+        assert!(gem_base.join("extensions/x86_64-linux/3.3.0/nokogiri-1.16.0").exists());
+        let end = project.read_signals().unwrap().into_iter().rfind(|s| s.r_type == "install_end").unwrap();
+        assert_eq!(end.payload["abi"]["rebuilt"], serde_json::json!(["nokogiri-1.16.0"]));
+        assert!(abi::scan(&gem_base, "3.3.0", "3.3.6").is_empty());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_install_progress_json() {
+        let cwd = synced_project("arc_install_progress_test");
+        fake_bundle(&cwd, "exit 0");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+
+        let (result, lines) =
+            progress::capture(|| install_with_cache(&project, &cwd, &cwd.join("gem-cache"), false, false, false));
+        result.unwrap();
+        // すべての行が文書化されたイベントとして読める
+        let events: Vec<ProgressEvent> = lines.iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+
+        let phases: Vec<(&str, &str)> = events
+            .iter()
+            .filter_map(|e| match e {
+                ProgressEvent::PhaseStart { name, .. } => Some(("start", name.as_str())),
+                ProgressEvent::PhaseEnd { name, .. } => Some(("end", name.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(phases.len(), 10);
+        assert_eq!(phases[2], ("start", phases::BUNDLER));
+        assert_eq!(phases[3], ("end", phases::BUNDLER));
+
+        // bundler は BUNDLER フェーズの中で起動・終了する
+        let bundler_start = events
+            .iter()
+            .position(|e| matches!(e, ProgressEvent::PhaseStart { name, .. } if name == phases::BUNDLER))
+            .unwrap();
+        let ProgressEvent::ChildSpawned { pid, command } = &events[bundler_start + 1] else { panic!() };
+        assert_eq!(command, "bundle install");
+        assert_eq!(events[bundler_start + 2], ProgressEvent::ChildExited { pid: *pid, code: Some(0) });
+
+        let Some(ProgressEvent::Summary { operation, success, .. }) = events.last() else { panic!() };
+        assert_eq!(operation, "sync");
+        assert!(success);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_install_through_symlinked_env() {
+        // 実体を scratch 側に作り、プロジェクトの .arc/env からシンボリックリンクで参照する
+        let scratch = synced_project("arc_symlinked_env_scratch");
+        let cwd = std::env::temp_dir().join("arc_symlinked_env_test");
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(cwd.join(".arc")).unwrap();
+        fs::copy(scratch.join("Gemfile"), cwd.join("Gemfile")).unwrap();
+        let target = scratch.join(crate::signals::ARC_ENV_DIR);
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        std::os::unix::fs::symlink(&target, &env_dir).unwrap();
+
+        fake_bundle(&cwd, "exit 0");
+
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        assert_eq!(project.env_storage.symlink_target.as_deref(), Some(target.as_path()));
+        install_with(&project, &cwd, false, false, false).unwrap();
+
+        // リンクはリンクのまま、sync の結果はリンク先に書かれる
+        assert!(fs::symlink_metadata(&env_dir).unwrap().file_type().is_symlink());
+        assert!(sync_skippable(&cwd, "3.3.6", false).unwrap());
+        assert!(runner::ruby_runtime_bin(&target).join("bundle").exists());
+
+        // リンク先が消えたら、分かりやすいエラーで止まる
+        fs::remove_dir_all(&scratch).unwrap();
+        let project = FluxProject::open(&cwd).unwrap();
+        let err = install_with(&project, &cwd, false, false, false).unwrap_err();
+        assert!(err.to_string().contains("リンク先"));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_preflight_failure_stops_install_unless_skipped() {
+        let cwd = synced_project("arc_install_preflight_test");
+        fake_bundle(&cwd, "exit 0");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+
+        // ロックを取っておらず、ruby も空のファイル: install の組を記録せずに止まる
+        let err = install_with(&project, &cwd, false, true, false).unwrap_err().to_string();
+        assert!(err.contains("--no-preflight"), "{}", err);
+        let types: Vec<String> = project.read_signals().unwrap().into_iter().map(|s| s.r_type).collect();
+        assert_eq!(types, ["init", "preflight_failed"]);
+
+        // --no-preflight なら確認せずに install する
+        install_with(&project, &cwd, false, false, false).unwrap();
+        let types: Vec<String> = project.read_signals().unwrap().into_iter().map(|s| s.r_type).collect();
+        assert_eq!(types, ["init", "preflight_failed", "install_start", "install_end"]);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_sync_unhealthy_env_not_skipped() {
+        let cwd = synced_project("arc_sync_unhealthy_test");
+        fs::remove_dir_all(cwd.join(crate::signals::ARC_ENV_DIR).join("ruby")).unwrap();
+        assert!(!sync_skippable(&cwd, "3.3.6", false).unwrap());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
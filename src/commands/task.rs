@@ -0,0 +1,137 @@
+//! `arc task <name>` / `arc test`: プロジェクトの定型コマンドを名前で実行する。
+//!
+//! 同じテストでも `rspec`・`bundle exec rspec`・`bin/rspec` と打ち方が分かれると、
+//! 統計がコマンドごとに散らばる。`[commands]` に定義したタスクをタスク名で記録し、
+//! `arc stats` でタスク名にまとめる。`test` だけは未定義でもプロジェクトから推測する。
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::config::{ArcConfig, CommandSpec};
+use crate::lockfile;
+
+/// テストタスクの名前
+pub const TEST_TASK: &str = "test";
+
+/// タスクの argv がどこから決まったか
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// config.toml の `[commands]`
+    Config,
+    /// プロジェクトのファイルから推測した (`test` のみ)
+    Detected(&'static str),
+}
+
+/// タスク名を解決し、`extra` を argv の末尾に追加する。
+/// `[commands]` の定義を優先し、`test` が未定義ならプロジェクトから推測する。
+pub fn resolve(config: &ArcConfig, cwd: &Path, name: &str, extra: &[String]) -> Result<(CommandSpec, Source)> {
+    let (mut spec, source) = match config.commands.get(name) {
+        Some(entry) => (entry.to_spec(), Source::Config),
+        None if name == TEST_TASK => match detect_test_command(cwd) {
+            Some((argv, reason)) => (CommandSpec::plain(argv), Source::Detected(reason)),
+            None => anyhow::bail!(
+                "テストコマンドを推測できません (bin/rspec・Gemfile.lock の rspec・Rakefile がありません)。\
+                 config.toml の [commands] に test = [\"...\"] を追加してください。"
+            ),
+        },
+        None => {
+            let known: Vec<&str> = config.commands.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                anyhow::bail!("タスク '{}' は定義されていません。config.toml の [commands] に追加してください。", name);
+            }
+            anyhow::bail!("タスク '{}' は定義されていません (定義済み: {})", name, known.join(", "));
+        }
+    };
+    spec.cmd.extend_from_slice(extra);
+    Ok((spec, source))
+}
+
+/// テストコマンドを推測する: `bin/rspec` > Gemfile.lock の rspec > Rakefile の `rake test`。
+pub fn detect_test_command(cwd: &Path) -> Option<(Vec<String>, &'static str)> {
+    let argv = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+    if cwd.join("bin/rspec").is_file() {
+        return Some((argv(&["bin/rspec"]), "bin/rspec"));
+    }
+    let locked_rspec = lockfile::parse(&cwd.join("Gemfile.lock"))
+        .is_ok_and(|lock| lock.specs.iter().any(|s| s.name == "rspec" || s.name == "rspec-core"));
+    if locked_rspec {
+        return Some((argv(&["bundle", "exec", "rspec"]), "rspec in Gemfile.lock"));
+    }
+    if cwd.join("Rakefile").is_file() {
+        return Some((argv(&["bundle", "exec", "rake", "test"]), "Rakefile"));
+    }
+    None
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const LOCK: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rspec-core (3.13.0)
+    rake (13.1.0)
+
+DEPENDENCIES
+  rspec-core
+";
+
+    fn project(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            fs::write(dir.join(path), content).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_detection_order() {
+        let all = project("arc_task_detect_all_test", &[("bin/rspec", ""), ("Gemfile.lock", LOCK), ("Rakefile", "")]);
+        assert_eq!(detect_test_command(&all).unwrap().0, ["bin/rspec"]);
+
+        let locked = project("arc_task_detect_lock_test", &[("Gemfile.lock", LOCK), ("Rakefile", "")]);
+        assert_eq!(detect_test_command(&locked).unwrap().0, ["bundle", "exec", "rspec"]);
+
+        let rake = project("arc_task_detect_rake_test", &[("Gemfile.lock", "GEM\n  specs:\n    rake (13.1.0)\n"), ("Rakefile", "")]);
+        assert_eq!(detect_test_command(&rake).unwrap().0, ["bundle", "exec", "rake", "test"]);
+
+        let empty = project("arc_task_detect_empty_test", &[]);
+        assert_eq!(detect_test_command(&empty), None);
+        assert!(resolve(&ArcConfig::default(), &empty, TEST_TASK, &[]).is_err());
+        for dir in [all, locked, rake, empty] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_config_takes_precedence() {
+        let dir = project("arc_task_precedence_test", &[("bin/rspec", "")]);
+        let mut config = ArcConfig::default();
+        let extra = ["spec/models".to_string()];
+
+        let (spec, source) = resolve(&config, &dir, TEST_TASK, &extra).unwrap();
+        assert_eq!(spec.cmd, ["bin/rspec", "spec/models"]);
+        assert_eq!(source, Source::Detected("bin/rspec"));
+
+        config.commands.insert("test".into(), vec!["bundle".into(), "exec".into(), "rspec".into()].into());
+        config.commands.insert("lint".into(), vec!["rubocop".into()].into());
+        let (spec, source) = resolve(&config, &dir, TEST_TASK, &extra).unwrap();
+        assert_eq!(spec.cmd, ["bundle", "exec", "rspec", "spec/models"]);
+        assert_eq!(source, Source::Config);
+        assert_eq!(resolve(&config, &dir, "lint", &[]).unwrap().0.cmd, ["rubocop"]);
+
+        let err = resolve(&config, &dir, "fmt", &[]).unwrap_err();
+        assert!(err.to_string().contains("lint, test"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,687 @@
+//! `arc tool`: プロジェクトの外で使う Ruby の CLI ツール (rubocop, solargraph など) の管理。
+//!
+//! pipx / `uv tool` と同じく、ツールごと・バージョンごとに独立した GEM_HOME に入れる:
+//!
+//! ```text
+//! ~/.arc/tools/<gem>/<version>/               GEM_HOME (gems/, specifications/, bin/)
+//! ~/.arc/tools/<gem>/<version>/ruby_runtime → ~/.arc/cache/rubies/<id>
+//! ~/.arc/bin/<executable>                     ランチャー (PATH に加える)
+//! ```
+//!
+//! ランチャーは GEM_HOME / GEM_PATH / RUBYLIB / LD_LIBRARY_PATH / PATH を設定し、そのバージョンの Ruby で
+//! Gem の実行ファイルを起動する。プロジェクトの bundler の設定 (`BUNDLE_GEMFILE`, `RUBYOPT`) は引き継がない。
+//! ランチャーを作るのはインストールした Gem 自身の実行ファイルだけで、依存する Gem のもの (`ruby-parse` など) は作らない。
+//!
+//! `gem install` は `.staging-<pid>` に入れてから `<version>` へ rename するため、失敗しても中途半端な
+//! バージョンは残らない。Ruby は既定のバージョンをグローバルキャッシュから使う (なければ取得する)。
+//! 操作はプロジェクトではなくユーザー単位のログ (`~/.arc/.flux/signals.jsonl`) に記録する。
+
+use anyhow::{Context, Result, bail};
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::time::Instant;
+
+use super::context::CommandContext;
+use super::runner::{build_ld_library_path, build_rubylib_path, ruby_bin, ruby_runtime_bin, ruby_runtime_lib, ruby_runtime_root, rubylib_dirs};
+use crate::binstubs;
+use crate::config::ArcConfig;
+use crate::perms;
+use crate::progress;
+use crate::ruby_version;
+use crate::signals::{self, FluxProject, SignalType};
+
+/// ツールを入れるディレクトリ (`~/.arc/tools`)
+pub const TOOLS_DIR: &str = "tools";
+/// ランチャーを置くディレクトリ (`~/.arc/bin`)
+pub const BIN_DIR: &str = "bin";
+/// arc が生成したランチャーであることを示すマーカー。これを含まないファイルは上書き・削除しない。
+const LAUNCHER_MARKER: &str = "# arc tool launcher";
+/// インストール中のディレクトリ (`.staging-<pid>`)
+const STAGING_PREFIX: &str = ".staging-";
+/// ランチャーに引き継がない、プロジェクトの bundler の設定
+const BUNDLER_VARS: [&str; 3] = ["BUNDLE_GEMFILE", "BUNDLE_BIN_PATH", "RUBYOPT"];
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// `arc tool` のディレクトリ構成。`root` はグローバルな arc ディレクトリ (`~/.arc`)
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub root: PathBuf,
+}
+
+impl Layout {
+    fn current() -> Result<Self> {
+        Ok(Self { root: signals::get_global_arc_dir()? })
+    }
+
+    pub fn tools_dir(&self) -> PathBuf {
+        self.root.join(TOOLS_DIR)
+    }
+
+    pub fn bin_dir(&self) -> PathBuf {
+        self.root.join(BIN_DIR)
+    }
+
+    pub fn tool_dir(&self, gem: &str) -> PathBuf {
+        self.tools_dir().join(gem)
+    }
+
+    pub fn version_dir(&self, gem: &str, version: &str) -> PathBuf {
+        self.tool_dir(gem).join(version)
+    }
+}
+
+/// インストール済みのツールの 1 バージョン
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledTool {
+    pub gem: String,
+    pub version: String,
+    /// GEM_HOME (`~/.arc/tools/<gem>/<version>`)
+    pub dir: PathBuf,
+    /// `ruby_runtime` のリンク先 (キャッシュの Ruby の ID)
+    pub ruby: Option<String>,
+    /// Gem 自身の実行ファイル
+    pub executables: Vec<String>,
+    /// `~/.arc/bin` のランチャーがこのバージョンを指している実行ファイル
+    pub linked: Vec<String>,
+}
+
+/// `~/.arc/bin` のランチャー
+#[derive(Debug, Clone, PartialEq)]
+pub struct Launcher {
+    pub name: String,
+    pub gem: String,
+    pub version: String,
+}
+
+// ─────────────────────────────────────────────
+// エントリポイント
+// ─────────────────────────────────────────────
+
+/// `arc tool install <gem> [--version V]`
+pub fn install(ctx: &CommandContext, gem: &str, version: Option<&str>) -> Result<()> {
+    let layout = Layout::current()?;
+    if ctx.dry_run {
+        validate_gem_name(gem)?;
+        crate::dry_run::note(&format!("would install {} into {} and record `x-tool-install`", gem, layout.tools_dir().display()));
+        return Ok(());
+    }
+    let ruby = ArcConfig::default().ruby.version;
+    let runtime = super::gem_cache::cached_ruby(&ruby)?;
+    let tool = install_at(&layout, gem, version, &runtime)?;
+
+    FluxProject::user_log(&layout.root)?.record(
+        SignalType::custom("tool", "install")?,
+        json!({
+            "gem": tool.gem,
+            "version": tool.version,
+            "requested_version": version,
+            "ruby_version": ruby,
+            "executables": tool.executables,
+            "launchers": tool.linked,
+        }),
+    )?;
+
+    println!("✅ Installed {} {} (Ruby {})", tool.gem, tool.version, ruby);
+    if tool.executables.is_empty() {
+        println!("   {} has no executables; nothing was added to {}", tool.gem, layout.bin_dir().display());
+    } else if !tool.linked.is_empty() {
+        println!("   Launchers in {}: {}", layout.bin_dir().display(), tool.linked.join(", "));
+    }
+    if !on_path(&layout.bin_dir()) {
+        println!("💡 Add {} to PATH to use the launchers", layout.bin_dir().display());
+    }
+    Ok(())
+}
+
+/// `arc tool list`
+pub fn list(_ctx: &CommandContext) -> Result<()> {
+    let layout = Layout::current()?;
+    let tools = list_at(&layout)?;
+    if tools.is_empty() {
+        println!("No tools installed. Run `arc tool install <gem>`.");
+        return Ok(());
+    }
+    for line in list_lines(&tools) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// `arc tool uninstall <gem> [--version V]`
+pub fn uninstall(ctx: &CommandContext, gem: &str, version: Option<&str>) -> Result<()> {
+    let layout = Layout::current()?;
+    if ctx.dry_run {
+        crate::dry_run::note(&format!("would uninstall {} from {} and record `x-tool-uninstall`", gem, layout.tools_dir().display()));
+        return Ok(());
+    }
+    let (versions, launchers) = uninstall_at(&layout, gem, version)?;
+    FluxProject::user_log(&layout.root)?.record(
+        SignalType::custom("tool", "uninstall")?,
+        json!({ "gem": gem, "versions": versions, "launchers": launchers }),
+    )?;
+    println!("🗑️  Uninstalled {} {}", gem, versions.join(", "));
+    if !launchers.is_empty() {
+        println!("   Removed launchers: {}", launchers.join(", "));
+    }
+    Ok(())
+}
+
+/// `arc tool run <gem> [--version V] -- args`: 入っていなければ一時ディレクトリに入れて 1 度だけ実行する
+pub fn run(ctx: &CommandContext, gem: &str, version: Option<&str>, args: &[String]) -> Result<()> {
+    let layout = Layout::current()?;
+    if ctx.dry_run {
+        crate::dry_run::note(&format!("would run {} {} and record `x-tool-run`", gem, args.join(" ")));
+        return Ok(());
+    }
+    let ruby = ArcConfig::default().ruby.version;
+    let started = Instant::now();
+    let outcome = run_at(&layout, gem, version, args, &|| super::gem_cache::cached_ruby(&ruby))?;
+    let code = outcome.status.code().unwrap_or(1);
+
+    FluxProject::user_log(&layout.root)?.record(
+        SignalType::custom("tool", "run")?,
+        json!({
+            "gem": gem,
+            "version": outcome.version,
+            "executable": outcome.executable,
+            "args": args,
+            "ephemeral": outcome.ephemeral,
+            "exit_code": code,
+            "success": outcome.status.success(),
+            "duration_ms": started.elapsed().as_millis() as u64,
+        }),
+    )?;
+    if !outcome.status.success() {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// インストール・削除
+// ─────────────────────────────────────────────
+
+/// `gem` を `layout` に入れ、ランチャーを作る。`runtime` はキャッシュの Ruby (`~/.arc/cache/rubies/<id>`)。
+/// 同じバージョンが入っていれば入れ直す。
+pub fn install_at(layout: &Layout, gem: &str, version: Option<&str>, runtime: &Path) -> Result<InstalledTool> {
+    validate_gem_name(gem)?;
+    let staging = layout.tool_dir(gem).join(format!("{}{}", STAGING_PREFIX, std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    perms::create_dir_all(&staging).with_context(|| format!("Failed to create {:?}", staging))?;
+
+    let installed = (|| {
+        std::os::unix::fs::symlink(runtime, ruby_runtime_root(&staging))?;
+        let mut gem_cmd = Command::new(ruby_runtime_bin(&staging).join("gem"));
+        gem_cmd.args(["install", gem, "--no-document"]);
+        if let Some(version) = version {
+            gem_cmd.args(["-v", version]);
+        }
+        gem_cmd.arg("--install-dir").arg(&staging).arg("--bindir").arg(staging.join(BIN_DIR));
+        isolate(&mut gem_cmd, &staging)?;
+        let line = format!("gem install {}{}", gem, version.map(|v| format!(" -v {}", v)).unwrap_or_default());
+        if !progress::run_child(&mut gem_cmd, &line, None).context("gem の起動に失敗しました")?.success() {
+            bail!("`{}` に失敗しました", line);
+        }
+        let installed = installed_version(&staging, gem)
+            .with_context(|| format!("`{}` finished but {} is not in {:?}", line, gem, staging))?;
+        let dest = layout.version_dir(gem, &installed);
+        if fs::symlink_metadata(&dest).is_ok() {
+            fs::remove_dir_all(&dest).with_context(|| format!("Failed to remove {:?}", dest))?;
+        }
+        fs::rename(&staging, &dest).with_context(|| format!("Failed to rename {:?} to {:?}", staging, dest))?;
+        Ok(installed)
+    })();
+    let installed = installed.inspect_err(|_| {
+        let _ = fs::remove_dir_all(&staging);
+        let _ = fs::remove_dir(layout.tool_dir(gem));
+    })?;
+
+    let dir = layout.version_dir(gem, &installed);
+    let executables = gem_executables(&dir, gem);
+    let linked = link_launchers(layout, gem, &installed, &executables)?;
+    Ok(InstalledTool {
+        gem: gem.to_string(),
+        ruby: runtime.file_name().map(|n| n.to_string_lossy().to_string()),
+        version: installed,
+        dir,
+        executables,
+        linked,
+    })
+}
+
+/// `version` (省略時はすべてのバージョン) を消し、それを指すランチャーも消す。
+/// 消したバージョンとランチャーを返す。
+pub fn uninstall_at(layout: &Layout, gem: &str, version: Option<&str>) -> Result<(Vec<String>, Vec<String>)> {
+    validate_gem_name(gem)?;
+    let installed: Vec<InstalledTool> = list_at(layout)?.into_iter().filter(|t| t.gem == gem).collect();
+    if installed.is_empty() {
+        bail!("{} is not installed. See `arc tool list`.", gem);
+    }
+    let targets: Vec<&InstalledTool> = installed.iter().filter(|t| version.is_none_or(|v| t.version == v)).collect();
+    if targets.is_empty() {
+        let versions: Vec<&str> = installed.iter().map(|t| t.version.as_str()).collect();
+        bail!("{} {} is not installed (installed: {})", gem, version.unwrap_or_default(), versions.join(", "));
+    }
+
+    let (mut versions, mut launchers) = (Vec::new(), Vec::new());
+    for tool in targets {
+        for name in &tool.linked {
+            let path = layout.bin_dir().join(name);
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {:?}", path))?;
+            launchers.push(name.clone());
+        }
+        fs::remove_dir_all(&tool.dir).with_context(|| format!("Failed to remove {:?}", tool.dir))?;
+        versions.push(tool.version.clone());
+    }
+    if versions.len() == installed.len() {
+        let _ = fs::remove_dir_all(layout.tool_dir(gem));
+    }
+    Ok((versions, launchers))
+}
+
+/// インストール済みのツール (Gem 名、バージョンの順)
+pub fn list_at(layout: &Layout) -> Result<Vec<InstalledTool>> {
+    let launchers = launchers(layout);
+    let mut tools = Vec::new();
+    for gem_dir in visible_dirs(&layout.tools_dir()) {
+        let gem = gem_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+        for dir in visible_dirs(&gem_dir) {
+            let version = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let linked = launchers.iter().filter(|l| l.gem == gem && l.version == version).map(|l| l.name.clone()).collect();
+            tools.push(InstalledTool {
+                ruby: fs::read_link(ruby_runtime_root(&dir)).ok().and_then(|t| Some(t.file_name()?.to_string_lossy().to_string())),
+                executables: gem_executables(&dir, &gem),
+                gem: gem.clone(),
+                version,
+                dir,
+                linked,
+            });
+        }
+    }
+    tools.sort_by(|a, b| a.gem.cmp(&b.gem).then_with(|| ruby_version::compare(&a.version, &b.version)));
+    Ok(tools)
+}
+
+/// `arc tool list` の各行。ランチャーが指しているバージョンに `*` を付ける。
+fn list_lines(tools: &[InstalledTool]) -> Vec<String> {
+    tools
+        .iter()
+        .map(|t| {
+            let mark = if t.linked.is_empty() { ' ' } else { '*' };
+            let ruby = t.ruby.as_deref().map(|r| format!(" (ruby {})", r)).unwrap_or_default();
+            let exes = if t.executables.is_empty() { "-".to_string() } else { t.executables.join(", ") };
+            format!("{} {} {}{}  {}  {}", mark, t.gem, t.version, ruby, exes, t.dir.display())
+        })
+        .collect()
+}
+
+/// `dir` 直下のディレクトリ (`.` で始まるもの = インストール中のものを除く)
+fn visible_dirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut dirs: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.') && e.path().is_dir())
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// Gem 名として使える文字だけか (ディレクトリ名にするため)
+fn validate_gem_name(gem: &str) -> Result<()> {
+    if gem.is_empty() || gem.starts_with(['.', '-']) || !gem.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        bail!("Invalid gem name: {:?}", gem);
+    }
+    Ok(())
+}
+
+/// `dir` に入っている `gem` のバージョン (`specifications/<gem>-<version>[-<platform>].gemspec`)
+fn installed_version(dir: &Path, gem: &str) -> Option<String> {
+    let prefix = format!("{}-", gem);
+    fs::read_dir(dir.join("specifications")).ok()?.flatten().find_map(|entry| {
+        let name = entry.file_name().into_string().ok()?;
+        let version = name.strip_suffix(".gemspec")?.strip_prefix(&prefix)?.split('-').next()?;
+        version.starts_with(|c: char| c.is_ascii_digit()).then(|| version.to_string())
+    })
+}
+
+/// `dir/bin` のうち `gem` 自身の実行ファイル (RubyGems のラッパーから判断する)
+fn gem_executables(dir: &Path, gem: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir.join(BIN_DIR)) else { return Vec::new() };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|e| fs::read_to_string(e.path()).ok().and_then(|c| binstubs::gem_for_executable(&c)).as_deref() == Some(gem))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+// ─────────────────────────────────────────────
+// ランチャー
+// ─────────────────────────────────────────────
+
+/// `gem` のランチャーを `version` に向ける。`executables` にない古いランチャーは消す。
+/// arc が作っていないファイルや、別のツールのランチャーは上書きしない。作ったランチャーを返す。
+fn link_launchers(layout: &Layout, gem: &str, version: &str, executables: &[String]) -> Result<Vec<String>> {
+    let bin = layout.bin_dir();
+    perms::create_dir_all(&bin).with_context(|| format!("Failed to create {:?}", bin))?;
+    for stale in launchers(layout).iter().filter(|l| l.gem == gem && !executables.contains(&l.name)) {
+        fs::remove_file(bin.join(&stale.name))?;
+    }
+
+    let dir = layout.version_dir(gem, version);
+    let mut linked = Vec::new();
+    for name in executables {
+        let path = bin.join(name);
+        if fs::symlink_metadata(&path).is_ok() {
+            match fs::read_to_string(&path).ok().as_deref().and_then(launcher_target) {
+                None => {
+                    eprintln!("⚠️  Skipped {}: it exists and was not created by arc", path.display());
+                    continue;
+                }
+                Some((owner, _)) if owner != gem => {
+                    eprintln!("⚠️  Skipped {}: it runs the {} tool (`arc tool uninstall {}` first)", path.display(), owner, owner);
+                    continue;
+                }
+                Some(_) => {}
+            }
+        }
+        fs::write(&path, render_launcher(gem, version, &dir, name))
+            .with_context(|| format!("Failed to write {:?}", path))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        linked.push(name.clone());
+    }
+    Ok(linked)
+}
+
+/// `~/.arc/bin` のランチャー (名前順)
+pub fn launchers(layout: &Layout) -> Vec<Launcher> {
+    let Ok(entries) = fs::read_dir(layout.bin_dir()) else { return Vec::new() };
+    let mut launchers: Vec<Launcher> = entries
+        .flatten()
+        .filter_map(|e| {
+            let (gem, version) = launcher_target(&fs::read_to_string(e.path()).ok()?)?;
+            Some(Launcher { name: e.file_name().to_string_lossy().to_string(), gem, version })
+        })
+        .collect();
+    launchers.sort_by(|a, b| a.name.cmp(&b.name));
+    launchers
+}
+
+/// ランチャーのマーカー行から (Gem 名, バージョン) を取り出す
+fn launcher_target(content: &str) -> Option<(String, String)> {
+    let rest = content.lines().find_map(|l| l.strip_prefix(LAUNCHER_MARKER))?;
+    let (gem, version) = rest.trim().strip_prefix("(gem: ")?.strip_suffix(')')?.split_once(' ')?;
+    Some((gem.to_string(), version.to_string()))
+}
+
+/// ランチャーの内容。`dir` の Ruby と GEM_HOME で `dir/bin/<name>` を起動する。
+fn render_launcher(gem: &str, version: &str, dir: &Path, name: &str) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n\
+         {LAUNCHER_MARKER} (gem: {gem} {version})\n\
+         # Generated by `arc tool install`. Do not edit.\n\
+         tool_dir={}\n\
+         unset {}\n\
+         GEM_HOME=\"$tool_dir\"\n\
+         GEM_PATH=\"$tool_dir\"\n\
+         export GEM_HOME GEM_PATH\n",
+        binstubs::shell_quote(&dir.to_string_lossy()),
+        BUNDLER_VARS.join(" "),
+    );
+    if let Some(dirs) = rubylib_dirs(dir) {
+        let dirs: Vec<String> = dirs
+            .iter()
+            .map(|d| d.strip_prefix(dir).map_or_else(|_| d.to_string_lossy().to_string(), |rel| format!("$tool_dir/{}", rel.display())))
+            .collect();
+        script.push_str(&format!("RUBYLIB=\"{}${{RUBYLIB:+:$RUBYLIB}}\"\nexport RUBYLIB\n", dirs.join(":")));
+    }
+    if ruby_runtime_lib(dir).exists() {
+        script.push_str("LD_LIBRARY_PATH=\"$tool_dir/ruby_runtime/lib${LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}\"\nexport LD_LIBRARY_PATH\n");
+    }
+    script.push_str("PATH=\"$tool_dir/bin:$tool_dir/ruby_runtime/bin:$PATH\"\nexport PATH\n");
+    script.push_str(&format!("exec \"$tool_dir/ruby_runtime/bin/ruby\" \"$tool_dir/bin/{}\" \"$@\"\n", name));
+    script
+}
+
+/// `gem install` や `arc tool run` の子プロセスに、ランチャーと同じ環境を設定する
+fn isolate(command: &mut Command, dir: &Path) -> Result<()> {
+    for var in BUNDLER_VARS {
+        command.env_remove(var);
+    }
+    command.env("GEM_HOME", dir).env("GEM_PATH", dir);
+    if let Some(rubylib) = build_rubylib_path(dir) {
+        command.env("RUBYLIB", rubylib);
+    }
+    if let Some(ld_path) = build_ld_library_path(dir) {
+        command.env("LD_LIBRARY_PATH", ld_path);
+    }
+    let mut path = vec![dir.join(BIN_DIR), ruby_runtime_bin(dir)];
+    path.extend(env::var_os("PATH").iter().flat_map(env::split_paths));
+    command.env("PATH", env::join_paths(path)?);
+    Ok(())
+}
+
+/// `dir` が PATH に含まれているか
+fn on_path(dir: &Path) -> bool {
+    env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|p| p == dir))
+}
+
+// ─────────────────────────────────────────────
+// arc tool run
+// ─────────────────────────────────────────────
+
+/// `run_at` の結果
+#[derive(Debug)]
+pub struct RunOutcome {
+    pub version: String,
+    pub executable: String,
+    /// 一時ディレクトリに入れて実行した (実行後に消した)
+    pub ephemeral: bool,
+    pub status: ExitStatus,
+}
+
+/// インストール済みのバージョン (指定がなければランチャーが指すもの、なければ最新) で実行する。
+/// 入っていなければ一時ディレクトリに入れて実行し、終わったら消す。`runtime` はそのときだけ呼ぶ。
+pub fn run_at(
+    layout: &Layout,
+    gem: &str,
+    version: Option<&str>,
+    args: &[String],
+    runtime: &dyn Fn() -> Result<PathBuf>,
+) -> Result<RunOutcome> {
+    validate_gem_name(gem)?;
+    let mut candidates: Vec<InstalledTool> =
+        list_at(layout)?.into_iter().filter(|t| t.gem == gem && version.is_none_or(|v| t.version == v)).collect();
+    candidates.sort_by(|a, b| {
+        (!a.linked.is_empty()).cmp(&!b.linked.is_empty()).then_with(|| ruby_version::compare(&a.version, &b.version))
+    });
+    if let Some(tool) = candidates.pop() {
+        return execute(&tool, args, false);
+    }
+
+    let scratch = Layout { root: env::temp_dir().join(format!("arc-tool-run-{}", signals::new_signal_id())) };
+    let outcome = runtime().and_then(|runtime| install_at(&scratch, gem, version, &runtime)).and_then(|tool| execute(&tool, args, true));
+    let _ = fs::remove_dir_all(&scratch.root);
+    outcome
+}
+
+/// `tool` の実行ファイル (Gem と同じ名前のもの、なければ唯一のもの) を起動する
+fn execute(tool: &InstalledTool, args: &[String], ephemeral: bool) -> Result<RunOutcome> {
+    let executable = match tool.executables.iter().find(|e| **e == tool.gem) {
+        Some(exe) => exe.clone(),
+        None => match tool.executables.as_slice() {
+            [only] => only.clone(),
+            [] => bail!("{} {} has no executables", tool.gem, tool.version),
+            many => bail!("{} {} has several executables ({}); install it and run one of them", tool.gem, tool.version, many.join(", ")),
+        },
+    };
+    let mut command = Command::new(ruby_bin(&tool.dir));
+    command.arg(tool.dir.join(BIN_DIR).join(&executable)).args(args);
+    isolate(&mut command, &tool.dir)?;
+    let status = command.status().with_context(|| format!("Failed to start {}", executable))?;
+    Ok(RunOutcome { version: tool.version.clone(), executable, ephemeral, status })
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `gem install` の代わりに、指定された GEM_HOME に Gem 本体と依存の実行ファイルを置く `gem`、
+    /// 引数と GEM_HOME を表示する `ruby` を持つ Ruby
+    fn stub_runtime(root: &Path) -> PathBuf {
+        let runtime = root.join("cache/rubies/3.3.6-linux-x86_64");
+        let bin = runtime.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(
+            bin.join("gem"),
+            "#!/bin/sh\n\
+             gem=\"$2\"; version=1.60.0; shift 2\n\
+             while [ $# -gt 0 ]; do\n\
+               case \"$1\" in -v) version=\"$2\"; shift ;; --install-dir) dir=\"$2\"; shift ;; --bindir) bindir=\"$2\"; shift ;; esac\n\
+               shift\n\
+             done\n\
+             [ \"$gem\" = missing ] && exit 2\n\
+             mkdir -p \"$dir/specifications\" \"$bindir\"\n\
+             : > \"$dir/specifications/$gem-$version.gemspec\"\n\
+             : > \"$dir/specifications/parser-3.3.0.0.gemspec\"\n\
+             printf \"require 'rubygems'\\nload Gem.activate_bin_path('%s', '%s', version)\\n\" \"$gem\" \"$gem\" > \"$bindir/$gem\"\n\
+             printf \"load Gem.activate_bin_path('parser', 'ruby-parse', version)\\n\" > \"$bindir/ruby-parse\"\n\
+             printf '%s' \"$GEM_HOME\" > \"$dir/gem_home\"\n",
+        )
+        .unwrap();
+        fs::write(bin.join("ruby"), "#!/bin/sh\nprintf '%s|%s|%s' \"$GEM_HOME\" \"${BUNDLE_GEMFILE:-}\" \"$*\"\n").unwrap();
+        for name in ["gem", "ruby"] {
+            fs::set_permissions(bin.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        runtime
+    }
+
+    fn layout(name: &str) -> (Layout, PathBuf) {
+        let root = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let runtime = stub_runtime(&root);
+        (Layout { root: root.join("home") }, runtime)
+    }
+
+    #[test]
+    fn test_install_layout_and_launcher() {
+        let (layout, runtime) = layout("arc_tool_install_test");
+        let tool = install_at(&layout, "rubocop", Some("1.60.0"), &runtime).unwrap();
+
+        let dir = layout.root.join("tools/rubocop/1.60.0");
+        assert_eq!(tool.dir, dir);
+        assert_eq!(fs::read_link(dir.join("ruby_runtime")).unwrap(), runtime);
+        assert_eq!(tool.ruby.as_deref(), Some("3.3.6-linux-x86_64"));
+        // gem install はツール専用の GEM_HOME (インストール中のディレクトリ) で動かす
+        let gem_home = fs::read_to_string(dir.join("gem_home")).unwrap();
+        assert!(gem_home.starts_with(&layout.tool_dir("rubocop").join(STAGING_PREFIX).to_string_lossy().to_string()), "{}", gem_home);
+        assert_eq!(visible_dirs(&layout.tool_dir("rubocop")), std::slice::from_ref(&dir));
+        assert_eq!(fs::read_dir(layout.tool_dir("rubocop")).unwrap().count(), 1);
+
+        // ランチャーは Gem 自身の実行ファイルだけ
+        assert_eq!(tool.executables, ["rubocop"]);
+        assert_eq!(tool.linked, ["rubocop"]);
+        let launcher = layout.bin_dir().join("rubocop");
+        assert!(!layout.bin_dir().join("ruby-parse").exists());
+        assert_eq!(fs::metadata(&launcher).unwrap().permissions().mode() & 0o777, 0o755);
+        let content = fs::read_to_string(&launcher).unwrap();
+        assert!(content.starts_with("#!/bin/sh\n# arc tool launcher (gem: rubocop 1.60.0)\n"), "{}", content);
+        assert!(content.contains(&format!("tool_dir='{}'\n", dir.display())));
+        assert!(content.contains("unset BUNDLE_GEMFILE BUNDLE_BIN_PATH RUBYOPT\n"));
+        assert!(content.ends_with("exec \"$tool_dir/ruby_runtime/bin/ruby\" \"$tool_dir/bin/rubocop\" \"$@\"\n"));
+
+        // ランチャーはプロジェクトの BUNDLE_GEMFILE を引き継がずに、ツールの GEM_HOME で起動する
+        let output = Command::new(&launcher).arg("--version").env("BUNDLE_GEMFILE", "/project/Gemfile").output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}||{}/bin/rubocop --version", dir.display(), dir.display()));
+        assert_eq!(launchers(&layout), [Launcher { name: "rubocop".into(), gem: "rubocop".into(), version: "1.60.0".into() }]);
+    }
+
+    #[test]
+    fn test_versions_relink_and_uninstall() {
+        let (layout, runtime) = layout("arc_tool_uninstall_test");
+        install_at(&layout, "rubocop", Some("1.60.0"), &runtime).unwrap();
+        install_at(&layout, "rubocop", Some("1.61.0"), &runtime).unwrap();
+        install_at(&layout, "solargraph", None, &runtime).unwrap();
+
+        let listed: Vec<(String, String, Vec<String>)> =
+            list_at(&layout).unwrap().into_iter().map(|t| (t.gem, t.version, t.linked)).collect();
+        assert_eq!(
+            listed,
+            [
+                ("rubocop".to_string(), "1.60.0".to_string(), vec![]),
+                ("rubocop".to_string(), "1.61.0".to_string(), vec!["rubocop".to_string()]),
+                ("solargraph".to_string(), "1.60.0".to_string(), vec!["solargraph".to_string()]),
+            ]
+        );
+        assert!(list_lines(&list_at(&layout).unwrap())[1].starts_with("* rubocop 1.61.0 (ruby 3.3.6-linux-x86_64)  rubocop  "));
+
+        // ランチャーが指すバージョンを消すとランチャーも消え、ほかのバージョンは残る
+        let (versions, removed) = uninstall_at(&layout, "rubocop", Some("1.61.0")).unwrap();
+        assert_eq!((versions, removed), (vec!["1.61.0".to_string()], vec!["rubocop".to_string()]));
+        assert!(!layout.bin_dir().join("rubocop").exists());
+        assert!(layout.version_dir("rubocop", "1.60.0").is_dir());
+        let err = uninstall_at(&layout, "rubocop", Some("9.9.9")).unwrap_err().to_string();
+        assert!(err.contains("installed: 1.60.0"), "{}", err);
+
+        let (versions, removed) = uninstall_at(&layout, "rubocop", None).unwrap();
+        assert_eq!((versions, removed), (vec!["1.60.0".to_string()], vec![]));
+        assert!(!layout.tool_dir("rubocop").exists());
+        assert!(layout.bin_dir().join("solargraph").exists());
+        assert!(uninstall_at(&layout, "rubocop", None).unwrap_err().to_string().contains("not installed"));
+    }
+
+    #[test]
+    fn test_foreign_files_are_not_overwritten() {
+        let (layout, runtime) = layout("arc_tool_foreign_test");
+        fs::create_dir_all(layout.bin_dir()).unwrap();
+        fs::write(layout.bin_dir().join("rubocop"), "#!/bin/sh\necho mine\n").unwrap();
+        let tool = install_at(&layout, "rubocop", None, &runtime).unwrap();
+        assert!(tool.linked.is_empty());
+        assert_eq!(fs::read_to_string(layout.bin_dir().join("rubocop")).unwrap(), "#!/bin/sh\necho mine\n");
+        // arc が作っていないファイルは削除もしない
+        uninstall_at(&layout, "rubocop", None).unwrap();
+        assert!(layout.bin_dir().join("rubocop").exists());
+    }
+
+    #[test]
+    fn test_failed_install_leaves_nothing() {
+        let (layout, runtime) = layout("arc_tool_failed_test");
+        let err = install_at(&layout, "missing", None, &runtime).unwrap_err().to_string();
+        assert!(err.contains("gem install missing"), "{}", err);
+        assert!(!layout.tool_dir("missing").exists());
+        assert!(list_at(&layout).unwrap().is_empty());
+        for name in ["", "../etc", ".hidden", "a/b"] {
+            assert!(install_at(&layout, name, None, &runtime).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_run_installed_and_ephemeral() {
+        let (layout, runtime) = layout("arc_tool_run_test");
+        let unused = || -> Result<PathBuf> { panic!("installed tools do not need a runtime") };
+        install_at(&layout, "rubocop", Some("1.60.0"), &runtime).unwrap();
+        let outcome = run_at(&layout, "rubocop", None, &["-a".to_string()], &unused).unwrap();
+        assert!(outcome.status.success() && !outcome.ephemeral);
+        assert_eq!((outcome.version.as_str(), outcome.executable.as_str()), ("1.60.0", "rubocop"));
+
+        // 入っていなければ一時ディレクトリに入れて実行し、ランチャーも作らない
+        let outcome = run_at(&layout, "standard", Some("1.35.0"), &[], &|| Ok(runtime.clone())).unwrap();
+        assert!(outcome.ephemeral);
+        assert_eq!(outcome.version, "1.35.0");
+        assert!(!layout.tool_dir("standard").exists());
+        assert!(!layout.bin_dir().join("standard").exists());
+    }
+}
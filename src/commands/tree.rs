@@ -0,0 +1,60 @@
+//! `arc tree`: Gemfile.lock の依存関係を木で表示する。
+
+use anyhow::Result;
+
+use crate::deptree::{self, Graph};
+use crate::lockfile;
+
+use super::context::CommandContext;
+
+pub fn tree(ctx: &CommandContext, gem: Option<&str>, invert: Option<&str>, depth: Option<usize>, json_output: bool) -> Result<()> {
+    let lock_path = ctx.root()?.join("Gemfile.lock");
+    if !lock_path.exists() {
+        anyhow::bail!("Gemfile.lock が見つかりません。`arc sync` を実行してください。");
+    }
+    let graph = tree_graph(Graph::from_lockfile(&lockfile::parse(&lock_path)?), gem, invert)?;
+
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&graph.reachable(depth).to_json())?);
+    } else {
+        print!("{}", deptree::render(&deptree::walk(&graph, depth)));
+    }
+    Ok(())
+}
+
+/// `arc tree` で表示するグラフ。`gem` / `invert` が Gemfile.lock になければ近い名前を添えてエラーにする。
+fn tree_graph(graph: Graph, gem: Option<&str>, invert: Option<&str>) -> Result<Graph> {
+    let names: Vec<String> = graph.nodes.keys().cloned().collect();
+    let rooted = match (gem, invert) {
+        (_, Some(target)) => graph.invert().rooted_at(target),
+        (Some(root), None) => graph.rooted_at(root),
+        (None, None) => Some(graph),
+    };
+    rooted.ok_or_else(|| {
+        let name = invert.or(gem).unwrap_or_default();
+        anyhow::anyhow!(
+            "Gemfile.lock に '{}' がありません{}",
+            name,
+            crate::suggest::hint(name, names.iter().map(String::as_str))
+        )
+    })
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_gem_names_suggest_closest() {
+        let lock = lockfile::parse_content("GEM\n  remote: https://rubygems.org/\n  specs:\n    rack (3.0.8)\n    rackup (2.1.0)\n      rack (>= 3)\n\nDEPENDENCIES\n  rackup\n");
+        assert!(tree_graph(Graph::from_lockfile(&lock), Some("rack"), None).is_ok());
+        let err = tree_graph(Graph::from_lockfile(&lock), Some("rackupp"), None).unwrap_err().to_string();
+        assert_eq!(err, "Gemfile.lock に 'rackupp' がありません (did you mean 'rackup'?)");
+        let err = tree_graph(Graph::from_lockfile(&lock), None, Some("rak")).unwrap_err().to_string();
+        assert!(err.ends_with("(did you mean 'rack'?)"), "{}", err);
+    }
+}
@@ -0,0 +1,15 @@
+//! `arc ui`: Signal ログを閲覧する TUI (`tui` feature でビルドした場合のみ)。
+
+use anyhow::Result;
+
+use super::context::CommandContext;
+
+#[cfg(feature = "tui")]
+pub fn ui(ctx: &CommandContext) -> Result<()> {
+    crate::ui::run(ctx.root()?)
+}
+
+#[cfg(not(feature = "tui"))]
+pub fn ui(_ctx: &CommandContext) -> Result<()> {
+    anyhow::bail!("arc ui is not available in this build; rebuild with `cargo install --features tui`")
+}
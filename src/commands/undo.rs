@@ -0,0 +1,445 @@
+//! `arc undo`: 直近の add / remove (`--all-since` なら指定した時点以降のすべて) を取り消す。
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::config::ArcConfig;
+use crate::display;
+use crate::gemfile;
+use crate::gemfile_hash;
+use crate::prompt;
+use crate::signals::{FluxProject, LogState, SignalType};
+
+use super::context::CommandContext;
+use super::guard_mutation;
+use super::remove::{edit_and_record, print_gemfile_diff};
+use super::sync::install_with_cache;
+
+// ─────────────────────────────────────────────
+// arc undo (Time Machine)
+// ─────────────────────────────────────────────
+
+/// `arc undo` の指定。
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UndoOptions {
+    pub dry_run: bool,
+    /// 取り消す操作の後で対象の Gem の行が手で編集されていても、確認せずに進める
+    pub force: bool,
+    /// 対象の Gem の行を、記録しておいた操作の前の状態にそのまま戻す
+    pub exact: bool,
+    /// 衝突したときに対話的に確認できる (stdin が TTY)
+    pub interactive: bool,
+}
+
+/// `all_since` は `--all-since` の基準点 (Signal ID・時刻・`init`)。
+pub fn undo(ctx: &CommandContext, yes: bool, opts: UndoOptions, all_since: Option<&str>) -> Result<()> {
+    let opts = UndoOptions { dry_run: opts.dry_run || ctx.dry_run, ..opts };
+    let cwd = ctx.root()?;
+    let project = ctx.project()?;
+    if !opts.dry_run {
+        guard_mutation(project, cwd, yes)?;
+    }
+    let gem_cache = crate::signals::get_global_gems_dir()?;
+    if let Some(point) = all_since {
+        return undo_since_at(project, cwd, point, yes, opts.dry_run, &gem_cache, &mut std::io::stdin().lock(), &mut std::io::stderr());
+    }
+    let opts = UndoOptions { interactive: std::io::stdin().is_terminal(), ..opts };
+    undo_at(project, cwd, opts, &gem_cache)
+}
+
+/// Gemfile の変更を取り消して install する。戻した Gemfile に一致するスナップショットがあり、
+/// その Gem がすべて `gem_cache` にあれば、ロックファイルを戻して `bundle install --local` を実行する。
+pub(super) fn undo_at(project: &FluxProject, cwd: &Path, opts: UndoOptions, gem_cache: &Path) -> Result<()> {
+    if project.log_state() == LogState::Empty {
+        anyhow::bail!(
+            "まだ操作が記録されていません ({} がないか空です)。取り消せる操作はありません。",
+            crate::signals::SIGNAL_FILE
+        );
+    }
+    let signals = project.read_signals()?;
+
+    let target = find_undo_target(&signals)?;
+
+    let gem_name = target.payload["gem"].as_str()
+        .context("シグナルに gem 名が含まれていません。")?;
+
+    let gemfile_path = cwd.join("Gemfile");
+    let content = fs::read_to_string(&gemfile_path).unwrap_or_default();
+    let drift = super::undo_check::check(&signals, target, &content);
+    let edits = super::undo_check::edits(target, opts.exact)?;
+    if let super::undo_check::Drift::Conflict(ref conflict) = drift {
+        for line in super::undo_check::summary_lines(conflict, &gemfile::preview(&gemfile_path, &edits)?) {
+            eprintln!("{}", line);
+        }
+    }
+
+    if opts.dry_run {
+        eprintln!("⏪ Undo (dry-run): {}", target.r_type);
+        return print_gemfile_diff(&gemfile_path, &edits);
+    }
+
+    match super::undo_check::decide(&drift, opts.force, opts.interactive) {
+        super::undo_check::Decision::Proceed => {}
+        super::undo_check::Decision::Ask => {
+            let question = "Undo anyway?";
+            if !prompt::confirm(&mut std::io::stdin().lock(), &mut std::io::stderr(), question)? {
+                anyhow::bail!("中止しました。");
+            }
+        }
+        super::undo_check::Decision::Refuse => anyhow::bail!(
+            "取り消す操作の後で '{}' の行が編集されています。確認のうえ `arc undo --force` (記録した行に戻すなら --exact) を実行してください。",
+            gem_name
+        ),
+    }
+
+    eprintln!("⏪ Undo: {}", target.r_type);
+
+    let external_edit = gemfile_hash::warn_if_modified(&signals, &gemfile_path);
+    match target.r_type.as_str() {
+        "add" => eprintln!("   Removing '{}' from Gemfile...", gem_name),
+        "remove" => eprintln!("   Restoring '{}' to Gemfile...", gem_name),
+        _ => unreachable!(),
+    }
+    let after = gemfile::edited(&gemfile_path, &edits)?;
+
+    let config = ArcConfig::load(&project.flux_dir)?;
+    let plan = match &after {
+        Some(after) => crate::snapshot::plan_for(&project.flux_dir, after.as_bytes(), &config.ruby.version, gem_cache),
+        None => crate::snapshot::plan(&project.flux_dir, cwd, &config.ruby.version, gem_cache),
+    };
+    let estimate = crate::snapshot::full_install_estimate_us(&crate::state::FluxState::from_signals(&signals));
+    let mut payload = json!({
+        "target_id":   target.id,
+        "target_type": target.r_type,
+        "gem":         gem_name,
+        "restore":     plan.to_json(estimate),
+    });
+    if matches!(drift, super::undo_check::Drift::Conflict(_)) {
+        payload["conflict_overridden"] = json!(true);
+    }
+    if opts.exact {
+        payload["exact"] = json!(true);
+    }
+    match after {
+        Some(after) => {
+            let command = format!("arc undo ({} {})", target.r_type, gem_name);
+            edit_and_record(project, &gemfile_path, &after, command, SignalType::Undo, payload, external_edit)?;
+        }
+        None => {
+            gemfile_hash::stamp(&mut payload, &gemfile_path, external_edit);
+            project.record(SignalType::Undo, payload)?;
+        }
+    }
+
+    restore_after_undo(project, cwd, plan, estimate, gem_cache)
+}
+
+/// undo で戻した Gemfile を install する。スナップショットのロックファイルが使えればキャッシュだけで済ませる。
+fn restore_after_undo(
+    project: &FluxProject,
+    cwd: &Path,
+    plan: crate::snapshot::RestorePlan,
+    estimate: Option<u64>,
+    gem_cache: &Path,
+) -> Result<()> {
+    crate::snapshot::take(&project.flux_dir, cwd, &cwd.join(crate::signals::ARC_ENV_DIR))?;
+    match plan {
+        crate::snapshot::RestorePlan::Fast { lockfile, .. } => {
+            let saved = estimate.map_or(String::new(), |us| format!(" (saves ~{})", display::fmt_duration_us(us)));
+            eprintln!("   ⚡ Restoring Gemfile.lock from snapshot; installing from cache{}", saved);
+            fs::write(cwd.join("Gemfile.lock"), lockfile).context("Gemfile.lock の復元に失敗しました")?;
+            install_with_cache(project, cwd, gem_cache, false, true, false)
+        }
+        crate::snapshot::RestorePlan::Resolve { reason } => {
+            eprintln!("   Resolving dependencies ({})", reason);
+            install_with_cache(project, cwd, gem_cache, false, false, false)
+        }
+    }
+}
+
+/// `arc undo --all-since`: 基準点より後の add / remove を一覧と Gem ごとの正味の変化で示し、確認してから
+/// 新しい順に 1 件ずつ undo を記録する。Gemfile は正味の変化がある Gem だけを書き換え、install は最後に 1 度だけ行う。
+#[allow(clippy::too_many_arguments)]
+pub(super) fn undo_since_at<R: std::io::BufRead, W: std::io::Write>(
+    project: &FluxProject,
+    cwd: &Path,
+    point: &str,
+    yes: bool,
+    dry_run: bool,
+    gem_cache: &Path,
+    input: &mut R,
+    out: &mut W,
+) -> Result<()> {
+    let signals = project.read_signals()?;
+    let start = super::undo_since::start_of(&signals, point)?;
+    let targets = super::undo_since::targets(&signals, start)?;
+    if targets.is_empty() {
+        writeln!(out, "ℹ️  No add/remove to undo since {}", point)?;
+        return Ok(());
+    }
+    let rollbacks = super::undo_since::net_effects(&targets);
+    let gem_of = |s: &crate::signals::Signal| s.payload["gem"].as_str().unwrap_or("?").to_string();
+
+    writeln!(out, "⏪ {} operation(s) since {} (newest first):", targets.len(), point)?;
+    for target in &targets {
+        writeln!(out, "   {}  {} {}", display::fmt_timestamp(&target.timestamp), target.r_type, gem_of(target))?;
+    }
+    let width = rollbacks.iter().map(|r| r.gem.chars().count()).max().unwrap_or(0);
+    writeln!(out, "   Net effect:")?;
+    for rollback in &rollbacks {
+        writeln!(out, "     {:<width$}  {}", rollback.gem, rollback.net.describe())?;
+    }
+    let earlier = super::undo_since::earlier_undos(&signals, start);
+    if earlier > 0 {
+        writeln!(out, "⚠️  {} `arc undo` since {} reverted earlier operations; those are left as they are", earlier, point)?;
+    }
+
+    let gemfile_path = cwd.join("Gemfile");
+    let edits: Vec<gemfile::Edit> = rollbacks.iter().map(|r| r.edits()).collect::<Result<Vec<_>>>()?.concat();
+    if dry_run {
+        writeln!(out, "⏪ Undo (dry-run)")?;
+        return print_gemfile_diff(&gemfile_path, &edits);
+    }
+    if !yes && !prompt::confirm(input, out, &format!("Undo {} operation(s)?", targets.len()))? {
+        anyhow::bail!("中止しました。");
+    }
+
+    let mut external_edit = gemfile_hash::warn_if_modified(&signals, &gemfile_path);
+    let config = ArcConfig::load(&project.flux_dir)?;
+    let plan = match gemfile::edited(&gemfile_path, &edits)? {
+        Some(after) => crate::snapshot::plan_for(&project.flux_dir, after.as_bytes(), &config.ruby.version, gem_cache),
+        None => crate::snapshot::plan(&project.flux_dir, cwd, &config.ruby.version, gem_cache),
+    };
+    let estimate = crate::snapshot::full_install_estimate_us(&crate::state::FluxState::from_signals(&signals));
+    for (i, target) in targets.iter().enumerate() {
+        let gem = gem_of(target);
+        let rollback = rollbacks.iter().find(|r| r.gem == gem).expect("net_effects covers every target");
+        let mut payload = json!({
+            "target_id":   target.id,
+            "target_type": target.r_type,
+            "gem":         gem,
+            "since":       point,
+            "net":         rollback.net.as_str(),
+        });
+        // install は最後の undo のもの
+        if i + 1 == targets.len() {
+            payload["restore"] = plan.to_json(estimate);
+        }
+        // Gem の行は、その Gem の最も古い操作を取り消すときにまとめて戻す
+        let gem_edits = if rollback.operations[0].id == target.id { rollback.edits()? } else { vec![] };
+        match gemfile::edited(&gemfile_path, &gem_edits)? {
+            Some(after) => {
+                let command = format!("arc undo --all-since {} ({} {})", point, target.r_type, gem);
+                edit_and_record(project, &gemfile_path, &after, command, SignalType::Undo, payload, external_edit)?;
+            }
+            None => {
+                gemfile_hash::stamp(&mut payload, &gemfile_path, external_edit);
+                project.record(SignalType::Undo, payload)?;
+            }
+        }
+        external_edit = false;
+    }
+    writeln!(out, "⏪ Undid {} operation(s)", targets.len())?;
+
+    restore_after_undo(project, cwd, plan, estimate, gem_cache)
+}
+
+/// 最新の「未取り消し」の add/remove を探す。
+/// それが `arc adopt` で合成された add の場合は取り消せないためエラーにする。
+pub(super) fn find_undo_target(signals: &[crate::signals::Signal]) -> Result<&crate::signals::Signal> {
+    // 既に取り消し済みのシグナル ID を収集する（所有型 String で保持）
+    let already_undone: std::collections::HashSet<&str> = signals.iter()
+        .filter(|s| s.r_type == "undo")
+        .filter_map(|s| s.payload["target_id"].as_str())
+        .collect();
+
+    let target = signals.iter().rev().find(|s| {
+        (s.r_type == "add" || s.r_type == "remove")
+            && !already_undone.contains(s.id.as_str())
+    });
+
+    match target {
+        Some(s) if s.payload["adopted"].as_bool() == Some(true) => anyhow::bail!(
+            "'{}' は `arc adopt` で取り込まれた依存のため取り消せません。`arc remove` を使用してください。",
+            s.payload["gem"].as_str().unwrap_or("?")
+        ),
+        Some(s) => Ok(s),
+        None    => anyhow::bail!("取り消し可能な操作（add/remove）が見つかりません。"),
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::remove::remove_one;
+    use crate::commands::state::print_empty_log_banner;
+    use crate::commands::tests::{fake_bundle, synced_project};
+    use crate::commands::undo_check;
+    use crate::sync_state;
+
+    #[test]
+    fn test_empty_log_banner_and_undo_message() {
+        let cwd = synced_project("arc_empty_log_test");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        fs::write(&project.signal_file, "").unwrap();
+
+        let mut out = Vec::new();
+        print_empty_log_banner(&mut out).unwrap();
+        let banner = String::from_utf8(out).unwrap();
+        assert!(banner.contains("no operations have been recorded yet") && banner.contains("arc add <gem>"), "{}", banner);
+
+        let err = undo_at(&project, &cwd, UndoOptions::default(), &cwd.join("gem-cache")).unwrap_err();
+        assert!(err.to_string().contains("まだ操作が記録されていません"), "{}", err);
+        // 記録があれば従来どおりのメッセージ
+        project.record(SignalType::ExecStart, serde_json::json!({})).unwrap();
+        let err = undo_at(&project, &cwd, UndoOptions::default(), &cwd.join("gem-cache")).unwrap_err();
+        assert!(err.to_string().contains("取り消し可能な操作"), "{}", err);
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_undo_refuses_after_manual_edit_of_target_line() {
+        let cwd = synced_project("arc_undo_manual_edit_test");
+        fake_bundle(&cwd, "exit 0");
+        let gem_cache = cwd.join("gem-cache");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let gemfile_path = cwd.join("Gemfile");
+
+        // arc remove と同じ記録: 削除した行とハッシュ
+        fs::write(&gemfile_path, "gem 'json'\ngroup :test do\n  gem 'rack', '~> 2.0', require: false\nend\n").unwrap();
+        assert!(remove_one(&project, &gemfile_path, "rack", false).unwrap());
+        // 手で別の rack を追加してから undo する
+        fs::write(&gemfile_path, "gem 'json'\ngroup :test do\nend\ngem 'rack'\n").unwrap();
+        let err = undo_at(&project, &cwd, UndoOptions::default(), &gem_cache).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+        assert_eq!(fs::read_to_string(&gemfile_path).unwrap(), "gem 'json'\ngroup :test do\nend\ngem 'rack'\n");
+
+        // --exact: 記録した行 (オプション・グループ込み) に戻す
+        let opts = UndoOptions { force: true, exact: true, ..Default::default() };
+        undo_at(&project, &cwd, opts, &gem_cache).unwrap();
+        assert_eq!(
+            fs::read_to_string(&gemfile_path).unwrap(),
+            "gem 'json'\ngroup :test do\n  gem 'rack', '~> 2.0', require: false\nend\n"
+        );
+        let undo = project.read_signals().unwrap().into_iter().rfind(|s| s.r_type == "undo").unwrap();
+        assert_eq!((undo.payload["conflict_overridden"].as_bool(), undo.payload["exact"].as_bool()), (Some(true), Some(true)));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_undo_all_since_rolls_back_to_checkpoint() {
+        let cwd = synced_project("arc_undo_all_since_test");
+        let log = cwd.join("bundle.log");
+        fake_bundle(&cwd, &format!("echo \"$@\" >> {}", log.display()));
+        let gem_cache = cwd.join("gem-cache");
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let gemfile_path = cwd.join("Gemfile");
+        let before = "gem 'json', require: false\ngem 'puma'\n";
+        fs::write(&gemfile_path, before).unwrap();
+        let checkpoint = project.record(SignalType::Adopt, serde_json::json!({})).unwrap();
+        // arc add と同じ記録
+        let add = |gem: &str| {
+            let edit = gemfile::Edit::InsertGem { name: gem.to_string(), version: None, group: None };
+            let after = gemfile::edited(&gemfile_path, &[edit]).unwrap().unwrap();
+            let lines = undo_check::lines_value(&gemfile::GemfileDoc::parse(&after).gem_lines(gem));
+            let payload = serde_json::json!({ "gem": gem, undo_check::LINES_KEY: lines });
+            edit_and_record(&project, &gemfile_path, &after, format!("arc add {}", gem), SignalType::Add, payload, false).unwrap();
+        };
+
+        // rake を追加・json を削除・pry を追加して削除 (pry は正味の変化なし)
+        add("rake");
+        assert!(remove_one(&project, &gemfile_path, "json", false).unwrap());
+        add("pry");
+        assert!(remove_one(&project, &gemfile_path, "pry", false).unwrap());
+        assert_eq!(fs::read_to_string(&gemfile_path).unwrap(), "gem 'puma'\ngem 'rake'\n");
+
+        // 確認で断れば何もしない
+        let mut out = Vec::new();
+        let err = undo_since_at(&project, &cwd, &checkpoint.id, false, false, &gem_cache, &mut "n\n".as_bytes(), &mut out).unwrap_err();
+        assert!(err.to_string().contains("中止しました"));
+        let listing = String::from_utf8(out).unwrap();
+        assert!(listing.contains("4 operation(s)") && listing.contains("pry   no net change"), "{}", listing);
+        assert!(!log.exists());
+
+        let mut out = Vec::new();
+        undo_since_at(&project, &cwd, &checkpoint.id, false, false, &gem_cache, &mut "y\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(fs::read_to_string(&gemfile_path).unwrap(), "gem 'puma'\ngem 'json', require: false\n");
+        // 取り消しは新しい順に 1 件ずつ記録し、install は 1 度だけ
+        let signals = project.read_signals().unwrap();
+        let undos: Vec<(String, String)> = signals
+            .iter()
+            .filter(|s| s.r_type == "undo")
+            .map(|s| (s.payload["gem"].as_str().unwrap().to_string(), s.payload["net"].as_str().unwrap().to_string()))
+            .collect();
+        let expected = [("pry", "unchanged"), ("pry", "unchanged"), ("json", "removed"), ("rake", "added")];
+        assert_eq!(undos, expected.map(|(g, n)| (g.to_string(), n.to_string())));
+        assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), 1);
+        assert!(signals.iter().rfind(|s| s.r_type == "undo").unwrap().payload["restore"].is_object());
+
+        // もう取り消すものはない
+        let mut out = Vec::new();
+        undo_since_at(&project, &cwd, &checkpoint.id, true, false, &gem_cache, &mut "".as_bytes(), &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("No add/remove to undo"));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_undo_restores_snapshot_from_cache() {
+        const LOCK: &str = "GEM\n  specs:\n    json (2.7.1)\n";
+        let cwd = synced_project("arc_undo_snapshot_test");
+        let env_dir = cwd.join(crate::signals::ARC_ENV_DIR);
+        fs::write(cwd.join("Gemfile.lock"), LOCK).unwrap();
+        sync_state::save(&env_dir, &sync_state::compute_digest(&cwd, "3.3.6").unwrap()).unwrap();
+        // 偽の bundle: 引数を記録し、--local でなければ依存を「解決」してロックファイルを書き換える
+        let log = cwd.join("bundle.log");
+        let script = format!(
+            "echo \"$@\" >> {log}\n\
+             case \"$*\" in *--local*) ;; *) printf 'GEM\\n  specs:\\n    resolved (1.0)\\n' > {lock} ;; esac\n",
+            log = log.display(),
+            lock = cwd.join("Gemfile.lock").display()
+        );
+        fake_bundle(&cwd, &script);
+        let gem_cache = cwd.join("gem-cache");
+        fs::create_dir_all(gem_cache.join("gems/json-2.7.1")).unwrap();
+        fs::create_dir_all(gem_cache.join("specifications")).unwrap();
+        fs::write(gem_cache.join("specifications/json-2.7.1.gemspec"), "").unwrap();
+
+        let project = FluxProject::init(&cwd, &Default::default(), serde_json::json!({})).unwrap().0;
+        let add_rake = || {
+            crate::snapshot::take(&project.flux_dir, &cwd, &env_dir).unwrap();
+            fs::write(cwd.join("Gemfile"), "gem 'json'\ngem 'rake'\n").unwrap();
+            project.record(SignalType::Add, serde_json::json!({ "gem": "rake" })).unwrap();
+            install_with_cache(&project, &cwd, &gem_cache, false, false, false).unwrap();
+        };
+        let last_bundle_args = || fs::read_to_string(&log).unwrap().lines().last().unwrap().to_string();
+        let last_restore = || {
+            project.read_signals().unwrap().into_iter().rfind(|s| s.r_type == "undo").unwrap().payload["restore"].clone()
+        };
+
+        // スナップショットのロックファイルを戻し、キャッシュだけで install する
+        add_rake();
+        undo_at(&project, &cwd, UndoOptions::default(), &gem_cache).unwrap();
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile")).unwrap(), "gem 'json'\n");
+        assert_eq!(fs::read_to_string(cwd.join("Gemfile.lock")).unwrap(), LOCK);
+        assert!(last_bundle_args().contains("--local"), "{}", last_bundle_args());
+        assert_eq!(last_restore()["path"], "snapshot");
+        // 通常の install の記録があれば、短縮できた時間の見込みを記録する
+        assert!(last_restore()["estimated_saved_us"].is_u64());
+
+        // キャッシュに Gem が欠けていれば、通常どおり依存を解決する
+        add_rake();
+        fs::remove_dir_all(gem_cache.join("gems/json-2.7.1")).unwrap();
+        undo_at(&project, &cwd, UndoOptions::default(), &gem_cache).unwrap();
+        assert!(!last_bundle_args().contains("--local"));
+        assert_eq!(last_restore()["path"], "resolve");
+        assert!(last_restore()["reason"].as_str().unwrap().contains("missing from the cache"));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
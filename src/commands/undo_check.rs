@@ -0,0 +1,263 @@
+//! `arc undo` の前に、取り消す操作の後で Gemfile が手で編集されていないかを確かめる。
+//!
+//! add / remove は、操作した Gem の `gem` 行を payload の `gemfile_lines` に記録する
+//! (add は追加した行、remove は削除した行)。undo の時点で Gemfile のハッシュが最後の記録と
+//! 異なれば、その Gem の行が操作の直後のままかを比べる:
+//!
+//! - 行が操作の直後のまま → 別の箇所の編集なので、そのまま取り消す
+//! - 行が変わっている (バージョン・オプションの変更、削除、再追加) → 確認を求める (`--force` で省略)
+//!
+//! `--exact` は、その Gem の行を操作の前の状態 (記録した行) にそのまま戻す。
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+
+use crate::gemfile::{Edit, GemLine, GemfileDoc};
+use crate::gemfile_hash;
+use crate::signals::Signal;
+
+/// payload に記録する `gem` 行のフィールド名
+pub const LINES_KEY: &str = "gemfile_lines";
+
+/// 記録用の `gem` 行 (`LINES_KEY` の値)
+pub fn lines_value(lines: &[GemLine]) -> Value {
+    serde_json::to_value(lines).unwrap_or_default()
+}
+
+/// Signal に記録された `gem` 行。記録のない古い Signal は `None`。
+fn recorded_lines(signal: &Signal) -> Option<Vec<GemLine>> {
+    serde_json::from_value(signal.payload.get(LINES_KEY)?.clone()).ok()
+}
+
+/// 取り消す操作の後の Gemfile の変化
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    /// 最後の記録から変わっていない (または記録がなく判断できない)
+    Unchanged,
+    /// 変わっているが、対象の Gem の行は操作の直後のまま
+    EditedElsewhere { since: String },
+    /// 対象の Gem の行が操作の直後と違う
+    Conflict(Conflict),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// 取り消す操作 (例: `add rack`)
+    pub operation: String,
+    /// 操作の直後の行 (記録がなければ `None`)
+    pub recorded: Option<Vec<String>>,
+    /// 現在の行
+    pub current: Vec<String>,
+    pub since: String,
+}
+
+/// `target` (add / remove) の後に Gemfile (`content`) が手で編集されていないかを調べる。
+pub fn check(signals: &[Signal], target: &Signal, content: &str) -> Drift {
+    let Some(last) = gemfile_hash::last_recorded(signals) else { return Drift::Unchanged };
+    if last.payload[gemfile_hash::PAYLOAD_KEY].as_str() == Some(crate::blobs::sha256_hex(content.as_bytes()).as_str()) {
+        return Drift::Unchanged;
+    }
+    let since = last.timestamp.clone();
+    let name = target.payload["gem"].as_str().unwrap_or_default();
+    let current: Vec<String> = GemfileDoc::parse(content).gem_lines(name).into_iter().map(|l| l.text).collect();
+    let recorded = recorded_lines(target).map(|lines| lines.into_iter().map(|l| l.text).collect::<Vec<_>>());
+
+    let intact = match target.r_type.as_str() {
+        // add の直後の行が記録されていなければ、行が残っているかだけを見る
+        "add" => recorded.as_ref().map_or(!current.is_empty(), |r| *r == current),
+        _ => current.is_empty(),
+    };
+    if intact {
+        return Drift::EditedElsewhere { since };
+    }
+    let recorded = match target.r_type.as_str() {
+        "add" => recorded,
+        _ => Some(vec![]),
+    };
+    Drift::Conflict(Conflict { operation: format!("{} {}", target.r_type, name), recorded, current, since })
+}
+
+/// undo で Gemfile に適用する編集。
+/// `exact` なら、その Gem の行を操作の前の状態 (remove で記録した行) にそのまま戻す。
+pub fn edits(target: &Signal, exact: bool) -> Result<Vec<Edit>> {
+    let name = target.payload["gem"].as_str().unwrap_or_default().to_string();
+    match (target.r_type.as_str(), exact) {
+        ("add", _) => Ok(vec![Edit::RemoveGem { name }]),
+        (_, false) => Ok(vec![Edit::InsertGem {
+            name,
+            version: target.payload["version"].as_str().map(String::from),
+            group: None,
+        }]),
+        (_, true) => {
+            let Some(lines) = recorded_lines(target).filter(|l| !l.is_empty()) else {
+                bail!("--exact: この remove は削除した行を記録していません (古い arc で記録された操作です)");
+            };
+            let mut edits = vec![Edit::RemoveGem { name }];
+            edits.extend(lines.into_iter().map(Edit::RestoreLine));
+            Ok(edits)
+        }
+    }
+}
+
+/// 操作したこと・現在の Gemfile・undo で変わること、の 3 方向の要約。
+pub fn summary_lines(conflict: &Conflict, diff: &str) -> Vec<String> {
+    let show = |lines: &[String]| match lines {
+        [] => "(no line)".to_string(),
+        lines => lines.join(" / "),
+    };
+    let mut out = vec![
+        format!("⚠️  The Gemfile was edited after `{}` (last recorded {}):", conflict.operation, conflict.since),
+        format!(
+            "   operation left:  {}",
+            conflict.recorded.as_deref().map_or("(not recorded)".to_string(), show)
+        ),
+        format!("   Gemfile now:     {}", show(&conflict.current)),
+        "   undo would change:".to_string(),
+    ];
+    match diff.is_empty() {
+        true => out.push("     (nothing)".to_string()),
+        false => out.extend(diff.lines().map(|l| format!("     {}", l))),
+    }
+    out
+}
+
+/// 衝突があったときに進めるかどうか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Proceed,
+    /// 対話的に確認する
+    Ask,
+    /// 非対話なので中止する (`--force` を案内する)
+    Refuse,
+}
+
+pub fn decide(drift: &Drift, force: bool, interactive: bool) -> Decision {
+    match drift {
+        Drift::Conflict(_) if force => Decision::Proceed,
+        Drift::Conflict(_) if interactive => Decision::Ask,
+        Drift::Conflict(_) => Decision::Refuse,
+        _ => Decision::Proceed,
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn signal(r_type: &str, payload: Value) -> Signal {
+        Signal {
+            id: uuid::Uuid::now_v7().to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: "2024-05-01T12:00:00+09:00".to_string(),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    /// `content` を作った add / remove の Signal (ハッシュと行を記録)
+    fn recorded(r_type: &str, gem: &str, content_after: &str, lines: &[&str]) -> Signal {
+        let lines: Vec<GemLine> = lines.iter().map(|t| GemLine { text: t.to_string(), group: None }).collect();
+        signal(
+            r_type,
+            json!({
+                "gem": gem,
+                "version": "~> 2.0",
+                gemfile_hash::PAYLOAD_KEY: crate::blobs::sha256_hex(content_after.as_bytes()),
+                LINES_KEY: lines_value(&lines),
+            }),
+        )
+    }
+
+    const AFTER_ADD: &str = "source 'https://rubygems.org'\ngem 'rails'\ngem 'rack', '~> 2.0'\n";
+
+    #[test]
+    fn test_unchanged_file_proceeds_without_prompt() {
+        let add = recorded("add", "rack", AFTER_ADD, &["gem 'rack', '~> 2.0'"]);
+        let signals = vec![add.clone()];
+        let drift = check(&signals, &add, AFTER_ADD);
+        assert_eq!(drift, Drift::Unchanged);
+        assert_eq!(decide(&drift, false, false), Decision::Proceed);
+        assert_eq!(decide(&drift, false, true), Decision::Proceed);
+    }
+
+    #[test]
+    fn test_edit_elsewhere_is_not_a_conflict() {
+        let add = recorded("add", "rack", AFTER_ADD, &["gem 'rack', '~> 2.0'"]);
+        let edited = format!("{}gem 'puma'\n", AFTER_ADD);
+        let drift = check(std::slice::from_ref(&add), &add, &edited);
+        assert!(matches!(drift, Drift::EditedElsewhere { .. }), "{:?}", drift);
+        assert_eq!(decide(&drift, false, false), Decision::Proceed);
+    }
+
+    #[test]
+    fn test_modified_line_after_add_conflicts() {
+        let add = recorded("add", "rack", AFTER_ADD, &["gem 'rack', '~> 2.0'"]);
+        let edited = AFTER_ADD.replace("gem 'rack', '~> 2.0'", "gem 'rack', '~> 3.0', require: false");
+        let Drift::Conflict(conflict) = check(std::slice::from_ref(&add), &add, &edited) else { panic!() };
+        assert_eq!(conflict.operation, "add rack");
+        assert_eq!(conflict.recorded, Some(vec!["gem 'rack', '~> 2.0'".to_string()]));
+        assert_eq!(conflict.current, ["gem 'rack', '~> 3.0', require: false"]);
+
+        let drift = Drift::Conflict(conflict.clone());
+        assert_eq!(decide(&drift, false, false), Decision::Refuse);
+        assert_eq!(decide(&drift, false, true), Decision::Ask);
+        assert_eq!(decide(&drift, true, false), Decision::Proceed);
+
+        let lines = summary_lines(&conflict, "-gem 'rack', '~> 3.0', require: false\n");
+        assert_eq!(lines[1], "   operation left:  gem 'rack', '~> 2.0'");
+        assert_eq!(lines[2], "   Gemfile now:     gem 'rack', '~> 3.0', require: false");
+        assert_eq!(lines[4], "     -gem 'rack', '~> 3.0', require: false");
+
+        // 手で削除した場合も衝突 (undo は何も変えない)
+        let removed = AFTER_ADD.replace("gem 'rack', '~> 2.0'\n", "");
+        let Drift::Conflict(conflict) = check(std::slice::from_ref(&add), &add, &removed) else { panic!() };
+        assert!(conflict.current.is_empty());
+        assert_eq!(summary_lines(&conflict, "")[4], "     (nothing)");
+    }
+
+    #[test]
+    fn test_readded_line_after_remove_conflicts() {
+        let after_remove = "source 'https://rubygems.org'\ngem 'rails'\n";
+        let remove = recorded("remove", "rack", after_remove, &["gem 'rack', '~> 2.0', require: false"]);
+        let signals = vec![remove.clone()];
+        assert_eq!(check(&signals, &remove, after_remove), Drift::Unchanged);
+        let readded = format!("{}gem 'rack'\n", after_remove);
+        let Drift::Conflict(conflict) = check(&signals, &remove, &readded) else { panic!() };
+        assert_eq!(conflict.recorded, Some(vec![]));
+        assert_eq!(conflict.current, ["gem 'rack'"]);
+    }
+
+    #[test]
+    fn test_old_signals_without_lines() {
+        // 行の記録もハッシュもない古いログは判断しない
+        let add = signal("add", json!({ "gem": "rack" }));
+        assert_eq!(check(std::slice::from_ref(&add), &add, "gem 'rack'\n"), Drift::Unchanged);
+        // ハッシュだけがある場合、行が残っていれば別の箇所の編集とみなす
+        let add = signal("add", json!({ "gem": "rack", gemfile_hash::PAYLOAD_KEY: "old" }));
+        assert!(matches!(check(std::slice::from_ref(&add), &add, "gem 'rack'\n"), Drift::EditedElsewhere { .. }));
+        let Drift::Conflict(conflict) = check(std::slice::from_ref(&add), &add, "gem 'rails'\n") else { panic!() };
+        assert_eq!(conflict.recorded, None);
+        assert!(edits(&signal("remove", json!({ "gem": "rack" })), true).is_err());
+    }
+
+    #[test]
+    fn test_exact_edits_restore_recorded_lines() {
+        let remove = recorded("remove", "rack", "", &["gem 'rack', '~> 2.0', require: false"]);
+        let mut doc = GemfileDoc::parse("source 'https://rubygems.org'\ngem 'rack'\n");
+        for edit in edits(&remove, true).unwrap() {
+            doc.apply(&edit);
+        }
+        assert_eq!(doc.render(), "source 'https://rubygems.org'\ngem 'rack', '~> 2.0', require: false\n");
+        // --exact なしではバージョンだけを戻す (既にあれば何もしない)
+        assert_eq!(
+            edits(&remove, false).unwrap(),
+            [Edit::InsertGem { name: "rack".into(), version: Some("~> 2.0".into()), group: None }]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! `arc undo --all-since <point>`: 基準点より後の Gemfile の変更 (add / remove) をまとめて取り消す。
+//!
+//! 取り消す操作は新しい順に並べ、Gem ごとに基準点の前と今の状態を比べた正味の変化 (`Net`) にまとめる。
+//! 基準点の後に追加して削除した Gem は正味の変化がないため、Gemfile には触れない (行を消して戻すと
+//! オプションやグループが崩れる)。変化のある Gem は、その Gem の最も古い操作を取り消す編集だけで
+//! 基準点の前の行に戻る。
+//!
+//! 基準点は Signal ID (一意に決まる先頭部分でもよい)、RFC 3339 の時刻、`init` (最初の init の直後)。
+
+use anyhow::{Result, bail};
+use chrono::DateTime;
+use std::collections::HashSet;
+
+use super::undo_check;
+use crate::gemfile::Edit;
+use crate::signals::Signal;
+
+/// 最初の `init` を基準点にする指定
+pub const INIT: &str = "init";
+
+/// 基準点より後の最初の Signal の位置。この位置から後が取り消す範囲になる。
+pub fn start_of(signals: &[Signal], point: &str) -> Result<usize> {
+    if point == INIT {
+        return match signals.iter().position(|s| s.r_type == "init") {
+            Some(position) => Ok(position + 1),
+            None => bail!("ログに init がありません"),
+        };
+    }
+    if let Ok(at) = DateTime::parse_from_rfc3339(point) {
+        // ログの順で、基準の時刻より後に記録された最初の Signal から
+        return Ok(signals
+            .iter()
+            .position(|s| DateTime::parse_from_rfc3339(&s.timestamp).is_ok_and(|ts| ts > at))
+            .unwrap_or(signals.len()));
+    }
+    crate::stats_compare::position_of(signals, point).map(|position| position + 1)
+}
+
+/// `signals[start..]` にある、まだ取り消していない add / remove (新しい順)。
+/// `arc adopt` で取り込んだ add は取り消せないためエラーにする。
+pub fn targets(signals: &[Signal], start: usize) -> Result<Vec<&Signal>> {
+    let undone: HashSet<&str> = signals
+        .iter()
+        .filter(|s| s.r_type == "undo")
+        .filter_map(|s| s.payload["target_id"].as_str())
+        .collect();
+    let targets: Vec<&Signal> = signals[start.min(signals.len())..]
+        .iter()
+        .rev()
+        .filter(|s| matches!(s.r_type.as_str(), "add" | "remove") && !undone.contains(s.id.as_str()))
+        .collect();
+    if let Some(adopted) = targets.iter().find(|s| s.payload["adopted"].as_bool() == Some(true)) {
+        bail!(
+            "'{}' は `arc adopt` で取り込まれた依存のため取り消せません。もっと後の基準点を指定してください。",
+            adopted.payload["gem"].as_str().unwrap_or("?")
+        );
+    }
+    Ok(targets)
+}
+
+/// 範囲の中の `arc undo` のうち、基準点より前の操作を取り消したもの (これは元に戻さない)
+pub fn earlier_undos(signals: &[Signal], start: usize) -> usize {
+    let start = start.min(signals.len());
+    let before: HashSet<&str> = signals[..start].iter().map(|s| s.id.as_str()).collect();
+    signals[start..]
+        .iter()
+        .filter(|s| s.r_type == "undo" && s.payload["target_id"].as_str().is_some_and(|id| before.contains(id)))
+        .count()
+}
+
+/// 1 つの Gem の、基準点の前から今までの正味の変化
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Net {
+    /// 基準点の後に追加された (取り消すと削除する)
+    Added,
+    /// 基準点の後に削除された (取り消すと戻す)
+    Removed,
+    /// 削除して別の行で追加し直した (取り消すと元の行に戻す)
+    Changed,
+    /// 基準点の前と同じ (Gemfile には触れない)
+    Unchanged,
+}
+
+impl Net {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Net::Added => "remove (added since the checkpoint)",
+            Net::Removed => "restore (removed since the checkpoint)",
+            Net::Changed => "restore the previous line (changed since the checkpoint)",
+            Net::Unchanged => "no net change",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Net::Added => "added",
+            Net::Removed => "removed",
+            Net::Changed => "changed",
+            Net::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Gem ごとの取り消し
+#[derive(Debug, Clone)]
+pub struct GemRollback<'a> {
+    pub gem: String,
+    pub net: Net,
+    /// その Gem の操作 (古い順)
+    pub operations: Vec<&'a Signal>,
+}
+
+impl GemRollback<'_> {
+    /// 基準点の前の行に戻す編集。正味の変化がなければ空。
+    pub fn edits(&self) -> Result<Vec<Edit>> {
+        let oldest = self.operations[0];
+        match self.net {
+            Net::Unchanged => Ok(vec![]),
+            Net::Added => undo_check::edits(oldest, false),
+            // 記録した行があればオプション・グループごと戻す
+            Net::Removed | Net::Changed => undo_check::edits(oldest, true).or_else(|_| {
+                let mut edits = vec![Edit::RemoveGem { name: self.gem.clone() }];
+                edits.extend(undo_check::edits(oldest, false)?);
+                Ok(edits)
+            }),
+        }
+    }
+}
+
+/// 取り消す操作 (新しい順) を Gem ごとの正味の変化にまとめる。Gem は最初に操作した順に並ぶ。
+pub fn net_effects<'a>(targets: &[&'a Signal]) -> Vec<GemRollback<'a>> {
+    let mut rollbacks: Vec<GemRollback<'a>> = Vec::new();
+    for signal in targets.iter().rev() {
+        let gem = signal.payload["gem"].as_str().unwrap_or_default();
+        match rollbacks.iter_mut().find(|r| r.gem == gem) {
+            Some(rollback) => rollback.operations.push(signal),
+            None => rollbacks.push(GemRollback { gem: gem.to_string(), net: Net::Unchanged, operations: vec![signal] }),
+        }
+    }
+    for rollback in &mut rollbacks {
+        let (oldest, newest) = (rollback.operations[0], rollback.operations[rollback.operations.len() - 1]);
+        // 最も古い操作が add なら基準点の前にはなく、最も新しい操作が add なら今はある
+        rollback.net = match (oldest.r_type == "add", newest.r_type == "add") {
+            (true, true) => Net::Added,
+            (true, false) => Net::Unchanged,
+            (false, false) => Net::Removed,
+            // 削除した行と追加し直した行を比べる。記録がなければ同じとみなし、触れない
+            (false, true) => match (lines(oldest), lines(newest)) {
+                (Some(before), Some(after)) if before != after => Net::Changed,
+                _ => Net::Unchanged,
+            },
+        };
+    }
+    rollbacks
+}
+
+fn lines(signal: &Signal) -> Option<Vec<String>> {
+    let lines = signal.payload.get(undo_check::LINES_KEY)?.as_array()?;
+    Some(lines.iter().filter_map(|l| l["text"].as_str().map(String::from)).collect())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn signal(id: &str, r_type: &str, minute: u32, payload: Value) -> Signal {
+        Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: format!("2026-03-01T10:{:02}:00+09:00", minute),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    fn op(id: &str, r_type: &str, minute: u32, gem: &str, line: &str) -> Signal {
+        signal(id, r_type, minute, json!({ "gem": gem, "gemfile_lines": [{ "text": line }] }))
+    }
+
+    fn log() -> Vec<Signal> {
+        vec![
+            signal("a0", "init", 0, json!({})),
+            op("a1", "add", 1, "rack", "gem 'rack'"),
+            op("b1", "add", 2, "pry", "gem 'pry'"),
+            op("b2", "remove", 3, "pry", "gem 'pry'"),
+            op("b3", "remove", 4, "puma", "gem 'puma', '~> 6.0'"),
+            op("b4", "add", 5, "puma", "gem 'puma', '~> 6.4'"),
+            op("b5", "add", 6, "sinatra", "gem 'sinatra'"),
+            op("b6", "remove", 7, "json", "gem 'json', require: false"),
+            op("b7", "add", 8, "json", "gem 'json', require: false"),
+            op("b8", "remove", 9, "sinatra", "gem 'sinatra'"),
+            op("b9", "add", 10, "sinatra", "gem 'sinatra', '~> 4.0'"),
+        ]
+    }
+
+    #[test]
+    fn test_start_of_checkpoint() {
+        let log = log();
+        assert_eq!(start_of(&log, "init").unwrap(), 1);
+        assert_eq!(start_of(&log, "a1").unwrap(), 2);
+        assert_eq!(start_of(&log, "b").unwrap_err().to_string(), "Signal ID 'b' に一致する Signal が 9 件あります。もっと長く指定してください");
+        assert_eq!(start_of(&log, "2026-03-01T10:04:30+09:00").unwrap(), 5);
+        assert_eq!(start_of(&log, "2026-03-01T02:00:00Z").unwrap(), log.len());
+        assert!(start_of(&log, "nope").is_err());
+        assert!(start_of(&log[1..], "init").is_err());
+    }
+
+    #[test]
+    fn test_targets_newest_first_skip_undone() {
+        let mut log = log();
+        log.push(signal("u1", "undo", 11, json!({ "target_id": "b9" })));
+        log.push(signal("u2", "undo", 12, json!({ "target_id": "a1" })));
+        let ids: Vec<&str> = targets(&log, 2).unwrap().iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["b8", "b7", "b6", "b5", "b4", "b3", "b2", "b1"]);
+        assert_eq!(earlier_undos(&log, 2), 1);
+
+        log.push(signal("c1", "add", 13, json!({ "gem": "rails", "adopted": true })));
+        assert!(targets(&log, 2).unwrap_err().to_string().contains("'rails'"));
+    }
+
+    #[test]
+    fn test_net_effect_collapses() {
+        let log = log();
+        let targets = targets(&log, 1).unwrap();
+        let rollbacks = net_effects(&targets);
+        let nets: Vec<(&str, Net, usize)> = rollbacks.iter().map(|r| (r.gem.as_str(), r.net, r.operations.len())).collect();
+        assert_eq!(
+            nets,
+            [
+                ("rack", Net::Added, 1),
+                ("pry", Net::Unchanged, 2),
+                ("puma", Net::Changed, 2),
+                // 追加・削除・別の行で追加: 基準点の前にはないので削除する
+                ("sinatra", Net::Added, 3),
+                ("json", Net::Unchanged, 2),
+            ]
+        );
+
+        assert!(rollbacks[1].edits().unwrap().is_empty());
+        assert_eq!(rollbacks[0].edits().unwrap(), [Edit::RemoveGem { name: "rack".to_string() }]);
+        // 変わった Gem は最も古い remove で消した行に戻す
+        let puma = rollbacks[2].edits().unwrap();
+        assert_eq!(puma[0], Edit::RemoveGem { name: "puma".to_string() });
+        assert!(matches!(&puma[1], Edit::RestoreLine(line) if line.text == "gem 'puma', '~> 6.0'"));
+    }
+
+    #[test]
+    fn test_net_effect_without_recorded_lines() {
+        // 古い arc の remove は行を記録していない: 追加し直していれば触れない、いなければ gem 行を戻す
+        let log = [
+            signal("r1", "remove", 1, json!({ "gem": "rake" })),
+            signal("r2", "add", 2, json!({ "gem": "rake", "version": "13.0" })),
+            signal("r3", "remove", 3, json!({ "gem": "json" })),
+        ];
+        let targets = targets(&log, 0).unwrap();
+        let rollbacks = net_effects(&targets);
+        assert_eq!(rollbacks.iter().map(|r| r.net).collect::<Vec<_>>(), [Net::Unchanged, Net::Removed]);
+        assert_eq!(
+            rollbacks[1].edits().unwrap(),
+            [
+                Edit::RemoveGem { name: "json".to_string() },
+                Edit::InsertGem { name: "json".to_string(), version: None, group: None },
+            ]
+        );
+    }
+}
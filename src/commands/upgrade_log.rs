@@ -0,0 +1,28 @@
+//! `arc upgrade-log`: Signal ログの古い行を現在のスキーマに書き換える。
+
+use anyhow::Result;
+
+use crate::display;
+
+use super::context::CommandContext;
+
+pub fn upgrade_log(ctx: &CommandContext) -> Result<()> {
+    let report = crate::upgrade_log::upgrade(&ctx.project()?.signal_file)?;
+
+    eprintln!(
+        "📜 Upgraded {} signal(s) to v{}, {} already current",
+        display::fmt_count(report.upgraded as u64),
+        crate::signals::SCHEMA_VERSION,
+        display::fmt_count(report.current as u64)
+    );
+    if report.kept_total() > 0 {
+        let reasons: Vec<String> =
+            report.kept.iter().map(|(reason, count)| format!("{}: {}", reason, display::fmt_count(*count as u64))).collect();
+        eprintln!(
+            "   Kept {} line(s) unchanged ({}); they stay readable as v1",
+            display::fmt_count(report.kept_total() as u64),
+            reasons.join(", ")
+        );
+    }
+    Ok(())
+}
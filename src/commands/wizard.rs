@@ -0,0 +1,218 @@
+//! `arc init --interactive` の質問。
+//!
+//! 質問はすべてファイルを作成する前に終える。途中で中断 (Ctrl-C / EOF) しても
+//! プロジェクトには何も残らず、回答が揃ってから transactional な init に渡す。
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+
+use crate::prompt::Prompter;
+
+/// 質問の既定値や選択肢の材料。
+#[derive(Debug, Clone, Default)]
+pub struct InitContext {
+    /// プロジェクト名の既定値 (通常はディレクトリ名)
+    pub default_name: Option<String>,
+    /// config.toml の既定の Ruby バージョン
+    pub default_ruby: String,
+    /// `.ruby-version` に書かれたバージョン
+    pub ruby_version_file: Option<String>,
+    /// グローバルキャッシュにある最新の Ruby
+    pub latest_cached: Option<String>,
+    pub gemfile_exists: bool,
+}
+
+/// ウィザードの回答。
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitAnswers {
+    pub name: Option<String>,
+    pub ruby_version: String,
+    /// Ruby バージョンの選び方 ("default" / ".ruby-version" / "cache" / "manual")
+    pub ruby_source: &'static str,
+    pub create_gemfile: bool,
+    pub bootstrap: bool,
+}
+
+impl InitAnswers {
+    /// init Signal の payload に加える内容
+    pub fn to_json(&self) -> Value {
+        json!({
+            "interactive": true,
+            "ruby_source": self.ruby_source,
+            "gemfile_created": self.create_gemfile,
+            "bootstrap": self.bootstrap,
+        })
+    }
+}
+
+/// 順に質問して回答を集める。ファイルには一切触れない。
+pub fn ask(prompter: &mut dyn Prompter, ctx: &InitContext) -> Result<InitAnswers> {
+    let name = prompter.text("Project name", ctx.default_name.as_deref().unwrap_or(""))?;
+
+    let choices = ruby_choices(ctx);
+    let mut labels: Vec<String> = choices
+        .iter()
+        .map(|(version, source)| format!("{} ({})", version, source_label(source)))
+        .collect();
+    labels.push("other…".to_string());
+    // .ruby-version があればそれを既定にする
+    let default = choices.iter().position(|(_, s)| *s == ".ruby-version").unwrap_or(0);
+    let picked = prompter.choose("Ruby version:", &labels, default)?;
+    let (ruby_version, ruby_source) = match choices.get(picked) {
+        Some((version, source)) => (version.clone(), *source),
+        None => (prompter.text("Ruby version", &ctx.default_ruby)?, "manual"),
+    };
+
+    let create_gemfile = !ctx.gemfile_exists && prompter.confirm("Create a Gemfile?", true)?;
+    let bootstrap = prompter.confirm(&format!("Download Ruby {} now (arc bootstrap)?", ruby_version), false)?;
+
+    Ok(InitAnswers {
+        name: (!name.is_empty()).then_some(name),
+        ruby_version,
+        ruby_source,
+        create_gemfile,
+        bootstrap,
+    })
+}
+
+/// Ruby バージョンの選択肢。同じバージョンは最初の 1 つだけ残す。
+fn ruby_choices(ctx: &InitContext) -> Vec<(String, &'static str)> {
+    let candidates = [
+        Some((ctx.default_ruby.clone(), "default")),
+        ctx.ruby_version_file.clone().map(|v| (v, ".ruby-version")),
+        ctx.latest_cached.clone().map(|v| (v, "cache")),
+    ];
+    let mut choices: Vec<(String, &'static str)> = Vec::new();
+    for (version, source) in candidates.into_iter().flatten() {
+        if !choices.iter().any(|(v, _)| *v == version) {
+            choices.push((version, source));
+        }
+    }
+    choices
+}
+
+fn source_label(source: &str) -> &str {
+    match source {
+        "default" => "arc default",
+        "cache" => "latest cached",
+        other => other,
+    }
+}
+
+/// `rubies_dir` (`~/.arc/cache/rubies`) にある、このプラットフォーム向けの最新の Ruby。
+pub fn latest_cached_ruby(rubies_dir: &Path) -> Option<String> {
+    let suffix = format!("-{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+    fs::read_dir(rubies_dir)
+        .ok()?
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.strip_suffix(&suffix).map(String::from))
+        .max_by_key(|v| version_key(v))
+}
+
+/// `3.10.1` が `3.9.0` より新しくなるよう、数値として比較するためのキー。
+fn version_key(version: &str) -> Vec<u64> {
+    version.split('.').map(|p| p.parse().unwrap_or(0)).collect()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// 決まった回答を順に返す `Prompter`。回答が尽きたら中断 (Ctrl-C 相当) する。
+    pub struct Canned(pub VecDeque<&'static str>);
+
+    impl Canned {
+        pub fn new(answers: &[&'static str]) -> Self {
+            Self(answers.iter().copied().collect())
+        }
+
+        fn next(&mut self) -> Result<&'static str> {
+            self.0.pop_front().ok_or_else(|| anyhow::anyhow!("interrupted"))
+        }
+    }
+
+    impl Prompter for Canned {
+        fn text(&mut self, _: &str, default: &str) -> Result<String> {
+            let answer = self.next()?;
+            Ok(if answer.is_empty() { default.to_string() } else { answer.to_string() })
+        }
+
+        fn choose(&mut self, _: &str, _: &[String], default: usize) -> Result<usize> {
+            let answer = self.next()?;
+            Ok(if answer.is_empty() { default } else { answer.parse::<usize>()? - 1 })
+        }
+
+        fn confirm(&mut self, _: &str, default: bool) -> Result<bool> {
+            let answer = self.next()?;
+            Ok(if answer.is_empty() { default } else { answer == "y" })
+        }
+    }
+
+    fn ctx() -> InitContext {
+        InitContext {
+            default_name: Some("shop".to_string()),
+            default_ruby: "3.3.6".to_string(),
+            ruby_version_file: Some("3.2.2".to_string()),
+            latest_cached: Some("3.3.6".to_string()),
+            gemfile_exists: false,
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let answers = ask(&mut Canned::new(&["", "", "", ""]), &ctx()).unwrap();
+        assert_eq!(
+            answers,
+            InitAnswers {
+                name: Some("shop".to_string()),
+                ruby_version: "3.2.2".to_string(),
+                ruby_source: ".ruby-version",
+                create_gemfile: true,
+                bootstrap: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_choices_are_deduplicated() {
+        // latest cached は default と同じバージョンなので選択肢に出ない
+        let choices = ruby_choices(&ctx());
+        assert_eq!(
+            choices,
+            [("3.3.6".to_string(), "default"), ("3.2.2".to_string(), ".ruby-version")]
+        );
+    }
+
+    #[test]
+    fn test_manual_version_and_existing_gemfile() {
+        let ctx = InitContext { gemfile_exists: true, ..ctx() };
+        // 3 番目 (other…) を選んで手入力。Gemfile の質問は飛ばされる
+        let answers = ask(&mut Canned::new(&["api", "3", "3.4.1", "y"]), &ctx).unwrap();
+        assert_eq!(answers.name.as_deref(), Some("api"));
+        assert_eq!(answers.ruby_version, "3.4.1");
+        assert_eq!(answers.ruby_source, "manual");
+        assert!(!answers.create_gemfile);
+        assert!(answers.bootstrap);
+    }
+
+    #[test]
+    fn test_latest_cached_ruby() {
+        let dir = std::env::temp_dir().join("arc_wizard_rubies_test");
+        let _ = fs::remove_dir_all(&dir);
+        let suffix = format!("-{}-{}", std::env::consts::OS, std::env::consts::ARCH);
+        for version in ["3.9.0", "3.10.1", "3.2.2"] {
+            fs::create_dir_all(dir.join(format!("{}{}", version, suffix))).unwrap();
+        }
+        fs::create_dir_all(dir.join("9.9.9-otheros-otherarch")).unwrap();
+        assert_eq!(latest_cached_ruby(&dir).as_deref(), Some("3.10.1"));
+        assert_eq!(latest_cached_ruby(&dir.join("missing")), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! `arc ws` — ワークスペースの全メンバーに対する操作。
+//!
+//! `arc ws sync` / `arc ws exec` は各メンバーのディレクトリで `arc sync` / `arc exec` を子プロセスとして実行する
+//! (メンバーごとのロック・隔離環境・Signal ログはそれぞれの arc が扱う)。
+//! 実行は `task_pool::TaskPool` で行い、子プロセスの出力は 1 行ずつ `[services/api] ` のようにメンバー名を付けて書き出す。
+//! 同時に実行する数は `--max-parallel` > ルートの `[parallel] jobs` > CPU 数。
+
+use anyhow::{Result, bail};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::context::CommandContext;
+use crate::config::ArcConfig;
+use crate::display;
+use crate::lockfile;
+use crate::signals::{FLUX_DIR, FluxProject, SignalType};
+use crate::state::FluxState;
+use crate::task_pool::{self, Task, TaskOutcome, TaskPool};
+use crate::workspace::Workspace;
+
+/// 各メンバーのディレクトリで `program args` を実行するタスク (ラベルはメンバー名)
+fn member_tasks(members: &[(String, PathBuf)], program: &Path, args: &[String]) -> Vec<Task> {
+    members
+        .iter()
+        .map(|(name, dir)| Task { cwd: Some(dir.clone()), ..Task::new(name.clone(), program, args.to_vec()) })
+        .collect()
+}
+
+/// `arc ws state` の 1 メンバーの行
+fn member_line(name: &str, dir: &Path) -> String {
+    let project = match FluxProject::open(dir) {
+        Ok(project) => project,
+        Err(e) => return format!("  {:<24} ⚠️  {}", name, e),
+    };
+    let ruby = ArcConfig::load(&project.flux_dir).map(|c| c.ruby.version).unwrap_or_else(|_| "?".to_string());
+    let gems = lockfile::parse(&dir.join("Gemfile.lock"))
+        .map(|lock| format!("{} gems", lock.specs.iter().map(|s| &s.name).collect::<std::collections::BTreeSet<_>>().len()))
+        .unwrap_or_else(|_| "no Gemfile.lock".to_string());
+    let state = FluxState::from_signals(&project.read_signals().unwrap_or_default());
+    let failure = state.executions.iter().filter(|e| !e.success).max_by_key(|e| e.started_at).map_or(
+        "no failures".to_string(),
+        |e| {
+            format!(
+                "last failure: {} (exit {}) {}",
+                e.command_line(),
+                e.exit_code.map_or("?".to_string(), |c| c.to_string()),
+                e.started_at.map(display::fmt_datetime).unwrap_or_default()
+            )
+        },
+    );
+    format!("  {:<24} ruby {:<8} {:<16} {}", name, ruby, gems, failure)
+}
+
+/// `arc ws state`: メンバーごとの Ruby バージョン・Gem 数・最後の失敗。
+pub fn state(ctx: &CommandContext) -> Result<()> {
+    let ws = Workspace::find(ctx.root()?)?;
+    println!("🗂  Workspace {:?} ({} members)", ws.root, ws.members.len());
+    for member in &ws.members {
+        println!("{}", member_line(&ws.name(member), member));
+    }
+    Ok(())
+}
+
+/// 全メンバーで `arc <args>` を実行する (`arc ws sync` / `arc ws exec`)。
+/// ルートが arc プロジェクトなら、結果を `x-ws-<operation>` として記録する。
+/// Ctrl-C で残りのメンバーを取り消し、実行中のものを止めてから結果を表示する。
+pub fn run(ctx: &CommandContext, operation: &str, args: &[String], max_parallel: Option<usize>, keep_going: bool) -> Result<()> {
+    let ws = Workspace::find(ctx.root()?)?;
+    let members: Vec<(String, PathBuf)> = ws.members.iter().map(|m| (ws.name(m), m.clone())).collect();
+    let jobs = match max_parallel {
+        Some(jobs) => jobs.max(1),
+        None => ArcConfig::load(&ws.root.join(FLUX_DIR))?.parallel.jobs(),
+    };
+    let mut arc_args = Vec::new();
+    if ctx.dry_run {
+        arc_args.push("--dry-run".to_string());
+    }
+    arc_args.extend_from_slice(args);
+    let tasks = member_tasks(&members, &std::env::current_exe()?, &arc_args);
+
+    let out = Mutex::new(std::io::stdout());
+    let (results, interrupted) = TaskPool::new(jobs).keep_going(keep_going).run_interruptible(&tasks, &out);
+
+    let count = |wanted: fn(&TaskOutcome) -> bool| results.iter().filter(|r| wanted(&r.outcome)).count();
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| matches!(r.outcome, TaskOutcome::Failed(_)))
+        .map(|r| r.label.as_str())
+        .collect();
+    let skipped = count(|o| *o == TaskOutcome::Skipped);
+    let cancelled = count(|o| *o == TaskOutcome::Cancelled);
+    eprintln!();
+    for line in task_pool::task_summary_lines(&results) {
+        eprintln!("{}", line);
+    }
+
+    // 読み取り専用のチェックアウトでは各メンバーの実行だけ行い、まとめの Signal は記録しない
+    if ws.root_is_project() && !crate::read_only::is_enabled() {
+        let project = FluxProject::open(&ws.root)?;
+        project.record(
+            SignalType::custom("ws", operation)?,
+            json!({
+                "args": args,
+                "members": members.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+                "failed": failed,
+                "skipped": skipped,
+                "cancelled": cancelled,
+                "exit_codes": results
+                    .iter()
+                    .filter_map(|r| match r.outcome {
+                        TaskOutcome::Failed(code) => Some((r.label.clone(), json!(code))),
+                        _ => None,
+                    })
+                    .collect::<serde_json::Map<_, _>>(),
+                "parallel": jobs,
+            }),
+        )?;
+    }
+    if interrupted {
+        bail!("arc {} was interrupted ({} member(s) cancelled)", args[0], cancelled);
+    }
+    if !failed.is_empty() {
+        bail!("arc {} failed in {} member(s): {}", args[0], failed.len(), failed.join(", "));
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::tests::fixture;
+    use std::fs;
+
+    /// メンバーごとに決まった終了コードで終わるシェルスクリプト (web だけが失敗する)
+    fn run_fixture(root: &Path, parallel: usize, keep_going: bool) -> (Vec<TaskOutcome>, Vec<String>) {
+        let ws = Workspace::load(root).unwrap();
+        let members: Vec<(String, PathBuf)> = ws.members.iter().map(|m| (ws.name(m), m.clone())).collect();
+        let script = "name=$(basename \"$PWD\"); echo start $name; echo warn $name >&2; sleep 0.1; echo done $name; \
+                      [ $name != web ] || exit 3";
+        let tasks = member_tasks(&members, Path::new("sh"), &["-c".to_string(), script.to_string()]);
+        let out: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+        let results = TaskPool::new(parallel).keep_going(keep_going).run(&tasks, &out);
+        let text = String::from_utf8(out.into_inner().unwrap()).unwrap();
+        (results.into_iter().map(|r| r.outcome).collect(), text.lines().map(String::from).collect())
+    }
+
+    #[test]
+    fn test_sequential_stops_on_first_failure() {
+        let root = fixture("arc_ws_run_seq_test", r#"["services/*", "tools/*"]"#);
+        let (outcomes, lines) = run_fixture(&root, 1, false);
+        assert_eq!(outcomes, [TaskOutcome::Success, TaskOutcome::Failed(Some(3)), TaskOutcome::Skipped]);
+        assert!(!lines.iter().any(|l| l.starts_with("[tools/cli]")));
+        // 1 つずつ実行するので、メンバーの出力は混ざらない
+        let api: Vec<&String> = lines.iter().take(3).collect();
+        assert!(api.iter().all(|l| l.starts_with("[services/api] ")), "{:?}", lines);
+
+        let (outcomes, _) = run_fixture(&root, 1, true);
+        assert_eq!(outcomes, [TaskOutcome::Success, TaskOutcome::Failed(Some(3)), TaskOutcome::Success]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_prefixes_every_line() {
+        let root = fixture("arc_ws_run_parallel_test", r#"["services/*", "tools/*"]"#);
+        let (outcomes, lines) = run_fixture(&root, 3, true);
+        // 3 つが同時に動く (どのメンバーも終わる前に全員が始まっている)
+        let first_done = lines.iter().position(|l| l.contains("] done ")).unwrap();
+        assert_eq!(lines[..first_done].iter().filter(|l| l.contains("] start ")).count(), 3, "{:?}", lines);
+        assert_eq!(outcomes, [TaskOutcome::Success, TaskOutcome::Failed(Some(3)), TaskOutcome::Success]);
+        assert_eq!(lines.len(), 9);
+        for name in ["services/api", "services/web", "tools/cli"] {
+            let short = name.rsplit('/').next().unwrap();
+            let own: Vec<&str> = lines.iter().filter_map(|l| l.strip_prefix(&format!("[{}] ", name))).collect();
+            assert_eq!(own.len(), 3, "{:?}", lines);
+            assert!(own.contains(&format!("warn {}", short).as_str()));
+            assert_eq!(own.iter().filter(|l| l.starts_with("start")).count(), 1);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_member_line() {
+        let root = fixture("arc_ws_member_line_test", r#"["services/*"]"#);
+        let dir = root.join("services/api");
+        fs::remove_dir_all(dir.join(".flux")).unwrap();
+        let project = FluxProject::init(&dir, &Default::default(), json!({})).unwrap().0;
+        fs::write(dir.join("Gemfile.lock"), "GEM\n  specs:\n    rake (13.1.0)\n    json (2.7.1)\n").unwrap();
+        let start = project.record(SignalType::ExecStart, json!({ "command": "rake", "args": ["db:migrate"] })).unwrap();
+        project
+            .record(SignalType::ExecEnd, json!({ "ref_id": start.id, "exit_code": 1, "success": false, "duration_ms": 5 }))
+            .unwrap();
+
+        let line = member_line("services/api", &dir);
+        assert!(line.contains("services/api") && line.contains("2 gems"), "{}", line);
+        assert!(line.contains("last failure: rake db:migrate (exit 1)"), "{}", line);
+        assert!(member_line("services/worker", &root.join("services/worker")).contains("⚠️"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
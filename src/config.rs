@@ -1,13 +1,87 @@
 //! `.arc/config.toml` の読み書きを担当するモジュール。
 //!
 //! ```toml
+//! [project]
+//! name = "my_app"
+//! description = "..."
+//!
 //! [ruby]
 //! version = "3.3.6"
+//!
+//! [stats]
+//! ignore = ["ls", "git status", "echo *"]
+//! track_disk = false   # sync / bootstrap の後に .arc/env のサイズを数えない (既定は数える)
+//!
+//! [cache]
+//! link_mode = "auto"   # "auto" | "reflink" | "hardlink" | "copy"
+//!
+//! [safety]
+//! confirm_mutations = true
+//!
+//! [log]
+//! payload_budget = 16384   # これを超える payload のフィールドは .flux/blobs/ に退避
+//!
+//! [run]
+//! shell = "bash"   # --shell で使うシェル (既定は sh)
+//! sandbox_home = true                              # HOME を .arc/env/home にする
+//! sandbox_home_passthrough = [".netrc", ".ssh"]    # 本当のホームへリンクするパス
+//!
+//! [signals]
+//! skip_types = ["exec_start", "exec_end"]   # 記録しない実行 (start/end は常に対で抑制)
+//! skip_commands = ["ls", "git *"]            # [stats] ignore と同じ書式
+//! retention_days = 90                        # これより古い Signal を自動で消す (arc gc --retention)
+//!
+//! [exec]
+//! system_ruby = "/usr/bin/ruby"   # System モードの ruby / gem / bundle をこのバイナリ (と同じディレクトリ) に固定する
+//! binstub_style = "backup"        # arc binstub がスクリプトを書き換え、元を <name>.orig に残す (既定は "wrap")
+//!
+//! [permissions]
+//! group_writable = true   # 作成するディレクトリを 2775、Signal ファイルを 664 にする
+//!
+//! [env]
+//! share_runtime = "per-repo"   # git worktree 間で ruby_runtime を共有する (既定は "per-project")
+//! share_gems = true            # Gem のディレクトリ (.arc/env/ruby) も共有する
+//! shared_dir = "../arc-shared" # 共有先 (省略時は ~/.arc/worktrees/<git common dir のハッシュ>)
+//! set = { DISABLE_SPRING = "1" } # exec / run / task のコマンドに設定する環境変数
+//!
+//! [aliases]
+//! spec = ["bundle", "exec", "rspec"]
+//! lint = ["bundle", "exec", "rubocop", "-A"]
+//! deploy = { cmd = ["bin/deploy"], env = { RAILS_ENV = "production" }, isolated = false }
+//!
+//! [commands]
+//! test = { cmd = ["bundle", "exec", "rspec"], env = { RAILS_ENV = "test" } }   # arc test (省略時は自動検出)
+//! fmt = ["bundle", "exec", "rubocop", "-a"]   # arc task fmt
+//!
+//! [template]
+//! strict = true   # 既定値のない未定義の ${VAR} をエラーにする
+//!
+//! [display]
+//! lang = "de"   # 件数の 3 桁区切り (de: 182.403、fr: 182 403、既定: 182,403)。--lang が優先
+//! ui_task = "test"   # arc ui の r で実行するタスク
+//!
+//! [parallel]
+//! jobs = 4   # arc ws・Signal フックを同時に実行する数 (既定: CPU 数)。--max-parallel が優先
+//!
+//! [gemfile]
+//! quote = "double"   # arc gemfile sort がそろえる引用符 (既定: single)
+//!
+//! [signal_hooks]
+//! "install_end" = "scripts/on-install.sh"   # Signal の記録後に実行する (stdin に Signal の JSON)
 //! ```
+//!
+//! 文字列の値には `${VAR}` / `${VAR:-default}` を書ける (`crate::template`)。
+//! 展開は読み込み時に行い、ファイルには書いたままの形で保存する。
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::blobs::DEFAULT_PAYLOAD_BUDGET;
+use crate::link::LinkMode;
+use crate::signals::SignalType;
+use crate::template::Lookup;
 
 const CONFIG_FILE: &str = "config.toml";
 const DEFAULT_RUBY_VERSION: &str = "3.3.6";
@@ -18,37 +92,554 @@ const DEFAULT_RUBY_VERSION: &str = "3.3.6";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ArcConfig {
+    #[serde(default)]
+    pub project: ProjectConfig,
     pub ruby: RubyConfig,
+    #[serde(default, skip_serializing_if = "StatsConfig::is_empty")]
+    pub stats: StatsConfig,
+    #[serde(default, skip_serializing_if = "CacheConfig::is_default")]
+    pub cache: CacheConfig,
+    #[serde(default, skip_serializing_if = "SafetyConfig::is_default")]
+    pub safety: SafetyConfig,
+    #[serde(default, skip_serializing_if = "LogConfig::is_default")]
+    pub log: LogConfig,
+    #[serde(default, skip_serializing_if = "OutputConfig::is_default")]
+    pub output: OutputConfig,
+    #[serde(default, skip_serializing_if = "RunConfig::is_default")]
+    pub run: RunConfig,
+    #[serde(default, skip_serializing_if = "SignalsConfig::is_default")]
+    pub signals: SignalsConfig,
+    #[serde(default, skip_serializing_if = "ExecConfig::is_default")]
+    pub exec: ExecConfig,
+    #[serde(default, skip_serializing_if = "PermissionsConfig::is_default")]
+    pub permissions: PermissionsConfig,
+    #[serde(default, skip_serializing_if = "EnvConfig::is_default")]
+    pub env: EnvConfig,
+    /// `arc run <alias>` で展開されるコマンド (名前 → argv または表)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, CommandEntry>,
+    /// `arc task <name>` (`arc test`) で実行するプロジェクトのタスク
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, CommandEntry>,
+    #[serde(default, skip_serializing_if = "TemplateConfig::is_default")]
+    pub template: TemplateConfig,
+    #[serde(default, skip_serializing_if = "DisplayConfig::is_default")]
+    pub display: DisplayConfig,
+    #[serde(default, skip_serializing_if = "SyncConfig::is_default")]
+    pub sync: SyncConfig,
+    #[serde(default, skip_serializing_if = "ParallelConfig::is_default")]
+    pub parallel: ParallelConfig,
+    #[serde(default, skip_serializing_if = "GemfileConfig::is_default")]
+    pub gemfile: GemfileConfig,
+    /// Signal を記録したときに実行するコマンド (Signal 種別のパターン → コマンド)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub signal_hooks: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DisplayConfig {
+    /// 件数の 3 桁区切りを選ぶ言語 (例: "de")。`--lang` が優先
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// 罫線・絵文字・色を使わず key=value の行で表示する (`--plain` と同じ)
+    #[serde(default)]
+    pub plain: bool,
+    /// `arc ui` の `r` で実行するタスク (省略時は `[commands]` の test)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ui_task: Option<String>,
+}
+
+impl DisplayConfig {
+    fn is_default(&self) -> bool {
+        self.lang.is_none() && !self.plain && self.ui_task.is_none()
+    }
+}
+
+/// `[sync] min_free_mb` の既定値
+pub const DEFAULT_MIN_FREE_MB: u64 = 256;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// sync の事前確認で必要とする `.arc/env` のファイルシステムの空き容量 (MiB)
+    #[serde(default = "default_min_free_mb")]
+    pub min_free_mb: u64,
+}
+
+fn default_min_free_mb() -> u64 {
+    DEFAULT_MIN_FREE_MB
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self { min_free_mb: DEFAULT_MIN_FREE_MB }
+    }
+}
+
+impl SyncConfig {
+    fn is_default(&self) -> bool {
+        self.min_free_mb == DEFAULT_MIN_FREE_MB
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ParallelConfig {
+    /// 同時に実行するタスク数 (`arc ws` の `--max-parallel` の既定、Signal フック)。省略時は CPU 数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+}
+
+impl ParallelConfig {
+    fn is_default(&self) -> bool {
+        self.jobs.is_none()
+    }
+
+    /// 同時に実行するタスク数 (0 は省略と同じ)
+    pub fn jobs(&self) -> usize {
+        self.jobs.filter(|&jobs| jobs > 0).unwrap_or_else(default_jobs)
+    }
+}
+
+/// `[parallel] jobs` の既定値 (CPU 数)
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// `[gemfile] quote`: `arc gemfile sort` がそろえる文字列リテラルの引用符。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuoteStyle {
+    /// `'rails'` (`arc add` が書く形)
+    #[default]
+    Single,
+    /// `"rails"` (`bundle init` / `rails new` が書く形)
+    Double,
+}
+
+impl QuoteStyle {
+    pub fn char(self) -> char {
+        match self {
+            QuoteStyle::Single => '\'',
+            QuoteStyle::Double => '"',
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GemfileConfig {
+    #[serde(default)]
+    pub quote: QuoteStyle,
+}
+
+impl GemfileConfig {
+    fn is_default(&self) -> bool {
+        self.quote == QuoteStyle::Single
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TemplateConfig {
+    /// 既定値のない未定義の `${VAR}` をエラーにする (既定では空文字列に展開する)
+    #[serde(default)]
+    pub strict: bool,
+}
+
+impl TemplateConfig {
+    fn is_default(&self) -> bool {
+        !self.strict
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProjectConfig {
+    /// プロジェクト名 (`arc init` 時にディレクトリ名から設定される)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// プロジェクトの説明
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RubyConfig {
     /// 使用する Ruby のバージョン (例: "3.3.6")
     pub version: String,
+    /// ruby-builder のアセット名の接尾辞 (例: "ubuntu-22.04")。OS / アーキテクチャからの自動選択を上書きする
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform_suffix: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsConfig {
+    /// コマンド統計から除外するコマンド (プログラム名・完全一致・`*`/`?` の glob)
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// sync / bootstrap の後に `.arc/env` のサイズを数えて記録する (`arc stats --disk`)
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub track_disk: bool,
+    /// `arc stats --flaky` で不安定とみなすスコア (反転数 / (試行数 - 1))
+    #[serde(default = "default_flaky_threshold", skip_serializing_if = "is_default_flaky_threshold")]
+    pub flaky_threshold: f64,
+    /// `arc stats --flaky` の対象にする最小の実行回数
+    #[serde(default = "default_flaky_min_runs", skip_serializing_if = "is_default_flaky_min_runs")]
+    pub flaky_min_runs: usize,
+}
+
+fn default_flaky_threshold() -> f64 {
+    crate::flaky::DEFAULT_THRESHOLD
+}
+
+fn default_flaky_min_runs() -> usize {
+    crate::flaky::DEFAULT_MIN_RUNS
+}
+
+fn is_default_flaky_threshold(value: &f64) -> bool {
+    *value == crate::flaky::DEFAULT_THRESHOLD
+}
+
+fn is_default_flaky_min_runs(value: &usize) -> bool {
+    *value == crate::flaky::DEFAULT_MIN_RUNS
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            ignore: Vec::new(),
+            track_disk: true,
+            flaky_threshold: crate::flaky::DEFAULT_THRESHOLD,
+            flaky_min_runs: crate::flaky::DEFAULT_MIN_RUNS,
+        }
+    }
+}
+
+impl StatsConfig {
+    fn is_empty(&self) -> bool {
+        self.ignore.is_empty()
+            && self.track_disk
+            && is_default_flaky_threshold(&self.flaky_threshold)
+            && is_default_flaky_min_runs(&self.flaky_min_runs)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// グローバルキャッシュからプロジェクトへの配置方法
+    #[serde(default)]
+    pub link_mode: LinkMode,
+}
+
+impl CacheConfig {
+    fn is_default(&self) -> bool {
+        self.link_mode == LinkMode::default()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SafetyConfig {
+    /// Gemfile を変更するコマンドで常に確認を求める
+    #[serde(default)]
+    pub confirm_mutations: bool,
+    /// `arc exec` / `arc run` でプロジェクト名の入力を求めるコマンドのパターン (`[stats] ignore` と同じ書式)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub confirm_patterns: Vec<String>,
+    /// `--yes` で `confirm_patterns` の確認を省略できるようにする
+    #[serde(default)]
+    pub allow_yes_bypass: bool,
+}
+
+impl SafetyConfig {
+    fn is_default(&self) -> bool {
+        !self.confirm_mutations && self.confirm_patterns.is_empty() && !self.allow_yes_bypass
+    }
+}
+
+/// `.flux/output/` (デタッチ実行の出力) の上限。新しい出力を作るときと `arc gc` で適用する
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OutputConfig {
+    /// 出力ディレクトリ全体の上限 (MB)。超えた分は古い出力から削除する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_mb: Option<u64>,
+    /// この日数より古い出力を削除する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_days: Option<u64>,
+    /// 1 ファイルの上限 (MB)。超えたファイルは末尾だけを残して切り詰める
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_mb: Option<u64>,
+    /// 1 日より古い出力を gzip で圧縮する
+    #[serde(default)]
+    pub compress: bool,
+}
+
+impl OutputConfig {
+    fn is_default(&self) -> bool {
+        self.max_total_mb.is_none() && self.max_age_days.is_none() && self.max_file_mb.is_none() && !self.compress
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// 1 つの Signal payload の上限 (バイト)。超えた分のフィールドは blob に退避する
+    #[serde(default = "default_payload_budget")]
+    pub payload_budget: usize,
+}
+
+fn default_payload_budget() -> usize {
+    DEFAULT_PAYLOAD_BUDGET
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { payload_budget: DEFAULT_PAYLOAD_BUDGET }
+    }
+}
+
+impl LogConfig {
+    fn is_default(&self) -> bool {
+        self.payload_budget == DEFAULT_PAYLOAD_BUDGET
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunConfig {
+    /// `--shell` で使うシェル (既定は `sh`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    /// Spring を使うプロジェクトを隔離環境で実行するとき `DISABLE_SPRING=1` を設定する (`--spring` で無効)
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub disable_spring: bool,
+    /// 隔離環境で実行するコマンドの HOME を `.arc/env/home` にする (`--sandbox-home` で 1 回だけ有効にもできる)
+    #[serde(default)]
+    pub sandbox_home: bool,
+    /// sandbox の HOME から本当のホームへリンクするパス (HOME からの相対パス。例: `.netrc`, `.ssh`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sandbox_home_passthrough: Vec<String>,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self { shell: None, disable_spring: true, sandbox_home: false, sandbox_home_passthrough: Vec::new() }
+    }
+}
+
+impl RunConfig {
+    fn is_default(&self) -> bool {
+        self.shell.is_none() && self.disable_spring && !self.sandbox_home && self.sandbox_home_passthrough.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PermissionsConfig {
+    /// グループで共有するプロジェクト向けに、作成するディレクトリ・ファイルをグループ書き込み可能にする
+    #[serde(default)]
+    pub group_writable: bool,
+}
+
+impl PermissionsConfig {
+    fn is_default(&self) -> bool {
+        !self.group_writable
+    }
+}
+
+/// `[env] share_runtime`: `ruby_runtime` をどの単位で持つか。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShareRuntime {
+    /// プロジェクト (worktree) ごとに配置する
+    #[default]
+    PerProject,
+    /// 同じリポジトリの worktree 間で 1 つを共有し、各 worktree からはシンボリックリンクで参照する
+    PerRepo,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EnvConfig {
+    #[serde(default)]
+    pub share_runtime: ShareRuntime,
+    /// per-repo のとき、Gem のディレクトリ (`.arc/env/ruby`) も共有する
+    #[serde(default)]
+    pub share_gems: bool,
+    /// 共有先のディレクトリ。相対パスはプロジェクトルートから解決する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_dir: Option<PathBuf>,
+    /// `arc exec` / `arc run` / `arc task` のコマンドに設定する環境変数。エイリアス・タスクの `env` が優先
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, String>,
+}
+
+impl EnvConfig {
+    fn is_default(&self) -> bool {
+        self.share_runtime == ShareRuntime::PerProject
+            && !self.share_gems
+            && self.shared_dir.is_none()
+            && self.set.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SignalsConfig {
+    /// 記録しない実行の Signal 種別 (`exec_*` / `run_*` のみ)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_types: Vec<String>,
+    /// 記録しないコマンド (`[stats] ignore` と同じ書式)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_commands: Vec<String>,
+    /// Signal を残す日数。古い Signal は変更するコマンドのついでに消す (`retention`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u64>,
+}
+
+impl SignalsConfig {
+    fn is_default(&self) -> bool {
+        self.skip_types.is_empty() && self.skip_commands.is_empty() && self.retention_days.is_none()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExecConfig {
+    /// System モードで素の `ruby` / `gem` / `bundle` を起動するときに使う Ruby の絶対パス
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_ruby: Option<PathBuf>,
+    /// `arc binstub` がプロジェクトのスクリプトをどう置き換えるか
+    #[serde(default, skip_serializing_if = "BinstubStyle::is_default")]
+    pub binstub_style: BinstubStyle,
+}
+
+/// `[exec] binstub_style`: `arc binstub` で元のスクリプトをどう残すか。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BinstubStyle {
+    /// 元のスクリプトを `<name>.orig` に移し、`<name>` をそれを起動するラッパーにする
+    #[default]
+    Wrap,
+    /// `<name>.orig` に複製を残し、`<name>` の先頭に起動用のヘッダーを加える (`ruby -x` で本体を実行する)
+    Backup,
+}
+
+impl BinstubStyle {
+    fn is_default(&self) -> bool {
+        *self == Self::Wrap
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Wrap => "wrap",
+            Self::Backup => "backup",
+        }
+    }
+}
+
+impl ExecConfig {
+    fn is_default(&self) -> bool {
+        self.system_ruby.is_none() && self.binstub_style.is_default()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(ruby) = &self.system_ruby
+            && !ruby.is_absolute()
+        {
+            anyhow::bail!("system_ruby は絶対パスで指定してください: {:?}", ruby);
+        }
+        Ok(())
+    }
 }
 
 impl Default for ArcConfig {
     fn default() -> Self {
         Self {
+            project: ProjectConfig::default(),
             ruby: RubyConfig {
                 version: DEFAULT_RUBY_VERSION.to_string(),
+                platform_suffix: None,
             },
+            stats: StatsConfig::default(),
+            cache: CacheConfig::default(),
+            safety: SafetyConfig::default(),
+            log: LogConfig::default(),
+            output: OutputConfig::default(),
+            run: RunConfig::default(),
+            signals: SignalsConfig::default(),
+            exec: ExecConfig::default(),
+            permissions: PermissionsConfig::default(),
+            env: EnvConfig::default(),
+            aliases: BTreeMap::new(),
+            commands: BTreeMap::new(),
+            template: TemplateConfig::default(),
+            display: DisplayConfig::default(),
+            sync: SyncConfig::default(),
+            parallel: ParallelConfig::default(),
+            gemfile: GemfileConfig::default(),
+            signal_hooks: BTreeMap::new(),
         }
     }
 }
 
 impl ArcConfig {
-    /// `flux_dir` (.arc/) 内の config.toml を読み込む。
+    /// `flux_dir` (.arc/) 内の config.toml を読み込み、値の `${VAR}` を環境変数で展開する。
     /// ファイルが存在しない場合はデフォルト値を返す。
     pub fn load(flux_dir: &Path) -> Result<Self> {
+        Self::load_with(flux_dir, Some(&|name| std::env::var(name).ok()))
+    }
+
+    /// `${VAR}` を展開せずに読み込む (`arc config list` や、書き戻す前の読み込み)。
+    pub fn load_raw(flux_dir: &Path) -> Result<Self> {
+        Self::load_with(flux_dir, None)
+    }
+
+    /// `lookup` が `Some` なら、その関数で文字列の値を展開してから読み込む。
+    pub fn load_with(flux_dir: &Path, lookup: Option<Lookup>) -> Result<Self> {
+        let _timer = crate::overhead::scope(crate::overhead::CONFIG);
         let path = flux_dir.join(CONFIG_FILE);
         if !path.exists() {
             return Ok(Self::default());
         }
         let content = std::fs::read_to_string(&path)
             .with_context(|| format!("config.toml の読み込みに失敗しました: {:?}", path))?;
-        toml::from_str(&content)
-            .with_context(|| format!("config.toml のパースに失敗しました: {:?}", path))
+        let mut table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("config.toml のパースに失敗しました: {:?}", path))?;
+        if let Some(lookup) = lookup {
+            let strict = table
+                .get("template")
+                .and_then(|t| t.get("strict"))
+                .and_then(toml::Value::as_bool)
+                .unwrap_or(false);
+            expand_strings(&mut table, lookup, strict)
+                .with_context(|| format!("config.toml の ${{...}} を展開できません: {:?}", path))?;
+        }
+        let config: Self = toml::Value::Table(table)
+            .try_into()
+            .with_context(|| format!("config.toml のパースに失敗しました: {:?}", path))?;
+        validate_aliases(&config.aliases)
+            .with_context(|| format!("config.toml の [aliases] が不正です: {:?}", path))?;
+        if let Some((name, _)) = config.commands.iter().find(|(_, entry)| entry.argv().is_empty()) {
+            anyhow::bail!("config.toml の [commands] が不正です: {:?}: タスク '{}' のコマンドが空です", path, name);
+        }
+        let env_tables = std::iter::once(("[env] set".to_string(), &config.env.set))
+            .chain(config.aliases.iter().map(|(name, entry)| (format!("[aliases] {}", name), entry.env())))
+            .chain(config.commands.iter().map(|(name, entry)| (format!("[commands] {}", name), entry.env())));
+        for (section, env) in env_tables {
+            validate_env_names(env).with_context(|| format!("config.toml の {} が不正です: {:?}", section, path))?;
+        }
+        config
+            .signals
+            .validate()
+            .with_context(|| format!("config.toml の [signals] が不正です: {:?}", path))?;
+        config.exec.validate().with_context(|| format!("config.toml の [exec] が不正です: {:?}", path))?;
+        Ok(config)
+    }
+
+    /// 展開前の config.toml を読み込んで `edit` で書き換え、保存する。
+    /// 展開済みの値をファイルに書き戻さないよう、設定の変更にはこれを使う。
+    pub fn update(flux_dir: &Path, edit: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut config = Self::load_raw(flux_dir)?;
+        edit(&mut config);
+        config.save(flux_dir)
     }
 
     /// `flux_dir` (.arc/) 内の config.toml に書き込む。
@@ -61,10 +652,298 @@ impl ArcConfig {
     }
 }
 
+/// テーブル内のすべての文字列 (配列の要素を含む) を展開する。
+fn expand_strings(table: &mut toml::Table, lookup: Lookup, strict: bool) -> Result<()> {
+    fn walk(value: &mut toml::Value, key: &str, lookup: Lookup, strict: bool) -> Result<()> {
+        match value {
+            toml::Value::String(s) => {
+                *s = crate::template::expand(s, lookup, strict).with_context(|| key.to_string())?;
+            }
+            toml::Value::Array(items) => {
+                for item in items {
+                    walk(item, key, lookup, strict)?;
+                }
+            }
+            toml::Value::Table(table) => {
+                for (k, v) in table.iter_mut() {
+                    walk(v, &join_key(key, k), lookup, strict)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    for (k, v) in table.iter_mut() {
+        walk(v, k, lookup, strict)?;
+    }
+    Ok(())
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) }
+}
+
+impl ArcConfig {
+    /// `section.key = value` の一覧 (`arc config list`)。値は TOML の表記。
+    pub fn list(&self) -> Result<Vec<(String, String)>> {
+        fn flatten(prefix: &str, value: &toml::Value, out: &mut Vec<(String, String)>) {
+            match value {
+                toml::Value::Table(table) => {
+                    for (k, v) in table {
+                        flatten(&join_key(prefix, k), v, out);
+                    }
+                }
+                other => out.push((prefix.to_string(), other.to_string())),
+            }
+        }
+        let mut entries = Vec::new();
+        flatten("", &toml::Value::try_from(self).context("config.toml のシリアライズに失敗しました")?, &mut entries);
+        Ok(entries)
+    }
+
+    /// `section.key` に `value` を設定し、変更前の値を返す (`arc config set`)。
+    /// `value` は TOML の値として読めればその型 (`true`, `3`, `["a"]`)、読めなければ文字列にする。
+    /// 設定にない key や、型の合わない値はエラー。
+    pub fn set(&mut self, key: &str, value: &str) -> Result<Option<String>> {
+        let mut root = toml::Value::try_from(&*self).context("config.toml のシリアライズに失敗しました")?;
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts.pop().filter(|k| !k.is_empty() && !parts.is_empty());
+        let Some(last) = last else { anyhow::bail!("Config key must be section.key (e.g. project.name): {}", key) };
+        let mut table = &mut root;
+        for part in parts {
+            let toml::Value::Table(t) = table else { anyhow::bail!("Unknown config key: {}", key) };
+            table = t.entry(part.to_string()).or_insert_with(|| toml::Value::Table(Default::default()));
+        }
+        let toml::Value::Table(table) = table else { anyhow::bail!("Unknown config key: {}", key) };
+        let parsed = toml::from_str::<toml::Table>(&format!("v = {}", value))
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        let old = table.insert(last.to_string(), parsed).map(|v| display_value(&v));
+
+        let updated: ArcConfig = root.try_into().with_context(|| format!("Invalid value for {}: {}", key, value))?;
+        // 設定にない key は読み込むときに捨てられる
+        if !updated.list()?.iter().any(|(k, _)| k == key) {
+            anyhow::bail!("Unknown config key: {}", key);
+        }
+        *self = updated;
+        Ok(old)
+    }
+}
+
+/// 値の表示。文字列は引用符を付けない
+pub fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// ─────────────────────────────────────────────
+// エイリアス
+// ─────────────────────────────────────────────
+
+/// `[aliases]` / `[commands]` の 1 件。argv の配列、または環境変数などを伴う表。
+///
+/// ```toml
+/// spec = ["bundle", "exec", "rspec"]
+/// test = { cmd = ["bundle", "exec", "rspec"], env = { RAILS_ENV = "test" }, isolated = true }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "コマンドの配列 ([\"rspec\", ...]) か、表 ({ cmd = [...], env = { KEY = \"value\" }, isolated = true }) を指定してください"
+)]
+pub enum CommandEntry {
+    Argv(Vec<String>),
+    Spec(CommandSpec),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandSpec {
+    pub cmd: Vec<String>,
+    /// `[env] set` の上に重ねる環境変数
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// `false` ならシステムの環境で実行する。省略時はコマンドの既定 (run / task は隔離環境)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolated: Option<bool>,
+}
+
+impl CommandEntry {
+    pub fn argv(&self) -> &[String] {
+        match self {
+            Self::Argv(argv) => argv,
+            Self::Spec(spec) => &spec.cmd,
+        }
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        static EMPTY: BTreeMap<String, String> = BTreeMap::new();
+        match self {
+            Self::Argv(_) => &EMPTY,
+            Self::Spec(spec) => &spec.env,
+        }
+    }
+
+    pub fn isolated(&self) -> Option<bool> {
+        match self {
+            Self::Argv(_) => None,
+            Self::Spec(spec) => spec.isolated,
+        }
+    }
+
+    /// 表の形にそろえる
+    pub fn to_spec(&self) -> CommandSpec {
+        CommandSpec { cmd: self.argv().to_vec(), env: self.env().clone(), isolated: self.isolated() }
+    }
+}
+
+impl From<Vec<String>> for CommandEntry {
+    fn from(argv: Vec<String>) -> Self {
+        Self::Argv(argv)
+    }
+}
+
+impl CommandSpec {
+    /// 設定にない、そのまま実行するコマンド
+    pub fn plain(argv: Vec<String>) -> Self {
+        Self { cmd: argv, ..Self::default() }
+    }
+
+    /// `isolated = false` でなければ隔離環境
+    pub fn isolated_or_default(&self) -> bool {
+        self.isolated.unwrap_or(true)
+    }
+}
+
+/// 環境変数名として使えない名前 (空、`=` や NUL を含む) をエラーにする。
+fn validate_env_names(env: &BTreeMap<String, String>) -> Result<()> {
+    if let Some(name) = env.keys().find(|k| k.is_empty() || k.contains(['=', '\0'])) {
+        anyhow::bail!("環境変数名 '{}' は使えません", name);
+    }
+    Ok(())
+}
+
+impl ArcConfig {
+    /// エイリアスを展開し、`extra` を argv の末尾に追加する。エイリアスでなければ `None`。
+    /// 展開結果の先頭が別のエイリアスであれば、もう 1 段だけ展開する。
+    /// その場合の `env` は内側の上に外側を重ね、`isolated` は外側の指定を優先する。
+    pub fn expand_alias(&self, name: &str, extra: &[String]) -> Option<CommandSpec> {
+        let mut spec = self.aliases.get(name)?.to_spec();
+        if let Some(inner) = spec.cmd.first().and_then(|first| self.aliases.get(first)) {
+            spec.cmd.splice(0..1, inner.argv().iter().cloned());
+            let mut env = inner.env().clone();
+            env.append(&mut spec.env);
+            spec.env = env;
+            spec.isolated = spec.isolated.or(inner.isolated());
+        }
+        spec.cmd.extend_from_slice(extra);
+        Some(spec)
+    }
+
+    /// コマンドに設定する環境変数。`[env] set` < エイリアス・タスクの `env` < コマンドラインの `KEY=value` の順に優先する。
+    pub fn command_env(&self, entry: &BTreeMap<String, String>, cli: &[(String, String)]) -> BTreeMap<String, String> {
+        let mut env = self.env.set.clone();
+        env.extend(entry.iter().map(|(k, v)| (k.clone(), v.clone())));
+        env.extend(cli.iter().cloned());
+        env
+    }
+}
+
+/// エイリアスの定義を検証する。
+/// 空の argv、循環参照、2 段を超える参照はエラーにする。
+fn validate_aliases(aliases: &BTreeMap<String, CommandEntry>) -> Result<()> {
+    for (name, entry) in aliases {
+        if entry.argv().is_empty() {
+            anyhow::bail!("エイリアス '{}' のコマンドが空です", name);
+        }
+
+        let mut chain = vec![name.as_str()];
+        let mut current = entry.argv();
+        while let Some(next) = current.first().filter(|first| aliases.contains_key(first.as_str())) {
+            if chain.contains(&next.as_str()) {
+                chain.push(next);
+                anyhow::bail!("エイリアスが循環しています: {}", chain.join(" → "));
+            }
+            chain.push(next);
+            current = aliases[next].argv();
+        }
+        if chain.len() > 2 {
+            anyhow::bail!(
+                "エイリアスから参照できるのは 1 段までです: {}",
+                chain.join(" → ")
+            );
+        }
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// Signal 記録の抑制
+// ─────────────────────────────────────────────
+
+/// 抑制できる実行の (start, end) の組。状態を変える Signal (init / add など) は抑制できない。
+const SKIPPABLE_PAIRS: [(SignalType, SignalType); 2] = [
+    (SignalType::ExecStart, SignalType::ExecEnd),
+    (SignalType::RunStart, SignalType::RunEnd),
+];
+
+impl SignalsConfig {
+    /// `skip_types` には実行の start/end 以外を指定できない。
+    fn validate(&self) -> Result<()> {
+        for name in &self.skip_types {
+            let skippable = SKIPPABLE_PAIRS
+                .iter()
+                .any(|(start, end)| *name == start.to_string() || *name == end.to_string());
+            if !skippable {
+                let allowed: Vec<String> = SKIPPABLE_PAIRS
+                    .iter()
+                    .flat_map(|(start, end)| [start.to_string(), end.to_string()])
+                    .collect();
+                anyhow::bail!(
+                    "skip_types に '{}' は指定できません (指定できる値: {})",
+                    name,
+                    allowed.join(", ")
+                );
+            }
+        }
+        if self.retention_days == Some(0) {
+            anyhow::bail!("retention_days は 1 以上で指定してください");
+        }
+        Ok(())
+    }
+
+    /// `start_type` で始まる実行を記録しない場合、その理由を返す。
+    /// start と end のどちらかが `skip_types` にあれば、対の両方を抑制する。
+    pub fn skip_reason(&self, start_type: &SignalType, program: &str, command_line: &str) -> Option<String> {
+        let pair = SKIPPABLE_PAIRS.iter().find(|(start, _)| start == start_type)?;
+        if let Some(name) = self
+            .skip_types
+            .iter()
+            .find(|name| **name == pair.0.to_string() || **name == pair.1.to_string())
+        {
+            return Some(format!("[signals] skip_types = \"{}\"", name));
+        }
+        self.skip_commands
+            .iter()
+            .find(|pattern| crate::state::matches_ignore(pattern, program, command_line))
+            .map(|pattern| format!("[signals] skip_commands = \"{}\"", pattern))
+    }
+}
+
 // ─────────────────────────────────────────────
 // ユーティリティ
 // ─────────────────────────────────────────────
 
+/// プロジェクトパスからデフォルトのプロジェクト名 (ディレクトリ名) を導出する。
+/// `.` のような相対パスも実際のディレクトリ名に解決する。
+pub fn default_project_name(path: &Path) -> Option<String> {
+    let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    resolved.file_name().map(|n| n.to_string_lossy().to_string())
+}
+
 /// Ruby バージョン文字列 (例: "3.3.6") から
 /// 内部ライブラリパス用の API バージョン (例: "3.3.0") を導出する。
 pub fn ruby_api_version(ruby_version: &str) -> String {
@@ -95,6 +974,263 @@ mod tests {
         assert!(s.contains("version"));
     }
 
+    #[test]
+    fn test_default_project_name() {
+        let dir = std::env::temp_dir().join("arc_name_test").join("my_app");
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(default_project_name(&dir).as_deref(), Some("my_app"));
+        assert_eq!(default_project_name(&dir.join("..").join("my_app")).as_deref(), Some("my_app"));
+        std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_project_section_optional() {
+        // [project] のない古い config.toml も読める
+        let config: ArcConfig = toml::from_str("[ruby]\nversion = \"3.3.6\"\n").unwrap();
+        assert!(config.project.name.is_none());
+
+        let mut config = ArcConfig::default();
+        config.project.name = Some("my_app".to_string());
+        let s = toml::to_string_pretty(&config).unwrap();
+        assert!(s.contains("[project]"));
+        assert!(s.contains("name = \"my_app\""));
+        assert!(!s.contains("description"));
+    }
+
+    #[test]
+    fn test_stats_ignore() {
+        let config: ArcConfig = toml::from_str(
+            "[ruby]\nversion = \"3.3.6\"\n\n[stats]\nignore = [\"ls\", \"echo *\"]\n",
+        ).unwrap();
+        assert_eq!(config.stats.ignore, ["ls", "echo *"]);
+
+        // 空の [stats] は書き出さない
+        let s = toml::to_string_pretty(&ArcConfig::default()).unwrap();
+        assert!(!s.contains("[stats]"));
+    }
+
+    #[test]
+    fn test_permissions_section() {
+        let config: ArcConfig = toml::from_str(
+            "[ruby]\nversion = \"3.3.6\"\n\n[permissions]\ngroup_writable = true\n",
+        ).unwrap();
+        assert!(config.permissions.group_writable);
+
+        // 未設定なら書き出さない (従来どおり umask に従う)
+        let s = toml::to_string_pretty(&ArcConfig::default()).unwrap();
+        assert!(!s.contains("[permissions]"));
+    }
+
+    #[test]
+    fn test_env_section() {
+        let config: ArcConfig = toml::from_str(
+            "[ruby]\nversion = \"3.3.6\"\n\n[env]\nshare_runtime = \"per-repo\"\nshare_gems = true\n",
+        ).unwrap();
+        assert_eq!(config.env.share_runtime, ShareRuntime::PerRepo);
+        assert!(config.env.share_gems);
+        assert!(toml::from_str::<ArcConfig>("[ruby]\nversion = \"3.3.6\"\n\n[env]\nshare_runtime = \"global\"\n").is_err());
+        assert!(!toml::to_string_pretty(&ArcConfig::default()).unwrap().contains("[env]"));
+    }
+
+    fn aliases(toml_src: &str) -> ArcConfig {
+        toml::from_str(&format!("[ruby]\nversion = \"3.3.6\"\n\n[aliases]\n{}", toml_src)).unwrap()
+    }
+
+    fn signals(toml_src: &str) -> SignalsConfig {
+        toml::from_str::<ArcConfig>(&format!("[ruby]\nversion = \"3.3.6\"\n\n[signals]\n{}", toml_src))
+            .unwrap()
+            .signals
+    }
+
+    #[test]
+    fn test_skip_types_are_paired() {
+        // end だけを指定しても、start ごと抑制する (end だけが記録されることはない)
+        let config = signals("skip_types = [\"exec_end\"]\n");
+        assert!(config.skip_reason(&SignalType::ExecStart, "rake", "rake").is_some());
+        assert!(config.skip_reason(&SignalType::RunStart, "rake", "rake").is_none());
+        assert!(config.skip_reason(&SignalType::InstallStart, "bundle", "bundle install").is_none());
+    }
+
+    #[test]
+    fn test_skip_commands_glob() {
+        let config = signals("skip_commands = [\"ls\", \"git *\"]\n");
+        let reason = config.skip_reason(&SignalType::ExecStart, "git", "git status").unwrap();
+        assert_eq!(reason, "[signals] skip_commands = \"git *\"");
+        assert!(config.skip_reason(&SignalType::RunStart, "ls", "ls -la").is_some());
+        assert!(config.skip_reason(&SignalType::ExecStart, "gitk", "gitk").is_none());
+        // bundle install は状態を変えるため、コマンドが一致しても抑制しない
+        let config = signals("skip_commands = [\"bundle *\"]\n");
+        assert!(config.skip_reason(&SignalType::InstallStart, "bundle", "bundle install").is_none());
+    }
+
+    #[test]
+    fn test_structural_skip_types_rejected() {
+        let dir = std::env::temp_dir().join("arc_config_skip_types_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["init", "add", "install_start", "bogus"] {
+            std::fs::write(
+                dir.join(CONFIG_FILE),
+                format!("[ruby]\nversion = \"3.3.6\"\n\n[signals]\nskip_types = [\"exec_start\", \"{}\"]\n", name),
+            )
+            .unwrap();
+            let err = format!("{:#}", ArcConfig::load(&dir).unwrap_err());
+            assert!(err.contains(&format!("skip_types に '{}' は指定できません", name)), "{}", err);
+            assert!(err.contains("exec_start, exec_end, run_start, run_end"));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_exec_system_ruby_must_be_absolute() {
+        let dir = std::env::temp_dir().join("arc_config_exec_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(CONFIG_FILE), "[ruby]\nversion = \"3.3.6\"\n\n[exec]\nsystem_ruby = \"/usr/bin/ruby\"\n").unwrap();
+        let config = ArcConfig::load(&dir).unwrap();
+        assert_eq!(config.exec.system_ruby.as_deref(), Some(Path::new("/usr/bin/ruby")));
+        std::fs::write(dir.join(CONFIG_FILE), "[ruby]\nversion = \"3.3.6\"\n\n[exec]\nsystem_ruby = \"bin/ruby\"\n").unwrap();
+        let err = format!("{:#}", ArcConfig::load(&dir).unwrap_err());
+        assert!(err.contains("[exec]") && err.contains("絶対パス"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_exec_binstub_style() {
+        assert_eq!(ArcConfig::default().exec.binstub_style, BinstubStyle::Wrap);
+        let config: ArcConfig = toml::from_str("[ruby]\nversion = \"3.3.6\"\n\n[exec]\nbinstub_style = \"backup\"\n").unwrap();
+        assert_eq!(config.exec.binstub_style, BinstubStyle::Backup);
+        assert!(toml::from_str::<ArcConfig>("[ruby]\nversion = \"3.3.6\"\n\n[exec]\nbinstub_style = \"inline\"\n").is_err());
+        assert!(!toml::to_string_pretty(&ArcConfig::default()).unwrap().contains("[exec]"));
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let config = aliases("spec = [\"bundle\", \"exec\", \"rspec\"]\nfast = [\"spec\", \"--fail-fast\"]\n");
+        assert!(validate_aliases(&config.aliases).is_ok());
+
+        let extra = vec!["spec/models".to_string()];
+        assert_eq!(config.expand_alias("spec", &extra).unwrap().cmd, ["bundle", "exec", "rspec", "spec/models"]);
+        assert_eq!(config.expand_alias("fast", &[]).unwrap().cmd, ["bundle", "exec", "rspec", "--fail-fast"]);
+        assert!(config.expand_alias("rspec", &[]).is_none());
+    }
+
+    #[test]
+    fn test_command_entry_forms() {
+        let config = aliases(
+            "spec = [\"rspec\"]\n\
+             test = { cmd = [\"spec\", \"--seed\", \"1\"], env = { RAILS_ENV = \"test\" } }\n\
+             deploy = { cmd = [\"bin/deploy\"], isolated = false }\n",
+        );
+        assert_eq!(config.aliases["spec"], CommandEntry::Argv(vec!["rspec".into()]));
+        let test = config.expand_alias("test", &[]).unwrap();
+        assert_eq!(test.cmd, ["rspec", "--seed", "1"]);
+        assert_eq!(test.env["RAILS_ENV"], "test");
+        assert!(test.isolated_or_default());
+        assert!(!config.expand_alias("deploy", &[]).unwrap().isolated_or_default());
+
+        // 書き戻しても同じ形を保つ
+        let saved = toml::to_string(&config).unwrap();
+        assert!(saved.contains("spec = [\"rspec\"]"), "{}", saved);
+        let reloaded: ArcConfig = toml::from_str(&saved).unwrap();
+        assert_eq!(reloaded.aliases, config.aliases);
+
+        // どちらの形にも合わない
+        for bad in ["spec = \"rspec\"\n", "spec = { cmd = [\"rspec\"], envs = { A = \"1\" } }\n", "spec = { env = { A = \"1\" } }\n"] {
+            let src = format!("[ruby]\nversion = \"3.3.6\"\n\n[aliases]\n{}", bad);
+            let err = toml::from_str::<ArcConfig>(&src).unwrap_err().to_string();
+            assert!(err.contains("コマンドの配列") && err.contains("cmd = [...]"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_inner_alias_env_is_overridden_by_outer() {
+        let config = aliases(
+            "spec = { cmd = [\"rspec\"], env = { A = \"inner\", B = \"inner\" }, isolated = false }\n\
+             fast = { cmd = [\"spec\", \"--fail-fast\"], env = { B = \"outer\" } }\n",
+        );
+        let fast = config.expand_alias("fast", &[]).unwrap();
+        assert_eq!(fast.cmd, ["rspec", "--fail-fast"]);
+        assert_eq!(fast.env, [("A".to_string(), "inner".to_string()), ("B".to_string(), "outer".to_string())].into());
+        assert_eq!(fast.isolated, Some(false));
+    }
+
+    #[test]
+    fn test_command_env_precedence() {
+        let mut config = ArcConfig::default();
+        let vars = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>();
+        config.env.set = vars(&[("A", "global"), ("B", "global"), ("C", "global")]).into_iter().collect();
+        let entry = vars(&[("B", "entry"), ("C", "entry")]).into_iter().collect();
+
+        let env = config.command_env(&entry, &vars(&[("C", "cli")]));
+        assert_eq!(env, vars(&[("A", "global"), ("B", "entry"), ("C", "cli")]).into_iter().collect());
+        assert_eq!(config.command_env(&BTreeMap::new(), &[]), config.env.set);
+    }
+
+    #[test]
+    fn test_invalid_env_name_rejected() {
+        let dir = std::env::temp_dir().join("arc_config_env_name_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(CONFIG_FILE),
+            "[ruby]\nversion = \"3.3.6\"\n\n[commands]\ntest = { cmd = [\"rspec\"], env = { \"A=B\" = \"1\" } }\n",
+        )
+        .unwrap();
+        let err = format!("{:#}", ArcConfig::load(&dir).unwrap_err());
+        assert!(err.contains("[commands] test") && err.contains("'A=B'"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_alias_cycles_rejected() {
+        let err = validate_aliases(&aliases("a = [\"b\"]\nb = [\"a\", \"-v\"]\n").aliases).unwrap_err();
+        assert!(err.to_string().contains("a → b → a"));
+
+        let err = validate_aliases(&aliases("a = [\"a\"]\n").aliases).unwrap_err();
+        assert!(err.to_string().contains("循環"));
+
+        let err = validate_aliases(&aliases("a = [\"b\"]\nb = [\"c\"]\nc = [\"ls\"]\n").aliases).unwrap_err();
+        assert!(err.to_string().contains("1 段まで"));
+
+        assert!(validate_aliases(&aliases("a = []\n").aliases).is_err());
+    }
+
+    #[test]
+    fn test_values_expanded_on_load() {
+        let dir = std::env::temp_dir().join("arc_config_template_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(CONFIG_FILE),
+            "[ruby]\nversion = \"${RUBY:-3.3.6}\"\n\n[env]\nshared_dir = \"/scratch/${USER}/arc\"\n\n[aliases]\nhi = [\"echo\", \"${USER}\", \"$${USER}\"]\n",
+        )
+        .unwrap();
+        let env = |name: &str| (name == "USER").then(|| "alice".to_string());
+
+        let config = ArcConfig::load_with(&dir, Some(&env)).unwrap();
+        assert_eq!(config.ruby.version, "3.3.6");
+        assert_eq!(config.env.shared_dir, Some(PathBuf::from("/scratch/alice/arc")));
+        assert_eq!(config.expand_alias("hi", &[]).unwrap().cmd, ["echo", "alice", "${USER}"]);
+        let listed = config.list().unwrap();
+        assert!(listed.contains(&("env.shared_dir".to_string(), "\"/scratch/alice/arc\"".to_string())), "{:?}", listed);
+
+        // 展開前の形で読み込み・保存する
+        let raw = ArcConfig::load_raw(&dir).unwrap();
+        assert_eq!(raw.ruby.version, "${RUBY:-3.3.6}");
+        ArcConfig::update(&dir, |c| c.project.name = Some("app".into())).unwrap();
+        let saved = std::fs::read_to_string(dir.join(CONFIG_FILE)).unwrap();
+        assert!(saved.contains("/scratch/${USER}/arc") && saved.contains("$${USER}"), "{}", saved);
+
+        // strict モードでは未定義の変数をエラーにする
+        let strict = saved.replace("[ruby]", "[template]\nstrict = true\n\n[ruby]").replace("${RUBY:-3.3.6}", "${RUBY}");
+        std::fs::write(dir.join(CONFIG_FILE), strict).unwrap();
+        let err = format!("{:#}", ArcConfig::load_with(&dir, Some(&env)).unwrap_err());
+        assert!(err.contains("ruby.version") && err.contains("RUBY"), "{}", err);
+        assert!(ArcConfig::load_raw(&dir).unwrap().template.strict);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn test_config_save_load() {
         let dir = std::env::temp_dir().join("arc_config_test");
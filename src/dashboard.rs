@@ -0,0 +1,549 @@
+//! `arc ui` のデータ層。画面 (`ui`、`tui` feature) はここで作った行を並べるだけにする。
+//!
+//! - `Feed`: ログに追記された Signal だけを読み (`FluxProject::read_from`)、`StateBuilder` に適用する
+//! - 要約は `arc state` の見出しと同じ `display::header_lines`
+//! - 履歴は Signal ごとの `HistoryRow` を `Filter` (種別・失敗だけ) で絞り込む
+//! - 詳細は選んだ Signal の payload と、デタッチ実行なら保存した出力 (`.flux/output/`) の末尾
+//!
+//! キー操作 (`Action`) による選択・絞り込みの変化もここで決め、`arc sync` やタスクの実行は
+//! `Spawn` として画面に返す (画面は端末を一度戻してから `arc` 自身を起動するので、通常どおり記録される)。
+
+use anyhow::Result;
+use std::path::Path;
+
+use crate::config::ArcConfig;
+use crate::display;
+use crate::output_store;
+use crate::signals::{FluxProject, Signal};
+use crate::state::{FluxState, StateBuilder};
+
+/// 3 つのペインを表示できる最小の端末の大きさ
+pub const MIN_WIDTH: u16 = 60;
+pub const MIN_HEIGHT: u16 = 16;
+/// 履歴と詳細を横に並べる幅
+pub const COLUMNS_WIDTH: u16 = 110;
+/// 詳細に表示する出力の行数
+pub const OUTPUT_TAIL_LINES: usize = 20;
+/// 履歴の要約の文字数
+const SUMMARY_CHARS: usize = 60;
+/// タスクを指定しないときに実行するタスク (`[commands]` にあれば)
+const DEFAULT_TASK: &str = "test";
+
+/// 端末の大きさに応じた配置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// 小さすぎるので案内だけを表示する
+    TooSmall,
+    /// 要約・履歴・詳細を縦に並べる
+    Stacked,
+    /// 要約の下に履歴と詳細を横に並べる
+    Columns,
+}
+
+pub fn layout_for(width: u16, height: u16) -> Layout {
+    if width < MIN_WIDTH || height < MIN_HEIGHT {
+        Layout::TooSmall
+    } else if width < COLUMNS_WIDTH {
+        Layout::Stacked
+    } else {
+        Layout::Columns
+    }
+}
+
+// ─────────────────────────────────────────────
+// ログの読み進め
+// ─────────────────────────────────────────────
+
+/// 読み進めたログの位置と、そこまでの Signal・State
+#[derive(Debug, Default)]
+pub struct Feed {
+    builder: StateBuilder,
+    signals: Vec<Signal>,
+    offset: u64,
+}
+
+impl Feed {
+    pub fn state(&self) -> &FluxState {
+        self.builder.state()
+    }
+
+    pub fn signals(&self) -> &[Signal] {
+        &self.signals
+    }
+
+    /// Signal を適用する
+    pub fn extend(&mut self, signals: Vec<Signal>) {
+        for signal in &signals {
+            self.builder.apply(signal);
+        }
+        self.signals.extend(signals);
+    }
+
+    /// ログに追記された Signal を読み、読んだ数を返す。
+    /// ログが書き換えられていたら (`arc undo` など) 最初から読み直す。
+    pub fn poll(&mut self, project: &FluxProject) -> Result<usize> {
+        let Some((signals, offset)) = project.read_from(self.offset)? else {
+            *self = Self::default();
+            return self.poll(project);
+        };
+        let count = signals.len();
+        self.extend(signals);
+        self.offset = offset;
+        Ok(count)
+    }
+}
+
+// ─────────────────────────────────────────────
+// 履歴
+// ─────────────────────────────────────────────
+
+/// 失敗を表す Signal (成功しなかった end、sync の事前確認の失敗)
+pub fn is_failure(signal: &Signal) -> bool {
+    (signal.r_type.ends_with("_end") && signal.payload["success"].as_bool() == Some(false))
+        || signal.r_type == "preflight_failed"
+}
+
+/// 履歴の絞り込み
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    /// この種別だけ
+    pub r_type: Option<String>,
+    /// 失敗だけ
+    pub failures_only: bool,
+}
+
+impl Filter {
+    pub fn matches(&self, signal: &Signal) -> bool {
+        self.r_type.as_ref().is_none_or(|t| *t == signal.r_type) && (!self.failures_only || is_failure(signal))
+    }
+
+    /// 種別の絞り込みを、ログに現れる種別 (名前順) の次のものにする。最後の次は絞り込みなし
+    pub fn cycle_type(&mut self, signals: &[Signal]) {
+        let mut types: Vec<&str> = signals.iter().map(|s| s.r_type.as_str()).collect();
+        types.sort_unstable();
+        types.dedup();
+        self.r_type = match &self.r_type {
+            None => types.first().map(|t| t.to_string()),
+            Some(current) => types.iter().find(|t| **t > current.as_str()).map(|t| t.to_string()),
+        };
+    }
+
+    pub fn label(&self) -> String {
+        let mut parts = vec![self.r_type.clone().unwrap_or_else(|| "all types".to_string())];
+        if self.failures_only {
+            parts.push("failures".to_string());
+        }
+        parts.join(", ")
+    }
+}
+
+/// 履歴の 1 行
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRow {
+    pub id: String,
+    pub r_type: String,
+    pub time: String,
+    pub user: String,
+    /// payload の要約 (`arc state --raw` の Payload 列と同じ)
+    pub summary: String,
+    pub failed: bool,
+}
+
+impl HistoryRow {
+    pub fn from_signal(signal: &Signal) -> Self {
+        Self {
+            id: signal.id.clone(),
+            r_type: signal.r_type.clone(),
+            time: display::fmt_timestamp(&signal.timestamp),
+            user: signal.user().to_string(),
+            summary: crate::json_preview::preview(&signal.payload, SUMMARY_CHARS),
+            failed: is_failure(signal),
+        }
+    }
+
+    pub fn line(&self) -> String {
+        format!("{} {} {:<14} {}", if self.failed { "❌" } else { "  " }, self.time, self.r_type, self.summary)
+    }
+}
+
+// ─────────────────────────────────────────────
+// 詳細
+// ─────────────────────────────────────────────
+
+/// 選んだ Signal の詳細
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Detail {
+    pub title: String,
+    /// 整形した payload の行
+    pub payload: Vec<String>,
+    /// 保存した出力の末尾 (デタッチ実行の start / end のみ)
+    pub output: Vec<String>,
+}
+
+impl Detail {
+    pub fn from_signal(signal: &Signal, output_dir: &Path) -> Self {
+        let payload = serde_json::to_string_pretty(&signal.payload).unwrap_or_default();
+        // 出力は run_start の ID で保存される
+        let run_id = match signal.r_type.as_str() {
+            "run_start" => Some(signal.id.as_str()),
+            "run_end" => signal.payload["ref_id"].as_str(),
+            _ => None,
+        };
+        Self {
+            title: format!("{} {} ({}, {})", signal.r_type, signal.id, signal.user(), display::fmt_timestamp(&signal.timestamp)),
+            payload: payload.lines().map(String::from).collect(),
+            output: run_id.map(|id| output_tail(output_dir, id, OUTPUT_TAIL_LINES)).unwrap_or_default(),
+        }
+    }
+
+    /// 詳細ペインに並べる行
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = self.payload.clone();
+        if !self.output.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("── output (last {} lines) ──", OUTPUT_TAIL_LINES));
+            lines.extend(self.output.iter().cloned());
+        }
+        lines
+    }
+}
+
+/// 保存した stdout と stderr の末尾の `limit` 行 (stderr は印を付けて後ろに)
+pub fn output_tail(output_dir: &Path, id: &str, limit: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (stream, prefix) in [("out", ""), ("err", "stderr: ")] {
+        if let Ok(Some(text)) = output_store::read(output_dir, id, stream) {
+            lines.extend(text.lines().map(|l| format!("{}{}", prefix, l)));
+        }
+    }
+    let skip = lines.len().saturating_sub(limit);
+    lines.split_off(skip)
+}
+
+// ─────────────────────────────────────────────
+// 操作
+// ─────────────────────────────────────────────
+
+/// キー操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Up,
+    Down,
+    Top,
+    /// 最新の Signal に移り、追記を追いかける
+    Latest,
+    LatestFailure,
+    CycleType,
+    ToggleFailures,
+    ClearFilter,
+    Sync,
+    RunTask,
+    Quit,
+}
+
+/// 文字キーの割り当て (矢印キーは画面側で `Up` / `Down` にする)
+pub fn action_for(key: char) -> Option<Action> {
+    Some(match key {
+        'k' => Action::Up,
+        'j' => Action::Down,
+        'g' => Action::Top,
+        'G' => Action::Latest,
+        'n' => Action::LatestFailure,
+        't' => Action::CycleType,
+        'f' => Action::ToggleFailures,
+        'c' => Action::ClearFilter,
+        's' => Action::Sync,
+        'r' => Action::RunTask,
+        'q' => Action::Quit,
+        _ => return None,
+    })
+}
+
+/// 画面の下に表示するキーの案内
+pub const HELP: &str = "j/k move  g/G top/latest  n last failure  t type  f failures  c clear  s sync  r task  q quit";
+
+/// 画面から起動する arc のコマンド
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spawn {
+    Sync,
+    Task(String),
+}
+
+impl Spawn {
+    /// `arc` に渡す引数
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            Spawn::Sync => vec!["sync".to_string()],
+            Spawn::Task(name) => vec!["task".to_string(), name.clone()],
+        }
+    }
+}
+
+/// `r` で実行するタスク: `[display] ui_task`、なければ `[commands]` の `test`
+pub fn ui_task(config: &ArcConfig) -> Option<String> {
+    config
+        .display
+        .ui_task
+        .clone()
+        .or_else(|| config.commands.contains_key(DEFAULT_TASK).then(|| DEFAULT_TASK.to_string()))
+}
+
+/// 画面の状態 (ログ・絞り込み・選択)
+#[derive(Debug, Default)]
+pub struct View {
+    pub feed: Feed,
+    pub filter: Filter,
+    /// 選んでいる Signal。`None` なら最新を追いかける
+    selected: Option<String>,
+    pub task: Option<String>,
+    /// 直前の操作の結果
+    pub status: String,
+}
+
+impl View {
+    pub fn new(task: Option<String>) -> Self {
+        Self { task, ..Default::default() }
+    }
+
+    /// 絞り込んだ履歴 (ログの順)
+    pub fn rows(&self) -> Vec<HistoryRow> {
+        self.feed.signals().iter().filter(|s| self.filter.matches(s)).map(HistoryRow::from_signal).collect()
+    }
+
+    /// `rows` の中で選んでいる位置
+    pub fn selected_index(&self, rows: &[HistoryRow]) -> Option<usize> {
+        match &self.selected {
+            Some(id) => rows.iter().position(|r| r.id == *id).or(rows.len().checked_sub(1)),
+            None => rows.len().checked_sub(1),
+        }
+    }
+
+    pub fn selected_signal(&self) -> Option<&Signal> {
+        let rows = self.rows();
+        let id = &rows.get(self.selected_index(&rows)?)?.id;
+        self.feed.signals().iter().find(|s| s.id == *id)
+    }
+
+    /// 最新を追いかけているか
+    pub fn following(&self) -> bool {
+        self.selected.is_none()
+    }
+
+    fn select(&mut self, rows: &[HistoryRow], index: usize) {
+        self.selected = match index + 1 >= rows.len() {
+            true => None,
+            false => Some(rows[index].id.clone()),
+        };
+    }
+
+    /// 操作を適用する。arc のコマンドを起動する操作ならそれを返す
+    pub fn apply(&mut self, action: Action) -> Option<Spawn> {
+        let rows = self.rows();
+        let current = self.selected_index(&rows);
+        match action {
+            Action::Up => {
+                if let Some(index) = current {
+                    self.select(&rows, index.saturating_sub(1));
+                }
+            }
+            Action::Down => {
+                if let Some(index) = current {
+                    self.select(&rows, index + 1);
+                }
+            }
+            Action::Top if !rows.is_empty() => self.select(&rows, 0),
+            Action::Top => {}
+            Action::Latest => self.selected = None,
+            Action::LatestFailure => match rows.iter().rposition(|r| r.failed) {
+                Some(index) => self.select(&rows, index),
+                None => self.status = "No failures in the list".to_string(),
+            },
+            Action::CycleType => {
+                self.filter.cycle_type(self.feed.signals());
+                self.status = format!("Filter: {}", self.filter.label());
+            }
+            Action::ToggleFailures => {
+                self.filter.failures_only = !self.filter.failures_only;
+                self.status = format!("Filter: {}", self.filter.label());
+            }
+            Action::ClearFilter => {
+                self.filter = Filter::default();
+                self.status = format!("Filter: {}", self.filter.label());
+            }
+            Action::Sync => return Some(Spawn::Sync),
+            Action::RunTask => match &self.task {
+                Some(task) => return Some(Spawn::Task(task.clone())),
+                None => self.status = "No task to run; set [display] ui_task".to_string(),
+            },
+            Action::Quit => {}
+        }
+        None
+    }
+
+    /// 要約ペインの行 (`arc state` の見出しと同じ)
+    pub fn summary(&self, config: &ArcConfig) -> Vec<String> {
+        display::header_lines(self.feed.state(), config, None)
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+    use std::fs;
+
+    fn signal(id: &str, r_type: &str, minute: u32, payload: Value) -> Signal {
+        Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: format!("2024-05-01T10:{:02}:00+09:00", minute),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    fn log() -> Vec<Signal> {
+        vec![
+            signal("1", "init", 0, json!({ "path": "/p", "version": "0.1.0" })),
+            signal("2", "run_start", 1, json!({ "command": "rspec", "args": [], "cwd": "/p" })),
+            signal("3", "run_end", 2, json!({ "ref_id": "2", "exit_code": 1, "success": false, "duration_us": 1000 })),
+            signal("4", "add", 3, json!({ "gem": "rack" })),
+            signal("5", "preflight_failed", 4, json!({ "reason": "disk" })),
+            signal("6", "exec_start", 5, json!({ "command": "rake", "args": [], "cwd": "/p" })),
+            signal("7", "exec_end", 6, json!({ "ref_id": "6", "exit_code": 0, "success": true, "duration_us": 1000 })),
+        ]
+    }
+
+    fn view() -> View {
+        let mut view = View::new(None);
+        view.feed.extend(log());
+        view
+    }
+
+    fn ids(rows: &[HistoryRow]) -> Vec<&str> {
+        rows.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_layout_degrades() {
+        assert_eq!(layout_for(40, 30), Layout::TooSmall);
+        assert_eq!(layout_for(120, 10), Layout::TooSmall);
+        assert_eq!(layout_for(80, 24), Layout::Stacked);
+        assert_eq!(layout_for(160, 40), Layout::Columns);
+    }
+
+    #[test]
+    fn test_rows_and_filters() {
+        let mut view = view();
+        let rows = view.rows();
+        assert_eq!(rows.len(), 7);
+        assert_eq!(rows[2].time, "2024-05-01 10:02");
+        assert_eq!(rows.iter().filter(|r| r.failed).count(), 2);
+        assert_eq!(rows[3].summary, r#"{"gem":"rack"}"#);
+        assert!(rows[2].line().starts_with("❌ 2024-05-01 10:02 run_end"));
+
+        view.apply(Action::ToggleFailures);
+        assert_eq!(ids(&view.rows()), ["3", "5"]);
+        assert_eq!(view.status, "Filter: all types, failures");
+        // 種別は名前順に巡回し、最後の次は絞り込みなし
+        view.apply(Action::ClearFilter);
+        let mut seen = Vec::new();
+        for _ in 0..8 {
+            view.apply(Action::CycleType);
+            seen.push(view.filter.r_type.clone().unwrap_or_default());
+        }
+        assert_eq!(seen, ["add", "exec_end", "exec_start", "init", "preflight_failed", "run_end", "run_start", ""]);
+    }
+
+    #[test]
+    fn test_selection_follows_latest() {
+        let mut view = view();
+        assert!(view.following());
+        assert_eq!(view.selected_signal().unwrap().id, "7");
+
+        view.apply(Action::LatestFailure);
+        assert_eq!(view.selected_signal().unwrap().id, "5");
+        view.apply(Action::Up);
+        view.apply(Action::Up);
+        assert_eq!(view.selected_signal().unwrap().id, "3");
+        // 追記されても選んだ Signal のまま
+        view.feed.extend(vec![signal("8", "add", 7, json!({ "gem": "puma" }))]);
+        assert_eq!(view.selected_signal().unwrap().id, "3");
+        view.apply(Action::Top);
+        assert_eq!(view.selected_signal().unwrap().id, "1");
+
+        view.apply(Action::Latest);
+        view.feed.extend(vec![signal("9", "remove", 8, json!({ "gem": "puma" }))]);
+        assert_eq!(view.selected_signal().unwrap().id, "9");
+        // 最後の行まで下がると追いかける状態に戻る
+        view.apply(Action::Up);
+        assert!(!view.following());
+        view.apply(Action::Down);
+        assert!(view.following());
+
+        // 絞り込みで選んだ Signal が消えたら最後の行
+        view.apply(Action::Top);
+        view.apply(Action::ToggleFailures);
+        assert_eq!(view.selected_signal().unwrap().id, "5");
+    }
+
+    #[test]
+    fn test_state_and_summary_follow_feed() {
+        let mut view = View::new(None);
+        view.feed.extend(log()[..2].to_vec());
+        assert!(view.feed.state().executions.is_empty());
+        view.feed.extend(log()[2..].to_vec());
+        assert_eq!(view.feed.state().executions.len(), 2);
+
+        let summary = view.summary(&ArcConfig::default());
+        assert!(summary.contains(&"  Project:     /p".to_string()), "{:?}", summary);
+        assert!(summary.contains(&"  Executions:  2".to_string()), "{:?}", summary);
+    }
+
+    #[test]
+    fn test_spawn_and_task() {
+        let mut view = view();
+        assert_eq!(view.apply(Action::Sync), Some(Spawn::Sync));
+        assert_eq!(view.apply(Action::RunTask), None);
+        assert!(view.status.contains("ui_task"));
+
+        let mut config = ArcConfig::default();
+        assert_eq!(ui_task(&config), None);
+        config.commands.insert("test".to_string(), crate::config::CommandEntry::Argv(vec!["rspec".to_string()]));
+        assert_eq!(ui_task(&config).as_deref(), Some("test"));
+        config.display.ui_task = Some("lint".to_string());
+        let mut view = View::new(ui_task(&config));
+        assert_eq!(view.apply(Action::RunTask).unwrap().args(), ["task", "lint"]);
+        assert_eq!(action_for('n'), Some(Action::LatestFailure));
+        assert_eq!(action_for('x'), None);
+    }
+
+    #[test]
+    fn test_detail_shows_payload_and_output_tail() {
+        let dir = std::env::temp_dir().join("arc_dashboard_output_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let out: String = (1..=25).map(|i| format!("line {}\n", i)).collect();
+        fs::write(dir.join("2.out"), out).unwrap();
+        fs::write(dir.join("2.err"), "boom\n").unwrap();
+
+        let log = log();
+        let detail = Detail::from_signal(&log[2], &dir);
+        assert!(detail.title.starts_with("run_end 3 (unknown, 2024-05-01 10:02)"));
+        assert!(detail.payload.contains(&r#"  "ref_id": "2","#.to_string()));
+        assert_eq!(detail.output.len(), OUTPUT_TAIL_LINES);
+        assert_eq!(detail.output.first().unwrap(), "line 7");
+        assert_eq!(detail.output.last().unwrap(), "stderr: boom");
+        assert!(detail.lines().contains(&"── output (last 20 lines) ──".to_string()));
+        // 出力のない Signal は payload だけ
+        let detail = Detail::from_signal(&log[3], &dir);
+        assert!(detail.output.is_empty());
+        assert_eq!(detail.lines(), detail.payload);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+//! Gemfile.lock の依存グラフ (`arc tree`)。
+//!
+//! グラフの構築・反転・走査はすべてパース済みの `Lockfile` に対する純粋関数。
+//! 表示は cargo tree と同じく、同じ Gem の 2 回目以降は `(*)` を付けて展開しない。
+
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::lockfile::Lockfile;
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// Gem をノード、依存関係を辺とする有向グラフ。
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    /// Gem 名 → ノード
+    pub nodes: BTreeMap<String, Node>,
+    /// 走査の起点 (通常は Gemfile の直接依存)
+    pub roots: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    /// 解決済みバージョン。specs に現れない Gem (bundler 等) は `None`
+    pub version: Option<String>,
+    pub edges: Vec<String>,
+}
+
+/// 木として表示する 1 行。
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeLine {
+    pub name: String,
+    pub version: Option<String>,
+    /// 祖先から自分までの各階層で、そのノードが兄弟の最後か (ルートは含まない)
+    pub last: Vec<bool>,
+    pub mark: Mark,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    None,
+    /// 既に展開済みのため省略した
+    Repeated,
+    /// 祖先に同じ Gem があるため打ち切った
+    Cycle,
+}
+
+// ─────────────────────────────────────────────
+// グラフ構築
+// ─────────────────────────────────────────────
+
+impl Graph {
+    /// ロックファイルから依存グラフを構築する。ルートは `DEPENDENCIES` の直接依存。
+    pub fn from_lockfile(lock: &Lockfile) -> Self {
+        let mut nodes: BTreeMap<String, Node> = BTreeMap::new();
+        for spec in &lock.specs {
+            // プラットフォーム違いで同名の spec が複数ある場合は依存をまとめる
+            let node = nodes.entry(spec.name.clone()).or_default();
+            node.version.get_or_insert_with(|| spec.version.clone());
+            for dep in &spec.dependencies {
+                if !node.edges.contains(dep) {
+                    node.edges.push(dep.clone());
+                }
+            }
+        }
+        for spec in &lock.specs {
+            for dep in &spec.dependencies {
+                nodes.entry(dep.clone()).or_default();
+            }
+        }
+
+        let mut roots: Vec<String> = lock.dependencies.clone();
+        roots.sort();
+        roots.dedup();
+        for root in &roots {
+            nodes.entry(root.clone()).or_default();
+        }
+
+        Self { nodes, roots }
+    }
+
+    /// 辺を反転したグラフ ("X に依存しているのは誰か") を返す。ルートは引き継がない。
+    pub fn invert(&self) -> Self {
+        let mut nodes: BTreeMap<String, Node> = self
+            .nodes
+            .iter()
+            .map(|(name, node)| (name.clone(), Node { version: node.version.clone(), edges: vec![] }))
+            .collect();
+        for (name, node) in &self.nodes {
+            for dep in &node.edges {
+                if let Some(target) = nodes.get_mut(dep) {
+                    target.edges.push(name.clone());
+                }
+            }
+        }
+        for node in nodes.values_mut() {
+            node.edges.sort();
+        }
+        Self { nodes, roots: vec![] }
+    }
+
+    /// ルートを `root` 1 つに差し替えたグラフを返す。
+    pub fn rooted_at(mut self, root: &str) -> Option<Self> {
+        if !self.nodes.contains_key(root) {
+            return None;
+        }
+        self.roots = vec![root.to_string()];
+        Some(self)
+    }
+
+    /// ルートから `max_depth` 以内で到達できるノードだけの部分グラフを返す。
+    pub fn reachable(&self, max_depth: Option<usize>) -> Self {
+        let mut depth_of: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut queue: std::collections::VecDeque<(&str, usize)> =
+            self.roots.iter().map(|r| (r.as_str(), 0)).collect();
+
+        while let Some((name, depth)) = queue.pop_front() {
+            if depth_of.contains_key(name) {
+                continue;
+            }
+            depth_of.insert(name, depth);
+            if max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(name) {
+                queue.extend(node.edges.iter().map(|e| (e.as_str(), depth + 1)));
+            }
+        }
+
+        let nodes = depth_of
+            .iter()
+            .map(|(name, depth)| {
+                let node = &self.nodes[*name];
+                let edges = if max_depth.is_some_and(|max| *depth >= max) {
+                    vec![]
+                } else {
+                    node.edges.clone()
+                };
+                (name.to_string(), Node { version: node.version.clone(), edges })
+            })
+            .collect();
+        Self { nodes, roots: self.roots.clone() }
+    }
+
+    /// 隣接リストを JSON に変換する。
+    pub fn to_json(&self) -> Value {
+        let gems: serde_json::Map<String, Value> = self
+            .nodes
+            .iter()
+            .map(|(name, node)| {
+                (name.clone(), json!({ "version": node.version, "dependencies": node.edges }))
+            })
+            .collect();
+        json!({ "roots": self.roots, "gems": gems })
+    }
+}
+
+// ─────────────────────────────────────────────
+// 走査・表示
+// ─────────────────────────────────────────────
+
+/// ルートから深さ優先で走査し、表示用の行を返す。
+/// `max_depth` はルートを 0 とした深さの上限。
+pub fn walk(graph: &Graph, max_depth: Option<usize>) -> Vec<TreeLine> {
+    let mut lines = Vec::new();
+    let mut expanded: BTreeSet<String> = BTreeSet::new();
+    for root in &graph.roots {
+        let mut path = Vec::new();
+        visit(graph, root, &mut vec![], &mut path, &mut expanded, max_depth, &mut lines);
+    }
+    lines
+}
+
+fn visit(
+    graph: &Graph,
+    name: &str,
+    last: &mut Vec<bool>,
+    path: &mut Vec<String>,
+    expanded: &mut BTreeSet<String>,
+    max_depth: Option<usize>,
+    lines: &mut Vec<TreeLine>,
+) {
+    let node = graph.nodes.get(name);
+    let edges = node.map(|n| n.edges.as_slice()).unwrap_or_default();
+
+    let mark = if path.iter().any(|p| p == name) {
+        Mark::Cycle
+    } else if !edges.is_empty() && expanded.contains(name) {
+        Mark::Repeated
+    } else {
+        Mark::None
+    };
+    lines.push(TreeLine {
+        name: name.to_string(),
+        version: node.and_then(|n| n.version.clone()),
+        last: last.clone(),
+        mark,
+    });
+
+    if mark != Mark::None || max_depth.is_some_and(|max| path.len() >= max) {
+        return;
+    }
+    expanded.insert(name.to_string());
+
+    path.push(name.to_string());
+    for (i, dep) in edges.iter().enumerate() {
+        last.push(i + 1 == edges.len());
+        visit(graph, dep, last, path, expanded, max_depth, lines);
+        last.pop();
+    }
+    path.pop();
+}
+
+/// 走査結果を罫線付きのテキストに整形する。
+pub fn render(lines: &[TreeLine]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        if let Some((is_last, ancestors)) = line.last.split_last() {
+            for ancestor_last in ancestors {
+                out.push_str(if *ancestor_last { "    " } else { "│   " });
+            }
+            out.push_str(if *is_last { "└── " } else { "├── " });
+        }
+        out.push_str(&line.name);
+        if let Some(ref version) = line.version {
+            out.push_str(&format!(" v{}", version));
+        }
+        match line.mark {
+            Mark::None => {}
+            Mark::Repeated => out.push_str(" (*)"),
+            Mark::Cycle => out.push_str(" (cycle)"),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile;
+
+    /// web → (router, views) → support のダイアモンド依存と、
+    /// 直接依存 json が support からも使われる共有依存を含む。
+    const LOCK: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    json (2.7.1)
+    router (1.2.0)
+      support (~> 3.0)
+    support (3.1.0)
+      json (>= 2.0)
+    views (0.9.0)
+      support (~> 3.0)
+    web (4.0.0)
+      router (~> 1.0)
+      views (~> 0.9)
+
+PLATFORMS
+  x86_64-linux
+
+DEPENDENCIES
+  json
+  web (~> 4.0)
+";
+
+    fn graph() -> Graph {
+        Graph::from_lockfile(&lockfile::parse_content(LOCK))
+    }
+
+    fn names(lines: &[TreeLine]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| format!("{}{}", "  ".repeat(l.last.len()), l.name))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_lockfile() {
+        let g = graph();
+        assert_eq!(g.roots, ["json", "web"]);
+        assert_eq!(g.nodes["web"].edges, ["router", "views"]);
+        assert_eq!(g.nodes["support"].version.as_deref(), Some("3.1.0"));
+    }
+
+    #[test]
+    fn test_walk_marks_repeated() {
+        let lines = walk(&graph(), None);
+        assert_eq!(
+            names(&lines),
+            ["json", "web", "  router", "    support", "      json", "  views", "    support"]
+        );
+        // 2 回目の support は展開しない。葉の json は何度出ても (*) を付けない
+        assert_eq!(lines[6].mark, Mark::Repeated);
+        assert_eq!(lines[4].mark, Mark::None);
+    }
+
+    #[test]
+    fn test_walk_depth_limit() {
+        let lines = walk(&graph(), Some(1));
+        assert_eq!(names(&lines), ["json", "web", "  router", "  views"]);
+    }
+
+    #[test]
+    fn test_invert() {
+        let inverted = graph().invert().rooted_at("support").unwrap();
+        let lines = walk(&inverted, None);
+        assert_eq!(names(&lines), ["support", "  router", "    web", "  views", "    web"]);
+        assert_eq!(inverted.nodes["json"].edges, ["support"]);
+        assert!(graph().invert().rooted_at("missing").is_none());
+    }
+
+    #[test]
+    fn test_cycle_protection() {
+        let lock = lockfile::parse_content(
+            "GEM\n  specs:\n    a (1.0)\n      b\n    b (1.0)\n      a\n\nDEPENDENCIES\n  a\n",
+        );
+        let lines = walk(&Graph::from_lockfile(&lock), None);
+        assert_eq!(names(&lines), ["a", "  b", "    a"]);
+        assert_eq!(lines[2].mark, Mark::Cycle);
+    }
+
+    #[test]
+    fn test_render() {
+        let out = render(&walk(&graph(), None));
+        assert!(out.starts_with("json v2.7.1\nweb v4.0.0\n├── router v1.2.0\n│   └── support v3.1.0\n"));
+        assert!(out.ends_with("└── views v0.9.0\n    └── support v3.1.0 (*)\n"));
+    }
+
+    #[test]
+    fn test_reachable_json() {
+        let sub = graph().rooted_at("router").unwrap().reachable(Some(1));
+        let value = sub.to_json();
+        assert_eq!(value["roots"], json!(["router"]));
+        assert_eq!(value["gems"]["router"]["dependencies"], json!(["support"]));
+        // 深さ上限にあるノードの辺は含めない
+        assert_eq!(value["gems"]["support"]["dependencies"], json!([]));
+        assert!(value["gems"].get("json").is_none());
+    }
+}
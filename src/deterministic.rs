@@ -0,0 +1,168 @@
+//! `ARC_DETERMINISTIC=1`: 同じ操作から同じ `signals.jsonl` を作る、時刻を止めたモード (テスト・ドキュメント・デモ用)。
+//!
+//! このモードでは `FluxProject::record` が:
+//!
+//! - n 番目 (0 始まり) の Signal の時刻を `EPOCH` + n 秒にする
+//! - ID を、その時刻とシード (`ARC_SEED`、既定は 0) から決まる UUID v7 にする
+//! - payload の所要時間 (`*_ms` / `*_us`) を 0 にする (時刻が止まっているため)
+//!
+//! 番号はログの行数で決まるので、別々のプロセスで実行しても続きの番号になる。
+//! 通常のモードで記録した Signal が混ざったログでは、再現できないため記録を拒否する。
+//! 環境変数を設定しなければ通常の動作には一切影響しない。
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+use std::cell::Cell;
+
+use crate::signals::Signal;
+
+/// 有効にする環境変数 (`1` で有効)
+pub const ENV: &str = "ARC_DETERMINISTIC";
+/// ID の元にするシード
+pub const SEED_ENV: &str = "ARC_SEED";
+/// 最初の Signal の時刻
+pub const EPOCH: &str = "2024-01-01T00:00:00+00:00";
+
+thread_local! {
+    static SEED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// 環境変数が設定されていれば有効にする (起動時)。
+pub fn enable_from_env() -> Result<()> {
+    if std::env::var(ENV).is_ok_and(|v| v == "1") {
+        let seed = match std::env::var(SEED_ENV) {
+            Ok(seed) => seed.parse().map_err(|_| anyhow::anyhow!("{} は整数で指定してください: {:?}", SEED_ENV, seed))?,
+            Err(_) => 0,
+        };
+        enable(seed);
+    }
+    Ok(())
+}
+
+pub fn enable(seed: u64) {
+    SEED.with(|s| s.set(Some(seed)));
+}
+
+pub fn is_enabled() -> bool {
+    SEED.with(|s| s.get()).is_some()
+}
+
+/// n 番目の Signal の時刻
+pub fn timestamp(index: u64) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(EPOCH).expect("EPOCH is RFC 3339") + chrono::Duration::seconds(index as i64)
+}
+
+/// n 番目の Signal の ID。時刻の部分は `timestamp(index)`、残りはシードと番号から決める
+pub fn signal_id(seed: u64, index: u64) -> String {
+    let mut state = seed ^ index.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let mut bytes = [0u8; 10];
+    bytes[..8].copy_from_slice(&splitmix64(&mut state).to_be_bytes());
+    bytes[8..].copy_from_slice(&splitmix64(&mut state).to_be_bytes()[..2]);
+    let millis = timestamp(index).timestamp_millis() as u64;
+    uuid::Builder::from_unix_timestamp_millis(millis, &bytes).into_uuid().to_string()
+}
+
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// 次に記録する Signal の (ID, 時刻)。無効なら `None`。
+/// 既存のログにこのモード (同じシード) で記録していない Signal があればエラーにする。
+pub fn next(existing: &[Signal]) -> Result<Option<(String, String)>> {
+    let Some(seed) = SEED.with(|s| s.get()) else { return Ok(None) };
+    for (index, signal) in existing.iter().enumerate() {
+        let index = index as u64;
+        if signal.id != signal_id(seed, index) || signal.timestamp != timestamp(index).to_rfc3339() {
+            bail!(
+                "{} のログに通常のモード (または別のシード) で記録した Signal があります (#{} {})。\
+                 再現できないため記録しません。新しいプロジェクトで実行してください",
+                ENV,
+                index + 1,
+                signal.id
+            );
+        }
+    }
+    let index = existing.len() as u64;
+    Ok(Some((signal_id(seed, index), timestamp(index).to_rfc3339())))
+}
+
+/// 有効なら payload の所要時間 (`*_ms` / `*_us` の数値) を 0 にする。
+pub fn freeze_durations(payload: &mut Value) {
+    if !is_enabled() {
+        return;
+    }
+    match payload {
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                if value.is_number() && (key.ends_with("_ms") || key.ends_with("_us")) {
+                    *value = Value::from(0);
+                } else {
+                    freeze_durations(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(freeze_durations),
+        _ => {}
+    }
+}
+
+/// テスト用: `f` をこのモードで実行する。
+#[cfg(test)]
+pub fn scoped<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    enable(seed);
+    let result = f();
+    SEED.with(|s| s.set(None));
+    result
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_ids_are_sequential_v7() {
+        let ids: Vec<String> = (0..3).map(|i| signal_id(7, i)).collect();
+        assert_eq!(ids, (0..3).map(|i| signal_id(7, i)).collect::<Vec<_>>());
+        assert_ne!(signal_id(7, 0), signal_id(8, 0));
+        assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+        let uuid = Uuid::parse_str(&ids[1]).unwrap();
+        assert_eq!(uuid.get_version_num(), 7);
+        assert_eq!(timestamp(1).to_rfc3339(), "2024-01-01T00:00:01+00:00");
+    }
+
+    #[test]
+    fn test_next_refuses_mixed_log() {
+        assert_eq!(next(&[]).unwrap(), None);
+        let signal = |id: String, timestamp: String| Signal { id, r_type: "add".to_string(), payload: json!({}), timestamp, meta: None, v: 2 };
+        scoped(1, || {
+            let (id, ts) = next(&[]).unwrap().unwrap();
+            let log = vec![signal(id, ts)];
+            assert_eq!(next(&log).unwrap().unwrap().1, "2024-01-01T00:00:01+00:00");
+
+            let mixed = vec![log[0].clone(), signal(Uuid::now_v7().to_string(), chrono::Local::now().to_rfc3339())];
+            let err = next(&mixed).unwrap_err().to_string();
+            assert!(err.contains("#2") && err.contains("記録しません"), "{}", err);
+        });
+        assert!(scoped(2, || next(&[signal(signal_id(1, 0), timestamp(0).to_rfc3339())])).is_err());
+    }
+
+    #[test]
+    fn test_freeze_durations() {
+        let mut payload = json!({ "duration_ms": 12, "phases": [{ "name": "x", "elapsed_us": 5 }], "gem": "rack", "bytes": 9 });
+        freeze_durations(&mut payload);
+        assert_eq!(payload["duration_ms"], 12);
+        scoped(0, || freeze_durations(&mut payload));
+        assert_eq!(payload, json!({ "duration_ms": 0, "phases": [{ "name": "x", "elapsed_us": 0 }], "gem": "rack", "bytes": 9 }));
+    }
+}
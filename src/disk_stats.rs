@@ -0,0 +1,131 @@
+//! `.arc/env` のサイズの推移 (`arc stats --disk`)。
+//!
+//! install_end / bootstrap の payload の `disk` (`fs_util::DirSize`) だけを使う純粋な集計。
+//! どの操作でサイズが増えたかは、直前の add / remove / undo から推定する。
+
+use crate::signals::{Signal, SignalType};
+
+/// 1 回の計測
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskPoint {
+    pub timestamp: String,
+    pub bytes: u64,
+    /// 時間切れで途中までしか数えていない (実際より小さい)
+    pub partial: bool,
+    /// 計測の直前の操作 (例: `add nokogiri`, `sync`, `bootstrap 3.3.6`)
+    pub operation: String,
+}
+
+/// サイズの推移と、最大の増加
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiskStats {
+    /// 時系列順
+    pub points: Vec<DiskPoint>,
+    /// 直前の計測からの増加が最も大きい計測の位置 (`points` の添字)
+    pub largest_jump: Option<usize>,
+}
+
+impl DiskStats {
+    pub fn from_signals(signals: &[Signal]) -> Self {
+        let mut stats = Self::default();
+        // install の前に Gemfile を変えた操作
+        let mut pending: Option<String> = None;
+        for signal in signals {
+            let p = &signal.payload;
+            let operation = match signal.r_type.as_str() {
+                t if t == SignalType::Add.to_string() || t == SignalType::Remove.to_string() => {
+                    pending = Some(format!("{} {}", t, p["gem"].as_str().unwrap_or("?")));
+                    continue;
+                }
+                t if t == SignalType::Undo.to_string() => {
+                    pending = Some("undo".to_string());
+                    continue;
+                }
+                t if t == SignalType::InstallEnd.to_string() => pending.take().unwrap_or_else(|| "sync".to_string()),
+                t if t == SignalType::Bootstrap.to_string() => {
+                    format!("bootstrap {}", p["ruby_version"].as_str().unwrap_or("?"))
+                }
+                _ => continue,
+            };
+            let Some(bytes) = p["disk"]["bytes"].as_u64() else { continue };
+            stats.points.push(DiskPoint {
+                timestamp: signal.timestamp.clone(),
+                bytes,
+                partial: p["disk"]["partial"].as_bool().unwrap_or(false),
+                operation,
+            });
+        }
+        stats.largest_jump = (1..stats.points.len())
+            .filter(|&i| stats.delta(i) > 0)
+            .max_by_key(|&i| stats.delta(i));
+        stats
+    }
+
+    /// `points[i]` の直前の計測からの増減 (先頭は 0)
+    pub fn delta(&self, i: usize) -> i64 {
+        match i {
+            0 => 0,
+            _ => self.points[i].bytes as i64 - self.points[i - 1].bytes as i64,
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn signal(r_type: &str, payload: Value) -> Signal {
+        Signal {
+            id: uuid::Uuid::now_v7().to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: "2024-03-01T10:00:00+09:00".to_string(),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    fn disk(bytes: u64) -> Value {
+        json!({ "bytes": bytes, "files": 1, "partial": false })
+    }
+
+    #[test]
+    fn test_points_and_largest_jump() {
+        let signals = vec![
+            signal("init", json!({})),
+            signal("bootstrap", json!({ "ruby_version": "3.3.6", "disk": disk(100) })),
+            signal("install_end", json!({ "disk": disk(300) })),
+            signal("add", json!({ "gem": "nokogiri" })),
+            signal("install_end", json!({ "disk": disk(2_300) })),
+            signal("remove", json!({ "gem": "rake" })),
+            signal("install_end", json!({ "disk": { "bytes": 2_000, "partial": true } })),
+            // 計測していない install (track_disk = false)
+            signal("add", json!({ "gem": "json" })),
+            signal("install_end", json!({})),
+            signal("install_end", json!({ "disk": disk(2_100) })),
+        ];
+        let stats = DiskStats::from_signals(&signals);
+        let ops: Vec<&str> = stats.points.iter().map(|p| p.operation.as_str()).collect();
+        assert_eq!(ops, ["bootstrap 3.3.6", "sync", "add nokogiri", "remove rake", "sync"]);
+        assert_eq!(stats.largest_jump, Some(2));
+        assert_eq!(stats.delta(2), 2_000);
+        assert_eq!(stats.delta(3), -300);
+        assert!(stats.points[3].partial);
+        assert_eq!(stats.delta(0), 0);
+    }
+
+    #[test]
+    fn test_no_growth_has_no_jump() {
+        let signals = vec![
+            signal("install_end", json!({ "disk": disk(500) })),
+            signal("install_end", json!({ "disk": disk(400) })),
+        ];
+        assert_eq!(DiskStats::from_signals(&signals).largest_jump, None);
+        assert_eq!(DiskStats::from_signals(&[]), DiskStats::default());
+    }
+}
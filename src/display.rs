@@ -1,9 +1,22 @@
 use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use std::io::IsTerminal;
+use std::time::Duration;
 use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::cache_stats::CacheStats;
+use crate::config::ArcConfig;
+use crate::disk_stats::DiskStats;
+use crate::exec_label;
+use crate::flaky::FlakyCommand;
+use crate::follow::FollowSummary;
 use crate::gemfile;
+use crate::lockfile::{self, Lockfile};
 use crate::signals;
-use crate::state::FluxState;
+use crate::stats_compare::Comparison;
+use crate::state::{CommandStats, DayActivity, Execution, FailureGroup, FluxState, RubyInstall};
 
 // ─────────────────────────────────────────────
 // 表示エントリポイント
@@ -11,188 +24,2187 @@ use crate::state::FluxState;
 
 /// Signal ログを生テーブルで表示する。
 pub fn render_raw(signals: &[&signals::Signal], flux_dir: &Path) -> Result<()> {
+    if is_plain() {
+        print_plain(&raw_records(signals, flux_dir));
+        return Ok(());
+    }
     eprintln!(
         "🦄 Flux Signals — {} entries from {:?}",
-        signals.len(),
+        fmt_count(signals.len() as u64),
         flux_dir
     );
 
-    let sep_top = "┌─────────────┬──────────────────────────────────────┬──────────────────────────────────────────────────┐";
-    let sep_mid = "├─────────────┼──────────────────────────────────────┼──────────────────────────────────────────────────┤";
-    let sep_bot = "└─────────────┴──────────────────────────────────────┴──────────────────────────────────────────────────┘";
+    for line in raw_lines(signals, is_verbose()) {
+        println!("{}", line);
+    }
+    Ok(())
+}
 
-    println!("{sep_top}");
-    println!("│ {:<11} │ {:<36} │ {:<48} │", "Type", "ID", "Payload");
-    println!("{sep_mid}");
+/// 生テーブルを行ごとに組み立てる。複数のユーザーの Signal が含まれる場合だけ User 列を加える。
+/// `verbose` なら、System モードで起動したバイナリ (`env_context.binary`) を次の行に添える。
+fn raw_lines(signals: &[&signals::Signal], verbose: bool) -> Vec<String> {
+    let show_user = signals::distinct_users(signals.iter().copied()) > 1;
+    let sep = |left: &str, mid: &str, right: &str| {
+        let mut cols = vec!["─".repeat(13)];
+        if show_user {
+            cols.push("─".repeat(14));
+        }
+        cols.push("─".repeat(38));
+        cols.push("─".repeat(50));
+        format!("{}{}{}", left, cols.join(mid), right)
+    };
+    let row = |r_type: &str, user: &str, id: &str, payload: &str| {
+        if show_user {
+            format!("│ {:<11} │ {:<12} │ {:<36} │ {:<48} │", r_type, signals::truncate_display(user, 12), id, payload)
+        } else {
+            format!("│ {:<11} │ {:<36} │ {:<48} │", r_type, id, payload)
+        }
+    };
 
+    let mut lines = vec![sep("┌", "┬", "┐")];
+    lines.push(row("Type", "User", "ID", "Payload"));
+    lines.push(sep("├", "┼", "┤"));
     for s in signals {
-        let payload = signals::truncate_display(&s.payload.to_string(), 48);
-        println!("│ {:<11} │ {:<36} │ {:<48} │", s.r_type, s.id, payload);
+        let payload = crate::json_preview::preview(&s.payload, 48);
+        lines.push(row(&s.r_type, s.user(), &s.id, &payload));
+        if verbose && let Some(binary) = crate::interpreter::describe(&s.payload["env_context"]) {
+            lines.push(row("", "", "", &format!("↳ {}", signals::truncate_display(&binary, 46))));
+        }
     }
+    lines.push(sep("└", "┴", "┘"));
+    lines
+}
 
-    println!("{sep_bot}");
-    Ok(())
+/// 生テーブルと同じ内容の `--plain` のレコード。payload は切り詰めない。
+fn raw_records(signals: &[&signals::Signal], flux_dir: &Path) -> Vec<Record> {
+    let mut records = vec![vec![("signals", signals.len().to_string()), ("source", flux_dir.display().to_string())]];
+    for s in signals {
+        records.push(vec![
+            ("signal", s.id.clone()),
+            ("type", s.r_type.clone()),
+            ("user", s.user().to_string()),
+            ("timestamp", s.timestamp.clone()),
+            ("payload", s.payload.to_string()),
+        ]);
+    }
+    records
 }
 
 /// Signal ログから状態を再構築し、サマリーとコマンド統計を表示する。
 ///
-/// `cwd` はプロジェクトルートの絶対パス。Gemfile の読み取りに使用する。
-pub fn render_full(signals: &[signals::Signal], cwd: &Path) -> Result<()> {
-    let state = FluxState::from_signals(signals);
-    let stats = state.command_stats();
+/// `gemfile` は依存関係の一覧を読む Gemfile の絶対パス (`--from` のように読まない場合は `None`)。
+/// `show_all` の場合は `stats.ignore` を無視してすべての実行を集計する。
+/// `layout` を省略した場合は端末幅から統計テーブルのレイアウトを選ぶ。
+/// `user` を指定した場合はそのユーザーの実行だけを集計する。
+/// 失敗した操作は原因とコマンドごとにまとめて表示する (`failures_verbose` なら 1 件ずつ)。
+pub fn render_full(
+    signals: &[signals::Signal],
+    gemfile: Option<&Path>,
+    config: &ArcConfig,
+    show_all: bool,
+    layout: Option<Layout>,
+    user: Option<&str>,
+    failures_verbose: bool,
+) -> Result<()> {
+    let mut state = FluxState::from_signals(signals);
+    if let Some(user) = user {
+        state.retain_user(user);
+    }
+    let gems = gemfile.and_then(|path| gemfile::parse(path).ok()).unwrap_or_default();
+    let lock = gemfile.and_then(|path| lockfile::parse(&path.with_file_name("Gemfile.lock")).ok());
+    let layout = layout.unwrap_or_else(Layout::detect);
+    let today = chrono::Local::now().date_naive();
+    if is_plain() {
+        print_plain(&full_records(&state, &gems, config, show_all, failures_verbose));
+        return Ok(());
+    }
+    for line in full_lines(&state, &gems, lock.as_ref(), config, show_all, layout, failures_verbose, today) {
+        match line {
+            Line::Out(line) => println!("{}", line),
+            Line::Err(line) => eprintln!("{}", line),
+        }
+    }
+    Ok(())
+}
+
+/// 出力先ごとの 1 行。統計テーブルは stdout、それ以外は stderr に出す。
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Out(String),
+    Err(String),
+}
+
+/// `render_full` の出力を組み立てる。`lock` は実行の表示にツールのバージョンを添えるのに使う。
+#[allow(clippy::too_many_arguments)]
+fn full_lines(
+    state: &FluxState,
+    gems: &[gemfile::GemEntry],
+    lock: Option<&Lockfile>,
+    config: &ArcConfig,
+    show_all: bool,
+    layout: Layout,
+    failures_verbose: bool,
+    today: NaiveDate,
+) -> Vec<Line> {
+    let ignore: &[String] = if show_all { &[] } else { &config.stats.ignore };
+    let stats = state.command_stats(ignore);
+    let hidden = state.ignored_count(ignore);
     let failed = state.failed_executions();
+    let err = |line: String| Line::Err(line);
 
     // ── ヘッダー ──────────────────────────────
-    eprintln!("⚡ Flux State");
-    eprintln!();
+    let mut lines = vec![err("⚡ Flux State".to_string()), err(String::new())];
+    lines.extend(header_lines(state, config, lock).into_iter().map(err));
 
-    if let Some(ref path) = state.project_path {
-        eprintln!("  Project:     {}", path);
+    // ── 依存関係 (Gemfile) ──────────────────
+    if !gems.is_empty() {
+        lines.push(err(String::new()));
+        lines.push(err(format!("  Dependencies ({}):", fmt_count(gems.len() as u64))));
+        for gem in gems {
+            match &gem.version {
+                Some(v) => lines.push(err(format!("    📦 {} ({})", gem.name, v))),
+                None    => lines.push(err(format!("    📦 {}", gem.name))),
+            }
+        }
     }
-    if let Some(ref ts) = state.initialized_at {
-        eprintln!("  Initialized: {}", fmt_timestamp(ts));
+
+    // ── コマンド統計テーブル ──────────────────
+    if !stats.is_empty() {
+        lines.push(err(String::new()));
+    }
+    lines.extend(stats_table_lines(&stats, hidden, layout, StatsGroup::Command));
+
+    // ── 失敗一覧 ─────────────────────────────
+    if !failed.is_empty() {
+        lines.push(err(String::new()));
+        lines.push(err(format!("⚠️  Failed Operations ({}):", fmt_count(failed.len() as u64))));
+        if failures_verbose || failed.len() == 1 {
+            for exec in &failed {
+                let exit = exec.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "?".to_string());
+                let dur  = exec.duration.map(fmt_duration).unwrap_or_else(|| "incomplete".to_string());
+                lines.push(err(format!("   ❌ {} (exit: {}, {})", exec_label::label(exec, lock), exit, dur)));
+            }
+        } else {
+            lines.extend(failure_group_lines(&state.failed_summary(), today).into_iter().map(err));
+            lines.push(err("   (use --failures-verbose to list every failure)".to_string()));
+        }
     }
-    eprintln!("  Signals:     {}", state.signal_count);
-    eprintln!("  Executions:  {}", state.executions.len());
+    lines
+}
 
+/// `full_lines` と同じ内容の `--plain` のレコード。
+fn full_records(
+    state: &FluxState,
+    gems: &[gemfile::GemEntry],
+    config: &ArcConfig,
+    show_all: bool,
+    failures_verbose: bool,
+) -> Vec<Record> {
+    let ignore: &[String] = if show_all { &[] } else { &config.stats.ignore };
+    let failed = state.failed_executions();
+
+    let mut records = vec![vec![
+        ("project", plain_opt(config.project.name.clone())),
+        ("description", plain_opt(config.project.description.clone())),
+        ("path", plain_opt(state.project_path.clone())),
+        ("initialized", plain_opt(state.initialized_at.clone())),
+        ("signals", state.signal_count.to_string()),
+        ("executions", state.executions.len().to_string()),
+        ("invalid_timestamps", state.anomalies.len().to_string()),
+    ]];
     if let Some(last) = state.last_execution() {
-        let icon = if last.success { "✅" } else { "❌" };
-        let dur = last.duration_ms.map(fmt_duration).unwrap_or_else(|| "⏳ running".to_string());
-        eprintln!("  Last:        {} {} ({})", icon, fmt_cmd(&last.command, &last.args), dur);
+        let status = match (last.success, last.duration) {
+            (_, None) => "running",
+            (true, _) => "ok",
+            (false, _) => "failed",
+        };
+        records.push(vec![
+            ("last", fmt_cmd(&last.command, &last.args)),
+            ("status", status.to_string()),
+            ("duration", plain_duration(last.duration)),
+        ]);
     }
+    for gem in gems {
+        records.push(vec![("gem", gem.name.clone()), ("version", plain_opt(gem.version.clone()))]);
+    }
+    records.extend(stats_records(&state.command_stats(ignore), StatsGroup::Command));
+    let hidden = state.ignored_count(ignore);
+    if hidden > 0 {
+        records.push(vec![("hidden", hidden.to_string())]);
+    }
+    if failures_verbose || failed.len() == 1 {
+        for exec in &failed {
+            records.push(vec![
+                ("failed", fmt_cmd(&exec.command, &exec.args)),
+                ("exit", plain_opt(exec.exit_code)),
+                ("duration", exec.duration.map_or("incomplete".to_string(), |d| plain_duration(Some(d)))),
+            ]);
+        }
+    } else {
+        for g in state.failed_summary() {
+            records.push(vec![
+                ("failed", g.command.clone()),
+                ("count", g.count.to_string()),
+                ("kind", g.kind.clone()),
+                ("first", plain_opt(g.first_at.map(|t| t.to_rfc3339()))),
+                ("last", plain_opt(g.last_at.map(|t| t.to_rfc3339()))),
+                ("last_exit", plain_opt(g.last_exit_code)),
+                ("last_signal", plain_opt(g.last_signal)),
+            ]);
+        }
+    }
+    records
+}
 
-    // ── 依存関係 (Gemfile) ──────────────────
-    // cwd を基準にした絶対パスで読み取る（相対パス依存を排除）
-    let gemfile_path = cwd.join("Gemfile");
-    if let Ok(gems) = gemfile::parse(&gemfile_path)
-        && !gems.is_empty() {
-            eprintln!();
-            eprintln!("  Dependencies ({}):", gems.len());
-            for gem in &gems {
-                match &gem.version {
-                    Some(v) => eprintln!("    📦 {} ({})", gem.name, v),
-                    None    => eprintln!("    📦 {}", gem.name),
-                }
+/// 原因とコマンドごとにまとめた失敗 (`arc state`)。`today` は「since Tue」のような曜日表示の基準。
+fn failure_group_lines(groups: &[FailureGroup], today: NaiveDate) -> Vec<String> {
+    let since = |ts: Option<DateTime<Utc>>| match ts.map(|ts| ts.with_timezone(&Local)) {
+        Some(ts) if (today - ts.date_naive()).num_days() < 7 => ts.format("%a").to_string(),
+        Some(ts) => ts.format("%Y-%m-%d").to_string(),
+        None => "an unknown time".to_string(),
+    };
+    groups
+        .iter()
+        .map(|g| {
+            let last = match (g.last_signal, g.last_exit_code) {
+                (Some(signal), _) => format!("killed by signal {}", signal),
+                (None, Some(code)) => format!("exit {}", code),
+                (None, None) => "no exit recorded".to_string(),
+            };
+            if g.count == 1 {
+                return format!("   ❌ {} failed {} ({}, {})", g.last_command_line, since(g.last_at), last, g.kind);
             }
+            let pattern = if g.command.contains(char::is_whitespace) { format!("{:?}", g.command) } else { g.command.clone() };
+            format!(
+                "   ❌ {} failed {}× since {} ({}), last {}, see `arc stats --follow {}`",
+                g.command, fmt_count(g.count as u64), since(g.first_at), g.kind, last, pattern
+            )
+        })
+        .collect()
+}
+
+fn print_stats_table(stats: &[CommandStats], hidden: usize, layout: Option<Layout>, group: StatsGroup) {
+    let layout = if stats.is_empty() { Layout::Table } else { layout.unwrap_or_else(Layout::detect) };
+    for line in stats_table_lines(stats, hidden, layout, group) {
+        match line {
+            Line::Out(line) => println!("{}", line),
+            Line::Err(line) => eprintln!("{}", line),
         }
+    }
+}
 
-    // ── コマンド統計テーブル ──────────────────
+/// 統計テーブル (stdout) と、`stats.ignore` で除外した件数のフッター (stderr)。
+fn stats_table_lines(stats: &[CommandStats], hidden: usize, layout: Layout, group: StatsGroup) -> Vec<Line> {
+    let mut lines = Vec::new();
     if !stats.is_empty() {
+        lines.extend(stats_lines(&stats_rows(stats), layout, group).into_iter().map(Line::Out));
+    }
+    if hidden > 0 {
+        lines.push(Line::Err(format!("  {} (use --all to include them)", fmt_hidden_footer(hidden))));
+    }
+    lines
+}
+
+/// 統計テーブルだけを表示する (`arc stats`)。`group` ごとに集計し、`user` を指定した場合は
+/// そのユーザーの実行だけを対象にする。`command` を指定した場合はそのコマンドに絞り、日別の推移を加える。
+pub fn render_stats(
+    signals: &[signals::Signal],
+    config: &ArcConfig,
+    show_all: bool,
+    layout: Option<Layout>,
+    group: StatsGroup,
+    user: Option<&str>,
+    command: Option<&str>,
+) {
+    let mut state = FluxState::from_signals(signals);
+    if let Some(user) = user {
+        state.retain_user(user);
+    }
+    if let Some(command) = command {
+        state.executions.retain(|e| e.display_name() == command);
+    }
+    let ignore: &[String] = if show_all { &[] } else { &config.stats.ignore };
+    let stats = match group {
+        StatsGroup::Command => state.command_stats(ignore),
+        StatsGroup::User => state.user_stats(ignore),
+        StatsGroup::Context => state.context_stats(ignore),
+    };
+    if is_plain() {
+        let mut records = stats_records(&stats, group);
+        let hidden = state.ignored_count(ignore);
+        if hidden > 0 {
+            records.push(vec![("hidden", hidden.to_string())]);
+        }
+        if command.is_some() {
+            records.extend(trend_records(&state.daily_stats(ignore)));
+        }
+        print_plain(&records);
+        return;
+    }
+    if stats.is_empty() {
+        eprintln!("📊 No executions recorded yet.");
+    }
+    print_stats_table(&stats, state.ignored_count(ignore), layout, group);
+
+    if command.is_some() && !stats.is_empty() {
         eprintln!();
-        let sep_top = "┌──────────────────────────┬───────┬──────────┬──────────┬──────────────┐";
-        let sep_mid = "├──────────────────────────┼───────┼──────────┼──────────┼──────────────┤";
-        let sep_bot = "└──────────────────────────┴───────┴──────────┴──────────┴──────────────┘";
-
-        println!("{sep_top}");
-        println!("│ {:<24} │ {:<5} │ {:<8} │ {:<8} │ {:<12} │", "Command", "Runs", "Success", "Failed", "Avg Time");
-        println!("{sep_mid}");
-
-        for stat in &stats {
-            let avg = stat.avg_duration_ms.map(fmt_duration).unwrap_or_else(|| "—".to_string());
-            let ok  = format!("✅ {}", stat.successes);
-            let ng  = if stat.failures > 0 { format!("❌ {}", stat.failures) } else { "—".to_string() };
-            println!(
-                "│ {:<24} │ {:<5} │ {:<8} │ {:<8} │ {:<12} │",
-                signals::truncate_display(&stat.command, 24),
-                stat.total_runs, ok, ng, avg
-            );
+        for line in trend_lines(&state.daily_stats(ignore)) {
+            println!("{}", line);
         }
+    }
+}
+
+/// 1 つのコマンドの日別の推移 (`arc stats --command`)。
+fn trend_lines(days: &[(String, CommandStats)]) -> Vec<String> {
+    days.iter()
+        .map(|(date, stat)| {
+            let ng = if stat.failures > 0 { format!(" ❌ {}", fmt_count(stat.failures as u64)) } else { String::new() };
+            let avg = stat.avg_duration.map(fmt_duration).unwrap_or_else(|| "—".to_string());
+            format!(
+                "  {}  {:>5} runs  ✅ {}{}  avg {}",
+                date, fmt_count(stat.total_runs as u64), fmt_count(stat.successes as u64), ng, avg
+            )
+        })
+        .collect()
+}
 
-        println!("{sep_bot}");
+fn trend_records(days: &[(String, CommandStats)]) -> Vec<Record> {
+    days.iter()
+        .map(|(date, stat)| {
+            vec![
+                ("date", date.clone()),
+                ("runs", stat.total_runs.to_string()),
+                ("success", stat.successes.to_string()),
+                ("failed", stat.failures.to_string()),
+                ("avg", plain_duration(stat.avg_duration)),
+            ]
+        })
+        .collect()
+}
+
+/// 1 つのコマンドの実行履歴 (`arc stats --follow`)。
+pub fn render_follow(pattern: &str, runs: &[&Execution], summary: &FollowSummary, lock: Option<&Lockfile>) {
+    if is_plain() {
+        return print_plain(&follow_records(pattern, runs, summary));
+    }
+    for line in follow_lines(pattern, runs, summary, lock) {
+        println!("{}", line);
     }
+}
 
-    // ── 失敗一覧 ─────────────────────────────
-    if !failed.is_empty() {
-        eprintln!();
-        eprintln!("⚠️  Failed Operations ({}):", failed.len());
-        for exec in &failed {
-            let exit = exec.exit_code.map(|c| c.to_string()).unwrap_or_else(|| "?".to_string());
-            let dur  = exec.duration_ms.map(fmt_duration).unwrap_or_else(|| "incomplete".to_string());
-            eprintln!("   ❌ {} (exit: {}, {})", fmt_cmd(&exec.command, &exec.args), exit, dur);
+fn follow_lines(pattern: &str, runs: &[&Execution], summary: &FollowSummary, lock: Option<&Lockfile>) -> Vec<String> {
+    if runs.is_empty() {
+        return vec![format!("🔎 No executions match {:?}", pattern)];
+    }
+    let show_user = runs.iter().map(|e| e.user.as_str()).collect::<std::collections::BTreeSet<_>>().len() > 1;
+    let mut lines = vec![
+        format!("🔎 {} run(s) matching {:?}", fmt_count(runs.len() as u64), pattern),
+        format!("  {:<16}  {:>10}  {:>6}  {}Command", "Started", "Duration", "Exit", if show_user { "User          " } else { "" }),
+    ];
+    lines.extend(runs.iter().map(|e| follow_run_line(e, show_user, lock)));
+    let at = |i: Option<usize>| match i {
+        Some(i) => runs[i].started_at.map(fmt_datetime).unwrap_or_else(|| "—".to_string()),
+        None => "never".to_string(),
+    };
+    lines.push(String::new());
+    lines.push(format!("  First failure: {}", at(summary.first_failure)));
+    lines.push(format!("  Last success:  {}", at(summary.last_success)));
+    if let Some((success, count)) = summary.streak {
+        let outcome = if success { "success" } else { "failure" };
+        lines.push(format!("  Streak:        {} {}{}", fmt_count(count as u64), outcome, if count == 1 { "" } else { "s" }));
+    }
+    lines
+}
+
+/// `arc stats --follow --watch` で新しく終わった実行を 1 行表示する。
+pub fn render_follow_run(e: &Execution, lock: Option<&Lockfile>) {
+    if is_plain() {
+        return print_plain(&[follow_run_record(e)]);
+    }
+    println!("{}", follow_run_line(e, false, lock));
+}
+
+fn follow_run_line(e: &Execution, show_user: bool, lock: Option<&Lockfile>) -> String {
+    let status = match (e.success, e.exit_code) {
+        (true, _) => "✅".to_string(),
+        (false, Some(code)) => format!("❌ {}", code),
+        (false, None) => "❌".to_string(),
+    };
+    let user = if show_user { format!("{:<14}", e.user) } else { String::new() };
+    format!(
+        "  {:<16}  {:>10}  {:>6}  {}{}",
+        e.started_at.map(fmt_datetime).unwrap_or_else(|| "—".to_string()),
+        e.duration.map(fmt_duration).unwrap_or_else(|| "—".to_string()),
+        status,
+        user,
+        match e.context.as_ref().and_then(|c| c.badge()) {
+            Some(badge) => format!("{} {}", exec_label::label(e, lock), badge),
+            None => exec_label::label(e, lock),
         }
+    )
+}
+
+fn follow_run_record(e: &Execution) -> Record {
+    vec![
+        ("started", plain_opt(e.started_at.map(|t| t.to_rfc3339()))),
+        ("duration", plain_duration(e.duration)),
+        ("status", if e.success { "ok" } else { "failed" }.to_string()),
+        ("exit", plain_opt(e.exit_code)),
+        ("user", e.user.clone()),
+        ("command", e.command_line()),
+        ("context", e.context.as_ref().map_or(crate::exec_context::UNKNOWN, |c| c.group()).to_string()),
+    ]
+}
+
+fn follow_records(pattern: &str, runs: &[&Execution], summary: &FollowSummary) -> Vec<Record> {
+    let started = |e: &Execution| plain_opt(e.started_at.map(|t| t.to_rfc3339()));
+    let mut records = vec![vec![("pattern", pattern.to_string()), ("runs", runs.len().to_string())]];
+    records.extend(runs.iter().map(|e| follow_run_record(e)));
+    if !runs.is_empty() {
+        let at = |i: Option<usize>| i.map_or("never".to_string(), |i| started(runs[i]));
+        let (outcome, count) = match summary.streak {
+            Some((success, count)) => (if success { "success" } else { "failure" }, count),
+            None => ("-", 0),
+        };
+        records.push(vec![
+            ("first_failure", at(summary.first_failure)),
+            ("last_success", at(summary.last_success)),
+            ("streak", count.to_string()),
+            ("streak_outcome", outcome.to_string()),
+        ]);
     }
+    records
+}
 
-    Ok(())
+/// 成功と失敗を行き来するコマンドを表示する (`arc stats --flaky`)。
+pub fn render_flaky(flaky: &[FlakyCommand], threshold: f64, min_runs: usize) {
+    if is_plain() {
+        return print_plain(&flaky_records(flaky, threshold, min_runs));
+    }
+    for line in flaky_lines(flaky, threshold, min_runs) {
+        println!("{}", line);
+    }
 }
 
-/// 直近の操作による差分を表示する。
-pub fn render_diff(signals: &[signals::Signal]) -> Result<()> {
-    if signals.is_empty() {
-        eprintln!("No signals found.");
-        return Ok(());
+fn flaky_lines(flaky: &[FlakyCommand], threshold: f64, min_runs: usize) -> Vec<String> {
+    if flaky.is_empty() {
+        return vec![format!("✅ No flaky commands (score ≥ {:.2} over ≥ {} runs)", threshold, min_runs)];
     }
+    let mut lines = vec![
+        format!("🎲 {} flaky command(s) (score ≥ {:.2} over ≥ {} runs)", flaky.len(), threshold, min_runs),
+        format!("  {:<24}  {:>5}  {:>9}  {:>5}  {}", "Command", "Score", "Pass rate", "Runs", "Last flip"),
+    ];
+    for row in flaky {
+        let f = &row.flakiness;
+        let runs = if f.attempts > f.runs { format!("{}+{}", f.runs, f.attempts - f.runs) } else { f.runs.to_string() };
+        lines.push(format!(
+            "  {:<24}  {:>5.2}  {:>8.0}%  {:>5}  {}",
+            row.command,
+            f.score,
+            f.pass_rate * 100.0,
+            runs,
+            f.last_flip.map(fmt_datetime).unwrap_or_else(|| "—".to_string())
+        ));
+    }
+    lines
+}
 
-    // 最新の「意味のある」シグナルを探す（exec/install の開始終了ではなくメタデータ系のみ）
-    let last = signals.iter()
-        .rfind(|s| matches!(s.r_type.as_str(), "add" | "remove" | "undo" | "bootstrap" | "init"));
+fn flaky_records(flaky: &[FlakyCommand], threshold: f64, min_runs: usize) -> Vec<Record> {
+    let mut records = vec![vec![
+        ("flaky", flaky.len().to_string()),
+        ("threshold", format!("{:.2}", threshold)),
+        ("min_runs", min_runs.to_string()),
+    ]];
+    for row in flaky {
+        let f = &row.flakiness;
+        records.push(vec![
+            ("command", row.command.clone()),
+            ("score", format!("{:.2}", f.score)),
+            ("pass_rate", format!("{:.0}%", f.pass_rate * 100.0)),
+            ("runs", f.runs.to_string()),
+            ("attempts", f.attempts.to_string()),
+            ("flips", f.flips.to_string()),
+            ("last_flip", plain_opt(f.last_flip.map(|t| t.to_rfc3339()))),
+        ]);
+    }
+    records
+}
 
-    let last = match last {
-        Some(s) => s,
-        None => {
-            eprintln!("No reversible operations found.");
-            return Ok(());
+/// 2 つの期間の統計を並べて表示する (`arc stats --compare`)。
+pub fn render_compare(before: &str, after: &str, comparison: &Comparison, threshold: f64) {
+    if is_plain() {
+        return print_plain(&compare_records(before, after, comparison, threshold));
+    }
+    for line in compare_lines(before, after, comparison, threshold) {
+        println!("{}", line);
+    }
+}
+
+fn compare_lines(before: &str, after: &str, comparison: &Comparison, threshold: f64) -> Vec<String> {
+    let mut lines = vec![format!("📊 Compare: {} → {} (regression above {}%)", before, after, threshold)];
+    if comparison.rows.is_empty() {
+        lines.push("  No command ran in both windows".to_string());
+    }
+
+    let change = |value: Option<f64>, unit: &str| match value {
+        Some(v) => format!(" ({:+.1}{})", v, unit),
+        None => String::new(),
+    };
+    let rate = |r: Option<f64>| r.map_or("—".to_string(), |r| format!("{:.1}%", r));
+    let duration = |d: Option<Duration>| d.map_or("—".to_string(), fmt_duration);
+    let mut table = vec![["Command".to_string(), "Runs".to_string(), "Fail rate".to_string(), "Mean".to_string(), "p95".to_string()]];
+    for row in &comparison.rows {
+        table.push([
+            row.command.clone(),
+            format!("{} → {}", fmt_count(row.before.runs as u64), fmt_count(row.after.runs as u64)),
+            format!("{} → {}{}", rate(row.before.failure_rate), rate(row.after.failure_rate), change(row.failure_rate_change(), "pt")),
+            format!("{} → {}{}", duration(row.before.mean), duration(row.after.mean), change(row.mean_change(), "%")),
+            format!("{} → {}{}", duration(row.before.p95), duration(row.after.p95), change(row.p95_change(), "%")),
+        ]);
+    }
+    if !comparison.rows.is_empty() {
+        let widths: Vec<usize> = (0..5).map(|i| table.iter().map(|r| r[i].chars().count()).max().unwrap_or(0)).collect();
+        for (i, cells) in table.iter().enumerate() {
+            let padded: Vec<String> = cells.iter().zip(&widths).map(|(c, w)| format!("{:<w$}", c, w = w)).collect();
+            let flag = if i > 0 && comparison.rows[i - 1].regression { "  ⚠️  regression" } else { "" };
+            lines.push(format!("  {}{}", padded.join("   ").trim_end(), flag));
         }
+    }
+
+    if !comparison.only_before.is_empty() || !comparison.only_after.is_empty() {
+        lines.push(String::new());
+    }
+    if !comparison.only_before.is_empty() {
+        lines.push(format!("  Only before: {}", comparison.only_before.join(", ")));
+    }
+    if !comparison.only_after.is_empty() {
+        lines.push(format!("  Only after:  {}", comparison.only_after.join(", ")));
+    }
+    lines
+}
+
+fn compare_records(before: &str, after: &str, comparison: &Comparison, threshold: f64) -> Vec<Record> {
+    let rate = |r: Option<f64>| plain_opt(r.map(|r| format!("{:.1}", r)));
+    let change = |c: Option<f64>| plain_opt(c.map(|c| format!("{:+.1}", c)));
+    let mut records = vec![vec![("before", before.to_string()), ("after", after.to_string()), ("threshold", threshold.to_string())]];
+    for row in &comparison.rows {
+        records.push(vec![
+            ("command", row.command.clone()),
+            ("runs_before", row.before.runs.to_string()),
+            ("runs_after", row.after.runs.to_string()),
+            ("fail_rate_before", rate(row.before.failure_rate)),
+            ("fail_rate_after", rate(row.after.failure_rate)),
+            ("fail_rate_change_pt", change(row.failure_rate_change())),
+            ("mean_before", plain_duration(row.before.mean)),
+            ("mean_after", plain_duration(row.after.mean)),
+            ("mean_change_pct", change(row.mean_change())),
+            ("p95_before", plain_duration(row.before.p95)),
+            ("p95_after", plain_duration(row.after.p95)),
+            ("p95_change_pct", change(row.p95_change())),
+            ("regression", row.regression.to_string()),
+        ]);
+    }
+    for command in &comparison.only_before {
+        records.push(vec![("only_before", command.clone())]);
+    }
+    for command in &comparison.only_after {
+        records.push(vec![("only_after", command.clone())]);
+    }
+    records
+}
+
+/// キャッシュの効果を表示する (`arc stats --cache`)。
+pub fn render_cache_stats(stats: &CacheStats) {
+    if is_plain() {
+        return print_plain(&[cache_stats_record(stats)]);
+    }
+    for line in cache_stats_lines(stats) {
+        println!("{}", line);
+    }
+}
+
+fn cache_stats_lines(stats: &CacheStats) -> Vec<String> {
+    let percent = |ratio: Option<f64>| ratio.map(|r| format!("{:.0}%", r * 100.0)).unwrap_or_else(|| "—".to_string());
+    vec![
+        "📦 Cache".to_string(),
+        format!(
+            "  Bootstrap hit rate:   {} ({}/{})",
+            percent(stats.hit_rate()), fmt_count(stats.bootstrap_hits as u64), fmt_count(stats.bootstraps as u64)
+        ),
+        format!(
+            "  Ruby linked:          {} (downloaded {})",
+            fmt_bytes(stats.bytes_linked), fmt_bytes(stats.bytes_downloaded)
+        ),
+        format!(
+            "  Gems restored / sync: {} avg ({} restored, {} fetched over {} syncs)",
+            percent(stats.avg_restored_ratio),
+            fmt_count(stats.gems_restored),
+            fmt_count(stats.gems_fetched),
+            fmt_count(stats.syncs as u64)
+        ),
+        format!(
+            "  Time saved:           {} (estimated)",
+            stats.est_saved_us.map(|us| format!("~{}", fmt_duration_us(us))).unwrap_or_else(|| "—".to_string())
+        ),
+    ]
+}
+
+fn cache_stats_record(stats: &CacheStats) -> Record {
+    let percent = |ratio: Option<f64>| plain_opt(ratio.map(|r| format!("{:.0}", r * 100.0)));
+    vec![
+        ("bootstrap_hit_rate_pct", percent(stats.hit_rate())),
+        ("bootstrap_hits", stats.bootstrap_hits.to_string()),
+        ("bootstraps", stats.bootstraps.to_string()),
+        ("ruby_linked_bytes", stats.bytes_linked.to_string()),
+        ("ruby_downloaded_bytes", stats.bytes_downloaded.to_string()),
+        ("gems_restored_avg_pct", percent(stats.avg_restored_ratio)),
+        ("gems_restored", stats.gems_restored.to_string()),
+        ("gems_fetched", stats.gems_fetched.to_string()),
+        ("syncs", stats.syncs.to_string()),
+        ("time_saved_estimate", plain_opt(stats.est_saved_us.map(|us| plain_duration(Some(Duration::from_micros(us)))))),
+    ]
+}
+
+// ─────────────────────────────────────────────
+// 環境のサイズの推移 (arc stats --disk)
+// ─────────────────────────────────────────────
+
+/// 表に出す計測の数 (新しいものから)
+const DISK_TABLE_ROWS: usize = 20;
+/// スパークラインに使う計測の数 (新しいものから)
+const SPARKLINE_WIDTH: usize = 60;
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// `.arc/env` のサイズの推移を表示する (`arc stats --disk`)。
+pub fn render_disk_stats(stats: &DiskStats) {
+    if is_plain() {
+        return print_plain(&disk_stats_records(stats));
+    }
+    for line in disk_stats_lines(stats) {
+        println!("{}", line);
+    }
+}
+
+fn disk_stats_lines(stats: &DiskStats) -> Vec<String> {
+    if stats.points.is_empty() {
+        return vec!["💾 No disk usage recorded yet (sizes are recorded after `arc sync` / `arc bootstrap`)".to_string()];
+    }
+    let size = |i: usize| {
+        let p = &stats.points[i];
+        format!("{}{}", if p.partial { "≥" } else { "" }, fmt_bytes(p.bytes))
+    };
+    let delta = |i: usize| match stats.delta(i) {
+        0 => "—".to_string(),
+        d if d > 0 => format!("+{}", fmt_bytes(d as u64)),
+        d => format!("-{}", fmt_bytes(d.unsigned_abs())),
     };
 
-    eprintln!("🔍 Last Project Change:");
-    eprintln!();
+    let bytes: Vec<u64> = stats.points.iter().map(|p| p.bytes).collect();
+    let mut lines = vec![
+        "💾 .arc/env size".to_string(),
+        format!("  {}", sparkline(&bytes[bytes.len().saturating_sub(SPARKLINE_WIDTH)..])),
+        String::new(),
+        format!("  {:<16}  {:>10}  {:>10}  Operation", "Date", "Size", "Change"),
+    ];
+    let start = stats.points.len().saturating_sub(DISK_TABLE_ROWS);
+    for i in start..stats.points.len() {
+        let mark = if stats.largest_jump == Some(i) { " ◀ largest jump" } else { "" };
+        lines.push(format!(
+            "  {:<16}  {:>10}  {:>10}  {}{}",
+            fmt_timestamp(&stats.points[i].timestamp), size(i), delta(i), stats.points[i].operation, mark
+        ));
+    }
+    if let Some(i) = stats.largest_jump {
+        lines.push(String::new());
+        lines.push(format!(
+            "  Largest jump: {} at {} ({})",
+            delta(i), fmt_timestamp(&stats.points[i].timestamp), stats.points[i].operation
+        ));
+    }
+    if stats.points.iter().any(|p| p.partial) {
+        lines.push("  ≥ = the walk hit its time budget; the real size is larger".to_string());
+    }
+    lines
+}
+
+fn disk_stats_records(stats: &DiskStats) -> Vec<Record> {
+    let mut records: Vec<Record> = stats
+        .points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            vec![
+                ("timestamp", p.timestamp.clone()),
+                ("bytes", p.bytes.to_string()),
+                ("change_bytes", stats.delta(i).to_string()),
+                ("partial", p.partial.to_string()),
+                ("operation", p.operation.clone()),
+            ]
+        })
+        .collect();
+    if let Some(i) = stats.largest_jump {
+        records.push(vec![("largest_jump_bytes", stats.delta(i).to_string()), ("at", stats.points[i].timestamp.clone())]);
+    }
+    records
+}
+
+/// 最小値から最大値までを 8 段階のブロック文字で表す。
+pub fn sparkline(values: &[u64]) -> String {
+    let (Some(min), Some(max)) = (values.iter().min(), values.iter().max()) else { return String::new() };
+    let range = (max - min).max(1);
+    values
+        .iter()
+        .map(|v| SPARK_LEVELS[((v - min) * (SPARK_LEVELS.len() as u64 - 1) / range) as usize])
+        .collect()
+}
+
+// ─────────────────────────────────────────────
+// Signal 種別の内訳 (arc state --summary)
+// ─────────────────────────────────────────────
+
+pub fn render_type_summary(signals: &[signals::Signal]) {
+    if is_plain() {
+        return print_plain(&type_summary_records(signals));
+    }
+    for line in type_summary_lines(signals) {
+        println!("{}", line);
+    }
+}
+
+/// 種別ごとに数えた件数 (組み込みの種別を定義順に、続けて独自種別を名前順に)。
+fn type_counts(signals: &[signals::Signal]) -> Vec<(&str, usize)> {
+    let mut counts: std::collections::BTreeMap<&str, usize> = Default::default();
+    for signal in signals {
+        *counts.entry(signal.r_type.as_str()).or_default() += 1;
+    }
+    let known: Vec<String> = signals::SignalType::KNOWN.iter().map(|t| t.to_string()).collect();
+    let mut names: Vec<&str> = known.iter().filter_map(|k| counts.get_key_value(k.as_str()).map(|(n, _)| *n)).collect();
+    names.extend(counts.keys().filter(|n| !known.iter().any(|k| k == *n)));
+    names.into_iter().map(|n| (n, counts[n])).collect()
+}
+
+fn type_summary_records(signals: &[signals::Signal]) -> Vec<Record> {
+    use signals::{SignalType, TypeKind};
+    type_counts(signals)
+        .into_iter()
+        .map(|(name, count)| {
+            let kind = match SignalType::kind(name) {
+                TypeKind::Known => "known",
+                TypeKind::Namespaced => "custom",
+                TypeKind::Legacy => "legacy",
+            };
+            vec![("type", name.to_string()), ("count", count.to_string()), ("kind", kind.to_string())]
+        })
+        .collect()
+}
 
-    match last.r_type.as_str() {
-        "add" => {
-            let gem = last.payload["gem"].as_str().unwrap_or("?");
-            eprintln!("  Gemfile:");
-            match last.payload["version"].as_str() {
-                Some(v) => eprintln!("  \x1b[32m+ gem '{}', '{}'\x1b[0m", gem, v),
-                None    => eprintln!("  \x1b[32m+ gem '{}'\x1b[0m", gem),
+/// 種別ごとの件数。組み込みの種別を定義順に、続けて独自種別を名前順に並べ、
+/// 名前空間のない古い独自種別には印を付ける。
+fn type_summary_lines(signals: &[signals::Signal]) -> Vec<String> {
+    use signals::{SignalType, TypeKind};
+    let counts = type_counts(signals);
+    let mut lines = vec![format!("🦄 {} signals, {} types", fmt_count(signals.len() as u64), counts.len())];
+    let mut legacy = 0;
+    for (name, count) in counts {
+        let mark = match SignalType::kind(name) {
+            TypeKind::Known => "",
+            TypeKind::Namespaced => "  (custom)",
+            TypeKind::Legacy => {
+                legacy += 1;
+                "  ⚠️ legacy: not x-<component>-<name>"
             }
+        };
+        lines.push(format!("  {:<24} {:>9}{}", name, fmt_count(count as u64), mark));
+    }
+    if legacy > 0 {
+        lines.push(String::new());
+        lines.push(format!(
+            "  {} legacy type(s) were recorded before namespacing; they still load, but new custom types must be x-<component>-<name>",
+            legacy
+        ));
+    }
+    lines
+}
+
+// ─────────────────────────────────────────────
+// 日別のアクティビティ (arc state --activity)
+// ─────────────────────────────────────────────
+
+/// `arc state --activity` で表示する日数 (12 週)
+pub const ACTIVITY_DAYS: usize = 84;
+
+/// 実行数の濃さの段階 (下限の実行数, ブロック文字, ASCII)
+const ACTIVITY_LEVELS: [(usize, char, char); 5] = [
+    (0, '·', '.'),
+    (1, '░', '1'),
+    (3, '▒', '2'),
+    (6, '▓', '3'),
+    (10, '█', '4'),
+];
+
+/// 日別のアクティビティを 1 日 1 文字の帯で表示する。
+pub fn render_activity(days: &[DayActivity], ascii: bool) {
+    if is_plain() {
+        let records: Vec<Record> = days
+            .iter()
+            .map(|d| vec![("date", d.date.to_string()), ("executions", d.executions.to_string()), ("failures", d.failures.to_string())])
+            .collect();
+        return print_plain(&records);
+    }
+    for line in activity_lines(days, ascii) {
+        println!("{}", line);
+    }
+}
+
+/// Gem のライセンス一覧。`group` が License ならライセンスごとに Gem を並べる。
+pub fn render_licenses(gems: &[crate::licenses::GemLicense], group: crate::licenses::LicenseGroup) {
+    if is_plain() {
+        let records: Vec<Record> = gems
+            .iter()
+            .map(|g| vec![("gem", g.name.clone()), ("version", g.version.clone()), ("licenses", g.licenses.join(","))])
+            .collect();
+        return print_plain(&records);
+    }
+    for line in license_lines(gems, group) {
+        println!("{}", line);
+    }
+}
+
+fn license_lines(gems: &[crate::licenses::GemLicense], group: crate::licenses::LicenseGroup) -> Vec<String> {
+    if gems.is_empty() {
+        return vec!["No installed gems.".to_string()];
+    }
+    match group {
+        crate::licenses::LicenseGroup::Gem => {
+            let width = gems.iter().map(|g| g.name.chars().count()).max().unwrap_or(0);
+            let version_width = gems.iter().map(|g| g.version.chars().count()).max().unwrap_or(0);
+            gems.iter()
+                .map(|g| format!("{:<width$}  {:<version_width$}  {}", g.name, g.version, g.licenses.join(", ")))
+                .collect()
+        }
+        crate::licenses::LicenseGroup::License => crate::licenses::by_license(gems)
+            .into_iter()
+            .map(|(license, gems)| format!("{} ({}): {}", license, gems.len(), gems.join(", ")))
+            .collect(),
+    }
+}
+
+/// bootstrap で入れた Ruby のバージョンを古い順に表示する。直前より古いものに印を付ける。
+pub fn render_ruby_history(history: &[RubyInstall]) {
+    if is_plain() {
+        let records: Vec<Record> = history
+            .iter()
+            .map(|r| {
+                vec![
+                    ("version", r.version.clone()),
+                    ("cache_hit", plain_opt(r.cache_hit.map(|hit| hit.to_string()))),
+                    ("timestamp", r.timestamp.clone()),
+                    ("signal", r.signal_id.clone()),
+                ]
+            })
+            .collect();
+        return print_plain(&records);
+    }
+    for line in ruby_history_lines(history) {
+        eprintln!("{}", line);
+    }
+}
+
+fn ruby_history_lines(history: &[RubyInstall]) -> Vec<String> {
+    if history.is_empty() {
+        return vec!["No Ruby has been bootstrapped yet.".to_string()];
+    }
+    let mut lines = vec![format!("💎 Ruby history ({} bootstrap(s)):", fmt_count(history.len() as u64))];
+    let mut previous: Option<&str> = None;
+    for install in history {
+        let source = match install.cache_hit {
+            Some(true) => "✨ cache hit",
+            Some(false) => "📦 downloaded",
+            None => "📝 recorded",
+        };
+        let mut line = format!("   {}  {:<12} {}", fmt_timestamp(&install.timestamp), install.version, source);
+        if let Some(prev) = previous.filter(|prev| crate::ruby_version::is_downgrade(prev, &install.version)) {
+            line.push_str(&format!("  ⬇️  downgrade from {}", prev));
         }
-        "remove" => {
-            let gem = last.payload["gem"].as_str().unwrap_or("?");
-            eprintln!("  Gemfile:");
-            eprintln!("  \x1b[31m- gem '{}'\x1b[0m", gem);
+        lines.push(line);
+        previous = Some(&install.version);
+    }
+    lines
+}
+
+fn activity_lines(days: &[DayActivity], ascii: bool) -> Vec<String> {
+    let (Some(first), Some(last)) = (days.first(), days.last()) else { return vec![] };
+    let cell = |day: &DayActivity| {
+        if day.failures > 0 {
+            return if ascii { 'x' } else { '✗' };
         }
-        "undo" => {
-            let target = last.payload["target_type"].as_str().unwrap_or("?");
-            let gem    = last.payload["gem"].as_str().unwrap_or("?");
-            eprintln!("  ⏪ Undo of '{}' ({})", target, gem);
+        let (_, block, digit) = ACTIVITY_LEVELS.iter().rev().find(|(min, _, _)| day.executions >= *min).unwrap();
+        if ascii { *digit } else { *block }
+    };
+    // 1 週間ごとに区切る
+    let strip = days
+        .chunks(7)
+        .map(|week| week.iter().map(cell).collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let legend = ACTIVITY_LEVELS
+        .iter()
+        .enumerate()
+        .map(|(i, (min, block, digit))| {
+            let range = match ACTIVITY_LEVELS.get(i + 1) {
+                _ if *min == 0 => "none".to_string(),
+                Some((next, _, _)) => format!("{}-{}", min, next - 1),
+                None => format!("{}+", min),
+            };
+            format!("{} {}", if ascii { *digit } else { *block }, range)
+        })
+        .collect::<Vec<_>>()
+        .join("  ");
+
+    let active = days.iter().filter(|d| d.executions > 0).count();
+    let executions: usize = days.iter().map(|d| d.executions).sum();
+    let red = days.iter().filter(|d| d.failures > 0).count();
+    vec![
+        format!("  Activity: {} .. {}", first.date, last.date),
+        format!("  {}", strip),
+        format!("  {}  {} failures", legend, if ascii { 'x' } else { '✗' }),
+        format!("  {} active day(s), {} execution(s), {} day(s) with failures", active, fmt_count(executions as u64), red),
+    ]
+}
+
+// ─────────────────────────────────────────────
+// 統計テーブルのレイアウト
+// ─────────────────────────────────────────────
+
+/// `arc stats --by` の集計単位。
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum StatsGroup {
+    Command,
+    /// Signal を記録したユーザー (記録のない古い Signal は `unknown`)
+    User,
+    /// 実行した場所: CI の提供元か `local` (記録のない古い Signal は `unknown`)
+    Context,
+}
+
+impl StatsGroup {
+    /// 統計テーブルの先頭列の見出し
+    fn label(self) -> &'static str {
+        match self {
+            StatsGroup::Command => "Command",
+            StatsGroup::User => "User",
+            StatsGroup::Context => "Context",
         }
-        "bootstrap" => {
-            let ruby = last.payload["ruby_version"].as_str().unwrap_or("?");
-            eprintln!("  Runtime:");
-            eprintln!("  \x1b[32m+ Ruby {}\x1b[0m", ruby);
+    }
+}
+
+/// コマンド統計テーブルのレイアウト。
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Layout {
+    /// 罫線なしの 2 列 (100 桁未満)
+    Compact,
+    /// 罫線付きのテーブル (100〜140 桁)
+    Table,
+    /// p95・最終実行・タグ列を加えたテーブル (140 桁超)
+    Wide,
+}
+
+impl Layout {
+    /// 端末の桁数からレイアウトを選ぶ。
+    pub fn for_width(columns: usize) -> Self {
+        match columns {
+            0..100 => Layout::Compact,
+            100..=140 => Layout::Table,
+            _ => Layout::Wide,
         }
-        _ => {
-            eprintln!("  Type: {}", last.r_type);
-            eprintln!("  Data: {}", last.payload);
+    }
+
+    /// stdout の端末幅からレイアウトを選ぶ。
+    /// パイプ等で TTY でない場合は、出力を安定させるため常に `Table` を使う。
+    pub fn detect() -> Self {
+        if !std::io::stdout().is_terminal() {
+            return Layout::Table;
         }
+        terminal_width().map(Self::for_width).unwrap_or(Layout::Table)
     }
+}
+
+/// stdout の端末幅 (桁数)。取得できなければ `$COLUMNS` を使う。
+fn terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ は winsize 構造体に書き込むだけで、失敗時は -1 を返す
+    let rc = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if rc == 0 && size.ws_col > 0 {
+        return Some(size.ws_col as usize);
+    }
+    std::env::var("COLUMNS").ok()?.parse().ok()
+}
+
+/// 1 コマンド分の統計を表示用の文字列に整形したもの。各レイアウトで共有する。
+struct StatsRow {
+    command: String,
+    runs: String,
+    ok: String,
+    ng: String,
+    avg: String,
+    p95: String,
+    last_run: String,
+    tags: String,
+}
 
-    eprintln!();
-    eprintln!("  Timestamp: {}", fmt_timestamp(&last.timestamp));
-    eprintln!("  Signal ID: {}", last.id);
+fn stats_rows(stats: &[CommandStats]) -> Vec<StatsRow> {
+    let dash = || "—".to_string();
+    stats
+        .iter()
+        .map(|stat| StatsRow {
+            command: stat.command.clone(),
+            runs: fmt_count(stat.total_runs as u64),
+            ok: format!("✅ {}", fmt_count(stat.successes as u64)),
+            ng: if stat.failures > 0 { format!("❌ {}", fmt_count(stat.failures as u64)) } else { dash() },
+            avg: stat.avg_duration.map(fmt_duration).unwrap_or_else(dash),
+            p95: stat.p95_duration.map(fmt_duration).unwrap_or_else(dash),
+            last_run: stat.last_run.map(fmt_datetime).unwrap_or_else(dash),
+            tags: stat.tags.join(","),
+        })
+        .collect()
+}
 
+/// 統計テーブルと同じ内容の `--plain` のレコード。
+fn stats_records(stats: &[CommandStats], group: StatsGroup) -> Vec<Record> {
+    let key = match group {
+        StatsGroup::Command => "command",
+        StatsGroup::User => "user",
+        StatsGroup::Context => "context",
+    };
+    stats
+        .iter()
+        .map(|stat| {
+            vec![
+                (key, stat.command.clone()),
+                ("runs", stat.total_runs.to_string()),
+                ("success", stat.successes.to_string()),
+                ("failed", stat.failures.to_string()),
+                ("avg", plain_duration(stat.avg_duration)),
+                ("p95", plain_duration(stat.p95_duration)),
+                ("last_run", plain_opt(stat.last_run.map(|t| t.to_rfc3339()))),
+                ("tags", if stat.tags.is_empty() { "-".to_string() } else { stat.tags.join(",") }),
+            ]
+        })
+        .collect()
+}
+
+/// 統計テーブルを `layout` に従って行ごとに組み立てる。
+fn stats_lines(rows: &[StatsRow], layout: Layout, group: StatsGroup) -> Vec<String> {
+    let mut lines = Vec::new();
+    match layout {
+        Layout::Compact => {
+            for row in rows {
+                let ng = if row.ng == "—" { String::new() } else { format!(" {}", row.ng) };
+                lines.push(format!(
+                    "  {:<32} {} runs {}{} avg {}",
+                    signals::truncate_display(&row.command, 32),
+                    row.runs, row.ok, ng, row.avg
+                ));
+            }
+        }
+        Layout::Table => {
+            lines.push("┌──────────────────────────┬─────────┬────────────┬────────────┬──────────────┐".to_string());
+            lines.push(format!("│ {:<24} │ {:>7} │ {:>10} │ {:>10} │ {:>12} │", group.label(), "Runs", "Success", "Failed", "Avg Time"));
+            lines.push("├──────────────────────────┼─────────┼────────────┼────────────┼──────────────┤".to_string());
+            for row in rows {
+                lines.push(format!(
+                    "│ {:<24} │ {:>7} │ {:>10} │ {:>10} │ {:>12} │",
+                    signals::truncate_display(&row.command, 24),
+                    row.runs, row.ok, row.ng, row.avg
+                ));
+            }
+            lines.push("└──────────────────────────┴─────────┴────────────┴────────────┴──────────────┘".to_string());
+        }
+        Layout::Wide => {
+            lines.push("┌──────────────────────────────────────────┬─────────┬────────────┬────────────┬──────────────┬──────────────┬──────────────────┬──────────────────────┐".to_string());
+            lines.push(format!(
+                "│ {:<40} │ {:>7} │ {:>10} │ {:>10} │ {:>12} │ {:>12} │ {:<16} │ {:<20} │",
+                group.label(), "Runs", "Success", "Failed", "Avg Time", "P95", "Last Run", "Tags"
+            ));
+            lines.push("├──────────────────────────────────────────┼─────────┼────────────┼────────────┼──────────────┼──────────────┼──────────────────┼──────────────────────┤".to_string());
+            for row in rows {
+                lines.push(format!(
+                    "│ {:<40} │ {:>7} │ {:>10} │ {:>10} │ {:>12} │ {:>12} │ {:<16} │ {:<20} │",
+                    signals::truncate_display(&row.command, 40),
+                    row.runs, row.ok, row.ng, row.avg, row.p95, row.last_run,
+                    signals::truncate_display(&row.tags, 20)
+                ));
+            }
+            lines.push("└──────────────────────────────────────────┴─────────┴────────────┴────────────┴──────────────┴──────────────┴──────────────────┴──────────────────────┘".to_string());
+        }
+    }
+    lines
+}
+
+/// `stats.ignore` によって集計から除外された実行数のフッター。
+fn fmt_hidden_footer(hidden: usize) -> String {
+    let noun = if hidden == 1 { "execution" } else { "executions" };
+    format!("{} {} hidden by stats.ignore", fmt_count(hidden as u64), noun)
+}
+
+/// `render_full` のヘッダー部分 (プロジェクト情報と直近の実行) を組み立てる。
+pub fn header_lines(state: &FluxState, config: &ArcConfig, lock: Option<&Lockfile>) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if let Some(ref name) = config.project.name {
+        lines.push(format!("  Name:        {}", name));
+    }
+    if let Some(ref description) = config.project.description {
+        lines.push(format!("               {}", description));
+    }
+    if let Some(ref path) = state.project_path {
+        lines.push(format!("  Project:     {}", path));
+    }
+    if let Some(ref ts) = state.initialized_at {
+        lines.push(format!("  Initialized: {}", fmt_timestamp(ts)));
+    }
+    lines.push(format!("  Signals:     {}", fmt_count(state.signal_count as u64)));
+    lines.push(format!("  Executions:  {}", fmt_count(state.executions.len() as u64)));
+    if !state.anomalies.is_empty() {
+        lines.push(format!("  ⚠️  {} signal(s) with invalid timestamps (treated as unknown)", state.anomalies.len()));
+    }
+
+    if let Some(last) = state.last_execution() {
+        let icon = if last.success { "✅" } else { "❌" };
+        let dur = last.duration.map(fmt_duration).unwrap_or_else(|| "⏳ running".to_string());
+        lines.push(format!("  Last:        {} {} ({})", icon, exec_label::label(last, lock), dur));
+    }
+
+    lines
+}
+
+/// `arc state --diff` で表示する直近の変更
+#[derive(Debug, Clone, PartialEq)]
+enum Change {
+    Added { gem: String, version: Option<String> },
+    Removed { gem: String },
+    Undo { target: String, gem: String },
+    Runtime { ruby: String },
+    Other { r_type: String, data: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LastChange {
+    change: Change,
+    timestamp: String,
+    id: String,
+}
+
+impl LastChange {
+    /// 最新の「意味のある」Signal (exec/install の開始終了ではなくメタデータ系のみ) から作る
+    fn from_signal(signal: &signals::Signal) -> Self {
+        let field = |key: &str| signal.payload[key].as_str().unwrap_or("?").to_string();
+        let change = match signal.r_type.as_str() {
+            "add" => Change::Added { gem: field("gem"), version: signal.payload["version"].as_str().map(str::to_string) },
+            "remove" => Change::Removed { gem: field("gem") },
+            "undo" => Change::Undo { target: field("target_type"), gem: field("gem") },
+            "bootstrap" => Change::Runtime { ruby: field("ruby_version") },
+            _ => Change::Other { r_type: signal.r_type.clone(), data: signal.payload.to_string() },
+        };
+        Self { change, timestamp: signal.timestamp.clone(), id: signal.id.clone() }
+    }
+}
+
+/// 直近の操作による差分を表示する。payload 内の blob 参照は `project` があれば内容に展開して表示する。
+pub fn render_diff(project: Option<&signals::FluxProject>, signals: &[signals::Signal]) -> Result<()> {
+    let last = signals
+        .iter()
+        .rfind(|s| matches!(s.r_type.as_str(), "add" | "remove" | "undo" | "bootstrap" | "init"));
+    let last = match last {
+        Some(s) => {
+            let payload = match project {
+                Some(project) => project.resolve_blob(&s.payload)?,
+                None => s.payload.clone(),
+            };
+            Some(LastChange::from_signal(&signals::Signal { payload, ..s.clone() }))
+        }
+        None => None,
+    };
+
+    if is_plain() {
+        print_plain(&diff_records(last.as_ref()));
+        return Ok(());
+    }
+    match last {
+        _ if signals.is_empty() => eprintln!("No signals found."),
+        None => eprintln!("No reversible operations found."),
+        Some(last) => {
+            for line in diff_lines(&last) {
+                eprintln!("{}", line);
+            }
+        }
+    }
     Ok(())
 }
 
+fn diff_lines(last: &LastChange) -> Vec<String> {
+    let mut lines = vec!["🔍 Last Project Change:".to_string(), String::new()];
+    match &last.change {
+        Change::Added { gem, version } => {
+            lines.push("  Gemfile:".to_string());
+            match version {
+                Some(v) => lines.push(format!("  \x1b[32m+ gem '{}', '{}'\x1b[0m", gem, v)),
+                None => lines.push(format!("  \x1b[32m+ gem '{}'\x1b[0m", gem)),
+            }
+        }
+        Change::Removed { gem } => {
+            lines.push("  Gemfile:".to_string());
+            lines.push(format!("  \x1b[31m- gem '{}'\x1b[0m", gem));
+        }
+        Change::Undo { target, gem } => lines.push(format!("  ⏪ Undo of '{}' ({})", target, gem)),
+        Change::Runtime { ruby } => {
+            lines.push("  Runtime:".to_string());
+            lines.push(format!("  \x1b[32m+ Ruby {}\x1b[0m", ruby));
+        }
+        Change::Other { r_type, data } => {
+            lines.push(format!("  Type: {}", r_type));
+            lines.push(format!("  Data: {}", data));
+        }
+    }
+    lines.push(String::new());
+    lines.push(format!("  Timestamp: {}", fmt_timestamp(&last.timestamp)));
+    lines.push(format!("  Signal ID: {}", last.id));
+    lines
+}
+
+/// `diff_lines` と同じ内容の `--plain` のレコード。変更がなければ `change=none`。
+fn diff_records(last: Option<&LastChange>) -> Vec<Record> {
+    let Some(last) = last else { return vec![vec![("change", "none".to_string())]] };
+    let mut record: Record = match &last.change {
+        Change::Added { gem, version } => vec![
+            ("change", "add".to_string()),
+            ("file", "Gemfile".to_string()),
+            ("gem", gem.clone()),
+            ("version", plain_opt(version.clone())),
+        ],
+        Change::Removed { gem } => vec![("change", "remove".to_string()), ("file", "Gemfile".to_string()), ("gem", gem.clone())],
+        Change::Undo { target, gem } => vec![("change", "undo".to_string()), ("target", target.clone()), ("gem", gem.clone())],
+        Change::Runtime { ruby } => vec![("change", "bootstrap".to_string()), ("ruby", ruby.clone())],
+        Change::Other { r_type, data } => vec![("change", r_type.clone()), ("data", data.clone())],
+    };
+    record.push(("timestamp", last.timestamp.clone()));
+    record.push(("signal", last.id.clone()));
+    vec![record]
+}
+
+// ─────────────────────────────────────────────
+// Gemfile の検査 (arc gemfile check)
+// ─────────────────────────────────────────────
+
+/// Gemfile の問題を、問題のある行と一緒に stderr に表示する。
+pub fn render_gemfile_issues(label: &str, issues: &[gemfile::ValidationIssue]) {
+    if is_plain() {
+        let records: Vec<Record> = issues.iter().map(|issue| gemfile_issue_record(label, issue)).collect();
+        return print_plain(&records);
+    }
+    for line in gemfile_issue_lines(label, issues) {
+        eprintln!("{}", line);
+    }
+}
+
+fn gemfile_issue_lines(label: &str, issues: &[gemfile::ValidationIssue]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for issue in issues {
+        let icon = match issue.severity {
+            gemfile::Severity::Error => "❌",
+            gemfile::Severity::Warning => "⚠️ ",
+        };
+        lines.push(format!("{} {}:{}: {}", icon, label, issue.line, issue.message));
+        lines.push(format!("   {:>4} | {}", issue.line, issue.text));
+    }
+    lines
+}
+
+fn gemfile_issue_record(label: &str, issue: &gemfile::ValidationIssue) -> Record {
+    let severity = match issue.severity {
+        gemfile::Severity::Error => "error",
+        gemfile::Severity::Warning => "warning",
+    };
+    vec![
+        ("severity", severity.to_string()),
+        ("file", label.to_string()),
+        ("line", issue.line.to_string()),
+        ("message", issue.message.clone()),
+        ("text", issue.text.clone()),
+    ]
+}
+
+// ─────────────────────────────────────────────
+// プレーン出力 (--plain)
+// ─────────────────────────────────────────────
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// すべての表示を `key=value` の行にする (`--plain` / `TERM=dumb` / `[display] plain = true`)。
+pub fn enable_plain() {
+    PLAIN.store(true, Ordering::Relaxed);
+}
+
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// `--plain` の 1 行分の `key=value`。キーはこの順に出す
+type Record = Vec<(&'static str, String)>;
+
+/// レコードを 1 行ずつ stdout に出す (表示の種類によらず stdout のみ)。
+fn print_plain(records: &[Record]) {
+    for record in records {
+        println!("{}", plain_line(record));
+    }
+}
+
+/// `key=value` を空白区切りで並べる。
+fn plain_line(record: &[(&str, String)]) -> String {
+    record.iter().map(|(key, value)| format!("{}={}", key, plain_value(value))).collect::<Vec<_>>().join(" ")
+}
+
+/// 空白・`"`・`=`・`\`・ASCII 以外の文字を含む値 (と空文字列) は `"` で囲む。
+/// 囲んだ値の中では `"` と `\` をエスケープし、制御文字と ASCII 以外の文字は `\u{..}` にする。
+fn plain_value(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_graphic() && !matches!(c, '"' | '=' | '\\')) {
+        return value.to_string();
+    }
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' ' => out.push(c),
+            c if c.is_ascii_graphic() => out.push(c),
+            c => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 値がなければ `-`
+fn plain_opt(value: Option<impl ToString>) -> String {
+    value.map_or("-".to_string(), |v| v.to_string())
+}
+
+/// `fmt_duration` と同じ形だが、`µs` を `us` にする。値がなければ `-`
+fn plain_duration(duration: Option<Duration>) -> String {
+    duration.map_or("-".to_string(), |d| fmt_duration(d).replace('µ', "u"))
+}
+
 // ─────────────────────────────────────────────
 // フォーマットヘルパー
 // ─────────────────────────────────────────────
 
-pub fn fmt_duration(ms: u64) -> String {
+/// バイト数を 2 進接頭辞で整形する。例: `512 B`, `1.5 KiB`, `42.0 MiB`
+pub fn fmt_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// 件数を 3 桁区切りで整形する。区切り文字は `--lang` / `[display] lang` で選ぶ (既定は `,`)。
+pub fn fmt_count(n: u64) -> String {
+    group_digits(n, grouping())
+}
+
+/// `n` を 3 桁ごとに `separator` で区切る。例: `group_digits(182403, ',')` → `182,403`
+pub fn group_digits(n: u64, separator: char) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(separator);
+        }
+        out.push(digit);
+    }
+    out
+}
+
+/// 言語タグ (`de`, `fr-FR`, `de_CH.UTF-8` など) に対応する 3 桁区切りの文字。知らない言語なら `None`。
+pub fn grouping_for(lang: &str) -> Option<char> {
+    let tag = lang.split('.').next().unwrap_or(lang).to_ascii_lowercase().replace('_', "-");
+    if tag == "de-ch" {
+        return Some('\'');
+    }
+    match tag.split('-').next().unwrap_or(&tag) {
+        "c" | "posix" | "en" | "ja" | "zh" | "ko" | "he" | "th" => Some(','),
+        "de" | "nl" | "it" | "es" | "pt" | "da" | "id" | "tr" | "el" => Some('.'),
+        // 本来は狭い空白 (U+202F) だが、端末で桁がずれないよう通常の空白にする
+        "fr" | "ru" | "uk" | "pl" | "cs" | "sv" | "fi" | "nb" | "no" => Some(' '),
+        _ => None,
+    }
+}
+
+static GROUPING: OnceLock<char> = OnceLock::new();
+
+/// 3 桁区切りの言語を決める。最初に呼ばれたものを使う (`--lang` は config.toml より先に呼ばれる)。
+pub fn set_lang(lang: &str) -> Result<()> {
+    let Some(separator) = grouping_for(lang) else {
+        anyhow::bail!("未対応の言語です: {:?} (例: en, de, fr, de-CH)", lang);
+    };
+    let _ = GROUPING.set(separator);
+    Ok(())
+}
+
+fn grouping() -> char {
+    GROUPING.get().copied().unwrap_or(',')
+}
+
+/// 実行時間を整形する。詳細は `fmt_duration_us` を参照。
+pub fn fmt_duration(duration: Duration) -> String {
+    fmt_duration_us(duration.as_micros() as u64)
+}
+
+/// マイクロ秒単位の実行時間を、最大 2 単位で読みやすく整形する。
+/// 例: `850µs`, `350ms`, `4.2s`, `3m 12s`, `2h 14m`
+///
+/// 各単位は四捨五入した値で判定するため、`59.96s` は `60.0s` ではなく `1m 0s` になる。
+pub fn fmt_duration_us(us: u64) -> String {
+    if us < 1_000 {
+        return format!("{}µs", us);
+    }
+
+    let ms = (us + 500) / 1_000;
     if ms < 1_000 {
-        format!("{}ms", ms)
-    } else if ms < 60_000 {
-        format!("{:.1}s", ms as f64 / 1_000.0)
+        return format!("{}ms", ms);
+    }
+
+    let tenths = (us + 50_000) / 100_000;
+    if tenths < 600 {
+        return format!("{}.{}s", tenths / 10, tenths % 10);
+    }
+
+    let secs = (us + 500_000) / 1_000_000;
+    if secs < 3_600 {
+        format!("{}m {}s", secs / 60, secs % 60)
     } else {
-        format!("{}m{}s", ms / 60_000, (ms % 60_000) / 1_000)
+        format!("{}h {}m", secs / 3_600, (secs % 3_600) / 60)
     }
 }
 
-fn fmt_timestamp(ts: &str) -> String {
+/// RFC 3339 の時刻を `YYYY-MM-DD HH:MM` に切り詰める。
+pub fn fmt_timestamp(ts: &str) -> String {
     if ts.len() >= 16 { ts[..16].replace('T', " ") } else { ts.to_string() }
 }
 
+/// 解釈済みの時刻を `fmt_timestamp` と同じ形 (ローカル時刻、分まで) に整形する。
+pub fn fmt_datetime(ts: DateTime<Utc>) -> String {
+    ts.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
+
 /// コマンドと引数を人間が読みやすい文字列に整形する。
 pub fn fmt_cmd(cmd: &str, args: &[String]) -> String {
     if args.is_empty() { cmd.to_string() } else { format!("{} {}", cmd, args.join(" ")) }
 }
+
+// ─────────────────────────────────────────────
+// 詳細出力 (--verbose)
+// ─────────────────────────────────────────────
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(enabled: bool) {
+    VERBOSE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// `--verbose` のときだけ stderr に出力する。
+pub fn verbose(message: &str) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        eprintln!("{}", message);
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_shows_project_name() {
+        let state = FluxState::from_signals(&[]);
+        let mut config = ArcConfig::default();
+        config.project.name = Some("my_app".to_string());
+        config.project.description = Some("A tiny app".to_string());
+
+        let lines = header_lines(&state, &config, None);
+        assert_eq!(lines[0], "  Name:        my_app");
+        assert!(lines[1].ends_with("A tiny app"));
+    }
+
+    #[test]
+    fn test_fmt_duration_boundaries() {
+        assert_eq!(fmt_duration(Duration::from_millis(0)), "0µs");
+        assert_eq!(fmt_duration(Duration::from_millis(350)), "350ms");
+        assert_eq!(fmt_duration(Duration::from_millis(999)), "999ms");
+        assert_eq!(fmt_duration(Duration::from_millis(1_000)), "1.0s");
+        assert_eq!(fmt_duration(Duration::from_millis(4_200)), "4.2s");
+        assert_eq!(fmt_duration(Duration::from_millis(59_900)), "59.9s");
+        assert_eq!(fmt_duration(Duration::from_millis(59_960)), "1m 0s");
+        assert_eq!(fmt_duration(Duration::from_millis(60_000)), "1m 0s");
+        assert_eq!(fmt_duration(Duration::from_millis(192_000)), "3m 12s");
+        assert_eq!(fmt_duration(Duration::from_millis(3_599_000)), "59m 59s");
+        assert_eq!(fmt_duration(Duration::from_millis(3_600_000)), "1h 0m");
+        assert_eq!(fmt_duration(Duration::from_millis(8_040_000)), "2h 14m");
+        assert_eq!(fmt_duration(Duration::from_millis(187 * 60_000 + 23_000)), "3h 7m");
+    }
+
+    #[test]
+    fn test_fmt_bytes() {
+        assert_eq!(fmt_bytes(512), "512 B");
+        assert_eq!(fmt_bytes(1023), "1023 B");
+        assert_eq!(fmt_bytes(1536), "1.5 KiB");
+        assert_eq!(fmt_bytes(42 * 1024 * 1024), "42.0 MiB");
+        assert_eq!(fmt_bytes(3 * 1024 * 1024 * 1024 + 512 * 1024 * 1024), "3.5 GiB");
+        assert_eq!(fmt_bytes(2048 * 1024 * 1024 * 1024), "2.0 TiB");
+        assert_eq!(fmt_bytes(u64::MAX), "16777216.0 TiB");
+    }
+
+    #[test]
+    fn test_group_digits_across_magnitudes() {
+        let cases = [
+            (0, "0", "0"),
+            (7, "7", "7"),
+            (999, "999", "999"),
+            (1_000, "1,000", "1.000"),
+            (182_403, "182,403", "182.403"),
+            (1_234_567, "1,234,567", "1.234.567"),
+            (u64::MAX, "18,446,744,073,709,551,615", "18.446.744.073.709.551.615"),
+        ];
+        for (n, comma, dot) in cases {
+            assert_eq!(group_digits(n, ','), comma);
+            assert_eq!(group_digits(n, '.'), dot);
+        }
+    }
+
+    #[test]
+    fn test_grouping_for_lang() {
+        assert_eq!(grouping_for("en"), Some(','));
+        assert_eq!(grouping_for("ja_JP.UTF-8"), Some(','));
+        assert_eq!(grouping_for("de"), Some('.'));
+        assert_eq!(grouping_for("pt-BR"), Some('.'));
+        assert_eq!(grouping_for("fr_FR"), Some(' '));
+        assert_eq!(grouping_for("de-CH"), Some('\''));
+        assert_eq!(grouping_for("de_CH.UTF-8"), Some('\''));
+        assert_eq!(grouping_for("xx"), None);
+        assert!(set_lang("xx").unwrap_err().to_string().contains("未対応の言語"));
+    }
+
+    #[test]
+    fn test_disk_stats_lines() {
+        use crate::disk_stats::DiskPoint;
+
+        let point = |bytes, partial, op: &str| DiskPoint {
+            timestamp: "2024-03-01T10:00:00+09:00".to_string(),
+            bytes,
+            partial,
+            operation: op.to_string(),
+        };
+        let stats = DiskStats {
+            points: vec![point(1024, false, "sync"), point(3 * 1024 * 1024, false, "add nokogiri"), point(2048, true, "remove rake")],
+            largest_jump: Some(1),
+        };
+        let lines = disk_stats_lines(&stats);
+        assert_eq!(lines[1], "  ▁█▁");
+        assert!(lines[5].ends_with("+3.0 MiB  add nokogiri ◀ largest jump"), "{}", lines[5]);
+        assert!(lines[6].contains("≥2.0 KiB"), "{}", lines[6]);
+        assert_eq!(lines[8], "  Largest jump: +3.0 MiB at 2024-03-01 10:00 (add nokogiri)");
+        assert!(lines.last().unwrap().contains("time budget"));
+        assert!(disk_stats_lines(&DiskStats::default())[0].contains("No disk usage"));
+
+        assert_eq!(sparkline(&[5, 5, 5]), "▁▁▁");
+        assert_eq!(sparkline(&[0, 7, 14]), "▁▄█");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_type_summary_flags_legacy_types() {
+        let signal = |r_type: &str| signals::Signal {
+            id: uuid::Uuid::now_v7().to_string(),
+            r_type: r_type.to_string(),
+            payload: serde_json::json!({}),
+            timestamp: "2024-03-01T10:00:00+09:00".to_string(),
+            meta: None,
+            v: 2,
+        };
+        let log: Vec<_> = ["shel_enter", "add", "init", "x-hook-start", "add"].into_iter().map(signal).collect();
+        let lines = type_summary_lines(&log);
+        assert_eq!(lines[0], "🦄 5 signals, 4 types");
+        assert!(lines[1].starts_with("  init ") && lines[2].starts_with("  add ") && lines[2].contains(" 2"));
+        assert!(lines[3].contains("shel_enter") && lines[3].contains("legacy"));
+        assert!(lines[4].contains("x-hook-start") && lines[4].ends_with("(custom)"));
+        assert!(lines[6].starts_with("  1 legacy type(s)"));
+    }
+
+    #[test]
+    fn test_follow_lines() {
+        let run = |id: &str, minute: u32, code: i64| {
+            vec![
+                signals::Signal {
+                    id: id.to_string(),
+                    r_type: "exec_start".to_string(),
+                    payload: serde_json::json!({ "command": "rake", "args": ["db:migrate"] }),
+                    timestamp: format!("2024-05-01T10:{:02}:00+09:00", minute),
+                    meta: None,
+                    v: 2,
+                },
+                signals::Signal {
+                    id: format!("{}-end", id),
+                    r_type: "exec_end".to_string(),
+                    payload: serde_json::json!({ "ref_id": id, "exit_code": code, "success": code == 0, "duration_ms": 1500 }),
+                    timestamp: format!("2024-05-01T10:{:02}:30+09:00", minute),
+                    meta: None,
+                    v: 2,
+                },
+            ]
+        };
+        let signals: Vec<_> = [run("a", 0, 0), run("b", 5, 1), run("c", 9, 2)].concat();
+        let state = FluxState::from_signals(&signals);
+        let runs: Vec<&Execution> = state.executions.iter().collect();
+        let lines = follow_lines("migrate", &runs, &FollowSummary::from_runs(&runs), None);
+        assert_eq!(lines[0], "🔎 3 run(s) matching \"migrate\"");
+        assert!(lines[3].contains("❌ 1") && lines[3].ends_with("rake db:migrate"), "{}", lines[3]);
+        // 時刻はローカル時刻で表示する
+        assert_eq!(lines[6], format!("  First failure: {}", fmt_datetime(runs[1].started_at.unwrap())));
+        assert_eq!(lines[7], format!("  Last success:  {}", fmt_datetime(runs[0].started_at.unwrap())));
+        assert_eq!(lines[8], "  Streak:        2 failures");
+        assert!(follow_lines("x", &[], &FollowSummary::default(), None)[0].contains("No executions"));
+    }
+
+    #[test]
+    fn test_failure_group_lines() {
+        let at = |s: &str| Some(DateTime::parse_from_rfc3339(s).unwrap().to_utc());
+        let group = |command: &str, count, first: &str, last: &str| FailureGroup {
+            kind: "exit_code".to_string(),
+            command: command.to_string(),
+            count,
+            first_at: at(first),
+            last_at: at(last),
+            last_command_line: format!("{} --fail-fast", command),
+            last_exit_code: Some(1),
+            last_signal: None,
+        };
+        // 2026-03-10 は火曜日
+        let today = NaiveDate::from_ymd_opt(2026, 3, 12).unwrap();
+        let lines = failure_group_lines(
+            &[
+                group("rspec", 14, "2026-03-10T09:00:00Z", "2026-03-12T09:00:00Z"),
+                group("db migrate", 2, "2026-02-01T09:00:00Z", "2026-03-01T09:00:00Z"),
+                FailureGroup { last_signal: Some(9), ..group("rake", 1, "2026-03-11T09:00:00Z", "2026-03-11T09:00:00Z") },
+            ],
+            today,
+        );
+        assert_eq!(lines[0], "   ❌ rspec failed 14× since Tue (exit_code), last exit 1, see `arc stats --follow rspec`");
+        assert!(lines[1].contains("since 2026-02-01") && lines[1].ends_with("`arc stats --follow \"db migrate\"`"), "{}", lines[1]);
+        assert_eq!(lines[2], "   ❌ rake --fail-fast failed Wed (killed by signal 9, exit_code)");
+    }
+
+    fn plain_fixture() -> Vec<signals::Signal> {
+        let signal = |id: &str, r_type: &str, ts: &str, payload: serde_json::Value| signals::Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: format!("2024-05-01T10:{}+09:00", ts),
+            meta: None,
+            v: 2,
+        };
+        vec![
+            signal("s0", "init", "00:00", serde_json::json!({ "path": "/srv/café" })),
+            signal("s1", "exec_start", "01:00", serde_json::json!({ "command": "rspec", "args": [] })),
+            signal("s2", "exec_end", "01:03", serde_json::json!({ "ref_id": "s1", "exit_code": 0, "success": true, "duration_ms": 3100 })),
+            signal("s3", "exec_start", "02:00", serde_json::json!({ "command": "echo", "args": ["✅ done"] })),
+            signal("s4", "exec_end", "02:01", serde_json::json!({ "ref_id": "s3", "exit_code": 1, "success": false, "duration_ms": 0 })),
+            signal("s5", "add", "03:00", serde_json::json!({ "gem": "rack", "version": "~> 3.0" })),
+        ]
+    }
+
+    #[test]
+    fn test_plain_value_quoting() {
+        assert_eq!(plain_value("rspec"), "rspec");
+        assert_eq!(plain_value("bundle exec rspec"), "\"bundle exec rspec\"");
+        assert_eq!(plain_value(""), "\"\"");
+        assert_eq!(plain_value("a=b"), "\"a=b\"");
+        assert_eq!(plain_value("say \"hi\"\\"), "\"say \\\"hi\\\"\\\\\"");
+        assert_eq!(plain_value("café\n"), "\"caf\\u{e9}\\u{a}\"");
+        assert_eq!(plain_line(&[("command", "rspec".to_string()), ("runs", "14".to_string())]), "command=rspec runs=14");
+    }
+
+    #[test]
+    fn test_plain_full_records() {
+        let signals = plain_fixture();
+        let state = FluxState::from_signals(&signals);
+        let mut config = ArcConfig::default();
+        config.project.name = Some("shop".to_string());
+        let gems = vec![gemfile::GemEntry { name: "rack".to_string(), version: Some("~> 3.0".to_string()), group: None }];
+        let lines: Vec<String> = full_records(&state, &gems, &config, false, false).iter().map(|r| plain_line(r)).collect();
+        assert_eq!(
+            lines,
+            [
+                "project=shop description=- path=\"/srv/caf\\u{e9}\" initialized=2024-05-01T10:00:00+09:00 signals=6 executions=2 invalid_timestamps=0",
+                "last=\"echo \\u{2705} done\" status=failed duration=0us",
+                "gem=rack version=\"~> 3.0\"",
+                "command=echo runs=1 success=0 failed=1 avg=0us p95=0us last_run=2024-05-01T01:02:00+00:00 tags=exec",
+                "command=rspec runs=1 success=1 failed=0 avg=3.1s p95=3.1s last_run=2024-05-01T01:01:00+00:00 tags=exec",
+                "failed=\"echo \\u{2705} done\" exit=1 duration=0us",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plain_raw_and_diff_records() {
+        let signals = plain_fixture();
+        let refs: Vec<&signals::Signal> = signals.iter().take(2).collect();
+        let lines: Vec<String> = raw_records(&refs, Path::new("/p/.flux")).iter().map(|r| plain_line(r)).collect();
+        assert_eq!(
+            lines,
+            [
+                "signals=2 source=/p/.flux",
+                "signal=s0 type=init user=unknown timestamp=2024-05-01T10:00:00+09:00 payload=\"{\\\"path\\\":\\\"/srv/caf\\u{e9}\\\"}\"",
+                "signal=s1 type=exec_start user=unknown timestamp=2024-05-01T10:01:00+09:00 payload=\"{\\\"args\\\":[],\\\"command\\\":\\\"rspec\\\"}\"",
+            ]
+        );
+
+        let last = LastChange::from_signal(&signals[5]);
+        assert_eq!(
+            plain_line(&diff_records(Some(&last))[0]),
+            "change=add file=Gemfile gem=rack version=\"~> 3.0\" timestamp=2024-05-01T10:03:00+09:00 signal=s5"
+        );
+        assert!(diff_lines(&last).contains(&"  \x1b[32m+ gem 'rack', '~> 3.0'\x1b[0m".to_string()));
+        assert_eq!(plain_line(&diff_records(None)[0]), "change=none");
+    }
+
+    #[test]
+    fn test_plain_stats_views() {
+        let signals = plain_fixture();
+        let state = FluxState::from_signals(&signals);
+        let runs: Vec<&Execution> = state.executions.iter().collect();
+        let follow: Vec<String> = follow_records("e", &runs, &FollowSummary::from_runs(&runs)).iter().map(|r| plain_line(r)).collect();
+        assert_eq!(follow[0], "pattern=e runs=2");
+        assert_eq!(follow[2], "started=2024-05-01T01:02:00+00:00 duration=0us status=failed exit=1 user=unknown command=\"echo \\u{2705} done\" context=unknown");
+        assert_eq!(follow[3], "first_failure=2024-05-01T01:02:00+00:00 last_success=2024-05-01T01:01:00+00:00 streak=1 streak_outcome=failure");
+
+        let summary: Vec<String> = type_summary_records(&signals).iter().map(|r| plain_line(r)).collect();
+        assert_eq!(summary[0], "type=init count=1 kind=known");
+
+        let cache = plain_line(&cache_stats_record(&CacheStats::default()));
+        assert!(cache.starts_with("bootstrap_hit_rate_pct=- bootstrap_hits=0 bootstraps=0 "), "{}", cache);
+
+        let disk = DiskStats {
+            points: vec![crate::disk_stats::DiskPoint {
+                timestamp: "2024-03-01T10:00:00+09:00".to_string(),
+                bytes: 2048,
+                partial: true,
+                operation: "add nokogiri".to_string(),
+            }],
+            largest_jump: None,
+        };
+        assert_eq!(
+            plain_line(&disk_stats_records(&disk)[0]),
+            "timestamp=2024-03-01T10:00:00+09:00 bytes=2048 change_bytes=0 partial=true operation=\"add nokogiri\""
+        );
+    }
+
+    #[test]
+    fn test_gemfile_issue_lines() {
+        let issues = gemfile::validate("gem 'rack'\ngem 'rack'\nend\n");
+        assert_eq!(
+            gemfile_issue_lines("Gemfile", &issues),
+            vec![
+                "⚠️  Gemfile:2: 'rack' is already declared on line 1",
+                "      2 | gem 'rack'",
+                "❌ Gemfile:3: `end` without a matching block",
+                "      3 | end",
+            ]
+        );
+        assert_eq!(
+            plain_line(&gemfile_issue_record("Gemfile", &issues[1])),
+            "severity=error file=Gemfile line=3 message=\"`end` without a matching block\" text=end"
+        );
+    }
+
+    #[test]
+    fn test_ruby_history_lines() {
+        let install = |version: &str, cache_hit: Option<bool>, minute: u32| RubyInstall {
+            version: version.to_string(),
+            cache_hit,
+            timestamp: format!("2026-03-01T09:{:02}:00+09:00", minute),
+            signal_id: minute.to_string(),
+        };
+        let history = [install("3.3.6", None, 0), install("3.4.1", Some(false), 5), install("3.3.6", Some(true), 9)];
+        assert_eq!(
+            ruby_history_lines(&history),
+            vec![
+                "💎 Ruby history (3 bootstrap(s)):",
+                "   2026-03-01 09:00  3.3.6        📝 recorded",
+                "   2026-03-01 09:05  3.4.1        📦 downloaded",
+                "   2026-03-01 09:09  3.3.6        ✨ cache hit  ⬇️  downgrade from 3.4.1",
+            ]
+        );
+        assert_eq!(ruby_history_lines(&[]), vec!["No Ruby has been bootstrapped yet."]);
+    }
+
+    #[test]
+    fn test_license_lines() {
+        use crate::licenses::{GemLicense, LicenseGroup};
+        let gem = |name: &str, version: &str, licenses: &[&str]| GemLicense {
+            name: name.to_string(),
+            version: version.to_string(),
+            licenses: licenses.iter().map(|l| l.to_string()).collect(),
+        };
+        let gems = [gem("racc", "1.7.3", &["Ruby", "BSD-2-Clause"]), gem("rake", "13.1.0", &["MIT"]), gem("x", "1", &["unknown"])];
+        assert_eq!(
+            license_lines(&gems, LicenseGroup::Gem),
+            vec!["racc  1.7.3   Ruby, BSD-2-Clause", "rake  13.1.0  MIT", "x     1       unknown"]
+        );
+        assert_eq!(
+            license_lines(&gems, LicenseGroup::License),
+            vec!["BSD-2-Clause (1): racc-1.7.3", "MIT (1): rake-13.1.0", "Ruby (1): racc-1.7.3", "unknown (1): x-1"]
+        );
+    }
+
+    #[test]
+    fn test_plain_output_is_ascii() {
+        use crate::stats_compare::{Row, Side};
+        let signals = plain_fixture();
+        let state = FluxState::from_signals(&signals);
+        let mut config = ArcConfig::default();
+        config.project.name = Some("ショップ".to_string());
+        config.project.description = Some("🛒 store".to_string());
+        let runs: Vec<&Execution> = state.executions.iter().collect();
+        let refs: Vec<&signals::Signal> = signals.iter().collect();
+        let side = Side { runs: 1, failure_rate: Some(0.0), mean: Some(Duration::from_micros(5)), p95: None };
+        let comparison = Comparison {
+            rows: vec![Row { command: "écho".to_string(), before: side.clone(), after: side, regression: false }],
+            only_before: vec!["✨".to_string()],
+            only_after: vec![],
+        };
+
+        let mut records = full_records(&state, &[], &config, true, true);
+        records.extend(raw_records(&refs, Path::new("/tmp/ü/.flux")));
+        records.extend(diff_records(Some(&LastChange::from_signal(&signals[3]))));
+        records.extend(stats_records(&state.user_stats(&[]), StatsGroup::User));
+        records.extend(trend_records(&state.daily_stats(&[])));
+        records.extend(follow_records("✅", &runs, &FollowSummary::from_runs(&runs)));
+        records.extend(compare_records("é..", "..ü", &comparison, 10.0));
+        records.extend(type_summary_records(&signals));
+        records.push(cache_stats_record(&CacheStats::default()));
+        for record in &records {
+            let line = plain_line(record);
+            assert!(line.is_ascii(), "{}", line);
+            assert!(!line.contains('\x1b'), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_flaky_lines() {
+        use crate::flaky::{Attempt, score};
+        let at = |minute: u32| Some(DateTime::parse_from_rfc3339(&format!("2024-05-01T10:{:02}:00+09:00", minute)).unwrap().to_utc());
+        let groups: Vec<Vec<Attempt>> = [true, false, true, false, true]
+            .iter()
+            .zip(1..)
+            .map(|(&success, minute)| vec![Attempt { success, at: at(minute) }])
+            .collect();
+        let flaky = vec![FlakyCommand { command: "rspec".into(), flakiness: score(&groups).unwrap() }];
+        let lines = flaky_lines(&flaky, 0.3, 5);
+        assert_eq!(lines[0], "🎲 1 flaky command(s) (score ≥ 0.30 over ≥ 5 runs)");
+        assert!(lines[2].starts_with("  rspec") && lines[2].contains(" 1.00 ") && lines[2].contains("60%"), "{}", lines[2]);
+        assert!(lines[2].ends_with(&fmt_datetime(at(5).unwrap())), "{}", lines[2]);
+        assert_eq!(flaky_lines(&[], 0.3, 5), ["✅ No flaky commands (score ≥ 0.30 over ≥ 5 runs)"]);
+
+        let records = flaky_records(&flaky, 0.3, 5);
+        assert_eq!(plain_line(&records[1]), "command=rspec score=1.00 pass_rate=60% runs=5 attempts=5 flips=4 last_flip=2024-05-01T01:05:00+00:00");
+    }
+
+    #[test]
+    fn test_compare_lines() {
+        use crate::stats_compare::{Row, Side};
+        let side = |runs, failure_rate, mean_ms| Side {
+            runs,
+            failure_rate,
+            mean: Some(Duration::from_millis(mean_ms)),
+            p95: None,
+        };
+        let comparison = Comparison {
+            rows: vec![
+                Row { command: "rspec".into(), before: side(10, Some(0.0), 1000), after: side(8, Some(25.0), 1300), regression: true },
+                Row { command: "rubocop".into(), before: side(4, Some(0.0), 500), after: side(4, Some(0.0), 500), regression: false },
+            ],
+            only_before: vec!["old".into()],
+            only_after: vec![],
+        };
+        let lines = compare_lines("2026-01-01..2026-01-31", "2026-02-01..", &comparison, 10.0);
+        assert_eq!(lines[0], "📊 Compare: 2026-01-01..2026-01-31 → 2026-02-01.. (regression above 10%)");
+        assert!(lines[2].starts_with("  rspec     10 → 8   0.0% → 25.0% (+25.0pt)   1.0s → 1.3s (+30.0%)"), "{}", lines[2]);
+        assert!(lines[2].ends_with("⚠️  regression"), "{}", lines[2]);
+        assert!(lines[3].contains("(+0.0%)") && !lines[3].contains("regression"), "{}", lines[3]);
+        assert_eq!(lines.last().unwrap(), "  Only before: old");
+    }
+
+    #[test]
+    fn test_cache_stats_lines_label_estimate() {
+        let stats = CacheStats { bootstraps: 4, bootstrap_hits: 3, est_saved_us: Some(90_000_000), ..Default::default() };
+        let lines = cache_stats_lines(&stats);
+        assert!(lines[1].contains("75% (3/4)"));
+        assert!(lines[4].contains("(estimated)"));
+        // 集計対象がなければ推定値は表示しない
+        let empty = cache_stats_lines(&CacheStats::default());
+        assert!(empty[4].contains("— (estimated)"));
+    }
+
+    #[test]
+    fn test_fmt_duration_us() {
+        assert_eq!(fmt_duration_us(850), "850µs");
+        assert_eq!(fmt_duration_us(999), "999µs");
+        assert_eq!(fmt_duration_us(1_000), "1ms");
+        assert_eq!(fmt_duration_us(999_499), "999ms");
+        assert_eq!(fmt_duration_us(999_500), "1.0s");
+    }
+
+    #[test]
+    fn test_hidden_footer() {
+        assert_eq!(fmt_hidden_footer(12), "12 executions hidden by stats.ignore");
+        assert_eq!(fmt_hidden_footer(1), "1 execution hidden by stats.ignore");
+    }
+
+    fn sample_rows() -> Vec<StatsRow> {
+        let signals: Vec<signals::Signal> = [
+            ("1", "run_start", serde_json::json!({ "command": "rspec", "args": [] })),
+            ("2", "run_end", serde_json::json!({ "ref_id": "1", "exit_code": 0, "success": true, "duration_us": 4_200_000 })),
+            ("3", "exec_start", serde_json::json!({ "command": "rspec", "args": [] })),
+            ("4", "exec_end", serde_json::json!({ "ref_id": "3", "exit_code": 1, "success": false, "duration_us": 1_000 })),
+        ]
+        .into_iter()
+        .map(|(id, r_type, payload)| signals::Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: format!("2026-03-0{}T10:00:00+09:00", id),
+            meta: None,
+            v: 2,
+        })
+        .collect();
+        stats_rows(&FluxState::from_signals(&signals).command_stats(&[]))
+    }
+
+    #[test]
+    fn test_layout_for_width() {
+        assert_eq!(Layout::for_width(80), Layout::Compact);
+        assert_eq!(Layout::for_width(99), Layout::Compact);
+        assert_eq!(Layout::for_width(100), Layout::Table);
+        assert_eq!(Layout::for_width(140), Layout::Table);
+        assert_eq!(Layout::for_width(200), Layout::Wide);
+    }
+
+    #[test]
+    fn test_stats_compact_layout() {
+        let lines = stats_lines(&sample_rows(), Layout::Compact, StatsGroup::Command);
+        assert_eq!(lines, [format!("  {:<32} 2 runs ✅ 1 ❌ 1 avg 2.1s", "rspec")]);
+    }
+
+    #[test]
+    fn test_stats_table_layout() {
+        let lines = stats_lines(&sample_rows(), Layout::Table, StatsGroup::Command);
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with('┌'));
+        assert_eq!(lines[3], format!("│ {:<24} │ {:>7} │ {:>10} │ {:>10} │ {:>12} │", "rspec", "2", "✅ 1", "❌ 1", "2.1s"));
+        assert!(lines.iter().all(|l| l.chars().count() == lines[0].chars().count()), "{:#?}", lines);
+    }
+
+    #[test]
+    fn test_stats_wide_layout() {
+        let lines = stats_lines(&sample_rows(), Layout::Wide, StatsGroup::Command);
+        assert!(lines[1].contains("P95") && lines[1].contains("Last Run") && lines[1].contains("Tags"));
+        assert!(lines[3].contains(" 4.2s │"));
+        let last_run = DateTime::parse_from_rfc3339("2026-03-03T10:00:00+09:00").unwrap().to_utc();
+        assert!(lines[3].contains(&fmt_datetime(last_run)));
+        assert!(lines[3].contains("exec,run"));
+    }
+
+    #[test]
+    fn test_raw_user_column_only_for_multiple_users() {
+        let signal = |id: &str, user: &str| signals::Signal {
+            id: id.to_string(),
+            r_type: "add".to_string(),
+            payload: serde_json::json!({}),
+            timestamp: "2026-03-01T10:00:00+09:00".to_string(),
+            meta: Some(signals::SignalMeta { user: Some(user.to_string()), host: None }),
+            v: 2,
+        };
+        let alice = [signal("1", "alice"), signal("2", "alice")];
+        let lines = raw_lines(&alice.iter().collect::<Vec<_>>(), false);
+        assert!(!lines[1].contains("User"));
+        assert_eq!(lines[0].chars().count(), lines[3].chars().count());
+
+        let shared = [signal("1", "alice"), signal("2", "bob")];
+        let lines = raw_lines(&shared.iter().collect::<Vec<_>>(), false);
+        assert!(lines[1].contains("User"));
+        assert!(lines[4].contains("│ bob "));
+        assert!(lines.iter().all(|l| l.chars().count() == lines[0].chars().count()));
+
+        let by_user = stats_lines(&sample_rows(), Layout::Table, StatsGroup::User);
+        assert!(by_user[1].starts_with("│ User "));
+    }
+
+    #[test]
+    fn test_raw_verbose_shows_system_binary() {
+        let start = signals::Signal {
+            id: "1".to_string(),
+            r_type: "exec_start".to_string(),
+            payload: serde_json::json!({
+                "command": "ruby",
+                "env_context": { "mode": "system", "binary": "/usr/bin/ruby3.2", "binary_version": "ruby 3.2.3" },
+            }),
+            timestamp: "2026-03-01T10:00:00+09:00".to_string(),
+            meta: None,
+            v: 2,
+        };
+        let signals = [&start];
+        assert_eq!(raw_lines(&signals, false).len(), 5);
+        let lines = raw_lines(&signals, true);
+        assert!(lines[4].contains("↳ /usr/bin/ruby3.2 (ruby 3.2.3)"), "{:?}", lines);
+        assert_eq!(lines[4].chars().count(), lines[0].chars().count());
+    }
+
+    #[test]
+    fn test_trend_lines() {
+        let runs = [("2026-03-01", true, 2_000_000), ("2026-03-01", false, 4_000_000), ("2026-03-02", true, 1_000_000)];
+        let mut signals = Vec::new();
+        for (i, (date, success, us)) in runs.into_iter().enumerate() {
+            let signal = |id: String, r_type: &str, payload| signals::Signal {
+                id,
+                r_type: r_type.to_string(),
+                payload,
+                timestamp: format!("{}T10:00:00Z", date),
+                meta: None,
+                v: 2,
+            };
+            let start = format!("s{}", i);
+            signals.push(signal(start.clone(), "run_start", serde_json::json!({ "command": "bin/rspec", "args": [], "task": "test" })));
+            signals.push(signal(
+                format!("e{}", i),
+                "run_end",
+                serde_json::json!({ "ref_id": start, "exit_code": if success { 0 } else { 1 }, "success": success, "duration_us": us }),
+            ));
+        }
+        let lines = trend_lines(&FluxState::from_signals(&signals).daily_stats(&[]));
+        assert_eq!(lines, ["  2026-03-01      2 runs  ✅ 1 ❌ 1  avg 3.0s", "  2026-03-02      1 runs  ✅ 1  avg 1.0s"]);
+    }
+
+    #[test]
+    fn test_header_without_name() {
+        let state = FluxState::from_signals(&[]);
+        let lines = header_lines(&state, &ArcConfig::default(), None);
+        assert!(lines.iter().all(|l| !l.contains("Name:")));
+    }
+
+    #[test]
+    fn test_activity_strip() {
+        let start = chrono::NaiveDate::from_ymd_opt(2026, 2, 26).unwrap();
+        let days: Vec<DayActivity> = [(0, 0), (1, 0), (4, 0), (7, 0), (12, 0), (2, 1), (0, 0), (3, 0)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(executions, failures))| DayActivity {
+                date: start + chrono::Days::new(i as u64),
+                executions,
+                failures,
+            })
+            .collect();
+
+        let lines = activity_lines(&days, false);
+        assert_eq!(lines[0], "  Activity: 2026-02-26 .. 2026-03-05");
+        assert_eq!(lines[1], "  ·░▒▓█✗· ▒");
+        assert_eq!(lines[2], "  · none  ░ 1-2  ▒ 3-5  ▓ 6-9  █ 10+  ✗ failures");
+        assert_eq!(lines[3], "  6 active day(s), 29 execution(s), 1 day(s) with failures");
+
+        let ascii = activity_lines(&days, true);
+        assert_eq!(ascii[1], "  .1234x. 2");
+        assert!(ascii.iter().all(|l| l.is_ascii()), "{:?}", ascii);
+        assert!(activity_lines(&[], true).is_empty());
+    }
+
+    #[test]
+    fn test_full_lines_snapshot() {
+        // 12,345 回の rspec (うち 1,234 回失敗) と 3 回の rake
+        let mut signals = Vec::new();
+        let mut push = |id: String, r_type: &str, payload, minute: usize| {
+            signals.push(signals::Signal {
+                id,
+                r_type: r_type.to_string(),
+                payload,
+                timestamp: format!("2026-03-{:02}T{:02}:{:02}:00+09:00", 1 + minute / 1440 % 28, minute / 60 % 24, minute % 60),
+                meta: None,
+                v: 2,
+            });
+        };
+        for i in 0..12_348 {
+            let (command, failed) = if i < 12_345 { ("rspec", i % 10 == 3) } else { ("rake", false) };
+            let code = if failed { 1 } else { 0 };
+            push(format!("s{}", i), "run_start", serde_json::json!({ "command": command, "args": [] }), i);
+            push(
+                format!("e{}", i),
+                "run_end",
+                serde_json::json!({ "ref_id": format!("s{}", i), "exit_code": code, "success": !failed, "duration_us": 1_500_000 }),
+                i,
+            );
+        }
+        let state = FluxState::from_signals(&signals);
+        let gems = gemfile::parse_content("gem 'rails', '~> 7.1'\ngem 'rspec'\n");
+        let today = NaiveDate::from_ymd_opt(2026, 3, 10).unwrap();
+        let lines = full_lines(&state, &gems, None, &ArcConfig::default(), false, Layout::Table, false, today);
+        let text: Vec<String> = lines
+            .iter()
+            .map(|line| match line {
+                Line::Out(l) => format!("out| {}", l),
+                Line::Err(l) => format!("err| {}", l),
+            })
+            .collect();
+        // 最初の失敗 (3 分目) の日付はローカル時刻で表示する
+        let first_failure = DateTime::parse_from_rfc3339("2026-03-01T00:03:00+09:00").unwrap().with_timezone(&Local);
+        let failed = format!(
+            "err|    ❌ rspec failed 1,235× since {} (exit_code), last exit 1, see `arc stats --follow rspec`",
+            first_failure.format("%Y-%m-%d")
+        );
+        let expected = [
+            "err| ⚡ Flux State",
+            "err| ",
+            "err|   Signals:     24,696",
+            "err|   Executions:  12,348",
+            "err|   Last:        ✅ rake (1.5s)",
+            "err| ",
+            "err|   Dependencies (2):",
+            "err|     📦 rails (~> 7.1)",
+            "err|     📦 rspec",
+            "err| ",
+            "out| ┌──────────────────────────┬─────────┬────────────┬────────────┬──────────────┐",
+            "out| │ Command                  │    Runs │    Success │     Failed │     Avg Time │",
+            "out| ├──────────────────────────┼─────────┼────────────┼────────────┼──────────────┤",
+            "out| │ rake                     │       3 │        ✅ 3 │          — │         1.5s │",
+            "out| │ rspec                    │  12,345 │   ✅ 11,110 │    ❌ 1,235 │         1.5s │",
+            "out| └──────────────────────────┴─────────┴────────────┴────────────┴──────────────┘",
+            "err| ",
+            "err| ⚠️  Failed Operations (1,235):",
+            failed.as_str(),
+            "err|    (use --failures-verbose to list every failure)",
+        ];
+        assert_eq!(text, expected);
+    }
+}
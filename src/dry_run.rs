@@ -0,0 +1,62 @@
+//! `arc --dry-run`: 何を実行するかだけを表示し、環境には触れない。
+//!
+//! 外部プロセス (bundler・curl・tar など) は `executor` が起動せずに表示し、
+//! `.flux` の外への書き込みは各コマンドが `is_enabled` を見て省く。
+//! Signal はログに書かず、このモジュールに溜めて最後に `print_summary` で表示する。
+
+use std::cell::{Cell, RefCell};
+
+use crate::progress;
+use crate::signals::Signal;
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    /// 記録されるはずだった Signal
+    static SIGNALS: RefCell<Vec<Signal>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn enable() {
+    ENABLED.with(|e| e.set(true));
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.with(|e| e.get())
+}
+
+/// 省いた処理を 1 行で表示する。
+pub fn note(message: &str) {
+    progress::human(&format!("[dry-run] {}", message));
+}
+
+/// ログに書く代わりに Signal を溜める。
+pub fn record(signal: Signal) {
+    SIGNALS.with(|s| s.borrow_mut().push(signal));
+}
+
+pub fn take_signals() -> Vec<Signal> {
+    SIGNALS.with(|s| std::mem::take(&mut *s.borrow_mut()))
+}
+
+/// 溜めた Signal を表示する (`--dry-run` のときだけ)。
+pub fn print_summary() {
+    if !is_enabled() {
+        return;
+    }
+    let signals = take_signals();
+    note(&format!("would record {} signal(s):", signals.len()));
+    for signal in signals {
+        if let Ok(line) = serde_json::to_string(&signal) {
+            progress::human(&format!("  {}", line));
+        }
+    }
+}
+
+/// テスト用: `f` を dry-run で実行し、記録されるはずだった Signal を返す。
+#[cfg(test)]
+pub fn scoped<T>(f: impl FnOnce() -> T) -> (T, Vec<Signal>) {
+    enable();
+    take_signals();
+    let result = f();
+    ENABLED.with(|e| e.set(false));
+    (result, take_signals())
+}
@@ -0,0 +1,356 @@
+//! `arc env diff <other>` — 2 つの環境 (プロジェクトか保存した env.lock) を並べて比べる。
+//!
+//! 比べるのは Ruby (エンジン・バージョン)、bundler のバージョン、Gem の集合、環境に関わる設定
+//! (`[env]` と `.arc/bundle-config`)。どちらの側も、プロジェクトのディレクトリなら config.toml・
+//! Gemfile.lock・`.arc/env.lock` から、ファイルなら env.lock として読む。
+//!
+//! 別の版の arc で作ったプロジェクトや env.lock でも読める範囲だけを使い、読めなかった項目は
+//! 「不明」として差分とは別に表示する。比較 (`compare`) は 2 つの `Manifest` に対する純粋な関数。
+
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use crate::config::ArcConfig;
+use crate::env_lock;
+use crate::lockfile::Lockfile;
+use crate::signals::FluxProject;
+
+/// 比べる側の環境。`None` の項目は分からなかったもの
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub ruby_engine: Option<String>,
+    pub ruby_version: Option<String>,
+    pub bundler: Option<String>,
+    /// Gem 名 → バージョン (プラットフォーム別の版が複数あれば `, ` でつなぐ)
+    pub gems: Option<BTreeMap<String, String>>,
+    /// 設定のキー → 値
+    pub config: Option<BTreeMap<String, String>>,
+}
+
+impl Manifest {
+    /// `path` がファイルなら env.lock として、ディレクトリならプロジェクトとして読む。
+    pub fn load(path: &Path) -> Result<Self> {
+        if path.is_file() {
+            let text = fs::read_to_string(path).with_context(|| format!("{:?} を読み込めません", path))?;
+            return Self::from_env_lock(&text).with_context(|| format!("{:?} は env.lock として読めません", path));
+        }
+        if !path.is_dir() {
+            bail!("{:?} はプロジェクトのディレクトリでも env.lock でもありません", path);
+        }
+        Ok(Self::from_project(path))
+    }
+
+    /// env.lock の内容から読む。版によって欠けているフィールドは不明とする。
+    pub fn from_env_lock(text: &str) -> Result<Self> {
+        let value: toml::Value = toml::from_str(text)?;
+        let string = |v: Option<&toml::Value>| v.and_then(|v| v.as_str()).map(String::from);
+        let ruby = value.get("ruby");
+        let gems = value.get("gems").and_then(|g| g.as_array()).map(|gems| {
+            gem_map(gems.iter().filter_map(|g| {
+                Some((string(g.get("name"))?, string(g.get("version"))?, string(g.get("platform"))))
+            }))
+        });
+        Ok(Self {
+            ruby_engine: string(ruby.and_then(|r| r.get("engine"))),
+            ruby_version: string(ruby.and_then(|r| r.get("version"))),
+            bundler: string(value.get("bundler")),
+            gems,
+            config: None,
+        })
+    }
+
+    /// プロジェクトのディレクトリから読む。config.toml・Gemfile.lock を優先し、なければ `.arc/env.lock` を使う。
+    pub fn from_project(dir: &Path) -> Self {
+        let saved = fs::read_to_string(env_lock::path(dir)).ok().and_then(|t| Self::from_env_lock(&t).ok()).unwrap_or_default();
+        let config = FluxProject::open(dir).ok().and_then(|p| ArcConfig::load(&p.flux_dir).ok());
+        let lock = crate::lockfile::parse(&dir.join("Gemfile.lock")).ok();
+        Self {
+            ruby_engine: config.as_ref().map(|_| "ruby".to_string()).or(saved.ruby_engine),
+            ruby_version: config.as_ref().map(|c| c.ruby.version.clone()).or(saved.ruby_version),
+            bundler: lock.as_ref().and_then(|l| l.bundled_with.clone()).or(saved.bundler),
+            gems: lock.as_ref().map(lock_gems).or(saved.gems),
+            config: config.as_ref().map(|c| config_entries(c, dir)),
+        }
+    }
+}
+
+fn lock_gems(lock: &Lockfile) -> BTreeMap<String, String> {
+    gem_map(lock.specs.iter().map(|spec| {
+        let (version, platform) = spec.version_and_platform();
+        (spec.name.clone(), version.to_string(), platform.map(String::from))
+    }))
+}
+
+fn gem_map(gems: impl Iterator<Item = (String, String, Option<String>)>) -> BTreeMap<String, String> {
+    let mut versions: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (name, version, platform) in gems {
+        let version = match platform {
+            Some(platform) => format!("{}-{}", version, platform),
+            None => version,
+        };
+        versions.entry(name).or_default().insert(version);
+    }
+    versions.into_iter().map(|(name, v)| (name, v.into_iter().collect::<Vec<_>>().join(", "))).collect()
+}
+
+/// 比べる設定: config.toml の `[env]` と、arc が隔離した bundler の設定
+fn config_entries(config: &ArcConfig, dir: &Path) -> BTreeMap<String, String> {
+    let mut entries: BTreeMap<String, String> =
+        config.list().unwrap_or_default().into_iter().filter(|(key, _)| key.starts_with("env.")).collect();
+    for (key, value) in crate::bundler_config::load(dir).unwrap_or_default() {
+        entries.insert(format!("bundle-config.{}", key), value);
+    }
+    entries
+}
+
+// ─────────────────────────────────────────────
+// 比較
+// ─────────────────────────────────────────────
+
+/// 表の 1 つのセル
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Value(String),
+    /// その側にない (Gem が入っていない・設定がない)
+    Absent,
+    /// その側からは分からない
+    Unknown,
+}
+
+impl Cell {
+    fn from(value: Option<&String>) -> Self {
+        value.map_or(Cell::Absent, |v| Cell::Value(v.clone()))
+    }
+
+    pub fn display(&self) -> &str {
+        match self {
+            Cell::Value(v) => v,
+            Cell::Absent => "—",
+            Cell::Unknown => "?",
+        }
+    }
+}
+
+/// 違いの種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// 両方にあり、値が違う
+    Differs,
+    /// 左にだけある
+    OnlyLeft,
+    /// 右にだけある
+    OnlyRight,
+    /// どちらかの側で分からない (差分には数えない)
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub item: String,
+    pub left: Cell,
+    pub right: Cell,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnvDiff {
+    pub rows: Vec<Row>,
+    /// 一致した項目の数
+    pub same: usize,
+}
+
+impl EnvDiff {
+    pub fn differences(&self) -> usize {
+        self.rows.iter().filter(|r| r.kind != Kind::Unknown).count()
+    }
+
+    pub fn unknown(&self) -> usize {
+        self.rows.len() - self.differences()
+    }
+
+    /// 項目・左・右の 3 列の表と要約
+    pub fn lines(&self, left: &str, right: &str) -> Vec<String> {
+        let mut lines = vec![format!("🔀 {} ↔ {}", left, right)];
+        if !self.rows.is_empty() {
+            let width = |cells: &mut dyn Iterator<Item = usize>| cells.max().unwrap_or(0);
+            let item_w = width(&mut self.rows.iter().map(|r| r.item.chars().count())).max(4);
+            let left_w = width(&mut self.rows.iter().map(|r| r.left.display().chars().count())).max(4);
+            lines.push(format!("  {:<item_w$}  {:<left_w$}  {}", "Item", "Left", "Right"));
+            for row in &self.rows {
+                lines.push(format!("  {:<item_w$}  {:<left_w$}  {}", row.item, row.left.display(), row.right.display()));
+            }
+        }
+        let mut summary = format!("  {} difference(s), {} identical", self.differences(), self.same);
+        if self.unknown() > 0 {
+            summary.push_str(&format!(", {} unknown (?)", self.unknown()));
+        }
+        lines.push(summary);
+        lines
+    }
+}
+
+/// 2 つの環境を比べる。行は Ruby・bundler・Gem・設定の順。
+pub fn compare(left: &Manifest, right: &Manifest) -> EnvDiff {
+    let mut diff = EnvDiff::default();
+    let scalars = [
+        ("ruby.engine", &left.ruby_engine, &right.ruby_engine),
+        ("ruby.version", &left.ruby_version, &right.ruby_version),
+        ("bundler", &left.bundler, &right.bundler),
+    ];
+    for (item, l, r) in scalars {
+        match (l, r) {
+            (Some(l), Some(r)) if l == r => diff.same += 1,
+            (Some(l), Some(r)) => diff.push(item, Cell::Value(l.clone()), Cell::Value(r.clone()), Kind::Differs),
+            (l, r) => diff.push(item, known(l.as_ref()), known(r.as_ref()), Kind::Unknown),
+        }
+    }
+    diff.compare_maps("gem ", &left.gems, &right.gems, "gems");
+    diff.compare_maps("", &left.config, &right.config, "config");
+    diff
+}
+
+fn known(value: Option<&String>) -> Cell {
+    value.map_or(Cell::Unknown, |v| Cell::Value(v.clone()))
+}
+
+impl EnvDiff {
+    fn push(&mut self, item: &str, left: Cell, right: Cell, kind: Kind) {
+        self.rows.push(Row { item: item.to_string(), left, right, kind });
+    }
+
+    /// 名前ごとに比べる。どちらかが分からなければ `whole` の 1 行だけを不明とする
+    fn compare_maps(
+        &mut self,
+        prefix: &str,
+        left: &Option<BTreeMap<String, String>>,
+        right: &Option<BTreeMap<String, String>>,
+        whole: &str,
+    ) {
+        let (Some(left), Some(right)) = (left, right) else {
+            let cell = |side: &Option<BTreeMap<String, String>>| match side {
+                Some(map) => Cell::Value(format!("{} listed", map.len())),
+                None => Cell::Unknown,
+            };
+            return self.push(whole, cell(left), cell(right), Kind::Unknown);
+        };
+        let names: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+        for name in names {
+            let (l, r) = (left.get(name), right.get(name));
+            let kind = match (l, r) {
+                (Some(l), Some(r)) if l == r => {
+                    self.same += 1;
+                    continue;
+                }
+                (Some(_), Some(_)) => Kind::Differs,
+                (Some(_), None) => Kind::OnlyLeft,
+                _ => Kind::OnlyRight,
+            };
+            self.push(&format!("{}{}", prefix, name), Cell::from(l), Cell::from(r), kind);
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(version: &str, gems: &[(&str, &str)], config: &[(&str, &str)]) -> Manifest {
+        let map = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Manifest {
+            ruby_engine: Some("ruby".to_string()),
+            ruby_version: Some(version.to_string()),
+            bundler: Some("2.5.3".to_string()),
+            gems: Some(map(gems)),
+            config: Some(map(config)),
+        }
+    }
+
+    fn kinds(diff: &EnvDiff) -> Vec<(&str, Kind)> {
+        diff.rows.iter().map(|r| (r.item.as_str(), r.kind)).collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let a = manifest("3.3.6", &[("rake", "13.1.0")], &[("env.share_gems", "true")]);
+        let diff = compare(&a, &a.clone());
+        assert!(diff.rows.is_empty());
+        assert_eq!((diff.differences(), diff.same), (0, 5));
+    }
+
+    #[test]
+    fn test_ruby_and_bundler_differ() {
+        let a = manifest("3.3.6", &[], &[]);
+        let b = Manifest { bundler: Some("2.6.0".to_string()), ..manifest("3.4.1", &[], &[]) };
+        let diff = compare(&a, &b);
+        assert_eq!(kinds(&diff), [("ruby.version", Kind::Differs), ("bundler", Kind::Differs)]);
+        assert_eq!(diff.rows[0].right, Cell::Value("3.4.1".to_string()));
+        assert_eq!(diff.same, 1);
+    }
+
+    #[test]
+    fn test_gem_sets() {
+        let a = manifest("3.3.6", &[("rake", "13.1.0"), ("pry", "0.14.2"), ("json", "2.7.1")], &[]);
+        let b = manifest("3.3.6", &[("rake", "13.2.1"), ("json", "2.7.1"), ("puma", "6.4.0")], &[]);
+        let diff = compare(&a, &b);
+        assert_eq!(
+            kinds(&diff),
+            [("gem pry", Kind::OnlyLeft), ("gem puma", Kind::OnlyRight), ("gem rake", Kind::Differs)]
+        );
+        assert_eq!(diff.rows[0].right, Cell::Absent);
+        assert_eq!(diff.differences(), 3);
+    }
+
+    #[test]
+    fn test_config_keys() {
+        let a = manifest("3.3.6", &[], &[("env.share_runtime", "\"per-repo\""), ("bundle-config.BUNDLE_JOBS", "4")]);
+        let b = manifest("3.3.6", &[], &[("env.share_runtime", "\"per-project\"")]);
+        let diff = compare(&a, &b);
+        assert_eq!(kinds(&diff), [("bundle-config.BUNDLE_JOBS", Kind::OnlyLeft), ("env.share_runtime", Kind::Differs)]);
+    }
+
+    #[test]
+    fn test_unknown_is_not_a_difference() {
+        // 古い env.lock: engine も gems もない
+        let old = Manifest::from_env_lock("arc_version = \"0.0.1\"\nbundler = \"2.5.3\"\n[ruby]\nversion = \"3.3.6\"\n").unwrap();
+        assert_eq!(old.ruby_engine, None);
+        assert_eq!(old.gems, None);
+        let a = manifest("3.3.6", &[("rake", "13.1.0")], &[]);
+        let diff = compare(&a, &old);
+        assert_eq!(kinds(&diff), [("ruby.engine", Kind::Unknown), ("gems", Kind::Unknown), ("config", Kind::Unknown)]);
+        assert_eq!(diff.differences(), 0);
+        assert_eq!(diff.rows[1].left, Cell::Value("1 listed".to_string()));
+        let lines = diff.lines("a", "b");
+        assert_eq!(lines.last().unwrap(), "  0 difference(s), 2 identical, 3 unknown (?)");
+    }
+
+    #[test]
+    fn test_env_lock_and_project_sides() {
+        let dir = std::env::temp_dir().join("arc_env_diff_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(".arc")).unwrap();
+        let lock = "GEM\n  specs:\n    nokogiri (1.16.0-x86_64-linux)\n    nokogiri (1.16.0-arm64-darwin)\n    rake (13.1.0)\n\nBUNDLED WITH\n   2.5.3\n";
+        fs::write(dir.join("Gemfile.lock"), lock).unwrap();
+        let saved = env_lock::EnvLock::build(&crate::lockfile::parse_content(lock), "3.3.6", Path::new("/nonexistent"));
+        saved.write(&dir).unwrap();
+
+        // Flux プロジェクトでないディレクトリ: Ruby は env.lock、Gem は Gemfile.lock から。設定は不明
+        let project = Manifest::load(&dir).unwrap();
+        assert_eq!(project.ruby_version.as_deref(), Some("3.3.6"));
+        assert_eq!(project.gems.as_ref().unwrap()["nokogiri"], "1.16.0-arm64-darwin, 1.16.0-x86_64-linux");
+        assert_eq!(project.config, None);
+        let file = Manifest::load(&env_lock::path(&dir)).unwrap();
+        assert_eq!(file.gems, project.gems);
+        assert_eq!(compare(&project, &file).differences(), 0);
+
+        assert!(Manifest::load(&dir.join("missing")).is_err());
+        fs::write(dir.join("broken.lock"), "not = [toml").unwrap();
+        assert!(Manifest::load(&dir.join("broken.lock")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+//! `.arc/env.lock` — sync 後の `.arc/env` の中身を 1 つのファイルに書き出したマニフェスト。
+//!
+//! ```toml
+//! arc_version = "0.1.0"
+//! bundler = "2.5.3"
+//!
+//! [ruby]
+//! engine = "ruby"
+//! version = "3.3.6"
+//! api_version = "3.3.0"
+//! checksum = "sha256:…"
+//!
+//! [[gems]]
+//! name = "nokogiri"
+//! version = "1.16.0"
+//! platform = "x86_64-linux"
+//! ```
+//!
+//! 同じ環境からは常に同じバイト列を生成する (時刻を含めず、Gem は名前順)。
+//! リポジトリにコミットしておけば、`arc env --verify-lock` で実際の環境とのずれを検出できる。
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::blobs::sha256_hex;
+use crate::config;
+use crate::lockfile::Lockfile;
+
+/// マニフェストのファイル名 (`.arc/env.lock`)
+pub const ENV_LOCK_FILE: &str = ".arc/env.lock";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvLock {
+    pub arc_version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundler: Option<String>,
+    pub ruby: RubyEntry,
+    #[serde(default)]
+    pub gems: Vec<GemEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RubyEntry {
+    pub engine: String,
+    pub version: String,
+    pub api_version: String,
+    /// `ruby_runtime/bin/ruby` の SHA-256。実行環境がなければ省略
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
+pub struct GemEntry {
+    pub name: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+}
+
+pub fn path(cwd: &Path) -> PathBuf {
+    cwd.join(ENV_LOCK_FILE)
+}
+
+impl EnvLock {
+    /// Gemfile.lock と Ruby の実行環境 (`ruby_bin`) からマニフェストを組み立てる。
+    pub fn build(lock: &Lockfile, ruby_version: &str, ruby_bin: &Path) -> Self {
+        let mut gems: Vec<GemEntry> = lock
+            .specs
+            .iter()
+            .map(|spec| {
+                let (version, platform) = spec.version_and_platform();
+                GemEntry { name: spec.name.clone(), version: version.to_string(), platform: platform.map(String::from) }
+            })
+            .collect();
+        gems.sort();
+        gems.dedup();
+        Self {
+            arc_version: env!("CARGO_PKG_VERSION").to_string(),
+            bundler: lock.bundled_with.clone(),
+            ruby: RubyEntry {
+                engine: "ruby".to_string(),
+                version: ruby_version.to_string(),
+                api_version: config::ruby_api_version(ruby_version),
+                checksum: fs::read(ruby_bin).ok().map(|bytes| format!("sha256:{}", sha256_hex(&bytes))),
+            },
+            gems,
+        }
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).context("env.lock のシリアライズに失敗しました")
+    }
+
+    /// 現在の環境から組み立て直したマニフェストと、書き出されている env.lock の差分。
+    /// 一致していれば `None`。
+    pub fn diff_against_file(&self, cwd: &Path) -> Result<Option<String>> {
+        let path = path(cwd);
+        let committed = fs::read_to_string(&path)
+            .with_context(|| format!("env.lock が見つかりません: {:?} (`arc sync` で生成されます)", path))?;
+        let current = self.to_toml()?;
+        Ok((committed != current).then(|| crate::gemfile::unified_diff(ENV_LOCK_FILE, &committed, &current)))
+    }
+
+    /// `.arc/env.lock` に書き出し、内容の SHA-256 を返す。
+    pub fn write(&self, cwd: &Path) -> Result<String> {
+        let content = self.to_toml()?;
+        let path = path(cwd);
+        fs::write(&path, &content).with_context(|| format!("env.lock の書き込みに失敗しました: {:?}", path))?;
+        Ok(sha256_hex(content.as_bytes()))
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile;
+
+    const LOCK: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rake (13.1.0)
+    nokogiri (1.16.0-x86_64-linux)
+      racc (~> 1.4)
+    nokogiri (1.16.0-arm64-darwin)
+    racc (1.7.3)
+
+BUNDLED WITH
+   2.5.3
+";
+
+    #[test]
+    fn test_manifest_is_deterministic() {
+        let dir = std::env::temp_dir().join("arc_env_lock_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(".arc")).unwrap();
+        let ruby = dir.join("ruby");
+        fs::write(&ruby, "binary").unwrap();
+
+        let first = EnvLock::build(&lockfile::parse_content(LOCK), "3.3.6", &ruby);
+        let hash = first.write(&dir).unwrap();
+        let written = fs::read(path(&dir)).unwrap();
+        // 2 度目の生成 (仕様の並びが違っても) はバイト単位で一致する
+        let reordered = LOCK.replace("    rake (13.1.0)\n", "").replace("    racc (1.7.3)\n", "    racc (1.7.3)\n    rake (13.1.0)\n");
+        let second = EnvLock::build(&lockfile::parse_content(&reordered), "3.3.6", &ruby);
+        assert_eq!(second.to_toml().unwrap().as_bytes(), written.as_slice());
+        assert_eq!(second.write(&dir).unwrap(), hash);
+
+        let names: Vec<String> = first.gems.iter().map(|g| format!("{}-{}", g.name, g.platform.as_deref().unwrap_or("ruby"))).collect();
+        assert_eq!(names, ["nokogiri-arm64-darwin", "nokogiri-x86_64-linux", "racc-ruby", "rake-ruby"]);
+        assert_eq!(first.bundler.as_deref(), Some("2.5.3"));
+        assert_eq!(first.ruby.api_version, "3.3.0");
+        assert_eq!(first.ruby.checksum, Some(format!("sha256:{}", sha256_hex(b"binary"))));
+        let text = String::from_utf8(written).unwrap();
+        assert_eq!(toml::from_str::<EnvLock>(&text).unwrap(), first);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! 実行した場所の記録 (start Signal の `context`)。
+//!
+//! 「手元では通るのに CI で落ちる」を Signal ログから追えるよう、start Signal に次を記録する:
+//!
+//! ```json
+//! "context": {
+//!   "tty": { "stdin": false, "stdout": false, "stderr": false },
+//!   "term": "xterm-256color",
+//!   "ci": { "provider": "github-actions", "run_id": "9876543210" },
+//!   "shlvl": 2
+//! }
+//! ```
+//!
+//! CI の判定は `PROVIDERS` の表 (提供元 → 判定に使う環境変数, 実行 ID の環境変数) で行い、
+//! 上から順に最初に当てはまったものを使う。汎用の `CI=true` は最後に見る。
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::IsTerminal;
+
+/// start Signal の payload のキー
+pub const PAYLOAD_KEY: &str = "context";
+/// CI でない実行 (`arc stats --by context`)
+pub const LOCAL: &str = "local";
+/// `context` の記録がない (古い) 実行
+pub const UNKNOWN: &str = "unknown";
+
+/// CI の提供元と、判定に使う環境変数
+pub struct Provider {
+    pub name: &'static str,
+    /// 設定されていれば (空・`false`・`0` 以外) この提供元とみなす
+    pub detector: &'static str,
+    /// ビルド・実行の ID
+    pub run_id: Option<&'static str>,
+}
+
+pub const PROVIDERS: &[Provider] = &[
+    Provider { name: "github-actions", detector: "GITHUB_ACTIONS", run_id: Some("GITHUB_RUN_ID") },
+    Provider { name: "gitlab-ci", detector: "GITLAB_CI", run_id: Some("CI_PIPELINE_ID") },
+    Provider { name: "circleci", detector: "CIRCLECI", run_id: Some("CIRCLE_BUILD_NUM") },
+    Provider { name: "buildkite", detector: "BUILDKITE", run_id: Some("BUILDKITE_BUILD_ID") },
+    Provider { name: "ci", detector: "CI", run_id: None },
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tty {
+    pub stdin: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ci {
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+/// 実行した場所
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecContext {
+    #[serde(default)]
+    pub tty: Tty,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ci: Option<Ci>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shlvl: Option<u32>,
+}
+
+impl ExecContext {
+    /// `var` で環境変数を引いて組み立てる (テストでは環境変数を差し替える)。
+    pub fn capture(var: impl Fn(&str) -> Option<String>, tty: Tty) -> Self {
+        Self {
+            tty,
+            term: var("TERM").filter(|t| !t.is_empty()),
+            ci: detect_ci(&var),
+            shlvl: var("SHLVL").and_then(|l| l.trim().parse().ok()),
+        }
+    }
+
+    /// このプロセスの環境
+    pub fn current() -> Self {
+        let tty = Tty {
+            stdin: std::io::stdin().is_terminal(),
+            stdout: std::io::stdout().is_terminal(),
+            stderr: std::io::stderr().is_terminal(),
+        };
+        Self::capture(|name| std::env::var(name).ok(), tty)
+    }
+
+    /// start Signal の payload から読む。記録のない (古い) Signal は `None`
+    pub fn from_payload(payload: &Value) -> Option<Self> {
+        serde_json::from_value(payload.get(PAYLOAD_KEY)?.clone()).ok()
+    }
+
+    /// `arc stats --by context` の集計単位: CI なら提供元、そうでなければ `local`
+    pub fn group(&self) -> &str {
+        self.ci.as_ref().map_or(LOCAL, |ci| ci.provider.as_str())
+    }
+
+    /// 実行履歴の行に添える印 (`[CI github-actions #9876543210]`)。CI でなければ `None`
+    pub fn badge(&self) -> Option<String> {
+        let ci = self.ci.as_ref()?;
+        Some(match &ci.run_id {
+            Some(id) => format!("[CI {} #{}]", ci.provider, id),
+            None => format!("[CI {}]", ci.provider),
+        })
+    }
+}
+
+/// `PROVIDERS` の順に調べ、最初に当てはまった CI
+pub fn detect_ci(var: impl Fn(&str) -> Option<String>) -> Option<Ci> {
+    let set = |name: &str| var(name).is_some_and(|v| !v.is_empty() && !v.eq_ignore_ascii_case("false") && v != "0");
+    let provider = PROVIDERS.iter().find(|p| set(p.detector))?;
+    Some(Ci {
+        provider: provider.name.to_string(),
+        run_id: provider.run_id.and_then(&var).filter(|id| !id.is_empty()),
+    })
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn test_detect_each_provider() {
+        type Vars<'a> = &'a [(&'a str, &'a str)];
+        let cases: [(Vars, &str, Option<&str>); 5] = [
+            (&[("GITHUB_ACTIONS", "true"), ("CI", "true"), ("GITHUB_RUN_ID", "9876543210")], "github-actions", Some("9876543210")),
+            (&[("GITLAB_CI", "true"), ("CI", "true"), ("CI_PIPELINE_ID", "4242")], "gitlab-ci", Some("4242")),
+            (&[("CIRCLECI", "true"), ("CI", "true"), ("CIRCLE_BUILD_NUM", "77")], "circleci", Some("77")),
+            (&[("BUILDKITE", "true"), ("BUILDKITE_BUILD_ID", "0190-abcd")], "buildkite", Some("0190-abcd")),
+            (&[("CI", "true")], "ci", None),
+        ];
+        for (vars, provider, run_id) in cases {
+            let ci = detect_ci(env(vars)).unwrap();
+            assert_eq!((ci.provider.as_str(), ci.run_id.as_deref()), (provider, run_id), "{:?}", vars);
+        }
+        // ID がなければ提供元だけ
+        assert_eq!(detect_ci(env(&[("GITHUB_ACTIONS", "true")])).unwrap().run_id, None);
+    }
+
+    #[test]
+    fn test_no_ci() {
+        assert_eq!(detect_ci(env(&[])), None);
+        for value in ["", "false", "FALSE", "0"] {
+            assert_eq!(detect_ci(env(&[("CI", value)])), None, "CI={:?}", value);
+        }
+        let context = ExecContext::capture(env(&[("TERM", "xterm-256color"), ("SHLVL", "2")]), Tty { stdin: true, stdout: true, stderr: true });
+        assert_eq!(context.group(), LOCAL);
+        assert_eq!(context.badge(), None);
+        assert_eq!(
+            serde_json::to_value(&context).unwrap(),
+            json!({ "tty": { "stdin": true, "stdout": true, "stderr": true }, "term": "xterm-256color", "shlvl": 2 })
+        );
+    }
+
+    #[test]
+    fn test_payload_round_trip() {
+        let context = ExecContext::capture(env(&[("GITHUB_ACTIONS", "true"), ("GITHUB_RUN_ID", "12")]), Tty::default());
+        let payload = json!({ "command": "rspec", PAYLOAD_KEY: context });
+        let read = ExecContext::from_payload(&payload).unwrap();
+        assert_eq!(read, context);
+        assert_eq!(read.group(), "github-actions");
+        assert_eq!(read.badge().as_deref(), Some("[CI github-actions #12]"));
+        assert_eq!(ExecContext::from_payload(&json!({ "command": "rspec" })), None);
+    }
+}
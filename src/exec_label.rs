@@ -0,0 +1,174 @@
+//! 実行履歴の表示用ラベル (`arc state` の直近の実行・失敗一覧、`arc stats --follow` の各行)。
+//!
+//! `bundle exec rspec` のような記録は、どの Gem を動かしたのかが読み取りにくい。表示のときだけ
+//! `bundle exec` / `bin/` を取り除き、ツールが Gemfile.lock の Gem に対応すれば解決済みのバージョンを添える
+//! (`rspec 3.13.0`)。install には直前の `arc add` / `arc remove` から数えた Gem の増減を添える (`+3 −1 gems`)。
+//!
+//! どれも描画時に Signal の payload とパース済みの Gemfile.lock から組み立てるだけで、記録は書き換えない。
+//! `--plain` / `--json` は記録どおりのコマンドラインを出す。
+
+use crate::display::fmt_cmd;
+use crate::lockfile::Lockfile;
+use crate::state::Execution;
+
+/// 実行ファイル名と Gem 名が異なる主なツール (実行ファイル名, Gem 名)。
+/// 実行ファイル名と同じ名前の Gem が lockfile にあればそちらを優先する。
+const EXECUTABLE_GEMS: &[(&str, &str)] = &[
+    ("rspec", "rspec-core"),
+    ("rails", "railties"),
+    ("standardrb", "standard"),
+    ("erblint", "erb_lint"),
+    ("haml-lint", "haml_lint"),
+];
+
+/// install の前に `arc add` / `arc remove` で増減した Gem の数
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GemDelta {
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl GemDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0
+    }
+
+    /// `+3 −1 gems` (増減のない側は省く)
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.added > 0 {
+            parts.push(format!("+{}", self.added));
+        }
+        if self.removed > 0 {
+            parts.push(format!("−{}", self.removed));
+        }
+        let unit = if self.added + self.removed == 1 { "gem" } else { "gems" };
+        format!("{} {}", parts.join(" "), unit)
+    }
+}
+
+/// `bundle exec <tool> args` → `<tool> args`、`bin/<tool>` → `<tool>`。それ以外はそのまま。
+pub fn normalize<'a>(command: &'a str, args: &'a [String]) -> (&'a str, &'a [String]) {
+    let (command, args) = match (command, args.split_first()) {
+        ("bundle", Some((sub, rest))) if sub == "exec" && !rest.is_empty() => (rest[0].as_str(), &rest[1..]),
+        _ => (command, args),
+    };
+    let command = command.strip_prefix("./").unwrap_or(command);
+    (command.strip_prefix("bin/").filter(|tool| !tool.is_empty()).unwrap_or(command), args)
+}
+
+/// ツール (正規化後の実行ファイル名) に対応する Gem の解決済みバージョン
+pub fn tool_version<'a>(tool: &str, lock: &'a Lockfile) -> Option<&'a str> {
+    let find = |name: &str| lock.specs.iter().find(|spec| spec.name == name).map(|spec| spec.version_and_platform().0);
+    find(tool).or_else(|| EXECUTABLE_GEMS.iter().find(|(exe, _)| *exe == tool).and_then(|(_, gem)| find(gem)))
+}
+
+/// 表示用のコマンドライン。`lock` がなければバージョンは添えない。
+pub fn label(execution: &Execution, lock: Option<&Lockfile>) -> String {
+    let (tool, args) = normalize(&execution.command, &execution.args);
+    let mut label = match lock.and_then(|lock| tool_version(tool, lock)) {
+        Some(version) => fmt_cmd(&format!("{} {}", tool, version), args),
+        None => fmt_cmd(tool, args),
+    };
+    if let Some(delta) = execution.gem_delta.filter(|d| !d.is_empty()) {
+        label.push_str(&format!(" ({})", delta.describe()));
+    }
+    label
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile::parse_content;
+    use crate::signals::Signal;
+    use crate::state::FluxState;
+    use serde_json::json;
+
+    const LOCK: &str = "GEM\n  remote: https://rubygems.org/\n  specs:\n    rake (13.1.0)\n    rspec-core (3.13.0)\n    nokogiri (1.16.0-x86_64-linux)\n    railties (7.1.3)\n";
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn signal(id: &str, r_type: &str, payload: serde_json::Value) -> Signal {
+        Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: "2026-03-01T10:00:00+09:00".to_string(),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let cases = [
+            ("bundle", "exec rspec spec/models", ("rspec", "spec/models")),
+            ("bundle", "exec bin/rails db:migrate", ("rails", "db:migrate")),
+            ("bin/rspec", "", ("rspec", "")),
+            ("./bin/rake", "test", ("rake", "test")),
+            ("bundle", "install --jobs 4", ("bundle", "install --jobs 4")),
+            ("bundle", "exec", ("bundle", "exec")),
+            ("bundle", "", ("bundle", "")),
+            ("bin/", "", ("bin/", "")),
+            ("rspec", "exec", ("rspec", "exec")),
+        ];
+        for (command, line, (tool, rest)) in cases {
+            let args = args(line);
+            let (got_tool, got_args) = normalize(command, &args);
+            assert_eq!((got_tool, got_args.join(" ").as_str()), (tool, rest), "{} {}", command, line);
+        }
+    }
+
+    #[test]
+    fn test_tool_version() {
+        let lock = parse_content(LOCK);
+        assert_eq!(tool_version("rake", &lock), Some("13.1.0"));
+        // 実行ファイル名と Gem 名が違うもの・プラットフォーム付きのもの
+        assert_eq!(tool_version("rspec", &lock), Some("3.13.0"));
+        assert_eq!(tool_version("rails", &lock), Some("7.1.3"));
+        assert_eq!(tool_version("nokogiri", &lock), Some("1.16.0"));
+        assert_eq!(tool_version("rubocop", &lock), None);
+        // 同じ名前の Gem があればそちらを使う
+        let lock = parse_content(&format!("{}    rspec (3.12.0)\n", LOCK));
+        assert_eq!(tool_version("rspec", &lock), Some("3.12.0"));
+    }
+
+    #[test]
+    fn test_label_with_and_without_lockfile() {
+        let state = FluxState::from_signals(&[
+            signal("s1", "exec_start", json!({ "command": "bundle", "args": ["exec", "rspec", "spec/a_spec.rb"] })),
+            signal("e1", "exec_end", json!({ "ref_id": "s1", "exit_code": 0, "success": true })),
+        ]);
+        let lock = parse_content(LOCK);
+        assert_eq!(label(&state.executions[0], Some(&lock)), "rspec 3.13.0 spec/a_spec.rb");
+        assert_eq!(label(&state.executions[0], None), "rspec spec/a_spec.rb");
+        assert_eq!(label(&state.executions[0], Some(&Lockfile::default())), "rspec spec/a_spec.rb");
+        // 記録は書き換えない
+        assert_eq!(state.executions[0].command_line(), "bundle exec rspec spec/a_spec.rb");
+    }
+
+    #[test]
+    fn test_install_delta() {
+        let state = FluxState::from_signals(&[
+            signal("a1", "add", json!({ "gem": "rake" })),
+            signal("a2", "add", json!({ "gem": "puma" })),
+            signal("a3", "add", json!({ "gem": "pry" })),
+            signal("r1", "remove", json!({ "gem": "byebug" })),
+            signal("s1", "install_start", json!({ "command": "bundle", "args": ["install"] })),
+            signal("e1", "install_end", json!({ "ref_id": "s1", "exit_code": 0, "success": true })),
+            signal("s2", "install_start", json!({ "command": "bundle", "args": ["install"] })),
+            signal("e2", "install_end", json!({ "ref_id": "s2", "exit_code": 0, "success": true })),
+            signal("r2", "remove", json!({ "gem": "pry" })),
+            signal("s3", "exec_start", json!({ "command": "bundle", "args": ["update"] })),
+            signal("e3", "exec_end", json!({ "ref_id": "s3", "exit_code": 0, "success": true })),
+        ]);
+        let labels: Vec<String> = state.executions.iter().map(|e| label(e, None)).collect();
+        assert_eq!(labels, ["bundle install (+3 −1 gems)", "bundle install", "bundle update (−1 gem)"]);
+    }
+}
@@ -0,0 +1,134 @@
+//! 外部プロセスの起動を差し替えられるようにする (`CommandExecutor`)。
+//!
+//! bundler・curl・tar・gem などの子プロセスはすべて `progress::run_child` からここを通る。
+//! 通常は `RealExecutor` が実際に起動し、`--dry-run` の間は `DryRunExecutor` が
+//! コマンドラインを表示するだけで成功を返す。テストでは `with` で `RecordingExecutor`
+//! に差し替え、何が起動されたかを確かめる。
+
+use std::cell::RefCell;
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+use std::rc::Rc;
+
+use crate::dry_run;
+use crate::progress;
+
+pub trait CommandExecutor {
+    /// コマンドを終了まで実行する。`tick` を渡した場合は終了まで定期的に呼ぶ。
+    fn run(&self, command: &mut Command, line: &str, tick: Option<&mut dyn FnMut()>) -> io::Result<ExitStatus>;
+}
+
+/// 実際に子プロセスを起動する
+pub struct RealExecutor;
+
+impl CommandExecutor for RealExecutor {
+    fn run(&self, command: &mut Command, line: &str, tick: Option<&mut dyn FnMut()>) -> io::Result<ExitStatus> {
+        progress::spawn_and_wait(command, line, tick)
+    }
+}
+
+/// 起動せずにコマンドラインを表示し、成功したものとして扱う
+pub struct DryRunExecutor;
+
+impl CommandExecutor for DryRunExecutor {
+    fn run(&self, command: &mut Command, _line: &str, _tick: Option<&mut dyn FnMut()>) -> io::Result<ExitStatus> {
+        dry_run::note(&format!("would run: {}", argv(command).join(" ")));
+        Ok(ExitStatus::from_raw(0))
+    }
+}
+
+/// `Command` のプログラムと引数
+pub fn argv(command: &Command) -> Vec<String> {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|s| s.to_string_lossy().to_string())
+        .collect()
+}
+
+thread_local! {
+    static OVERRIDE: RefCell<Option<Rc<dyn CommandExecutor>>> = const { RefCell::new(None) };
+}
+
+/// このスレッドで使う実装でコマンドを実行する: 差し替え > dry-run > 実際の起動。
+pub fn run(command: &mut Command, line: &str, tick: Option<&mut dyn FnMut()>) -> io::Result<ExitStatus> {
+    if let Some(executor) = OVERRIDE.with(|o| o.borrow().clone()) {
+        return executor.run(command, line, tick);
+    }
+    if dry_run::is_enabled() {
+        return DryRunExecutor.run(command, line, tick);
+    }
+    RealExecutor.run(command, line, tick)
+}
+
+/// `f` の間、このスレッドのコマンドを `executor` で実行する。
+#[cfg(test)]
+pub fn with<T>(executor: Rc<dyn CommandExecutor>, f: impl FnOnce() -> T) -> T {
+    let previous = OVERRIDE.with(|o| o.borrow_mut().replace(executor));
+    let result = f();
+    OVERRIDE.with(|o| *o.borrow_mut() = previous);
+    result
+}
+
+/// `RecordingExecutor` が記録した 1 回の起動
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub argv: Vec<String>,
+    pub cwd: Option<std::path::PathBuf>,
+}
+
+/// テスト用: 起動したコマンドを記録し、`exit_code` の終了コードを返す (実際には起動しない)
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordingExecutor {
+    pub calls: RefCell<Vec<Call>>,
+    /// プログラム名ごとの終了コード (なければ 0)
+    pub exit_codes: Vec<(String, i32)>,
+}
+
+#[cfg(test)]
+impl RecordingExecutor {
+    pub fn programs(&self) -> Vec<String> {
+        self.calls.borrow().iter().map(|c| c.argv[0].clone()).collect()
+    }
+}
+
+#[cfg(test)]
+impl CommandExecutor for RecordingExecutor {
+    fn run(&self, command: &mut Command, _line: &str, _tick: Option<&mut dyn FnMut()>) -> io::Result<ExitStatus> {
+        let argv = argv(command);
+        let code = self.exit_codes.iter().find(|(p, _)| argv[0].ends_with(p.as_str())).map_or(0, |(_, c)| *c);
+        self.calls.borrow_mut().push(Call { argv, cwd: command.get_current_dir().map(std::path::PathBuf::from) });
+        // ExitStatus の生の値は wait(2) の形式 (終了コードは上位 8 ビット)
+        Ok(ExitStatus::from_raw(code << 8))
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_and_dry_run() {
+        let fake = Rc::new(RecordingExecutor { exit_codes: vec![("false".into(), 3)], ..Default::default() });
+        let statuses = with(fake.clone(), || {
+            let a = run(Command::new("true").arg("x").current_dir("/tmp"), "true x", None).unwrap();
+            let b = run(&mut Command::new("false"), "false", None).unwrap();
+            (a, b)
+        });
+        assert!(statuses.0.success());
+        assert_eq!(statuses.1.code(), Some(3));
+        assert_eq!(fake.calls.borrow()[0], Call { argv: vec!["true".into(), "x".into()], cwd: Some("/tmp".into()) });
+        assert_eq!(fake.programs(), ["true", "false"]);
+
+        // dry-run では起動しない (失敗するはずのコマンドも成功として扱う)
+        let (status, _) = dry_run::scoped(|| run(&mut Command::new("false"), "false", None).unwrap());
+        assert!(status.success());
+        assert!(!run(&mut Command::new("false"), "false", None).unwrap().success());
+    }
+}
@@ -0,0 +1,161 @@
+//! `arc stats --flaky` — 成功と失敗を行き来するコマンド (不安定なテスト) を見つける。
+//!
+//! 平均や失敗率では、ずっと失敗しているコマンドと交互に失敗するコマンドを区別できない。
+//! コマンド (表示名) ごとに実行を開始時刻の順に並べ、隣り合う試行で結果が変わった回数 (反転) を数え、
+//! `反転数 / (試行数 - 1)` をスコアとする (0 = 安定、1 = 毎回反転)。
+//!
+//! 1 回の実行は「試行のグループ」として扱う。再試行した実行はグループに複数の試行を持ち、
+//! 最終的に成功していてもグループ内の失敗→成功を反転として数える。
+//! スコアの計算 (`score`) は試行の並びに対する純粋な関数。
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::state::Execution;
+
+/// 集計の対象にする最小の実行回数 (`[stats] flaky_min_runs` の既定値)
+pub const DEFAULT_MIN_RUNS: usize = 5;
+/// 不安定とみなすスコア (`[stats] flaky_threshold` の既定値)
+pub const DEFAULT_THRESHOLD: f64 = 0.3;
+
+/// 1 回の試行
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    pub success: bool,
+    pub at: Option<DateTime<Utc>>,
+}
+
+/// 試行の並びの集計
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Flakiness {
+    /// 実行 (グループ) の数
+    pub runs: usize,
+    /// 試行の数 (再試行を含む)
+    pub attempts: usize,
+    /// 結果が反転した回数
+    pub flips: usize,
+    /// `flips / (attempts - 1)`
+    pub score: f64,
+    /// 最終的に成功した実行の割合
+    pub pass_rate: f64,
+    /// 直近の反転 (反転した後の試行の時刻)
+    #[serde(serialize_with = "crate::state::serialize_rfc3339")]
+    pub last_flip: Option<DateTime<Utc>>,
+}
+
+/// 時系列順の実行 (それぞれ試行のグループ) を集計する。実行がなければ `None`。
+pub fn score(groups: &[Vec<Attempt>]) -> Option<Flakiness> {
+    let attempts: Vec<&Attempt> = groups.iter().flatten().collect();
+    let runs = groups.iter().filter(|g| !g.is_empty()).count();
+    if runs == 0 {
+        return None;
+    }
+    let flipped: Vec<&Attempt> = attempts.windows(2).filter(|w| w[0].success != w[1].success).map(|w| w[1]).collect();
+    let passed = groups.iter().filter(|g| g.last().is_some_and(|a| a.success)).count();
+    Some(Flakiness {
+        runs,
+        attempts: attempts.len(),
+        flips: flipped.len(),
+        score: if attempts.len() < 2 { 0.0 } else { flipped.len() as f64 / (attempts.len() - 1) as f64 },
+        pass_rate: passed as f64 / runs as f64,
+        last_flip: flipped.last().and_then(|a| a.at),
+    })
+}
+
+/// 不安定と判定したコマンド
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FlakyCommand {
+    pub command: String,
+    #[serde(flatten)]
+    pub flakiness: Flakiness,
+}
+
+/// `executions` をコマンド (表示名) ごとに集計し、`min_runs` 回以上実行されスコアが `threshold` 以上のものを
+/// スコアの高い順に返す。再試行の記録がないため、実行はそれぞれ 1 試行のグループになる。
+pub fn analyze(executions: &[&Execution], min_runs: usize, threshold: f64) -> Vec<FlakyCommand> {
+    let mut by_command: BTreeMap<&str, Vec<&Execution>> = BTreeMap::new();
+    for execution in executions {
+        by_command.entry(execution.display_name()).or_default().push(execution);
+    }
+    let mut flaky: Vec<FlakyCommand> = by_command
+        .into_iter()
+        .filter_map(|(command, mut runs)| {
+            runs.sort_by_key(|e| e.started_at);
+            let groups: Vec<Vec<Attempt>> =
+                runs.iter().map(|e| vec![Attempt { success: e.success, at: e.started_at }]).collect();
+            let flakiness = score(&groups)?;
+            (flakiness.runs >= min_runs && flakiness.score >= threshold)
+                .then(|| FlakyCommand { command: command.to_string(), flakiness })
+        })
+        .collect();
+    flaky.sort_by(|a, b| b.flakiness.score.total_cmp(&a.flakiness.score).then_with(|| a.command.cmp(&b.command)));
+    flaky
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `pattern` の 1 文字が 1 試行 (`.` = 成功, `F` = 失敗)、空白がグループの区切り。時刻は n 分目
+    fn groups(pattern: &str) -> Vec<Vec<Attempt>> {
+        let mut minute = 0;
+        pattern
+            .split(' ')
+            .map(|group| {
+                group
+                    .chars()
+                    .map(|c| {
+                        minute += 1;
+                        let at = DateTime::parse_from_rfc3339(&format!("2026-03-01T10:{:02}:00+09:00", minute)).unwrap().to_utc();
+                        Attempt { success: c == '.', at: Some(at) }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stable_pass() {
+        let result = score(&groups(". . . . . .")).unwrap();
+        assert_eq!((result.runs, result.flips, result.score, result.pass_rate), (6, 0, 0.0, 1.0));
+        assert_eq!(result.last_flip, None);
+        assert_eq!(score(&[]), None);
+        assert_eq!(score(&groups(".")).unwrap().score, 0.0);
+    }
+
+    #[test]
+    fn test_stable_fail() {
+        let result = score(&groups("F F F F F")).unwrap();
+        assert_eq!((result.flips, result.score, result.pass_rate), (0, 0.0, 0.0));
+        // 1 度だけ直った場合は反転 1 回 (壊れて直ったコマンドは不安定ではない)
+        let fixed = score(&groups("F F F F . . . .")).unwrap();
+        assert_eq!(fixed.flips, 1);
+        assert!(fixed.score < DEFAULT_THRESHOLD);
+    }
+
+    #[test]
+    fn test_alternating() {
+        let result = score(&groups(". F . F . F")).unwrap();
+        assert_eq!((result.flips, result.score, result.pass_rate), (5, 1.0, 0.5));
+        assert_eq!(result.last_flip.unwrap().to_rfc3339(), "2026-03-01T01:06:00+00:00");
+    }
+
+    #[test]
+    fn test_retry_groups_count_as_flips() {
+        // すべて最終的には成功しているが、2 回に 1 回は再試行で成功している
+        let result = score(&groups(". F. . F. . FF.")).unwrap();
+        assert_eq!(result.runs, 6);
+        assert_eq!(result.attempts, 10);
+        assert_eq!(result.pass_rate, 1.0);
+        assert_eq!(result.flips, 6);
+        assert_eq!(result.score, 6.0 / 9.0);
+        assert_eq!(result.last_flip.unwrap().to_rfc3339(), "2026-03-01T01:10:00+00:00");
+        // 同じ結果の並びでも、再試行がなければ安定
+        assert_eq!(score(&groups(". . . . . .")).unwrap().score, 0.0);
+    }
+}
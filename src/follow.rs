@@ -0,0 +1,232 @@
+//! `arc stats --follow <pattern>` — 1 つのコマンドの実行履歴を時系列で追う。
+//!
+//! start/end を組み立て済みの `Execution` の一覧を、表示名 (タスク名・エイリアス名) か
+//! コマンドラインで絞り込む。既定は部分一致、`--regex` で正規表現。
+//!
+//! `--watch` では `Watch` がログに追記された行だけを `StateBuilder` に適用し、新しく終わった実行を返す。
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+
+use crate::signals::FluxProject;
+use crate::state::{Execution, StateBuilder};
+
+/// 実行を絞り込む条件
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// `regex` なら `pattern` を正規表現としてコンパイルする (不正ならその理由をエラーにする)。
+    pub fn new(pattern: &str, regex: bool) -> Result<Self> {
+        if !regex {
+            return Ok(Matcher::Substring(pattern.to_string()));
+        }
+        Regex::new(pattern).map(Matcher::Regex).map_err(|e| anyhow!("--follow の正規表現が不正です: {:?}\n{}", pattern, e))
+    }
+
+    pub fn matches(&self, execution: &Execution) -> bool {
+        let name = execution.display_name();
+        let line = execution.command_line();
+        match self {
+            Matcher::Substring(s) => name.contains(s.as_str()) || line.contains(s.as_str()),
+            Matcher::Regex(re) => re.is_match(name) || re.is_match(&line),
+        }
+    }
+}
+
+/// 条件に一致する実行を開始時刻の順に並べる (同時刻はログの順)。
+pub fn runs<'a>(executions: &'a [Execution], matcher: &Matcher) -> Vec<&'a Execution> {
+    let mut runs: Vec<&Execution> = executions.iter().filter(|e| matcher.matches(e)).collect();
+    runs.sort_by_key(|e| e.started_at);
+    runs
+}
+
+/// 履歴の要約 (`runs` の添字)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FollowSummary {
+    /// 最初の失敗
+    pub first_failure: Option<usize>,
+    /// 最後の成功
+    pub last_success: Option<usize>,
+    /// 直近から同じ結果が続いている回数と、その結果 (成功なら true)
+    pub streak: Option<(bool, usize)>,
+}
+
+impl FollowSummary {
+    pub fn from_runs(runs: &[&Execution]) -> Self {
+        let streak = runs.last().map(|last| {
+            let count = runs.iter().rev().take_while(|e| e.success == last.success).count();
+            (last.success, count)
+        });
+        Self {
+            first_failure: runs.iter().position(|e| !e.success),
+            last_success: runs.iter().rposition(|e| e.success),
+            streak,
+        }
+    }
+}
+
+/// `--watch` で読み進めたログの位置と、そこまでの State
+#[derive(Debug, Default)]
+pub struct Watch {
+    builder: StateBuilder,
+    offset: u64,
+}
+
+impl Watch {
+    /// ここまでに終わった実行
+    pub fn executions(&self) -> &[Execution] {
+        &self.builder.state().executions
+    }
+
+    /// ログに追記された Signal を適用し、新しく終わった実行を返す。
+    /// ログが書き換えられていたら (`arc undo` など) 最初から読み直し、そのときは何も返さない。
+    pub fn poll(&mut self, project: &FluxProject) -> Result<&[Execution]> {
+        let Some((signals, offset)) = project.read_from(self.offset)? else {
+            *self = Self::default();
+            self.poll(project)?;
+            return Ok(&[]);
+        };
+        let seen = self.executions().len();
+        for signal in &signals {
+            self.builder.apply(signal);
+        }
+        self.offset = offset;
+        Ok(&self.executions()[seen..])
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::Signal;
+    use crate::state::FluxState;
+    use serde_json::{Value, json};
+
+    fn signal(id: &str, r_type: &str, minute: u32, payload: Value) -> Signal {
+        Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: format!("2024-05-01T10:{:02}:00+09:00", minute),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    /// migrate と rspec が入り組んだログ (rspec の終了は migrate の開始より後)
+    fn log() -> Vec<Signal> {
+        let run = |id: &str, minute: u32, args: &[&str], task: Option<&str>| {
+            let mut payload = json!({ "command": "bundle", "args": args });
+            if let Some(task) = task {
+                payload["task"] = json!(task);
+            }
+            signal(id, "run_start", minute, payload)
+        };
+        let end = |id: &str, minute: u32, start: &str, code: i64| {
+            signal(id, "run_end", minute, json!({ "ref_id": start, "exit_code": code, "success": code == 0, "duration_ms": 100 }))
+        };
+        vec![
+            run("s1", 0, &["exec", "rake", "db:migrate"], None),
+            end("e1", 1, "s1", 0),
+            run("s2", 2, &["exec", "rspec"], Some("test")),
+            run("s3", 3, &["exec", "rake", "db:migrate"], None),
+            end("e3", 4, "s3", 1),
+            end("e2", 5, "s2", 0),
+            run("s4", 6, &["exec", "rake", "db:migrate:status"], None),
+            end("e4", 7, "s4", 0),
+            run("s5", 8, &["exec", "rake", "db:migrate"], None),
+            end("e5", 9, "s5", 1),
+            run("s6", 10, &["exec", "rake", "db:migrate"], None),
+            end("e6", 11, "s6", 1),
+        ]
+    }
+
+    #[test]
+    fn test_substring_filters_paired_runs() {
+        let state = FluxState::from_signals(&log());
+        let matcher = Matcher::new("db:migrate", false).unwrap();
+        let found = runs(&state.executions, &matcher);
+        let ids: Vec<&str> = found.iter().map(|e| e.start_id.as_str()).collect();
+        assert_eq!(ids, ["s1", "s3", "s4", "s5", "s6"]);
+        assert_eq!(found[1].exit_code, Some(1));
+
+        // 表示名 (タスク名) でも一致する
+        let tests = runs(&state.executions, &Matcher::new("test", false).unwrap());
+        assert_eq!(tests.len(), 1);
+        assert_eq!(tests[0].command_line(), "bundle exec rspec");
+    }
+
+    #[test]
+    fn test_regex_mode() {
+        let state = FluxState::from_signals(&log());
+        let exact = Matcher::new(r"db:migrate$", true).unwrap();
+        let ids: Vec<&str> = runs(&state.executions, &exact).iter().map(|e| e.start_id.as_str()).collect();
+        assert_eq!(ids, ["s1", "s3", "s5", "s6"]);
+        // 部分一致では正規表現として解釈しない
+        assert!(runs(&state.executions, &Matcher::new(r"db:migrate$", false).unwrap()).is_empty());
+
+        let err = Matcher::new("db:(migrate", true).unwrap_err().to_string();
+        assert!(err.contains("--follow の正規表現が不正です") && err.contains("unclosed group"), "{}", err);
+    }
+
+    #[test]
+    fn test_summary_and_streak() {
+        let state = FluxState::from_signals(&log());
+        let found = runs(&state.executions, &Matcher::new(r"db:migrate$", true).unwrap());
+        let summary = FollowSummary::from_runs(&found);
+        assert_eq!(summary, FollowSummary { first_failure: Some(1), last_success: Some(0), streak: Some((false, 3)) });
+
+        let found = runs(&state.executions, &Matcher::new("status", false).unwrap());
+        assert_eq!(FollowSummary::from_runs(&found).streak, Some((true, 1)));
+        assert_eq!(FollowSummary::from_runs(&[]), FollowSummary::default());
+    }
+
+    #[test]
+    fn test_watch_reads_only_appended_lines() {
+        use std::io::Write;
+        let dir = std::env::temp_dir().join("arc_follow_watch_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let project = FluxProject::init(&dir, &Default::default(), json!({})).unwrap().0;
+        let append = |signals: &[Signal], partial: &str| {
+            let mut file = std::fs::OpenOptions::new().append(true).open(&project.signal_file).unwrap();
+            for signal in signals {
+                writeln!(file, "{}", serde_json::to_string(signal).unwrap()).unwrap();
+            }
+            write!(file, "{}", partial).unwrap();
+        };
+        let log = log();
+        let ids = |runs: &[Execution]| runs.iter().map(|e| e.start_id.clone()).collect::<Vec<_>>();
+
+        let mut watch = Watch::default();
+        assert!(watch.poll(&project).unwrap().is_empty());
+        append(&log[..4], "");
+        assert_eq!(ids(watch.poll(&project).unwrap()), ["s1"]);
+        // s2 はまだ終わっていない。書き込み途中の行は次に回す
+        let line = serde_json::to_string(&log[5]).unwrap();
+        let (head, tail) = line.split_at(10);
+        append(&log[4..5], head);
+        assert_eq!(ids(watch.poll(&project).unwrap()), ["s3"]);
+        append(&[], &format!("{}\n", tail));
+        assert_eq!(ids(watch.poll(&project).unwrap()), ["s2"]);
+        append(&log[6..], "");
+        assert_eq!(ids(watch.poll(&project).unwrap()), ["s4", "s5", "s6"]);
+        assert_eq!(watch.executions().len(), FluxState::from_signals(&project.read_signals().unwrap()).executions.len());
+
+        // ログが書き換えられたら読み直す
+        std::fs::write(&project.signal_file, format!("{}\n", serde_json::to_string(&log[0]).unwrap())).unwrap();
+        assert!(watch.poll(&project).unwrap().is_empty());
+        assert!(watch.executions().is_empty());
+        append(&log[1..2], "");
+        assert_eq!(ids(watch.poll(&project).unwrap()), ["s1"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
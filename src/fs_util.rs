@@ -0,0 +1,179 @@
+//! ファイルシステムの補助関数。
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+use std::ffi::CString;
+use std::fs;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// install / bootstrap の後に `.arc/env` のサイズを数える時間の上限
+pub const DISK_WALK_BUDGET: Duration = Duration::from_secs(2);
+
+/// ディレクトリのサイズ。`partial` なら時間切れで途中までの値 (実際より小さい)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DirSize {
+    pub bytes: u64,
+    pub files: u64,
+    pub partial: bool,
+}
+
+impl DirSize {
+    /// Signal の payload に記録する形 (`{"bytes", "files", "partial"}`)
+    pub fn to_json(self) -> Value {
+        json!({ "bytes": self.bytes, "files": self.files, "partial": self.partial })
+    }
+}
+
+/// `path` のあるファイルシステムの空き容量 (root 以外が使える分、バイト)。
+pub fn free_space(path: &Path) -> std::io::Result<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::zeroed();
+    // SAFETY: NUL 終端したパスと、書き込み先の statvfs を渡す
+    if unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: statvfs が成功したので初期化済み
+    let stat = unsafe { stat.assume_init() };
+    // フィールドの型はプラットフォームによって u32 / u64 のどちらか
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// `root` 以下のファイルの合計サイズ (見かけのサイズ)。シンボリックリンクは辿らない。
+/// `budget` を過ぎたらそこで打ち切り、`partial` を立てて返す。`root` がなければ 0。
+pub fn dir_size(root: &Path, budget: Duration) -> DirSize {
+    let deadline = Instant::now() + budget;
+    let mut size = DirSize::default();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            if Instant::now() >= deadline {
+                size.partial = true;
+                return size;
+            }
+            let Ok(meta) = entry.path().symlink_metadata() else { continue };
+            if meta.is_dir() {
+                stack.push(entry.path());
+            } else {
+                size.bytes += meta.len();
+                size.files += 1;
+            }
+        }
+    }
+    size
+}
+
+// ─────────────────────────────────────────────
+// 追記されるファイルの書き換え
+// ─────────────────────────────────────────────
+
+/// 書き換え中に追記された場合に読み直す回数
+const REWRITE_ATTEMPTS: usize = 3;
+
+/// ロックを取らずに追記されるファイル (Signal ログ) を書き換える。
+/// `attempt` は毎回ファイルを読み直して `replace_unless_appended` で置き換え、途中で追記されて
+/// 置き換えられなかった場合は `None` を返す。その行を失わないよう、数回まで読み直してやり直す。
+pub fn rewrite_with_retry<T>(path: &Path, mut attempt: impl FnMut() -> Result<Option<T>>) -> Result<T> {
+    for _ in 0..REWRITE_ATTEMPTS {
+        if let Some(done) = attempt()? {
+            return Ok(done);
+        }
+    }
+    bail!("{:?} への追記が続いているため書き換えられませんでした。しばらくしてから再実行してください", path)
+}
+
+/// `content` を一時ファイル (`<path>.<suffix>`) に書いてから `path` を置き換える。
+/// `path` の長さが読んだときの `read_len` から変わっていれば (追記された)、置き換えずに `false`。
+pub fn replace_unless_appended(path: &Path, content: &str, read_len: u64, suffix: &str) -> Result<bool> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}", suffix));
+    let tmp = std::path::PathBuf::from(tmp);
+    let write = || -> std::io::Result<bool> {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        let original = fs::metadata(path)?;
+        if original.len() != read_len {
+            return Ok(false);
+        }
+        fs::set_permissions(&tmp, original.permissions())?;
+        fs::rename(&tmp, path)?;
+        Ok(true)
+    };
+    let result = write();
+    if !matches!(result, Ok(true)) {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| format!("{:?} を書き換えられません", path))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_space() {
+        assert!(free_space(&std::env::temp_dir()).unwrap() > 0);
+        assert!(free_space(Path::new("/nonexistent/arc_fs_util_free_test")).is_err());
+    }
+
+    #[test]
+    fn test_dir_size_of_fixture_tree() {
+        let root = std::env::temp_dir().join("arc_fs_util_size_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("gems/rake-13.1.0/lib")).unwrap();
+        fs::write(root.join("gems/rake-13.1.0/lib/rake.rb"), vec![0u8; 1000]).unwrap();
+        fs::write(root.join("gems/rake-13.1.0/README"), vec![0u8; 24]).unwrap();
+        fs::write(root.join("top"), vec![0u8; 3]).unwrap();
+        // リンク先は数えない
+        std::os::unix::fs::symlink(root.join("gems"), root.join("link")).unwrap();
+
+        let size = dir_size(&root, DISK_WALK_BUDGET);
+        assert_eq!((size.bytes, size.partial), (1027 + fs::symlink_metadata(root.join("link")).unwrap().len(), false));
+        assert_eq!(size.files, 4);
+        assert_eq!(size.to_json()["partial"], false);
+
+        // 時間切れなら partial
+        let size = dir_size(&root, Duration::ZERO);
+        assert!(size.partial);
+        assert!(size.bytes < 1027);
+        assert_eq!(dir_size(&root.join("missing"), DISK_WALK_BUDGET), DirSize::default());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_retries_after_append() {
+        let dir = std::env::temp_dir().join("arc_fs_util_rewrite_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("signals.jsonl");
+        fs::write(&path, "a\nb\n").unwrap();
+
+        // 1 回目は読んだ後に追記される
+        let mut attempts = 0;
+        let result = rewrite_with_retry(&path, || {
+            attempts += 1;
+            let content = fs::read_to_string(&path).unwrap();
+            if attempts == 1 {
+                fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"c\n").unwrap();
+            }
+            let kept = content.replace("a\n", "");
+            Ok(replace_unless_appended(&path, &kept, content.len() as u64, "test")?.then_some(attempts))
+        });
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b\nc\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // 追記が続けば諦める
+        assert!(rewrite_with_retry(&path, || Ok(None::<()>)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
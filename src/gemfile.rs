@@ -2,8 +2,11 @@
 ///
 /// Bundler の DSL は Ruby なので完全なパースは行わない。
 /// 実用上の範囲（`gem 'name'` / `gem "name"` / バージョン指定付き）を対象とする。
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 use std::path::Path;
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 
 // ─────────────────────────────────────────────
 // 型定義
@@ -14,6 +17,8 @@ use anyhow::{Context, Result};
 pub struct GemEntry {
     pub name: String,
     pub version: Option<String>,
+    /// `group :development, :test do ... end` ブロック内の場合のグループ名
+    pub group: Option<String>,
 }
 
 // ─────────────────────────────────────────────
@@ -28,11 +33,43 @@ pub fn parse(gemfile: &Path) -> Result<Vec<GemEntry>> {
 }
 
 /// 文字列から `gem` 宣言を解析する（テスト可能な純粋関数）。
+/// `group ... do` ブロックを追跡し、ブロック内の Gem にグループ名を付与する。
 pub fn parse_content(content: &str) -> Vec<GemEntry> {
-    content
-        .lines()
-        .filter_map(parse_gem_line)
-        .collect()
+    let mut entries = Vec::new();
+    // `do` ブロックのスタック。group 以外のブロック (platforms 等) は None を積む
+    let mut blocks: Vec<Option<String>> = Vec::new();
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed == "end" {
+            blocks.pop();
+            continue;
+        }
+        if trimmed.ends_with(" do") || trimmed.contains(" do |") {
+            let group = trimmed
+                .strip_prefix("group ")
+                .map(|rest| parse_group_names(rest.trim_end_matches(" do")));
+            blocks.push(group);
+            continue;
+        }
+
+        if let Some(mut entry) = parse_gem_line(line) {
+            entry.group = blocks.iter().rev().find_map(|g| g.clone());
+            entries.push(entry);
+        }
+    }
+
+    entries
+}
+
+/// `:development, :test` → `development, test`
+fn parse_group_names(spec: &str) -> String {
+    spec.split(',')
+        .map(|g| g.trim().trim_start_matches(':').trim_matches(|c| c == '\'' || c == '"'))
+        .filter(|g| !g.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 /// 1行を解析して `GemEntry` を返す。
@@ -60,7 +97,7 @@ fn parse_gem_line(line: &str) -> Option<GemEntry> {
     // バージョン指定: 2番目以降のクォート内文字列（あれば）
     let version = extract_version_specs(rest, &name);
 
-    Some(GemEntry { name, version })
+    Some(GemEntry { name, version, group: None })
 }
 
 /// 文字列から最初のシングル/ダブルクォートで囲まれた部分を抽出する。
@@ -116,61 +153,791 @@ fn extract_version_specs(line: &str, gem_name: &str) -> Option<String> {
 // 操作
 // ─────────────────────────────────────────────
 
-/// Gemfile に Gem を追加する。既に存在する場合は `false` を返す。
-/// 存在チェックは行単位の完全一致（Gem 名が一致する行があるか）で行う。
-pub fn add_gem(gemfile: &Path, gem_name: &str, version: Option<&str>) -> Result<bool> {
-    let content = if gemfile.exists() {
-        std::fs::read_to_string(gemfile)?
-    } else {
-        "source 'https://rubygems.org'\n".to_string()
+/// 新しく作成する Gemfile の内容
+pub const SCAFFOLD: &str = "source 'https://rubygems.org'\n";
+
+/// 編集を適用した後の Gemfile の内容。変わらなければ `None`。ファイルには書き込まない。
+/// Gemfile がなければ `SCAFFOLD` に適用する。
+pub fn edited(gemfile: &Path, edits: &[Edit]) -> Result<Option<String>> {
+    let content = if gemfile.exists() { read(gemfile)? } else { SCAFFOLD.to_string() };
+    let mut doc = GemfileDoc::parse(&content);
+    let mut changed = false;
+    for edit in edits {
+        changed |= doc.apply(edit);
+    }
+    Ok(changed.then(|| doc.render()))
+}
+
+/// 編集を適用した場合の unified diff を返す。ファイルには書き込まない (`--dry-run`)。
+pub fn preview(gemfile: &Path, edits: &[Edit]) -> Result<String> {
+    let before = if gemfile.exists() { read(gemfile)? } else { SCAFFOLD.to_string() };
+    let mut doc = GemfileDoc::parse(&before);
+    for edit in edits {
+        doc.apply(edit);
+    }
+    Ok(unified_diff("Gemfile", &before, &doc.render()))
+}
+
+fn read(gemfile: &Path) -> Result<String> {
+    std::fs::read_to_string(gemfile)
+        .with_context(|| format!("Gemfile の読み込みに失敗しました: {:?}", gemfile))
+}
+
+// ─────────────────────────────────────────────
+// 行モデル (構造化エディタ)
+// ─────────────────────────────────────────────
+//
+// Gemfile を種別付きの行の列として保持し、編集は影響する行だけを書き換える。
+// 触れなかった行はバイト単位でそのまま (CRLF・末尾の改行の有無も含む) 出力される。
+
+/// 行の種別。
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineKind {
+    Source,
+    Gem(GemSpan),
+    /// `group :development, :test do` (グループ名は `development, test` に正規化)
+    GroupStart(String),
+    /// group 以外の `do` ブロック (`platforms :jruby do` など)
+    BlockStart,
+    BlockEnd,
+    Comment,
+    Blank,
+    Other,
+}
+
+/// `gem` 行の各部分の位置 (行内のバイト範囲)。
+#[derive(Debug, Clone, PartialEq)]
+pub struct GemSpan {
+    /// Gem 名 (クォートの内側)
+    pub name: Range<usize>,
+    /// 名前の閉じクォートの直後から、最後のバージョン指定の閉じクォートまで。
+    /// バージョン指定がなければ空の範囲
+    pub versions: Range<usize>,
+    /// 名前に使われているクォート
+    pub quote: char,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    text: String,
+    kind: LineKind,
+}
+
+impl Line {
+    fn new(text: String) -> Self {
+        let kind = classify(&text);
+        Self { text, kind }
+    }
+
+    fn gem_name(&self) -> Option<&str> {
+        match &self.kind {
+            LineKind::Gem(span) => Some(&self.text[span.name.clone()]),
+            _ => None,
+        }
+    }
+}
+
+/// Gemfile への編集操作。
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// Gem を追加する。`group` を指定した場合はそのグループのブロック内 (なければ新しいブロック) に置く
+    InsertGem { name: String, version: Option<String>, group: Option<String> },
+    /// 同名の `gem` 行をすべて削除する
+    RemoveGem { name: String },
+    /// バージョン指定を置き換える (`None` で削除)。オプションやコメントはそのまま
+    #[allow(dead_code)] // バージョン固定 (pin) で使用予定
+    SetVersion { name: String, version: Option<String> },
+    /// `gem` 行を別のグループ (`None` はトップレベル) に移す
+    #[allow(dead_code)] // グループ指定の追加で使用予定
+    MoveToGroup { name: String, group: Option<String> },
+    /// 記録しておいた `gem` 行 (`GemLine`) をそのまま戻す。同名の行があっても追加する
+    RestoreLine(GemLine),
+}
+
+/// `gem` 行の内容 (インデントを除く) と、それを囲むグループ
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GemLine {
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// 行単位でパースした Gemfile。
+#[derive(Debug, Clone)]
+pub struct GemfileDoc {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+impl GemfileDoc {
+    pub fn parse(content: &str) -> Self {
+        let trailing_newline = content.ends_with('\n');
+        let body = content.strip_suffix('\n').unwrap_or(content);
+        let lines = if content.is_empty() {
+            vec![]
+        } else {
+            body.split('\n').map(|l| Line::new(l.to_string())).collect()
+        };
+        Self { lines, trailing_newline }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = self.lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n");
+        if self.trailing_newline {
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.lines.iter().any(|l| l.gem_name() == Some(name))
+    }
+
+    /// `name` の `gem` 行 (出現順)
+    pub fn gem_lines(&self, name: &str) -> Vec<GemLine> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.gem_name() == Some(name))
+            .map(|(i, l)| GemLine { text: l.text.trim().to_string(), group: self.group_at(i) })
+            .collect()
+    }
+
+    /// 編集を適用する。内容が変わった場合は `true`。
+    pub fn apply(&mut self, edit: &Edit) -> bool {
+        match edit {
+            Edit::InsertGem { name, version, group } => {
+                if self.contains(name) {
+                    return false;
+                }
+                self.insert(gem_line(name, version.as_deref()), group.as_deref());
+                true
+            }
+            Edit::RemoveGem { name } => {
+                let before = self.lines.len();
+                self.lines.retain(|l| l.gem_name() != Some(name));
+                self.lines.len() != before
+            }
+            Edit::SetVersion { name, version } => {
+                let mut changed = false;
+                for line in self.lines.iter_mut().filter(|l| l.gem_name() == Some(name)) {
+                    let LineKind::Gem(span) = &line.kind else { continue };
+                    let versions = match version {
+                        Some(v) => format!(", {q}{}{q}", v, q = span.quote),
+                        None => String::new(),
+                    };
+                    let text = format!("{}{}{}", &line.text[..span.versions.start], versions, &line.text[span.versions.end..]);
+                    if text != line.text {
+                        *line = Line::new(text);
+                        changed = true;
+                    }
+                }
+                changed
+            }
+            Edit::MoveToGroup { name, group } => {
+                let Some(index) = self.lines.iter().position(|l| l.gem_name() == Some(name)) else { return false };
+                if self.group_at(index).as_deref() == group.as_deref() {
+                    return false;
+                }
+                let line = self.lines.remove(index);
+                self.insert(line.text.trim().to_string(), group.as_deref());
+                true
+            }
+            Edit::RestoreLine(line) => {
+                self.insert(line.text.clone(), line.group.as_deref());
+                true
+            }
+        }
+    }
+
+    /// `index` 行目を囲む最も内側の group ブロックのグループ名。
+    fn group_at(&self, index: usize) -> Option<String> {
+        let mut blocks: Vec<Option<&str>> = Vec::new();
+        for line in &self.lines[..index] {
+            match &line.kind {
+                LineKind::GroupStart(g) => blocks.push(Some(g)),
+                LineKind::BlockStart => blocks.push(None),
+                LineKind::BlockEnd => {
+                    blocks.pop();
+                }
+                _ => {}
+            }
+        }
+        blocks.iter().rev().find_map(|g| g.map(String::from))
+    }
+
+    /// インデントなしの行 `text` を、`group` のブロック内またはトップレベルの末尾に挿入する。
+    fn insert(&mut self, text: String, group: Option<&str>) {
+        let Some(group) = group else {
+            self.append(vec![text]);
+            return;
+        };
+        let Some(start) = self.lines.iter().position(|l| l.kind == LineKind::GroupStart(group.to_string())) else {
+            let names: Vec<String> = group.split(", ").map(|g| format!(":{}", g)).collect();
+            let block = vec![String::new(), format!("group {} do", names.join(", ")), format!("  {}", text), "end".to_string()];
+            let block = if self.lines.iter().all(|l| l.text.is_empty()) { block[1..].to_vec() } else { block };
+            self.append(block);
+            return;
+        };
+
+        // 対応する `end` を探し、ブロック内の最後の gem 行に合わせてインデントする
+        let mut depth = 0;
+        let mut end = self.lines.len();
+        let mut indent = format!("{}  ", indentation(&self.lines[start].text));
+        for (i, line) in self.lines.iter().enumerate().skip(start + 1) {
+            match line.kind {
+                LineKind::GroupStart(_) | LineKind::BlockStart => depth += 1,
+                LineKind::BlockEnd if depth == 0 => {
+                    end = i;
+                    break;
+                }
+                LineKind::BlockEnd => depth -= 1,
+                LineKind::Gem(_) if depth == 0 => indent = indentation(&line.text).to_string(),
+                _ => {}
+            }
+        }
+        self.lines.insert(end, Line::new(format!("{}{}", indent, text)));
+    }
+
+    /// 末尾の空行を詰めてから、トップレベルに行を追加する。
+    fn append(&mut self, texts: Vec<String>) {
+        while self.lines.last().is_some_and(|l| l.text.is_empty()) {
+            self.lines.pop();
+        }
+        self.lines.extend(texts.into_iter().map(Line::new));
+        self.trailing_newline = true;
+    }
+}
+
+/// 新しく追加する `gem` 行
+fn gem_line(name: &str, version: Option<&str>) -> String {
+    match version {
+        Some(v) => format!("gem '{}', '{}'", name, v),
+        None => format!("gem '{}'", name),
+    }
+}
+
+fn indentation(text: &str) -> &str {
+    &text[..text.len() - text.trim_start().len()]
+}
+
+/// 1 行の種別を判定する。ブロックの判定は `parse_content` と同じ規則に従う。
+fn classify(text: &str) -> LineKind {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return LineKind::Blank;
+    }
+    if trimmed.starts_with('#') {
+        return LineKind::Comment;
+    }
+    if trimmed == "end" {
+        return LineKind::BlockEnd;
+    }
+    if trimmed.ends_with(" do") || trimmed.contains(" do |") {
+        return match trimmed.strip_prefix("group ") {
+            Some(rest) => LineKind::GroupStart(parse_group_names(rest.trim_end_matches(" do"))),
+            None => LineKind::BlockStart,
+        };
+    }
+    if let Some(span) = gem_span(text) {
+        return LineKind::Gem(span);
+    }
+    if trimmed.starts_with("source ") {
+        return LineKind::Source;
+    }
+    LineKind::Other
+}
+
+/// `gem 'name', '~> 1.0', require: false  # comment` の各部分の位置を求める。
+/// バージョン指定は名前に続く (キーワード引数より前の) 文字列リテラルとみなす。
+fn gem_span(text: &str) -> Option<GemSpan> {
+    fn skip_ws(text: &str, pos: usize) -> usize {
+        pos + (text[pos..].len() - text[pos..].trim_start().len())
+    }
+    fn quote_at(text: &str, pos: usize) -> Option<char> {
+        text[pos..].chars().next().filter(|c| *c == '\'' || *c == '"')
+    }
+
+    let rest = text.trim_start();
+    let after = rest.strip_prefix("gem ").or_else(|| rest.strip_prefix("gem("))?;
+    let pos = skip_ws(text, text.len() - after.len());
+    let quote = quote_at(text, pos)?;
+    let name_start = pos + 1;
+    let name_end = name_start + text[name_start..].find(quote)?;
+
+    let versions_start = name_end + 1;
+    let mut end = versions_start;
+    loop {
+        let comma = skip_ws(text, end);
+        if !text[comma..].starts_with(',') {
+            break;
+        }
+        let arg = skip_ws(text, comma + 1);
+        let Some(q) = quote_at(text, arg) else { break };
+        let Some(close) = text[arg + 1..].find(q) else { break };
+        end = arg + 1 + close + 1;
+    }
+    Some(GemSpan { name: name_start..name_end, versions: versions_start..end, quote })
+}
+
+// ─────────────────────────────────────────────
+// 構文の検査 (arc gemfile check)
+// ─────────────────────────────────────────────
+//
+// bundler に渡す前に、よくある書き間違いを行単位で見つける。Ruby のパーサーではないため、
+// 確実に誤りと言えるものだけを報告し、判断できない行 (式展開を含む文字列など) は検査しない。
+
+/// 問題の重さ。`Error` は操作を止め、`Warning` は表示だけする
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// `validate` が見つけた問題
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationIssue {
+    /// 1 始まりの行番号
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+    /// 問題のある行 (前後の空白を除く)
+    pub text: String,
+}
+
+/// ブロックを開く行の先頭のキーワード (`gem 'x' if y` のような後置は含まない)
+const BLOCK_KEYWORDS: [&str; 9] = ["if", "unless", "case", "while", "until", "begin", "def", "class", "module"];
+/// 同じブロックの中で別の枝を始めるキーワード
+const BRANCH_KEYWORDS: [&str; 6] = ["else", "elsif", "when", "in", "rescue", "ensure"];
+
+/// 1 行のうちコメントを除いたコード部分と、閉じていない引用符 (あれば)。
+fn split_code(line: &str) -> (&str, Option<char>) {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '#' => return (&line[..i], None),
+            None => {}
+        }
+    }
+    (line, quote)
+}
+
+fn first_word(code: &str) -> &str {
+    code.split(|c: char| !(c.is_alphanumeric() || c == '_')).next().unwrap_or("")
+}
+
+/// `do` ブロックを開く行か (`group :test do`、`git_source(:github) do |repo|`)
+fn opens_do_block(code: &str) -> bool {
+    let code = match code.strip_suffix('|') {
+        // `do |repo|` のブロック引数を除く
+        Some(rest) => match rest.rfind('|') {
+            Some(open) => rest[..open].trim_end(),
+            None => return false,
+        },
+        None => code,
+    };
+    code == "do" || code.ends_with(" do") || code.ends_with(")do")
+}
+
+/// Gem 名に使える文字か (RubyGems の規則)
+fn valid_gem_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// `'~> 1.0'`・`'>= 7.1.3.2'`・`'1.0.0.rc1'` のようなバージョン指定に見えるか
+fn looks_like_requirement(spec: &str) -> bool {
+    let spec = spec.trim();
+    let version = ["~>", ">=", "<=", "!=", "=", ">", "<"]
+        .iter()
+        .find_map(|op| spec.strip_prefix(op))
+        .unwrap_or(spec)
+        .trim_start();
+    let (release, pre) = match version.split_once('-') {
+        Some((release, pre)) => (release, Some(pre)),
+        None => (version, None),
     };
+    let mut segments = release.split('.');
+    let starts_with_number = segments.next().is_some_and(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()));
+    starts_with_number
+        && segments.all(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric()))
+        && pre.is_none_or(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+}
 
-    // 行単位の重複チェック（部分一致を防ぐ）
-    if parse_content(&content).iter().any(|e| e.name == gem_name) {
-        return Ok(false); // 既存
+/// クォートされた文字列を順に取り出す (`'a', "b"` → `["a", "b"]`)
+fn quoted_strings(s: &str) -> Vec<&str> {
+    let mut strings = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find(['\'', '"']) {
+        let quote = rest[start..].chars().next().unwrap_or('"');
+        let inner = &rest[start + 1..];
+        let Some(end) = inner.find(quote) else { break };
+        strings.push(&inner[..end]);
+        rest = &inner[end + 1..];
     }
+    strings
+}
 
-    let new_line = match version {
-        Some(v) => format!("gem '{}', '{}'\n", gem_name, v),
-        None    => format!("gem '{}'\n", gem_name),
+/// Gemfile の内容を検査する。見つけた問題を行番号の順に返す。
+pub fn validate(content: &str) -> Vec<ValidationIssue> {
+    struct Block {
+        line: usize,
+        text: String,
+        scope: usize,
+    }
+    let mut issues = Vec::new();
+    let mut issue = |line: usize, severity, message: String, text: &str| {
+        issues.push(ValidationIssue { line, severity, message, text: text.trim().to_string() });
     };
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut next_scope = 1;
+    // (scope, Gem 名) → (行番号, バージョン指定)
+    let mut declared: std::collections::HashMap<(usize, String), (usize, String)> = Default::default();
+
+    for (i, text) in content.lines().enumerate() {
+        let line = i + 1;
+        let (code, unclosed) = split_code(text);
+        let code = code.trim();
+        let scope = blocks.last().map_or(0, |b| b.scope);
+        let word = first_word(code);
+        let is_gem = code.starts_with("gem ") || code.starts_with("gem(");
+
+        if let Some(quote) = unclosed
+            && (is_gem || word == "source")
+        {
+            issue(line, Severity::Error, format!("Unterminated string: missing closing {}", quote), text);
+            continue;
+        }
+
+        if word == "end" {
+            if blocks.pop().is_none() {
+                issue(line, Severity::Error, "`end` without a matching block".to_string(), text);
+            }
+            continue;
+        }
+        if BRANCH_KEYWORDS.contains(&word) && let Some(block) = blocks.last_mut() {
+            block.scope = next_scope;
+            next_scope += 1;
+            continue;
+        }
+        let one_line = code.ends_with(" end") || code.contains("; end");
+        if (opens_do_block(code) || BLOCK_KEYWORDS.contains(&word)) && !one_line {
+            blocks.push(Block { line, text: code.to_string(), scope: next_scope });
+            next_scope += 1;
+            continue;
+        }
+
+        if !is_gem {
+            continue;
+        }
+        let Some(span) = gem_span(text) else { continue };
+        let name = &text[span.name.clone()];
+        if name.contains("#{") {
+            continue;
+        }
+        if !valid_gem_name(name) {
+            issue(line, Severity::Error, format!("Invalid gem name '{}': use letters, digits, '.', '_' or '-'", name), text);
+            continue;
+        }
+        let specs = quoted_strings(&text[span.versions.clone()]);
+        for spec in specs.iter().filter(|s| !s.contains("#{") && !looks_like_requirement(s)) {
+            issue(line, Severity::Error, format!("'{}' does not look like a version requirement (e.g. '~> 1.2')", spec), text);
+        }
+        let requirement = specs.join(", ");
+        match declared.get(&(scope, name.to_string())) {
+            Some((first, previous)) if *previous != requirement => issue(
+                line,
+                Severity::Error,
+                format!("'{}' is declared again with a different requirement (first on line {})", name, first),
+                text,
+            ),
+            Some((first, _)) => issue(line, Severity::Warning, format!("'{}' is already declared on line {}", name, first), text),
+            None => {
+                declared.insert((scope, name.to_string()), (line, requirement));
+            }
+        }
+    }
+
+    for block in blocks {
+        issue(block.line, Severity::Error, "Block is never closed with `end`".to_string(), &block.text);
+    }
+    issues.sort_by_key(|i| i.line);
+    issues
+}
+
+// ─────────────────────────────────────────────
+// 整形 (arc gemfile sort)
+// ─────────────────────────────────────────────
+
+impl GemfileDoc {
+    /// スコープ (トップレベル・各ブロック・`if` の各枝) ごとに `gem` 行を名前順に並べ、
+    /// `gem` 行の引用符を `quote` に、カンマの前後の空白を `, ` にそろえる。
+    ///
+    /// `gem` 行の直前に続くコメント行と、末尾のカンマで続く行はその `gem` 行と一緒に動く。
+    /// 並べ替えは `gem` 行が占めていた位置の間で行うため、それ以外の行は動かず、スコープもまたがない。
+    pub fn sorted(&self, quote: char) -> GemfileDoc {
+        let lines: Vec<Line> = self
+            .lines
+            .iter()
+            .map(|l| match l.kind {
+                LineKind::Gem(_) => Line::new(normalize_gem_line(&l.text, quote)),
+                _ => l.clone(),
+            })
+            .collect();
+        let scopes = line_scopes(&lines);
 
-    let new_content = format!("{}\n{}", content.trim_end_matches('\n'), new_line);
-    std::fs::write(gemfile, new_content)
-        .with_context(|| format!("Gemfile の書き込みに失敗しました: {:?}", gemfile))?;
+        // スコープ → そのスコープの gem (コメント・継続行を含む行の範囲) の出現順
+        let mut units: BTreeMap<usize, Vec<Range<usize>>> = BTreeMap::new();
+        let mut i = 0;
+        while i < lines.len() {
+            if !matches!(lines[i].kind, LineKind::Gem(_)) {
+                i += 1;
+                continue;
+            }
+            let mut start = i;
+            while start > 0 && lines[start - 1].kind == LineKind::Comment && scopes[start - 1] == scopes[i] {
+                start -= 1;
+            }
+            let mut end = i + 1;
+            while end < lines.len() && continues(&lines[end - 1].text) {
+                end += 1;
+            }
+            units.entry(scopes[i]).or_default().push(start..end);
+            i = end;
+        }
 
-    Ok(true) // 追加した
+        // 位置 (範囲の先頭) → (位置の終わり, そこに置く gem)
+        let mut placed: HashMap<usize, (usize, Range<usize>)> = HashMap::new();
+        for slots in units.values() {
+            let mut sorted = slots.clone();
+            sorted.sort_by_cached_key(|unit| {
+                let name = lines[unit.clone()].iter().find_map(Line::gem_name).unwrap_or_default();
+                (name.to_lowercase(), name.to_string())
+            });
+            for (slot, unit) in slots.iter().zip(sorted) {
+                placed.insert(slot.start, (slot.end, unit));
+            }
+        }
+        let mut out = Vec::with_capacity(lines.len());
+        let mut i = 0;
+        while i < lines.len() {
+            match placed.get(&i) {
+                Some((end, unit)) => {
+                    out.extend(lines[unit.clone()].iter().cloned());
+                    i = *end;
+                }
+                None => {
+                    out.push(lines[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        GemfileDoc { lines: out, trailing_newline: self.trailing_newline }
+    }
 }
 
-/// Gemfile から Gem を削除する。削除できた場合は `true` を返す。
-pub fn remove_gem(gemfile: &Path, gem_name: &str) -> Result<bool> {
-    let content = std::fs::read_to_string(gemfile)
-        .with_context(|| format!("Gemfile の読み込みに失敗しました: {:?}", gemfile))?;
+/// 各行が属するスコープの番号 (0 はトップレベル)。ブロックの判定は `validate` と同じ規則に従う。
+/// ブロックを開く行と `end` は外側のスコープに属し、`else` などの枝は新しいスコープを始める。
+fn line_scopes(lines: &[Line]) -> Vec<usize> {
+    let mut stack: Vec<usize> = Vec::new();
+    let mut next_scope = 1;
+    let mut scopes = Vec::with_capacity(lines.len());
+    for line in lines {
+        let code = split_code(&line.text).0.trim();
+        let word = first_word(code);
+        let one_line = code.ends_with(" end") || code.contains("; end");
+        if word == "end" {
+            stack.pop();
+            scopes.push(stack.last().copied().unwrap_or(0));
+        } else if BRANCH_KEYWORDS.contains(&word) && let Some(scope) = stack.last_mut() {
+            *scope = next_scope;
+            next_scope += 1;
+            scopes.push(*scope);
+        } else if (opens_do_block(code) || BLOCK_KEYWORDS.contains(&word)) && !one_line {
+            scopes.push(stack.last().copied().unwrap_or(0));
+            stack.push(next_scope);
+            next_scope += 1;
+        } else {
+            scopes.push(stack.last().copied().unwrap_or(0));
+        }
+    }
+    scopes
+}
+
+/// 末尾のカンマ (またはバックスラッシュ) で次の行に続くか
+fn continues(text: &str) -> bool {
+    let code = split_code(text).0.trim_end();
+    code.ends_with(',') || code.ends_with('\\')
+}
 
-    let mut removed = false;
-    let new_lines: Vec<&str> = content
-        .lines()
-        .filter(|line| {
-            if let Some(entry) = parse_gem_line(line)
-                && entry.name == gem_name {
-                    removed = true;
-                    return false; // この行を除外
+/// 開き引用符の直後の `s` で、閉じ引用符 `quote` の位置 (バックスラッシュでエスケープされたものは除く)
+fn closing_quote(s: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `gem` 行の文字列リテラルの引用符を `quote` に、カンマの前後の空白を `, ` にそろえる。
+/// インデント・コメント・行末の `\r` はそのまま。エスケープや `#` を含む文字列は変えない (意味が変わりうるため)。
+fn normalize_gem_line(text: &str, quote: char) -> String {
+    let (body, cr) = match text.strip_suffix('\r') {
+        Some(body) => (body, "\r"),
+        None => (text, ""),
+    };
+    let indent = indentation(body);
+    let mut out = String::from(indent);
+    let mut rest = &body[indent.len()..];
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\'' | '"' => {
+                let Some(close) = closing_quote(&rest[1..], c) else {
+                    out.push_str(rest);
+                    break;
+                };
+                let inner = &rest[1..1 + close];
+                if inner.contains(['\'', '"', '\\', '#']) {
+                    out.push_str(&rest[..close + 2]);
+                } else {
+                    out.push(quote);
+                    out.push_str(inner);
+                    out.push(quote);
                 }
-            true
-        })
-        .collect();
+                rest = &rest[close + 2..];
+            }
+            ',' => {
+                out.truncate(out.trim_end().len());
+                rest = rest[1..].trim_start();
+                out.push_str(if rest.is_empty() { "," } else { ", " });
+            }
+            '#' => {
+                out.push_str(rest);
+                break;
+            }
+            _ => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    out.push_str(cr);
+    out
+}
+
+// ─────────────────────────────────────────────
+// dry-run 用の差分
+// ─────────────────────────────────────────────
+
+/// 差分の前後に表示する行数
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// `before` → `after` の unified diff。差分がなければ空文字列。
+pub fn unified_diff(label: &str, before: &str, after: &str) -> String {
+    let a: Vec<&str> = before.lines().collect();
+    let b: Vec<&str> = after.lines().collect();
+    let ops = diff_ops(&a, &b);
+    let changes: Vec<usize> = (0..ops.len()).filter(|&i| ops[i].0 != DiffOp::Equal).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // 各 op の手前までに消費した (旧, 新) の行数
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut old, mut new) = (0, 0);
+    for (op, _) in &ops {
+        positions.push((old, new));
+        match op {
+            DiffOp::Equal => { old += 1; new += 1; }
+            DiffOp::Delete => old += 1,
+            DiffOp::Insert => new += 1,
+        }
+    }
 
-    if removed {
-        // 末尾改行を保持
-        let mut new_content = new_lines.join("\n");
-        if !new_content.ends_with('\n') {
-            new_content.push('\n');
+    // 近い変更を 1 つのハンクにまとめる
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &c in &changes {
+        let start = c.saturating_sub(DIFF_CONTEXT);
+        let end = (c + 1 + DIFF_CONTEXT).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
         }
-        std::fs::write(gemfile, new_content)
-            .with_context(|| format!("Gemfile の書き込みに失敗しました: {:?}", gemfile))?;
     }
 
-    Ok(removed)
+    let range = |start: usize, count: usize| {
+        if count == 0 { format!("{},0", start) } else { format!("{},{}", start + 1, count) }
+    };
+    let mut out = format!("--- a/{}\n+++ b/{}\n", label, label);
+    for (start, end) in hunks {
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|(op, _)| *op != DiffOp::Insert).count();
+        let new_count = hunk.iter().filter(|(op, _)| *op != DiffOp::Delete).count();
+        let (old_start, new_start) = positions[start];
+        out.push_str(&format!("@@ -{} +{} @@\n", range(old_start, old_count), range(new_start, new_count)));
+        for (op, line) in hunk {
+            let sign = match op {
+                DiffOp::Equal => ' ',
+                DiffOp::Delete => '-',
+                DiffOp::Insert => '+',
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// 最長共通部分列による行単位の差分 (Gemfile は小さいので O(nm) で十分)。
+fn diff_ops<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(DiffOp, &'a str)> {
+    let (n, m) = (a.len(), b.len());
+    // lcs[i][j] = a[i..] と b[j..] の最長共通部分列の長さ
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a[i] == b[j] {
+            ops.push((DiffOp::Equal, a[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push((DiffOp::Delete, a[i]));
+            i += 1;
+        } else {
+            ops.push((DiffOp::Insert, b[j]));
+            j += 1;
+        }
+    }
+    ops
 }
 
 // ─────────────────────────────────────────────
@@ -216,6 +983,24 @@ mod tests {
         assert!(!gems.iter().any(|e| e.name == "json"));
     }
 
+    #[test]
+    fn test_parse_groups() {
+        let content = "\
+gem 'rails'
+group :development, :test do
+  gem 'rspec'
+end
+platforms :jruby do
+  gem 'jdbc'
+end
+";
+        let gems = parse_content(content);
+        assert_eq!(gems.len(), 3);
+        assert!(gems[0].group.is_none());
+        assert_eq!(gems[1].group.as_deref(), Some("development, test"));
+        assert!(gems[2].group.is_none());
+    }
+
     #[test]
     fn test_skip_comments() {
         let content = "# gem 'commented_out'\ngem 'active'\n";
@@ -223,4 +1008,264 @@ mod tests {
         assert_eq!(gems.len(), 1);
         assert_eq!(gems[0].name, "active");
     }
+
+    // ── 構造化エディタ ──
+
+    const RAILS_APP: &str = include_str!("testdata/gemfiles/rails_app.Gemfile");
+    const MESSY: &str = include_str!("testdata/gemfiles/messy.Gemfile");
+    const LIBRARY: &str = include_str!("testdata/gemfiles/library.Gemfile");
+    const CONDITIONAL: &str = include_str!("testdata/gemfiles/conditional.Gemfile");
+
+    /// 行モデル導入前の `add_gem` (文字列操作) をそのまま残したもの
+    fn legacy_add(content: &str, gem_name: &str, version: Option<&str>) -> Option<String> {
+        if parse_content(content).iter().any(|e| e.name == gem_name) {
+            return None;
+        }
+        let new_line = match version {
+            Some(v) => format!("gem '{}', '{}'\n", gem_name, v),
+            None    => format!("gem '{}'\n", gem_name),
+        };
+        Some(format!("{}\n{}", content.trim_end_matches('\n'), new_line))
+    }
+
+    /// 行モデル導入前の `remove_gem`
+    fn legacy_remove(content: &str, gem_name: &str) -> Option<String> {
+        let mut removed = false;
+        let new_lines: Vec<&str> = content
+            .lines()
+            .filter(|line| {
+                if let Some(entry) = parse_gem_line(line)
+                    && entry.name == gem_name {
+                        removed = true;
+                        return false;
+                    }
+                true
+            })
+            .collect();
+        removed.then(|| {
+            let mut new_content = new_lines.join("\n");
+            if !new_content.ends_with('\n') {
+                new_content.push('\n');
+            }
+            new_content
+        })
+    }
+
+    fn edited(content: &str, edit: Edit) -> Option<String> {
+        let mut doc = GemfileDoc::parse(content);
+        doc.apply(&edit).then(|| doc.render())
+    }
+
+    #[test]
+    fn test_editor_matches_legacy_on_corpus() {
+        let corpus = [
+            SCAFFOLD,
+            "source 'https://rubygems.org'\ngem 'json'\n",
+            "gem 'rails', '~> 7.0'\n",
+            "gem \"json\"\n",
+            "gem 'json-rails'\n",
+            "gem 'rails'\ngroup :development, :test do\n  gem 'rspec'\nend\nplatforms :jruby do\n  gem 'jdbc'\nend\n",
+            "# gem 'commented_out'\ngem 'active'\n",
+            "source 'https://rubygems.org'\n\ngem 'rake'\n\n\n",
+            "source 'https://rubygems.org'\ngem 'rake'",
+            RAILS_APP,
+            MESSY,
+        ];
+        for content in corpus {
+            let mut names: Vec<String> = parse_content(content).into_iter().map(|e| e.name).collect();
+            names.extend(["json".to_string(), "zeitwerk".to_string()]);
+            for name in &names {
+                for version in [None, Some("~> 2.6")] {
+                    let add = Edit::InsertGem { name: name.clone(), version: version.map(String::from), group: None };
+                    assert_eq!(edited(content, add), legacy_add(content, name, version), "add {} to {:?}", name, content);
+                }
+                // 旧実装は末尾の改行を正規化していたため、単一の改行で終わるファイルだけを比べる
+                if content.ends_with('\n') && !content.ends_with("\n\n") {
+                    let remove = Edit::RemoveGem { name: name.clone() };
+                    assert_eq!(edited(content, remove), legacy_remove(content, name), "remove {} from {:?}", name, content);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_untouched_bytes_are_preserved() {
+        // CRLF・末尾の改行なし・末尾の空行は旧実装では書き換わっていた
+        let crlf = "source 'https://rubygems.org'\r\ngem 'a'\r\ngem 'b'";
+        assert_eq!(edited(crlf, Edit::RemoveGem { name: "a".into() }).unwrap(), "source 'https://rubygems.org'\r\ngem 'b'");
+        let blank_tail = "gem 'a'\ngem 'b'\n\n";
+        assert_eq!(edited(blank_tail, Edit::RemoveGem { name: "a".into() }).unwrap(), "gem 'b'\n\n");
+        assert_eq!(GemfileDoc::parse(MESSY).render(), MESSY);
+        assert_eq!(GemfileDoc::parse("").render(), "");
+    }
+
+    #[test]
+    fn test_gem_span() {
+        let line = "  gem 'nokogiri', '>= 1.15', '< 2.0', require: false   # pinned";
+        let span = gem_span(line).unwrap();
+        assert_eq!(&line[span.name.clone()], "nokogiri");
+        assert_eq!(&line[span.versions.clone()], ", '>= 1.15', '< 2.0'");
+        assert_eq!(span.quote, '\'');
+        let line = "gem(\"json\")";
+        let span = gem_span(line).unwrap();
+        assert_eq!(&line[span.name], "json");
+        assert!(span.versions.is_empty());
+        assert!(gem_span("gemspec").is_none());
+        assert_eq!(classify("group :development, :test do"), LineKind::GroupStart("development, test".into()));
+        assert!(matches!(classify("\tgem 'x' # note"), LineKind::Gem(_)));
+    }
+
+    #[test]
+    fn test_set_version_keeps_options_and_comments() {
+        let set = |name: &str, version: Option<&str>| {
+            edited(MESSY, Edit::SetVersion { name: name.into(), version: version.map(String::from) }).unwrap()
+        };
+        assert!(set("nokogiri", Some("~> 1.16")).contains("gem 'nokogiri', '~> 1.16', require: false   #   pinned for CVE\n"));
+        assert!(set("json", Some("~> 2.8")).contains("gem(\"json\", \"~> 2.8\")\n"));
+        assert!(set("rack", None).contains("gem 'rack' # trailing comment"));
+        assert!(set("oddly-indented", Some("1.0")).contains("\n    gem 'oddly-indented', '1.0'\n"));
+        // 変更のない行は 1 行も変わらない
+        let diff = unified_diff("Gemfile", MESSY, &set("rack", Some(">= 3.0")));
+        assert_eq!(diff.lines().filter(|l| l.starts_with(['+', '-']) && !l.starts_with("---") && !l.starts_with("+++")).count(), 2);
+        assert_eq!(edited(MESSY, Edit::SetVersion { name: "json".into(), version: Some("~> 2.7".into()) }), None);
+    }
+
+    #[test]
+    fn test_insert_and_move_between_groups() {
+        let inserted = edited(
+            MESSY,
+            Edit::InsertGem { name: "pry".into(), version: None, group: Some("development, test".into()) },
+        )
+        .unwrap();
+        assert!(inserted.contains("    gem 'simplecov', require: false\n  end\n    gem 'pry'\nend\n"));
+        assert_eq!(parse_content(&inserted).iter().find(|e| e.name == "pry").unwrap().group.as_deref(), Some("development, test"));
+
+        let moved = edited(RAILS_APP, Edit::MoveToGroup { name: "bootsnap".into(), group: Some("development".into()) }).unwrap();
+        assert!(!moved.contains("\ngem \"bootsnap\""));
+        assert!(moved.contains("  gem \"web-console\"\n  gem \"bootsnap\", require: false\nend\n"));
+        assert_eq!(edited(&moved, Edit::MoveToGroup { name: "bootsnap".into(), group: Some("development".into()) }), None);
+
+        // 存在しないグループは末尾にブロックを作る
+        let created = edited(SCAFFOLD, Edit::InsertGem { name: "rack".into(), version: None, group: Some("production".into()) }).unwrap();
+        assert_eq!(created, "source 'https://rubygems.org'\n\ngroup :production do\n  gem 'rack'\nend\n");
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let before = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+        let after = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        assert_eq!(
+            unified_diff("Gemfile", before, after),
+            "--- a/Gemfile\n+++ b/Gemfile\n@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n@@ -7,3 +7,4 @@\n g\n h\n i\n+j\n"
+        );
+        assert_eq!(unified_diff("Gemfile", before, before), "");
+        assert_eq!(unified_diff("Gemfile", "", "x\n"), "--- a/Gemfile\n+++ b/Gemfile\n@@ -0,0 +1,1 @@\n+x\n");
+    }
+
+    fn issues(content: &str) -> Vec<(usize, Severity, String)> {
+        validate(content).into_iter().map(|i| (i.line, i.severity, i.message)).collect()
+    }
+
+    #[test]
+    fn test_validate_accepts_real_gemfiles() {
+        for (name, content) in [("rails_app", RAILS_APP), ("messy", MESSY), ("library", LIBRARY), ("conditional", CONDITIONAL)] {
+            assert_eq!(validate(content), vec![], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_validate_unterminated_string() {
+        let found = issues("source 'https://rubygems.org'\ngem 'rails\ngem \"puma\", '>= 5.0'  # it's fine\n");
+        assert_eq!(found, vec![(2, Severity::Error, "Unterminated string: missing closing '".to_string())]);
+        assert_eq!(validate("source \"https://rubygems.org\n")[0].text, "source \"https://rubygems.org");
+    }
+
+    #[test]
+    fn test_validate_unbalanced_blocks() {
+        let found = issues("group :test do\n  gem 'rspec'\n\ngem 'rake'\n");
+        assert_eq!(found, vec![(1, Severity::Error, "Block is never closed with `end`".to_string())]);
+        let found = issues("gem 'rake'\nend\n");
+        assert_eq!(found, vec![(2, Severity::Error, "`end` without a matching block".to_string())]);
+        // 1 行で閉じる if / ブロック引数付きの do
+        assert!(validate("if x then gem 'a' end\nfoo.each do |g|\n  gem g\nend\n").is_empty());
+    }
+
+    #[test]
+    fn test_validate_duplicates_per_scope() {
+        let found = issues("gem 'rack'\ngem 'rack'\ngem 'pg', '~> 1.1'\ngem 'pg', '~> 1.5'\n");
+        assert_eq!(found[0], (2, Severity::Warning, "'rack' is already declared on line 1".to_string()));
+        assert_eq!(found[1].0, 4);
+        assert_eq!(found[1].1, Severity::Error);
+        // 別のブロックや if / else の枝なら重複ではない
+        assert!(validate("gem 'debug'\ngroup :test do\n  gem 'debug'\nend\nif a\n  gem 'x', '1.0'\nelse\n  gem 'x', '2.0'\nend\n").is_empty());
+    }
+
+    #[test]
+    fn test_validate_names_and_requirements() {
+        let found = issues("gem 'rails!'\ngem 'pg', 'latest'\ngem 'puma', '~> 6', '>= 6.4.2', '1.0-rc.1'\ngem \"x-#{v}\", \"~> #{v}\"\n");
+        assert_eq!(
+            found,
+            vec![
+                (1, Severity::Error, "Invalid gem name 'rails!': use letters, digits, '.', '_' or '-'".to_string()),
+                (2, Severity::Error, "'latest' does not look like a version requirement (e.g. '~> 1.2')".to_string()),
+            ]
+        );
+        assert!(looks_like_requirement("= 1.2.3.beta") && looks_like_requirement("!=2.0"));
+        assert!(!looks_like_requirement("~>") && !looks_like_requirement("1..2") && !looks_like_requirement(">= v1"));
+    }
+
+    // ── 整形 ──
+
+    fn sorted(content: &str, quote: char) -> String {
+        GemfileDoc::parse(content).sorted(quote).render()
+    }
+
+    #[test]
+    fn test_sort_fixtures() {
+        assert_eq!(sorted(MESSY, '\''), include_str!("testdata/gemfiles/messy.sorted.Gemfile"));
+        assert_eq!(sorted(RAILS_APP, '"'), include_str!("testdata/gemfiles/rails_app.sorted.Gemfile"));
+        // if/else・case の各枝とブロックの中身は、それぞれの中でだけ並べ替える
+        let conditional = sorted(CONDITIONAL, '\'');
+        assert!(conditional.contains("if rails_version == 'main'\n  gem 'rails', github: 'rails/rails', branch: 'main'\nelse\n"));
+        assert!(conditional.contains("when /darwin/\n  gem 'rb-fsevent'"));
+        assert!(conditional.contains("\ngem 'mysql2' if ENV['DB'] == 'mysql'\ngem 'nokogiri', '~> 1.16'"));
+        assert!(conditional.contains("%w[rspec-core rspec-expectations rspec-mocks].each do |lib|\n    gem lib, '~> 3.13'\n"));
+    }
+
+    #[test]
+    fn test_sort_keeps_comments_and_continuations() {
+        let content = "source 'https://rubygems.org'\n\n# Web server\n# (pinned)\ngem 'puma'\ngem 'bootsnap',\n  require: false\nruby '3.3.0'\ngem 'Aws-sdk'\n";
+        assert_eq!(
+            sorted(content, '\''),
+            "source 'https://rubygems.org'\n\ngem 'Aws-sdk'\ngem 'bootsnap',\n  require: false\nruby '3.3.0'\n# Web server\n# (pinned)\ngem 'puma'\n"
+        );
+    }
+
+    #[test]
+    fn test_normalize_gem_line() {
+        let cases = [
+            ("gem \"rack\" ,'>= 2.2' ,  require:false", '\'', "gem 'rack', '>= 2.2', require:false"),
+            ("  gem 'rack', \"~> 2.0\" # \"keep\" ,  this", '"', "  gem \"rack\", \"~> 2.0\" # \"keep\" ,  this"),
+            // エスケープ・式展開・引用符を含む文字列は変えない
+            ("gem \"x\", \"~> #{v}\", path: \"a\\\"b\"", '\'', "gem 'x', \"~> #{v}\", path: \"a\\\"b\""),
+            ("gem 'a\\'b', 'it\"s'", '"', "gem 'a\\'b', 'it\"s'"),
+            ("gem 'bootsnap',", '"', "gem \"bootsnap\","),
+            ("gem 'rack'\r", '"', "gem \"rack\"\r"),
+        ];
+        for (line, quote, expected) in cases {
+            assert_eq!(normalize_gem_line(line, quote), expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_sort_is_idempotent() {
+        for content in [RAILS_APP, MESSY, LIBRARY, CONDITIONAL] {
+            for quote in ['\'', '"'] {
+                let once = sorted(content, quote);
+                assert_eq!(sorted(&once, quote), once);
+                // gem の行数は変わらない
+                assert_eq!(parse_content(&once).len(), parse_content(content).len());
+            }
+        }
+    }
 }
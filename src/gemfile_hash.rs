@@ -0,0 +1,114 @@
+//! Gemfile の内容ハッシュによる、arc を通さない編集の検出。
+//!
+//! Gemfile を読み書きするコマンドは、操作後の Gemfile の SHA-256 を Signal の payload
+//! (`gemfile_sha256`) に記録する。次に Gemfile を変更するとき、現在のハッシュが
+//! 最後に記録されたハッシュと異なれば、その間に arc の外で編集されたとみなす。
+
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::blobs::sha256_hex;
+use crate::signals::Signal;
+
+/// payload に記録するフィールド名
+pub const PAYLOAD_KEY: &str = "gemfile_sha256";
+/// 外部編集を検出したときに立てるフィールド名
+pub const EXTERNAL_EDIT_KEY: &str = "external_edit_detected";
+
+/// Gemfile の SHA-256。ファイルがなければ `None`。
+pub fn hash(gemfile_path: &Path) -> Option<String> {
+    fs::read(gemfile_path).ok().map(|content| sha256_hex(&content))
+}
+
+/// 最後に Gemfile のハッシュを記録した Signal。
+pub fn last_recorded(signals: &[Signal]) -> Option<&Signal> {
+    signals.iter().rev().find(|s| s.payload[PAYLOAD_KEY].is_string())
+}
+
+/// 最後に記録されたハッシュと現在の Gemfile が異なれば、その記録の時刻を返す。
+/// 記録がない (古いログ) 場合は判断できないため `None`。
+pub fn modified_since(signals: &[Signal], gemfile_path: &Path) -> Option<String> {
+    let last = last_recorded(signals)?;
+    let current = hash(gemfile_path);
+    (last.payload[PAYLOAD_KEY].as_str() != current.as_deref()).then(|| last.timestamp.clone())
+}
+
+/// Gemfile を変更する前に呼ぶ。外部編集を検出したら警告を出し、`true` を返す。
+pub fn warn_if_modified(signals: &[Signal], gemfile_path: &Path) -> bool {
+    match modified_since(signals, gemfile_path) {
+        Some(since) => {
+            eprintln!("⚠️  Gemfile was modified outside arc since {}", since);
+            true
+        }
+        None => false,
+    }
+}
+
+/// payload に現在の Gemfile のハッシュを加える。`external_edit` なら検出フラグも加える。
+pub fn stamp(payload: &mut Value, gemfile_path: &Path, external_edit: bool) {
+    stamp_hash(payload, hash(gemfile_path), external_edit);
+}
+
+/// `stamp` と同じだが、書き込む前の Gemfile のハッシュを使う (intent に記録する payload)。
+pub fn stamp_hash(payload: &mut Value, hash: Option<String>, external_edit: bool) {
+    let Some(fields) = payload.as_object_mut() else { return };
+    if let Some(hash) = hash {
+        fields.insert(PAYLOAD_KEY.to_string(), Value::String(hash));
+    }
+    if external_edit {
+        fields.insert(EXTERNAL_EDIT_KEY.to_string(), Value::Bool(true));
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn signal(payload: Value, timestamp: &str) -> Signal {
+        Signal {
+            id: "id".to_string(),
+            r_type: "add".to_string(),
+            payload,
+            timestamp: timestamp.to_string(),
+            meta: None,
+            v: 2,
+        }
+    }
+
+    #[test]
+    fn test_modified_since() {
+        let dir = std::env::temp_dir().join("arc_gemfile_hash_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let gemfile = dir.join("Gemfile");
+        fs::write(&gemfile, "gem 'rake'\n").unwrap();
+
+        let mut payload = json!({ "gem": "rake" });
+        stamp(&mut payload, &gemfile, false);
+        assert_eq!(payload[PAYLOAD_KEY], sha256_hex(b"gem 'rake'\n"));
+        assert!(payload.get(EXTERNAL_EDIT_KEY).is_none());
+
+        let signals = vec![
+            signal(json!({}), "2026-01-01T00:00:00Z"),
+            signal(payload, "2026-01-02T00:00:00Z"),
+            // ハッシュを持たない Signal は無視する
+            signal(json!({ "command": "ls" }), "2026-01-03T00:00:00Z"),
+        ];
+        assert_eq!(modified_since(&signals, &gemfile), None);
+
+        fs::write(&gemfile, "gem 'rake'\ngem 'rails'\n").unwrap();
+        assert_eq!(modified_since(&signals, &gemfile).as_deref(), Some("2026-01-02T00:00:00Z"));
+        // 削除も外部編集として扱う
+        fs::remove_file(&gemfile).unwrap();
+        assert!(modified_since(&signals, &gemfile).is_some());
+        // 記録がない古いログでは判断しない
+        assert_eq!(modified_since(&signals[..1], &gemfile), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
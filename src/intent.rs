@@ -0,0 +1,240 @@
+//! `.flux/intent.json` — Gemfile の書き換えと Signal の記録を 1 つの操作として扱うための先行記録 (write-ahead intent)。
+//!
+//! add / remove / undo は Gemfile を書き換える前に、これから記録する Signal と書き換え前後の Gemfile の SHA-256 を
+//! intent.json に書き、Signal を記録できたら消す。途中で arc が落ちると intent.json が残る:
+//!
+//! - 次に arc を実行したときに「前回の `arc add nokogiri` が完了していない可能性がある」と警告する
+//! - `arc doctor --resolve-intent` が Gemfile のハッシュを見て、Signal を記録して完了させるか破棄するかを決める
+//!
+//! Signal の ID は intent を書くときに採番しておき、記録済みかどうかは ID で判断する。
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::signals::{Signal, pid_alive};
+
+/// intent のファイル名 (`.flux/intent.json`)
+pub const INTENT_FILE: &str = "intent.json";
+
+/// Gemfile を書き換える前 (テストで落ちた状況を再現する位置)
+pub const BEFORE_EDIT: &str = "before-edit";
+/// Gemfile を書き換えた後、Signal を記録する前
+pub const AFTER_EDIT: &str = "after-edit";
+
+/// 実行中の操作 (intent.json の中身)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Intent {
+    pub pid: u32,
+    /// 実行したコマンド (例: `arc add nokogiri`)
+    pub command: String,
+    /// 記録する Signal の ID (先に採番する)
+    pub signal_id: String,
+    pub signal_type: String,
+    pub payload: Value,
+    /// 書き換え前の Gemfile の SHA-256。Gemfile がなければ `None`
+    pub before_sha256: Option<String>,
+    /// 書き換え後の Gemfile の SHA-256
+    pub after_sha256: String,
+    /// RFC 3339
+    pub started_at: String,
+}
+
+impl Intent {
+    pub fn new(command: String, signal_id: String, signal_type: String, payload: Value, before_sha256: Option<String>, after_sha256: String) -> Self {
+        Self {
+            pid: std::process::id(),
+            command,
+            signal_id,
+            signal_type,
+            payload,
+            before_sha256,
+            after_sha256,
+            started_at: chrono::Local::now().to_rfc3339(),
+        }
+    }
+
+    /// 別の arc が実行中の操作か (完了していない操作ではない)
+    fn in_progress(&self) -> bool {
+        self.pid != std::process::id() && pid_alive(self.pid as libc::pid_t)
+    }
+}
+
+/// 残っていた intent の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Signal は記録済み (intent を消す前に落ちた)。intent を消すだけ
+    Recorded,
+    /// Gemfile は書き換え済み。Signal を記録して完了させる
+    Complete,
+    /// Gemfile は書き換え前のまま。破棄する
+    Discard,
+    /// Gemfile が書き換え前とも後とも一致しない (その後に編集された)。記録せずに破棄する
+    Unknown,
+}
+
+impl Resolution {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Resolution::Recorded => "the signal was already recorded; only the intent is left over",
+            Resolution::Complete => "the Gemfile was edited but the signal is missing; it will be recorded",
+            Resolution::Discard => "the Gemfile was not edited; the intent will be discarded",
+            Resolution::Unknown => "the Gemfile has changed since; the intent will be discarded without recording",
+        }
+    }
+}
+
+/// 残っていた intent をどう扱うかを、Signal ログと現在の Gemfile のハッシュから決める。
+pub fn resolve(intent: &Intent, signals: &[Signal], current_sha256: Option<&str>) -> Resolution {
+    if signals.iter().any(|s| s.id == intent.signal_id) {
+        Resolution::Recorded
+    } else if current_sha256 == Some(intent.after_sha256.as_str()) {
+        Resolution::Complete
+    } else if current_sha256 == intent.before_sha256.as_deref() {
+        Resolution::Discard
+    } else {
+        Resolution::Unknown
+    }
+}
+
+pub fn path(flux_dir: &Path) -> PathBuf {
+    flux_dir.join(INTENT_FILE)
+}
+
+/// 残っている intent を読む。なければ `None`。
+pub fn read(flux_dir: &Path) -> Result<Option<Intent>> {
+    let path = path(flux_dir);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("{:?} を読み込めません", path)),
+    };
+    serde_json::from_str(&content).map(Some).with_context(|| format!("{:?} の形式が不正です", path))
+}
+
+/// 書き込んだ intent。操作が完了したら `finish` で消す (drop では消さない)。
+#[derive(Debug)]
+#[must_use = "call finish() once the signal is recorded"]
+pub struct Pending {
+    path: PathBuf,
+}
+
+impl Pending {
+    pub fn finish(self) -> Result<()> {
+        fs::remove_file(&self.path).with_context(|| format!("{:?} を削除できません", self.path))
+    }
+}
+
+/// 操作を始める前に intent を書く。前の操作の intent が残っていれば上書きせずに失敗する。
+pub fn begin(flux_dir: &Path, intent: &Intent) -> Result<Pending> {
+    if let Some(previous) = read(flux_dir)? {
+        bail!(
+            "前回の `{}` が完了していない可能性があります ({} に記録が残っています)\n  `arc doctor --resolve-intent` で記録を完了させるか破棄してから、もう一度実行してください",
+            previous.command,
+            path(flux_dir).display()
+        );
+    }
+    let path = path(flux_dir);
+    let tmp = flux_dir.join(format!("{}.tmp", INTENT_FILE));
+    fs::write(&tmp, serde_json::to_vec(intent)?).with_context(|| format!("{:?} に書き込めません", tmp))?;
+    fs::rename(&tmp, &path).with_context(|| format!("{:?} に書き込めません", path))?;
+    Ok(Pending { path })
+}
+
+/// 完了させずに intent を消す (`arc doctor --resolve-intent`)。
+pub fn clear(flux_dir: &Path) -> Result<()> {
+    let path = path(flux_dir);
+    fs::remove_file(&path).with_context(|| format!("{:?} を削除できません", path))
+}
+
+/// 完了していない操作の intent が残っていれば警告する (起動時)。
+pub fn warn_if_pending(flux_dir: &Path) {
+    let Ok(Some(intent)) = read(flux_dir) else { return };
+    if intent.in_progress() {
+        return;
+    }
+    eprintln!(
+        "⚠️  A previous `{}` may not have completed (started {}). Run `arc doctor --resolve-intent`",
+        intent.command,
+        crate::display::fmt_timestamp(&intent.started_at)
+    );
+}
+
+#[cfg(test)]
+thread_local! {
+    static FAIL_AT: std::cell::Cell<Option<&'static str>> = const { std::cell::Cell::new(None) };
+}
+
+/// テスト用: 操作の途中 (`BEFORE_EDIT` / `AFTER_EDIT`) で落ちたことにする。
+#[cfg(test)]
+pub fn fail_at(point: Option<&'static str>) {
+    FAIL_AT.with(|f| f.set(point));
+}
+
+/// `point` で落ちるよう指定されていればエラーを返す (テスト以外では何もしない)。
+pub fn fail_point(point: &'static str) -> Result<()> {
+    #[cfg(test)]
+    if FAIL_AT.with(|f| f.get()) == Some(point) {
+        bail!("injected failure at {}", point);
+    }
+    let _ = point;
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn intent() -> Intent {
+        Intent::new(
+            "arc add rack".to_string(),
+            "sig-1".to_string(),
+            "add".to_string(),
+            json!({ "gem": "rack" }),
+            Some("before".to_string()),
+            "after".to_string(),
+        )
+    }
+
+    fn signal(id: &str) -> Signal {
+        Signal { id: id.to_string(), r_type: "add".to_string(), payload: json!({}), timestamp: String::new(), meta: None, v: 2 }
+    }
+
+    #[test]
+    fn test_resolve_by_gemfile_hash() {
+        let intent = intent();
+        assert_eq!(resolve(&intent, &[], Some("after")), Resolution::Complete);
+        assert_eq!(resolve(&intent, &[], Some("before")), Resolution::Discard);
+        assert_eq!(resolve(&intent, &[], Some("edited")), Resolution::Unknown);
+        assert_eq!(resolve(&intent, &[], None), Resolution::Unknown);
+        assert_eq!(resolve(&intent, &[signal("other"), signal("sig-1")], Some("edited")), Resolution::Recorded);
+
+        // Gemfile を新しく作る操作では、Gemfile がないことが「書き換え前」
+        let created = Intent { before_sha256: None, ..intent };
+        assert_eq!(resolve(&created, &[], None), Resolution::Discard);
+    }
+
+    #[test]
+    fn test_begin_refuses_to_overwrite_leftover() {
+        let flux = std::env::temp_dir().join("arc_intent_begin_test");
+        let _ = fs::remove_dir_all(&flux);
+        fs::create_dir_all(&flux).unwrap();
+        assert_eq!(read(&flux).unwrap(), None);
+
+        let pending = begin(&flux, &intent()).unwrap();
+        assert_eq!(read(&flux).unwrap().unwrap().signal_id, "sig-1");
+        let err = begin(&flux, &intent()).unwrap_err().to_string();
+        assert!(err.contains("`arc add rack` が完了していない") && err.contains("--resolve-intent"), "{}", err);
+
+        pending.finish().unwrap();
+        assert_eq!(read(&flux).unwrap(), None);
+        fs::remove_dir_all(&flux).unwrap();
+    }
+}
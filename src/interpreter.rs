@@ -0,0 +1,294 @@
+//! System モードで実際に起動するバイナリの解決と記録。
+//!
+//! System モードは PATH をそのまま使うため、Ruby が何本も入っていると、どれが動いたのかがログから分からない。
+//! 起動する前に argv[0] を PATH から探してシンボリックリンクを辿り、その絶対パスを start Signal の
+//! `env_context` に記録する:
+//!
+//! ```json
+//! "env_context": {
+//!   "mode": "system",
+//!   "binary": "/usr/lib/ruby/3.2/bin/ruby",
+//!   "binary_version": "ruby 3.2.3 (2024-01-18 revision 52bb2ac0a6) [x86_64-linux]",
+//!   "system_ruby": true
+//! }
+//! ```
+//!
+//! ruby・bundle・rake・python は `--version` の 1 行目も添える。結果はバイナリのパスと mtime をキーに
+//! `.flux/interpreters.json` に残し、同じバイナリでは 2 回目から起動しない。
+//! `[exec] system_ruby` を設定すると、素の `ruby` をそのパスに、`gem` / `bundle` を同じディレクトリのものに置き換える。
+
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use crate::config::ExecConfig;
+
+/// `--version` の結果を残すファイル (`.flux/` 直下)
+pub const PROBE_CACHE: &str = "interpreters.json";
+/// `--version` を調べるコマンド
+const PROBED: [&str; 5] = ["ruby", "bundle", "rake", "python", "python3"];
+/// `[exec] system_ruby` で置き換えるコマンド
+const PINNED: [&str; 3] = ["ruby", "gem", "bundle"];
+
+/// 起動するバイナリと、`env_context` に記録する内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemBinary {
+    /// `Command::new` に渡すもの (置き換えがなければ argv[0] のまま)
+    pub program: PathBuf,
+    /// シンボリックリンクを辿った絶対パス (見つからなければ `None`)
+    pub resolved: Option<PathBuf>,
+    /// `--version` の 1 行目
+    pub version: Option<String>,
+    /// `[exec] system_ruby` で置き換えた
+    pub pinned: bool,
+}
+
+impl SystemBinary {
+    /// `cmd` を起動する前に、置き換え・解決・`--version` の確認を行う。
+    pub fn decide(cmd: &str, cwd: &Path, exec: &ExecConfig, flux_dir: &Path) -> Self {
+        let pin = exec.system_ruby.as_deref().and_then(|ruby| pinned(cmd, ruby));
+        let program = pin.clone().unwrap_or_else(|| PathBuf::from(cmd));
+        let path = std::env::var_os("PATH");
+        let resolved = resolve(&program.to_string_lossy(), path.as_deref(), cwd);
+        let version = resolved
+            .as_deref()
+            .filter(|_| is_probed(cmd) && !crate::dry_run::is_enabled())
+            .and_then(|binary| probe(binary, &flux_dir.join(PROBE_CACHE), version_line));
+        Self { program, resolved, version, pinned: pin.is_some() }
+    }
+
+    /// start Signal の `env_context` に加えるフィールド
+    pub fn context_fields(&self) -> Value {
+        let mut fields = Map::new();
+        if let Some(resolved) = &self.resolved {
+            fields.insert("binary".to_string(), json!(resolved.to_string_lossy()));
+        }
+        if let Some(version) = &self.version {
+            fields.insert("binary_version".to_string(), json!(version));
+        }
+        if self.pinned {
+            fields.insert("system_ruby".to_string(), json!(true));
+        }
+        Value::Object(fields)
+    }
+}
+
+/// `[exec] system_ruby` による置き換え先。パスを含まない `ruby` / `gem` / `bundle` だけを置き換える
+pub fn pinned(cmd: &str, system_ruby: &Path) -> Option<PathBuf> {
+    if !PINNED.contains(&cmd) {
+        return None;
+    }
+    if cmd == "ruby" {
+        return Some(system_ruby.to_path_buf());
+    }
+    Some(system_ruby.parent()?.join(cmd))
+}
+
+/// `cmd` を `path` (PATH の値) から探し、シンボリックリンクを辿った絶対パスを返す。
+/// `/` を含む場合は PATH を使わず `cwd` からのパスとして扱う (シェルと同じ)。
+pub fn resolve(cmd: &str, path: Option<&OsStr>, cwd: &Path) -> Option<PathBuf> {
+    let found = if cmd.contains('/') {
+        Some(cwd.join(cmd)).filter(|p| is_executable(p))
+    } else {
+        std::env::split_paths(path?).map(|dir| cwd.join(dir).join(cmd)).find(|p| is_executable(p))
+    };
+    fs::canonicalize(found?).ok()
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+fn is_probed(cmd: &str) -> bool {
+    let name = Path::new(cmd).file_name().and_then(OsStr::to_str).unwrap_or(cmd);
+    PROBED.contains(&name)
+}
+
+// ─────────────────────────────────────────────
+// --version の確認
+// ─────────────────────────────────────────────
+
+/// キャッシュのキー (`<パス>@<mtime のナノ秒>`)。mtime が取れなければキャッシュしない
+fn cache_key(binary: &Path) -> Option<String> {
+    let modified = fs::metadata(binary).and_then(|m| m.modified()).ok()?;
+    let nanos = modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some(format!("{}@{}", binary.display(), nanos))
+}
+
+/// `binary` の `--version` を `cache` から引き、なければ `run` で調べて書き足す。
+/// バイナリが入れ替わると mtime が変わるので、古い結果は使われない。
+pub fn probe(binary: &Path, cache: &Path, run: impl Fn(&Path) -> Option<String>) -> Option<String> {
+    let Some(key) = cache_key(binary) else { return run(binary) };
+    let mut entries: BTreeMap<String, String> =
+        fs::read_to_string(cache).ok().and_then(|c| serde_json::from_str(&c).ok()).unwrap_or_default();
+    if let Some(version) = entries.get(&key) {
+        return Some(version.clone());
+    }
+    let version = run(binary)?;
+    // 同じパスの古い mtime の結果は捨てる
+    let prefix = format!("{}@", binary.display());
+    entries.retain(|k, _| !k.starts_with(&prefix));
+    entries.insert(key, version.clone());
+    if !crate::read_only::is_enabled()
+        && let Ok(content) = serde_json::to_string_pretty(&entries)
+    {
+        let _ = fs::write(cache, content);
+    }
+    Some(version)
+}
+
+/// `<binary> --version` の 1 行目 (python 2 は stderr に書く)
+fn version_line(binary: &Path) -> Option<String> {
+    let output = Command::new(binary).arg("--version").stdin(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+    String::from_utf8_lossy(&text).lines().map(str::trim).find(|l| !l.is_empty()).map(String::from)
+}
+
+/// `env_context` の記録を 1 行にまとめる (`arc state --raw --verbose`)
+pub fn describe(env_context: &Value) -> Option<String> {
+    let binary = env_context.get("binary")?.as_str()?;
+    let mut line = binary.to_string();
+    if let Some(version) = env_context.get("binary_version").and_then(Value::as_str) {
+        line.push_str(&format!(" ({})", version));
+    }
+    if env_context.get("system_ruby").and_then(Value::as_bool) == Some(true) {
+        line.push_str(" [exec] system_ruby");
+    }
+    Some(line)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::os::unix::fs::symlink;
+
+    /// `bin_a` と `bin_b` の 2 つのディレクトリを持つ PATH のフィクスチャ
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("arc_interpreter_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for sub in ["bin_a", "bin_b", "opt/ruby/bin"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        dir
+    }
+
+    fn executable(path: &Path) {
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn path_of(dir: &Path, subs: &[&str]) -> std::ffi::OsString {
+        std::env::join_paths(subs.iter().map(|s| dir.join(s))).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_follows_path_order_and_symlinks() {
+        let dir = fixture("resolve");
+        executable(&dir.join("opt/ruby/bin/ruby"));
+        symlink(dir.join("opt/ruby/bin/ruby"), dir.join("bin_b/ruby")).unwrap();
+        // 実行権限のないファイルは飛ばす
+        fs::write(dir.join("bin_a/ruby"), "").unwrap();
+
+        let path = path_of(&dir, &["bin_a", "bin_b"]);
+        let resolved = resolve("ruby", Some(&path), &dir).unwrap();
+        assert_eq!(resolved, fs::canonicalize(dir.join("opt/ruby/bin/ruby")).unwrap());
+
+        executable(&dir.join("bin_a/ruby"));
+        let resolved = resolve("ruby", Some(&path), &dir).unwrap();
+        assert_eq!(resolved, fs::canonicalize(dir.join("bin_a/ruby")).unwrap());
+
+        assert_eq!(resolve("rake", Some(&path), &dir), None);
+        assert_eq!(resolve("ruby", None, &dir), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_relative_and_absolute_commands() {
+        let dir = fixture("relative");
+        executable(&dir.join("bin_a/tool"));
+        let empty = path_of(&dir, &["bin_b"]);
+        let expected = fs::canonicalize(dir.join("bin_a/tool")).unwrap();
+        assert_eq!(resolve("bin_a/tool", Some(&empty), &dir), Some(expected.clone()));
+        assert_eq!(resolve(&expected.to_string_lossy(), Some(&empty), Path::new("/")), Some(expected));
+        assert_eq!(resolve("tool", Some(&empty), &dir), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pinned_rewrites_bare_ruby_tools() {
+        let ruby = Path::new("/opt/ruby/bin/ruby");
+        assert_eq!(pinned("ruby", ruby), Some(PathBuf::from("/opt/ruby/bin/ruby")));
+        assert_eq!(pinned("gem", ruby), Some(PathBuf::from("/opt/ruby/bin/gem")));
+        assert_eq!(pinned("bundle", ruby), Some(PathBuf::from("/opt/ruby/bin/bundle")));
+        assert_eq!(pinned("rake", ruby), None);
+        assert_eq!(pinned("./ruby", ruby), None);
+        assert_eq!(pinned("/usr/bin/ruby", ruby), None);
+    }
+
+    #[test]
+    fn test_decide_records_pinned_binary() {
+        let dir = fixture("decide");
+        executable(&dir.join("opt/ruby/bin/ruby"));
+        let exec = ExecConfig { system_ruby: Some(dir.join("opt/ruby/bin/ruby")), ..Default::default() };
+        let flux_dir = dir.join(".flux");
+        fs::create_dir_all(&flux_dir).unwrap();
+        let binary = SystemBinary::decide("ruby", &dir, &exec, &flux_dir);
+        assert!(binary.pinned);
+        assert_eq!(binary.program, dir.join("opt/ruby/bin/ruby"));
+        let fields = binary.context_fields();
+        assert_eq!(fields["binary"], fs::canonicalize(dir.join("opt/ruby/bin/ruby")).unwrap().to_string_lossy().as_ref());
+        assert_eq!(fields["system_ruby"], true);
+
+        let binary = SystemBinary::decide("rspec", &dir, &exec, &flux_dir);
+        assert!(!binary.pinned);
+        assert_eq!(binary.program, PathBuf::from("rspec"));
+        assert_eq!(binary.version, None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_probe_cache_keys_on_path_and_mtime() {
+        let dir = fixture("probe");
+        let ruby = dir.join("bin_a/ruby");
+        executable(&ruby);
+        let cache = dir.join(PROBE_CACHE);
+        let calls = Cell::new(0);
+        let run = |_: &Path| {
+            calls.set(calls.get() + 1);
+            Some(format!("ruby 3.3.{}", calls.get()))
+        };
+        assert_eq!(probe(&ruby, &cache, run).as_deref(), Some("ruby 3.3.1"));
+        assert_eq!(probe(&ruby, &cache, run).as_deref(), Some("ruby 3.3.1"));
+        assert_eq!(calls.get(), 1);
+
+        // バイナリが入れ替わる (mtime が変わる) と調べ直し、古い結果は捨てる
+        let later = fs::metadata(&ruby).unwrap().modified().unwrap() + std::time::Duration::from_secs(5);
+        fs::File::options().write(true).open(&ruby).unwrap().set_modified(later).unwrap();
+        assert_eq!(probe(&ruby, &cache, run).as_deref(), Some("ruby 3.3.2"));
+        assert_eq!(calls.get(), 2);
+        let entries: BTreeMap<String, String> = serde_json::from_str(&fs::read_to_string(&cache).unwrap()).unwrap();
+        assert_eq!(entries.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_describe() {
+        let context = json!({ "mode": "system", "binary": "/usr/bin/ruby", "binary_version": "ruby 3.3.6", "system_ruby": true });
+        assert_eq!(describe(&context).as_deref(), Some("/usr/bin/ruby (ruby 3.3.6) [exec] system_ruby"));
+        assert_eq!(describe(&json!({ "mode": "system", "binary": "/bin/ls" })).as_deref(), Some("/bin/ls"));
+        assert_eq!(describe(&json!({ "mode": "isolated" })), None);
+    }
+}
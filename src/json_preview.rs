@@ -0,0 +1,180 @@
+//! payload の一部だけを表示する (`arc state --raw` の Payload 列)。
+//!
+//! 以前は `payload.to_string()` で全体を文字列にしてから 48 文字に切り詰めていた。blob に逃がした後でも
+//! env の差分や長い引数の配列は大きく、描画のたびに丸ごと確保していた。ここでは `serde_json::to_writer` で
+//! 上限つきのバッファに書き出し、上限に達したら書き出し先がエラーを返して書き出しを止める。
+//! 省いた量は、値を文字列にせずにたどって数えたバイト数 (`encoded_len`) から求める。
+//!
+//! 上限に収まる payload は `to_string()` と同じ文字列になる。超えた場合は先頭に
+//! `…(+14.0 KiB)` のように省いた量を添え、全体で上限の文字数に収める。
+
+use std::io;
+
+use crate::display::fmt_bytes;
+
+/// 上限の文字数までだけ保持する書き出し先。上限を超える文字が来たらエラーを返して書き出しを止める
+struct Capped {
+    buf: Vec<u8>,
+    chars: usize,
+    limit: usize,
+}
+
+impl io::Write for Capped {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        for &b in bytes {
+            // UTF-8 の継続バイト以外が 1 文字の始まり
+            if b & 0xC0 != 0x80 {
+                if self.chars == self.limit {
+                    return Err(io::Error::other("preview limit reached"));
+                }
+                self.chars += 1;
+            }
+            self.buf.push(b);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `value` を最大 `max_chars` 文字で表示する。収まらなければ末尾を `…(+<省いた量>)` にする。
+pub fn preview(value: &serde_json::Value, max_chars: usize) -> String {
+    let mut out = Capped { buf: Vec::new(), chars: 0, limit: max_chars };
+    // `Value` の書き出しで起きるエラーは上限に達したときの `Capped` のエラーだけなので、切り詰めとして扱う
+    let truncated = serde_json::to_writer(&mut out, value).is_err();
+    let text = String::from_utf8_lossy(&out.buf);
+    if !truncated {
+        return text.into_owned();
+    }
+    let total = encoded_len(value);
+
+    // 省いた量の表記の長さで残せる文字数が変わるので、収まるまで詰め直す
+    let prefix_len = |keep: usize| text.char_indices().nth(keep).map_or(text.len(), |(i, _)| i);
+    let mut keep = max_chars;
+    loop {
+        let marker = format!("…(+{})", fmt_bytes((total - prefix_len(keep)) as u64));
+        let width = marker.chars().count();
+        if keep + width <= max_chars || keep == 0 {
+            return format!("{}{}", &text[..prefix_len(keep)], marker);
+        }
+        keep = max_chars.saturating_sub(width).min(keep - 1);
+    }
+}
+
+/// `value.to_string()` のバイト数。文字列を作らずに値をたどって数える
+fn encoded_len(value: &serde_json::Value) -> usize {
+    use serde_json::Value;
+    match value {
+        Value::Null | Value::Bool(true) => 4,
+        Value::Bool(false) => 5,
+        Value::Number(n) => n.to_string().len(),
+        Value::String(s) => string_len(s),
+        Value::Array(items) => 2 + items.iter().map(encoded_len).sum::<usize>() + items.len().saturating_sub(1),
+        Value::Object(map) => {
+            2 + map.iter().map(|(k, v)| string_len(k) + 1 + encoded_len(v)).sum::<usize>() + map.len().saturating_sub(1)
+        }
+    }
+}
+
+/// 引用符とエスケープを含めた JSON 文字列のバイト数 (serde_json と同じエスケープ)
+fn string_len(s: &str) -> usize {
+    let escaped: usize = s
+        .bytes()
+        .map(|b| match b {
+            b'"' | b'\\' | b'\x08' | b'\x0c' | b'\n' | b'\r' | b'\t' => 2,
+            0x00..=0x1f => 6,
+            _ => 1,
+        })
+        .sum();
+    escaped + 2
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 省略された preview を (先頭, 省いたバイト数の表記) に分ける
+    fn split(preview: &str) -> (&str, &str) {
+        let (head, marker) = preview.split_once("…(+").expect(preview);
+        (head, marker.strip_suffix(')').unwrap())
+    }
+
+    #[test]
+    fn test_small_payload_is_unchanged() {
+        for value in [json!({}), json!({ "gem": "rake", "version": "13.1.0" }), json!(["日本語", 1, null]), json!("a\"b\\c\n")] {
+            assert_eq!(preview(&value, 48), value.to_string());
+        }
+        // ちょうど上限の長さ
+        let value = json!("x".repeat(46));
+        assert_eq!(value.to_string().chars().count(), 48);
+        assert_eq!(preview(&value, 48), value.to_string());
+    }
+
+    #[test]
+    fn test_nested_value_cut_mid_key() {
+        let value = json!({ "outer": { "a_rather_long_key_name": [1, 2, { "inner": "value" }] }, "tail": true });
+        let full = value.to_string();
+        let shown = preview(&value, 24);
+        assert!(shown.chars().count() <= 24, "{}", shown);
+        let (head, size) = split(&shown);
+        // 途中のキーで切れても、先頭は全体の文字列と一致する
+        assert!(full.starts_with(head) && head.len() > 10, "{}", head);
+        assert!(full[head.len()..].starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_'), "{}", head);
+        assert_eq!(size, fmt_bytes((full.len() - head.len()) as u64));
+    }
+
+    #[test]
+    fn test_unicode_boundary() {
+        let value = json!({ "msg": "日本語のメッセージがとても長く続きます、まだまだ続きます" });
+        let full = value.to_string();
+        for max in 10..30 {
+            let shown = preview(&value, max);
+            assert!(shown.chars().count() <= max, "{}: {}", max, shown);
+            let (head, size) = split(&shown);
+            assert!(full.starts_with(head));
+            assert_eq!(size, fmt_bytes((full.len() - head.len()) as u64));
+        }
+    }
+
+    #[test]
+    fn test_encoded_len_matches_to_string() {
+        let values = [
+            json!(null),
+            json!([true, false, [], {}]),
+            json!({ "n": [0, -12, 3.5, 1e300, u64::MAX, i64::MIN] }),
+            json!({ "esc": "\"\\/\u{8}\u{c}\n\r\t\u{1}\u{1f}\u{7f}", "日本語": "é🎉" }),
+        ];
+        for value in values {
+            assert_eq!(encoded_len(&value), value.to_string().len(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_size_hint() {
+        // 省いた量は全体のバイト数から残した先頭を引いたもの
+        let value = json!({ "env": "e".repeat(14 * 1024 + 20) });
+        let shown = preview(&value, 48);
+        let (head, size) = split(&shown);
+        assert_eq!(size, "14.0 KiB");
+        assert_eq!(size, fmt_bytes((value.to_string().len() - head.len()) as u64));
+        assert_eq!(shown.chars().count(), 48);
+        // 省いた量が小さければバイト単位
+        let value = json!({ "args": ["--format", "documentation", "--order", "random"] });
+        let shown = preview(&value, 40);
+        let (head, size) = split(&shown);
+        assert_eq!(size, format!("{} B", value.to_string().len() - head.len()));
+        // 大きな配列
+        let value = json!((0..100_000).collect::<Vec<u32>>());
+        let shown = preview(&value, 48);
+        let (head, size) = split(&shown);
+        assert_eq!(head, &value.to_string()[..head.len()]);
+        assert_eq!(size, fmt_bytes((value.to_string().len() - head.len()) as u64));
+    }
+}
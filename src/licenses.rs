@@ -0,0 +1,289 @@
+//! インストール済みの Gem のライセンス一覧 (`arc licenses`)。
+//!
+//! `.arc/env/ruby/<api>/specifications/*.gemspec` を読み、`Gem::Specification.new` の DSL のうち
+//! `name` / `version` / `license` / `licenses` の代入だけを解釈する。ヒアドキュメント (`<<~DESC`) の中身は読み飛ばす。
+//! 解釈できない gemspec (ブロックがない、ヒアドキュメントが閉じていない) は、環境の ruby に読ませる。
+//! ライセンスの記載がない Gem は省かずに `unknown` として扱う。
+
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// ライセンスの記載がない Gem のライセンス名
+pub const UNKNOWN: &str = "unknown";
+
+/// `gemspec` から ruby でライセンスを読むスクリプト (1 行に 1 つ)
+const RUBY_SCRIPT: &str = "spec = Gem::Specification.load(ARGV[0]) or abort; puts spec.licenses";
+
+static ASSIGNMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*[a-z_]+\.(name|version|licenses?)\s*=\s*(.+)$").unwrap());
+static HEREDOC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<<[~-]?(['"]?)([A-Za-z_][A-Za-z0-9_]*)['"]?"#).unwrap());
+static WORD_ARRAY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"%[wW][\[(\{<]([^\])\}>]*)[\])\}>]").unwrap());
+
+/// `arc licenses --group-by`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum LicenseGroup {
+    Gem,
+    License,
+}
+
+/// 1 つの Gem のライセンス
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GemLicense {
+    pub name: String,
+    pub version: String,
+    /// 記載がなければ `["unknown"]`
+    pub licenses: Vec<String>,
+}
+
+/// gemspec から読み取った値
+#[derive(Debug, Default, PartialEq)]
+pub struct GemspecInfo {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub licenses: Vec<String>,
+}
+
+// ─────────────────────────────────────────────
+// gemspec の解釈
+// ─────────────────────────────────────────────
+
+/// 右辺の文字列リテラル (`"MIT".freeze`, `'MIT'`, `%w[MIT Ruby]`) を順に取り出す。
+fn string_literals(rhs: &str) -> Vec<String> {
+    if let Some(words) = WORD_ARRAY.captures(rhs) {
+        return words[1].split_whitespace().map(String::from).collect();
+    }
+    let mut values = Vec::new();
+    let mut rest = rhs;
+    while let Some(start) = rest.find(['"', '\'']) {
+        let quote = rest[start..].chars().next().unwrap();
+        let Some(len) = rest[start + 1..].find(quote) else { break };
+        values.push(rest[start + 1..start + 1 + len].to_string());
+        rest = &rest[start + 1 + len + 1..];
+    }
+    values
+}
+
+/// gemspec の内容を解釈する。`Gem::Specification.new` がない場合や、ヒアドキュメントが閉じていない場合は `None`。
+pub fn parse_gemspec(content: &str) -> Option<GemspecInfo> {
+    if !content.contains("Gem::Specification.new") {
+        return None;
+    }
+    let mut info = GemspecInfo::default();
+    let mut heredocs: Vec<String> = Vec::new();
+    for line in content.lines() {
+        // ヒアドキュメントの本文は終端の行まで読み飛ばす
+        if let Some(terminator) = heredocs.first() {
+            if line.trim() == terminator {
+                heredocs.remove(0);
+            }
+            continue;
+        }
+        let code = line.trim_start();
+        if code.starts_with('#') {
+            continue;
+        }
+        heredocs.extend(HEREDOC.captures_iter(line).map(|c| c[2].to_string()));
+        let Some(assignment) = ASSIGNMENT.captures(line) else { continue };
+        let values = string_literals(&assignment[2]);
+        match &assignment[1] {
+            "name" => info.name = values.into_iter().next(),
+            "version" => info.version = values.into_iter().next(),
+            _ => info.licenses = values,
+        }
+    }
+    heredocs.is_empty().then_some(info)
+}
+
+/// `rake-13.1.0` / `nokogiri-1.16.0-x86_64-linux` を名前とバージョンに分ける (最初の `-<数字>` で区切る)。
+fn split_spec_name(stem: &str) -> (String, String) {
+    let at = stem
+        .char_indices()
+        .find(|&(i, c)| c == '-' && stem[i + 1..].starts_with(|d: char| d.is_ascii_digit()))
+        .map(|(i, _)| i);
+    match at {
+        Some(i) => (stem[..i].to_string(), stem[i + 1..].to_string()),
+        None => (stem.to_string(), String::new()),
+    }
+}
+
+/// 環境の ruby に gemspec を読ませてライセンスを得る。
+fn ruby_licenses(ruby: &Path, spec: &Path) -> Option<Vec<String>> {
+    let output = std::process::Command::new(ruby).args(["-e", RUBY_SCRIPT]).arg(spec).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).lines().map(String::from).collect())
+}
+
+// ─────────────────────────────────────────────
+// 一覧
+// ─────────────────────────────────────────────
+
+/// `gem_base/specifications` の Gem のライセンスを名前順に返す。
+/// 解釈できない gemspec は `ruby` があればそれで読み、なければライセンスを `unknown` とする。
+pub fn scan(gem_base: &Path, ruby: Option<&Path>) -> Vec<GemLicense> {
+    let dir = gem_base.join("specifications");
+    let mut gems: Vec<GemLicense> = fs::read_dir(&dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|x| x == "gemspec"))
+        .map(|entry| {
+            let path = entry.path();
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let (file_name, file_version) = split_spec_name(&stem);
+            let content = fs::read_to_string(&path).unwrap_or_default();
+            let info = match parse_gemspec(&content) {
+                Some(info) => info,
+                None => GemspecInfo {
+                    licenses: ruby.and_then(|ruby| ruby_licenses(ruby, &path)).unwrap_or_default(),
+                    ..Default::default()
+                },
+            };
+            // 定数 (`LegacyTool::VERSION`) で書かれたバージョンはファイル名から補う
+            let version = info.version.filter(|v| !v.is_empty()).unwrap_or(file_version);
+            let mut licenses: Vec<String> = info.licenses.into_iter().filter(|l| !l.trim().is_empty()).collect();
+            if licenses.is_empty() {
+                licenses.push(UNKNOWN.to_string());
+            }
+            GemLicense { name: info.name.unwrap_or(file_name), version, licenses }
+        })
+        .collect();
+    gems.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.version.cmp(&b.version)));
+    gems
+}
+
+/// ライセンスごとの Gem (`<name>-<version>`)。複数のライセンスを持つ Gem はそれぞれに入る。
+pub fn by_license(gems: &[GemLicense]) -> BTreeMap<String, Vec<String>> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for gem in gems {
+        for license in &gem.licenses {
+            groups.entry(license.clone()).or_default().push(format!("{}-{}", gem.name, gem.version));
+        }
+    }
+    groups
+}
+
+/// `deny` (大文字小文字は区別しない) に含まれるライセンスを持つ Gem と、そのライセンス。
+pub fn forbidden<'a>(gems: &'a [GemLicense], deny: &[String]) -> Vec<(&'a GemLicense, &'a str)> {
+    gems.iter()
+        .flat_map(|gem| gem.licenses.iter().map(move |license| (gem, license.as_str())))
+        .filter(|(_, license)| deny.iter().any(|d| d.eq_ignore_ascii_case(license)))
+        .collect()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAKE: &str = include_str!("testdata/gemspecs/rake-13.1.0.gemspec");
+    const RACC: &str = include_str!("testdata/gemspecs/racc-1.7.3.gemspec");
+    const NOKOGIRI: &str = include_str!("testdata/gemspecs/nokogiri-1.16.0-x86_64-linux.gemspec");
+    const LEGACY_TOOL: &str = include_str!("testdata/gemspecs/legacy_tool-0.3.1.gemspec");
+    const MYSTERY: &str = include_str!("testdata/gemspecs/mystery-2.0.0.gemspec");
+
+    fn info(name: &str, version: Option<&str>, licenses: &[&str]) -> GemspecInfo {
+        GemspecInfo {
+            name: Some(name.to_string()),
+            version: version.map(String::from),
+            licenses: licenses.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_generated_gemspecs() {
+        assert_eq!(parse_gemspec(RAKE), Some(info("rake", Some("13.1.0"), &["MIT"])));
+        assert_eq!(parse_gemspec(RACC), Some(info("racc", Some("1.7.3"), &["Ruby", "BSD-2-Clause"])));
+        assert_eq!(parse_gemspec(NOKOGIRI), Some(info("nokogiri", Some("1.16.0"), &["MIT"])));
+        assert_eq!(parse_gemspec(MYSTERY), Some(info("mystery", Some("2.0.0"), &[])));
+    }
+
+    #[test]
+    fn test_parse_skips_heredoc_body() {
+        // 説明文の中の `spec.license = "GPL-3.0"` は代入ではない。バージョンは定数なので読めない
+        assert_eq!(parse_gemspec(LEGACY_TOOL), Some(info("legacy_tool", None, &["Apache-2.0", "MIT"])));
+        assert_eq!(
+            parse_gemspec("Gem::Specification.new do |s|\n  s.description = <<-'EOS'\n  s.license = 'GPL'\n  EOS\n  s.license = 'BSD'\nend\n")
+                .unwrap()
+                .licenses,
+            ["BSD"]
+        );
+    }
+
+    #[test]
+    fn test_parse_failures() {
+        assert_eq!(parse_gemspec("--- !ruby/object:Gem::Specification\nname: rake\n"), None);
+        assert_eq!(parse_gemspec("Gem::Specification.new do |s|\n  s.description = <<~DESC\n  s.license = 'MIT'\nend\n"), None);
+    }
+
+    #[test]
+    fn test_split_spec_name() {
+        assert_eq!(split_spec_name("rake-13.1.0"), ("rake".to_string(), "13.1.0".to_string()));
+        assert_eq!(split_spec_name("net-http-0.4.1"), ("net-http".to_string(), "0.4.1".to_string()));
+        assert_eq!(split_spec_name("nokogiri-1.16.0-x86_64-linux"), ("nokogiri".to_string(), "1.16.0-x86_64-linux".to_string()));
+    }
+
+    #[test]
+    fn test_scan_groups_and_gates() {
+        let root = std::env::temp_dir().join("arc_licenses_scan_test");
+        let _ = fs::remove_dir_all(&root);
+        let specs = root.join("specifications");
+        fs::create_dir_all(&specs).unwrap();
+        for (file, content) in [
+            ("rake-13.1.0.gemspec", RAKE),
+            ("racc-1.7.3.gemspec", RACC),
+            ("legacy_tool-0.3.1.gemspec", LEGACY_TOOL),
+            ("mystery-2.0.0.gemspec", MYSTERY),
+            ("broken-1.0.0.gemspec", "this is not a gemspec"),
+        ] {
+            fs::write(specs.join(file), content).unwrap();
+        }
+
+        let gems = scan(&root, None);
+        let listed: Vec<(&str, &str, String)> =
+            gems.iter().map(|g| (g.name.as_str(), g.version.as_str(), g.licenses.join(","))).collect();
+        assert_eq!(
+            listed,
+            [
+                ("broken", "1.0.0", UNKNOWN.to_string()),
+                ("legacy_tool", "0.3.1", "Apache-2.0,MIT".to_string()),
+                ("mystery", "2.0.0", UNKNOWN.to_string()),
+                ("racc", "1.7.3", "Ruby,BSD-2-Clause".to_string()),
+                ("rake", "13.1.0", "MIT".to_string()),
+            ]
+        );
+
+        let groups = by_license(&gems);
+        assert_eq!(groups["MIT"], ["legacy_tool-0.3.1", "rake-13.1.0"]);
+        assert_eq!(groups[UNKNOWN], ["broken-1.0.0", "mystery-2.0.0"]);
+
+        let denied = forbidden(&gems, &["bsd-2-clause".to_string(), "GPL-3.0".to_string()]);
+        assert_eq!(denied.iter().map(|(g, l)| (g.name.as_str(), *l)).collect::<Vec<_>>(), [("racc", "BSD-2-Clause")]);
+        assert!(forbidden(&gems, &[]).is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_scan_falls_back_to_ruby() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = std::env::temp_dir().join("arc_licenses_ruby_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("specifications")).unwrap();
+        fs::write(root.join("specifications/odd-1.0.gemspec"), "eval(File.read('real.gemspec'))").unwrap();
+        let ruby = root.join("ruby");
+        fs::write(&ruby, "#!/bin/sh\necho 'LGPL-2.1'\necho 'MIT'\n").unwrap();
+        fs::set_permissions(&ruby, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let gems = scan(&root, Some(&ruby));
+        assert_eq!(gems[0].licenses, ["LGPL-2.1", "MIT"]);
+        assert_eq!((gems[0].name.as_str(), gems[0].version.as_str()), ("odd", "1.0"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,458 @@
+//! キャッシュ ↔ プロジェクト間のファイル配置 (reflink / hardlink / copy)。
+//!
+//! ハードリンクはキャッシュとプロジェクトで inode を共有するため、
+//! プロジェクト内で Gem のファイルを編集するとキャッシュまで書き換わってしまう。
+//! reflink (copy-on-write クローン) が使えるファイルシステム (btrfs / XFS 等) では
+//! それを優先し、使えない場合にハードリンク、最後に通常のコピーへフォールバックする。
+//!
+//! `.arc/env` がシンボリックリンクや bind mount で別のディスクに置かれている場合、
+//! reflink もハードリンクも必ず失敗するため、デバイス番号を事前に比較してコピーだけを使う。
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::perms;
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// `[cache] link_mode` で選択する配置方法。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// reflink → hardlink → copy の順に試す
+    #[default]
+    Auto,
+    /// reflink → copy (inode を共有するハードリンクは使わない)
+    Reflink,
+    /// hardlink → copy
+    Hardlink,
+    /// 常にコピーする
+    Copy,
+}
+
+impl LinkMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkMode::Auto     => "auto",
+            LinkMode::Reflink  => "reflink",
+            LinkMode::Hardlink => "hardlink",
+            LinkMode::Copy     => "copy",
+        }
+    }
+
+    /// このモードで試す方法を優先順に返す。
+    /// 別デバイスへの配置では reflink もハードリンクも成功しないため、コピーだけを返す。
+    fn strategies(&self, cross_device: bool) -> &'static [Strategy] {
+        if cross_device {
+            return &[Strategy::Copy];
+        }
+        match self {
+            LinkMode::Auto     => &[Strategy::Reflink, Strategy::Hardlink, Strategy::Copy],
+            LinkMode::Reflink  => &[Strategy::Reflink, Strategy::Copy],
+            LinkMode::Hardlink => &[Strategy::Hardlink, Strategy::Copy],
+            LinkMode::Copy     => &[Strategy::Copy],
+        }
+    }
+}
+
+/// 1 ファイルを配置した方法。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    Reflink,
+    Hardlink,
+    Copy,
+}
+
+/// 方法ごとの配置ファイル数。
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LinkReport {
+    pub reflink: usize,
+    pub hardlink: usize,
+    pub copy: usize,
+    /// 配置したファイルの合計サイズ
+    pub bytes: u64,
+    /// 配置元と配置先が別デバイスだったため、最初からコピーした
+    pub cross_device: bool,
+}
+
+impl LinkReport {
+    pub fn total(&self) -> usize {
+        self.reflink + self.hardlink + self.copy
+    }
+
+    pub fn merge(&mut self, other: &LinkReport) {
+        self.reflink += other.reflink;
+        self.hardlink += other.hardlink;
+        self.copy += other.copy;
+        self.bytes += other.bytes;
+        self.cross_device |= other.cross_device;
+    }
+
+    fn count(&mut self, strategy: Strategy) {
+        match strategy {
+            Strategy::Reflink  => self.reflink += 1,
+            Strategy::Hardlink => self.hardlink += 1,
+            Strategy::Copy     => self.copy += 1,
+        }
+    }
+
+    /// Signal の payload 用の JSON
+    pub fn to_json(&self, mode: LinkMode) -> serde_json::Value {
+        serde_json::json!({
+            "mode": mode.as_str(),
+            "reflink": self.reflink,
+            "hardlink": self.hardlink,
+            "copy": self.copy,
+            "bytes": self.bytes,
+            "cross_device": self.cross_device,
+        })
+    }
+}
+
+// ─────────────────────────────────────────────
+// ファイル操作 (テストで差し替え可能)
+// ─────────────────────────────────────────────
+
+/// 1 ファイルを配置する低レベル操作。テストでは失敗を注入した実装に差し替える。
+trait FileOps {
+    fn reflink(&self, src: &Path, dest: &Path) -> io::Result<()>;
+    fn hardlink(&self, src: &Path, dest: &Path) -> io::Result<()>;
+    fn copy(&self, src: &Path, dest: &Path) -> io::Result<()>;
+}
+
+/// 実際のファイルシステムに対する操作。
+struct NativeOps;
+
+impl FileOps for NativeOps {
+    fn reflink(&self, src: &Path, dest: &Path) -> io::Result<()> {
+        let src_file = File::open(src)?;
+        let dest_file = File::create(dest)?;
+
+        // SAFETY: 両方のファイルディスクリプタはこのスコープで有効
+        let rc = unsafe { libc::ioctl(dest_file.as_raw_fd(), libc::FICLONE, src_file.as_raw_fd()) };
+        if rc == -1 {
+            let err = io::Error::last_os_error();
+            drop(dest_file);
+            let _ = fs::remove_file(dest);
+            return Err(err);
+        }
+
+        fs::set_permissions(dest, src_file.metadata()?.permissions())
+    }
+
+    fn hardlink(&self, src: &Path, dest: &Path) -> io::Result<()> {
+        fs::hard_link(src, dest)
+    }
+
+    fn copy(&self, src: &Path, dest: &Path) -> io::Result<()> {
+        fs::copy(src, dest).map(|_| ())
+    }
+}
+
+/// `mode` の優先順に従って 1 ファイルを配置し、成功した方法を返す。
+fn place_file(ops: &dyn FileOps, src: &Path, dest: &Path, mode: LinkMode, cross_device: bool) -> Result<Strategy> {
+    let mut last_err = None;
+
+    for strategy in mode.strategies(cross_device) {
+        let result = match strategy {
+            Strategy::Reflink  => ops.reflink(src, dest),
+            Strategy::Hardlink => ops.hardlink(src, dest),
+            Strategy::Copy     => ops.copy(src, dest),
+        };
+        match result {
+            Ok(()) => return Ok(*strategy),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| io::Error::other("no strategy available")))
+        .with_context(|| format!("配置に失敗しました: {:?} → {:?}", src, dest))
+}
+
+// ─────────────────────────────────────────────
+// ツリーの配置
+// ─────────────────────────────────────────────
+
+/// `src` (ファイルまたはディレクトリ) を `dest` に再帰的に配置する。
+/// 失敗した場合は途中まで作成した `dest` を削除してエラーを返す。
+pub fn link_tree(src: &Path, dest: &Path, mode: LinkMode) -> Result<LinkReport> {
+    let cross_device = same_device(src, dest) == Some(false);
+    let mut report = LinkReport { cross_device, ..Default::default() };
+    match link_tree_with(&NativeOps, src, dest, mode, cross_device, &mut report) {
+        Ok(()) => Ok(report),
+        Err(e) => {
+            let _ = remove_tree(dest);
+            Err(e)
+        }
+    }
+}
+
+fn link_tree_with(
+    ops: &dyn FileOps,
+    src: &Path,
+    dest: &Path,
+    mode: LinkMode,
+    cross_device: bool,
+    report: &mut LinkReport,
+) -> Result<()> {
+    let meta = fs::symlink_metadata(src)
+        .with_context(|| format!("Failed to stat {:?}", src))?;
+
+    if meta.file_type().is_symlink() {
+        let target = fs::read_link(src)?;
+        std::os::unix::fs::symlink(&target, dest)
+            .with_context(|| format!("シンボリックリンクの作成に失敗しました: {:?}", dest))?;
+    } else if meta.is_dir() {
+        perms::create_dir_all(dest).with_context(|| format!("Failed to create {:?}", dest))?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            link_tree_with(ops, &entry.path(), &dest.join(entry.file_name()), mode, cross_device, report)?;
+        }
+        fs::set_permissions(dest, perms::widen_dir(meta.permissions()))?;
+    } else {
+        report.count(place_file(ops, src, dest, mode, cross_device)?);
+        report.bytes += meta.len();
+    }
+
+    Ok(())
+}
+
+/// シンボリックリンクを辿らずに数えたディスク使用量。
+pub fn tree_size(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else { return 0 };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| tree_size(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+/// `src` と、`dest` (未作成なら存在する最も近い祖先) が同じデバイス上にあるか。
+/// シンボリックリンクは辿った先で比較する。判定できない場合は `None`。
+pub fn same_device(src: &Path, dest: &Path) -> Option<bool> {
+    let src_dev = fs::metadata(src).ok()?.dev();
+    let dest_dev = dest.ancestors().find_map(|p| fs::metadata(p).ok())?.dev();
+    Some(src_dev == dest_dev)
+}
+
+/// `path` を削除する。シンボリックリンクは辿らず、リンク自体だけを削除する。
+///
+/// `remove_dir_all` にシンボリックリンクを渡すと環境によってはリンク先の中身まで
+/// 消してしまうため、削除はすべてこの関数を通す。リンク先は利用者の管理下とみなし、
+/// arc からは決して削除しない。
+pub fn remove_tree(path: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+// ─────────────────────────────────────────────
+// env ディレクトリの保存先
+// ─────────────────────────────────────────────
+
+/// `.arc/env` の実体がどこにあるか。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnvStorage {
+    /// `.arc/env` がシンボリックリンクの場合のリンク先 (`read_link` の値)
+    pub symlink_target: Option<PathBuf>,
+    /// リンク先が存在しない
+    pub dangling: bool,
+    /// グローバルキャッシュと同じデバイス上にあるか (判定できなければ `None`)
+    pub same_device_as_cache: Option<bool>,
+}
+
+impl EnvStorage {
+    /// `cache_dir` が決まらない (`arc_home` で解決できない) ときはデバイスを比べない。
+    pub fn inspect(env_dir: &Path, cache_dir: Option<&Path>) -> Self {
+        let symlink_target = fs::symlink_metadata(env_dir)
+            .ok()
+            .filter(|m| m.file_type().is_symlink())
+            .and_then(|_| fs::read_link(env_dir).ok());
+        Self {
+            dangling: symlink_target.is_some() && !env_dir.exists(),
+            symlink_target,
+            same_device_as_cache: cache_dir.and_then(|cache_dir| same_device(cache_dir, env_dir)),
+        }
+    }
+
+    /// `arc env` で表示する 1 行の説明。
+    pub fn describe(&self) -> String {
+        let location = match (&self.symlink_target, self.dangling) {
+            (Some(target), true) => return format!("symlink → {} (missing!)", target.display()),
+            (Some(target), false) => format!("symlink → {}", target.display()),
+            (None, _) => "directory".to_string(),
+        };
+        match self.same_device_as_cache {
+            Some(true) => format!("{}, same device as cache", location),
+            Some(false) => format!("{}, other device than cache (files are copied)", location),
+            None => location,
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// 指定した方法を失敗させ、呼び出し順を記録する FileOps
+    struct FlakyOps {
+        fail: Vec<Strategy>,
+        calls: RefCell<Vec<Strategy>>,
+    }
+
+    impl FlakyOps {
+        fn new(fail: &[Strategy]) -> Self {
+            Self { fail: fail.to_vec(), calls: RefCell::new(vec![]) }
+        }
+
+        fn attempt(&self, strategy: Strategy) -> io::Result<()> {
+            self.calls.borrow_mut().push(strategy);
+            if self.fail.contains(&strategy) {
+                Err(io::Error::from_raw_os_error(libc::EOPNOTSUPP))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl FileOps for FlakyOps {
+        fn reflink(&self, _: &Path, _: &Path) -> io::Result<()> { self.attempt(Strategy::Reflink) }
+        fn hardlink(&self, _: &Path, _: &Path) -> io::Result<()> { self.attempt(Strategy::Hardlink) }
+        fn copy(&self, _: &Path, _: &Path) -> io::Result<()> { self.attempt(Strategy::Copy) }
+    }
+
+    fn place(mode: LinkMode, fail: &[Strategy]) -> (Result<Strategy>, Vec<Strategy>) {
+        let ops = FlakyOps::new(fail);
+        let result = place_file(&ops, Path::new("a"), Path::new("b"), mode, false);
+        (result, ops.calls.into_inner())
+    }
+
+    #[test]
+    fn test_auto_prefers_reflink() {
+        let (result, calls) = place(LinkMode::Auto, &[]);
+        assert_eq!(result.unwrap(), Strategy::Reflink);
+        assert_eq!(calls, [Strategy::Reflink]);
+    }
+
+    #[test]
+    fn test_auto_fallback_order() {
+        let (result, calls) = place(LinkMode::Auto, &[Strategy::Reflink]);
+        assert_eq!(result.unwrap(), Strategy::Hardlink);
+        assert_eq!(calls, [Strategy::Reflink, Strategy::Hardlink]);
+
+        let (result, calls) = place(LinkMode::Auto, &[Strategy::Reflink, Strategy::Hardlink]);
+        assert_eq!(result.unwrap(), Strategy::Copy);
+        assert_eq!(calls, [Strategy::Reflink, Strategy::Hardlink, Strategy::Copy]);
+    }
+
+    #[test]
+    fn test_reflink_mode_never_hardlinks() {
+        let (result, calls) = place(LinkMode::Reflink, &[Strategy::Reflink]);
+        assert_eq!(result.unwrap(), Strategy::Copy);
+        assert_eq!(calls, [Strategy::Reflink, Strategy::Copy]);
+    }
+
+    #[test]
+    fn test_all_strategies_fail() {
+        let (result, calls) = place(LinkMode::Hardlink, &[Strategy::Hardlink, Strategy::Copy]);
+        assert!(result.is_err());
+        assert_eq!(calls, [Strategy::Hardlink, Strategy::Copy]);
+    }
+
+    #[test]
+    fn test_link_tree_copies_structure() {
+        let root = std::env::temp_dir().join("arc_link_tree_test");
+        let _ = fs::remove_dir_all(&root);
+        let src = root.join("src");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("lib/a.rb"), "puts 1").unwrap();
+        std::os::unix::fs::symlink("lib/a.rb", src.join("link.rb")).unwrap();
+
+        let report = link_tree(&src, &root.join("dest"), LinkMode::Copy).unwrap();
+        assert_eq!(report, LinkReport { reflink: 0, hardlink: 0, copy: 1, bytes: 6, cross_device: false });
+        assert_eq!(fs::read_to_string(root.join("dest/lib/a.rb")).unwrap(), "puts 1");
+        assert_eq!(fs::read_link(root.join("dest/link.rb")).unwrap(), Path::new("lib/a.rb"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_cross_device_skips_links() {
+        let ops = FlakyOps::new(&[]);
+        let result = place_file(&ops, Path::new("a"), Path::new("b"), LinkMode::Auto, true);
+        assert_eq!(result.unwrap(), Strategy::Copy);
+        assert_eq!(ops.calls.into_inner(), [Strategy::Copy]);
+    }
+
+    #[test]
+    fn test_symlinked_env_storage() {
+        let root = std::env::temp_dir().join("arc_link_env_storage_test");
+        let _ = fs::remove_dir_all(&root);
+        let scratch = root.join("scratch");
+        fs::create_dir_all(scratch.join("ruby")).unwrap();
+        fs::create_dir_all(root.join("project/.arc")).unwrap();
+        let env_dir = root.join("project/.arc/env");
+        std::os::unix::fs::symlink(&scratch, &env_dir).unwrap();
+
+        let storage = EnvStorage::inspect(&env_dir, Some(&root));
+        assert_eq!(storage.symlink_target.as_deref(), Some(scratch.as_path()));
+        assert!(!storage.dangling);
+        assert_eq!(storage.same_device_as_cache, Some(true));
+        assert!(storage.describe().starts_with("symlink → "));
+
+        // リンクを削除してもリンク先の中身は残る
+        remove_tree(&env_dir).unwrap();
+        assert!(fs::symlink_metadata(&env_dir).is_err());
+        assert!(scratch.join("ruby").is_dir());
+
+        // リンク先が消えたリンクは dangling として報告する
+        std::os::unix::fs::symlink(root.join("gone"), &env_dir).unwrap();
+        let storage = EnvStorage::inspect(&env_dir, Some(&root));
+        assert!(storage.dangling);
+        assert!(storage.describe().contains("missing"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_link_mode_config_values() {
+        #[derive(Deserialize)]
+        struct Wrapper { link_mode: LinkMode }
+        let w: Wrapper = toml::from_str("link_mode = \"reflink\"").unwrap();
+        assert_eq!(w.link_mode, LinkMode::Reflink);
+    }
+
+    /// reflink 対応のファイルシステム (btrfs / XFS) 上でのみ成功する。
+    /// `ARC_REFLINK_TEST_DIR=/mnt/btrfs cargo test -- --ignored` で実行する。
+    #[test]
+    #[ignore]
+    fn test_real_reflink() {
+        let base = std::env::var("ARC_REFLINK_TEST_DIR")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir());
+        let root = base.join("arc_reflink_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a"), "cow").unwrap();
+
+        NativeOps.reflink(&root.join("a"), &root.join("b")).unwrap();
+        fs::write(root.join("b"), "changed").unwrap();
+        // クローンへの書き込みは元のファイルに影響しない
+        assert_eq!(fs::read_to_string(root.join("a")).unwrap(), "cow");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
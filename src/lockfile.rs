@@ -0,0 +1,200 @@
+/// Gemfile.lock の読み取りユーティリティ。
+///
+/// Bundler のロックファイルはインデントで構造を表す:
+///
+/// ```text
+/// GEM
+///   remote: https://rubygems.org/
+///   specs:
+///     rubocop (1.60.0)
+///       json (~> 2.3)
+/// ```
+///
+/// `specs:` 直下 (4 スペース) が解決済みの Gem、その下 (6 スペース) が依存関係。
+use std::path::Path;
+use anyhow::{Context, Result};
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+/// Gemfile.lock の `specs:` に記載された解決済み Gem。
+#[derive(Debug, Clone)]
+pub struct LockSpec {
+    pub name: String,
+    /// 解決済みバージョン
+    pub version: String,
+    /// 依存する Gem の名前 (バージョン制約は除く)
+    pub dependencies: Vec<String>,
+    /// spec の下に `ruby (>= 2.7)` の行があれば、その要件 (`required_ruby_version`)
+    pub required_ruby_version: Option<String>,
+}
+
+/// パース済みの Gemfile.lock。
+#[derive(Debug, Clone, Default)]
+pub struct Lockfile {
+    pub specs: Vec<LockSpec>,
+    /// `DEPENDENCIES` セクションに記載された直接依存の Gem 名
+    pub dependencies: Vec<String>,
+    /// `BUNDLED WITH` に記載された bundler のバージョン
+    pub bundled_with: Option<String>,
+}
+
+impl LockSpec {
+    /// バージョンとプラットフォームに分ける (`1.16.0-x86_64-linux` → `1.16.0`, `x86_64-linux`)。
+    /// Gem のバージョンには `-` が含まれないため、最初の `-` 以降をプラットフォームとみなす。
+    pub fn version_and_platform(&self) -> (&str, Option<&str>) {
+        match self.version.split_once('-') {
+            Some((version, platform)) => (version, Some(platform)),
+            None => (&self.version, None),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// パース
+// ─────────────────────────────────────────────
+
+/// Gemfile.lock を読み込んでパースする。
+pub fn parse(lockfile: &Path) -> Result<Lockfile> {
+    let content = std::fs::read_to_string(lockfile)
+        .with_context(|| format!("Gemfile.lock の読み込みに失敗しました: {:?}", lockfile))?;
+    Ok(parse_content(&content))
+}
+
+/// 文字列から Gemfile.lock を解析する（テスト可能な純粋関数）。
+/// GEM / GIT / PATH の各セクションの `specs:` と `DEPENDENCIES` を対象とする。
+pub fn parse_content(content: &str) -> Lockfile {
+    let mut lock = Lockfile::default();
+    let mut section = "";
+    let mut in_specs = false;
+
+    for line in content.lines() {
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+
+        if indent == 0 {
+            // 新しいセクション (GEM, PLATFORMS, DEPENDENCIES ...)
+            section = trimmed;
+            in_specs = false;
+            continue;
+        }
+        if section == "BUNDLED WITH" {
+            if !trimmed.is_empty() {
+                lock.bundled_with = Some(trimmed.to_string());
+            }
+            continue;
+        }
+        if section == "DEPENDENCIES" {
+            if indent == 2 && !trimmed.is_empty() {
+                lock.dependencies.push(dependency_name(trimmed).to_string());
+            }
+            continue;
+        }
+        if indent == 2 {
+            in_specs = trimmed == "specs:";
+            continue;
+        }
+        if !in_specs {
+            continue;
+        }
+        if indent == 4 {
+            if let Some(spec) = parse_spec_line(trimmed) {
+                lock.specs.push(spec);
+            }
+        } else if indent == 6
+            && let Some(spec) = lock.specs.last_mut() {
+                match dependency_name(trimmed) {
+                    "ruby" => spec.required_ruby_version = dependency_requirement(trimmed).map(String::from),
+                    name => spec.dependencies.push(name.to_string()),
+                }
+            }
+    }
+
+    lock
+}
+
+/// `name (version)` 形式の行を解析する。
+fn parse_spec_line(line: &str) -> Option<LockSpec> {
+    let (name, rest) = line.split_once(' ')?;
+    let version = rest.strip_prefix('(')?.strip_suffix(')')?;
+    Some(LockSpec {
+        name: name.to_string(),
+        version: version.to_string(),
+        dependencies: vec![],
+        required_ruby_version: None,
+    })
+}
+
+/// `json (~> 2.3)` / `my_gem!` のような依存行から Gem 名だけを取り出す。
+fn dependency_name(line: &str) -> &str {
+    let name = line.split_whitespace().next().unwrap_or(line);
+    name.trim_end_matches('!')
+}
+
+/// `json (~> 2.3)` から括弧の中の要件を取り出す。
+fn dependency_requirement(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once('(')?;
+    rest.strip_suffix(')')
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    ast (2.4.2)
+    rubocop (1.60.0)
+      ast (~> 2.4)
+      json (~> 2.3)
+
+PLATFORMS
+  x86_64-linux
+
+DEPENDENCIES
+  rubocop
+
+BUNDLED WITH
+   2.5.3
+";
+
+    #[test]
+    fn test_parse_specs() {
+        let lock = parse_content(LOCK);
+        let names: Vec<&str> = lock.specs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["ast", "rubocop"]);
+        assert_eq!(lock.specs[1].version, "1.60.0");
+        assert_eq!(lock.specs[1].dependencies, ["ast", "json"]);
+        assert!(lock.specs[0].dependencies.is_empty());
+    }
+
+    #[test]
+    fn test_parse_direct_dependencies() {
+        let lock = parse_content("DEPENDENCIES\n  rails (~> 7.0)\n  my_gem!\n\nBUNDLED WITH\n   2.5.3\n");
+        assert!(lock.specs.is_empty());
+        assert_eq!(lock.dependencies, ["rails", "my_gem"]);
+        assert_eq!(lock.bundled_with.as_deref(), Some("2.5.3"));
+    }
+
+    #[test]
+    fn test_version_and_platform() {
+        let lock = parse_content("GEM\n  specs:\n    nokogiri (1.16.0-x86_64-linux)\n    rake (13.1.0)\n");
+        assert_eq!(lock.specs[0].version_and_platform(), ("1.16.0", Some("x86_64-linux")));
+        assert_eq!(lock.specs[1].version_and_platform(), ("13.1.0", None));
+    }
+
+    #[test]
+    fn test_required_ruby_version_line() {
+        let lock = parse_content("GEM\n  specs:\n    nokogiri (1.16.0)\n      racc (~> 1.4)\n      ruby (>= 3.0, < 3.4.dev)\n    rake (13.1.0)\n");
+        assert_eq!(lock.specs[0].required_ruby_version.as_deref(), Some(">= 3.0, < 3.4.dev"));
+        assert_eq!(lock.specs[0].dependencies, ["racc"]);
+        assert_eq!(lock.specs[1].required_ruby_version, None);
+    }
+}
@@ -1,29 +1,289 @@
+mod abi;
+mod arc_home;
+mod binstubs;
+mod blobs;
+mod bundler_config;
+mod cache_layout;
+mod cache_stats;
 mod cli;
 mod commands;
 mod config;
+#[cfg_attr(not(feature = "tui"), allow(dead_code))] // 画面 (ui) は tui feature のみ
+mod dashboard;
+mod deptree;
+mod deterministic;
+mod disk_stats;
 mod display;
+mod dry_run;
+mod env_diff;
+mod env_lock;
+mod exec_context;
+mod exec_label;
+mod executor;
+mod flaky;
+mod follow;
+mod fs_util;
 mod gemfile;
+mod gemfile_hash;
+mod intent;
+mod interpreter;
+mod json_preview;
+mod licenses;
+mod link;
+mod lockfile;
+mod output_store;
+mod overhead;
+mod perms;
+mod progress;
+mod project_lock;
+mod prompt;
+mod prune;
+mod pty;
+mod read_only;
+mod registry;
+mod ruby_version;
+mod safe_path;
+mod signal_hooks;
 mod signals;
+mod snapshot;
 mod state;
+mod stats_compare;
+mod stats_export;
+mod suggest;
+mod sync_state;
+mod task_pool;
+mod template;
+mod transcript;
+mod type_filter;
+#[cfg(feature = "tui")]
+mod ui;
+mod upgrade_log;
+mod version_req;
+mod workspace;
+mod worktree;
 
 use anyhow::Result;
-use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{BaselineCommand, BundleConfigCommand, CacheCommand, Cli, Commands, ConfigCommand, EnvCommand, GemfileCommand, ToolCommand, WsCommand};
 
 fn main() -> Result<()> {
-    let cli = Cli::parse();
+    overhead::start();
+    let cli = Cli::try_parse_suggesting(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    if cli.timings || cli.verbose {
+        overhead::enable_footer();
+    }
+    display::set_verbose(cli.verbose);
+    if let Some(lang) = &cli.lang {
+        display::set_lang(lang)?;
+    }
+    if cli.plain || std::env::var("TERM").is_ok_and(|t| t == "dumb") {
+        display::enable_plain();
+    }
+    if cli.no_signal_hooks {
+        signal_hooks::disable();
+    }
+    perms::enable_from_env();
+    deterministic::enable_from_env()?;
+    if cli.progress_json {
+        progress::enable_json(cli.progress_fd)?;
+    }
+    let dry = cli.dry_run;
+    if dry {
+        dry_run::enable();
+    }
+
+    let cwd_flux = std::env::current_dir().map(|d| d.join(signals::FLUX_DIR)).unwrap_or_default();
+    if cli.read_only || read_only::detect(&cwd_flux) {
+        display::verbose("🔒 Read-only mode: .flux is not written");
+        read_only::enable();
+    }
+    // arc prompt はプロンプトの描画ごとに呼ばれるため、警告を出さず、レジストリも書き換えない
+    let prompt = matches!(cli.command, Commands::Prompt { .. });
+    if !prompt && !matches!(cli.command, Commands::Doctor { .. }) {
+        intent::warn_if_pending(&cwd_flux);
+    }
+    let mutating = mutating(&cli.command);
+    if let Some((name, _)) = &mutating {
+        read_only::refuse(name)?;
+    }
+
+    // プロジェクトを変更するコマンドは、実行中ずっと .flux/lock を保持する
+    let _lock = match mutating {
+        Some((_, root)) if !dry && root.join(signals::FLUX_DIR).is_dir() => {
+            let timeout = if cli.no_wait { 0 } else { cli.lock_timeout };
+            let command = std::env::args().collect::<Vec<_>>().join(" ");
+            let lock = project_lock::acquire(&root.join(signals::FLUX_DIR), &command, std::time::Duration::from_secs(timeout))?;
+            // Signal ログがない・空のプロジェクトでも、記録する前に正しい権限でログを作っておく
+            let project = signals::FluxProject::open(&root)?;
+            project.ensure_signal_file()?;
+            lock.record_broken(&project)?;
+            if !matches!(cli.command, Commands::Gc { .. }) {
+                commands::auto_retention(&project);
+            }
+            Some(lock)
+        }
+        _ => None,
+    };
 
-    match cli.command {
-        Commands::Init { path }                     => commands::init(&path),
-        Commands::State { json, raw, diff, r#type } => commands::state(json, raw, diff, r#type),
-        Commands::Exec { command }                  => commands::exec(&command),
-        Commands::Sync                              => commands::sync(),
-        Commands::Add { gem, version }              => commands::add(&gem, version.as_deref()),
-        Commands::Remove { gem }                    => commands::remove(&gem),
-        Commands::Undo                              => commands::undo(),
-        Commands::Bootstrap { version }             => commands::bootstrap(version.as_deref()),
-        Commands::Run { command }                   => commands::run(&command),
-        Commands::Env                               => commands::env(),
-        Commands::Shell                             => commands::shell(),
+    let ctx = commands::CommandContext::new(dry);
+    let result = match cli.command {
+        Commands::Init { path, name, description, interactive, no_interactive } => {
+            let interactive = match (interactive, no_interactive) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            };
+            commands::init(&ctx, &path, name, description, interactive)
+        }
+        Commands::Adopt                             => commands::adopt(&ctx),
+        Commands::State { activity: true, json, ascii, from, .. } => commands::activity(&ctx, json, ascii, from.as_deref()),
+        Commands::State { summary: true, from, .. } => commands::state_summary(&ctx, from.as_deref()),
+        Commands::State { ruby_history: true, json, from, .. } => commands::ruby_history(&ctx, json, from.as_deref()),
+        Commands::State { json, stream, r#type, user, limit, offset, cursor, from, .. } if json || stream => {
+            commands::state_json(&ctx, r#type, user.as_deref(), cursor, offset, limit, stream, from.as_deref())
+        }
+        Commands::State { limit, offset, cursor, .. } if limit.is_some() || offset > 0 || cursor.is_some() => {
+            anyhow::bail!("--limit / --offset / --cursor は --json か --stream と一緒に指定してください。")
+        }
+        Commands::State { raw, diff, r#type, all, layout, user, failures_verbose, from, .. } => {
+            commands::state(&ctx, raw, diff, r#type, all, layout, user.as_deref(), failures_verbose, from.as_deref())
+        }
+        Commands::Stats { export: Some(format), output, all_commands, all, from, .. } => {
+            commands::export_stats(&ctx, format, output.as_deref(), all_commands, all, from.as_deref())
+        }
+        Commands::Stats { disk: true, from, .. }    => commands::disk_stats(&ctx, from.as_deref()),
+        Commands::Stats { compare_signals: Some(range), threshold, all, from, .. } => {
+            commands::compare_stats(&ctx, commands::CompareWindows::AroundSignals(range), threshold, all, from.as_deref())
+        }
+        Commands::Stats { compare: true, before: Some(before), after: Some(after), threshold, all, from, .. } => {
+            commands::compare_stats(&ctx, commands::CompareWindows::Ranges(before, after), threshold, all, from.as_deref())
+        }
+        Commands::Stats { flaky: true, window, all, user, from, .. } => {
+            commands::flaky_stats(&ctx, window.as_deref(), all, user.as_deref(), from.as_deref())
+        }
+        Commands::Stats { follow: Some(pattern), regex, watch, all, user, from, .. } => {
+            commands::follow(&ctx, &pattern, regex, watch, all, user.as_deref(), from.as_deref())
+        }
+        Commands::Stats { cache, all, layout, by, user, command, from, .. } => {
+            commands::stats(&ctx, cache, all, layout, by, user.as_deref(), command.as_deref(), from.as_deref())
+        }
+        Commands::Exec { shell, yes, command }      => commands::exec(&ctx, &command, shell, yes),
+        Commands::Sync { check, force, force_rebuild, no_preflight } => {
+            commands::sync(&ctx, check, force, force_rebuild, no_preflight)
+        }
+        Commands::Add { gem, version, yes, dry_run } => commands::add(&ctx, &gem, version.as_deref(), yes, dry_run),
+        Commands::Remove { gem, yes, purge, dry_run } => commands::remove(&ctx, gem.as_deref(), yes, purge, dry_run),
+        Commands::PruneGems { dry_run }             => commands::prune_gems(&ctx, dry_run),
+        Commands::Licenses { group_by, json, fail_on } => commands::licenses(&ctx, group_by, json, &fail_on),
+        Commands::Undo { yes, dry_run, force, exact, all_since } => commands::undo(
+            &ctx,
+            yes,
+            commands::UndoOptions { dry_run, force, exact, interactive: false },
+            all_since.as_deref(),
+        ),
+        Commands::Bootstrap { versions, cache_only, installed, use_version, allow_downgrade, ignore_ruby_constraints } => {
+            commands::bootstrap(&ctx, &versions, cache_only, installed, use_version.as_deref(), allow_downgrade, ignore_ruby_constraints)
+        }
+        Commands::Run { list_bins: true, .. }       => commands::list_bins(&ctx),
+        Commands::Run { list_aliases: true, .. }    => commands::list_aliases(&ctx),
+        Commands::Run { detach, shell, yes, spring, sandbox_home, command, .. } => {
+            commands::run(&ctx, &command, detach, false, shell, yes, spring, sandbox_home)
+        }
+        Commands::R { command }                     => commands::run(&ctx, &command, false, true, false, false, false, false),
+        Commands::Test { args }                     => commands::task(&ctx, "test", &args),
+        Commands::Task { name, args }               => commands::task(&ctx, &name, &args),
+        Commands::Ps                                => commands::ps(&ctx),
+        Commands::Stop { id }                       => commands::stop(&ctx, &id),
+        Commands::Output { id, stderr }             => commands::output(&ctx, &id, stderr),
+        Commands::Reap { alias, spring, sandbox_home, fields, command } => {
+            commands::reap(&ctx, &command, alias.as_deref(), spring, sandbox_home, fields.as_deref())
+        }
+        Commands::Gc { retention, dry_run }         => commands::gc(&ctx, retention, dry_run),
+        Commands::Ui                                => commands::ui(&ctx),
+        Commands::UpgradeLog                        => commands::upgrade_log(&ctx),
+        Commands::Clean { runtime, bootsnap, home, all, yes } => {
+            commands::clean(&ctx, runtime || all, bootsnap || all, home || all, yes)
+        }
+        Commands::Tree { gem, invert, depth, json } => {
+            commands::tree(&ctx, gem.as_deref(), invert.as_deref(), depth, json)
+        }
+        Commands::Env { command: Some(EnvCommand::Diff { other, base }), .. } => commands::env_diff(&ctx, &other, base.as_deref()),
+        Commands::Env { check_path, verify_lock, names, .. } => commands::env(&ctx, check_path, verify_lock, &names),
+        Commands::Config { command: ConfigCommand::List { resolved } } => commands::config_list(&ctx, resolved),
+        Commands::Config { command: ConfigCommand::Set { key, value } } => commands::config_set(&ctx, &key, &value),
+        Commands::Gemfile { command: GemfileCommand::Check { path } } => commands::gemfile_check(&ctx, path.as_deref()),
+        Commands::Gemfile { command: GemfileCommand::Sort { check, write } } => commands::gemfile_sort(&ctx, check, write),
+        Commands::BundleConfig { command }          => commands::bundle_config(&ctx, command),
+        Commands::Cache { command: CacheCommand::Warm { from_lockfile, ruby } } => {
+            commands::cache_warm(&ctx, &from_lockfile, ruby.as_deref())
+        }
+        Commands::Cache { command: CacheCommand::Clean { all: _ } } => commands::cache_clean(&ctx),
+        Commands::Recent { limit, json }            => commands::recent(&ctx, limit, json),
+        Commands::Report { json, output }           => commands::report(&ctx, json, output.as_deref()),
+        Commands::Export { bundle, redact, max_size } => commands::export(&ctx, &bundle, redact, max_size),
+        Commands::Import { file, path }             => commands::import(&ctx, &file, &path),
+        Commands::Binstub { scripts, all, remove } => commands::binstub(&ctx, &scripts, all, remove),
+        Commands::Prompt { format, init } => commands::prompt(&ctx, format.as_deref(), init),
+        Commands::Shell { record_history, transcript, transcript_format } => {
+            commands::shell(&ctx, record_history, transcript.then_some(transcript_format))
+        }
+        Commands::Changelog { since, until, format, include_reverted, out } => {
+            commands::changelog(&ctx, since.as_deref(), until.as_deref(), format, include_reverted, out.as_deref())
+        }
+        Commands::Doctor { resolve_intent }         => commands::doctor(&ctx, resolve_intent),
+        Commands::Ws { command: WsCommand::State }  => commands::workspace::state(&ctx),
+        Commands::Baseline { command: BaselineCommand::Set { name, command, signal } } => {
+            commands::baseline::set(&ctx, &name, command.as_deref(), signal.as_deref())
+        }
+        Commands::Baseline { command: BaselineCommand::Compare { name, command, max_regression } } => {
+            commands::baseline::compare_cmd(&ctx, &name, command.as_deref(), max_regression)
+        }
+        Commands::Baseline { command: BaselineCommand::List } => commands::baseline::list(&ctx),
+        Commands::Tool { command: ToolCommand::Install { gem, version } } => commands::tool::install(&ctx, &gem, version.as_deref()),
+        Commands::Tool { command: ToolCommand::List } => commands::tool::list(&ctx),
+        Commands::Tool { command: ToolCommand::Uninstall { gem, version } } => commands::tool::uninstall(&ctx, &gem, version.as_deref()),
+        Commands::Tool { command: ToolCommand::Run { gem, version, args } } => commands::tool::run(&ctx, &gem, version.as_deref(), &args),
+        Commands::Ws { command: WsCommand::Sync { max_parallel, keep_going } } => {
+            commands::workspace::run(&ctx, "sync", &["sync".to_string()], max_parallel, keep_going)
+        }
+        Commands::Ws { command: WsCommand::Exec { max_parallel, keep_going, command } } => {
+            let args: Vec<String> = std::iter::once("exec".to_string()).chain(command).collect();
+            commands::workspace::run(&ctx, "exec", &args, max_parallel, keep_going)
+        }
+    };
+
+    dry_run::print_summary();
+    signal_hooks::wait_pending(signal_hooks::EXIT_WAIT);
+    overhead::print_footer();
+    // 成功したコマンドのプロジェクトを「最後に使ったプロジェクト」として記録する
+    if result.is_ok() && !dry && !prompt {
+        let _ = registry::touch_current();
     }
+    result
+}
+
+/// プロジェクトを変更するコマンドの名前と、ロックを取るプロジェクトのルート。
+/// 読み取りだけのコマンド (と、記録だけをする exec / run) は `None`
+fn mutating(command: &Commands) -> Option<(&'static str, std::path::PathBuf)> {
+    let name = match command {
+        Commands::Adopt => "adopt",
+        Commands::Sync { check: false, .. } => "sync",
+        Commands::Add { .. } => "add",
+        Commands::Remove { .. } => "remove",
+        Commands::Undo { .. } => "undo",
+        Commands::Bootstrap { installed: false, .. } => "bootstrap",
+        Commands::Clean { .. } => "clean",
+        Commands::PruneGems { .. } => "prune-gems",
+        Commands::UpgradeLog => "upgrade-log",
+        Commands::Gc { .. } => "gc",
+        Commands::Doctor { resolve_intent: true } => "doctor",
+        Commands::Binstub { .. } => "binstub",
+        Commands::Config { command: ConfigCommand::Set { .. } } => "config",
+        Commands::Gemfile { command: GemfileCommand::Sort { write: true, .. } } => "gemfile",
+        Commands::BundleConfig { command: BundleConfigCommand::Set { .. } | BundleConfigCommand::Unset { .. } } => "bundle-config",
+        Commands::Baseline { command: BaselineCommand::Set { .. } } => "baseline",
+        Commands::Init { path, .. } => return Some(("init", path.clone())),
+        Commands::Import { path, .. } => return Some(("import", path.clone())),
+        _ => return None,
+    };
+    Some((name, std::env::current_dir().ok()?))
 }
@@ -0,0 +1,390 @@
+//! `.flux/output/` (デタッチ実行の stdout / stderr) の容量管理。
+//!
+//! 出力は `<run_start の ID>.out` / `.err` に保存される。`arc shell --transcript` の記録
+//! (`<shell_enter の ID>.transcript` / `.cast`) も同じく扱う。`[output]` の上限は新しい出力を作る直前
+//! (reaper) と `arc gc` で次の順に適用する。実行中のプロセスの出力には触れない。
+//!
+//! 1. `max_file_mb` を超えたファイルを、末尾だけを残して切り詰める (先頭に印を付ける)
+//! 2. `compress = true` なら、1 日より古いファイルを gzip で圧縮する (`<id>.out.gz`)
+//! 3. `max_age_days` より古い出力と、`max_total_mb` を超えた分の古い出力を削除する
+//!
+//! 読み出しは `read` を通し、圧縮済みのファイルも透過的に展開する。
+
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use crate::config::OutputConfig;
+
+/// 出力の種類 (ファイルの拡張子)
+pub const STREAMS: [&str; 2] = ["out", "err"];
+/// 圧縮済みファイルの拡張子
+const GZ_EXT: &str = "gz";
+/// これより古いファイルを圧縮する
+const COMPRESS_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+const MB: u64 = 1024 * 1024;
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// `[output]` の上限 (バイト・期間に換算したもの)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policy {
+    pub max_total_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub max_file_bytes: Option<u64>,
+    pub compress: bool,
+}
+
+impl Policy {
+    pub fn from_config(config: &OutputConfig) -> Self {
+        Self {
+            max_total_bytes: config.max_total_mb.map(|mb| mb * MB),
+            max_age: config.max_age_days.map(|days| Duration::from_secs(days * DAY_SECS)),
+            max_file_bytes: config.max_file_mb.map(|mb| mb * MB),
+            compress: config.compress,
+        }
+    }
+
+    fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 1 回の実行の出力 (`<id>.out` / `<id>.err`、圧縮済みを含む)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub id: String,
+    pub files: Vec<PathBuf>,
+    pub bytes: u64,
+    /// ファイルのうち最も新しい更新時刻
+    pub modified: SystemTime,
+}
+
+/// 削除の理由
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// `max_age_days` より古い
+    Age,
+    /// `max_total_mb` を超えた
+    Size,
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Age => "max_age_days",
+            Self::Size => "max_total_mb",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Eviction {
+    pub id: String,
+    pub bytes: u64,
+    pub reason: Reason,
+}
+
+/// `enforce` の結果
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub evicted: Vec<Eviction>,
+    pub truncated: usize,
+    pub compressed: usize,
+}
+
+impl GcReport {
+    /// 期限内 (`max_age_days` 以内) なのに容量のために削除した出力
+    pub fn early_evictions(&self) -> impl Iterator<Item = &Eviction> {
+        self.evicted.iter().filter(|e| e.reason == Reason::Size)
+    }
+}
+
+/// `<id>.<stream>[.gz]` から `(id, stream)` を取り出す。出力ファイルでなければ `None`
+fn parse_name(name: &str) -> Option<(&str, &str)> {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let (id, stream) = name.rsplit_once('.')?;
+    let known = STREAMS.contains(&stream) || crate::transcript::EXTENSIONS.contains(&stream);
+    (known && !id.is_empty() && !id.starts_with('.')).then_some((id, stream))
+}
+
+/// 出力ディレクトリの出力を、古い順 (更新時刻、同じなら ID の順) に返す。
+pub fn scan(dir: &Path) -> Result<Vec<Capture>> {
+    let Ok(entries) = fs::read_dir(dir) else { return Ok(vec![]) };
+    let mut captures: Vec<Capture> = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some((id, _)) = parse_name(&name) else { continue };
+        let meta = entry.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        match captures.iter_mut().find(|c| c.id == id) {
+            Some(capture) => {
+                capture.files.push(entry.path());
+                capture.bytes += meta.len();
+                capture.modified = capture.modified.max(modified);
+            }
+            None => captures.push(Capture { id: id.to_string(), files: vec![entry.path()], bytes: meta.len(), modified }),
+        }
+    }
+    for capture in &mut captures {
+        capture.files.sort();
+    }
+    captures.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.id.cmp(&b.id)));
+    Ok(captures)
+}
+
+/// 出力ディレクトリの (出力の数, 合計バイト数)
+pub fn usage(dir: &Path) -> Result<(usize, u64)> {
+    let captures = scan(dir)?;
+    Ok((captures.len(), captures.iter().map(|c| c.bytes).sum()))
+}
+
+/// 削除する出力を決める。`keep` (実行中のプロセス) の出力は削除しない。
+/// 期限切れの出力をすべて削除したうえで、合計が上限に収まるまで古い順に削除する。
+pub fn plan_evictions(captures: &[Capture], policy: &Policy, now: SystemTime, keep: &BTreeSet<String>) -> Vec<Eviction> {
+    let mut evictions = Vec::new();
+    let mut total: u64 = captures.iter().map(|c| c.bytes).sum();
+    let expired = |c: &Capture| {
+        policy
+            .max_age
+            .is_some_and(|max| now.duration_since(c.modified).is_ok_and(|age| age > max))
+    };
+
+    for capture in captures.iter().filter(|c| !keep.contains(&c.id)) {
+        let reason = if expired(capture) {
+            Reason::Age
+        } else if policy.max_total_bytes.is_some_and(|max| total > max) {
+            Reason::Size
+        } else {
+            continue;
+        };
+        total -= capture.bytes;
+        evictions.push(Eviction { id: capture.id.clone(), bytes: capture.bytes, reason });
+    }
+    evictions
+}
+
+/// 切り詰めたファイルの先頭に付ける印
+fn truncation_marker(dropped: u64, kept: u64) -> String {
+    format!("[arc: output truncated — dropped the first {} bytes, kept the last {} bytes]\n", dropped, kept)
+}
+
+/// `max` バイトを超えたファイルを末尾の `max` バイトだけに切り詰める。切り詰めたら `true`
+pub fn truncate(path: &Path, max: u64) -> Result<bool> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+    let len = file.metadata()?.len();
+    if len <= max {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::Start(len - max))?;
+    let mut tail = Vec::with_capacity(max as usize);
+    file.read_to_end(&mut tail)?;
+
+    let tmp = path.with_extension("truncating");
+    let mut out = File::create(&tmp).with_context(|| format!("Failed to write {:?}", tmp))?;
+    out.write_all(truncation_marker(len - max, max).as_bytes())?;
+    out.write_all(&tail)?;
+    out.set_modified(file.metadata()?.modified()?)?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(true)
+}
+
+/// `gzip` で圧縮する (`<path>.gz` に置き換わる。更新時刻は元のファイルのまま)
+fn compress(path: &Path) -> Result<()> {
+    let status = Command::new("gzip")
+        .arg("-n")
+        .arg("-f")
+        .arg(path)
+        .status()
+        .context("gzip を実行できません")?;
+    anyhow::ensure!(status.success(), "{:?} の圧縮に失敗しました ({})", path, status);
+    Ok(())
+}
+
+/// `[output]` の上限を適用する。`keep` (実行中のプロセス) の出力には触れない。
+pub fn enforce(dir: &Path, policy: &Policy, keep: &BTreeSet<String>, now: SystemTime) -> Result<GcReport> {
+    let mut report = GcReport::default();
+    if policy.is_unlimited() || !dir.exists() {
+        return Ok(report);
+    }
+
+    for capture in scan(dir)?.iter().filter(|c| !keep.contains(&c.id)) {
+        for path in &capture.files {
+            let compressed = path.extension().is_some_and(|e| e == GZ_EXT);
+            if let Some(max) = policy.max_file_bytes
+                && !compressed
+                && truncate(path, max)?
+            {
+                report.truncated += 1;
+            }
+            let old = now.duration_since(capture.modified).is_ok_and(|age| age > COMPRESS_AFTER);
+            if policy.compress && !compressed && old {
+                compress(path)?;
+                report.compressed += 1;
+            }
+        }
+    }
+
+    // 切り詰め・圧縮で大きさが変わるため、読み直してから決める
+    let captures = scan(dir)?;
+    report.evicted = plan_evictions(&captures, policy, now, keep);
+    for eviction in &report.evicted {
+        let capture = captures.iter().find(|c| c.id == eviction.id).expect("planned from scan");
+        for path in &capture.files {
+            fs::remove_file(path).with_context(|| format!("Failed to remove {:?}", path))?;
+        }
+    }
+    Ok(report)
+}
+
+/// 出力を読む。圧縮済みなら展開する。どちらのファイルもなければ `None`
+pub fn read(dir: &Path, id: &str, stream: &str) -> Result<Option<String>> {
+    let plain = crate::safe_path::join_within(dir, format!("{}.{}", id, stream))?;
+    if let Ok(bytes) = fs::read(&plain) {
+        return Ok(Some(String::from_utf8_lossy(&bytes).into_owned()));
+    }
+    let gz = crate::safe_path::join_within(dir, format!("{}.{}.{}", id, stream, GZ_EXT))?;
+    if !gz.exists() {
+        return Ok(None);
+    }
+    let output = Command::new("gzip")
+        .arg("-dc")
+        .arg(&gz)
+        .output()
+        .context("gzip を実行できません")?;
+    anyhow::ensure!(output.status.success(), "{:?} を展開できません: {}", gz, String::from_utf8_lossy(&output.stderr).trim());
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(DAY_SECS);
+
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// `<id>.out` を `bytes` バイト、`age` だけ前の更新時刻で作る
+    fn write_capture(dir: &Path, id: &str, bytes: usize, age: Duration, now: SystemTime) {
+        let path = dir.join(format!("{}.out", id));
+        fs::write(&path, "x".repeat(bytes)).unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(now - age).unwrap();
+    }
+
+    fn capture(id: &str, bytes: u64, age_days: u64, now: SystemTime) -> Capture {
+        Capture { id: id.to_string(), files: vec![], bytes, modified: now - DAY * age_days as u32 }
+    }
+
+    fn ids(evictions: &[Eviction]) -> Vec<(&str, Reason)> {
+        evictions.iter().map(|e| (e.id.as_str(), e.reason)).collect()
+    }
+
+    #[test]
+    fn test_parse_name() {
+        assert_eq!(parse_name("0193.out"), Some(("0193", "out")));
+        assert_eq!(parse_name("0193.err.gz"), Some(("0193", "err")));
+        assert_eq!(parse_name("0194.cast.gz"), Some(("0194", "cast")));
+        assert_eq!(parse_name("0194.transcript"), Some(("0194", "transcript")));
+        assert_eq!(parse_name("0193.log"), None);
+        assert_eq!(parse_name(".0193.out"), None);
+    }
+
+    #[test]
+    fn test_plan_evictions_by_age_then_size() {
+        let now = SystemTime::now();
+        let captures = [capture("a", 40, 10, now), capture("b", 40, 3, now), capture("c", 40, 2, now), capture("d", 40, 0, now)];
+        let policy = Policy { max_total_bytes: Some(100), max_age: Some(DAY * 7), ..Default::default() };
+
+        // a は期限切れ、残り 120 バイトから b を消して 80 バイトに収める
+        let plan = plan_evictions(&captures, &policy, now, &BTreeSet::new());
+        assert_eq!(ids(&plan), vec![("a", Reason::Age), ("b", Reason::Size)]);
+
+        // 実行中の出力は残し、次に古いものを消す
+        let keep = BTreeSet::from(["b".to_string()]);
+        let plan = plan_evictions(&captures, &policy, now, &keep);
+        assert_eq!(ids(&plan), vec![("a", Reason::Age), ("c", Reason::Size)]);
+
+        assert!(plan_evictions(&captures, &Policy::default(), now, &BTreeSet::new()).is_empty());
+    }
+
+    #[test]
+    fn test_truncate_keeps_tail_with_marker() {
+        let dir = fixture("arc_output_truncate_test");
+        let path = dir.join("0001.out");
+        fs::write(&path, "0123456789").unwrap();
+
+        assert!(!truncate(&path, 10).unwrap());
+        assert!(truncate(&path, 4).unwrap());
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, format!("{}6789", truncation_marker(6, 4)));
+        assert!(!dir.join("0001.truncating").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_output_is_read_transparently() {
+        let dir = fixture("arc_output_compress_test");
+        let now = SystemTime::now();
+        write_capture(&dir, "old", 5, DAY * 2, now);
+        write_capture(&dir, "new", 5, Duration::ZERO, now);
+        fs::write(dir.join("old.err"), "boom\n").unwrap();
+        File::options().write(true).open(dir.join("old.err")).unwrap().set_modified(now - DAY * 2).unwrap();
+
+        let report = enforce(&dir, &Policy { compress: true, ..Default::default() }, &BTreeSet::new(), now).unwrap();
+        assert_eq!(report.compressed, 2);
+        assert!(dir.join("old.out.gz").exists() && !dir.join("old.out").exists());
+        assert!(dir.join("new.out").exists());
+
+        assert_eq!(read(&dir, "old", "out").unwrap().as_deref(), Some("xxxxx"));
+        assert_eq!(read(&dir, "old", "err").unwrap().as_deref(), Some("boom\n"));
+        assert_eq!(read(&dir, "new", "out").unwrap().as_deref(), Some("xxxxx"));
+        assert_eq!(read(&dir, "missing", "out").unwrap(), None);
+
+        // 圧縮しても更新時刻は変わらず、古い順のまま
+        let order: Vec<String> = scan(&dir).unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(order, ["old", "new"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_enforce_fills_past_cap_and_keeps_newest() {
+        let dir = fixture("arc_output_enforce_test");
+        let now = SystemTime::now();
+        // 古い順に c0 .. c5 (各 1 KiB)
+        for i in 0..6u32 {
+            write_capture(&dir, &format!("c{}", i), 1024, Duration::from_secs(60 * (6 - i as u64)), now);
+        }
+        write_capture(&dir, "huge", 10 * 1024, Duration::from_secs(30), now);
+        let keep = BTreeSet::from(["c1".to_string()]);
+        let policy = Policy { max_total_bytes: Some(5 * 1024), max_file_bytes: Some(2048), ..Default::default() };
+
+        let report = enforce(&dir, &policy, &keep, now).unwrap();
+        assert_eq!(report.truncated, 1);
+        assert_eq!(ids(&report.evicted), vec![("c0", Reason::Size), ("c2", Reason::Size), ("c3", Reason::Size), ("c4", Reason::Size)]);
+        assert_eq!(report.early_evictions().count(), 4);
+
+        let survivors: Vec<String> = scan(&dir).unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(survivors, ["c1", "c5", "huge"]);
+        assert!(read(&dir, "huge", "out").unwrap().unwrap().starts_with("[arc: output truncated"));
+        let (count, bytes) = usage(&dir).unwrap();
+        assert_eq!(count, 3);
+        assert!(bytes <= 5 * 1024);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+//! arc 自身のオーバーヘッドの計測。
+//!
+//! プロジェクトを開く・設定を読む・Signal の読み書き・キャッシュの復元などの処理を `scope` で囲み、
+//! 子プロセスの実行時間 (`CHILD`) を除いた経過時間を arc のオーバーヘッドとする。
+//! `--timings` / `--verbose` で終了時に内訳を表示し、end Signal の payload に `arc_overhead_ms` を記録する:
+//!
+//! ```text
+//! arc overhead: 38ms (open 4ms · read 21ms · record 2×6ms)
+//! ```
+//!
+//! 入れ子になった `scope` は外側だけを数える (子プロセスは常に数える)。
+
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
+
+use crate::display;
+
+/// プロジェクトを開く (`FluxProject::open`)
+pub const OPEN: &str = "open";
+/// config.toml の読み込み
+pub const CONFIG: &str = "config";
+/// Signal ログの読み込み
+pub const READ: &str = "read";
+/// Signal の記録
+pub const RECORD: &str = "record";
+/// キャッシュからの Gem の復元
+pub const RESTORE: &str = "restore";
+/// 子プロセスの実行 (オーバーヘッドに含めない)
+pub const CHILD: &str = "child";
+
+/// end Signal の payload に記録するフィールド名
+pub const PAYLOAD_KEY: &str = "arc_overhead_ms";
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    name: &'static str,
+    count: u32,
+    total: Duration,
+}
+
+thread_local! {
+    static STARTED: Cell<Option<Instant>> = const { Cell::new(None) };
+    static ENTRIES: RefCell<Vec<Entry>> = const { RefCell::new(Vec::new()) };
+    /// 計測中の (子プロセス以外の) scope の深さ
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+    static FOOTER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 計測の起点 (コマンドの開始)。呼ばなければ最初の `scope` が起点になる
+pub fn start() {
+    STARTED.with(|s| s.set(Some(Instant::now())));
+}
+
+/// 終了時に内訳を表示する (`--timings` / `--verbose`)
+pub fn enable_footer() {
+    FOOTER.with(|f| f.set(true));
+}
+
+fn started() -> Instant {
+    STARTED.with(|s| *s.get().get_or_insert_with(Instant::now))
+}
+
+/// 計測中の区間。drop したときに所要時間を記録する
+pub struct Scope {
+    name: &'static str,
+    timer: Instant,
+    counted: bool,
+}
+
+/// `name` の区間の計測を始める。`let _timer = overhead::scope(overhead::READ);`
+pub fn scope(name: &'static str) -> Scope {
+    started();
+    let counted = name == CHILD || DEPTH.with(|d| d.get()) == 0;
+    if name != CHILD {
+        DEPTH.with(|d| d.set(d.get() + 1));
+    }
+    Scope { name, timer: Instant::now(), counted }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if self.name != CHILD {
+            DEPTH.with(|d| d.set(d.get().saturating_sub(1)));
+        }
+        if self.counted {
+            add(self.name, self.timer.elapsed());
+        }
+    }
+}
+
+fn add(name: &'static str, elapsed: Duration) {
+    ENTRIES.with(|entries| {
+        let mut entries = entries.borrow_mut();
+        match entries.iter_mut().find(|e| e.name == name) {
+            Some(entry) => {
+                entry.count += 1;
+                entry.total += elapsed;
+            }
+            None => entries.push(Entry { name, count: 1, total: elapsed }),
+        }
+    });
+}
+
+fn child_time() -> Duration {
+    ENTRIES.with(|e| e.borrow().iter().filter(|e| e.name == CHILD).map(|e| e.total).sum())
+}
+
+/// ここまでの arc のオーバーヘッド (起点からの経過時間から子プロセスの時間を除いたもの)
+pub fn elapsed() -> Duration {
+    started().elapsed().saturating_sub(child_time())
+}
+
+pub fn elapsed_ms() -> u64 {
+    elapsed().as_millis() as u64
+}
+
+/// `arc overhead: 38ms (open 4ms · read 21ms · record 2×6ms)`
+pub fn footer() -> String {
+    let us = |d: Duration| display::fmt_duration_us(d.as_micros() as u64);
+    let parts: Vec<String> = ENTRIES.with(|entries| {
+        entries
+            .borrow()
+            .iter()
+            .filter(|e| e.name != CHILD)
+            .map(|e| match e.count {
+                1 => format!("{} {}", e.name, us(e.total)),
+                n => format!("{} {}×{}", e.name, n, us(e.total / n)),
+            })
+            .collect()
+    });
+    match parts.is_empty() {
+        true => format!("arc overhead: {}", us(elapsed())),
+        false => format!("arc overhead: {} ({})", us(elapsed()), parts.join(" · ")),
+    }
+}
+
+/// `--timings` / `--verbose` のときだけ内訳を stderr に表示する。
+pub fn print_footer() {
+    if FOOTER.with(|f| f.get()) {
+        eprintln!("⏱  {}", footer());
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sleep(ms: u64) {
+        std::thread::sleep(Duration::from_millis(ms));
+    }
+
+    #[test]
+    fn test_child_time_is_excluded() {
+        start();
+        {
+            let _t = scope(OPEN);
+            sleep(5);
+        }
+        {
+            let _t = scope(CHILD);
+            sleep(200);
+        }
+        for _ in 0..2 {
+            let _t = scope(RECORD);
+            sleep(3);
+        }
+        let overhead = elapsed();
+        assert!(overhead >= Duration::from_millis(11), "{:?}", overhead);
+        assert!(overhead < Duration::from_millis(150), "{:?}", overhead);
+
+        let footer = footer();
+        assert!(footer.starts_with("arc overhead: "), "{}", footer);
+        assert!(footer.contains("(open ") && footer.contains(" · record 2×"), "{}", footer);
+        assert!(!footer.contains("child"), "{}", footer);
+    }
+
+    #[test]
+    fn test_nested_scopes_count_once() {
+        start();
+        {
+            let _outer = scope(OPEN);
+            let _inner = scope(CONFIG);
+            let _read = scope(READ);
+        }
+        let footer = footer();
+        assert!(footer.contains("(open ") && !footer.contains("config") && !footer.contains("read"), "{}", footer);
+        assert_eq!(DEPTH.with(|d| d.get()), 0);
+    }
+}
@@ -0,0 +1,149 @@
+//! 共有ディレクトリ向けのパーミッション (`[permissions] group_writable`)。
+//!
+//! デプロイサーバーなどでプロジェクトをグループで共有する場合、`.flux` や `.arc/env`、
+//! グローバルキャッシュが作成者の umask で作られると、他のメンバーが `signals.jsonl` に
+//! 追記できず、キャッシュも再利用できない。group_writable のときは:
+//!
+//! - arc が作るディレクトリを `0o2775` にする (setgid でグループ所有を引き継ぐ)
+//! - Signal ファイルを `0o664` にする
+//! - プロセスの umask を `0o002` にし、bundler などの子プロセスが作るファイルも揃える
+//!
+//! 設定されていない場合は従来どおり umask に従う。
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// group_writable のディレクトリのモード (setgid + rwxrwxr-x)
+pub const GROUP_DIR_MODE: u32 = 0o2775;
+/// group_writable のファイルのモード
+pub const GROUP_FILE_MODE: u32 = 0o664;
+/// 設定ファイルを使わずに有効にする環境変数
+pub const ENV_VAR: &str = "ARC_GROUP_WRITABLE";
+
+static GROUP_WRITABLE: AtomicBool = AtomicBool::new(false);
+
+/// group_writable を有効にする。一度有効にしたらプロセスの終了まで戻さない。
+pub fn enable_group_writable() {
+    if !GROUP_WRITABLE.swap(true, Ordering::Relaxed) {
+        unsafe { libc::umask(0o002) };
+    }
+}
+
+/// `ARC_GROUP_WRITABLE=1` なら有効にする。
+pub fn enable_from_env() {
+    if std::env::var(ENV_VAR).is_ok_and(|v| v == "1") {
+        enable_group_writable();
+    }
+}
+
+pub fn group_writable() -> bool {
+    GROUP_WRITABLE.load(Ordering::Relaxed)
+}
+
+/// 現在の設定で作るべきディレクトリのモード。`None` は umask に任せる。
+pub fn dir_mode() -> Option<u32> {
+    group_writable().then_some(GROUP_DIR_MODE)
+}
+
+/// 現在の設定で作るべきファイルのモード。`None` は umask に任せる。
+pub fn file_mode() -> Option<u32> {
+    group_writable().then_some(GROUP_FILE_MODE)
+}
+
+/// 現在の設定で `fs::create_dir_all` する。
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    create_dir_all_with_mode(path, dir_mode())
+}
+
+/// `fs::create_dir_all` と同じだが、新しく作ったディレクトリにだけ `mode` を設定する。
+/// umask の影響を受けないよう、作成後に明示的に chmod する。既存のディレクトリは変更しない。
+pub fn create_dir_all_with_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    let Some(mode) = mode else { return fs::create_dir_all(path) };
+    let missing: Vec<&Path> = path.ancestors().take_while(|p| !p.as_os_str().is_empty() && !p.exists()).collect();
+    fs::create_dir_all(path)?;
+    for dir in missing.iter().rev() {
+        fs::set_permissions(dir, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+/// 新しく作ったファイルに現在の設定のモードを設定する。
+pub fn apply_file_mode(path: &Path) -> io::Result<()> {
+    match file_mode() {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+        None => Ok(()),
+    }
+}
+
+/// コピー元から引き継いだディレクトリのモードに、必要ならグループの書き込みと setgid を加える。
+pub fn widen_dir(permissions: fs::Permissions) -> fs::Permissions {
+    match dir_mode() {
+        Some(mode) => fs::Permissions::from_mode(permissions.mode() | mode),
+        None => permissions,
+    }
+}
+
+/// 現在のユーザーが `path` に書き込めるか (存在しない場合は `true`)。
+pub fn writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    if !path.exists() {
+        return true;
+    }
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else { return false };
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode_of(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_create_dir_all_with_mode() {
+        let root = std::env::temp_dir().join("arc_perms_mode_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::set_permissions(&root, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let nested = root.join("a/b/c");
+        create_dir_all_with_mode(&nested, Some(GROUP_DIR_MODE)).unwrap();
+        for dir in ["a", "a/b", "a/b/c"] {
+            assert_eq!(mode_of(&root.join(dir)), GROUP_DIR_MODE, "{}", dir);
+        }
+        // 既存の祖先は変更しない
+        assert_eq!(mode_of(&root), 0o755);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_default_follows_umask() {
+        let root = std::env::temp_dir().join("arc_perms_default_test");
+        let _ = fs::remove_dir_all(&root);
+        let expected = root.with_extension("expected");
+        let _ = fs::remove_dir_all(&expected);
+
+        create_dir_all_with_mode(&root.join("x"), None).unwrap();
+        fs::create_dir_all(&expected).unwrap();
+        assert_eq!(mode_of(&root.join("x")), mode_of(&expected));
+        assert_eq!(mode_of(&root.join("x")) & 0o2000, 0);
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_dir_all(&expected).unwrap();
+    }
+
+    #[test]
+    fn test_widen_dir_when_disabled() {
+        // テストではプロセス全体の設定を変えないため、無効時の振る舞いだけを確かめる
+        let perms = fs::Permissions::from_mode(0o755);
+        assert_eq!(widen_dir(perms).mode() & 0o7777, 0o755);
+    }
+}
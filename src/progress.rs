@@ -0,0 +1,204 @@
+//! 長い処理 (`arc sync` / `arc bootstrap`) の進捗の出力先。
+//!
+//! 通常は人間向けの行を stderr に出す。`--progress-json` の場合は人間向けの出力を止め、
+//! 代わりに `ProgressEvent` を 1 行 1 JSON で stderr (または `--progress-fd` の fd) に書く。
+//! どちらのモードでも同じ呼び出し (`human` / `phase` / `emit`) を通すため、
+//! イベントと実際の処理がずれることはない。
+//!
+//! イベントの形式 (`event` フィールドで種別を判別する):
+//!
+//! ```json
+//! {"event":"phase_start","name":"bundler","at":"2026-03-02T09:00:00+09:00"}
+//! {"event":"phase_end","name":"bundler","at":"...","duration_us":1200}
+//! {"event":"download","bytes":1048576,"total":null}
+//! {"event":"child_spawned","pid":4242,"command":"bundle install"}
+//! {"event":"child_exited","pid":4242,"code":0}
+//! {"event":"summary","operation":"sync","success":true,"duration_us":5300}
+//! ```
+//!
+//! 既存のフィールドの意味は変えないこと (連携先がこの形式に依存する)。
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::{AsFd, FromRawFd};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// ダウンロードの進捗を出す間隔
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// フェーズの開始 (`at` は RFC 3339)
+    PhaseStart { name: String, at: String },
+    PhaseEnd { name: String, at: String, duration_us: u64 },
+    /// ダウンロード済みのバイト数。`total` は分かる場合のみ
+    Download { bytes: u64, total: Option<u64> },
+    /// 子プロセスの起動 (`command` はコマンドライン)
+    ChildSpawned { pid: u32, command: String },
+    /// 子プロセスの終了。シグナルで終了した場合 `code` は `null`
+    ChildExited { pid: u32, code: Option<i32> },
+    /// 処理全体の結果
+    Summary { operation: String, success: bool, duration_us: u64 },
+}
+
+/// `--progress-json` の出力先
+enum Sink {
+    Stderr,
+    Fd(File),
+}
+
+static SINK: OnceLock<Mutex<Sink>> = OnceLock::new();
+
+thread_local! {
+    /// テスト用: このスレッドのイベントを出力せずに溜める
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// `--progress-json` を有効にする。`fd` を指定した場合はその fd にイベントを書く。
+pub fn enable_json(fd: Option<i32>) -> Result<()> {
+    let sink = match fd {
+        Some(fd) => {
+            // 閉じた fd を渡された場合に備え、有効かどうかを先に確かめる
+            if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+                anyhow::bail!("--progress-fd {} は開かれていません", fd);
+            }
+            Sink::Fd(unsafe { File::from_raw_fd(fd) })
+        }
+        None => Sink::Stderr,
+    };
+    SINK.set(Mutex::new(sink)).ok().context("--progress-json は既に有効です")
+}
+
+pub fn is_json() -> bool {
+    CAPTURED.with(|c| c.borrow().is_some()) || SINK.get().is_some()
+}
+
+/// イベントを書く。人間向けのモードでは何もしない。
+pub fn emit(event: &ProgressEvent) {
+    let Ok(line) = serde_json::to_string(event) else { return };
+    let captured = CAPTURED.with(|c| match c.borrow_mut().as_mut() {
+        Some(lines) => {
+            lines.push(line.clone());
+            true
+        }
+        None => false,
+    });
+    if captured {
+        return;
+    }
+    if let Some(sink) = SINK.get()
+        && let Ok(mut sink) = sink.lock()
+    {
+        // 進捗の出力に失敗しても本来の処理は続ける
+        let _ = match &mut *sink {
+            Sink::Stderr => writeln!(std::io::stderr(), "{}", line),
+            Sink::Fd(file) => writeln!(file, "{}", line),
+        };
+    }
+}
+
+/// 人間向けの 1 行。`--progress-json` の間は出力しない。
+pub fn human(line: &str) {
+    if !is_json() {
+        eprintln!("{}", line);
+    }
+}
+
+/// `f` を 1 つのフェーズとして実行し、開始と終了のイベントを出す。
+pub fn phase<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    emit(&ProgressEvent::PhaseStart { name: name.to_string(), at: now() });
+    let timer = Instant::now();
+    let result = f();
+    emit(&ProgressEvent::PhaseEnd {
+        name: name.to_string(),
+        at: now(),
+        duration_us: timer.elapsed().as_micros() as u64,
+    });
+    result
+}
+
+/// 子プロセスの stderr。イベントを stderr に書いている間は、JSON の行と混ざらないよう stdout に流す。
+pub fn child_stderr() -> Stdio {
+    let events_on_stderr = SINK
+        .get()
+        .and_then(|s| s.lock().ok().map(|s| matches!(*s, Sink::Stderr)))
+        .unwrap_or(false);
+    if events_on_stderr && let Ok(stdout) = std::io::stdout().as_fd().try_clone_to_owned() {
+        return Stdio::from(stdout);
+    }
+    Stdio::inherit()
+}
+
+/// 子プロセスを実行し、起動と終了のイベントを出す。`tick` を渡した場合は終了まで定期的に呼ぶ。
+/// 実際に起動するかは `executor` が決める (`--dry-run` やテストでは起動しない)。
+pub fn run_child(command: &mut Command, line: &str, tick: Option<&mut dyn FnMut()>) -> io::Result<ExitStatus> {
+    let _timer = crate::overhead::scope(crate::overhead::CHILD);
+    crate::executor::run(command, line, tick)
+}
+
+/// `run_child` の実体 (`executor::RealExecutor`)。
+pub fn spawn_and_wait(command: &mut Command, line: &str, tick: Option<&mut dyn FnMut()>) -> io::Result<ExitStatus> {
+    let mut child = command.stderr(child_stderr()).spawn()?;
+    let pid = child.id();
+    emit(&ProgressEvent::ChildSpawned { pid, command: line.to_string() });
+    let status = match tick {
+        None => child.wait()?,
+        Some(tick) => loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            tick();
+            std::thread::sleep(TICK_INTERVAL);
+        },
+    };
+    emit(&ProgressEvent::ChildExited { pid, code: status.code() });
+    Ok(status)
+}
+
+fn now() -> String {
+    chrono::Local::now().to_rfc3339()
+}
+
+/// `f` の実行中にこのスレッドで出たイベントを、JSON の行として返す。
+#[cfg(test)]
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    CAPTURED.with(|c| *c.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let lines = CAPTURED.with(|c| c.borrow_mut().take()).unwrap_or_default();
+    (result, lines)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_format() {
+        let event = ProgressEvent::ChildExited { pid: 42, code: None };
+        assert_eq!(serde_json::to_string(&event).unwrap(), r#"{"event":"child_exited","pid":42,"code":null}"#);
+        let parsed: ProgressEvent =
+            serde_json::from_str(r#"{"event":"download","bytes":10,"total":20}"#).unwrap();
+        assert_eq!(parsed, ProgressEvent::Download { bytes: 10, total: Some(20) });
+    }
+
+    #[test]
+    fn test_capture_phase() {
+        let (value, lines) = capture(|| phase("link", || 7));
+        assert_eq!(value, 7);
+        let events: Vec<ProgressEvent> = lines.iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert!(matches!(&events[0], ProgressEvent::PhaseStart { name, .. } if name == "link"));
+        assert!(matches!(&events[1], ProgressEvent::PhaseEnd { name, .. } if name == "link"));
+        // キャプチャの外では人間向けのモードに戻る
+        assert!(!is_json());
+    }
+}
@@ -0,0 +1,291 @@
+//! `.flux/lock` — プロジェクトを変更する操作 (sync / add / remove / undo / bootstrap / clean / prune-gems / import / doctor)
+//! の排他制御。
+//!
+//! 操作の間 `flock(LOCK_EX)` を保持し、ファイルには保持しているプロセス (pid・コマンド・開始時刻) を書く。
+//! 2 つ目の arc は既定で `--lock-timeout` (60 秒) まで保持者を表示しながら待ち、時間切れなら保持者を示して失敗する。
+//! `--no-wait` ならすぐに失敗する。読み取りだけのコマンドはロックを取らない。
+//!
+//! flock はプロセスの終了で解放されるため、ファイルに残った保持者の pid が既に終了していれば古いロックとして扱い、
+//! 取り除いた保持者は `x-lock-broken` として記録する:
+//!
+//! - flock が取れた → 前の arc が後始末せずに終了した。メモを出して上書きする
+//! - flock が取れない → 終了した arc の fd を子プロセスが引き継いでいる。ファイルを作り直してロックを奪う
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::signals::{FluxProject, SignalType, pid_alive};
+
+/// ロックファイル名 (`.flux/lock`)
+pub const LOCK_FILE: &str = "lock";
+/// `--lock-timeout` の既定値 (秒)
+pub const DEFAULT_TIMEOUT_SECS: u64 = 60;
+/// 待っている間にロックを取り直す間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// ロックを保持しているプロセス (ロックファイルの中身)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Holder {
+    pub pid: u32,
+    /// 実行したコマンドライン (例: `arc sync --force`)
+    pub command: String,
+    /// RFC 3339
+    pub started_at: String,
+}
+
+impl Holder {
+    fn current(command: &str) -> Self {
+        Self { pid: std::process::id(), command: command.to_string(), started_at: chrono::Local::now().to_rfc3339() }
+    }
+
+    fn is_alive(&self) -> bool {
+        pid_alive(self.pid as libc::pid_t)
+    }
+
+    fn describe(&self) -> String {
+        format!("pid {} (`{}`, started {})", self.pid, self.command, self.started_at)
+    }
+}
+
+/// 保持中のロック。drop で保持者を消して解放する
+#[derive(Debug)]
+pub struct ProjectLock {
+    file: File,
+    /// 取得時に取り除いた古いロックの保持者 (取得時に表示済み)
+    pub broke_stale: Option<Holder>,
+}
+
+impl ProjectLock {
+    /// 古いロックを取り除いていれば、その保持者を `x-lock-broken` として記録する。
+    pub fn record_broken(&self, project: &FluxProject) -> Result<()> {
+        let Some(holder) = &self.broke_stale else { return Ok(()) };
+        project.record(SignalType::custom("lock", "broken")?, holder)?;
+        Ok(())
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        // ファイルを閉じると flock も解放される
+        let _ = self.file.set_len(0);
+    }
+}
+
+pub fn path(flux_dir: &Path) -> PathBuf {
+    flux_dir.join(LOCK_FILE)
+}
+
+/// `.flux/lock` を取得する。他の arc が保持していれば `timeout` まで待つ (`Duration::ZERO` なら待たない)。
+pub fn acquire(flux_dir: &Path, command: &str, timeout: Duration) -> Result<ProjectLock> {
+    let path = path(flux_dir);
+    let deadline = Instant::now() + timeout;
+    let mut announced = false;
+    let mut broke_stale = None;
+    loop {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("ロックファイルを開けません: {:?}", path))?;
+        if try_lock(&file)? {
+            // 待っている間に古いロックとして作り直されたファイルなら取り直す
+            if !same_file(&file, &path) {
+                continue;
+            }
+            if let Some(holder) = read_holder(&mut file).filter(|h| !h.is_alive()) {
+                eprintln!("🔓 Removed a stale project lock left by {}", holder.describe());
+                broke_stale = Some(holder);
+            }
+            write_holder(&mut file, &Holder::current(command))?;
+            return Ok(ProjectLock { file, broke_stale });
+        }
+
+        let holder = read_holder(&mut file);
+        match &holder {
+            Some(h) if !h.is_alive() => {
+                // 終了した arc の fd を引き継いだ子プロセスが flock を保持している
+                eprintln!("🔓 Breaking a stale project lock held open after {} exited", h.describe());
+                broke_stale = holder.clone();
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            _ => {}
+        }
+        let who = holder.as_ref().map_or("another arc process".to_string(), Holder::describe);
+        if Instant::now() >= deadline {
+            match timeout.is_zero() {
+                true => bail!("プロジェクトは他の arc が使用中です: {}\n  (--no-wait のため待たずに終了しました)", who),
+                false => bail!(
+                    "プロジェクトは他の arc が使用中です: {}\n  {} 秒待ちましたが解放されませんでした (--lock-timeout で延長できます)",
+                    who,
+                    timeout.as_secs()
+                ),
+            }
+        }
+        if !announced {
+            eprintln!("⏳ Waiting for the project lock held by {} (up to {}s)...", who, timeout.as_secs());
+            announced = true;
+        }
+        std::thread::sleep(POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+    }
+}
+
+/// このプロセスが `.flux/lock` を保持しているか (ロックファイルに書いた保持者で判断する)。
+pub fn held_by_current_process(flux_dir: &Path) -> bool {
+    let Ok(mut file) = File::open(path(flux_dir)) else { return false };
+    read_holder(&mut file).is_some_and(|h| h.pid == std::process::id())
+}
+
+fn try_lock(file: &File) -> Result<bool> {
+    // SAFETY: 有効な fd に対する flock の呼び出し
+    let rc = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if rc == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(err).context("プロジェクトのロックに失敗しました"),
+    }
+}
+
+/// 開いている `file` がまだ `path` にあるファイルか
+fn same_file(file: &File, path: &Path) -> bool {
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+fn read_holder(file: &mut File) -> Option<Holder> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut content).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn write_holder(file: &mut File, holder: &Holder) -> Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    serde_json::to_writer(&mut *file, holder)?;
+    file.flush()?;
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn flux_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name).join(".flux");
+        let _ = fs::remove_dir_all(dir.parent().unwrap());
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 既に終了したプロセスの pid
+    fn dead_pid() -> u32 {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    #[test]
+    fn test_second_holder_waits_then_acquires() {
+        let dir = flux_dir("arc_project_lock_wait_test");
+        let first = acquire(&dir, "arc sync", Duration::ZERO).unwrap();
+        let holder: Holder = serde_json::from_str(&fs::read_to_string(path(&dir)).unwrap()).unwrap();
+        assert_eq!((holder.pid, holder.command.as_str()), (std::process::id(), "arc sync"));
+
+        let (tx, rx) = mpsc::channel();
+        let waiter = {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                let started = Instant::now();
+                tx.send(()).unwrap();
+                let lock = acquire(&dir, "arc add rack", Duration::from_secs(10)).unwrap();
+                (started.elapsed(), lock.broke_stale.clone())
+            })
+        };
+        rx.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        drop(first);
+        let (waited, broke) = waiter.join().unwrap();
+        assert!(waited >= Duration::from_millis(250), "{:?}", waited);
+        assert_eq!(broke, None);
+        // 解放されたロックファイルは空
+        assert_eq!(fs::read_to_string(path(&dir)).unwrap(), "");
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_no_wait_and_timeout_report_the_holder() {
+        let dir = flux_dir("arc_project_lock_busy_test");
+        let _held = acquire(&dir, "arc sync --force", Duration::ZERO).unwrap();
+        let other = {
+            let dir = dir.clone();
+            std::thread::spawn(move || acquire(&dir, "arc remove rack", Duration::ZERO).unwrap_err().to_string())
+        };
+        let err = other.join().unwrap();
+        assert!(err.contains(&format!("pid {}", std::process::id())) && err.contains("`arc sync --force`"), "{}", err);
+        assert!(err.contains("--no-wait"), "{}", err);
+
+        let started = Instant::now();
+        let err = acquire(&dir, "arc undo", Duration::from_millis(300)).unwrap_err().to_string();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(err.contains("--lock-timeout"), "{}", err);
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_stale_locks_are_broken() {
+        let dir = flux_dir("arc_project_lock_stale_test");
+        let stale = Holder { pid: dead_pid(), command: "arc sync".into(), started_at: "2024-01-01T00:00:00+09:00".into() };
+
+        // 後始末せずに終了した arc のロック (flock は解放済み)
+        fs::write(path(&dir), serde_json::to_string(&stale).unwrap()).unwrap();
+        let lock = acquire(&dir, "arc add rack", Duration::ZERO).unwrap();
+        assert_eq!(lock.broke_stale, Some(stale.clone()));
+        drop(lock);
+
+        // 終了した arc の fd を子プロセスが引き継いで flock を保持している
+        fs::write(path(&dir), serde_json::to_string(&stale).unwrap()).unwrap();
+        let inherited = File::open(path(&dir)).unwrap();
+        assert!(try_lock(&inherited).unwrap());
+        let lock = acquire(&dir, "arc add rack", Duration::ZERO).unwrap();
+        assert_eq!(lock.broke_stale, Some(stale));
+        assert!(same_file(&lock.file, &path(&dir)));
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_broken_holder_is_recorded() {
+        let (root, project) = crate::signals::temp_project("arc_project_lock_record_test");
+        let stale = Holder { pid: dead_pid(), command: "arc sync".into(), started_at: "2024-01-01T00:00:00+09:00".into() };
+        let broken = |project: &FluxProject| project.read_signals().unwrap().into_iter().filter(|s| s.r_type == "x-lock-broken").collect::<Vec<_>>();
+
+        acquire(&project.flux_dir, "arc add rack", Duration::ZERO).unwrap().record_broken(&project).unwrap();
+        assert!(broken(&project).is_empty());
+
+        fs::write(path(&project.flux_dir), serde_json::to_string(&stale).unwrap()).unwrap();
+        acquire(&project.flux_dir, "arc add rack", Duration::ZERO).unwrap().record_broken(&project).unwrap();
+        let recorded = broken(&project);
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(serde_json::from_value::<Holder>(recorded[0].payload.clone()).unwrap(), stale);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
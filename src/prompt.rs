@@ -0,0 +1,319 @@
+/// 対話的な入力 (番号選択・確認) のユーティリティ。
+///
+/// すべての関数は入力と出力を引数で受け取るため、テストではカーソルで入力を与えられる。
+/// プロンプトは stdout を汚さないよう、呼び出し側で stderr を渡すこと。
+use std::io::{BufRead, Write};
+use std::path::Path;
+use anyhow::Result;
+
+use crate::gemfile::GemEntry;
+use crate::registry::Registry;
+
+// ─────────────────────────────────────────────
+// 確認
+// ─────────────────────────────────────────────
+
+/// `[y/N]` 形式の確認を行う。`y` / `yes` 以外 (EOF を含む) はすべて拒否として扱う。
+pub fn confirm<R: BufRead, W: Write>(input: &mut R, out: &mut W, question: &str) -> Result<bool> {
+    write!(out, "{} [y/N] ", question)?;
+    out.flush()?;
+
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// 変更系コマンドの対象プロジェクトを確認する。
+///
+/// `root` が最後に使ったプロジェクトと異なる場合、または `always` の場合のみ
+/// プロジェクト名とパスを目立つように表示して確認を求める。
+/// 確認が不要な場合は何も出力せずに `true` を返す。
+pub fn confirm_target<R: BufRead, W: Write>(
+    registry: &Registry,
+    root: &Path,
+    name: Option<&str>,
+    always: bool,
+    input: &mut R,
+    out: &mut W,
+) -> Result<bool> {
+    if !always && !registry.is_other_project(root) {
+        return Ok(true);
+    }
+
+    writeln!(out)?;
+    writeln!(out, "  ⚠️  Target project: {}", name.unwrap_or("(unnamed)"))?;
+    writeln!(out, "      {}", root.display())?;
+    if let Some(last) = registry.last_active.as_deref().filter(|l| *l != root) {
+        writeln!(out, "      (last active: {})", last.display())?;
+    }
+    writeln!(out)?;
+    confirm(input, out, "Continue?")
+}
+
+// ─────────────────────────────────────────────
+// Gem の選択
+// ─────────────────────────────────────────────
+
+/// Gem の一覧を番号付きで表示し、番号または名前の部分一致で 1 つ以上選ばせる。
+///
+/// 入力は空白またはカンマ区切り (例: `1 3`, `rub,2`)。
+/// 不正な番号や曖昧な部分一致があれば再入力を求める。
+/// 空行または EOF の場合は空の選択 (キャンセル) を返す。
+pub fn pick_gems<R: BufRead, W: Write>(
+    entries: &[GemEntry],
+    input: &mut R,
+    out: &mut W,
+) -> Result<Vec<String>> {
+    for (i, entry) in entries.iter().enumerate() {
+        let version = entry.version.as_deref().unwrap_or("");
+        let group = entry.group.as_deref().map(|g| format!("[{}]", g)).unwrap_or_default();
+        writeln!(out, "  {:>3}) {:<24} {:<16} {}", i + 1, entry.name, version, group)?;
+    }
+
+    loop {
+        write!(out, "Select gems to remove (numbers or names, empty to cancel): ")?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            return Ok(vec![]);
+        }
+
+        match resolve_selection(entries, &line) {
+            Ok(selected) => return Ok(selected),
+            Err(msg) => writeln!(out, "  ⚠️  {}", msg)?,
+        }
+    }
+}
+
+/// 入力行を Gem 名のリストに解決する。重複は取り除き、入力順を保つ。
+fn resolve_selection(entries: &[GemEntry], line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut selected: Vec<String> = Vec::new();
+
+    for token in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| !t.is_empty()) {
+        let name = match token.parse::<usize>() {
+            Ok(n) => entries
+                .get(n.wrapping_sub(1))
+                .map(|e| e.name.clone())
+                .ok_or_else(|| format!("{} は範囲外の番号です (1-{})", n, entries.len()))?,
+            Err(_) => resolve_name(entries, token)?,
+        };
+        if !selected.contains(&name) {
+            selected.push(name);
+        }
+    }
+
+    Ok(selected)
+}
+
+/// 名前の部分一致で Gem を 1 つに絞り込む。完全一致があればそれを優先する。
+fn resolve_name(entries: &[GemEntry], token: &str) -> std::result::Result<String, String> {
+    let needle = token.to_lowercase();
+    if let Some(exact) = entries.iter().find(|e| e.name.to_lowercase() == needle) {
+        return Ok(exact.name.clone());
+    }
+
+    let matches: Vec<&GemEntry> = entries
+        .iter()
+        .filter(|e| e.name.to_lowercase().contains(&needle))
+        .collect();
+
+    match matches.as_slice() {
+        [one] => Ok(one.name.clone()),
+        [] => Err(format!("'{}' に一致する Gem がありません", token)),
+        many => Err(format!(
+            "'{}' は複数の Gem に一致します: {}",
+            token,
+            many.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+// ─────────────────────────────────────────────
+// 質問 (ウィザード用)
+// ─────────────────────────────────────────────
+
+/// ウィザード形式の質問。テストでは決まった回答を返す実装に差し替える。
+/// 入力が途切れた (EOF) 場合はエラーを返し、ウィザード全体を中断させる。
+pub trait Prompter {
+    /// 自由入力。空行なら `default` を返す
+    fn text(&mut self, question: &str, default: &str) -> Result<String>;
+    /// 番号で 1 つ選ばせ、そのインデックスを返す。空行なら `default`
+    fn choose(&mut self, question: &str, options: &[String], default: usize) -> Result<usize>;
+    /// `[Y/n]` / `[y/N]` 形式の確認。空行なら `default`
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool>;
+}
+
+/// 端末 (または任意の BufRead / Write) を使う `Prompter`。
+pub struct IoPrompter<R: BufRead, W: Write> {
+    pub input: R,
+    pub out: W,
+}
+
+impl<R: BufRead, W: Write> IoPrompter<R, W> {
+    fn read_answer(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            anyhow::bail!("入力が中断されました。何も作成していません。");
+        }
+        Ok(line.trim().to_string())
+    }
+}
+
+impl<R: BufRead, W: Write> Prompter for IoPrompter<R, W> {
+    fn text(&mut self, question: &str, default: &str) -> Result<String> {
+        write!(self.out, "{} [{}]: ", question, default)?;
+        self.out.flush()?;
+        let answer = self.read_answer()?;
+        Ok(if answer.is_empty() { default.to_string() } else { answer })
+    }
+
+    fn choose(&mut self, question: &str, options: &[String], default: usize) -> Result<usize> {
+        writeln!(self.out, "{}", question)?;
+        for (i, option) in options.iter().enumerate() {
+            writeln!(self.out, "  {:>3}) {}", i + 1, option)?;
+        }
+        loop {
+            write!(self.out, "Select [{}]: ", default + 1)?;
+            self.out.flush()?;
+            let answer = self.read_answer()?;
+            if answer.is_empty() {
+                return Ok(default);
+            }
+            match answer.parse::<usize>() {
+                Ok(n) if (1..=options.len()).contains(&n) => return Ok(n - 1),
+                _ => writeln!(self.out, "  ⚠️  1-{} の番号を入力してください", options.len())?,
+            }
+        }
+    }
+
+    fn confirm(&mut self, question: &str, default: bool) -> Result<bool> {
+        write!(self.out, "{} {} ", question, if default { "[Y/n]" } else { "[y/N]" })?;
+        self.out.flush()?;
+        let answer = self.read_answer()?.to_lowercase();
+        Ok(match answer.as_str() {
+            "" => default,
+            "y" | "yes" => true,
+            _ => false,
+        })
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn entries() -> Vec<GemEntry> {
+        crate::gemfile::parse_content("gem 'rails', '~> 7.0'\ngem 'rubocop'\ngem 'rubocop-rails'\ngem 'json'\n")
+    }
+
+    fn pick(input: &str) -> (Vec<String>, String) {
+        let mut out = Vec::new();
+        let selected = pick_gems(&entries(), &mut Cursor::new(input), &mut out).unwrap();
+        (selected, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_pick_by_number() {
+        let (selected, out) = pick("1 4\n");
+        assert_eq!(selected, ["rails", "json"]);
+        assert!(out.contains("  1) rails"));
+        assert!(out.contains("~> 7.0"));
+    }
+
+    #[test]
+    fn test_pick_by_substring() {
+        // "rubocop" は完全一致を優先、"js" は部分一致
+        let (selected, _) = pick("rubocop,js\n");
+        assert_eq!(selected, ["rubocop", "json"]);
+    }
+
+    #[test]
+    fn test_invalid_then_valid() {
+        let (selected, out) = pick("9\nrub\n2 2\n");
+        assert_eq!(selected, ["rubocop"]);
+        assert!(out.contains("範囲外"));
+        assert!(out.contains("複数の Gem に一致"));
+    }
+
+    #[test]
+    fn test_empty_selection_cancels() {
+        assert!(pick("\n").0.is_empty());
+        assert!(pick("").0.is_empty());
+        assert!(pick("0\n").0.is_empty());
+    }
+
+    #[test]
+    fn test_io_prompter() {
+        let mut p = IoPrompter { input: Cursor::new("\nmyapp\n5\n2\n\nn\n"), out: Vec::new() };
+        assert_eq!(p.text("Name", "dir").unwrap(), "dir");
+        assert_eq!(p.text("Name", "dir").unwrap(), "myapp");
+        let options = vec!["3.3.6".to_string(), "3.2.2".to_string()];
+        // 範囲外の番号は再入力
+        assert_eq!(p.choose("Ruby", &options, 0).unwrap(), 1);
+        assert!(p.confirm("Gemfile?", true).unwrap());
+        assert!(!p.confirm("Bootstrap?", true).unwrap());
+        // 入力が尽きたら中断
+        assert!(p.text("Name", "dir").is_err());
+
+        let out = String::from_utf8(p.out).unwrap();
+        assert!(out.contains("    2) 3.2.2"));
+        assert!(out.contains("1-2 の番号"));
+    }
+
+    fn guard(last_active: Option<&str>, root: &str, always: bool, input: &str) -> (bool, String) {
+        let registry = Registry {
+            last_active: last_active.map(std::path::PathBuf::from),
+            projects: vec![],
+        };
+        let mut out = Vec::new();
+        let ok = confirm_target(&registry, Path::new(root), Some("app"), always, &mut Cursor::new(input), &mut out)
+            .unwrap();
+        (ok, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_confirm_target_same_project() {
+        let (ok, out) = guard(Some("/src/app"), "/src/app", false, "");
+        assert!(ok);
+        assert!(out.is_empty());
+
+        // まだプロジェクトを触っていない場合も確認しない
+        let (ok, out) = guard(None, "/src/app", false, "");
+        assert!(ok);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_confirm_target_cross_project() {
+        let (ok, out) = guard(Some("/src/other"), "/src/app", false, "y\n");
+        assert!(ok);
+        assert!(out.contains("Target project: app"));
+        assert!(out.contains("/src/app"));
+        assert!(out.contains("last active: /src/other"));
+
+        let (ok, _) = guard(Some("/src/other"), "/src/app", false, "n\n");
+        assert!(!ok);
+    }
+
+    #[test]
+    fn test_confirm_target_always() {
+        let (ok, out) = guard(Some("/src/app"), "/src/app", true, "\n");
+        assert!(!ok);
+        assert!(out.contains("Target project"));
+        assert!(!out.contains("last active"));
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut out = Vec::new();
+        assert!(confirm(&mut Cursor::new("y\n"), &mut out, "Remove?").unwrap());
+        assert!(!confirm(&mut Cursor::new("\n"), &mut out, "Remove?").unwrap());
+        assert!(!confirm(&mut Cursor::new(""), &mut out, "Remove?").unwrap());
+    }
+}
@@ -0,0 +1,212 @@
+//! Gemfile.lock から外れた Gem の削除 (`arc remove --purge` / `arc prune-gems`)。
+//!
+//! bundler は GEM_HOME から古い Gem を消さないため、`.arc/env/ruby/<api>/gems/` には
+//! 削除・更新した Gem が残り続ける。Gemfile.lock で解決された集合に含まれない
+//! Gem のディレクトリ (と gemspec・ネイティブ拡張・.gem キャッシュ) を削除する。
+
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::link;
+use crate::lockfile::Lockfile;
+
+/// 常に残す Gem (bundler 自身)
+const PROTECTED: [&str; 1] = ["bundler"];
+
+/// 削除対象の Gem。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orphan {
+    /// `gems/` 直下のディレクトリ名 (例: `rake-13.0.6`)
+    pub dir_name: String,
+    /// gemspec・拡張などを含めたディスク使用量
+    pub bytes: u64,
+}
+
+/// Gemfile.lock で解決された Gem のディレクトリ名 (`<name>-<version>`) の集合。
+/// プラットフォーム付きのバージョン (`1.16.0-x86_64-linux`) もそのままディレクトリ名になる。
+pub fn required_set(lock: &Lockfile) -> BTreeSet<String> {
+    lock.specs.iter().map(|s| format!("{}-{}", s.name, s.version)).collect()
+}
+
+/// Ruby 本体に同梱された default gem の名前 (`<runtime>/lib/ruby/gems/<api>/specifications/default`)。
+pub fn default_gem_names(ruby_runtime: &Path, api_version: &str) -> BTreeSet<String> {
+    let dir = ruby_runtime
+        .join("lib/ruby/gems")
+        .join(api_version)
+        .join("specifications/default");
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| {
+                    let file = e.file_name().to_string_lossy().to_string();
+                    let stem = file.strip_suffix(".gemspec")?;
+                    Some(gem_name(stem).to_string())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// `rake-13.0.6` → `rake`、`nokogiri-1.16.0-x86_64-linux` → `nokogiri`。
+/// 名前にも `-` が含まれうるため、数字で始まる最初の要素の手前までを名前とみなす。
+fn gem_name(dir_name: &str) -> &str {
+    let mut end = dir_name.len();
+    for (i, _) in dir_name.match_indices('-') {
+        if dir_name[i + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            end = i;
+            break;
+        }
+    }
+    &dir_name[..end]
+}
+
+/// `gem_base` (`.arc/env/ruby/<api>`) の Gem のうち、`required` にも `protected` にも含まれないもの。
+pub fn find_orphans(gem_base: &Path, required: &BTreeSet<String>, protected: &BTreeSet<String>) -> Vec<Orphan> {
+    let Ok(entries) = fs::read_dir(gem_base.join("gems")) else { return vec![] };
+    let mut orphans: Vec<Orphan> = entries
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|dir| !required.contains(dir))
+        .filter(|dir| {
+            let name = gem_name(dir);
+            !PROTECTED.contains(&name) && !protected.contains(name)
+        })
+        .map(|dir_name| {
+            let bytes = gem_paths(gem_base, &dir_name).iter().map(|p| link::tree_size(p)).sum();
+            Orphan { dir_name, bytes }
+        })
+        .collect();
+    orphans.sort_by(|a, b| a.dir_name.cmp(&b.dir_name));
+    orphans
+}
+
+/// Gem を削除し、削除したバイト数を返す。
+pub fn purge(gem_base: &Path, orphans: &[Orphan]) -> Result<u64> {
+    let mut reclaimed = 0;
+    for orphan in orphans {
+        for path in gem_paths(gem_base, &orphan.dir_name) {
+            if fs::symlink_metadata(&path).is_ok() {
+                link::remove_tree(&path)?;
+            }
+        }
+        reclaimed += orphan.bytes;
+    }
+    Ok(reclaimed)
+}
+
+/// 1 つの Gem に属するパス (存在しないものも含む)。
+fn gem_paths(gem_base: &Path, dir_name: &str) -> Vec<std::path::PathBuf> {
+    let mut paths = vec![
+        gem_base.join("gems").join(dir_name),
+        gem_base.join("specifications").join(format!("{}.gemspec", dir_name)),
+        gem_base.join("cache").join(format!("{}.gem", dir_name)),
+    ];
+    // extensions/<platform>/<abi>/<gem>
+    if let Ok(platforms) = fs::read_dir(gem_base.join("extensions")) {
+        for platform in platforms.flatten() {
+            if let Ok(abis) = fs::read_dir(platform.path()) {
+                paths.extend(abis.flatten().map(|abi| abi.path().join(dir_name)));
+            }
+        }
+    }
+    paths
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lockfile;
+
+    const LOCK: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    nokogiri (1.16.0-x86_64-linux)
+      racc (~> 1.4)
+    racc (1.7.3)
+    rake (13.1.0)
+
+DEPENDENCIES
+  nokogiri
+  rake
+";
+
+    /// `.arc/env/ruby/3.3.0` 相当のフィクスチャ
+    fn fixture(name: &str) -> std::path::PathBuf {
+        let base = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&base);
+        for dir in [
+            "nokogiri-1.16.0-x86_64-linux", "racc-1.7.3", "rake-13.1.0",
+            // 以下は Gemfile.lock から外れたもの
+            "rake-13.0.6", "rails-html-sanitizer-1.6.0", "bundler-2.5.6", "json-2.7.1",
+        ] {
+            fs::create_dir_all(base.join("gems").join(dir).join("lib")).unwrap();
+            fs::write(base.join("gems").join(dir).join("lib/main.rb"), "x".repeat(100)).unwrap();
+            fs::create_dir_all(base.join("specifications")).unwrap();
+            fs::write(base.join("specifications").join(format!("{}.gemspec", dir)), "spec").unwrap();
+        }
+        let ext = base.join("extensions/x86_64-linux/3.3.0/rails-html-sanitizer-1.6.0");
+        fs::create_dir_all(&ext).unwrap();
+        fs::write(ext.join("gem.build_complete"), "").unwrap();
+        base
+    }
+
+    #[test]
+    fn test_gem_name() {
+        assert_eq!(gem_name("rake-13.0.6"), "rake");
+        assert_eq!(gem_name("rails-html-sanitizer-1.6.0"), "rails-html-sanitizer");
+        assert_eq!(gem_name("nokogiri-1.16.0-x86_64-linux"), "nokogiri");
+        assert_eq!(gem_name("weird"), "weird");
+    }
+
+    #[test]
+    fn test_prune_removes_exactly_orphans() {
+        let base = fixture("arc_prune_test");
+        let required = required_set(&lockfile::parse_content(LOCK));
+        // json は Ruby 本体の default gem として扱う
+        let protected: BTreeSet<String> = ["json".to_string()].into();
+
+        let orphans = find_orphans(&base, &required, &protected);
+        let names: Vec<&str> = orphans.iter().map(|o| o.dir_name.as_str()).collect();
+        assert_eq!(names, ["rails-html-sanitizer-1.6.0", "rake-13.0.6"]);
+        // gem 本体 100 バイト + gemspec 4 バイト
+        assert_eq!(orphans[1].bytes, 104);
+
+        assert_eq!(purge(&base, &orphans).unwrap(), orphans.iter().map(|o| o.bytes).sum::<u64>());
+        let mut left: Vec<String> = fs::read_dir(base.join("gems"))
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(
+            left,
+            ["bundler-2.5.6", "json-2.7.1", "nokogiri-1.16.0-x86_64-linux", "racc-1.7.3", "rake-13.1.0"]
+        );
+        assert!(!base.join("specifications/rake-13.0.6.gemspec").exists());
+        assert!(base.join("specifications/rake-13.1.0.gemspec").exists());
+        assert!(!base.join("extensions/x86_64-linux/3.3.0/rails-html-sanitizer-1.6.0").exists());
+        assert!(find_orphans(&base, &required, &protected).is_empty());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_default_gem_names() {
+        let runtime = std::env::temp_dir().join("arc_prune_runtime_test");
+        let _ = fs::remove_dir_all(&runtime);
+        let dir = runtime.join("lib/ruby/gems/3.3.0/specifications/default");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("json-2.7.1.gemspec"), "").unwrap();
+        fs::write(dir.join("net-http-0.4.1.gemspec"), "").unwrap();
+        let names: Vec<String> = default_gem_names(&runtime, "3.3.0").into_iter().collect();
+        assert_eq!(names, ["json", "net-http"]);
+        fs::remove_dir_all(&runtime).unwrap();
+    }
+}
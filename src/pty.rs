@@ -0,0 +1,359 @@
+//! 擬似端末 (pty) で子プロセスを動かし、入出力を中継する (`arc shell --transcript`)。
+//!
+//! 子プロセスは pty の slave を制御端末にした新しいセッションで起動する。親は入力 (実端末なら
+//! raw モードにした stdin) をそのまま master へ流し、master の出力を実端末と記録先 (`Sink`) の
+//! 両方に書く。実端末のサイズが変わったら (SIGWINCH) master に同じサイズを設定し、記録先にも知らせる。
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// 記録先を書き出す間隔 (arc が途中で落ちても、ここまでの記録は残る)
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// master を待つ間隔 (端末サイズの変更と子プロセスの終了をこの間隔で確かめる)
+const POLL_INTERVAL_MS: libc::c_int = 100;
+const BUF_SIZE: usize = 8192;
+
+/// 端末のサイズ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WinSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl WinSize {
+    /// 端末でないときに使うサイズ
+    pub const DEFAULT: WinSize = WinSize { rows: 24, cols: 80 };
+
+    /// `fd` が端末ならそのサイズ。
+    pub fn of(fd: RawFd) -> Option<Self> {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ は winsize を書き込むだけ
+        let rc = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) };
+        (rc == 0 && size.ws_row > 0 && size.ws_col > 0).then_some(Self { rows: size.ws_row, cols: size.ws_col })
+    }
+
+    fn to_libc(self) -> libc::winsize {
+        libc::winsize { ws_row: self.rows, ws_col: self.cols, ws_xpixel: 0, ws_ypixel: 0 }
+    }
+}
+
+/// master の出力の記録先。
+pub trait Sink {
+    fn output(&mut self, data: &[u8]) -> Result<()>;
+    fn resize(&mut self, size: WinSize) -> Result<()>;
+    /// `FLUSH_INTERVAL` ごとと、セッションの終わりに呼ぶ
+    fn flush(&mut self) -> Result<()>;
+}
+
+// ─────────────────────────────────────────────
+// pty
+// ─────────────────────────────────────────────
+
+/// 開いた pty の組。
+pub struct Pty {
+    master: File,
+    slave: OwnedFd,
+}
+
+impl Pty {
+    pub fn open(size: WinSize) -> Result<Self> {
+        let (mut master, mut slave) = (-1, -1);
+        let ws = size.to_libc();
+        // SAFETY: openpty は成功時に 2 つの fd を返す。名前と termios は使わない
+        let rc = unsafe { libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), &ws) };
+        if rc != 0 {
+            return Err(std::io::Error::last_os_error()).context("pty を開けません");
+        }
+        // SAFETY: openpty が返した fd の所有権をここで引き取る
+        let (master, slave) = unsafe { (File::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+        // 子プロセスに余分な fd を渡さない (stdio に複製した slave は exec 後も残る)
+        set_cloexec(master.as_raw_fd())?;
+        set_cloexec(slave.as_raw_fd())?;
+        Ok(Self { master, slave })
+    }
+
+    /// `command` を slave を制御端末にした新しいセッションで実行し、終わるまで中継する。
+    /// `input` (実端末なら stdin) は別スレッドで master に流す。`size_source` を指定すると、
+    /// SIGWINCH を受けるたびにその端末のサイズを master に反映する。
+    pub fn run(
+        self,
+        mut command: Command,
+        input: Option<Box<dyn Read + Send>>,
+        out: &mut dyn Write,
+        sink: &mut dyn Sink,
+        size_source: Option<RawFd>,
+    ) -> Result<ExitStatus> {
+        command
+            .stdin(Stdio::from(self.slave.try_clone()?))
+            .stdout(Stdio::from(self.slave.try_clone()?))
+            .stderr(Stdio::from(self.slave.try_clone()?));
+        // SAFETY: pre_exec 内では async-signal-safe な setsid(2) と ioctl(2) のみを呼ぶ
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = command.spawn()?;
+        // slave を持っているのが子プロセスだけになると、子プロセスの終了で master の読み出しが終わる
+        drop(command);
+        drop(self.slave);
+
+        if let Some(mut input) = input {
+            let mut master = self.master.try_clone()?;
+            std::thread::spawn(move || {
+                let mut buf = [0u8; BUF_SIZE];
+                while let Ok(n) = input.read(&mut buf) {
+                    if n == 0 || master.write_all(&buf[..n]).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let _winch = size_source.map(|_| WinchHandler::install());
+        let mut master = self.master;
+        let mut buf = [0u8; BUF_SIZE];
+        let mut last_flush = Instant::now();
+        let mut exited = None;
+        loop {
+            if let Some(fd) = size_source
+                && WINCH.swap(false, Ordering::SeqCst)
+                && let Some(size) = WinSize::of(fd)
+            {
+                resize_fd(master.as_raw_fd(), size)?;
+                sink.resize(size)?;
+            }
+            // 子プロセスが終わったら、残っている出力を読み切って抜ける (子孫が slave を持っていても止まらない)
+            let timeout = if exited.is_some() { 0 } else { POLL_INTERVAL_MS };
+            if readable(master.as_raw_fd(), timeout)? {
+                match master.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        out.write_all(&buf[..n])?;
+                        out.flush()?;
+                        sink.output(&buf[..n])?;
+                    }
+                    // Linux では slave がすべて閉じると EIO になる
+                    Err(e) if e.raw_os_error() == Some(libc::EIO) => break,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e).context("pty の読み出しに失敗しました"),
+                }
+            } else if exited.is_some() {
+                break;
+            } else {
+                exited = child.try_wait()?;
+            }
+            if last_flush.elapsed() >= FLUSH_INTERVAL {
+                sink.flush()?;
+                last_flush = Instant::now();
+            }
+        }
+        sink.flush()?;
+        match exited {
+            Some(status) => Ok(status),
+            None => Ok(child.wait()?),
+        }
+    }
+}
+
+fn set_cloexec(fd: RawFd) -> Result<()> {
+    // SAFETY: fd フラグを設定するだけ
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// master にサイズを設定する (前面のプロセスグループに SIGWINCH が届く)。
+fn resize_fd(fd: RawFd, size: WinSize) -> Result<()> {
+    let ws = size.to_libc();
+    // SAFETY: TIOCSWINSZ は winsize を読むだけ
+    if unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &ws) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("pty のサイズを設定できません");
+    }
+    Ok(())
+}
+
+/// `fd` が `timeout_ms` 以内に読めるようになったか (EOF・エラーも読める扱い)。
+fn readable(fd: RawFd, timeout_ms: libc::c_int) -> Result<bool> {
+    let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+    // SAFETY: pollfd 1 つを渡すだけ
+    let rc = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+    if rc == -1 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == std::io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(err.into());
+    }
+    Ok(rc > 0)
+}
+
+// ─────────────────────────────────────────────
+// 端末の設定
+// ─────────────────────────────────────────────
+
+/// SIGWINCH を受けたか。`WinchHandler` がある間だけハンドラーを設定する
+static WINCH: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_winch(_: libc::c_int) {
+    WINCH.store(true, Ordering::SeqCst);
+}
+
+struct WinchHandler {
+    previous: libc::sighandler_t,
+}
+
+impl WinchHandler {
+    fn install() -> Self {
+        WINCH.store(false, Ordering::SeqCst);
+        let handler = on_winch as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        Self { previous: unsafe { libc::signal(libc::SIGWINCH, handler) } }
+    }
+}
+
+impl Drop for WinchHandler {
+    fn drop(&mut self) {
+        unsafe { libc::signal(libc::SIGWINCH, self.previous) };
+    }
+}
+
+/// 端末を raw モードにし、drop で元の設定に戻す。
+pub struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// `fd` が端末なら raw モードにする。端末でなければ `None`
+    pub fn enable(fd: RawFd) -> Option<Self> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: tcgetattr / tcsetattr は termios を読み書きするだけ
+        if unsafe { libc::isatty(fd) } != 1 || unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return None;
+        }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        (unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } == 0).then_some(Self { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 受け取った出力とサイズの変更を覚えておく記録先
+    #[derive(Default)]
+    struct Collect {
+        data: Vec<u8>,
+        sizes: Vec<WinSize>,
+        flushes: usize,
+    }
+
+    impl Sink for Collect {
+        fn output(&mut self, data: &[u8]) -> Result<()> {
+            self.data.extend_from_slice(data);
+            Ok(())
+        }
+        fn resize(&mut self, size: WinSize) -> Result<()> {
+            self.sizes.push(size);
+            Ok(())
+        }
+        fn flush(&mut self) -> Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn sh(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    #[test]
+    fn test_child_runs_on_a_terminal_and_output_is_mirrored() {
+        let pty = Pty::open(WinSize { rows: 30, cols: 100 }).unwrap();
+        let (mut out, mut sink) = (Vec::new(), Collect::default());
+        let status = pty
+            .run(sh("test -t 0 && test -t 1 && echo tty; stty size; exit 3"), None, &mut out, &mut sink, None)
+            .unwrap();
+
+        assert_eq!(status.code(), Some(3));
+        let text = String::from_utf8_lossy(&out);
+        assert!(text.contains("tty"), "{:?}", text);
+        assert!(text.contains("30 100"), "{:?}", text);
+        assert_eq!(sink.data, out);
+        assert!(sink.flushes >= 1);
+    }
+
+    #[test]
+    fn test_scripted_input_reaches_child() {
+        let pty = Pty::open(WinSize::DEFAULT).unwrap();
+        let input: Box<dyn Read + Send> = Box::new(std::io::Cursor::new(b"scripted\n".to_vec()));
+        let (mut out, mut sink) = (Vec::new(), Collect::default());
+        let status = pty.run(sh("read line; echo \"got:$line\""), Some(input), &mut out, &mut sink, None).unwrap();
+
+        assert!(status.success());
+        assert!(String::from_utf8_lossy(&sink.data).contains("got:scripted"));
+    }
+
+    #[test]
+    fn test_background_descendant_does_not_block_exit() {
+        // 子孫が slave を開いたままでも、子プロセスが終われば戻る
+        let pty = Pty::open(WinSize::DEFAULT).unwrap();
+        let (mut out, mut sink) = (Vec::new(), Collect::default());
+        let started = Instant::now();
+        let status = pty.run(sh("sleep 5 & echo done"), None, &mut out, &mut sink, None).unwrap();
+
+        assert!(status.success());
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert!(String::from_utf8_lossy(&out).contains("done"));
+    }
+
+    #[test]
+    fn test_resize_is_visible_on_slave() {
+        let pty = Pty::open(WinSize::DEFAULT).unwrap();
+        resize_fd(pty.master.as_raw_fd(), WinSize { rows: 50, cols: 132 }).unwrap();
+        assert_eq!(WinSize::of(pty.slave.as_raw_fd()), Some(WinSize { rows: 50, cols: 132 }));
+        assert_eq!(WinSize::of(pty.master.as_raw_fd()), Some(WinSize { rows: 50, cols: 132 }));
+    }
+
+    #[test]
+    fn test_winch_is_forwarded_to_child_and_sink() {
+        // size_source に別の pty を使い、そのサイズを変えて SIGWINCH を送る
+        let source = Pty::open(WinSize { rows: 40, cols: 120 }).unwrap();
+        let pty = Pty::open(WinSize::DEFAULT).unwrap();
+        let (mut out, mut sink) = (Vec::new(), Collect::default());
+        let source_fd = source.master.as_raw_fd();
+        let signaller = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(300));
+            unsafe { libc::kill(libc::getpid(), libc::SIGWINCH) };
+        });
+        let status = pty.run(sh("sleep 1; stty size"), None, &mut out, &mut sink, Some(source_fd)).unwrap();
+        signaller.join().unwrap();
+
+        assert!(status.success());
+        assert_eq!(sink.sizes, [WinSize { rows: 40, cols: 120 }]);
+        assert!(String::from_utf8_lossy(&out).contains("40 120"));
+    }
+}
@@ -0,0 +1,102 @@
+//! `arc --read-only`: 読み取り専用でマウントされたチェックアウト (デプロイ先など) 向けのモード。
+//!
+//! `.flux/` か Signal ログに書き込めなければ自動で有効になる。このモードでは:
+//!
+//! - プロジェクトを変更するコマンド (sync / add / remove など) はすぐに失敗する
+//! - exec / run は子プロセスを実行するが、Signal は記録しない (最初の 1 回だけ警告する)
+//! - state / env などの表示だけのコマンドはそのまま動く
+//!
+//! 書き込めるかは `access(2)` で調べる (試しに書き込むことはしない)。
+
+use anyhow::{Result, bail};
+use std::cell::Cell;
+use std::path::Path;
+
+use crate::perms;
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    /// Signal を記録しなかったことを警告済みか
+    static WARNED: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn enable() {
+    ENABLED.with(|e| e.set(true));
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.with(|e| e.get())
+}
+
+/// `flux_dir` (`.flux/`) か Signal ログに書き込めないか
+pub fn detect(flux_dir: &Path) -> bool {
+    flux_dir.is_dir() && (!perms::writable(flux_dir) || !perms::writable(&flux_dir.join("signals.jsonl")))
+}
+
+/// 読み取り専用モードなら、プロジェクトを変更する `arc <command>` を拒否する。
+pub fn refuse(command: &str) -> Result<()> {
+    if is_enabled() {
+        bail!(
+            "読み取り専用モードのため `arc {}` は実行できません (プロジェクトを変更するコマンドです)\n  .flux/ に書き込めないか、--read-only が指定されています",
+            command
+        );
+    }
+    Ok(())
+}
+
+/// Signal を記録しなかったことを、最初の 1 回だけ警告する。
+pub fn warn_skipped_record() {
+    if !WARNED.with(|w| w.replace(true)) {
+        eprintln!("⚠️  Read-only mode: this run is not recorded in .flux/signals.jsonl");
+    }
+}
+
+/// テスト用: `f` を読み取り専用モードで実行する。
+#[cfg(test)]
+pub fn scoped<T>(f: impl FnOnce() -> T) -> T {
+    enable();
+    let result = f();
+    ENABLED.with(|e| e.set(false));
+    WARNED.with(|w| w.set(false));
+    result
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_detect_unwritable_flux_dir() {
+        let root = std::env::temp_dir().join("arc_read_only_detect_test");
+        let _ = fs::remove_dir_all(&root);
+        let flux = root.join(".flux");
+        fs::create_dir_all(&flux).unwrap();
+        fs::write(flux.join("signals.jsonl"), "").unwrap();
+        assert!(!detect(&flux));
+        assert!(!detect(&root.join("missing")));
+
+        fs::set_permissions(flux.join("signals.jsonl"), fs::Permissions::from_mode(0o444)).unwrap();
+        fs::set_permissions(&flux, fs::Permissions::from_mode(0o555)).unwrap();
+        // root は access(2) でも書き込めると判定される
+        // SAFETY: geteuid は副作用のない問い合わせ
+        if unsafe { libc::geteuid() } != 0 {
+            assert!(detect(&flux));
+        }
+        fs::set_permissions(&flux, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_refuse_only_in_read_only_mode() {
+        assert!(refuse("add").is_ok());
+        let err = scoped(|| refuse("add")).unwrap_err().to_string();
+        assert!(err.contains("読み取り専用モードのため `arc add` は実行できません"), "{}", err);
+        assert!(refuse("add").is_ok());
+    }
+}
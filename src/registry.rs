@@ -0,0 +1,185 @@
+//! `~/.arc/projects.toml` — ユーザーが触った arc プロジェクトのレジストリ。
+//!
+//! ```toml
+//! last_active = "/home/me/src/my_app"
+//!
+//! [[projects]]
+//! path = "/home/me/src/my_app"
+//! name = "my_app"
+//! last_used = "2026-02-18T10:00:00+09:00"
+//! ```
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::config::ArcConfig;
+use crate::signals::{self, FluxProject};
+
+const REGISTRY_FILE: &str = "projects.toml";
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    /// 最後にコマンドが成功したプロジェクトのルート
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_active: Option<PathBuf>,
+    #[serde(default)]
+    pub projects: Vec<ProjectEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectEntry {
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub last_used: String,
+}
+
+// ─────────────────────────────────────────────
+// 読み書き
+// ─────────────────────────────────────────────
+
+/// レジストリファイルのパス (~/.arc/projects.toml)
+pub fn registry_path() -> Result<PathBuf> {
+    Ok(signals::get_global_arc_dir()?.join(REGISTRY_FILE))
+}
+
+impl Registry {
+    /// レジストリを読み込む。存在しない場合は空のレジストリを返す。
+    /// 壊れている (パースできない) 場合も空として扱い、次の保存で書き直す。
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("projects.toml の読み込みに失敗しました: {:?}", path))?;
+        match toml::from_str(&content) {
+            Ok(registry) => Ok(registry),
+            Err(e) => {
+                crate::progress::human(&format!("⚠️  Ignoring unreadable {} ({}); it will be rewritten", path.display(), e.message()));
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// 一時ファイルに書いてから rename する。`arc ws` の子プロセスなどが同時に保存しても、
+    /// 読む側が途中まで書かれたファイルを見ることはない (後から保存した内容が残る)。
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = toml::to_string_pretty(self)
+            .context("projects.toml のシリアライズに失敗しました")?;
+        let tmp = path.with_file_name(format!(".{}.tmp-{}", REGISTRY_FILE, std::process::id()));
+        std::fs::write(&tmp, content)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .inspect_err(|_| {
+                let _ = std::fs::remove_file(&tmp);
+            })
+            .with_context(|| format!("projects.toml の書き込みに失敗しました: {:?}", path))
+    }
+
+    /// `root` を最後に使ったプロジェクトとして記録する。
+    pub fn touch(&mut self, root: &Path, name: Option<String>) {
+        let now = Local::now().to_rfc3339();
+        match self.projects.iter_mut().find(|p| p.path == root) {
+            Some(entry) => {
+                entry.last_used = now;
+                if name.is_some() {
+                    entry.name = name;
+                }
+            }
+            None => self.projects.push(ProjectEntry {
+                path: root.to_path_buf(),
+                name,
+                last_used: now,
+            }),
+        }
+        self.last_active = Some(root.to_path_buf());
+    }
+
+    /// `root` が最後に使ったプロジェクトと異なるか。
+    /// まだどのプロジェクトも使っていない場合は `false`。
+    pub fn is_other_project(&self, root: &Path) -> bool {
+        self.last_active.as_deref().is_some_and(|last| last != root)
+    }
+}
+
+/// カレントディレクトリが Flux プロジェクトであれば、レジストリの last_active を更新する。
+/// コマンド成功後に毎回呼ばれる（ベストエフォート）。
+pub fn touch_current() -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let Ok(project) = FluxProject::open(&cwd) else {
+        return Ok(());
+    };
+    let root = cwd.canonicalize().unwrap_or(cwd);
+    let name = ArcConfig::load(&project.flux_dir).ok().and_then(|c| c.project.name);
+
+    let path = registry_path()?;
+    let mut registry = Registry::load_from(&path)?;
+    registry.touch(&root, name);
+    registry.save_to(&path)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touch_and_other_project() {
+        let mut registry = Registry::default();
+        assert!(!registry.is_other_project(Path::new("/src/a")));
+
+        registry.touch(Path::new("/src/a"), Some("a".to_string()));
+        assert!(!registry.is_other_project(Path::new("/src/a")));
+        assert!(registry.is_other_project(Path::new("/src/b")));
+
+        registry.touch(Path::new("/src/b"), None);
+        registry.touch(Path::new("/src/a"), None);
+        assert_eq!(registry.projects.len(), 2);
+        assert_eq!(registry.projects[0].name.as_deref(), Some("a"));
+        assert_eq!(registry.last_active.as_deref(), Some(Path::new("/src/a")));
+    }
+
+    #[test]
+    fn test_registry_save_load() {
+        let path = std::env::temp_dir().join("arc_registry_test").join(REGISTRY_FILE);
+        let mut registry = Registry::default();
+        registry.touch(Path::new("/src/a"), Some("a".to_string()));
+        registry.save_to(&path).unwrap();
+
+        let loaded = Registry::load_from(&path).unwrap();
+        assert_eq!(loaded.last_active.as_deref(), Some(Path::new("/src/a")));
+        assert_eq!(loaded.projects[0].name.as_deref(), Some("a"));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_registry_is_replaced() {
+        let dir = std::env::temp_dir().join("arc_registry_corrupt_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(REGISTRY_FILE);
+        // 同時に書かれて途中で切れたファイル
+        std::fs::write(&path, "last_active = \"/src/a\"\n[[projects]]\npath = \"/src").unwrap();
+
+        let mut registry = Registry::load_from(&path).unwrap();
+        assert!(registry.projects.is_empty());
+        registry.touch(Path::new("/src/b"), None);
+        registry.save_to(&path).unwrap();
+
+        assert_eq!(Registry::load_from(&path).unwrap().last_active.as_deref(), Some(Path::new("/src/b")));
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().flatten().map(|e| e.file_name()).collect();
+        assert_eq!(files, [REGISTRY_FILE]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+//! Ruby バージョンの大小比較 (`arc bootstrap` のダウングレード検出)。
+//!
+//! 数値部分 (`3.4.0`) を短い方を 0 で埋めて比べ、同じならプレリリース (`3.4.0-rc1`, `3.4.0.preview2`) を
+//! リリースより前とする。プレリリース同士は英字と数字の並びを順に比べる (`preview1` < `preview2` < `rc1`)。
+
+use std::cmp::Ordering;
+
+/// プレリリースの構成要素。数字は英字より前 (semver と同じ)
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Token<'a> {
+    Num(u64),
+    Word(&'a str),
+}
+
+/// 数値部分とプレリリース部分に分ける (`3.4.0-rc1` / `3.4.0.rc1` → (`[3, 4, 0]`, `rc1`))。
+fn split(version: &str) -> (Vec<u64>, &str) {
+    let version = version.trim().trim_start_matches("ruby-");
+    // `-` の後、または最初の数字でない区切りからがプレリリース
+    let end = version.find('-').unwrap_or(version.len());
+    let mut numbers = Vec::new();
+    let mut offset = 0;
+    for segment in version[..end].split('.') {
+        match segment.parse() {
+            Ok(n) => numbers.push(n),
+            Err(_) => break,
+        }
+        offset += segment.len() + 1;
+    }
+    (numbers, version.get(offset.min(version.len())..).unwrap_or(""))
+}
+
+/// プレリリースを英字と数字の並びに分ける (`rc.10` / `rc10` → `[rc, 10]`)。
+fn tokens(pre: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    for part in pre.split(['.', '-']) {
+        let mut rest = part;
+        while let Some(c) = rest.chars().next() {
+            let digit = c.is_ascii_digit();
+            let (run, tail) = rest.split_at(rest.find(|ch: char| ch.is_ascii_digit() != digit).unwrap_or(rest.len()));
+            tokens.push(run.parse().map_or(Token::Word(run), Token::Num));
+            rest = tail;
+        }
+    }
+    tokens
+}
+
+/// 2 つの Ruby バージョンを比べる。
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let ((a_core, a_pre), (b_core, b_pre)) = (split(a), split(b));
+    let len = a_core.len().max(b_core.len());
+    let pad = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    let core = (0..len).map(|i| pad(&a_core, i).cmp(&pad(&b_core, i))).find(|o| o.is_ne()).unwrap_or(Ordering::Equal);
+    core.then_with(|| match (a_pre.is_empty(), b_pre.is_empty()) {
+        (true, true) => Ordering::Equal,
+        // プレリリースはリリースより前
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => tokens(a_pre).cmp(&tokens(b_pre)),
+    })
+}
+
+/// `from` から `to` への変更がダウングレードか
+pub fn is_downgrade(from: &str, to: &str) -> bool {
+    compare(to, from).is_lt()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_release_versions() {
+        assert_eq!(compare("3.3.6", "3.4.0"), Ordering::Less);
+        assert_eq!(compare("3.10.0", "3.9.9"), Ordering::Greater);
+        assert_eq!(compare("3.4", "3.4.0"), Ordering::Equal);
+        assert_eq!(compare("ruby-3.4.1", "3.4.1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_compare_prereleases() {
+        let ordered = ["3.3.6", "3.4.0-preview1", "3.4.0.preview2", "3.4.0-rc1", "3.4.0-rc2", "3.4.0", "3.4.1-rc1", "3.4.1"];
+        for pair in ordered.windows(2) {
+            assert_eq!(compare(pair[0], pair[1]), Ordering::Less, "{} < {}", pair[0], pair[1]);
+            assert_eq!(compare(pair[1], pair[0]), Ordering::Greater, "{} > {}", pair[1], pair[0]);
+        }
+        assert_eq!(compare("3.4.0-rc10", "3.4.0-rc9"), Ordering::Greater);
+        assert_eq!(compare("3.4.0.rc1", "3.4.0-rc1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_is_downgrade() {
+        assert!(is_downgrade("3.4.0", "3.3.6"));
+        assert!(is_downgrade("3.4.0", "3.4.0-rc1"));
+        assert!(!is_downgrade("3.4.0-rc1", "3.4.0"));
+        assert!(!is_downgrade("3.4.0", "3.4.0"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Signal ログに記録されたパスを読む前の検査 (パストラバーサル対策)。
+//!
+//! Signal の payload には blob の参照や出力ファイルのパスなどが文字列で残る。壊れた・細工されたログの
+//! `../../etc/passwd` や絶対パスでプロジェクトの外を読まないよう、ログの内容をもとにファイルを読む前に
+//! `safe_join` でパスを解決し、`.flux` か `.arc` の中に収まっているか確かめる。
+//!
+//! 解決は 1 要素ずつ行い、シンボリックリンクはその場でリンク先に置き換える (存在しない・切れたリンクも含む)。
+//! そのため `.flux` の中にあってもプロジェクトの外を指すシンボリックリンクは拒否される。
+
+use anyhow::{Result, bail};
+use serde_json::Value;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::blobs;
+use crate::signals::{ARC_ENV_DIR, FLUX_DIR, FluxProject, Signal};
+
+/// シンボリックリンクをたどる回数の上限 (ループ対策)
+const MAX_LINKS: usize = 40;
+
+/// `project_root` を基準に `recorded` (相対・絶対) を解決し、`.flux` か `.arc` の中にあればそのパスを返す。
+pub fn safe_join(project_root: &Path, recorded: impl AsRef<Path>) -> Result<PathBuf> {
+    let arc_dir = Path::new(ARC_ENV_DIR).parent().unwrap_or(Path::new(ARC_ENV_DIR));
+    let allowed = [FLUX_DIR.as_ref(), arc_dir];
+    let recorded = recorded.as_ref();
+    let resolved = resolve(&project_root.join(recorded))?;
+    for dir in allowed {
+        if resolved.starts_with(resolve(&project_root.join(dir))?) {
+            return Ok(resolved);
+        }
+    }
+    bail!("refusing to read path outside project: {} (resolves to {})", recorded.display(), resolved.display())
+}
+
+/// `base` を基準に `recorded` を解決し、`base` の中にあればそのパスを返す (展開したバンドルなど、プロジェクト以外用)。
+pub fn join_within(base: &Path, recorded: impl AsRef<Path>) -> Result<PathBuf> {
+    let recorded = recorded.as_ref();
+    let resolved = resolve(&base.join(recorded))?;
+    if !resolved.starts_with(resolve(base)?) {
+        bail!("refusing to read path outside {}: {} (resolves to {})", base.display(), recorded.display(), resolved.display());
+    }
+    Ok(resolved)
+}
+
+/// `..` とシンボリックリンクを解決した絶対パス。存在しない要素はそのまま付け足す。
+fn resolve(path: &Path) -> Result<PathBuf> {
+    let path = if path.is_absolute() { path.to_path_buf() } else { std::env::current_dir()?.join(path) };
+    // 未処理の要素 (末尾から取り出す)。リンク先の要素は残りの要素より先に処理する
+    let split = |path: &Path| path.components().rev().map(|c| c.as_os_str().to_os_string()).collect::<Vec<_>>();
+    let mut pending = split(&path);
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+    while let Some(part) = pending.pop() {
+        match Path::new(&part).components().next() {
+            Some(Component::Prefix(_) | Component::RootDir) => resolved = PathBuf::from(&part),
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            Some(Component::Normal(name)) => {
+                resolved.push(name);
+                let Ok(target) = fs::read_link(&resolved) else { continue };
+                links += 1;
+                if links > MAX_LINKS {
+                    bail!("too many levels of symbolic links: {}", path.display());
+                }
+                resolved.pop();
+                pending.extend(split(&target));
+            }
+            Some(Component::CurDir) | None => {}
+        }
+    }
+    Ok(resolved)
+}
+
+/// Signal ログのパス (blob の参照・デタッチ実行の出力) と `.flux` 内のシンボリックリンクのうち、
+/// プロジェクトの外を指すもの (`arc doctor`)。各要素は表示用の 1 行。
+pub fn audit(project: &FluxProject, signals: &[Signal]) -> Vec<String> {
+    let mut issues = Vec::new();
+    for signal in signals {
+        let mut refs = std::collections::BTreeSet::new();
+        blobs::collect_refs(&signal.payload, &mut refs);
+        let blob_paths = refs.into_iter().map(|hash| project.blobs_dir().join(hash));
+        let outputs = ["stdout", "stderr"].into_iter().filter_map(|key| signal.payload.get(key).and_then(Value::as_str).map(PathBuf::from));
+        for path in blob_paths.chain(outputs) {
+            if let Err(e) = safe_join(&project.root, &path) {
+                issues.push(format!("signal {} ({}): {}", signal.id, signal.r_type, e));
+            }
+        }
+    }
+    let mut dirs = vec![project.flux_dir.clone()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_symlink()
+                && let Err(e) = safe_join(&project.root, entry.path())
+            {
+                issues.push(format!("symlink {}: {}", entry.path().display(), e));
+            }
+        }
+    }
+    issues
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::os::unix::fs::symlink;
+
+    fn project(name: &str) -> (PathBuf, FluxProject) {
+        let (root, project) = crate::signals::temp_project(name);
+        fs::create_dir_all(root.join(".arc/env")).unwrap();
+        (fs::canonicalize(&root).unwrap(), project)
+    }
+
+    #[test]
+    fn test_relative_traversal() {
+        let (root, _project) = project("arc_safe_path_relative_test");
+        fs::create_dir_all(root.join(".flux/blobs")).unwrap();
+        fs::write(root.join(".flux/blobs/abc"), "{}").unwrap();
+        assert_eq!(safe_join(&root, ".flux/blobs/abc").unwrap(), root.join(".flux/blobs/abc"));
+        // 存在しないファイルも、中に収まっていれば解決できる
+        assert_eq!(safe_join(&root, ".flux/output/./x.out").unwrap(), root.join(".flux/output/x.out"));
+        assert_eq!(safe_join(&root, ".arc/env/bin/../lib").unwrap(), root.join(".arc/env/lib"));
+
+        for recorded in [".flux/blobs/../../../../etc/passwd", ".flux/../Gemfile", "Gemfile", ".flux/blobs/../../.fluxx/a"] {
+            let err = safe_join(&root, recorded).unwrap_err().to_string();
+            assert!(err.starts_with("refusing to read path outside project"), "{}: {}", recorded, err);
+        }
+        let err = join_within(&root.join(".flux"), "blobs/../../secret").unwrap_err().to_string();
+        assert!(err.contains("refusing to read path outside"), "{}", err);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_absolute_paths() {
+        let (root, project) = project("arc_safe_path_absolute_test");
+        assert!(safe_join(&root, "/etc/passwd").is_err());
+        assert!(safe_join(&root, std::env::temp_dir()).is_err());
+        let inside = project.flux_dir.join("output/01.out");
+        assert!(safe_join(&root, &inside).is_ok());
+        assert!(join_within(&root, "/etc/passwd").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_escaping_symlink() {
+        let (root, project) = project("arc_safe_path_symlink_test");
+        let outside = root.with_file_name("arc_safe_path_symlink_outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret"), "s3cret").unwrap();
+        fs::create_dir_all(project.blobs_dir()).unwrap();
+        symlink(outside.join("secret"), project.blobs_dir().join("deadbeef")).unwrap();
+        symlink("../../../arc_safe_path_symlink_outside", project.flux_dir.join("escape")).unwrap();
+        // .flux の中を指すリンクは構わない
+        symlink("blobs", project.flux_dir.join("alias")).unwrap();
+
+        assert!(safe_join(&root, ".flux/blobs/deadbeef").is_err());
+        assert!(safe_join(&root, ".flux/escape/secret").is_err());
+        assert!(safe_join(&root, ".flux/alias/deadbeef").is_err());
+        assert_eq!(safe_join(&root, ".flux/alias/other").unwrap(), root.join(".flux/blobs/other"));
+        let looped = project.flux_dir.join("loop");
+        symlink(&looped, &looped).unwrap();
+        assert!(safe_join(&root, &looped).unwrap_err().to_string().contains("too many levels"));
+
+        // blob の読み込みも、リンク先がプロジェクトの外なら拒否する
+        let err = project.resolve_blob(&json!({ "$blob": "deadbeef", "bytes": 6 })).unwrap_err();
+        assert!(format!("{:#}", err).contains("refusing to read path outside project"), "{:#}", err);
+
+        let signal = project.record(crate::signals::SignalType::RunStart, json!({
+            "command": "puma", "args": [], "stdout": "/etc/passwd", "log": { "$blob": "../../x", "bytes": 1 },
+        })).unwrap();
+        let issues = audit(&project, &project.read_signals().unwrap());
+        assert!(issues.iter().any(|i| i.contains(&signal.id) && i.contains("/etc/passwd")), "{:?}", issues);
+        assert!(issues.iter().any(|i| i.contains(&signal.id) && i.contains("../../x")), "{:?}", issues);
+        assert!(issues.iter().any(|i| i.starts_with("symlink") && i.contains("escape")), "{:?}", issues);
+        assert!(issues.iter().any(|i| i.starts_with("symlink") && i.contains("deadbeef")), "{:?}", issues);
+        assert!(!issues.iter().any(|i| i.contains("alias")), "{:?}", issues);
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+}
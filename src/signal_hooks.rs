@@ -0,0 +1,294 @@
+//! `[signal_hooks]` — Signal を記録したときに実行するユーザー定義のコマンド。
+//!
+//! ```toml
+//! [signal_hooks]
+//! "install_end" = "scripts/on-install.sh"
+//! "*_end" = "touch /run/arc/changed"
+//! ```
+//!
+//! キーは Signal 種別のパターン (`*` / `?` の glob)、値は `sh -c` で実行するコマンド。
+//! `FluxProject::record` がログに 1 行書いた後、一致するフックをプロジェクトルートで起動する:
+//!
+//! - stdin に Signal の JSON (1 行)、環境変数 `ARC_SIGNAL_TYPE` / `ARC_SIGNAL_ID` を渡す
+//! - 一致したフックは `task_pool::TaskPool` で同時に `[parallel] jobs` 個まで実行する
+//! - 終了を待たずに操作を続け、arc の終了時に最大 `EXIT_WAIT` だけ待つ。実行中のフックは放置し、
+//!   順番待ちのまま始まらなかったフックは始めずに警告を表示する
+//! - 起動の失敗や 0 以外の終了は `--verbose` のときだけ表示し、操作の結果には影響しない。
+//!   `--verbose` ではフックの出力も `[コマンド] ` を付けて stderr に表示する
+//!
+//! フックの中で実行した arc が記録した Signal ではフックを実行しない (環境変数 `ARC_SIGNAL_HOOK` で判断する)。
+//! フックが `ARC_SIGNAL_HOOK` を外して意図的に入れ子にした場合も、深さは `MAX_DEPTH` までに制限する。
+//! `--no-signal-hooks` か `ARC_NO_SIGNAL_HOOKS=1` ですべてのフックを止められる。
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::display;
+use crate::progress;
+use crate::signals::Signal;
+use crate::state::glob_match;
+use crate::task_pool::{Closer, Task, TaskOutcome, TaskPool, TaskResult};
+
+/// フックを止める環境変数 (`1` で無効)
+pub const DISABLE_ENV: &str = "ARC_NO_SIGNAL_HOOKS";
+/// フックのプロセスに渡す目印 (フックを起動した Signal の ID)
+pub const MARKER_ENV: &str = "ARC_SIGNAL_HOOK";
+/// フックの入れ子の深さ
+pub const DEPTH_ENV: &str = "ARC_SIGNAL_HOOK_DEPTH";
+/// 入れ子にできるフックの深さの上限
+pub const MAX_DEPTH: u32 = 3;
+/// arc の終了時にフックの終了を待つ時間
+pub const EXIT_WAIT: Duration = Duration::from_secs(2);
+
+thread_local! {
+    static DISABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 1 つの Signal で起動したフック
+struct Fired {
+    /// フックのコマンド (`TaskPool` に渡した順)
+    commands: Vec<String>,
+    /// まだ始まっていないフックを始めないようにする
+    closer: Closer,
+    /// `TaskPool` を実行しているスレッド
+    handle: JoinHandle<Vec<TaskResult>>,
+}
+
+/// 起動して終了を待っていないフック
+static PENDING: Mutex<Vec<Fired>> = Mutex::new(Vec::new());
+
+/// `--no-signal-hooks`
+pub fn disable() {
+    DISABLED.with(|d| d.set(true));
+}
+
+fn enabled() -> bool {
+    if DISABLED.with(|d| d.get()) || std::env::var(DISABLE_ENV).is_ok_and(|v| v == "1") {
+        return false;
+    }
+    allowed(std::env::var_os(MARKER_ENV).is_some(), depth())
+}
+
+/// フックから実行された arc (目印がある) の Signal ではフックを実行しない。深さは `MAX_DEPTH` 未満まで
+fn allowed(inside_hook: bool, depth: u32) -> bool {
+    !inside_hook && depth < MAX_DEPTH
+}
+
+fn depth() -> u32 {
+    std::env::var(DEPTH_ENV).ok().and_then(|d| d.parse().ok()).unwrap_or(0)
+}
+
+/// `signal` の種別に一致するフックを、同時に `jobs` 個まで起動する。終了は待たない。
+pub fn fire(root: &Path, hooks: &BTreeMap<String, String>, jobs: usize, signal: &Signal) {
+    if hooks.is_empty() || !enabled() {
+        return;
+    }
+    let tasks: Vec<Task> = hooks
+        .iter()
+        .filter(|(pattern, _)| glob_match(pattern.as_bytes(), signal.r_type.as_bytes()))
+        .map(|(_, command)| task(root, command, signal))
+        .collect();
+    if tasks.is_empty() {
+        return;
+    }
+    let commands = tasks.iter().map(|t| t.label.clone()).collect();
+    let verbose = display::is_verbose();
+    let pool = TaskPool::new(jobs);
+    let closer = pool.closer();
+    let handle = std::thread::spawn(move || {
+        let (pool, out): (TaskPool, Box<dyn Write + Send>) = if verbose {
+            (pool, Box::new(std::io::stderr()))
+        } else {
+            (pool.discard_output(), Box::new(std::io::sink()))
+        };
+        pool.run(&tasks, &Mutex::new(out))
+    });
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).push(Fired { commands, closer, handle });
+}
+
+fn task(root: &Path, command: &str, signal: &Signal) -> Task {
+    let mut task = Task::new(command, "sh", vec!["-c".to_string(), command.to_string()]);
+    task.cwd = Some(root.to_path_buf());
+    task.env = BTreeMap::from([
+        ("ARC_SIGNAL_TYPE".to_string(), signal.r_type.clone()),
+        ("ARC_SIGNAL_ID".to_string(), signal.id.clone()),
+        (MARKER_ENV.to_string(), signal.id.clone()),
+        (DEPTH_ENV.to_string(), (depth() + 1).to_string()),
+    ]);
+    task.stdin = Some(format!("{}\n", serde_json::to_string(signal).unwrap_or_default()).into_bytes());
+    task
+}
+
+/// 起動したフックの終了を最大 `timeout` 待ち、失敗したものを `--verbose` で表示する。
+/// 時間内に終わらなかったフックは止めずに残す。`[parallel] jobs` を超えて順番待ちのまま
+/// 始まらなかったフックはもう始めず、警告を表示してコマンドを返す。
+pub fn wait_pending(timeout: Duration) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    let mut pending = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    loop {
+        let (finished, running): (Vec<_>, Vec<_>) = pending.into_iter().partition(|fired| fired.handle.is_finished());
+        pending = running;
+        for result in finished.into_iter().filter_map(|fired| fired.handle.join().ok()).flatten() {
+            match result.outcome {
+                TaskOutcome::Failed(Some(code)) => {
+                    display::verbose(&format!("⚠️  signal hook `{}` exited with {}", result.label, code))
+                }
+                TaskOutcome::Failed(None) => display::verbose(&format!("⚠️  signal hook `{}` failed", result.label)),
+                _ => {}
+            }
+        }
+        if pending.is_empty() || Instant::now() >= deadline {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut skipped = Vec::new();
+    for fired in pending {
+        let (started, queued) = fired.commands.split_at(fired.closer.close());
+        for command in started {
+            display::verbose(&format!("⚠️  signal hook `{}` is still running; not waiting for it", command));
+        }
+        for command in queued {
+            progress::human(&format!("⚠️  signal hook `{}` was not started before arc exited", command));
+        }
+        skipped.extend_from_slice(queued);
+    }
+    skipped
+}
+
+/// テスト用: `f` をフック無効で実行する。
+#[cfg(test)]
+pub fn disabled_scope<T>(f: impl FnOnce() -> T) -> T {
+    disable();
+    let result = f();
+    DISABLED.with(|d| d.set(false));
+    result
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::{FluxProject, SignalType};
+    use serde_json::json;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// `wait_pending` はすべてのテストのフックを待つ (始まっていないフックは止める) ので、1 つずつ実行する
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn serial() -> std::sync::MutexGuard<'static, ()> {
+        SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn project(name: &str, hooks: &[(&str, &str)]) -> (PathBuf, FluxProject) {
+        let (root, mut project) = crate::signals::temp_project(name);
+        project.signal_hooks = hooks.iter().map(|(p, c)| (p.to_string(), c.to_string())).collect();
+        (root, project)
+    }
+
+    fn wait_for(path: &Path) -> String {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Ok(content) = fs::read_to_string(path)
+                && content.ends_with('\n')
+            {
+                return content;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("{:?} was not written", path);
+    }
+
+    #[test]
+    fn test_hook_receives_signal_on_stdin() {
+        let _serial = serial();
+        let (root, project) = project("arc_signal_hooks_deliver_test", &[("x-test-*", "cat > hook.json; echo \"$ARC_SIGNAL_TYPE $ARC_SIGNAL_ID\" > hook.env")]);
+        let signal = project.record(SignalType::parse("x-test-done").unwrap(), json!({ "gem": "rack" })).unwrap();
+        project.record(SignalType::parse("add").unwrap(), json!({})).unwrap();
+        wait_pending(EXIT_WAIT);
+
+        let delivered: Signal = serde_json::from_str(&wait_for(&root.join("hook.json"))).unwrap();
+        assert_eq!(delivered.id, signal.id);
+        assert_eq!(delivered.payload["gem"], "rack");
+        assert_eq!(wait_for(&root.join("hook.env")), format!("x-test-done {}\n", signal.id));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_slow_hook_does_not_block_record() {
+        let _serial = serial();
+        let (root, project) = project("arc_signal_hooks_slow_test", &[("*", "sleep 3; cat > late.json")]);
+        let started = Instant::now();
+        project.record(SignalType::parse("add").unwrap(), json!({})).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+
+        let started = Instant::now();
+        wait_pending(Duration::from_millis(100));
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        assert!(!root.join("late.json").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_queued_hooks_are_reported_at_exit() {
+        let _serial = serial();
+        let (root, mut project) = project("arc_signal_hooks_queued_test", &[("a*", "sleep 1; echo done > first"), ("ad?", "touch second")]);
+        project.parallel_jobs = 1;
+        project.record(SignalType::parse("add").unwrap(), json!({})).unwrap();
+        // 1 つ目が終わる前に待つのをやめると、順番待ちの 2 つ目は始めずに報告する
+        assert_eq!(wait_pending(Duration::from_millis(100)), ["touch second"]);
+        wait_for(&root.join("first"));
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!root.join("second").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_hooks_respect_parallel_jobs() {
+        let _serial = serial();
+        let hook = |n: u32| format!("echo start {0} >> order.log; sleep 0.2; echo end {0} >> order.log; exit {0}", n);
+        let (root, mut project) = project("arc_signal_hooks_jobs_test", &[("a*", &hook(0)), ("ad?", &hook(2))]);
+        project.parallel_jobs = 1;
+        project.record(SignalType::parse("add").unwrap(), json!({})).unwrap();
+        wait_pending(EXIT_WAIT);
+        // 1 つずつ実行するので、2 つ目は 1 つ目が終わってから始まる
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut order = String::new();
+        while order.lines().count() < 4 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+            order = fs::read_to_string(root.join("order.log")).unwrap_or_default();
+        }
+        assert_eq!(order, "start 0\nend 0\nstart 2\nend 2\n");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_recursion_guard() {
+        let _serial = serial();
+        let (root, project) = project(
+            "arc_signal_hooks_guard_test",
+            &[("*", "cat >> fired.jsonl; echo \"$ARC_SIGNAL_HOOK $ARC_SIGNAL_HOOK_DEPTH\" > marker")],
+        );
+        disabled_scope(|| project.record(SignalType::parse("add").unwrap(), json!({})).unwrap());
+        let signal = project.record(SignalType::parse("add").unwrap(), json!({})).unwrap();
+        wait_pending(EXIT_WAIT);
+        assert_eq!(wait_for(&root.join("fired.jsonl")).lines().count(), 1);
+        // フックの中の arc はこの目印を見てフックを実行しない
+        assert_eq!(wait_for(&root.join("marker")), format!("{} 1\n", signal.id));
+        assert!(allowed(false, 0));
+        assert!(!allowed(true, 1));
+        // 目印を外した入れ子でも深さで止まる
+        assert!(allowed(false, MAX_DEPTH - 1));
+        assert!(!allowed(false, MAX_DEPTH));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
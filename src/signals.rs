@@ -1,33 +1,54 @@
 use anyhow::{bail, Context, Result};
 use chrono::Local;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::blobs::{self, DEFAULT_PAYLOAD_BUDGET};
+use crate::config::ArcConfig;
+use crate::link::EnvStorage;
+use crate::perms;
+
 /// Flux Core のデータディレクトリ名
-const FLUX_DIR: &str = ".flux";
+pub const FLUX_DIR: &str = ".flux";
+/// 初期化中の一時ディレクトリの接頭辞 (`.flux.tmp-<pid>`)
+const FLUX_TMP_PREFIX: &str = ".flux.tmp-";
 /// Signal ログファイル名
-const SIGNAL_FILE: &str = "signals.jsonl";
+pub const SIGNAL_FILE: &str = "signals.jsonl";
+/// 親 Signal の ID を記録する payload のフィールド名
+pub const PARENT_ID_KEY: &str = "parent_id";
+/// これ以上時刻が巻き戻っている Signal を警告する (秒)
+pub const TIMESTAMP_REGRESSION_THRESHOLD_SECS: i64 = 300;
+/// `read_tail` で末尾から読む既定のバイト数
+pub const TAIL_READ_BYTES: u64 = 4 * 1024;
+/// payload から退避したフィールドの保存先 (`.flux/blobs/`)
+const BLOBS_DIR: &str = "blobs";
+/// `gc_blobs` が消さない、作られたばかりの blob の猶予 (`record` は blob を書いてから参照する行を追記するため)
+const BLOB_GC_GRACE: std::time::Duration = std::time::Duration::from_secs(60 * 60);
 /// プロジェクト固有の環境ディレクトリ (Gem のインストール先)
 pub const ARC_ENV_DIR: &str = ".arc/env";
-/// グローバルキャッシュルート名
-pub const ARC_CACHE_ROOT: &str = ".arc/cache";
+/// グローバルな arc ディレクトリ名 (~/.arc)
+pub const ARC_GLOBAL_ROOT: &str = ".arc";
+/// グローバルキャッシュのディレクトリ名 (~/.arc/cache)
+pub const ARC_CACHE_DIR: &str = "cache";
+
+/// グローバルな arc ディレクトリを取得する (~/.arc)。決め方は `arc_home` を参照。
+pub fn get_global_arc_dir() -> Result<PathBuf> {
+    Ok(crate::arc_home::get()?.root.path.clone())
+}
 
 /// グローバルなキャッシュディレクトリを取得する (~/.arc/cache)
-pub fn get_global_cache_dir() -> PathBuf {
-    // std::env::home_dir() は deprecated のため、HOME 環境変数を直接参照する
-    let home = std::env::var("HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from("/tmp"));
-    home.join(ARC_CACHE_ROOT)
+pub fn get_global_cache_dir() -> Result<PathBuf> {
+    Ok(crate::arc_home::get()?.cache.path.clone())
 }
 
 /// Gem のグローバルキャッシュディレクトリを取得する (~/.arc/cache/gems)
-pub fn get_global_gems_dir() -> PathBuf {
-    get_global_cache_dir().join("gems")
+pub fn get_global_gems_dir() -> Result<PathBuf> {
+    Ok(get_global_cache_dir()?.join("gems"))
 }
 
 // ─────────────────────────────────────────────
@@ -43,14 +64,148 @@ pub enum SignalType {
     ExecEnd,
     InstallStart,
     InstallEnd,
+    SyncSkipped,
     RunStart,
     RunEnd,
     Add,
     Remove,
     Bootstrap,
     Undo,
-    /// 自由形式のシグナルタイプ (arc shell 等の拡張煎に使用)
-    Custom(String),
+    Import,
+    Adopt,
+    Prune,
+    /// `arc shell` の開始
+    ShellEnter,
+    /// `arc shell` の終了
+    ShellExit,
+    /// `arc shell` 内で実行したコマンド (シェル履歴からの取り込み)
+    ShellCmd,
+    /// sync の事前確認で問題が見つかり、install を始めずに中止した
+    PreflightFailed,
+    /// `[signals] retention_days` で古い Signal を消した
+    RetentionPurge,
+    /// `arc binstub` でプロジェクトのスクリプトを binstub にした・元に戻した
+    Binstub,
+    /// `arc config set` で設定を変えた (変更前と変更後の値)
+    ConfigSet,
+    /// 外部のスクリプト・拡張が記録する種別 (`x-<component>-<name>`)。
+    /// `SignalType::custom` / `SignalType::parse` でしか作れない
+    Custom(CustomType),
+}
+
+/// 名前空間付きの独自種別の接頭辞
+pub const CUSTOM_PREFIX: &str = "x-";
+
+/// 検証済みの独自種別の名前 (`x-<component>-<name>`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomType(String);
+
+impl CustomType {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// ログに現れる種別の分類 (`arc state --summary`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeKind {
+    /// 組み込みの種別
+    Known,
+    /// 名前空間付きの独自種別 (`x-<component>-<name>`)
+    Namespaced,
+    /// 規約より前に記録された、名前空間のない独自種別。読み込みはできる
+    Legacy,
+}
+
+impl SignalType {
+    /// `Custom` 以外のすべての種別 (定義順)
+    pub const KNOWN: [SignalType; 22] = [
+        SignalType::Init,
+        SignalType::ExecStart,
+        SignalType::ExecEnd,
+        SignalType::InstallStart,
+        SignalType::InstallEnd,
+        SignalType::SyncSkipped,
+        SignalType::RunStart,
+        SignalType::RunEnd,
+        SignalType::Add,
+        SignalType::Remove,
+        SignalType::Bootstrap,
+        SignalType::Undo,
+        SignalType::Import,
+        SignalType::Adopt,
+        SignalType::Prune,
+        SignalType::ShellEnter,
+        SignalType::ShellExit,
+        SignalType::ShellCmd,
+        SignalType::PreflightFailed,
+        SignalType::RetentionPurge,
+        SignalType::Binstub,
+        SignalType::ConfigSet,
+    ];
+
+    /// 子プロセスの実行を記録する種別 (exec / run / shell)。
+    /// 読み取り専用モードでは、これらだけ記録せずにコマンドを続ける
+    pub fn is_execution(&self) -> bool {
+        matches!(
+            self,
+            SignalType::ExecStart
+                | SignalType::ExecEnd
+                | SignalType::RunStart
+                | SignalType::RunEnd
+                | SignalType::ShellEnter
+                | SignalType::ShellExit
+                | SignalType::ShellCmd
+        )
+    }
+
+    /// 名前空間付きの独自種別 `x-<component>-<name>` を作る。
+    /// `component` は英小文字・数字、`name` は英小文字・数字・`-`・`_` のみ。
+    pub fn custom(component: &str, name: &str) -> Result<Self> {
+        if component.contains('-') {
+            bail!("Signal 種別の component に '-' は使えません: {}", component);
+        }
+        Self::parse(&format!("{}{}-{}", CUSTOM_PREFIX, component, name))
+    }
+
+    /// 記録する種別の名前を検証する。組み込みの種別に完全に一致するか、
+    /// `x-<component>-<name>` の形でなければエラー (有効な形を案内する)。
+    pub fn parse(name: &str) -> Result<Self> {
+        if let Some(known) = Self::KNOWN.iter().find(|t| t.to_string() == name) {
+            return Ok(known.clone());
+        }
+        if is_namespaced(name) {
+            return Ok(SignalType::Custom(CustomType(name.to_string())));
+        }
+        let known: Vec<String> = Self::KNOWN.iter().map(|t| t.to_string()).collect();
+        bail!(
+            "Signal 種別 '{}' は記録できません\n  有効な形: 組み込みの種別 ({}) か、x-<component>-<name> (例: x-hook-start)\n  component は英小文字・数字、name は英小文字・数字・'-'・'_' のみ",
+            name,
+            known.join(", ")
+        )
+    }
+
+    /// ログに現れた種別の名前を分類する (古いログの名前空間のない種別も読み込める)。
+    pub fn kind(name: &str) -> TypeKind {
+        if Self::KNOWN.iter().any(|t| t.to_string() == name) {
+            TypeKind::Known
+        } else if is_namespaced(name) {
+            TypeKind::Namespaced
+        } else {
+            TypeKind::Legacy
+        }
+    }
+}
+
+/// `x-<component>-<name>` の形か
+fn is_namespaced(name: &str) -> bool {
+    let Some((component, rest)) = name.strip_prefix(CUSTOM_PREFIX).and_then(|r| r.split_once('-')) else {
+        return false;
+    };
+    !component.is_empty()
+        && component.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && rest.chars().next().is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && rest.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
 }
 
 impl fmt::Display for SignalType {
@@ -61,12 +216,23 @@ impl fmt::Display for SignalType {
             SignalType::ExecEnd      => "exec_end",
             SignalType::InstallStart => "install_start",
             SignalType::InstallEnd   => "install_end",
+            SignalType::SyncSkipped  => "sync_skipped",
             SignalType::RunStart     => "run_start",
             SignalType::RunEnd       => "run_end",
             SignalType::Add          => "add",
             SignalType::Remove       => "remove",
             SignalType::Bootstrap    => "bootstrap",
             SignalType::Undo         => "undo",
+            SignalType::Import       => "import",
+            SignalType::Adopt        => "adopt",
+            SignalType::Prune        => "prune",
+            SignalType::ShellEnter   => "shell_enter",
+            SignalType::ShellExit    => "shell_exit",
+            SignalType::ShellCmd     => "shell_cmd",
+            SignalType::PreflightFailed => "preflight_failed",
+            SignalType::RetentionPurge => "retention_purge",
+            SignalType::Binstub      => "binstub",
+            SignalType::ConfigSet    => "config_set",
             SignalType::Custom(name) => name.as_str(),
         };
         write!(f, "{}", s)
@@ -90,6 +256,73 @@ pub struct Signal {
     pub payload: serde_json::Value,
     /// Signal が記録された時刻 (RFC 3339)
     pub timestamp: String,
+    /// 記録したユーザー・ホスト。古いログには存在しない
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<SignalMeta>,
+    /// 形式のバージョン (`SCHEMA_VERSION`)。`v` のない古い Signal は 1 (`arc upgrade-log` で移行できる)
+    #[serde(default = "legacy_schema_version")]
+    pub v: u32,
+}
+
+/// `FluxProject::record` が書く Signal の形式のバージョン
+pub const SCHEMA_VERSION: u32 = 2;
+
+fn legacy_schema_version() -> u32 {
+    1
+}
+
+/// meta を持たない (記録者の分からない) Signal のユーザー名
+pub const UNKNOWN_USER: &str = "unknown";
+
+/// Signal を記録した環境。共有ホストで複数のメンバーが同じプロジェクトを操作するとき、
+/// 誰の操作かを区別するために使う。
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SignalMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
+
+impl SignalMeta {
+    /// 現在のプロセスのユーザー (`USER` / `LOGNAME`) とホスト名。
+    pub fn current() -> Self {
+        Self {
+            user: std::env::var("USER").or_else(|_| std::env::var("LOGNAME")).ok().filter(|u| !u.is_empty()),
+            host: hostname(),
+        }
+    }
+}
+
+impl Signal {
+    /// 記録したユーザー。記録されていなければ `UNKNOWN_USER`。
+    pub fn user(&self) -> &str {
+        self.meta.as_ref().and_then(|m| m.user.as_deref()).unwrap_or(UNKNOWN_USER)
+    }
+}
+
+/// `signals` に含まれるユーザー数 (`UNKNOWN_USER` も 1 人と数える)。
+pub fn distinct_users<'a>(signals: impl IntoIterator<Item = &'a Signal>) -> usize {
+    signals.into_iter().map(Signal::user).collect::<BTreeSet<_>>().len()
+}
+
+/// プロセス `pid` が存在するか (他のユーザーのプロセスも存在すると判定する)。
+pub fn pid_alive(pid: libc::pid_t) -> bool {
+    // SAFETY: シグナル 0 は存在確認のみで、プロセスには何も送らない
+    let rc = unsafe { libc::kill(pid, 0) };
+    rc == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// ホスト名 (`gethostname`)。
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: buf は十分な長さを持ち、gethostname は NUL 終端を書き込む
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok().filter(|s| !s.is_empty())
 }
 
 // ─────────────────────────────────────────────
@@ -106,13 +339,84 @@ pub struct FluxProject {
     pub flux_dir: PathBuf,
     /// `signals.jsonl` のパス
     pub signal_file: PathBuf,
+    /// 1 つの payload の上限 (バイト)。`[log] payload_budget`
+    pub payload_budget: usize,
+    /// `.arc/env` の保存先 (シンボリックリンクかどうか)。開いた時点で解決する
+    pub env_storage: EnvStorage,
+    /// `[signal_hooks]` (Signal 種別のパターン → コマンド)
+    pub signal_hooks: BTreeMap<String, String>,
+    /// `[parallel] jobs` (フックを同時に実行する数)
+    pub parallel_jobs: usize,
+    /// ユーザー単位のログ (`FluxProject::user_log`) か。読み取り専用モードの対象外
+    user: bool,
+}
+
+/// プロジェクトの Signal ログの状態 (`FluxProject::log_state_at`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogState {
+    /// `.flux/` に Signal が記録されている
+    Healthy,
+    /// `.flux/` はあるが `signals.jsonl` がない、または 0 バイト (書き込み中のクラッシュなど)
+    Empty,
+    /// `.flux/` がない (プロジェクトではない)
+    Absent,
+}
+
+/// `FluxProject::record_with` の指定。既定値では `record` と同じく現在時刻と新しい ID を使う。
+#[derive(Debug, Clone, Default)]
+pub struct RecordOptions {
+    /// RFC 3339 の時刻 (過去の時刻も可)
+    pub timestamp: Option<String>,
+    /// Signal ID。既にログにある ID はエラーになる
+    pub id: Option<String>,
+    /// 親 Signal の ID。payload の `parent_id` に記録する
+    pub parent_id: Option<String>,
+}
+
+/// `FluxProject::gc_blobs` の結果。
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub removed: usize,
+    pub freed_bytes: u64,
+    pub kept: usize,
+}
+
+/// `FluxProject::init` の途中経過。テストで各段階の直後に失敗を注入するために使う。
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InitStep {
+    ConfigWritten,
+    SignalRecorded,
+}
+
+/// 中断された init が残した `.flux.tmp-<pid>` のうち、作成したプロセスが既に
+/// 終了しているものを削除する。実行中の init の一時ディレクトリには触れない。
+fn remove_stale_init_dirs(project_root: &Path) {
+    let Ok(entries) = fs::read_dir(project_root) else { return };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name.to_str().and_then(|n| n.strip_prefix(FLUX_TMP_PREFIX)) else { continue };
+        if !pid.parse::<libc::pid_t>().is_ok_and(pid_alive) {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
 }
 
 impl FluxProject {
-    /// 新しい Flux プロジェクトを初期化する。
-    /// `.flux/` ディレクトリと `signals.jsonl` を作成する。
+    /// 新しい Flux プロジェクトを初期化し、最初の init Signal を返す。
+    ///
+    /// `config.toml` と init Signal をプロジェクト内の一時ディレクトリ (`.flux.tmp-<pid>`) に
+    /// 書いてから `.flux/` へ rename するため、途中で失敗しても中途半端な `.flux/` は残らない。
     /// 既に初期化済みの場合はエラーを返す。
-    pub fn init(project_root: &Path) -> Result<Self> {
+    pub fn init<T: Serialize>(project_root: &Path, config: &ArcConfig, payload: T) -> Result<(Self, Signal)> {
+        Self::init_with_hook(project_root, config, payload, &mut |_| Ok(()))
+    }
+
+    fn init_with_hook<T: Serialize>(
+        project_root: &Path,
+        config: &ArcConfig,
+        payload: T,
+        hook: &mut dyn FnMut(InitStep) -> Result<()>,
+    ) -> Result<(Self, Signal)> {
         let flux_dir = project_root.join(FLUX_DIR);
         let signal_file = flux_dir.join(SIGNAL_FILE);
 
@@ -122,22 +426,65 @@ impl FluxProject {
                 signal_file
             );
         }
+        if fs::symlink_metadata(&flux_dir).is_ok() {
+            bail!(
+                "{:?} exists but has no {} (left by an interrupted init?). Remove it and run `arc init` again.",
+                flux_dir, SIGNAL_FILE
+            );
+        }
+
+        remove_stale_init_dirs(project_root);
+        let tmp_dir = project_root.join(format!("{}{}", FLUX_TMP_PREFIX, std::process::id()));
+        let _ = fs::remove_dir_all(&tmp_dir);
+        if config.permissions.group_writable {
+            perms::enable_group_writable();
+        }
+        perms::create_dir_all(&tmp_dir)
+            .with_context(|| format!("Failed to create {:?}", tmp_dir))?;
 
-        fs::create_dir_all(&flux_dir)
-            .with_context(|| format!("Failed to create {:?}", flux_dir))?;
+        let budget = config.log.payload_budget;
+        let staged = (|| {
+            config.save(&tmp_dir).context("config.toml の初期化に失敗しました")?;
+            hook(InitStep::ConfigWritten)?;
+            let signal = Self::at(project_root, tmp_dir.clone(), budget).record(SignalType::Init, payload)?;
+            hook(InitStep::SignalRecorded)?;
+            fs::rename(&tmp_dir, &flux_dir)
+                .with_context(|| format!("Failed to rename {:?} to {:?}", tmp_dir, flux_dir))?;
+            Ok(signal)
+        })();
+
+        match staged {
+            Ok(signal) => {
+                let mut project = Self::at(project_root, flux_dir, budget);
+                project.signal_hooks = config.signal_hooks.clone();
+                project.parallel_jobs = config.parallel.jobs();
+                Ok((project, signal))
+            }
+            Err(e) => {
+                let _ = fs::remove_dir_all(&tmp_dir);
+                Err(e)
+            }
+        }
+    }
 
-        Ok(Self {
+    fn at(project_root: &Path, flux_dir: PathBuf, payload_budget: usize) -> Self {
+        Self {
             root: project_root.to_path_buf(),
+            signal_file: flux_dir.join(SIGNAL_FILE),
             flux_dir,
-            signal_file,
-        })
+            payload_budget,
+            env_storage: EnvStorage::inspect(&project_root.join(ARC_ENV_DIR), get_global_cache_dir().ok().as_deref()),
+            signal_hooks: BTreeMap::new(),
+            parallel_jobs: crate::config::default_jobs(),
+            user: false,
+        }
     }
 
     /// 既存の Flux プロジェクトを開く。
     /// カレントディレクトリから `.flux/` を探す。存在しない場合はエラーを返す。
     pub fn open(project_root: &Path) -> Result<Self> {
+        let _timer = crate::overhead::scope(crate::overhead::OPEN);
         let flux_dir = project_root.join(FLUX_DIR);
-        let signal_file = flux_dir.join(SIGNAL_FILE);
 
         if !flux_dir.exists() {
             bail!(
@@ -146,52 +493,323 @@ impl FluxProject {
             );
         }
 
-        Ok(Self {
-            root: project_root.to_path_buf(),
-            flux_dir,
-            signal_file,
-        })
+        // config.toml が壊れていても Signal の読み書きはできるよう、デフォルトにフォールバックする
+        let config = ArcConfig::load(&flux_dir).ok();
+        if config.as_ref().is_some_and(|c| c.permissions.group_writable) {
+            perms::enable_group_writable();
+        }
+        // 知らない言語なら既定の区切りのまま (config.toml の誤りで Signal の読み書きを止めない)
+        if let Some(lang) = config.as_ref().and_then(|c| c.display.lang.as_deref()) {
+            let _ = crate::display::set_lang(lang);
+        }
+        if config.as_ref().is_some_and(|c| c.display.plain) {
+            crate::display::enable_plain();
+        }
+        let payload_budget = config.as_ref().map_or(DEFAULT_PAYLOAD_BUDGET, |c| c.log.payload_budget);
+
+        let mut project = Self::at(project_root, flux_dir, payload_budget);
+        if let Some(config) = config {
+            project.parallel_jobs = config.parallel.jobs();
+            project.signal_hooks = config.signal_hooks;
+        }
+        Ok(project)
+    }
+
+    /// プロジェクトに属さない操作 (`arc tool`) を記録するユーザー単位のログ (`~/.arc/.flux`)。
+    /// なければ作る。`arc_root` はグローバルな arc ディレクトリ (`get_global_arc_dir`)。
+    pub fn user_log(arc_root: &Path) -> Result<Self> {
+        let flux_dir = arc_root.join(FLUX_DIR);
+        perms::create_dir_all(&flux_dir).with_context(|| format!("Failed to create {:?}", flux_dir))?;
+        let mut project = Self::at(arc_root, flux_dir, DEFAULT_PAYLOAD_BUDGET);
+        project.user = true;
+        project.ensure_signal_file()?;
+        Ok(project)
+    }
+
+    /// `project_root` の Signal ログの状態。開く前に判定できるよう、ファイルを調べるだけにする。
+    pub fn log_state_at(project_root: &Path) -> LogState {
+        let flux_dir = project_root.join(FLUX_DIR);
+        if !flux_dir.is_dir() {
+            return LogState::Absent;
+        }
+        match fs::metadata(flux_dir.join(SIGNAL_FILE)) {
+            Ok(meta) if meta.len() > 0 => LogState::Healthy,
+            _ => LogState::Empty,
+        }
+    }
+
+    pub fn log_state(&self) -> LogState {
+        Self::log_state_at(&self.root)
+    }
+
+    /// Signal ログがない、または空なら、記録する前に正しい権限で作っておく (変更系コマンド用)。
+    /// 0 バイトのファイルは作成時の権限が適用されていない可能性があるため、権限だけ付け直す。
+    pub fn ensure_signal_file(&self) -> Result<()> {
+        if self.log_state() != LogState::Empty {
+            return Ok(());
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.signal_file)
+            .with_context(|| format!("Failed to create {:?}", self.signal_file))?;
+        perms::apply_file_mode(&self.signal_file)?;
+        Ok(())
+    }
+
+    /// `.arc/env` がリンク先の存在しないシンボリックリンクならエラーを返す。
+    /// そのまま進めると `create_dir_all` がリンクを辿れずに分かりにくいエラーになる。
+    pub fn ensure_env_usable(&self) -> Result<()> {
+        if let (Some(target), true) = (&self.env_storage.symlink_target, self.env_storage.dangling) {
+            bail!(
+                "{} はシンボリックリンクですが、リンク先 {:?} が存在しません。ディスクがマウントされているか確認してください。",
+                ARC_ENV_DIR, target
+            );
+        }
+        Ok(())
     }
 
     /// Signal を記録し、記録された Signal を返す。
     /// `SignalType` を受け取ることで型安全性を保証する。
     pub fn record<T: Serialize>(&self, signal_type: SignalType, payload: T) -> Result<Signal> {
+        self.record_with(signal_type, payload, RecordOptions::default())
+    }
+
+    /// 事前に採番した ID (`new_signal_id`) で Signal を記録する。
+    /// 出力ファイル名など、記録より前に ID が必要な場合に使用する。採番したばかりの ID は重複しないため、
+    /// ログとの照合 (ログ全体の読み込み) はしない。
+    pub fn record_with_id<T: Serialize>(
+        &self,
+        id: String,
+        signal_type: SignalType,
+        payload: T,
+    ) -> Result<Signal> {
+        self.append(signal_type, payload, RecordOptions { id: Some(id), ..Default::default() }, false)
+    }
+
+    /// 時刻・ID・親 Signal を指定して記録する (adopt / import による過去の操作の再構成、テスト用の固定時刻など)。
+    ///
+    /// 過去の時刻を指定しても、Signal は常にログの末尾に追記される。
+    /// 再生順を決めるのはファイル上の順序であり、`timestamp` ではない。
+    ///
+    /// payload が `payload_budget` を超える場合、大きなフィールドは `.flux/blobs/` に退避される。
+    pub fn record_with<T: Serialize>(
+        &self,
+        signal_type: SignalType,
+        payload: T,
+        options: RecordOptions,
+    ) -> Result<Signal> {
+        self.append(signal_type, payload, options, true)
+    }
+
+    /// `check_id` なら、明示された ID を既存のログと照合する。
+    fn append<T: Serialize>(
+        &self,
+        signal_type: SignalType,
+        payload: T,
+        options: RecordOptions,
+        check_id: bool,
+    ) -> Result<Signal> {
+        let _timer = crate::overhead::scope(crate::overhead::RECORD);
+        // 種別は作る時点で検証済みだが、ログに書く名前を念のため規約と照合する
+        SignalType::parse(&signal_type.to_string())?;
+        // ARC_DETERMINISTIC=1 では時刻と ID をログの行数から決める
+        let fixed = match crate::deterministic::is_enabled() {
+            true => crate::deterministic::next(&self.read_signals()?)?,
+            false => None,
+        };
+        let timestamp = match options.timestamp {
+            Some(ts) => {
+                chrono::DateTime::parse_from_rfc3339(&ts)
+                    .with_context(|| format!("RFC 3339 形式の時刻ではありません: {}", ts))?;
+                ts
+            }
+            None => fixed.as_ref().map_or_else(|| Local::now().to_rfc3339(), |(_, ts)| ts.clone()),
+        };
+        // 外から持ち込んだ ID は import の往復などで重複しうるため、既存のログと照合する
+        let id = match options.id {
+            Some(id) => {
+                if check_id && self.read_signals()?.iter().any(|s| s.id == id) {
+                    bail!("ID {} の Signal は既に記録されています", id);
+                }
+                id
+            }
+            None => fixed.map_or_else(new_signal_id, |(id, _)| id),
+        };
+
+        let mut payload = serde_json::to_value(payload)?;
+        crate::deterministic::freeze_durations(&mut payload);
+        if let (Some(parent), Some(fields)) = (options.parent_id, payload.as_object_mut()) {
+            fields.insert(PARENT_ID_KEY.to_string(), serde_json::Value::String(parent));
+        }
+        // --dry-run ではログに書かず、最後にまとめて表示する
+        if crate::dry_run::is_enabled() {
+            let signal = Signal {
+                id,
+                r_type: signal_type.to_string(),
+                payload,
+                timestamp,
+                meta: Some(SignalMeta::current()),
+                v: SCHEMA_VERSION,
+            };
+            crate::dry_run::record(signal.clone());
+            return Ok(signal);
+        }
+        // 読み取り専用のチェックアウトでは、子プロセスの実行だけ記録せずに続ける。
+        // それ以外の種別は、`mutating()` で拒否し損ねたコマンドが記録を失わないようエラーにする
+        if crate::read_only::is_enabled() && !self.user {
+            if !signal_type.is_execution() {
+                bail!("読み取り専用モードのため `{}` を記録できません", signal_type);
+            }
+            crate::read_only::warn_skipped_record();
+            return Ok(Signal {
+                id,
+                r_type: signal_type.to_string(),
+                payload,
+                timestamp,
+                meta: Some(SignalMeta::current()),
+                v: SCHEMA_VERSION,
+            });
+        }
+        let payload = blobs::spill(payload, self.payload_budget, &mut |bytes: &[u8]| self.write_blob(bytes))?;
         let signal = Signal {
-            id: Uuid::now_v7().to_string(),
+            id,
             r_type: signal_type.to_string(),
-            payload: serde_json::to_value(payload)?,
-            timestamp: Local::now().to_rfc3339(),
+            payload,
+            timestamp,
+            meta: Some(SignalMeta::current()),
+            v: SCHEMA_VERSION,
         };
 
         let json = serde_json::to_string(&signal)?;
 
+        let created = !self.signal_file.exists();
         let mut file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.signal_file)
             .with_context(|| format!("Failed to open {:?}", self.signal_file))?;
+        if created {
+            perms::apply_file_mode(&self.signal_file)?;
+        }
 
         writeln!(file, "{}", json)?;
+        crate::signal_hooks::fire(&self.root, &self.signal_hooks, self.parallel_jobs, &signal);
 
         Ok(signal)
     }
 
+    /// 先に採番する Signal の ID (`record_with_id` で使う)。`ARC_DETERMINISTIC=1` では次の番号の ID。
+    pub fn next_signal_id(&self) -> Result<String> {
+        if !crate::deterministic::is_enabled() {
+            return Ok(new_signal_id());
+        }
+        Ok(crate::deterministic::next(&self.read_signals()?)?.map_or_else(new_signal_id, |(id, _)| id))
+    }
+
     /// すべての Signal を時系列順に読み込む。
     pub fn read_signals(&self) -> Result<Vec<Signal>> {
-        if !self.signal_file.exists() {
-            return Ok(vec![]);
+        let _timer = crate::overhead::scope(crate::overhead::READ);
+        self.iter_signals()?.collect()
+    }
+
+    /// Signal を時系列順に 1 行ずつ読み込む。ログ全体をメモリに載せない。
+    pub fn iter_signals(&self) -> Result<impl Iterator<Item = Result<Signal>>> {
+        let file = match fs::File::open(&self.signal_file) {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(file.into_iter().flat_map(|f| parse_lines(std::io::BufReader::new(f))))
+    }
+
+    /// ログの `offset` バイト目から、改行まで書き終わった行の Signal を読み込み、次に読む位置と一緒に返す。
+    /// 書き込み途中の最後の行は次に回す。ログが `offset` より短い (書き換えられた) 場合は `None`。
+    pub fn read_from(&self, offset: u64) -> Result<Option<(Vec<Signal>, u64)>> {
+        let mut file = match fs::File::open(&self.signal_file) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((offset == 0).then(|| (vec![], 0))),
+            Err(e) => return Err(e.into()),
+        };
+        if file.metadata()?.len() < offset {
+            return Ok(None);
         }
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let complete = buf.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let signals = parse_lines(&buf[..complete]).collect::<Result<Vec<_>>>()?;
+        Ok(Some((signals, offset + complete as u64)))
+    }
+
+    /// ログの末尾 `max_bytes` バイトに収まる Signal を時系列順に読み込む。
+    /// 先頭の途中から始まる行と、パースできない行は読み飛ばす。
+    pub fn read_tail(&self, max_bytes: u64) -> Result<Vec<Signal>> {
+        read_tail_at(&self.signal_file, max_bytes)
+    }
 
-        let content = fs::read_to_string(&self.signal_file)?;
-        let mut signals = Vec::new();
+    /// blob の保存先 (`.flux/blobs/`)
+    pub fn blobs_dir(&self) -> PathBuf {
+        self.flux_dir.join(BLOBS_DIR)
+    }
 
-        for (i, line) in content.lines().enumerate() {
-            let signal: Signal = serde_json::from_str(line)
-                .with_context(|| format!("Failed to parse signal at line {}", i + 1))?;
-            signals.push(signal);
+    /// 内容を `.flux/blobs/<sha256>` に書き込み、ハッシュを返す。同じ内容は 1 度だけ保存される。
+    fn write_blob(&self, bytes: &[u8]) -> Result<String> {
+        let hash = blobs::sha256_hex(bytes);
+        let dir = self.blobs_dir();
+        let path = dir.join(&hash);
+        if !path.exists() {
+            perms::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+            // 途中まで書かれた blob を参照されないよう、一時ファイル経由で置き換える
+            let tmp = dir.join(format!(".{}.tmp", hash));
+            fs::write(&tmp, bytes).with_context(|| format!("Failed to write {:?}", tmp))?;
+            fs::rename(&tmp, &path)?;
         }
+        Ok(hash)
+    }
 
-        Ok(signals)
+    /// payload 内の blob 参照を、保存されている内容に置き換えた値を返す。
+    pub fn resolve_blob(&self, value: &serde_json::Value) -> Result<serde_json::Value> {
+        blobs::inline(value, &mut |hash: &str| {
+            let path = crate::safe_path::safe_join(&self.root, self.blobs_dir().join(hash))?;
+            let bytes = fs::read(&path).with_context(|| format!("blob が見つかりません: {:?}", path))?;
+            serde_json::from_slice(&bytes).with_context(|| format!("blob のパースに失敗しました: {:?}", path))
+        })
+    }
+
+    /// どの Signal からも参照されていない blob を削除する。
+    /// 記録の途中 (blob を書き、まだ参照する行を追記していない) の blob を消さないよう、
+    /// `BLOB_GC_GRACE` より新しい blob は残す。
+    pub fn gc_blobs(&self) -> Result<GcReport> {
+        let mut report = GcReport::default();
+        let dir = self.blobs_dir();
+        if !dir.exists() {
+            return Ok(report);
+        }
+
+        let signals = self.read_signals()?;
+        let mut referenced = BTreeSet::new();
+        for signal in &signals {
+            blobs::collect_refs(&signal.payload, &mut referenced);
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if referenced.contains(name.as_str()) {
+                report.kept += 1;
+                continue;
+            }
+            let meta = entry.metadata()?;
+            if meta.modified().ok().and_then(|m| m.elapsed().ok()).is_none_or(|age| age < BLOB_GC_GRACE) {
+                report.kept += 1;
+                continue;
+            }
+            report.freed_bytes += meta.len();
+            fs::remove_file(entry.path())?;
+            report.removed += 1;
+        }
+        Ok(report)
     }
 }
 
@@ -199,6 +817,56 @@ impl FluxProject {
 // ヘルパー関数
 // ─────────────────────────────────────────────
 
+/// Signal ログ `signal_file` の末尾 `max_bytes` バイトに収まる Signal を時系列順に読み込む
+/// (`FluxProject::read_tail` の実体)。プロジェクトを開かずに読みたい `arc prompt` からも使う。
+pub fn read_tail_at(signal_file: &Path, max_bytes: u64) -> Result<Vec<Signal>> {
+    let Ok(mut file) = fs::File::open(signal_file) else { return Ok(vec![]) };
+    let len = file.metadata()?.len();
+    // 1 バイト手前から読み、その改行までを捨てる (行頭から始まっていれば空行を捨てるだけ)
+    let start = len.saturating_sub(max_bytes + 1);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = Vec::new();
+    file.take(max_bytes + 1).read_to_end(&mut buf)?;
+
+    let content = String::from_utf8_lossy(&buf);
+    let mut lines = content.lines();
+    if start > 0 {
+        lines.next();
+    }
+    Ok(lines.filter_map(|line| serde_json::from_str(line).ok()).collect())
+}
+
+/// NDJSON の Signal ログを 1 行ずつパースする (`.flux/signals.jsonl` と `--from` の入力で共通)。
+/// 空行は読み飛ばし、パースできない行は行番号付きのエラーにする。
+pub fn parse_lines<R: BufRead>(reader: R) -> impl Iterator<Item = Result<Signal>> {
+    reader.lines().enumerate().filter_map(|(i, line)| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(serde_json::from_str(&line).with_context(|| format!("Failed to parse signal at line {}", i + 1))),
+        Err(e) => Some(Err(e.into())),
+    })
+}
+
+/// 新しい Signal ID (UUID v7) を採番する。
+pub fn new_signal_id() -> String {
+    Uuid::now_v7().to_string()
+}
+
+/// それまでの最も新しい時刻より `threshold_secs` 秒以上前の時刻を持つ Signal。
+/// 再生はファイル順なので壊れはしないが、時計のずれや不正な import の兆候になる。
+pub fn timestamp_regressions(signals: &[Signal], threshold_secs: i64) -> Vec<&Signal> {
+    let mut latest: Option<chrono::DateTime<chrono::FixedOffset>> = None;
+    let mut regressed = Vec::new();
+    for signal in signals {
+        let Ok(ts) = chrono::DateTime::parse_from_rfc3339(&signal.timestamp) else { continue };
+        match latest {
+            Some(max) if (max - ts).num_seconds() > threshold_secs => regressed.push(signal),
+            Some(max) if ts <= max => {}
+            _ => latest = Some(ts),
+        }
+    }
+    regressed
+}
+
 /// 文字列を指定文字数で安全に切り詰める（Unicode 安全）。
 pub fn truncate_display(s: &str, max_chars: usize) -> String {
     let char_count = s.chars().count();
@@ -209,3 +877,329 @@ pub fn truncate_display(s: &str, max_chars: usize) -> String {
         s.to_string()
     }
 }
+
+/// テスト用: 一時ディレクトリの `name` に初期化したプロジェクト (既にあれば作り直す)。
+#[cfg(test)]
+pub fn temp_project(name: &str) -> (PathBuf, FluxProject) {
+    let root = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    let project = FluxProject::init(&root, &Default::default(), serde_json::json!({})).unwrap().0;
+    (root, project)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn project(name: &str) -> (PathBuf, FluxProject) {
+        let (root, mut project) = temp_project(name);
+        project.payload_budget = 1024;
+        (root, project)
+    }
+
+    fn leftovers(root: &Path) -> Vec<String> {
+        fs::read_dir(root)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_log_state() {
+        let (root, project) = project("arc_log_state_test");
+        assert_eq!(project.log_state(), LogState::Healthy);
+        assert_eq!(FluxProject::log_state_at(&root.join("missing")), LogState::Absent);
+
+        // 書き込み中のクラッシュで残った 0 バイトのファイル
+        fs::write(&project.signal_file, "").unwrap();
+        assert_eq!(project.log_state(), LogState::Empty);
+        assert!(project.read_signals().unwrap().is_empty());
+
+        fs::remove_file(&project.signal_file).unwrap();
+        let project = FluxProject::open(&root).unwrap();
+        assert_eq!(project.log_state(), LogState::Empty);
+        project.ensure_signal_file().unwrap();
+        assert!(project.signal_file.is_file());
+        assert_eq!(project.log_state(), LogState::Empty);
+
+        project.record(SignalType::Add, json!({ "gem": "rack" })).unwrap();
+        assert_eq!(project.log_state(), LogState::Healthy);
+        // 記録があれば何もしない
+        project.ensure_signal_file().unwrap();
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_custom_types_must_be_namespaced() {
+        assert_eq!(SignalType::custom("hook", "start").unwrap().to_string(), "x-hook-start");
+        assert_eq!(SignalType::custom("ci2", "run_end").unwrap().to_string(), "x-ci2-run_end");
+        assert_eq!(SignalType::parse("add").unwrap(), SignalType::Add);
+        assert_eq!(SignalType::parse("shell_enter").unwrap(), SignalType::ShellEnter);
+
+        for bad in ["shel_enter", "x-hook", "x--start", "x-Hook-start", "x-hook-", "x-hook-st art", "hook-start"] {
+            let err = SignalType::parse(bad).unwrap_err().to_string();
+            assert!(err.contains(bad) && err.contains("x-<component>-<name>"), "{}", err);
+        }
+        assert!(SignalType::custom("my-tool", "x").is_err());
+    }
+
+    #[test]
+    fn test_legacy_custom_types_still_load() {
+        let root = std::env::temp_dir().join("arc_signals_legacy_type_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let project = FluxProject::init(&root, &Default::default(), serde_json::json!({})).unwrap().0;
+        let legacy = r#"{"id":"legacy-1","type":"annotation","payload":{},"timestamp":"2024-01-01T00:00:00+09:00"}"#;
+        let mut file = OpenOptions::new().append(true).open(&project.signal_file).unwrap();
+        writeln!(file, "{}", legacy).unwrap();
+        project.record(SignalType::custom("hook", "start").unwrap(), serde_json::json!({})).unwrap();
+
+        let types: Vec<String> = project.read_signals().unwrap().into_iter().map(|s| s.r_type).collect();
+        assert_eq!(types, ["init", "annotation", "x-hook-start"]);
+        assert_eq!(SignalType::kind("annotation"), TypeKind::Legacy);
+        assert_eq!(SignalType::kind("x-hook-start"), TypeKind::Namespaced);
+        assert_eq!(SignalType::kind("shell_exit"), TypeKind::Known);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_init_is_transactional() {
+        let root = std::env::temp_dir().join("arc_signals_init_tx_test");
+        for step in [InitStep::ConfigWritten, InitStep::SignalRecorded] {
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+
+            let result = FluxProject::init_with_hook(&root, &ArcConfig::default(), json!({}), &mut |s| {
+                if s == step { bail!("injected failure at {:?}", s) } else { Ok(()) }
+            });
+            assert!(result.is_err());
+            // 中途半端な .flux も一時ディレクトリも残らない
+            assert!(leftovers(&root).is_empty(), "{:?}: {:?}", step, leftovers(&root));
+
+            let (project, signal) = FluxProject::init(&root, &ArcConfig::default(), json!({ "path": "x" })).unwrap();
+            assert_eq!(signal.r_type, "init");
+            assert_eq!(project.read_signals().unwrap().len(), 1);
+            assert!(project.flux_dir.join("config.toml").exists());
+            assert_eq!(leftovers(&root), [FLUX_DIR]);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_init_cleans_stale_tmp_dir() {
+        let root = std::env::temp_dir().join("arc_signals_init_stale_test");
+        let _ = fs::remove_dir_all(&root);
+        // 強制終了された init が残した一時ディレクトリ (pid は存在しない値)
+        let stale = root.join(format!("{}999999999", FLUX_TMP_PREFIX));
+        fs::create_dir_all(&stale).unwrap();
+        fs::write(stale.join(SIGNAL_FILE), "").unwrap();
+
+        FluxProject::init(&root, &ArcConfig::default(), json!({})).unwrap();
+        assert_eq!(leftovers(&root), [FLUX_DIR]);
+        assert!(FluxProject::init(&root, &ArcConfig::default(), json!({})).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_init_rejects_incomplete_flux_dir() {
+        let root = std::env::temp_dir().join("arc_signals_init_partial_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(FLUX_DIR)).unwrap();
+
+        let Err(err) = FluxProject::init(&root, &ArcConfig::default(), json!({})) else { panic!("should fail") };
+        assert!(err.to_string().contains("interrupted init"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_large_payload_spills_to_blob() {
+        let (root, project) = project("arc_signals_blob_test");
+        let gemfile = "gem 'rails', '~> 7.1'\n".repeat(500);
+        let signal = project
+            .record(SignalType::custom("test", "gemfile-snapshot").unwrap(), json!({ "gemfile": gemfile, "gem": "rails" }))
+            .unwrap();
+
+        // ログの行は上限内に収まり、大きなフィールドは参照になる
+        let log = fs::read_to_string(&project.signal_file).unwrap();
+        assert!(log.lines().last().unwrap().len() < 1024);
+        let hash = blobs::blob_ref(&signal.payload["gemfile"]).unwrap();
+        assert!(project.flux_dir.join(BLOBS_DIR).join(hash).exists());
+        assert_eq!(signal.payload["gem"], "rails");
+
+        let stored = &project.read_signals().unwrap()[1];
+        let resolved = project.resolve_blob(&stored.payload).unwrap();
+        assert_eq!(resolved, json!({ "gemfile": gemfile, "gem": "rails" }));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_gc_removes_only_orphan_blobs() {
+        let (root, project) = project("arc_signals_gc_test");
+        project.record(SignalType::custom("test", "snapshot").unwrap(), json!({ "content": "a".repeat(4096) })).unwrap();
+        let orphan = project.write_blob(b"\"unreferenced\"").unwrap();
+        // 書いたばかりの blob は、参照する行がまだ追記されていないだけかもしれないため残す
+        let fresh = project.write_blob(b"\"in flight\"").unwrap();
+        let blobs = project.flux_dir.join(BLOBS_DIR);
+        let old = std::time::SystemTime::now() - BLOB_GC_GRACE * 2;
+        for entry in fs::read_dir(&blobs).unwrap() {
+            let path = entry.unwrap().path();
+            if !path.ends_with(&fresh) {
+                fs::File::options().append(true).open(path).unwrap().set_modified(old).unwrap();
+            }
+        }
+
+        let report = project.gc_blobs().unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.kept, 2);
+        assert!(blobs.join(&fresh).exists());
+        assert!(!project.flux_dir.join(BLOBS_DIR).join(orphan).exists());
+
+        // 参照されている blob は残り、解決できる
+        let signal = &project.read_signals().unwrap()[1];
+        assert_eq!(project.resolve_blob(&signal.payload).unwrap()["content"], "a".repeat(4096));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn at(timestamp: &str) -> RecordOptions {
+        RecordOptions { timestamp: Some(timestamp.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn test_read_only_skips_only_execution_records() {
+        let (dir, project) = project("arc_record_read_only_test");
+        let user = FluxProject::user_log(&dir.join("user")).unwrap();
+        crate::read_only::scoped(|| {
+            project.record(SignalType::ExecStart, serde_json::json!({})).unwrap();
+            let err = project.record(SignalType::Add, serde_json::json!({ "gem": "rake" })).unwrap_err().to_string();
+            assert!(err.contains("`add`"), "{}", err);
+            // ユーザー単位のログはプロジェクトの外にあるため記録する
+            user.record(SignalType::custom("tool", "run").unwrap(), serde_json::json!({})).unwrap();
+        });
+        assert_eq!(project.read_signals().unwrap().len(), 1);
+        assert_eq!(user.read_signals().unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_with_explicit_timestamp_and_parent() {
+        let (dir, project) = project("arc_record_with_ts_test");
+        let options = RecordOptions {
+            parent_id: Some("parent".to_string()),
+            ..at("2020-01-02T03:04:05+09:00")
+        };
+        let signal = project.record_with(SignalType::Add, serde_json::json!({ "gem": "rake" }), options).unwrap();
+        assert_eq!(signal.timestamp, "2020-01-02T03:04:05+09:00");
+        assert_eq!(signal.payload[PARENT_ID_KEY], "parent");
+
+        // 過去の時刻でも末尾に追記される
+        let signals = project.read_signals().unwrap();
+        assert_eq!(signals.last().unwrap().id, signal.id);
+        assert!(project.record_with(SignalType::Add, serde_json::json!({}), at("yesterday")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_with_rejects_duplicate_id() {
+        let (dir, project) = project("arc_record_with_id_test");
+        let options = RecordOptions { id: Some("imported-1".to_string()), ..Default::default() };
+        project.record_with(SignalType::Import, serde_json::json!({}), options.clone()).unwrap();
+        let err = project.record_with(SignalType::Import, serde_json::json!({}), options).unwrap_err();
+        assert!(err.to_string().contains("imported-1"));
+        assert_eq!(project.read_signals().unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay_follows_file_order() {
+        let (dir, project) = project("arc_record_with_order_test");
+        // 終了の方が開始より前の時刻を持つ (取り込んだ履歴など)
+        let start = project
+            .record_with(SignalType::ExecStart, serde_json::json!({ "command": "rake", "args": [] }), at("2099-03-02T10:00:00+09:00"))
+            .unwrap();
+        project
+            .record_with(
+                SignalType::ExecEnd,
+                serde_json::json!({ "ref_id": start.id, "exit_code": 0, "success": true }),
+                at("2099-03-01T10:00:00+09:00"),
+            )
+            .unwrap();
+        project
+            .record_with(SignalType::ExecStart, serde_json::json!({ "command": "ls", "args": [] }), at("2099-03-01T09:00:00+09:00"))
+            .unwrap();
+
+        let signals = project.read_signals().unwrap();
+        let state = crate::state::FluxState::from_signals(&signals);
+        let commands: Vec<&str> = state.executions.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["rake", "ls"]);
+        assert!(state.executions[0].success);
+
+        let regressed = timestamp_regressions(&signals, TIMESTAMP_REGRESSION_THRESHOLD_SECS);
+        let ids: Vec<&str> = regressed.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, [signals[2].id.as_str(), signals[3].id.as_str()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_tail_skips_partial_line() {
+        let root = std::env::temp_dir().join("arc_read_tail_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let (project, _) = FluxProject::init(&root, &ArcConfig::default(), serde_json::json!({})).unwrap();
+        for i in 0..50 {
+            project.record(SignalType::Add, serde_json::json!({ "gem": format!("gem{}", i) })).unwrap();
+        }
+        let all = project.read_signals().unwrap();
+        assert_eq!(project.read_tail(u64::MAX / 2).unwrap().len(), all.len());
+
+        let tail = project.read_tail(1024).unwrap();
+        assert!(!tail.is_empty() && tail.len() < all.len());
+        assert_eq!(tail.last().unwrap().id, all.last().unwrap().id);
+        assert_eq!(tail[0].id, all[all.len() - tail.len()].id);
+
+        // ちょうど行頭から読む場合も、その行を含める
+        let last_line = serde_json::to_string(all.last().unwrap()).unwrap().len() as u64 + 1;
+        assert_eq!(project.read_tail(last_line).unwrap().len(), 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_meta_is_optional() {
+        // meta を持たない古い Signal も読めて、unknown として扱う
+        let old: Signal = serde_json::from_str(r#"{"id":"1","type":"add","payload":{},"timestamp":"2026-01-01T00:00:00Z"}"#).unwrap();
+        assert_eq!(old.meta, None);
+        assert_eq!(old.user(), UNKNOWN_USER);
+        assert!(!serde_json::to_string(&old).unwrap().contains("meta"));
+
+        let (dir, project) = project("arc_record_meta_test");
+        let recorded = project.record(SignalType::Add, json!({})).unwrap();
+        assert_eq!(recorded.meta, Some(SignalMeta::current()));
+        assert_eq!(project.read_signals().unwrap().pop().unwrap().meta, recorded.meta);
+
+        let by = |user: &str| Signal { meta: Some(SignalMeta { user: Some(user.into()), host: None }), ..old.clone() };
+        assert_eq!(distinct_users(&[by("alice"), by("alice")]), 1);
+        assert_eq!(distinct_users(&[by("alice"), old.clone(), by("bob")]), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_schema_version() {
+        // v のない Signal は v1
+        let old: Signal = serde_json::from_str(r#"{"id":"1","type":"add","payload":{},"timestamp":"2026-01-01T00:00:00Z"}"#).unwrap();
+        assert_eq!(old.v, 1);
+
+        let (dir, project) = project("arc_record_schema_version_test");
+        project.record(SignalType::Add, json!({})).unwrap();
+        let line = fs::read_to_string(&project.signal_file).unwrap().lines().last().unwrap().to_string();
+        assert!(line.ends_with(&format!(r#","v":{}}}"#, SCHEMA_VERSION)), "{}", line);
+        assert_eq!(project.read_signals().unwrap().pop().unwrap().v, SCHEMA_VERSION);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+//! Gemfile.lock のスナップショット (`.flux/snapshots/`) による undo の高速化。
+//!
+//! add / remove / undo が install を始める前に、その時点で同期済みの環境の Gemfile.lock を
+//! sync-state のダイジェスト (Gemfile・Gemfile.lock・Ruby バージョン) をキーに保存する。
+//! undo で Gemfile を戻した結果が、いずれかのスナップショットのダイジェストと一致し、
+//! そのロックファイルの Gem がすべてグローバルキャッシュにあれば、ロックファイルを戻して
+//! `bundle install --local` を実行するだけで済む (依存の再解決とダウンロードが不要)。
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::lockfile;
+use crate::prune;
+use crate::state::FluxState;
+use crate::sync_state;
+
+/// スナップショットのディレクトリ名 (`.flux/snapshots/`)
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+/// ロックファイルのスナップショットの拡張子
+const LOCK_SUFFIX: &str = ".Gemfile.lock";
+
+/// スナップショットのディレクトリ
+pub fn snapshots_dir(flux_dir: &Path) -> PathBuf {
+    flux_dir.join(SNAPSHOTS_DIR)
+}
+
+/// 環境が同期済み (sync-state がある) なら、現在の Gemfile.lock をそのダイジェストで保存する。
+/// 保存したダイジェストを返す。同じダイジェストは 1 度だけ保存される。
+pub fn take(flux_dir: &Path, cwd: &Path, env_dir: &Path) -> Result<Option<String>> {
+    let Some(digest) = sync_state::load(env_dir) else { return Ok(None) };
+    let Ok(lock) = fs::read(cwd.join("Gemfile.lock")) else { return Ok(None) };
+    let dir = snapshots_dir(flux_dir);
+    let path = dir.join(format!("{}{}", digest, LOCK_SUFFIX));
+    if !path.exists() {
+        crate::perms::create_dir_all(&dir)?;
+        fs::write(&path, lock).with_context(|| format!("Failed to write {:?}", path))?;
+    }
+    Ok(Some(digest))
+}
+
+/// undo 後の install の進め方。
+#[derive(Debug, Clone, PartialEq)]
+pub enum RestorePlan {
+    /// スナップショットのロックファイルを戻し、キャッシュだけで `bundle install --local` する
+    Fast { digest: String, lockfile: String },
+    /// 通常どおり依存を解決する
+    Resolve { reason: String },
+}
+
+impl RestorePlan {
+    /// undo Signal に記録する内容。`estimate_us` は通常の install にかかる時間の見込み。
+    pub fn to_json(&self, estimate_us: Option<u64>) -> Value {
+        match self {
+            RestorePlan::Fast { digest, .. } => json!({
+                "path": "snapshot",
+                "snapshot": digest,
+                "estimated_saved_us": estimate_us,
+            }),
+            RestorePlan::Resolve { reason } => json!({ "path": "resolve", "reason": reason }),
+        }
+    }
+}
+
+/// 現在の Gemfile (undo で戻した後) に一致するスナップショットを探し、進め方を決める。
+pub fn plan(flux_dir: &Path, cwd: &Path, ruby_version: &str, gem_cache: &Path) -> RestorePlan {
+    match fs::read(cwd.join("Gemfile")) {
+        Ok(gemfile) => plan_for(flux_dir, &gemfile, ruby_version, gem_cache),
+        Err(_) => RestorePlan::Resolve { reason: "Gemfile not found".to_string() },
+    }
+}
+
+/// `plan` と同じだが、書き込む前の Gemfile の内容で探す。
+pub fn plan_for(flux_dir: &Path, gemfile: &[u8], ruby_version: &str, gem_cache: &Path) -> RestorePlan {
+    let resolve = |reason: &str| RestorePlan::Resolve { reason: reason.to_string() };
+    let Ok(entries) = fs::read_dir(snapshots_dir(flux_dir)) else { return resolve("no snapshots") };
+
+    let found = entries.flatten().find_map(|e| {
+        let name = e.file_name().to_string_lossy().to_string();
+        let digest = name.strip_suffix(LOCK_SUFFIX)?.to_string();
+        // プロジェクトの外を指すシンボリックリンクは読まない
+        let path = crate::safe_path::safe_join(flux_dir.parent()?, e.path()).ok()?;
+        let lock = fs::read_to_string(path).ok()?;
+        (sync_state::digest_of(gemfile, lock.as_bytes(), ruby_version) == digest).then_some((digest, lock))
+    });
+    let Some((digest, lockfile)) = found else { return resolve("no snapshot matches the restored Gemfile") };
+
+    let missing = missing_from_cache(&lockfile, gem_cache);
+    if !missing.is_empty() {
+        return RestorePlan::Resolve {
+            reason: format!("{} gem(s) missing from the cache (e.g. {})", missing.len(), missing[0]),
+        };
+    }
+    RestorePlan::Fast { digest, lockfile }
+}
+
+/// ロックファイルの Gem のうち、グローバルキャッシュ (`gems/` と `specifications/`) にないもの。
+pub fn missing_from_cache(lock_content: &str, gem_cache: &Path) -> Vec<String> {
+    prune::required_set(&lockfile::parse_content(lock_content))
+        .into_iter()
+        .filter(|dir| {
+            !gem_cache.join("gems").join(dir).is_dir()
+                || !gem_cache.join("specifications").join(format!("{}.gemspec", dir)).is_file()
+        })
+        .collect()
+}
+
+/// 依存を解決する通常の install (`--local` なし) の平均時間。成功した記録がなければ `None`。
+pub fn full_install_estimate_us(state: &FluxState) -> Option<u64> {
+    let durations: Vec<u64> = state
+        .executions
+        .iter()
+        .filter(|e| e.kind == "install" && e.success && !e.args.iter().any(|a| a == "--local"))
+        .filter_map(|e| e.duration.map(|d| d.as_micros() as u64))
+        .collect();
+    (!durations.is_empty()).then(|| durations.iter().sum::<u64>() / durations.len() as u64)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCK: &str = "GEM\n  specs:\n    json (2.7.1)\n    nokogiri (1.16.0-x86_64-linux)\n";
+
+    fn cache_with(root: &Path, dirs: &[&str]) -> PathBuf {
+        let cache = root.join("cache");
+        for dir in dirs {
+            fs::create_dir_all(cache.join("gems").join(dir)).unwrap();
+            fs::create_dir_all(cache.join("specifications")).unwrap();
+            fs::write(cache.join("specifications").join(format!("{}.gemspec", dir)), "").unwrap();
+        }
+        cache
+    }
+
+    /// Gemfile + LOCK で同期済みのプロジェクトと、そのスナップショット
+    fn fixture(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let (cwd, flux) = (root.join("app"), root.join("app/.flux"));
+        let env = cwd.join(".arc/env");
+        fs::create_dir_all(&flux).unwrap();
+        fs::write(cwd.join("Gemfile"), "gem 'json'\ngem 'nokogiri'\n").unwrap();
+        fs::write(cwd.join("Gemfile.lock"), LOCK).unwrap();
+        sync_state::save(&env, &sync_state::compute_digest(&cwd, "3.3.6").unwrap()).unwrap();
+        (root, cwd, env)
+    }
+
+    #[test]
+    fn test_plan_requires_digest_match() {
+        let (root, cwd, env) = fixture("arc_snapshot_digest_test");
+        let flux = cwd.join(".flux");
+        let digest = take(&flux, &cwd, &env).unwrap().unwrap();
+        let cache = cache_with(&root, &["json-2.7.1", "nokogiri-1.16.0-x86_64-linux"]);
+
+        // add で Gemfile とロックファイルが変わった後、undo で Gemfile だけが戻った状態
+        fs::write(cwd.join("Gemfile.lock"), "GEM\n  specs:\n    rake (13.1.0)\n").unwrap();
+        assert_eq!(plan(&flux, &cwd, "3.3.6", &cache), RestorePlan::Fast { digest, lockfile: LOCK.to_string() });
+
+        // Ruby のバージョンが違えばダイジェストは一致しない
+        assert!(matches!(plan(&flux, &cwd, "3.4.0", &cache), RestorePlan::Resolve { .. }));
+        // Gemfile が違っても一致しない
+        fs::write(cwd.join("Gemfile"), "gem 'json'\n").unwrap();
+        assert_eq!(
+            plan(&flux, &cwd, "3.3.6", &cache),
+            RestorePlan::Resolve { reason: "no snapshot matches the restored Gemfile".into() }
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_plan_requires_complete_cache() {
+        let (root, cwd, env) = fixture("arc_snapshot_cache_test");
+        let flux = cwd.join(".flux");
+        take(&flux, &cwd, &env).unwrap();
+        let cache = cache_with(&root, &["json-2.7.1"]);
+
+        assert_eq!(missing_from_cache(LOCK, &cache), ["nokogiri-1.16.0-x86_64-linux"]);
+        match plan(&flux, &cwd, "3.3.6", &cache) {
+            RestorePlan::Resolve { reason } => assert!(reason.contains("1 gem(s) missing"), "{}", reason),
+            other => panic!("{:?}", other),
+        }
+        // gemspec がなければキャッシュにないものとして扱う
+        fs::create_dir_all(cache.join("gems/nokogiri-1.16.0-x86_64-linux")).unwrap();
+        assert_eq!(missing_from_cache(LOCK, &cache).len(), 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_take_requires_synced_env() {
+        let (root, cwd, env) = fixture("arc_snapshot_take_test");
+        let flux = cwd.join(".flux");
+        sync_state::clear(&env);
+        assert_eq!(take(&flux, &cwd, &env).unwrap(), None);
+        assert!(!snapshots_dir(&flux).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
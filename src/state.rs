@@ -1,5 +1,10 @@
+use crate::exec_context::ExecContext;
+use crate::exec_label::GemDelta;
 use crate::signals::Signal;
-use std::collections::HashMap;
+use chrono::{DateTime, Days, Local, NaiveDate, TimeZone, Utc};
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
 
 // ─────────────────────────────────────────────
 // State (Signal ログから再構築される環境状態)
@@ -7,29 +12,153 @@ use std::collections::HashMap;
 
 /// 個々のコマンド実行記録（exec_start + exec_end のペア）
 /// Phase 2 で `cwd`, `ended_at`, `start_id` の内容を利用予定。
-#[derive(Debug)]
+///
+/// 時刻と実行時間は `FluxState::from_signals` で一度だけ解釈する。時刻は UTC で持つ。
+/// JSON では実行時間をミリ秒の数値、時刻を RFC 3339 の文字列で表す (Signal の payload と同じ形)。
+#[derive(Debug, Serialize)]
 #[allow(dead_code)]
 pub struct Execution {
     pub command: String,
+    /// `arc run <alias>` で実行された場合のエイリアス名
+    pub alias: Option<String>,
+    /// `arc task <name>` (`arc test`) で実行された場合のタスク名
+    pub task: Option<String>,
+    /// 実行経路 ("exec" / "run" / "install")
+    pub kind: String,
+    /// `arc run --detach` で実行されたか
+    pub detached: bool,
     pub args: Vec<String>,
     pub cwd: String,
     pub exit_code: Option<i64>,
     pub success: bool,
-    pub duration_ms: Option<u64>,
-    pub started_at: String,
-    pub ended_at: Option<String>,
+    /// 失敗の分類 (install の `failure_kind`)
+    pub failure_kind: Option<String>,
+    /// 子プロセスを終了させたシグナルの番号
+    pub signal: Option<i64>,
+    /// 実行時間 (マイクロ秒の精度)。`duration_us` を持たない古い Signal では `duration_ms` から換算する
+    #[serde(rename = "duration_ms", serialize_with = "serialize_ms")]
+    pub duration: Option<Duration>,
+    /// 開始時刻。開始 Signal がないか、時刻が不正なら `None`
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub ended_at: Option<DateTime<Utc>>,
     pub start_id: String,
+    /// 実行したユーザー (開始 Signal の meta)。記録がなければ `signals::UNKNOWN_USER`
+    pub user: String,
+    /// install (`bundle install` / `bundle update`) の前に `arc add` / `arc remove` で増減した Gem の数 (表示用)
+    #[serde(skip)]
+    pub gem_delta: Option<GemDelta>,
+    /// 実行した場所 (TTY・CI など、開始 Signal の `context`)。記録のない古い Signal では `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ExecContext>,
+}
+
+impl Execution {
+    /// コマンドと引数をスペース区切りでつなげたコマンドライン
+    pub fn command_line(&self) -> String {
+        if self.args.is_empty() {
+            self.command.clone()
+        } else {
+            format!("{} {}", self.command, self.args.join(" "))
+        }
+    }
+
+    /// 統計で使う表示名。タスク・エイリアス経由の実行はその名前でまとめる
+    pub fn display_name(&self) -> &str {
+        self.task.as_deref().or(self.alias.as_deref()).unwrap_or(&self.command)
+    }
+
+    /// `stats.ignore` のいずれかのパターンに一致するか
+    pub fn is_ignored(&self, patterns: &[String]) -> bool {
+        let line = self.command_line();
+        patterns.iter().any(|p| matches_ignore(p, &self.command, &line))
+    }
+
+    /// 失敗の分類: 記録された `failure_kind`、シグナルによる終了 (`signal`)、
+    /// 終了コードだけ (`exit_code`) の順に見る。どれもなければ `unclassified`
+    pub fn failure_class(&self) -> &str {
+        match (&self.failure_kind, self.signal, self.exit_code) {
+            (Some(kind), _, _) => kind,
+            (None, Some(_), _) => "signal",
+            (None, None, Some(_)) => "exit_code",
+            (None, None, None) => UNCLASSIFIED,
+        }
+    }
+}
+
+/// 分類できない失敗 (終了の記録がない実行など) のグループ名
+pub const UNCLASSIFIED: &str = "unclassified";
+
+/// 同じ分類・同じコマンドの失敗をまとめたもの (`FluxState::failed_summary`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FailureGroup {
+    /// `Execution::failure_class`
+    pub kind: String,
+    /// 表示名 (`Execution::display_name`)
+    pub command: String,
+    pub count: usize,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub first_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub last_at: Option<DateTime<Utc>>,
+    /// 最後の失敗のコマンドライン・終了コード・シグナル
+    pub last_command_line: String,
+    pub last_exit_code: Option<i64>,
+    pub last_signal: Option<i64>,
+}
+
+/// start/end Signal の種別から実行経路 ("exec_start" → "exec") を取り出す。
+fn execution_kind(r_type: &str) -> String {
+    r_type.split('_').next().unwrap_or(r_type).to_string()
+}
+
+/// `bundle install` / `bundle update` (`arc exec` などで直接実行したもの) か
+fn is_bundle_install(command: &str, args: &[String]) -> bool {
+    command == "bundle" && args.first().is_some_and(|sub| sub == "install" || sub == "update")
+}
+
+/// `stats.ignore` のパターン照合。
+/// - glob 文字 (`*`, `?`) を含む場合: コマンドライン全体に対する glob
+/// - 含まない場合: プログラム名またはコマンドライン全体との完全一致
+pub fn matches_ignore(pattern: &str, program: &str, command_line: &str) -> bool {
+    if pattern.contains(['*', '?']) {
+        glob_match(pattern.as_bytes(), command_line.as_bytes())
+    } else {
+        pattern == program || pattern == command_line
+    }
+}
+
+/// `*` (0 文字以上) と `?` (任意の 1 文字) のみをサポートする簡易 glob。
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], text) || (!text.is_empty() && glob_match(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p == t => glob_match(&pattern[1..], &text[1..]),
+        _ => false,
+    }
 }
 
 /// コマンドごとの集計統計
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct CommandStats {
     pub command: String,
     pub total_runs: usize,
     pub successes: usize,
     pub failures: usize,
-    pub avg_duration_ms: Option<u64>,
-    pub last_run: String,
+    #[serde(rename = "avg_duration_ms", serialize_with = "serialize_ms")]
+    pub avg_duration: Option<Duration>,
+    /// 95 パーセンタイルの実行時間 (nearest-rank)
+    #[serde(rename = "p95_duration_ms", serialize_with = "serialize_ms")]
+    pub p95_duration: Option<Duration>,
+    /// 最後の実行の開始時刻。開始時刻の分かる実行がなければ `None`
+    #[serde(serialize_with = "serialize_rfc3339")]
+    pub last_run: Option<DateTime<Utc>>,
+    /// 実行経路や `detached` などのタグ (重複なし・ソート済み)
+    pub tags: Vec<String>,
 }
 
 /// Signal ログから再構築されたプロジェクト状態
@@ -45,93 +174,407 @@ pub struct FluxState {
     pub executions: Vec<Execution>,
     /// Signal 総数
     pub signal_count: usize,
+    /// 解釈できなかった値 (不正な時刻など)
+    pub anomalies: Vec<Anomaly>,
+    /// bootstrap で入れた Ruby (時系列順)
+    pub ruby_installs: Vec<RubyInstall>,
+}
+
+/// bootstrap Signal 1 件分の Ruby (`arc state --ruby-history`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RubyInstall {
+    pub version: String,
+    /// キャッシュから入れた (`adopt` で記録したものは `None`)
+    pub cache_hit: Option<bool>,
+    pub timestamp: String,
+    pub signal_id: String,
+}
+
+/// Signal の中で解釈できなかった値。該当するフィールドは `None` として扱う。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub signal_id: String,
+    pub field: &'static str,
+    pub value: String,
+}
+
+/// Signal の `timestamp` を解釈する。不正なら `anomalies` に記録して `None` を返す。
+fn parse_timestamp(signal: &Signal, anomalies: &mut Vec<Anomaly>) -> Option<DateTime<Utc>> {
+    match DateTime::parse_from_rfc3339(&signal.timestamp) {
+        Ok(ts) => Some(ts.to_utc()),
+        Err(_) => {
+            anomalies.push(Anomaly { signal_id: signal.id.clone(), field: "timestamp", value: signal.timestamp.clone() });
+            None
+        }
+    }
+}
+
+/// 実行時間をミリ秒の数値として書き出す (`duration_ms` と同じ切り捨て)。
+fn serialize_ms<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(d) => serializer.serialize_u64(d.as_millis() as u64),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// 時刻を RFC 3339 の文字列 (UTC) として書き出す (Signal の `timestamp` と同じ形)。
+pub(crate) fn serialize_rfc3339<S: Serializer>(ts: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match ts {
+        Some(ts) => serializer.serialize_str(&ts.to_rfc3339()),
+        None => serializer.serialize_none(),
+    }
 }
 
 impl FluxState {
-    /// Signal のベクターから State を再構築する
+    /// Signal のベクターから State を再構築する (`StateBuilder` に順に適用した結果と同じ)
     pub fn from_signals(signals: &[Signal]) -> Self {
-        let mut state = FluxState {
-            project_path: None,
-            version: None,
-            initialized_at: None,
-            executions: Vec::new(),
-            signal_count: signals.len(),
-        };
+        signals.iter().fold(StateBuilder::new(), |mut builder, signal| {
+            builder.apply(signal);
+            builder
+        }).finish()
+    }
 
-        // exec_start を一時的に保持する HashMap
-        let mut pending_starts: HashMap<String, &Signal> = HashMap::new();
+    /// コマンドごとの統計を計算する。`ignore` に一致する実行は集計から除外する。
+    pub fn command_stats(&self, ignore: &[String]) -> Vec<CommandStats> {
+        self.command_stats_where(ignore, |_| true)
+    }
 
-        for signal in signals {
-            match signal.r_type.as_str() {
-                "init" => {
-                    state.initialized_at = Some(signal.timestamp.clone());
-                    state.project_path = signal.payload.get("path")
-                        .and_then(|v| v.as_str())
-                        .map(|s| s.to_string());
-                    state.version = signal.payload.get("version")
-                        .and_then(|v| v.as_str())
-                        .map(|s| s.to_string());
+    /// `keep` を満たす実行だけでコマンドごとの統計を計算する (`arc stats --compare` の期間)。
+    pub fn command_stats_where(&self, ignore: &[String], keep: impl Fn(&Execution) -> bool) -> Vec<CommandStats> {
+        let mut stats_map: HashMap<String, Vec<&Execution>> = HashMap::new();
+
+        for exec in self.executions.iter().filter(|e| !e.is_ignored(ignore) && keep(e)) {
+            stats_map.entry(exec.display_name().to_string()).or_default().push(exec);
+        }
+
+        let mut stats: Vec<CommandStats> = stats_map
+            .into_iter()
+            .map(|(command, execs)| summarize(command, &execs))
+            .collect();
+
+        // 最新のコマンドが上に来るようにソート
+        stats.sort_by_key(|s| std::cmp::Reverse(s.last_run));
+        stats
+    }
+
+    /// ユーザーごとの統計を計算する (`arc stats --by user`)。並び順は `command_stats` と同じ。
+    pub fn user_stats(&self, ignore: &[String]) -> Vec<CommandStats> {
+        self.stats_by(ignore, |exec| exec.user.as_str())
+    }
+
+    /// 実行した場所ごとの統計 (`arc stats --by context`)。CI の提供元・`local`・記録のない古い実行は `unknown`
+    pub fn context_stats(&self, ignore: &[String]) -> Vec<CommandStats> {
+        self.stats_by(ignore, |exec| exec.context.as_ref().map_or(crate::exec_context::UNKNOWN, ExecContext::group))
+    }
+
+    /// `key` ごとの統計。最近実行したものが上に来る
+    fn stats_by<'a>(&'a self, ignore: &[String], key: impl Fn(&'a Execution) -> &'a str) -> Vec<CommandStats> {
+        let mut stats_map: HashMap<&str, Vec<&Execution>> = HashMap::new();
+        for exec in self.executions.iter().filter(|e| !e.is_ignored(ignore)) {
+            stats_map.entry(key(exec)).or_default().push(exec);
+        }
+
+        let mut stats: Vec<CommandStats> = stats_map
+            .into_iter()
+            .map(|(key, execs)| summarize(key.to_string(), &execs))
+            .collect();
+        stats.sort_by_key(|s| std::cmp::Reverse(s.last_run));
+        stats
+    }
+
+    /// `user` の実行だけを残す (`--user`)。
+    pub fn retain_user(&mut self, user: &str) {
+        self.executions.retain(|e| e.user == user);
+    }
+
+    /// 日付 (開始時刻のローカル日付 `YYYY-MM-DD`) × コマンドごとの統計を計算する。
+    /// 日付の昇順、同じ日付内ではコマンド名の昇順に並ぶ。開始時刻が不明な実行は含めない。
+    pub fn daily_stats(&self, ignore: &[String]) -> Vec<(String, CommandStats)> {
+        let mut buckets: BTreeMap<(String, String), Vec<&Execution>> = BTreeMap::new();
+
+        for exec in self.executions.iter().filter(|e| !e.is_ignored(ignore)) {
+            let Some(started) = exec.started_at else { continue };
+            buckets
+                .entry((started.with_timezone(&Local).date_naive().to_string(), exec.display_name().to_string()))
+                .or_default()
+                .push(exec);
+        }
+
+        buckets
+            .into_iter()
+            .map(|((date, command), execs)| (date, summarize(command, &execs)))
+            .collect()
+    }
+
+    /// `ignore` によって集計から除外される実行の数
+    pub fn ignored_count(&self, ignore: &[String]) -> usize {
+        self.executions.iter().filter(|e| e.is_ignored(ignore)).count()
+    }
+
+    /// bootstrap で入れた Ruby のバージョンの履歴 (古い順)
+    pub fn ruby_history(&self) -> &[RubyInstall] {
+        &self.ruby_installs
+    }
+
+    /// 最後に実行されたコマンド
+    pub fn last_execution(&self) -> Option<&Execution> {
+        self.executions.last()
+    }
+
+    /// 失敗したコマンドの一覧
+    pub fn failed_executions(&self) -> Vec<&Execution> {
+        self.executions.iter().filter(|e| !e.success).collect()
+    }
+
+    /// 失敗を分類 (`Execution::failure_class`) とコマンドの組でまとめる。最後の失敗が新しいグループが先。
+    pub fn failed_summary(&self) -> Vec<FailureGroup> {
+        // (グループ, 最後の失敗のログ上の位置)
+        let mut groups: Vec<(FailureGroup, usize)> = Vec::new();
+        for (index, exec) in self.executions.iter().enumerate().filter(|(_, e)| !e.success) {
+            let kind = exec.failure_class();
+            let command = exec.display_name();
+            let position = groups.iter().position(|(g, _)| g.kind == kind && g.command == command);
+            let (group, last) = match position {
+                Some(i) => &mut groups[i],
+                None => {
+                    groups.push((
+                        FailureGroup {
+                            kind: kind.to_string(),
+                            command: command.to_string(),
+                            count: 0,
+                            first_at: exec.started_at,
+                            last_at: None,
+                            last_command_line: String::new(),
+                            last_exit_code: None,
+                            last_signal: None,
+                        },
+                        index,
+                    ));
+                    groups.last_mut().unwrap()
                 }
-                "exec_start" | "install_start" | "run_start" => {
-                    // For these start signals, we just store them to match with their corresponding end signals.
-                    // The actual logic for active_operation, history_count, etc., is not part of FluxState.
-                    pending_starts.insert(signal.id.clone(), signal);
+            };
+            group.count += 1;
+            group.first_at = match (group.first_at, exec.started_at) {
+                (Some(first), Some(started)) => Some(first.min(started)),
+                (first, started) => first.or(started),
+            };
+            if (exec.started_at, index) >= (group.last_at, *last) {
+                group.last_at = exec.started_at;
+                group.last_command_line = exec.command_line();
+                group.last_exit_code = exec.exit_code;
+                group.last_signal = exec.signal;
+                *last = index;
+            }
+        }
+        groups.sort_by_key(|(g, last)| std::cmp::Reverse((g.last_at, *last)));
+        groups.into_iter().map(|(g, _)| g).collect()
+    }
+
+    /// 今日までの `days` 日間の日別の実行数・失敗数 (古い日付が先)。
+    /// 日付の境界はローカルタイムゾーンで数える。
+    pub fn activity(&self, days: usize) -> Vec<DayActivity> {
+        self.activity_in(days, Local::now().date_naive(), &Local)
+    }
+
+    /// `today` までの `days` 日間を、タイムゾーン `tz` の日付で集計する。
+    pub fn activity_in<Tz: TimeZone>(&self, days: usize, today: NaiveDate, tz: &Tz) -> Vec<DayActivity> {
+        let Some(first) = today.checked_sub_days(Days::new(days.saturating_sub(1) as u64)) else { return vec![] };
+        let mut buckets: Vec<DayActivity> = first
+            .iter_days()
+            .take(days)
+            .map(|date| DayActivity { date, executions: 0, failures: 0 })
+            .collect();
+
+        for exec in &self.executions {
+            let Some(started) = exec.started_at else { continue };
+            let date = started.with_timezone(tz).date_naive();
+            let Ok(offset) = usize::try_from((date - first).num_days()) else { continue };
+            if let Some(day) = buckets.get_mut(offset) {
+                day.executions += 1;
+                if !exec.success {
+                    day.failures += 1;
                 }
-                "exec_end" | "install_end" | "run_end" => {
-                    // For these end signals, we process them similarly to exec_end.
-                    // The logic for active_operation, last_exit_code, etc., is not part of FluxState.
-                    let ref_id = signal.payload.get("ref_id")
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("");
-
-                    let start_signal = pending_starts.remove(ref_id);
-
-                    let (command, args, cwd, started_at, start_id) = if let Some(start) = start_signal {
-                        let cmd = start.payload.get("command")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("unknown")
-                            .to_string();
-                        let args = start.payload.get("args")
-                            .and_then(|v| v.as_array())
-                            .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
-                            .unwrap_or_default();
-                        let cwd = start.payload.get("cwd")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("")
-                            .to_string();
-                        (cmd, args, cwd, start.timestamp.clone(), start.id.clone())
-                    } else {
-                        ("unknown".to_string(), vec![], String::new(), String::new(), String::new())
-                    };
-
-                    let exit_code = signal.payload.get("exit_code")
-                        .and_then(|v| v.as_i64());
-                    let success = signal.payload.get("success")
-                        .and_then(|v| v.as_bool())
-                        .unwrap_or(false);
-                    let duration_ms = signal.payload.get("duration_ms")
-                        .and_then(|v| v.as_u64());
-
-                    state.executions.push(Execution {
-                        command,
-                        args,
-                        cwd,
-                        exit_code,
-                        success,
-                        duration_ms,
-                        started_at,
-                        ended_at: Some(signal.timestamp.clone()),
-                        start_id,
+            }
+        }
+        buckets
+    }
+}
+
+/// 1 日分の実行数 (`arc state --activity`)。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayActivity {
+    pub date: NaiveDate,
+    pub executions: usize,
+    pub failures: usize,
+}
+
+// ─────────────────────────────────────────────
+// StateBuilder (Signal を 1 件ずつ適用する)
+// ─────────────────────────────────────────────
+
+/// Signal を 1 件ずつ適用して `FluxState` を組み立てる。
+///
+/// ログを監視する表示 (`arc stats --follow --watch`) は、新しく追記された行だけを `apply` すればよい。
+/// 対になる end を待っている start と、次の install に付ける `arc add` / `arc remove` の数を持ち越す。
+/// パーセンタイルなどの統計は `FluxState` から読むときに計算する。
+#[derive(Debug)]
+pub struct StateBuilder {
+    /// end まで揃った実行だけを含む State
+    state: FluxState,
+    /// end を待っている start (ログの中の位置と Signal)
+    pending_starts: HashMap<String, (usize, Signal)>,
+    /// 直前の install 以降の `arc add` / `arc remove`
+    gem_delta: GemDelta,
+}
+
+impl Default for StateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateBuilder {
+    pub fn new() -> Self {
+        StateBuilder {
+            state: FluxState {
+                project_path: None,
+                version: None,
+                initialized_at: None,
+                executions: Vec::new(),
+                signal_count: 0,
+                anomalies: Vec::new(),
+                ruby_installs: Vec::new(),
+            },
+            pending_starts: HashMap::new(),
+            gem_delta: GemDelta::default(),
+        }
+    }
+
+    /// ここまでに適用した Signal の State。end を待っている start はまだ `executions` に含まない。
+    pub fn state(&self) -> &FluxState {
+        &self.state
+    }
+
+    /// Signal を 1 件適用する。完了した実行は `state().executions` の末尾に加わる。
+    pub fn apply(&mut self, signal: &Signal) {
+        match signal.r_type.as_str() {
+            "init" => {
+                self.state.initialized_at = Some(signal.timestamp.clone());
+                self.state.project_path = signal.payload.get("path")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+                self.state.version = signal.payload.get("version")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+            }
+            "bootstrap" => {
+                if let Some(version) = signal.payload.get("ruby_version").and_then(|v| v.as_str()) {
+                    self.state.ruby_installs.push(RubyInstall {
+                        version: version.to_string(),
+                        cache_hit: signal.payload.get("cache_hit").and_then(|v| v.as_bool()),
+                        timestamp: signal.timestamp.clone(),
+                        signal_id: signal.id.clone(),
                     });
                 }
-                _ => {
-                    // Unknown signal types are ignored (forward compatibility)
-                }
+            }
+            "add" => self.gem_delta.added += 1,
+            "remove" => self.gem_delta.removed += 1,
+            "exec_start" | "install_start" | "run_start" => {
+                // For these start signals, we just store them to match with their corresponding end signals.
+                // The actual logic for active_operation, history_count, etc., is not part of FluxState.
+                self.pending_starts.insert(signal.id.clone(), (self.state.signal_count, signal.clone()));
+            }
+            "exec_end" | "install_end" | "run_end" => {
+                // For these end signals, we process them similarly to exec_end.
+                // The logic for active_operation, last_exit_code, etc., is not part of FluxState.
+                let ref_id = signal.payload.get("ref_id")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("");
+
+                let start_signal = self.pending_starts.remove(ref_id).map(|(_, start)| start);
+                let start_signal = start_signal.as_ref();
+                let user = start_signal.unwrap_or(signal).user().to_string();
+
+                let alias = start_signal.and_then(|s| s.payload.get("alias"))
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+                let task = start_signal.and_then(|s| s.payload.get("task"))
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+                let detached = signal.payload.get("detached")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let (command, args, cwd, started_at, start_id) = if let Some(start) = start_signal {
+                    let cmd = start.payload.get("command")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("unknown")
+                        .to_string();
+                    let args = start.payload.get("args")
+                        .and_then(|v| v.as_array())
+                        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+                        .unwrap_or_default();
+                    let cwd = start.payload.get("cwd")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("")
+                        .to_string();
+                    (cmd, args, cwd, parse_timestamp(start, &mut self.state.anomalies), start.id.clone())
+                } else {
+                    ("unknown".to_string(), vec![], String::new(), None, String::new())
+                };
+
+                let exit_code = signal.payload.get("exit_code")
+                    .and_then(|v| v.as_i64());
+                let failure_kind = signal.payload.get("failure_kind")
+                    .and_then(|v| v.as_str())
+                    .map(String::from);
+                let term_signal = signal.payload.get("signal")
+                    .and_then(|v| v.as_i64());
+                let success = signal.payload.get("success")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let duration = signal.payload.get("duration_us")
+                    .and_then(|v| v.as_u64())
+                    .map(Duration::from_micros)
+                    .or_else(|| signal.payload.get("duration_ms").and_then(|v| v.as_u64()).map(Duration::from_millis));
+                let ended_at = parse_timestamp(signal, &mut self.state.anomalies);
+                let installs = signal.r_type == "install_end" || is_bundle_install(&command, &args);
+                let gem_delta = installs.then(|| std::mem::take(&mut self.gem_delta));
+
+                self.state.executions.push(Execution {
+                    command,
+                    alias,
+                    task,
+                    kind: execution_kind(&signal.r_type),
+                    detached,
+                    args,
+                    cwd,
+                    exit_code,
+                    success,
+                    failure_kind,
+                    signal: term_signal,
+                    duration,
+                    started_at,
+                    ended_at,
+                    start_id,
+                    user,
+                    gem_delta,
+                    context: start_signal.and_then(|s| ExecContext::from_payload(&s.payload)),
+                });
+            }
+            _ => {
+                // Unknown signal types are ignored (forward compatibility)
             }
         }
+        self.state.signal_count += 1;
+    }
 
-        // 未完了の exec_start (SIGKILL 等で exec_end がない) を orphan として記録
-        for (_id, start) in pending_starts {
+    /// 適用を終える。end のない start は未完了の実行として末尾に加える。
+    pub fn finish(self) -> FluxState {
+        // 未完了の exec_start (SIGKILL 等で exec_end がない) を orphan としてログの順に記録
+        let mut pending: Vec<(usize, Signal)> = self.pending_starts.into_values().collect();
+        pending.sort_by_key(|(position, _)| *position);
+        let mut state = self.state;
+        for (_, start) in &pending {
             let cmd = start.payload.get("command")
                 .and_then(|v| v.as_str())
                 .unwrap_or("unknown")
@@ -146,72 +589,516 @@ impl FluxState {
                 .to_string();
             state.executions.push(Execution {
                 command: cmd,
+                alias: start.payload.get("alias").and_then(|v| v.as_str()).map(String::from),
+                task: start.payload.get("task").and_then(|v| v.as_str()).map(String::from),
+                kind: execution_kind(&start.r_type),
+                detached: start.payload.get("detached").and_then(|v| v.as_bool()).unwrap_or(false),
                 args,
                 cwd,
                 exit_code: None,
                 success: false,
-                duration_ms: None,
-                started_at: start.timestamp.clone(),
+                failure_kind: None,
+                signal: None,
+                duration: None,
+                started_at: parse_timestamp(start, &mut state.anomalies),
                 ended_at: None,
                 start_id: start.id.clone(),
+                user: start.user().to_string(),
+                gem_delta: None,
+                context: ExecContext::from_payload(&start.payload),
             });
         }
 
         state
     }
+}
 
-    /// コマンドごとの統計を計算する
-    pub fn command_stats(&self) -> Vec<CommandStats> {
-        let mut stats_map: HashMap<String, Vec<&Execution>> = HashMap::new();
+/// 同じコマンドとしてまとめた実行を集計する。
+fn summarize(command: String, execs: &[&Execution]) -> CommandStats {
+    let total_runs = execs.len();
+    let successes = execs.iter().filter(|e| e.success).count();
+    let failures = total_runs - successes;
 
-        for exec in &self.executions {
-            stats_map.entry(exec.command.clone()).or_default().push(exec);
+    let mut durations: Vec<Duration> = execs.iter()
+        .filter_map(|e| e.duration)
+        .collect();
+    durations.sort_unstable();
+    let avg_duration = if durations.is_empty() {
+        None
+    } else {
+        Some(durations.iter().sum::<Duration>() / durations.len() as u32)
+    };
+    let p95_duration = percentile(&durations, 95);
+
+    let mut tags: Vec<String> = execs.iter().map(|e| e.kind.clone()).collect();
+    if execs.iter().any(|e| e.detached) {
+        tags.push("detached".to_string());
+    }
+    tags.sort();
+    tags.dedup();
+
+    let last_run = execs.iter()
+        .filter_map(|e| e.started_at)
+        .max();
+
+    CommandStats {
+        command,
+        total_runs,
+        successes,
+        failures,
+        avg_duration,
+        p95_duration,
+        last_run,
+        tags,
+    }
+}
+
+/// ソート済みの値の `p` パーセンタイル (nearest-rank 法)。
+fn percentile<T: Copy>(sorted: &[T], p: usize) -> Option<T> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (sorted.len() * p).div_ceil(100).max(1);
+    sorted.get(rank - 1).copied()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn signal(id: &str, r_type: &str, payload: serde_json::Value) -> Signal {
+        Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: format!("2026-01-01T00:00:0{}+09:00", id),
+            meta: None,
+            v: 2,
         }
+    }
 
-        let mut stats: Vec<CommandStats> = stats_map
-            .into_iter()
-            .map(|(command, execs)| {
-                let total_runs = execs.len();
-                let successes = execs.iter().filter(|e| e.success).count();
-                let failures = total_runs - successes;
-
-                let durations: Vec<u64> = execs.iter()
-                    .filter_map(|e| e.duration_ms)
-                    .collect();
-                let avg_duration_ms = if durations.is_empty() {
-                    None
-                } else {
-                    Some(durations.iter().sum::<u64>() / durations.len() as u64)
+    /// 成功した exec の start/end ペアを作る
+    fn exec_pair(n: u32, command: &str, args: &[&str]) -> Vec<Signal> {
+        let start = format!("{}", n * 2);
+        vec![
+            signal(&start, "exec_start", json!({ "command": command, "args": args })),
+            signal(&format!("{}", n * 2 + 1), "exec_end",
+                json!({ "ref_id": start, "exit_code": 0, "success": true, "duration_ms": 5 })),
+        ]
+    }
+
+    /// テスト用の疑似乱数 (xorshift64)。シードごとに同じ列になる
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    /// start/end の対応が崩れたもの・不正な時刻・未知の種類を含むランダムな Signal ログ
+    fn random_log(rng: &mut Rng, len: usize) -> Vec<Signal> {
+        const TYPES: [&str; 11] = [
+            "init", "bootstrap", "add", "remove", "exec_start", "install_start", "run_start",
+            "exec_end", "install_end", "run_end", "x-plugin-event",
+        ];
+        let commands = [("bundle", vec!["install"]), ("bundle", vec!["exec", "rspec"]), ("rake", vec![]), ("ruby", vec!["-v"])];
+        let mut starts: Vec<String> = Vec::new();
+        (0..len)
+            .map(|i| {
+                let r_type = TYPES[rng.below(TYPES.len())];
+                let mut id = format!("s{}", i);
+                let payload = match r_type {
+                    "init" => json!({ "path": format!("/p/{}", i), "version": "0.1.0" }),
+                    "bootstrap" => json!({ "ruby_version": format!("3.{}.0", rng.below(4)), "cache_hit": rng.below(2) == 0 }),
+                    t if t.ends_with("_start") => {
+                        // 同じ ID の start が 2 度現れることもある
+                        if !starts.is_empty() && rng.below(10) == 0 {
+                            id = starts[rng.below(starts.len())].clone();
+                        }
+                        starts.push(id.clone());
+                        let (command, args) = &commands[rng.below(commands.len())];
+                        let alias = (rng.below(2) == 0).then_some("spec");
+                        json!({ "command": command, "args": args, "alias": alias, "tty": { "stdin": rng.below(2) == 0 } })
+                    }
+                    t if t.ends_with("_end") => {
+                        let ref_id = match starts.len() {
+                            0 => "missing".to_string(),
+                            n => starts[rng.below(n)].clone(),
+                        };
+                        json!({ "ref_id": ref_id, "exit_code": rng.below(3), "success": rng.below(2) == 0, "duration_us": rng.below(5_000_000) })
+                    }
+                    _ => json!({}),
                 };
+                let mut signal = signal(&id, r_type, payload);
+                signal.timestamp = match rng.below(20) {
+                    0 => "yesterday".to_string(),
+                    _ => format!("2026-01-01T{:02}:{:02}:00+09:00", rng.below(24), rng.below(60)),
+                };
+                signal
+            })
+            .collect()
+    }
 
-                let last_run = execs.iter()
-                    .max_by_key(|e| &e.started_at)
-                    .map(|e| e.started_at.clone())
-                    .unwrap_or_default();
+    /// 比較のため、State を読み取れる値にまとめる
+    fn fingerprint(state: &FluxState) -> serde_json::Value {
+        // 最後の実行時刻が同じコマンドの並びは決まらないため、名前で並べる
+        let mut stats = state.command_stats(&[]);
+        stats.sort_by(|a, b| a.command.cmp(&b.command));
+        json!({
+            "project_path": state.project_path,
+            "version": state.version,
+            "initialized_at": state.initialized_at,
+            "executions": state.executions,
+            "signal_count": state.signal_count,
+            "anomalies": state.anomalies,
+            "ruby_installs": state.ruby_installs,
+            "stats": stats,
+        })
+    }
 
-                CommandStats {
-                    command,
-                    total_runs,
-                    successes,
-                    failures,
-                    avg_duration_ms,
-                    last_run,
+    #[test]
+    fn test_incremental_matches_batch() {
+        for seed in 1..=200u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let len = rng.below(60);
+            let signals = random_log(&mut rng, len);
+            let batch = FluxState::from_signals(&signals);
+
+            // ランダムな区切りで少しずつ適用する (--watch で追記を読むのと同じ)
+            let mut builder = StateBuilder::new();
+            let mut applied = 0;
+            while applied < signals.len() {
+                let next = (applied + 1 + rng.below(8)).min(signals.len());
+                for signal in &signals[applied..next] {
+                    builder.apply(signal);
                 }
-            })
+                applied = next;
+                // 途中の State は、そこまでのログをまとめて組み立てた結果から未完了の実行を除いたもの
+                let prefix = FluxState::from_signals(&signals[..applied]);
+                let done = &builder.state().executions;
+                assert_eq!(
+                    serde_json::to_value(done).unwrap(),
+                    serde_json::to_value(&prefix.executions[..done.len()]).unwrap(),
+                    "seed {} after {} signals", seed, applied
+                );
+                assert!(prefix.executions[done.len()..].iter().all(|e| e.ended_at.is_none() && e.duration.is_none()));
+            }
+            assert_eq!(fingerprint(&builder.finish()), fingerprint(&batch), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_orphans_keep_log_order() {
+        let signals: Vec<Signal> = (1..=6)
+            .map(|i| signal(&i.to_string(), "exec_start", json!({ "command": format!("c{}", i) })))
             .collect();
+        let state = FluxState::from_signals(&signals);
+        let commands: Vec<&str> = state.executions.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["c1", "c2", "c3", "c4", "c5", "c6"]);
+    }
 
-        // 最新のコマンドが上に来るようにソート
-        stats.sort_by(|a, b| b.last_run.cmp(&a.last_run));
-        stats
+    #[test]
+    fn test_percentile() {
+        let values: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&values, 95), Some(19));
+        assert_eq!(percentile(&[7], 95), Some(7));
+        assert_eq!(percentile::<u64>(&[], 95), None);
     }
 
-    /// 最後に実行されたコマンド
-    pub fn last_execution(&self) -> Option<&Execution> {
-        self.executions.last()
+    #[test]
+    fn test_stats_grouped_by_alias() {
+        let signals = vec![
+            signal("1", "run_start", json!({ "command": "bundle", "args": ["exec", "rspec"], "alias": "spec" })),
+            signal("2", "run_end", json!({ "ref_id": "1", "exit_code": 0, "success": true, "duration_ms": 5 })),
+            signal("3", "run_start", json!({ "command": "bundle", "args": ["exec", "rubocop"] })),
+            signal("4", "run_end", json!({ "ref_id": "3", "exit_code": 0, "success": true, "duration_ms": 5 })),
+        ];
+        let state = FluxState::from_signals(&signals);
+        assert_eq!(state.executions[0].alias.as_deref(), Some("spec"));
+        assert_eq!(state.executions[0].command_line(), "bundle exec rspec");
+
+        let mut stats = state.command_stats(&[]);
+        stats.sort_by(|a, b| a.command.cmp(&b.command));
+        let names: Vec<&str> = stats.iter().map(|s| s.command.as_str()).collect();
+        assert_eq!(names, ["bundle", "spec"]);
+        assert_eq!(stats[1].tags, ["run"]);
+        assert_eq!(stats[1].p95_duration, Some(Duration::from_millis(5)));
     }
 
-    /// 失敗したコマンドの一覧
-    pub fn failed_executions(&self) -> Vec<&Execution> {
-        self.executions.iter().filter(|e| !e.success).collect()
+    #[test]
+    fn test_execution_json_keeps_payload_shape() {
+        let signals = vec![
+            signal("1", "run_start", json!({ "command": "rake", "args": ["test"] })),
+            signal("2", "run_end", json!({ "ref_id": "1", "exit_code": 0, "success": true, "duration_ms": 1, "duration_us": 1_500 })),
+            signal("3", "exec_start", json!({ "command": "sleep", "args": [] })),
+        ];
+        let state = FluxState::from_signals(&signals);
+        assert_eq!(state.executions[0].duration, Some(Duration::from_micros(1_500)));
+
+        let json = serde_json::to_value(&state.executions[0]).unwrap();
+        assert_eq!(json["duration_ms"], 1);
+        // 時刻は UTC の RFC 3339 で書き出す
+        assert_eq!(json["started_at"], "2025-12-31T15:00:01+00:00");
+        assert_eq!(json["ended_at"], "2025-12-31T15:00:02+00:00");
+        assert!(json.get("duration").is_none());
+
+        // 終了していない実行
+        let json = serde_json::to_value(&state.executions[1]).unwrap();
+        assert!(json["duration_ms"].is_null() && json["ended_at"].is_null());
+        assert_eq!(json["started_at"], "2025-12-31T15:00:03+00:00");
+    }
+
+    #[test]
+    fn test_command_stats_json_keeps_shape() {
+        let mut signals = exec_pair(1, "rake", &["test"]);
+        signals.extend(exec_pair(2, "rake", &["test"]));
+        let stats = FluxState::from_signals(&signals).command_stats(&[]);
+        let json = serde_json::to_value(&stats[0]).unwrap();
+        assert_eq!(json["avg_duration_ms"], 5);
+        assert_eq!(json["p95_duration_ms"], 5);
+        assert_eq!(json["last_run"], "2025-12-31T15:00:04+00:00");
+        assert_eq!(json["total_runs"], 2);
+    }
+
+    #[test]
+    fn test_invalid_timestamp_becomes_anomaly() {
+        let mut signals = vec![
+            signal("1", "exec_start", json!({ "command": "rake", "args": [] })),
+            signal("2", "exec_end", json!({ "ref_id": "1", "exit_code": 1, "success": false, "duration_ms": 5 })),
+        ];
+        signals[0].timestamp = "yesterday".to_string();
+        let state = FluxState::from_signals(&signals);
+        assert_eq!(state.anomalies, [Anomaly { signal_id: "1".into(), field: "timestamp", value: "yesterday".into() }]);
+
+        let exec = &state.executions[0];
+        assert_eq!(exec.started_at, None);
+        assert!(exec.ended_at.is_some());
+        assert!(serde_json::to_value(exec).unwrap()["started_at"].is_null());
+
+        // 開始時刻が不明な実行は日別の集計に含めず、統計では「最終実行なし」になる
+        assert!(state.daily_stats(&[]).is_empty());
+        let stats = state.command_stats(&[]);
+        assert_eq!((stats[0].failures, stats[0].last_run), (1, None));
+    }
+
+    /// `day` 日の `hour` 時に始まって失敗した実行 (end の payload に `extra` を加える)
+    fn failure(id: &str, day: u32, hour: u32, command: &str, end: serde_json::Value) -> Vec<Signal> {
+        let at = |minute: u32| format!("2026-03-{:02}T{:02}:{:02}:00+09:00", day, hour, minute);
+        let mut payload = json!({ "ref_id": id, "success": false });
+        payload.as_object_mut().unwrap().extend(end.as_object().unwrap().clone());
+        vec![
+            Signal { timestamp: at(0), ..signal(id, "run_start", json!({ "command": command, "args": ["spec"] })) },
+            Signal { timestamp: at(1), ..signal(&format!("{}-end", id), "run_end", payload) },
+        ]
+    }
+
+    #[test]
+    fn test_failed_summary_groups_by_kind_and_command() {
+        let signals = [
+            failure("a", 1, 9, "rspec", json!({ "exit_code": 1 })),
+            failure("b", 2, 9, "bundle", json!({ "exit_code": 17, "failure_kind": "network_error" })),
+            failure("c", 3, 9, "rspec", json!({ "exit_code": 1 })),
+            failure("d", 4, 9, "rspec", json!({ "exit_code": null, "signal": 9 })),
+            failure("e", 5, 9, "rspec", json!({ "exit_code": 2 })),
+            // 終了の記録がない (exit_end に何も残っていない) 失敗
+            failure("f", 3, 12, "rake", json!({})),
+        ]
+        .concat();
+        let groups = FluxState::from_signals(&signals).failed_summary();
+        let keys: Vec<(&str, &str, usize)> = groups.iter().map(|g| (g.kind.as_str(), g.command.as_str(), g.count)).collect();
+        assert_eq!(
+            keys,
+            [("exit_code", "rspec", 3), ("signal", "rspec", 1), ("unclassified", "rake", 1), ("network_error", "bundle", 1)]
+        );
+
+        let rspec = &groups[0];
+        assert_eq!(rspec.first_at.unwrap().to_rfc3339(), "2026-03-01T00:00:00+00:00");
+        assert_eq!(rspec.last_at.unwrap().to_rfc3339(), "2026-03-05T00:00:00+00:00");
+        assert_eq!((rspec.last_exit_code, rspec.last_signal), (Some(2), None));
+        assert_eq!(rspec.last_command_line, "rspec spec");
+        assert_eq!(groups[1].last_signal, Some(9));
+
+        let json = serde_json::to_value(&groups[3]).unwrap();
+        assert_eq!(json["kind"], "network_error");
+        assert_eq!(json["last_at"], "2026-03-02T00:00:00+00:00");
+    }
+
+    #[test]
+    fn test_failed_summary_orphans_are_unclassified() {
+        let signals = vec![signal("1", "exec_start", json!({ "command": "sleep", "args": ["10"] }))];
+        let groups = FluxState::from_signals(&signals).failed_summary();
+        assert_eq!(groups.len(), 1);
+        assert_eq!((groups[0].kind.as_str(), groups[0].count), (UNCLASSIFIED, 1));
+        assert!(FluxState::from_signals(&exec_pair(1, "ls", &[])).failed_summary().is_empty());
+    }
+
+    #[test]
+    fn test_matches_ignore_exact() {
+        assert!(matches_ignore("ls", "ls", "ls -la"));
+        assert!(matches_ignore("git status", "git", "git status"));
+        assert!(!matches_ignore("git status", "git", "git status -s"));
+        assert!(!matches_ignore("git", "gitk", "gitk"));
+    }
+
+    #[test]
+    fn test_matches_ignore_glob() {
+        assert!(matches_ignore("echo *", "echo", "echo hello world"));
+        assert!(!matches_ignore("echo *", "echo", "echo"));
+        assert!(matches_ignore("git st?tus*", "git", "git status -s"));
+        assert!(matches_ignore("*", "rake", "rake test"));
+        assert!(!matches_ignore("rake t?", "rake", "rake test"));
+    }
+
+    #[test]
+    fn test_command_stats_ignore() {
+        let mut signals = exec_pair(0, "ls", &["-la"]);
+        signals.extend(exec_pair(1, "git", &["status"]));
+        signals.extend(exec_pair(2, "rake", &["test"]));
+        let state = FluxState::from_signals(&signals);
+
+        let ignore = vec!["ls".to_string(), "git status".to_string()];
+        let stats = state.command_stats(&ignore);
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].command, "rake");
+        assert_eq!(state.ignored_count(&ignore), 2);
+
+        // 除外されても実行履歴自体は残る
+        assert_eq!(state.executions.len(), 3);
+        assert_eq!(state.command_stats(&[]).len(), 3);
+        assert_eq!(state.ignored_count(&[]), 0);
+    }
+
+    #[test]
+    fn test_activity_across_month_boundary() {
+        let at = |id: &str, timestamp: &str, r_type: &str, payload: serde_json::Value| Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: timestamp.to_string(),
+            meta: None,
+            v: 2,
+        };
+        let exec = |n: u32, started: &str, success: bool| {
+            let start = format!("s{}", n);
+            vec![
+                at(&start, started, "exec_start", json!({ "command": "rake" })),
+                at(&format!("e{}", n), started, "exec_end", json!({ "ref_id": start, "exit_code": if success { 0 } else { 1 }, "success": success })),
+            ]
+        };
+        let signals: Vec<Signal> = [
+            // UTC では 2 月 28 日だが、+09:00 では 3 月 1 日
+            exec(1, "2026-02-28T20:00:00Z", true),
+            exec(2, "2026-02-28T23:59:59+09:00", false),
+            exec(3, "2026-03-01T00:00:00+09:00", true),
+            exec(4, "2026-03-02T08:00:00+09:00", false),
+            // 範囲外
+            exec(5, "2026-02-26T12:00:00+09:00", true),
+            exec(6, "2026-03-03T00:00:00+09:00", true),
+        ]
+        .concat();
+        let state = FluxState::from_signals(&signals);
+        let tz = chrono::FixedOffset::east_opt(9 * 3600).unwrap();
+        let today = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+
+        let activity = state.activity_in(4, today, &tz);
+        let summary: Vec<(String, usize, usize)> =
+            activity.iter().map(|d| (d.date.to_string(), d.executions, d.failures)).collect();
+        assert_eq!(
+            summary,
+            [
+                ("2026-02-27".to_string(), 0, 0),
+                ("2026-02-28".to_string(), 1, 1),
+                ("2026-03-01".to_string(), 2, 0),
+                ("2026-03-02".to_string(), 1, 1),
+            ]
+        );
+        assert_eq!(serde_json::to_value(&activity[1]).unwrap(), json!({ "date": "2026-02-28", "executions": 1, "failures": 1 }));
+    }
+
+    #[test]
+    fn test_user_filter_and_grouping() {
+        let by = |user: Option<&str>, id: &str, r_type: &str, payload: serde_json::Value| Signal {
+            meta: user.map(|u| crate::signals::SignalMeta { user: Some(u.to_string()), host: None }),
+            ..signal(id, r_type, payload)
+        };
+        let end = |ref_id: &str, success: bool| json!({ "ref_id": ref_id, "exit_code": if success { 0 } else { 1 }, "success": success });
+        let signals = vec![
+            by(Some("alice"), "1", "exec_start", json!({ "command": "rake", "args": [] })),
+            by(Some("alice"), "2", "exec_end", end("1", true)),
+            by(Some("bob"), "3", "exec_start", json!({ "command": "rspec", "args": [] })),
+            by(Some("bob"), "4", "exec_end", end("3", false)),
+            by(Some("alice"), "5", "exec_start", json!({ "command": "rspec", "args": [] })),
+            by(Some("alice"), "6", "exec_end", end("5", true)),
+            // 記録者の分からない古い Signal
+            by(None, "7", "exec_start", json!({ "command": "rake", "args": [] })),
+            by(None, "8", "exec_end", end("7", true)),
+        ];
+        let state = FluxState::from_signals(&signals);
+
+        let stats = state.user_stats(&[]);
+        let mut grouped: Vec<(&str, usize, usize)> =
+            stats.iter().map(|s| (s.command.as_str(), s.total_runs, s.failures)).collect();
+        grouped.sort();
+        assert_eq!(grouped, [("alice", 2, 0), ("bob", 1, 1), ("unknown", 1, 0)]);
+
+        let mut alice = FluxState::from_signals(&signals);
+        alice.retain_user("alice");
+        assert_eq!(alice.executions.len(), 2);
+        assert!(alice.failed_executions().is_empty());
+        let mut bob = FluxState::from_signals(&signals);
+        bob.retain_user("bob");
+        assert_eq!(bob.command_stats(&[]).len(), 1);
+        assert_eq!(bob.failed_executions().len(), 1);
+        let mut unknown = FluxState::from_signals(&signals);
+        unknown.retain_user(crate::signals::UNKNOWN_USER);
+        assert_eq!(unknown.executions[0].command, "rake");
+    }
+
+    #[test]
+    fn test_context_grouping() {
+        let ci = json!({ "tty": { "stdin": false, "stdout": false, "stderr": false }, "ci": { "provider": "github-actions", "run_id": "12" } });
+        let local = json!({ "tty": { "stdin": true, "stdout": true, "stderr": true }, "term": "xterm-256color", "shlvl": 1 });
+        let end = |ref_id: &str, success: bool| json!({ "ref_id": ref_id, "exit_code": if success { 0 } else { 1 }, "success": success });
+        let state = FluxState::from_signals(&[
+            signal("1", "exec_start", json!({ "command": "rspec", "args": [], "context": local })),
+            signal("2", "exec_end", end("1", true)),
+            signal("3", "exec_start", json!({ "command": "rspec", "args": [], "context": ci })),
+            signal("4", "exec_end", end("3", false)),
+            // context の記録がない古い Signal
+            signal("5", "exec_start", json!({ "command": "rspec", "args": [] })),
+            signal("6", "exec_end", end("5", true)),
+        ]);
+        let stats = state.context_stats(&[]);
+        let mut grouped: Vec<(&str, usize, usize)> =
+            stats.iter().map(|s| (s.command.as_str(), s.total_runs, s.failures)).collect();
+        grouped.sort();
+        assert_eq!(grouped, [("github-actions", 1, 1), ("local", 1, 0), ("unknown", 1, 0)]);
+        assert_eq!(state.executions[1].context.as_ref().and_then(|c| c.badge()).as_deref(), Some("[CI github-actions #12]"));
+    }
+
+    #[test]
+    fn test_ruby_history() {
+        let signals = vec![
+            signal("1", "bootstrap", json!({ "ruby_version": "3.3.0", "adopted": true })),
+            signal("2", "bootstrap", json!({ "ruby_version": "3.4.0-rc1", "cache_hit": false })),
+            signal("3", "add", json!({ "gem": "rake" })),
+            signal("4", "bootstrap", json!({ "cache_hit": true })),
+            signal("5", "bootstrap", json!({ "ruby_version": "3.4.1", "cache_hit": true, "switched_from": "3.4.0-rc1" })),
+        ];
+        let state = FluxState::from_signals(&signals);
+        let history: Vec<(&str, Option<bool>, &str)> =
+            state.ruby_history().iter().map(|r| (r.version.as_str(), r.cache_hit, r.signal_id.as_str())).collect();
+        assert_eq!(history, [("3.3.0", None, "1"), ("3.4.0-rc1", Some(false), "2"), ("3.4.1", Some(true), "5")]);
+        assert_eq!(state.ruby_history()[2].timestamp, "2026-01-01T00:00:05+09:00");
+        assert!(FluxState::from_signals(&[]).ruby_history().is_empty());
     }
 }
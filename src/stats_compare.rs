@@ -0,0 +1,334 @@
+//! `arc stats --compare` — 2 つの期間のコマンド統計を並べて比べる (依存を更新した前後など)。
+//!
+//! 期間の指定は 2 種類:
+//!
+//! - 日付の範囲 `2026-01-01..2026-01-31` (両端を含む。`2026-02-01..` / `..2026-01-31` のように片側を省略できる)。
+//!   開始時刻の日付 (記録したときのオフセット) で判定する。`2026-01-15` だけならその日
+//! - Signal ID の範囲 `<id>..<id>` (ログ上の位置。両端の Signal 自身は含まない)。ID は一意に決まる先頭部分でもよい
+//!
+//! 比較そのもの (`compare`) は 2 つの `CommandStats` の集合に対する純粋な関数。
+
+use anyhow::{Result, bail};
+use chrono::{Local, NaiveDate};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::signals::Signal;
+use crate::state::{CommandStats, Execution};
+
+/// 悪化とみなす変化の既定値 (平均・p95 は %、失敗率はポイント)
+pub const DEFAULT_THRESHOLD: f64 = 10.0;
+
+// ─────────────────────────────────────────────
+// 期間
+// ─────────────────────────────────────────────
+
+/// 集計する期間
+#[derive(Debug, Clone, PartialEq)]
+pub enum Window {
+    /// 開始日の範囲 (両端を含む)
+    Dates { from: Option<NaiveDate>, to: Option<NaiveDate> },
+    /// ログ上の位置の範囲 (両端を含まない)
+    Signals { after: Option<usize>, before: Option<usize> },
+}
+
+/// 範囲の端
+enum Bound {
+    Date(NaiveDate),
+    Position(usize),
+}
+
+impl Window {
+    /// `A..B` / `A..` / `..B` / `YYYY-MM-DD` を解釈する。Signal ID は `signals` の中から探す。
+    pub fn parse(range: &str, signals: &[Signal]) -> Result<Self> {
+        let Some((from, to)) = range.split_once("..") else {
+            let Some(date) = parse_date(range) else {
+                bail!("期間 '{}' は A..B の形 (日付か Signal ID) で指定してください", range);
+            };
+            return Ok(Window::Dates { from: Some(date), to: Some(date) });
+        };
+        let bound = |s: &str| -> Result<Option<Bound>> {
+            if s.is_empty() {
+                return Ok(None);
+            }
+            match parse_date(s) {
+                Some(date) => Ok(Some(Bound::Date(date))),
+                None => Ok(Some(Bound::Position(position_of(signals, s)?))),
+            }
+        };
+        use Bound::{Date, Position};
+        Ok(match (bound(from)?, bound(to)?) {
+            (None, None) => bail!("期間 '{}' の両端が空です", range),
+            (Some(Date(from)), Some(Date(to))) => Window::Dates { from: Some(from), to: Some(to) },
+            (Some(Date(from)), None) => Window::Dates { from: Some(from), to: None },
+            (None, Some(Date(to))) => Window::Dates { from: None, to: Some(to) },
+            (Some(Position(after)), Some(Position(before))) => Window::Signals { after: Some(after), before: Some(before) },
+            (Some(Position(after)), None) => Window::Signals { after: Some(after), before: None },
+            (None, Some(Position(before))) => Window::Signals { after: None, before: Some(before) },
+            _ => bail!("期間 '{}' で日付と Signal ID を混ぜることはできません", range),
+        })
+    }
+
+    /// `--compare-signals A[..B]`: A より前と、B (省略時は A) より後の 2 つの期間
+    pub fn around(range: &str, signals: &[Signal]) -> Result<(Self, Self)> {
+        let (first, last) = range.split_once("..").unwrap_or((range, range));
+        let (first, last) = (position_of(signals, first)?, position_of(signals, last)?);
+        if last < first {
+            bail!("--compare-signals の範囲が逆順です: {}", range);
+        }
+        Ok((Window::Signals { after: None, before: Some(first) }, Window::Signals { after: Some(last), before: None }))
+    }
+
+    /// `execution` がこの期間に含まれるか。`positions` は Signal ID → ログ上の位置
+    pub fn contains(&self, execution: &Execution, positions: &HashMap<&str, usize>) -> bool {
+        match self {
+            Window::Dates { from, to } => {
+                let Some(date) = execution.started_at.map(|t| t.with_timezone(&Local).date_naive()) else { return false };
+                from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+            }
+            Window::Signals { after, before } => {
+                let Some(&position) = positions.get(execution.start_id.as_str()) else { return false };
+                after.is_none_or(|after| position > after) && before.is_none_or(|before| position < before)
+            }
+        }
+    }
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+}
+
+/// Signal ID (または一意に決まる先頭部分) のログ上の位置
+pub fn position_of(signals: &[Signal], id: &str) -> Result<usize> {
+    if let Some(position) = signals.iter().position(|s| s.id == id) {
+        return Ok(position);
+    }
+    let matches: Vec<usize> = signals.iter().enumerate().filter(|(_, s)| s.id.starts_with(id)).map(|(i, _)| i).collect();
+    match matches.as_slice() {
+        [position] => Ok(*position),
+        [] => bail!("Signal '{}' がログにありません", id),
+        _ => bail!("Signal ID '{}' に一致する Signal が {} 件あります。もっと長く指定してください", id, matches.len()),
+    }
+}
+
+/// Signal ID → ログ上の位置
+pub fn positions(signals: &[Signal]) -> HashMap<&str, usize> {
+    signals.iter().enumerate().map(|(i, s)| (s.id.as_str(), i)).collect()
+}
+
+// ─────────────────────────────────────────────
+// 比較
+// ─────────────────────────────────────────────
+
+/// 1 つの期間でのコマンドの統計
+#[derive(Debug, Clone, PartialEq)]
+pub struct Side {
+    pub runs: usize,
+    /// 失敗率 (%)。実行がなければ `None`
+    pub failure_rate: Option<f64>,
+    pub mean: Option<Duration>,
+    pub p95: Option<Duration>,
+}
+
+impl Side {
+    fn of(stats: &CommandStats) -> Self {
+        Side {
+            runs: stats.total_runs,
+            failure_rate: failure_rate(stats.failures, stats.total_runs),
+            mean: stats.avg_duration,
+            p95: stats.p95_duration,
+        }
+    }
+}
+
+/// 両方の期間にあるコマンドの比較
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub command: String,
+    pub before: Side,
+    pub after: Side,
+    /// 平均・p95 の増加 (%) か失敗率の増加 (ポイント) がしきい値を超えた
+    pub regression: bool,
+}
+
+impl Row {
+    pub fn mean_change(&self) -> Option<f64> {
+        pct_change(self.before.mean?, self.after.mean?)
+    }
+
+    pub fn p95_change(&self) -> Option<f64> {
+        pct_change(self.before.p95?, self.after.p95?)
+    }
+
+    /// 失敗率の差 (ポイント)
+    pub fn failure_rate_change(&self) -> Option<f64> {
+        Some(self.after.failure_rate? - self.before.failure_rate?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Comparison {
+    /// コマンド名の順
+    pub rows: Vec<Row>,
+    /// 比較元の期間にだけあるコマンド
+    pub only_before: Vec<String>,
+    /// 比較先の期間にだけあるコマンド
+    pub only_after: Vec<String>,
+}
+
+/// 失敗率 (%)。0 回なら `None`
+pub fn failure_rate(failures: usize, runs: usize) -> Option<f64> {
+    (runs > 0).then(|| failures as f64 * 100.0 / runs as f64)
+}
+
+/// `before` から `after` への変化率 (%)。`before` が 0 なら `None`
+pub fn pct_change(before: Duration, after: Duration) -> Option<f64> {
+    if before.is_zero() {
+        return None;
+    }
+    let (before, after) = (before.as_micros() as f64, after.as_micros() as f64);
+    Some((after - before) * 100.0 / before)
+}
+
+/// 2 つの期間の統計を比べる。
+pub fn compare(before: &[CommandStats], after: &[CommandStats], threshold: f64) -> Comparison {
+    let before: BTreeMap<&str, &CommandStats> = before.iter().map(|s| (s.command.as_str(), s)).collect();
+    let after: BTreeMap<&str, &CommandStats> = after.iter().map(|s| (s.command.as_str(), s)).collect();
+
+    let mut comparison = Comparison::default();
+    for (command, b) in &before {
+        let Some(a) = after.get(command) else {
+            comparison.only_before.push(command.to_string());
+            continue;
+        };
+        let mut row = Row { command: command.to_string(), before: Side::of(b), after: Side::of(a), regression: false };
+        row.regression = [row.mean_change(), row.p95_change(), row.failure_rate_change()]
+            .into_iter()
+            .flatten()
+            .any(|change| change > threshold);
+        comparison.rows.push(row);
+    }
+    comparison.only_after = after.keys().filter(|c| !before.contains_key(*c)).map(|c| c.to_string()).collect();
+    comparison
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FluxState;
+    use serde_json::json;
+
+    fn stats(command: &str, runs: usize, failures: usize, mean_ms: Option<u64>, p95_ms: Option<u64>) -> CommandStats {
+        CommandStats {
+            command: command.to_string(),
+            total_runs: runs,
+            successes: runs - failures,
+            failures,
+            avg_duration: mean_ms.map(Duration::from_millis),
+            p95_duration: p95_ms.map(Duration::from_millis),
+            last_run: None,
+            tags: vec![],
+        }
+    }
+
+    fn signal(id: &str, r_type: &str, timestamp: &str, payload: serde_json::Value) -> Signal {
+        Signal { id: id.to_string(), r_type: r_type.to_string(), payload, timestamp: timestamp.to_string(), meta: None, v: 2 }
+    }
+
+    /// `id` の start と end の組 (`duration_ms` の実行)
+    fn run(id: &str, command: &str, day: &str, duration_ms: u64, exit_code: i64) -> [Signal; 2] {
+        let ts = format!("{}T10:00:00+00:00", day);
+        [
+            signal(id, "exec_start", &ts, json!({ "command": command, "args": [] })),
+            signal(&format!("{}-end", id), "exec_end", &ts, json!({ "ref_id": id, "exit_code": exit_code, "duration_ms": duration_ms })),
+        ]
+    }
+
+    #[test]
+    fn test_compare_flags_regressions_and_missing_commands() {
+        let before = [stats("rspec", 10, 0, Some(1000), Some(2000)), stats("rubocop", 4, 0, Some(500), Some(600)), stats("old", 1, 0, None, None)];
+        let after = [stats("rspec", 8, 2, Some(1300), Some(2100)), stats("rubocop", 4, 0, Some(520), Some(600)), stats("new", 2, 1, Some(10), Some(10))];
+        let comparison = compare(&before, &after, DEFAULT_THRESHOLD);
+
+        assert_eq!(comparison.only_before, ["old"]);
+        assert_eq!(comparison.only_after, ["new"]);
+        let names: Vec<&str> = comparison.rows.iter().map(|r| r.command.as_str()).collect();
+        assert_eq!(names, ["rspec", "rubocop"]);
+
+        let rspec = &comparison.rows[0];
+        assert!(rspec.regression);
+        assert_eq!(rspec.mean_change(), Some(30.0));
+        assert_eq!(rspec.p95_change(), Some(5.0));
+        assert_eq!(rspec.failure_rate_change(), Some(25.0));
+        // 4% の増加はしきい値未満
+        assert!(!comparison.rows[1].regression);
+        assert!(compare(&before, &after, 3.0).rows[1].regression);
+    }
+
+    #[test]
+    fn test_zero_runs_and_zero_durations_do_not_divide() {
+        assert_eq!(failure_rate(0, 0), None);
+        assert_eq!(failure_rate(1, 4), Some(25.0));
+        assert_eq!(pct_change(Duration::ZERO, Duration::from_millis(5)), None);
+        assert_eq!(pct_change(Duration::from_millis(200), Duration::from_millis(100)), Some(-50.0));
+
+        let comparison = compare(&[stats("a", 0, 0, Some(0), None)], &[stats("a", 3, 3, Some(10), Some(10))], DEFAULT_THRESHOLD);
+        let row = &comparison.rows[0];
+        assert_eq!((row.mean_change(), row.p95_change(), row.failure_rate_change()), (None, None, None));
+        assert!(!row.regression);
+    }
+
+    #[test]
+    fn test_date_windows() {
+        let signals: Vec<Signal> = [run("a", "rspec", "2026-01-10", 100, 0), run("b", "rspec", "2026-02-03", 300, 1)].concat();
+        let state = FluxState::from_signals(&signals);
+        let positions = positions(&signals);
+        let runs = |range: &str| {
+            let window = Window::parse(range, &signals).unwrap();
+            state.command_stats_where(&[], |e| window.contains(e, &positions)).iter().map(|s| s.total_runs).sum::<usize>()
+        };
+
+        assert_eq!(runs("2026-01-01..2026-01-31"), 1);
+        assert_eq!(runs("2026-02-01.."), 1);
+        assert_eq!(runs("..2026-02-03"), 2);
+        assert_eq!(runs("2026-01-10"), 1);
+        assert_eq!(runs("2026-03-01.."), 0);
+        assert!(Window::parse("..", &signals).is_err());
+        assert!(Window::parse("last week", &signals).is_err());
+    }
+
+    #[test]
+    fn test_signal_windows() {
+        let bootstrap = signal("01bootstrap", "bootstrap", "2026-01-20T00:00:00+00:00", json!({}));
+        let signals: Vec<Signal> = [
+            run("01aa", "rspec", "2026-01-10", 100, 0).to_vec(),
+            run("01ab", "rspec", "2026-01-11", 100, 0).to_vec(),
+            vec![bootstrap],
+            run("01ca", "rspec", "2026-01-21", 200, 0).to_vec(),
+        ]
+        .concat();
+        let state = FluxState::from_signals(&signals);
+        let positions = positions(&signals);
+        let stats = |window: &Window| state.command_stats_where(&[], |e| window.contains(e, &positions));
+
+        let (before, after) = Window::around("01bootstrap", &signals).unwrap();
+        let comparison = compare(&stats(&before), &stats(&after), DEFAULT_THRESHOLD);
+        assert_eq!((comparison.rows[0].before.runs, comparison.rows[0].after.runs), (2, 1));
+        assert_eq!(comparison.rows[0].mean_change(), Some(100.0));
+        assert!(comparison.rows[0].regression);
+
+        // 両端は含まない。先頭部分でも指定できる
+        let window = Window::parse("01aa..01bo", &signals).unwrap();
+        assert_eq!(window, Window::Signals { after: Some(0), before: Some(4) });
+        assert_eq!(stats(&window)[0].total_runs, 1);
+
+        assert!(Window::parse("01a..", &signals).unwrap_err().to_string().contains("4 件"));
+        assert!(Window::parse("zz..", &signals).unwrap_err().to_string().contains("ありません"));
+        assert!(Window::parse("2026-01-01..01ca", &signals).unwrap_err().to_string().contains("混ぜる"));
+        assert!(Window::around("01ca..01aa", &signals).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! `arc stats --export` で書き出す日別・コマンド別の統計。
+//!
+//! 表計算ソフトにそのまま取り込めるよう、列の順序は固定し、日付は ISO 8601 (`YYYY-MM-DD`) で出力する。
+//! 列を追加する場合は末尾に足すこと (既存のシートの数式が列位置に依存するため)。
+
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::state::FluxState;
+
+/// `--all-commands` を指定しない場合に書き出すコマンド数 (実行回数の多い順)
+pub const DEFAULT_TOP: usize = 10;
+
+/// CSV の列 (この順で出力する)
+const COLUMNS: [&str; 7] = [
+    "date", "command", "runs", "failures", "avg_duration_ms", "p95_duration_ms", "tags",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// 1 日 × 1 コマンド分の統計。
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportRow {
+    pub date: String,
+    pub command: String,
+    pub runs: usize,
+    pub failures: usize,
+    pub avg_duration_ms: Option<u64>,
+    pub p95_duration_ms: Option<u64>,
+    pub tags: Vec<String>,
+}
+
+/// 書き出す行を組み立てる。`top` を指定した場合は、ログ全体での実行回数が多い
+/// 上位 `top` コマンドだけを残す (同数ならコマンド名順)。
+pub fn rows(state: &FluxState, ignore: &[String], top: Option<usize>) -> Vec<ExportRow> {
+    let daily = state.daily_stats(ignore);
+
+    let keep: Option<Vec<String>> = top.map(|n| {
+        let mut totals: HashMap<&str, usize> = HashMap::new();
+        for (_, stat) in &daily {
+            *totals.entry(stat.command.as_str()).or_default() += stat.total_runs;
+        }
+        let mut ranked: Vec<(&str, usize)> = totals.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked.into_iter().take(n).map(|(name, _)| name.to_string()).collect()
+    });
+
+    daily
+        .into_iter()
+        .filter(|(_, stat)| keep.as_ref().is_none_or(|k| k.contains(&stat.command)))
+        .map(|(date, stat)| ExportRow {
+            date,
+            command: stat.command,
+            runs: stat.total_runs,
+            failures: stat.failures,
+            avg_duration_ms: stat.avg_duration.map(to_ms),
+            p95_duration_ms: stat.p95_duration.map(to_ms),
+            tags: stat.tags,
+        })
+        .collect()
+}
+
+/// ミリ秒に四捨五入する。
+fn to_ms(duration: Duration) -> u64 {
+    (duration.as_micros() as u64 + 500) / 1_000
+}
+
+// ─────────────────────────────────────────────
+// 出力形式
+// ─────────────────────────────────────────────
+
+/// RFC 4180 形式の CSV。改行は `\n`、タグは `;` 区切りで 1 列にまとめる。
+pub fn to_csv(rows: &[ExportRow]) -> String {
+    let mut out = COLUMNS.join(",");
+    out.push('\n');
+    for row in rows {
+        let fields = [
+            row.date.clone(),
+            row.command.clone(),
+            row.runs.to_string(),
+            row.failures.to_string(),
+            row.avg_duration_ms.map(|v| v.to_string()).unwrap_or_default(),
+            row.p95_duration_ms.map(|v| v.to_string()).unwrap_or_default(),
+            row.tags.join(";"),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// カンマ・ダブルクォート・改行を含むフィールドはダブルクォートで囲み、`"` は `""` にする。
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// CSV と同じ列名を持つオブジェクトの配列。値がない列は `null`。
+pub fn to_json(rows: &[ExportRow]) -> Value {
+    rows.iter()
+        .map(|row| {
+            json!({
+                "date": row.date,
+                "command": row.command,
+                "runs": row.runs,
+                "failures": row.failures,
+                "avg_duration_ms": row.avg_duration_ms,
+                "p95_duration_ms": row.p95_duration_ms,
+                "tags": row.tags,
+            })
+        })
+        .collect()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::Signal;
+
+    /// (開始時刻, start の種別, コマンド, エイリアス, 成功, 所要時間 µs)
+    type Run = (&'static str, &'static str, &'static str, Option<&'static str>, bool, u64);
+
+    /// 3 週間にまたがる合成ログ。カンマやダブルクォートを含むコマンド、失敗、
+    /// エイリアス経由の実行、終了していない実行を含む。日付はローカル時刻で分けるので、時刻は UTC で書く。
+    fn multi_week_log() -> Vec<Signal> {
+        let runs: [Run; 9] = [
+            ("2026-03-02T09:00:00Z", "exec_start", "rake", None, true, 1_200_400),
+            ("2026-03-02T10:00:00Z", "exec_start", "rake", None, false, 800_000),
+            ("2026-03-03T09:00:00Z", "run_start", "bundle", Some("spec"), true, 5_000_000),
+            ("2026-03-09T09:00:00Z", "exec_start", "echo a,b", None, true, 1_000),
+            ("2026-03-09T11:00:00Z", "run_start", "bundle", Some("spec"), false, 7_000_000),
+            ("2026-03-10T09:00:00Z", "exec_start", "say \"hi\"", None, true, 2_499),
+            ("2026-03-16T09:00:00Z", "exec_start", "rake", None, true, 1_000_000),
+            ("2026-03-16T09:30:00Z", "run_start", "bundle", Some("spec"), true, 6_000_000),
+            ("2026-03-17T09:00:00Z", "exec_start", "ls", None, true, 3_000),
+        ];
+
+        let mut signals = Vec::new();
+        for (i, (ts, start_type, command, alias, success, duration_us)) in runs.into_iter().enumerate() {
+            let start_id = format!("s{}", i);
+            let mut payload = json!({ "command": command, "args": [] });
+            if let Some(alias) = alias {
+                payload["alias"] = json!(alias);
+            }
+            signals.push(Signal {
+                id: start_id.clone(),
+                r_type: start_type.to_string(),
+                payload,
+                timestamp: ts.to_string(),
+                meta: None,
+                v: 2,
+            });
+            signals.push(Signal {
+                id: format!("e{}", i),
+                r_type: start_type.replace("_start", "_end"),
+                payload: json!({
+                    "ref_id": start_id,
+                    "exit_code": if success { 0 } else { 1 },
+                    "success": success,
+                    "duration_us": duration_us,
+                }),
+                timestamp: ts.to_string(),
+                meta: None,
+                v: 2,
+            });
+        }
+        // 終了していない実行 (失敗として数える)
+        signals.push(Signal {
+            id: "orphan".to_string(),
+            r_type: "exec_start".to_string(),
+            payload: json!({ "command": "rake", "args": [] }),
+            timestamp: "2026-03-16T12:00:00Z".to_string(),
+            meta: None,
+            v: 2,
+        });
+        signals
+    }
+
+    #[test]
+    fn test_csv_golden() {
+        let state = FluxState::from_signals(&multi_week_log());
+        let csv = to_csv(&rows(&state, &[], None));
+        assert_eq!(csv, include_str!("testdata/stats_export.csv"));
+    }
+
+    #[test]
+    fn test_csv_golden_top_n() {
+        let state = FluxState::from_signals(&multi_week_log());
+        // rake (4 回) と spec (3 回) だけが残る
+        let csv = to_csv(&rows(&state, &[], Some(2)));
+        assert_eq!(csv, include_str!("testdata/stats_export_top2.csv"));
+    }
+
+    #[test]
+    fn test_json_rows() {
+        let state = FluxState::from_signals(&multi_week_log());
+        let value = to_json(&rows(&state, &["ls".to_string()], None));
+        let rows = value.as_array().unwrap();
+        assert_eq!(rows.len(), 7);
+        assert_eq!(rows[0]["date"], "2026-03-02");
+        assert_eq!(rows[0]["avg_duration_ms"], 1000);
+        assert_eq!(rows[0]["tags"], json!(["exec"]));
+        assert!(rows.iter().all(|r| r["command"] != "ls"));
+    }
+
+    #[test]
+    fn test_csv_field_quoting() {
+        assert_eq!(csv_field("rake"), "rake");
+        assert_eq!(csv_field("echo a,b"), "\"echo a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+    }
+}
@@ -0,0 +1,81 @@
+//! 打ち間違えた名前 (Gem 名・Signal 種別) に近い候補を探す ("did you mean ...?")。
+//!
+//! 距離は大文字・小文字を区別しない Levenshtein 距離。名前の長さの 1/3 (最低 1) を超えて離れた候補は提案しない。
+
+/// `a` と `b` の編集距離。`max` を超えると分かった時点で打ち切って `None` を返す。
+pub fn distance_within(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        // 行の最小値は以降の行で小さくならない
+        if row.iter().min().is_some_and(|&m| m > max) {
+            return None;
+        }
+        prev = row;
+    }
+    Some(prev[b.len()]).filter(|&d| d <= max)
+}
+
+/// `input` に対して提案する距離の上限
+fn cutoff(input: &str) -> usize {
+    (input.chars().count() / 3).max(1)
+}
+
+/// 候補のうち `input` に最も近いもの。同じ距離なら先に現れた候補。
+pub fn closest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let input = input.to_lowercase();
+    let max = cutoff(&input);
+    candidates
+        .into_iter()
+        .filter_map(|c| distance_within(&input, &c.to_lowercase(), max).map(|d| (d, c)))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// エラーメッセージに付ける ` (did you mean 'x'?)`。近い候補がなければ空文字列。
+pub fn hint<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    closest(input, candidates).map_or(String::new(), |c| format!(" (did you mean '{}'?)", c))
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_within() {
+        assert_eq!(distance_within("nokigiri", "nokogiri", 2), Some(1));
+        assert_eq!(distance_within("remve", "remove", 1), Some(1));
+        assert_eq!(distance_within("kitten", "sitting", 3), Some(3));
+        assert_eq!(distance_within("kitten", "sitting", 2), None);
+        assert_eq!(distance_within("", "abc", 3), Some(3));
+        assert_eq!(distance_within("rack", "rack", 0), Some(0));
+        // 長さの差だけで上限を超える
+        assert_eq!(distance_within("a", "activesupport", 3), None);
+    }
+
+    #[test]
+    fn test_closest_respects_cutoff() {
+        let gems = ["rails", "rake", "nokogiri", "puma"];
+        assert_eq!(closest("nokigiri", gems), Some("nokogiri"));
+        assert_eq!(closest("Rails", gems), Some("rails"));
+        // 同じ距離なら先の候補
+        assert_eq!(closest("rak", ["rack", "rake"]), Some("rack"));
+        assert_eq!(closest("sidekiq", gems), None);
+        assert_eq!(closest("pg", gems), None);
+        assert_eq!(hint("pumma", gems), " (did you mean 'puma'?)");
+        assert_eq!(hint("sidekiq", gems), "");
+    }
+}
@@ -0,0 +1,126 @@
+//! `arc sync` の高速な up-to-date 判定。
+//!
+//! 成功した install の後に Gemfile / Gemfile.lock / Ruby バージョンのダイジェストを
+//! `.arc/env/.sync-state` に保存し、次回の `arc sync` で比較する。
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::blobs::sha256_hex;
+
+/// ダイジェストの保存先ファイル名 (`.arc/env/` 内)
+const SYNC_STATE_FILE: &str = ".sync-state";
+
+// ─────────────────────────────────────────────
+// ダイジェスト
+// ─────────────────────────────────────────────
+
+/// Gemfile / Gemfile.lock の内容と Ruby バージョンからダイジェスト (SHA-256) を計算する。
+/// 存在しないファイルは空として扱う。
+pub fn compute_digest(cwd: &Path, ruby_version: &str) -> Result<String> {
+    let mut contents = Vec::new();
+    for name in ["Gemfile", "Gemfile.lock"] {
+        let path = cwd.join(name);
+        contents.push(if path.exists() {
+            fs::read(&path).with_context(|| format!("{:?} の読み込みに失敗しました", path))?
+        } else {
+            vec![]
+        });
+    }
+    Ok(digest_of(&contents[0], &contents[1], ruby_version))
+}
+
+/// `compute_digest` と同じダイジェストを内容から計算する (スナップショットの照合用)。
+pub fn digest_of(gemfile: &[u8], lockfile: &[u8], ruby_version: &str) -> String {
+    let mut input: Vec<u8> = Vec::new();
+    for (name, content) in [("Gemfile", gemfile), ("Gemfile.lock", lockfile)] {
+        // 境界を明示して、ファイル間で内容が移動しただけの場合と区別する
+        input.extend_from_slice(format!("{}:{}\n", name, content.len()).as_bytes());
+        input.extend_from_slice(content);
+    }
+    input.extend_from_slice(format!("ruby:{}\n", ruby_version).as_bytes());
+
+    sha256_hex(&input)
+}
+
+// ─────────────────────────────────────────────
+// 保存・比較
+// ─────────────────────────────────────────────
+
+/// 保存されているダイジェストを読み込む。
+pub fn load(env_path: &Path) -> Option<String> {
+    fs::read_to_string(env_path.join(SYNC_STATE_FILE))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// ダイジェストを保存する。
+pub fn save(env_path: &Path, digest: &str) -> Result<()> {
+    crate::perms::create_dir_all(env_path)?;
+    let path = env_path.join(SYNC_STATE_FILE);
+    fs::write(&path, format!("{}\n", digest))
+        .with_context(|| format!("{:?} の書き込みに失敗しました", path))
+}
+
+/// 保存されているダイジェストを削除する (install 失敗時・開始時)。
+pub fn clear(env_path: &Path) {
+    let _ = fs::remove_file(env_path.join(SYNC_STATE_FILE));
+}
+
+/// 現在のダイジェストが保存済みのものと一致するか。
+pub fn is_current(env_path: &Path, digest: &str) -> bool {
+    load(env_path).as_deref() == Some(digest)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let cwd = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&cwd);
+        fs::create_dir_all(&cwd).unwrap();
+        fs::write(cwd.join("Gemfile"), "source 'https://rubygems.org'\ngem 'json'\n").unwrap();
+        fs::write(cwd.join("Gemfile.lock"), "GEM\n  specs:\n    json (2.7.1)\n").unwrap();
+        let env = cwd.join(".arc/env");
+        (cwd, env)
+    }
+
+    #[test]
+    fn test_skip_when_unchanged() {
+        let (cwd, env) = fixture("arc_sync_state_skip_test");
+        let digest = compute_digest(&cwd, "3.3.6").unwrap();
+        assert!(!is_current(&env, &digest));
+
+        save(&env, &digest).unwrap();
+        assert!(is_current(&env, &compute_digest(&cwd, "3.3.6").unwrap()));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_gemfile_edit_invalidates() {
+        let (cwd, env) = fixture("arc_sync_state_edit_test");
+        save(&env, &compute_digest(&cwd, "3.3.6").unwrap()).unwrap();
+
+        fs::write(cwd.join("Gemfile"), "source 'https://rubygems.org'\ngem 'rails'\n").unwrap();
+        assert!(!is_current(&env, &compute_digest(&cwd, "3.3.6").unwrap()));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_ruby_version_and_clear_invalidate() {
+        let (cwd, env) = fixture("arc_sync_state_ruby_test");
+        let digest = compute_digest(&cwd, "3.3.6").unwrap();
+        save(&env, &digest).unwrap();
+
+        assert!(!is_current(&env, &compute_digest(&cwd, "3.4.0").unwrap()));
+        clear(&env);
+        assert!(!is_current(&env, &digest));
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+}
@@ -0,0 +1,396 @@
+//! `TaskPool` — 複数のコマンドの並列実行 (arc ws・Signal フック)。
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::display;
+
+/// `TaskPool` で実行する 1 つのコマンド
+#[derive(Debug, Clone)]
+pub struct Task {
+    /// 出力の各行の先頭に付ける名前 (`[label] `)
+    pub label: String,
+    pub program: OsString,
+    pub args: Vec<String>,
+    /// 省略時は arc のカレントディレクトリ
+    pub cwd: Option<PathBuf>,
+    /// 追加する環境変数
+    pub env: BTreeMap<String, String>,
+    /// stdin に書き込むデータ (`None` なら stdin は空)
+    pub stdin: Option<Vec<u8>>,
+}
+
+impl Task {
+    pub fn new(label: impl Into<String>, program: impl Into<OsString>, args: Vec<String>) -> Self {
+        Self { label: label.into(), program: program.into(), args, cwd: None, env: BTreeMap::new(), stdin: None }
+    }
+}
+
+/// 1 タスクの結果
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskOutcome {
+    Success,
+    /// 終了コード (起動できなかった・シグナルで終了した場合は `None`)
+    Failed(Option<i32>),
+    /// 先に失敗したタスクがあったため実行しなかった
+    Skipped,
+    /// 中断した (Ctrl-C)。実行中だったタスクは SIGTERM で止める
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskResult {
+    pub label: String,
+    pub outcome: TaskOutcome,
+    /// 実行しなかったタスクは 0
+    pub duration: Duration,
+}
+
+/// Ctrl-C (SIGINT) を受けたか。`TaskPool::run_interruptible` の間だけハンドラーを設定する
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, AtomicOrdering::SeqCst);
+}
+
+/// タスクを同時に `jobs` 個まで実行する。
+///
+/// 子プロセスの stdout / stderr は行ごとに読み、`[label] ` を付けて 1 行ずつまとめて書き出すため、
+/// 複数のタスクの出力が行の途中で混ざることはない。子プロセスは独立したプロセスグループで起動し、
+/// 中断したときはグループごと SIGTERM で止める。
+pub struct TaskPool {
+    jobs: usize,
+    keep_going: bool,
+    discard_output: bool,
+    cancel: Arc<AtomicBool>,
+    queue: Arc<Mutex<Queue>>,
+}
+
+/// まだ始めていないタスクの取り出し位置
+#[derive(Debug, Default)]
+struct Queue {
+    /// 次に取り出すタスクの位置 (= 取り出したタスクの数)
+    next: usize,
+    /// `Closer::close` の後は新しいタスクを取り出さない
+    closed: bool,
+}
+
+/// `TaskPool::closer`: 実行中のタスクは止めずに、まだ始めていないタスクを始めないようにする
+pub struct Closer(Arc<Mutex<Queue>>);
+
+impl Closer {
+    /// 以後タスクを始めない (残りは `Skipped`)。それまでに取り出したタスクの数を返す。
+    /// タスクは `tasks` の順に取り出すので、`tasks[n..]` が始まらなかったタスク。
+    pub fn close(&self) -> usize {
+        let mut queue = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        queue.closed = true;
+        queue.next
+    }
+}
+
+impl TaskPool {
+    pub fn new(jobs: usize) -> Self {
+        Self {
+            jobs: jobs.max(1),
+            keep_going: true,
+            discard_output: false,
+            cancel: Arc::new(AtomicBool::new(false)),
+            queue: Arc::default(),
+        }
+    }
+
+    /// `false` なら失敗したタスクの後は新しいタスクを始めない (残りは `Skipped`)
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// 子プロセスの出力を読まずに捨てる (stdout / stderr を /dev/null にする)
+    pub fn discard_output(mut self) -> Self {
+        self.discard_output = true;
+        self
+    }
+
+    /// テスト用: `true` にすると未実行のタスクを取り消し、実行中のタスクを止める (Ctrl-C と同じ)
+    #[cfg(test)]
+    pub fn canceller(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancel)
+    }
+
+    /// 別のスレッドから新しいタスクの開始を止めるためのハンドル
+    pub fn closer(&self) -> Closer {
+        Closer(Arc::clone(&self.queue))
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(AtomicOrdering::SeqCst) || INTERRUPTED.load(AtomicOrdering::SeqCst)
+    }
+
+    /// `run` の間 Ctrl-C を受け取り、中断として扱う (arc 自身は終了せず、結果を返す)。
+    /// 中断した場合は `interrupted` が `true` になる。
+    pub fn run_interruptible(&self, tasks: &[Task], out: &Mutex<dyn Write + Send>) -> (Vec<TaskResult>, bool) {
+        INTERRUPTED.store(false, AtomicOrdering::SeqCst);
+        let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        let previous = unsafe { libc::signal(libc::SIGINT, handler) };
+        let results = self.run(tasks, out);
+        unsafe { libc::signal(libc::SIGINT, previous) };
+        (results, INTERRUPTED.swap(false, AtomicOrdering::SeqCst))
+    }
+
+    /// タスクを実行し、終わるまで待つ。結果は `tasks` の順。
+    pub fn run(&self, tasks: &[Task], out: &Mutex<dyn Write + Send>) -> Vec<TaskResult> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner()).next = 0;
+        let stop = AtomicBool::new(false);
+        let results = Mutex::new(
+            tasks
+                .iter()
+                .map(|t| TaskResult { label: t.label.clone(), outcome: TaskOutcome::Skipped, duration: Duration::ZERO })
+                .collect::<Vec<_>>(),
+        );
+        std::thread::scope(|scope| {
+            for _ in 0..self.jobs.min(tasks.len()) {
+                scope.spawn(|| {
+                    while !stop.load(AtomicOrdering::SeqCst) {
+                        let i = {
+                            let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+                            if queue.closed || queue.next >= tasks.len() {
+                                break;
+                            }
+                            queue.next += 1;
+                            queue.next - 1
+                        };
+                        let task = &tasks[i];
+                        if self.cancelled() {
+                            results.lock().unwrap()[i].outcome = TaskOutcome::Cancelled;
+                            continue;
+                        }
+                        let timer = Instant::now();
+                        let outcome = self.run_one(task, out);
+                        if !self.keep_going && matches!(outcome, TaskOutcome::Failed(_)) {
+                            stop.store(true, AtomicOrdering::SeqCst);
+                        }
+                        results.lock().unwrap()[i] = TaskResult { label: task.label.clone(), outcome, duration: timer.elapsed() };
+                    }
+                });
+            }
+        });
+        results.into_inner().unwrap()
+    }
+
+    fn run_one(&self, task: &Task, out: &Mutex<dyn Write + Send>) -> TaskOutcome {
+        let prefix = format!("[{}] ", task.label);
+        let output = || if self.discard_output { Stdio::null() } else { Stdio::piped() };
+        let mut command = Command::new(&task.program);
+        command
+            .args(&task.args)
+            .envs(&task.env)
+            .stdin(if task.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(output())
+            .stderr(output())
+            .process_group(0);
+        if let Some(cwd) = &task.cwd {
+            command.current_dir(cwd);
+        }
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                write_prefixed(out, &prefix, format!("failed to start: {}\n", e).as_bytes());
+                return TaskOutcome::Failed(None);
+            }
+        };
+        // stdin を読まないコマンドでも待たされないよう、書き込みは別スレッドで行う
+        if let (Some(mut stdin), Some(input)) = (child.stdin.take(), task.stdin.clone()) {
+            std::thread::spawn(move || {
+                let _ = stdin.write_all(&input);
+            });
+        }
+
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        let (status, terminated) = std::thread::scope(|scope| {
+            if let Some(stdout) = stdout {
+                scope.spawn(|| forward_lines(stdout, &prefix, out));
+            }
+            if let Some(stderr) = stderr {
+                scope.spawn(|| forward_lines(stderr, &prefix, out));
+            }
+            self.wait(&mut child)
+        });
+        match status {
+            _ if terminated => TaskOutcome::Cancelled,
+            Some(status) if status.success() => TaskOutcome::Success,
+            Some(status) => TaskOutcome::Failed(status.code()),
+            None => TaskOutcome::Failed(None),
+        }
+    }
+
+    /// 子プロセスの終了を待つ。中断されたらプロセスグループに SIGTERM を送る。
+    fn wait(&self, child: &mut std::process::Child) -> (Option<ExitStatus>, bool) {
+        let mut terminated = false;
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => return (Some(status), terminated),
+                Ok(None) => {}
+                Err(_) => return (None, terminated),
+            }
+            if !terminated && self.cancelled() {
+                unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGTERM) };
+                terminated = true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+/// `stream` を行ごとに読み、`prefix` を付けて `out` に書く。末尾に改行のない行には改行を補う。
+fn forward_lines(stream: impl Read, prefix: &str, out: &Mutex<dyn Write + Send>) {
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).is_ok_and(|n| n > 0) {
+        if line.last() != Some(&b'\n') {
+            line.push(b'\n');
+        }
+        write_prefixed(out, prefix, &line);
+        line.clear();
+    }
+}
+
+/// 1 行を 1 回の書き込みで出力する (ロックを取ったまま書くので他のタスクの行と混ざらない)
+fn write_prefixed(out: &Mutex<dyn Write + Send>, prefix: &str, line: &[u8]) {
+    let mut buf = Vec::with_capacity(prefix.len() + line.len());
+    buf.extend_from_slice(prefix.as_bytes());
+    buf.extend_from_slice(line);
+    let mut out = out.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = out.write_all(&buf);
+    let _ = out.flush();
+}
+
+/// 実行後の一覧 (ラベル, 実行時間, 結果) の行
+pub fn task_summary_lines(results: &[TaskResult]) -> Vec<String> {
+    results
+        .iter()
+        .map(|result| {
+            let status = match result.outcome {
+                TaskOutcome::Success => "✅".to_string(),
+                TaskOutcome::Failed(Some(code)) => format!("❌ exit {}", code),
+                TaskOutcome::Failed(None) => "❌".to_string(),
+                TaskOutcome::Skipped => "⏭  skipped".to_string(),
+                TaskOutcome::Cancelled => "🛑 cancelled".to_string(),
+            };
+            let duration = if result.duration.is_zero() { String::new() } else { display::fmt_duration(result.duration) };
+            format!("  {:<24} {:>8}  {}", result.label, duration, status)
+        })
+        .collect()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(label: &str, script: &str) -> Task {
+        Task::new(label, "sh", vec!["-c".to_string(), script.to_string()])
+    }
+
+    fn run(pool: &TaskPool, tasks: &[Task]) -> (Vec<TaskResult>, String) {
+        let out: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+        let results = pool.run(tasks, &out);
+        (results, String::from_utf8(out.into_inner().unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_overlapping_output_is_never_split() {
+        // 長い行を一気に書くタスクと、1 行を少しずつ書くタスクを同時に動かす
+        let long = "x".repeat(3000);
+        let tasks = [
+            sh("alpha", &format!("for i in $(seq 1 200); do echo \"alpha $i {}\"; echo \"alpha-err $i\" >&2; done", long)),
+            sh("beta", "for i in $(seq 1 20); do printf 'beta %s ' $i; sleep 0.002; printf 'tail\\n'; done"),
+            sh("gamma", "for i in $(seq 1 200); do echo \"gamma $i\"; done; printf 'gamma no-newline'"),
+        ];
+        let (results, text) = run(&TaskPool::new(3), &tasks);
+        assert!(results.iter().all(|r| r.outcome == TaskOutcome::Success), "{:?}", results);
+
+        let mut counts = BTreeMap::new();
+        for line in text.lines() {
+            let (label, rest) = line.strip_prefix('[').and_then(|l| l.split_once("] ")).unwrap_or_else(|| panic!("{}", line));
+            assert!(rest.starts_with(label), "mis-prefixed: {}", &line[..line.len().min(80)]);
+            match label {
+                "alpha" if rest.starts_with("alpha-err") => assert_eq!(rest.split(' ').count(), 2, "{}", rest),
+                "alpha" => assert_eq!(rest, format!("alpha {} {}", rest.split(' ').nth(1).unwrap(), long)),
+                "beta" => assert!(rest.ends_with(" tail"), "split: {}", rest),
+                _ => {}
+            }
+            *counts.entry(label.to_string()).or_insert(0) += 1;
+        }
+        assert_eq!(counts["alpha"], 400);
+        assert_eq!(counts["beta"], 20);
+        assert_eq!(counts["gamma"], 201);
+        assert!(text.ends_with("[gamma] gamma no-newline\n") || text.contains("[gamma] gamma no-newline\n"));
+    }
+
+    #[test]
+    fn test_failures_and_keep_going() {
+        let tasks = [sh("a", "exit 0"), sh("b", "exit 3"), sh("c", "echo c")];
+        let (results, _) = run(&TaskPool::new(1).keep_going(false), &tasks);
+        let outcomes: Vec<_> = results.iter().map(|r| r.outcome.clone()).collect();
+        assert_eq!(outcomes, [TaskOutcome::Success, TaskOutcome::Failed(Some(3)), TaskOutcome::Skipped]);
+
+        let (results, text) = run(&TaskPool::new(2), &tasks);
+        assert_eq!(results[2].outcome, TaskOutcome::Success);
+        assert_eq!(text, "[c] c\n");
+        let summary = task_summary_lines(&results);
+        assert!(summary[1].contains("b") && summary[1].ends_with("❌ exit 3"), "{:?}", summary);
+
+        let (results, text) = run(&TaskPool::new(1), &[Task::new("missing", "/nonexistent/tool", vec![])]);
+        assert_eq!(results[0].outcome, TaskOutcome::Failed(None));
+        assert!(text.starts_with("[missing] failed to start"), "{}", text);
+    }
+
+    #[test]
+    fn test_stdin_env_and_discarded_output() {
+        let mut task = sh("hook", "read line; echo \"$line $GREETING\" > out.txt; echo ignored");
+        let dir = std::env::temp_dir().join("arc_task_pool_stdin_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        task.cwd = Some(dir.clone());
+        task.env.insert("GREETING".to_string(), "hello".to_string());
+        task.stdin = Some(b"{\"id\":1}\n".to_vec());
+        let (results, text) = run(&TaskPool::new(1).discard_output(), &[task]);
+        assert_eq!(results[0].outcome, TaskOutcome::Success);
+        assert!(text.is_empty());
+        assert_eq!(std::fs::read_to_string(dir.join("out.txt")).unwrap(), "{\"id\":1} hello\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cancel_terminates_running_and_pending_tasks() {
+        let pool = TaskPool::new(2);
+        let cancel = pool.canceller();
+        let tasks: Vec<Task> = (0..4).map(|i| sh(&format!("t{}", i), &format!("echo started {}; sleep 10; echo finished", i))).collect();
+        let started = Instant::now();
+        let (results, text) = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(300));
+                cancel.store(true, AtomicOrdering::SeqCst);
+            });
+            run(&pool, &tasks)
+        });
+        assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+        assert!(results.iter().all(|r| r.outcome == TaskOutcome::Cancelled), "{:?}", results);
+        // 実行中だった 2 つは始まっていて、残りは起動していない
+        assert_eq!(text.lines().filter(|l| l.contains("started")).count(), 2, "{}", text);
+        assert!(!text.contains("finished"));
+        assert!(results[..2].iter().all(|r| r.duration > Duration::ZERO));
+        assert!(results[2..].iter().all(|r| r.duration == Duration::ZERO));
+        assert!(task_summary_lines(&results).iter().all(|l| l.ends_with("🛑 cancelled")));
+    }
+}
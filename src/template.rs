@@ -0,0 +1,143 @@
+//! config.toml の値に書ける環境変数の展開 (`${VAR}` / `${VAR:-default}`)。
+//!
+//! | 書き方 | 展開結果 |
+//! |---|---|
+//! | `${VAR}` | `VAR` の値。未定義なら空文字列 (strict モードではエラー) |
+//! | `${VAR:-default}` | `VAR` が未定義か空なら `default` |
+//! | `$${...}` | `${...}` をそのまま残す |
+//! | `$VAR` / 単独の `$` | そのまま (展開しない) |
+//!
+//! 入れ子 (`${A:-${B}}`) には対応しない。`default` は最初の `}` までで、それ以降はそのままの文字列になる。
+
+use anyhow::{Result, bail};
+
+/// 変数名から値を引く関数 (通常は `std::env::var`)
+pub type Lookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// `input` 内の `${...}` を `lookup` で展開する。
+/// `strict` の場合、既定値のない未定義の変数をエラーにする。
+pub fn expand(input: &str, lookup: Lookup, strict: bool) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with("$${") {
+            // エスケープ: `$$` の後の `${` は展開しない
+            out.push('$');
+            rest = &rest[2..];
+            let end = rest.find('}').map_or(rest.len(), |i| i + 1);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if !rest.starts_with("${") {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+        let Some(end) = rest.find('}') else { bail!("`${{` が閉じられていません: {:?}", input) };
+        let expr = &rest[2..end];
+        rest = &rest[end + 1..];
+
+        let (name, default) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expr, None),
+        };
+        if !is_valid_name(name) {
+            bail!("環境変数名が不正です: ${{{}}} ({:?})", expr, input);
+        }
+        if default.is_some_and(|d| d.contains("${")) {
+            bail!("${{...}} の入れ子には対応していません: {:?}", input);
+        }
+        match (lookup(name).filter(|v| !v.is_empty() || default.is_none()), default) {
+            (Some(value), _) => out.push_str(&value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) if strict => bail!("環境変数 {} が定義されていません ({:?})", name, input),
+            (None, None) => {}
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_ascii_alphabetic()) && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "USER" => Some("alice".into()),
+            "EMPTY" => Some(String::new()),
+            "TOKEN" => Some("s3cr${et}".into()),
+            _ => None,
+        }
+    }
+
+    fn lenient(input: &str) -> String {
+        expand(input, &env, false).unwrap()
+    }
+
+    fn strict(input: &str) -> Result<String> {
+        expand(input, &env, true)
+    }
+
+    #[test]
+    fn test_plain_and_defined() {
+        assert_eq!(lenient(""), "");
+        assert_eq!(lenient("no variables"), "no variables");
+        assert_eq!(lenient("/scratch/${USER}/arc-cache"), "/scratch/alice/arc-cache");
+        assert_eq!(lenient("${USER}${USER}"), "alicealice");
+        assert_eq!(lenient("${USER:-bob}"), "alice");
+        // 展開した値は再展開しない
+        assert_eq!(lenient("${TOKEN}"), "s3cr${et}");
+    }
+
+    #[test]
+    fn test_undefined_and_defaults() {
+        assert_eq!(lenient("a${MISSING}b"), "ab");
+        assert_eq!(lenient("${MISSING:-fallback}"), "fallback");
+        assert_eq!(lenient("${MISSING:-}"), "");
+        assert_eq!(lenient("${EMPTY}"), "");
+        assert_eq!(lenient("${EMPTY:-used}"), "used");
+        assert_eq!(lenient("${MISSING:-a:-b}"), "a:-b");
+        // 既定値は最初の `}` まで
+        assert_eq!(lenient("${MISSING:-x}y}"), "xy}");
+
+        assert!(strict("${MISSING}").unwrap_err().to_string().contains("MISSING"));
+        assert_eq!(strict("${MISSING:-ok}").unwrap(), "ok");
+        assert_eq!(strict("${EMPTY}").unwrap(), "");
+    }
+
+    #[test]
+    fn test_escapes_and_literals() {
+        assert_eq!(lenient("$${USER}"), "${USER}");
+        assert_eq!(lenient("$${MISSING:-x} ${USER}"), "${MISSING:-x} alice");
+        assert_eq!(strict("$${MISSING}").unwrap(), "${MISSING}");
+        assert_eq!(lenient("$USER costs $5 $"), "$USER costs $5 $");
+        assert_eq!(lenient("$$"), "$$");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(strict("${USER").unwrap_err().to_string().contains("閉じられていません"));
+        assert!(lenient_err("prefix ${"));
+        assert!(lenient_err("${}"));
+        assert!(lenient_err("${1ABC}"));
+        assert!(lenient_err("${A-B}"));
+        assert!(strict("${MISSING:-${USER}}").unwrap_err().to_string().contains("入れ子"));
+    }
+
+    fn lenient_err(input: &str) -> bool {
+        expand(input, &env, false).is_err()
+    }
+}
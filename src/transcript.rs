@@ -0,0 +1,322 @@
+//! `arc shell --transcript` の記録と再生。
+//!
+//! 記録は `.flux/output/<shell_enter の ID>.<拡張子>` に書き、`arc output <ID>` で再生する。
+//!
+//! | 形式 | 拡張子 | 内容 |
+//! |---|---|---|
+//! | `text` | `.transcript` | 開始・終了の時刻の行で挟んだ、端末への出力そのまま |
+//! | `cast` | `.cast` | asciinema v2 (1 行目がヘッダー、以降は `[経過秒, "o" または "r", データ]`) |
+//!
+//! 記録は `pty::FLUSH_INTERVAL` ごとに書き出す。秘密情報の除去はしない。
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::pty::{Sink, WinSize};
+
+/// 記録ファイルの拡張子 (`output_store` はこれらも出力として扱う)
+pub const EXTENSIONS: [&str; 2] = ["transcript", "cast"];
+/// 再生するときに詰める無操作の時間の上限 (asciinema の idle_time_limit と同じ考え方)
+const MAX_IDLE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum TranscriptFormat {
+    /// 端末への出力をそのまま保存する
+    Text,
+    /// asciinema v2 の cast ファイル (時間つき)
+    Cast,
+}
+
+impl TranscriptFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TranscriptFormat::Text => "text",
+            TranscriptFormat::Cast => "cast",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Text => EXTENSIONS[0],
+            TranscriptFormat::Cast => EXTENSIONS[1],
+        }
+    }
+}
+
+// ─────────────────────────────────────────────
+// 記録
+// ─────────────────────────────────────────────
+
+/// セッションの出力を記録ファイルに書く `Sink`。
+pub struct Recorder {
+    format: TranscriptFormat,
+    out: BufWriter<File>,
+    started: Instant,
+    bytes: u64,
+    /// 次の出力に回す、末尾の不完全な UTF-8 (cast 用)
+    carry: Vec<u8>,
+}
+
+impl Recorder {
+    /// 記録ファイルを作り、ヘッダーを書く。`started_at` は RFC 3339 の開始時刻。
+    pub fn create(path: &Path, format: TranscriptFormat, size: WinSize, started_at: &str, shell: &str) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("記録ファイルを作成できません: {:?}", path))?;
+        let mut out = BufWriter::new(file);
+        match format {
+            TranscriptFormat::Text => writeln!(out, "# arc shell transcript started {} ({})", started_at, shell)?,
+            TranscriptFormat::Cast => {
+                let timestamp = chrono::DateTime::parse_from_rfc3339(started_at).map_or(0, |t| t.timestamp());
+                let term = std::env::var("TERM").unwrap_or_default();
+                writeln!(out, "{}", cast_header(size, timestamp, shell, &term))?;
+            }
+        }
+        out.flush()?;
+        Ok(Self { format, out, started: Instant::now(), bytes: 0, carry: Vec::new() })
+    }
+
+    /// 終了の記録を書いて閉じる。記録した出力のバイト数を返す。
+    pub fn finish(mut self, ended_at: &str, exit_code: i32) -> Result<u64> {
+        if self.format == TranscriptFormat::Text {
+            writeln!(self.out, "\n# arc shell transcript ended {} (exit code {})", ended_at, exit_code)?;
+        } else if !self.carry.is_empty() {
+            let rest = String::from_utf8_lossy(&std::mem::take(&mut self.carry)).into_owned();
+            writeln!(self.out, "{}", cast_event(self.started.elapsed(), "o", &rest))?;
+        }
+        self.out.flush()?;
+        Ok(self.bytes)
+    }
+}
+
+impl Sink for Recorder {
+    fn output(&mut self, data: &[u8]) -> Result<()> {
+        self.bytes += data.len() as u64;
+        match self.format {
+            TranscriptFormat::Text => self.out.write_all(data)?,
+            TranscriptFormat::Cast => {
+                let text = take_utf8(&mut self.carry, data);
+                if !text.is_empty() {
+                    writeln!(self.out, "{}", cast_event(self.started.elapsed(), "o", &text))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn resize(&mut self, size: WinSize) -> Result<()> {
+        if self.format == TranscriptFormat::Cast {
+            let size = format!("{}x{}", size.cols, size.rows);
+            writeln!(self.out, "{}", cast_event(self.started.elapsed(), "r", &size))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+/// asciinema v2 のヘッダー行。
+pub fn cast_header(size: WinSize, timestamp: i64, shell: &str, term: &str) -> String {
+    json!({
+        "version": 2,
+        "width": size.cols,
+        "height": size.rows,
+        "timestamp": timestamp,
+        "env": { "SHELL": shell, "TERM": term },
+    })
+    .to_string()
+}
+
+/// asciinema v2 のイベント行 (`[経過秒, コード, データ]`)。
+pub fn cast_event(elapsed: Duration, code: &str, data: &str) -> String {
+    json!([elapsed.as_micros() as f64 / 1_000_000.0, code, data]).to_string()
+}
+
+/// `carry` に `data` を足し、文字列にできる分を取り出す。末尾の不完全な UTF-8 は `carry` に残す
+/// (pty の読み出しは文字の途中で切れることがある)。不正なバイトは U+FFFD にする。
+fn take_utf8(carry: &mut Vec<u8>, data: &[u8]) -> String {
+    carry.extend_from_slice(data);
+    let mut end = carry.len();
+    for back in 1..=carry.len().min(3) {
+        let byte = carry[carry.len() - back];
+        if byte & 0xC0 == 0x80 {
+            continue; // 継続バイト: さらに前に先頭バイトがある
+        }
+        let needed = match byte {
+            0xF0.. => 4,
+            0xE0.. => 3,
+            0xC0.. => 2,
+            _ => 1,
+        };
+        if needed > back {
+            end = carry.len() - back;
+        }
+        break;
+    }
+    let text = String::from_utf8_lossy(&carry[..end]).into_owned();
+    carry.drain(..end);
+    text
+}
+
+// ─────────────────────────────────────────────
+// 再生
+// ─────────────────────────────────────────────
+
+/// `<id>.cast` / `<id>.transcript` (圧縮済みを含む) を探す。
+pub fn find(output_dir: &Path, id: &str) -> Result<Option<(TranscriptFormat, String)>> {
+    for format in [TranscriptFormat::Cast, TranscriptFormat::Text] {
+        if let Some(content) = crate::output_store::read(output_dir, id, format.extension())? {
+            return Ok(Some((format, content)));
+        }
+    }
+    Ok(None)
+}
+
+/// 記録を `out` に再生する。cast はイベントの間隔どおりに `sleep` しながら書く (無操作の時間は
+/// `MAX_IDLE` に詰める)。text はそのまま書く。
+pub fn replay(format: TranscriptFormat, content: &str, out: &mut dyn Write, sleep: &mut dyn FnMut(Duration)) -> Result<()> {
+    if format == TranscriptFormat::Text {
+        out.write_all(content.as_bytes())?;
+        return Ok(out.flush()?);
+    }
+    let mut previous = 0.0;
+    for line in content.lines() {
+        // ヘッダーと、読めない行 (切り詰めの印など) は飛ばす
+        let Ok(Value::Array(event)) = serde_json::from_str::<Value>(line) else { continue };
+        let (Some(at), Some(code)) = (event.first().and_then(Value::as_f64), event.get(1).and_then(Value::as_str)) else {
+            continue;
+        };
+        let gap = Duration::from_secs_f64((at - previous).max(0.0)).min(MAX_IDLE);
+        previous = at;
+        if code != "o" {
+            continue;
+        }
+        if !gap.is_zero() {
+            sleep(gap);
+        }
+        out.write_all(event.get(2).and_then(Value::as_str).unwrap_or_default().as_bytes())?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_cast_header_and_events() {
+        let header: Value = serde_json::from_str(&cast_header(WinSize { rows: 24, cols: 80 }, 1_700_000_000, "/bin/bash", "xterm")).unwrap();
+        assert_eq!(header["version"], 2);
+        assert_eq!(header["width"], 80);
+        assert_eq!(header["height"], 24);
+        assert_eq!(header["timestamp"], 1_700_000_000);
+        assert_eq!(header["env"]["SHELL"], "/bin/bash");
+
+        assert_eq!(cast_event(Duration::from_millis(1500), "o", "hi\r\n\"x\""), r#"[1.5,"o","hi\r\n\"x\""]"#);
+        assert_eq!(cast_event(Duration::ZERO, "r", "100x30"), r#"[0.0,"r","100x30"]"#);
+    }
+
+    #[test]
+    fn test_take_utf8_carries_split_characters() {
+        let mut carry = Vec::new();
+        let bytes = "aあ".as_bytes(); // "あ" は 3 バイト
+        assert_eq!(take_utf8(&mut carry, &bytes[..2]), "a");
+        assert_eq!(carry.len(), 1);
+        assert_eq!(take_utf8(&mut carry, &bytes[2..3]), "");
+        assert_eq!(take_utf8(&mut carry, &bytes[3..]), "あ");
+        assert!(carry.is_empty());
+        // 不正なバイトは持ち越さない
+        assert_eq!(take_utf8(&mut carry, b"x\xffy"), "x\u{fffd}y");
+        assert!(carry.is_empty());
+    }
+
+    #[test]
+    fn test_text_recorder_wraps_output_with_timestamps() {
+        let dir = fixture("arc_transcript_text_test");
+        let path = dir.join("01.transcript");
+        let mut recorder = Recorder::create(&path, TranscriptFormat::Text, WinSize::DEFAULT, "2026-01-02T03:04:05+00:00", "/bin/sh").unwrap();
+        recorder.output(b"$ echo hi\r\nhi\r\n").unwrap();
+        recorder.resize(WinSize { rows: 10, cols: 10 }).unwrap();
+        recorder.flush().unwrap();
+        // 書き出した分は、閉じる前でもファイルにある
+        assert!(fs::read_to_string(&path).unwrap().contains("hi\r\n"));
+        let bytes = recorder.finish("2026-01-02T03:05:00+00:00", 0).unwrap();
+
+        assert_eq!(bytes, 15);
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            "# arc shell transcript started 2026-01-02T03:04:05+00:00 (/bin/sh)\n$ echo hi\r\nhi\r\n\n# arc shell transcript ended 2026-01-02T03:05:00+00:00 (exit code 0)\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cast_recorder_writes_asciinema_v2() {
+        let dir = fixture("arc_transcript_cast_test");
+        let path = dir.join("01.cast");
+        let mut recorder = Recorder::create(&path, TranscriptFormat::Cast, WinSize { rows: 30, cols: 100 }, "2026-01-02T03:04:05+00:00", "/bin/sh").unwrap();
+        let bytes = "é\r\n".as_bytes();
+        recorder.output(&bytes[..1]).unwrap();
+        recorder.output(&bytes[1..]).unwrap();
+        recorder.resize(WinSize { rows: 40, cols: 120 }).unwrap();
+        assert_eq!(recorder.finish("", 0).unwrap(), 4);
+
+        let lines: Vec<Value> = fs::read_to_string(&path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["width"], 100);
+        assert_eq!(lines[0]["timestamp"], 1_767_323_045);
+        assert_eq!((lines[1][1].as_str(), lines[1][2].as_str()), (Some("o"), Some("é\r\n")));
+        assert_eq!((lines[2][1].as_str(), lines[2][2].as_str()), (Some("r"), Some("120x40")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay_cast_with_capped_timing() {
+        let cast = [
+            cast_header(WinSize::DEFAULT, 0, "/bin/sh", ""),
+            r#"[0.5,"o","a"]"#.to_string(),
+            r#"[0.5,"o","b"]"#.to_string(),
+            r#"[1.0,"r","10x10"]"#.to_string(),
+            "[arc: truncated]".to_string(),
+            r#"[30.0,"o","c"]"#.to_string(),
+        ]
+        .join("\n");
+        let (mut out, mut sleeps) = (Vec::new(), Vec::new());
+        replay(TranscriptFormat::Cast, &cast, &mut out, &mut |d| sleeps.push(d)).unwrap();
+
+        assert_eq!(out, b"abc");
+        assert_eq!(sleeps, [Duration::from_millis(500), MAX_IDLE]);
+    }
+
+    #[test]
+    fn test_find_and_replay_text() {
+        let dir = fixture("arc_transcript_find_test");
+        fs::write(dir.join("01.transcript"), "hello\n").unwrap();
+        let (format, content) = find(&dir, "01").unwrap().unwrap();
+        assert_eq!(format, TranscriptFormat::Text);
+        assert!(find(&dir, "02").unwrap().is_none());
+
+        let mut out = Vec::new();
+        replay(format, &content, &mut out, &mut |_| panic!("text is not timed")).unwrap();
+        assert_eq!(out, b"hello\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
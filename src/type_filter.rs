@@ -0,0 +1,135 @@
+//! `arc state --type` の Signal 種別フィルタ。
+//!
+//! `-t add -t remove` のような繰り返し指定、`-t add,remove` のようなカンマ区切り、
+//! `-t '!exec_start'` のような `!` による除外を組み合わせられる。
+//! 肯定と否定が混在する場合は「肯定の集合から否定を除いたもの」になる。
+
+use anyhow::{Result, bail};
+use std::collections::BTreeSet;
+
+use crate::signals::SignalType;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeFilter {
+    /// 空なら全種別を対象にする
+    include: BTreeSet<String>,
+    exclude: BTreeSet<String>,
+}
+
+impl TypeFilter {
+    /// `--type` の値 (繰り返し分) からフィルタを組み立てる。
+    /// 既知の種別にも、ログに現れる種別 (`present`) にも一致しない名前はエラーにする。
+    pub fn parse<'a>(specs: &[String], present: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut valid: BTreeSet<String> = SignalType::KNOWN.iter().map(|t| t.to_string()).collect();
+        valid.extend(present.into_iter().map(String::from));
+
+        let mut filter = Self::default();
+        for item in specs.iter().flat_map(|s| s.split(',')).map(str::trim).filter(|s| !s.is_empty()) {
+            let (negated, name) = match item.strip_prefix('!') {
+                Some(name) => (true, name.trim()),
+                None => (false, item),
+            };
+            if !valid.contains(name) {
+                bail!("{}", unknown_type_message(name, &valid));
+            }
+            if negated {
+                filter.exclude.insert(name.to_string());
+            } else {
+                filter.include.insert(name.to_string());
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, r_type: &str) -> bool {
+        (self.include.is_empty() || self.include.contains(r_type)) && !self.exclude.contains(r_type)
+    }
+}
+
+/// 既知の種別を定義順に、続けてログにだけ現れる種別を名前順に並べたエラーメッセージ。
+fn unknown_type_message(name: &str, valid: &BTreeSet<String>) -> String {
+    let mut values: Vec<String> = SignalType::KNOWN.iter().map(|t| t.to_string()).collect();
+    let custom: Vec<String> = valid.iter().filter(|v| !values.contains(v)).cloned().collect();
+    values.extend(custom);
+    format!(
+        "不明な Signal 種別です: '{}'{}\n  指定できる値: {}",
+        name,
+        crate::suggest::hint(name, values.iter().map(String::as_str)),
+        values.join(", ")
+    )
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(specs: &[&str]) -> TypeFilter {
+        let specs: Vec<String> = specs.iter().map(|s| s.to_string()).collect();
+        TypeFilter::parse(&specs, ["shell_command"]).unwrap()
+    }
+
+    #[test]
+    fn test_empty_matches_everything() {
+        let f = filter(&[]);
+        assert!(f.matches("add"));
+        assert!(f.matches("shell_command"));
+    }
+
+    #[test]
+    fn test_repeated_and_comma_separated() {
+        for f in [filter(&["add", "remove"]), filter(&["add,remove"]), filter(&["add, remove,"])] {
+            assert!(f.matches("add"));
+            assert!(f.matches("remove"));
+            assert!(!f.matches("exec_start"));
+        }
+    }
+
+    #[test]
+    fn test_negation_only() {
+        let f = filter(&["!exec_start", "!exec_end"]);
+        assert!(f.matches("add"));
+        assert!(!f.matches("exec_start"));
+        assert!(!f.matches("exec_end"));
+    }
+
+    #[test]
+    fn test_positive_minus_negative() {
+        let f = filter(&["add,remove,undo", "!remove"]);
+        assert!(f.matches("add"));
+        assert!(f.matches("undo"));
+        assert!(!f.matches("remove"));
+        assert!(!f.matches("init"));
+    }
+
+    #[test]
+    fn test_custom_type_from_log() {
+        let f = filter(&["shell_command"]);
+        assert!(f.matches("shell_command"));
+        assert!(!f.matches("add"));
+    }
+
+    #[test]
+    fn test_unknown_type_error_lists_valid_values() {
+        let err = TypeFilter::parse(&["ad".to_string()], ["shell_command"]).unwrap_err().to_string();
+        assert!(err.starts_with("不明な Signal 種別です: 'ad' (did you mean 'add'?)\n  指定できる値: init, exec_start, exec_end,"));
+        assert!(err.ends_with("adopt, prune, shell_enter, shell_exit, shell_cmd, preflight_failed, retention_purge, binstub, config_set, shell_command"));
+
+        let err = TypeFilter::parse(&["!exec".to_string()], []).unwrap_err().to_string();
+        assert!(err.contains("'exec'"));
+    }
+
+    #[test]
+    fn test_unknown_type_suggests_closest() {
+        let err = TypeFilter::parse(&["instal_end".to_string()], []).unwrap_err().to_string();
+        assert!(err.starts_with("不明な Signal 種別です: 'instal_end' (did you mean 'install_end'?)\n"), "{}", err);
+        // ログにだけ現れる種別からも提案する
+        let err = TypeFilter::parse(&["!shell_comand".to_string()], ["shell_command"]).unwrap_err().to_string();
+        assert!(err.contains("(did you mean 'shell_command'?)"), "{}", err);
+        let err = TypeFilter::parse(&["deploy".to_string()], []).unwrap_err().to_string();
+        assert!(!err.contains("did you mean"), "{}", err);
+    }
+}
@@ -0,0 +1,211 @@
+//! `arc ui` の画面 (`tui` feature、ratatui)。表示する内容はすべて `dashboard` で作る。
+//!
+//! 要約・履歴・詳細の 3 つのペインを、端末の幅に応じて縦か横に並べる。小さすぎる端末では案内だけを表示する。
+//! 0.5 秒ごとにログの追記を読み、`arc sync` やタスクは端末を一度戻してから `arc` 自身として起動する。
+//! `ratatui::init` はパニック時に端末を戻すフックを設定するので、途中で落ちても端末は元に戻る。
+
+use anyhow::{Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::ArcConfig;
+use crate::dashboard::{self, Action, Detail, Layout, Spawn, View};
+use crate::signals::FluxProject;
+
+/// ログの追記を読む間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn run(cwd: &Path) -> Result<()> {
+    let project = FluxProject::open(cwd)?;
+    let config = ArcConfig::load(&project.flux_dir).unwrap_or_default();
+    let mut view = View::new(dashboard::ui_task(&config));
+    view.feed.poll(&project)?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &project, &config, &mut view);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, project: &FluxProject, config: &ArcConfig, view: &mut View) -> Result<()> {
+    let output_dir = crate::commands::output_dir(&project.flux_dir);
+    loop {
+        terminal.draw(|frame| draw(frame, view, config, &output_dir))?;
+        if event::poll(POLL_INTERVAL)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            let action = match key.code {
+                KeyCode::Up => Some(Action::Up),
+                KeyCode::Down => Some(Action::Down),
+                KeyCode::Home => Some(Action::Top),
+                KeyCode::End => Some(Action::Latest),
+                KeyCode::Esc => Some(Action::Quit),
+                KeyCode::Char(c) => dashboard::action_for(c),
+                _ => None,
+            };
+            match action {
+                Some(Action::Quit) => return Ok(()),
+                Some(action) => {
+                    if let Some(spawn) = view.apply(action) {
+                        view.status = suspended(terminal, &spawn)?;
+                    }
+                }
+                None => {}
+            }
+        }
+        view.feed.poll(project)?;
+    }
+}
+
+/// 端末を戻して `arc <spawn>` を実行し、Enter を待ってから画面に戻る。結果を返す
+fn suspended(terminal: &mut DefaultTerminal, spawn: &Spawn) -> Result<String> {
+    ratatui::restore();
+    let args = spawn.args();
+    println!("$ arc {}", args.join(" "));
+    let status = std::process::Command::new(std::env::current_exe()?)
+        .args(&args)
+        .status()
+        .context("arc を起動できません");
+    println!("\nPress Enter to return to arc ui");
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+    *terminal = ratatui::init();
+    Ok(match status? {
+        status if status.success() => format!("arc {} finished", args.join(" ")),
+        status => format!("arc {} failed ({})", args.join(" "), status),
+    })
+}
+
+/// 1 画面分を描く
+pub fn draw(frame: &mut Frame, view: &View, config: &ArcConfig, output_dir: &Path) {
+    let area = frame.area();
+    let layout = dashboard::layout_for(area.width, area.height);
+    if layout == Layout::TooSmall {
+        let message = format!(
+            "Terminal too small for arc ui ({}x{}); need at least {}x{}. Press q to quit.",
+            area.width,
+            area.height,
+            dashboard::MIN_WIDTH,
+            dashboard::MIN_HEIGHT
+        );
+        frame.render_widget(Paragraph::new(message).wrap(Wrap { trim: true }), area);
+        return;
+    }
+
+    let summary = view.summary(config);
+    let [top, body, footer] = ratatui::layout::Layout::vertical([
+        Constraint::Length(summary.len() as u16 + 2),
+        Constraint::Min(4),
+        Constraint::Length(1),
+    ])
+    .areas(area);
+    frame.render_widget(Paragraph::new(summary.join("\n")).block(titled("arc state")), top);
+
+    let direction = if layout == Layout::Columns { Direction::Horizontal } else { Direction::Vertical };
+    let [history, detail] =
+        ratatui::layout::Layout::default().direction(direction).constraints([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(body);
+    draw_history(frame, view, history);
+    draw_detail(frame, view, output_dir, detail);
+
+    let status = if view.status.is_empty() { dashboard::HELP.to_string() } else { format!("{}  |  {}", view.status, dashboard::HELP) };
+    frame.render_widget(Paragraph::new(status).style(Style::default().fg(Color::DarkGray)), footer);
+}
+
+fn titled(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(format!(" {} ", title))
+}
+
+fn draw_history(frame: &mut Frame, view: &View, area: Rect) {
+    let rows = view.rows();
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|row| {
+            let style = if row.failed { Style::default().fg(Color::Red) } else { Style::default() };
+            ListItem::new(row.line()).style(style)
+        })
+        .collect();
+    let follow = if view.following() { ", following" } else { "" };
+    let title = format!("history ({}{})", view.filter.label(), follow);
+    let list = List::new(items).block(titled(&title)).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(view.selected_index(&rows));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_detail(frame: &mut Frame, view: &View, output_dir: &Path, area: Rect) {
+    let Some(signal) = view.selected_signal() else {
+        frame.render_widget(Paragraph::new("No signals").block(titled("detail")), area);
+        return;
+    };
+    let detail = Detail::from_signal(signal, output_dir);
+    // 出力の末尾が見えるよう、収まらない分は先頭から省く
+    let lines = detail.lines();
+    let visible = area.height.saturating_sub(2) as usize;
+    let text = lines[lines.len().saturating_sub(visible)..].join("\n");
+    frame.render_widget(Paragraph::new(text).block(titled(&detail.title)), area);
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::Signal;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use serde_json::json;
+
+    fn view() -> View {
+        let signal = |id: &str, r_type: &str, payload: serde_json::Value| Signal {
+            id: id.to_string(),
+            r_type: r_type.to_string(),
+            payload,
+            timestamp: "2024-05-01T10:00:00+09:00".to_string(),
+            meta: None,
+            v: 2,
+        };
+        let mut view = View::new(None);
+        view.feed.extend(vec![
+            signal("1", "init", json!({ "path": "/p" })),
+            signal("2", "run_start", json!({ "command": "rspec", "args": [], "cwd": "/p" })),
+            signal("3", "run_end", json!({ "ref_id": "2", "exit_code": 1, "success": false, "duration_us": 1000 })),
+        ]);
+        view
+    }
+
+    fn render(width: u16, height: u16, view: &View) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let dir = std::env::temp_dir().join("arc_ui_render_test_missing");
+        terminal.draw(|frame| draw(frame, view, &ArcConfig::default(), &dir)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_draws_three_panes() {
+        let screen = render(120, 30, &view());
+        assert!(screen.contains("arc state"), "{}", screen);
+        assert!(screen.contains("Project:     /p"), "{}", screen);
+        assert!(screen.contains("history (all types, following)"), "{}", screen);
+        assert!(screen.contains("run_end 3"), "{}", screen);
+        assert!(screen.contains("\"ref_id\": \"2\""), "{}", screen);
+        assert!(screen.contains("q quit"), "{}", screen);
+    }
+
+    #[test]
+    fn test_too_small_terminal() {
+        let screen = render(40, 10, &view());
+        assert!(screen.contains("Terminal too small"), "{}", screen);
+        assert!(!screen.contains("history"), "{}", screen);
+    }
+}
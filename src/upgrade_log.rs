@@ -0,0 +1,230 @@
+//! `arc upgrade-log`: `signals.jsonl` の v1 の Signal (`v` のないもの) を現在の形式 (`SCHEMA_VERSION`) に書き換える。
+//!
+//! 機械的に移せるものだけを移す:
+//!
+//! - exec_end / run_end の `duration_ms` しかない payload に `duration_us` を補う
+//! - オフセットのない時刻 (`2024-01-01 12:00:00` など) を UTC として RFC 3339 に直す。
+//!   オフセットのある時刻は、記録したときの時刻を表示に使うためそのまま残す
+//! - `v` を付ける
+//!
+//! 移せない行 (JSON として読めない、知らないフィールドがある、時刻を解釈できない) は元の内容のまま残す。
+//! 読み込み側は v1 も引き続き読めるため、残った行があってもログは使える。
+//! 書き換えは一時ファイルに書いてから rename し、何度実行しても結果は変わらない。
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::signals::{SCHEMA_VERSION, Signal};
+
+/// 1 行の移行結果
+#[derive(Debug, Clone, PartialEq)]
+pub enum Migrated {
+    /// v1 から書き換えた
+    Upgraded(String),
+    /// 既に現在の形式
+    Current,
+    /// 移せないため元の内容のまま残す (理由)
+    Kept(&'static str),
+}
+
+/// 移行の集計
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpgradeReport {
+    pub upgraded: usize,
+    pub current: usize,
+    /// 残した行の数 (理由ごと)
+    pub kept: BTreeMap<&'static str, usize>,
+}
+
+impl UpgradeReport {
+    pub fn kept_total(&self) -> usize {
+        self.kept.values().sum()
+    }
+}
+
+/// Signal の既知のフィールド。これ以外を持つ行は書き換えると失われるため残す
+const FIELDS: [&str; 6] = ["id", "type", "payload", "timestamp", "meta", "v"];
+
+/// 1 行を現在の形式に移す。
+pub fn migrate_line(line: &str) -> Migrated {
+    let Ok(value) = serde_json::from_str::<Value>(line) else { return Migrated::Kept("not JSON") };
+    let Some(fields) = value.as_object() else { return Migrated::Kept("not a signal") };
+    if fields.keys().any(|k| !FIELDS.contains(&k.as_str())) {
+        return Migrated::Kept("unknown fields");
+    }
+    let Ok(mut signal) = serde_json::from_value::<Signal>(value) else { return Migrated::Kept("not a signal") };
+    if signal.v >= SCHEMA_VERSION {
+        return Migrated::Current;
+    }
+    let Some(timestamp) = normalize_timestamp(&signal.timestamp) else { return Migrated::Kept("unparsable timestamp") };
+    signal.timestamp = timestamp;
+    if matches!(signal.r_type.as_str(), "exec_end" | "run_end")
+        && let Some(payload) = signal.payload.as_object_mut()
+        && !payload.contains_key("duration_us")
+        && let Some(ms) = payload.get("duration_ms").and_then(Value::as_u64)
+    {
+        payload.insert("duration_us".to_string(), Value::from(ms * 1000));
+    }
+    signal.v = SCHEMA_VERSION;
+    match serde_json::to_string(&signal) {
+        Ok(json) => Migrated::Upgraded(json),
+        Err(_) => Migrated::Kept("not a signal"),
+    }
+}
+
+/// RFC 3339 の時刻はそのまま、オフセットのない時刻は UTC として RFC 3339 にする。解釈できなければ `None`。
+fn normalize_timestamp(ts: &str) -> Option<String> {
+    if DateTime::parse_from_rfc3339(ts).is_ok() {
+        return Some(ts.to_string());
+    }
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(ts, format).ok())
+        .map(|naive| naive.and_utc().fixed_offset().to_rfc3339())
+}
+
+/// `content` (signals.jsonl の中身) を移行した内容と集計を返す。書き換える行がなければ内容は `None`。
+pub fn migrate(content: &str) -> (Option<String>, UpgradeReport) {
+    let mut report = UpgradeReport::default();
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let (body, newline) = line.strip_suffix('\n').map_or((line, ""), |b| (b, "\n"));
+        if body.trim().is_empty() {
+            out.push_str(line);
+            continue;
+        }
+        match migrate_line(body) {
+            Migrated::Upgraded(json) => {
+                report.upgraded += 1;
+                out.push_str(&json);
+                out.push_str(newline);
+            }
+            Migrated::Current => {
+                report.current += 1;
+                out.push_str(line);
+            }
+            Migrated::Kept(reason) => {
+                *report.kept.entry(reason).or_default() += 1;
+                out.push_str(line);
+            }
+        }
+    }
+    ((report.upgraded > 0).then_some(out), report)
+}
+
+/// `signal_file` を移行する。書き換える行があれば一時ファイルに書いてから置き換える。
+/// ロックを取らない exec / run が途中で追記した場合は、その行を失わないよう読み直してやり直す。
+pub fn upgrade(signal_file: &Path) -> Result<UpgradeReport> {
+    crate::fs_util::rewrite_with_retry(signal_file, || {
+        let content = match fs::read_to_string(signal_file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Some(UpgradeReport::default())),
+            Err(e) => return Err(e).with_context(|| format!("{:?} を読み込めません", signal_file)),
+        };
+        let (migrated, report) = migrate(&content);
+        let Some(migrated) = migrated else { return Ok(Some(report)) };
+        if crate::dry_run::is_enabled() {
+            crate::dry_run::note(&format!("would rewrite {}", signal_file.display()));
+            return Ok(Some(report));
+        }
+        let replaced = crate::fs_util::replace_unless_appended(signal_file, &migrated, content.len() as u64, "upgrade")?;
+        Ok(replaced.then_some(report))
+    })
+}
+
+/// ログの中の v1 の Signal の数 (`arc state` の案内)
+pub fn legacy_count(signals: &[Signal]) -> usize {
+    signals.iter().filter(|s| s.v < SCHEMA_VERSION).count()
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FluxState;
+
+    /// v1 のログ (`v` なし、exec_end は duration_ms だけ、オフセットのない時刻を含む)
+    const V1_LOG: &str = r#"{"id":"01","type":"init","payload":{"project_path":"/work/shop","version":"0.1.0"},"timestamp":"2024-03-01T09:00:00+09:00"}
+{"id":"02","type":"exec_start","payload":{"command":"rake","args":["spec"],"cwd":"/work/shop"},"timestamp":"2024-03-01T09:01:00+09:00","meta":{"user":"kai"}}
+{"id":"03","type":"exec_end","payload":{"ref_id":"02","exit_code":0,"success":true,"duration_ms":1500},"timestamp":"2024-03-01T09:01:01+09:00"}
+{"id":"04","type":"exec_start","payload":{"command":"rspec","args":[],"cwd":"/work/shop"},"timestamp":"2024-03-01 00:02:00"}
+{"id":"05","type":"exec_end","payload":{"ref_id":"04","exit_code":1,"success":false,"duration_ms":20,"duration_us":20400},"timestamp":"2024-03-01 00:02:00.5"}
+{"id":"06","type":"add","payload":{"gem":"rack"},"timestamp":"yesterday"}
+{"id":"07","type":"add","payload":{"gem":"puma"},"timestamp":"2024-03-01T09:03:00+09:00","seq":7}
+not json
+{"id":"08","type":"remove","payload":{"gem":"rack"},"timestamp":"2024-03-01T09:04:00+09:00","v":2}
+"#;
+
+    fn parse(content: &str) -> Vec<Signal> {
+        content.lines().filter_map(|l| serde_json::from_str(l).ok()).collect()
+    }
+
+    fn executions(state: &FluxState) -> Value {
+        serde_json::to_value(&state.executions).unwrap()
+    }
+
+    #[test]
+    fn test_migrate_line() {
+        let Migrated::Upgraded(json) = migrate_line(V1_LOG.lines().nth(2).unwrap()) else { panic!() };
+        let signal: Signal = serde_json::from_str(&json).unwrap();
+        assert_eq!((signal.v, signal.payload["duration_us"].as_u64()), (SCHEMA_VERSION, Some(1_500_000)));
+        assert_eq!(signal.timestamp, "2024-03-01T09:01:01+09:00");
+
+        let Migrated::Upgraded(json) = migrate_line(V1_LOG.lines().nth(3).unwrap()) else { panic!() };
+        assert!(json.contains(r#""timestamp":"2024-03-01T00:02:00+00:00""#), "{}", json);
+        // 既にある duration_us は上書きしない
+        let Migrated::Upgraded(json) = migrate_line(V1_LOG.lines().nth(4).unwrap()) else { panic!() };
+        assert!(json.contains(r#""duration_us":20400"#) && json.contains("00:02:00.500+00:00"), "{}", json);
+
+        assert_eq!(migrate_line(V1_LOG.lines().nth(5).unwrap()), Migrated::Kept("unparsable timestamp"));
+        assert_eq!(migrate_line(V1_LOG.lines().nth(6).unwrap()), Migrated::Kept("unknown fields"));
+        assert_eq!(migrate_line("not json"), Migrated::Kept("not JSON"));
+        assert_eq!(migrate_line(V1_LOG.lines().nth(8).unwrap()), Migrated::Current);
+    }
+
+    #[test]
+    fn test_upgrade_is_atomic_idempotent_and_preserves_state() {
+        let dir = std::env::temp_dir().join("arc_upgrade_log_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("signals.jsonl");
+        fs::write(&file, V1_LOG).unwrap();
+        let before = FluxState::from_signals(&parse(V1_LOG));
+        assert_eq!(legacy_count(&parse(V1_LOG)), 7);
+
+        let report = upgrade(&file).unwrap();
+        assert_eq!((report.upgraded, report.current, report.kept_total()), (5, 1, 3));
+        assert_eq!(report.kept[&"unparsable timestamp"], 1);
+        assert!(!dir.join("signals.jsonl.upgrade").exists());
+
+        let content = fs::read_to_string(&file).unwrap();
+        assert_eq!(content.lines().count(), V1_LOG.lines().count());
+        // 移せない行は元のまま
+        assert!(content.contains("not json\n") && content.contains(r#""seq":7"#) && content.contains(r#""timestamp":"yesterday""#));
+        let after = FluxState::from_signals(&parse(&content));
+        assert_eq!(legacy_count(&parse(&content)), 2);
+
+        // 実行の再構成は変わらず、解釈できなかった時刻だけが減る
+        assert_eq!(after.signal_count, before.signal_count);
+        let (before_runs, after_runs) = (executions(&before), executions(&after));
+        assert_eq!(before_runs[0], after_runs[0]);
+        assert_eq!(before_runs[1]["duration_ms"], after_runs[1]["duration_ms"]);
+        assert!(before_runs[1]["started_at"].is_null());
+        assert_eq!(after_runs[1]["started_at"], "2024-03-01T00:02:00+00:00");
+        assert_eq!(before.anomalies.len(), 2);
+        assert!(after.anomalies.is_empty());
+
+        // 2 回目は何も変えない
+        let again = upgrade(&file).unwrap();
+        assert_eq!((again.upgraded, again.current, again.kept_total()), (0, 6, 3));
+        assert_eq!(fs::read_to_string(&file).unwrap(), content);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
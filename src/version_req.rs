@@ -0,0 +1,389 @@
+//! RubyGems 形式のバージョン要件 (`~> 3.1`, `>= 2.7, < 4`, `3.3.0`) の解釈と、複数の要件の共通部分。
+//!
+//! `arc bootstrap` が選んだ Ruby を、Gemfile の `ruby` 指定や Gem の `required_ruby_version` と照らし合わせるのに使う。
+//! バージョンの大小は `ruby_version::compare` に任せる (プレリリースはリリースより前)。
+//! `~>` は RubyGems と同じく、最後の区切りを 1 つ落として繰り上げた版を上限にする
+//! (`~> 3.1` は `>= 3.1, < 4`、`~> 3.1.2` は `>= 3.1.2, < 3.2`)。
+
+use anyhow::{Result, bail};
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::ruby_version::compare;
+
+// ─────────────────────────────────────────────
+// 型定義
+// ─────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    /// `~>`
+    Pessimistic,
+}
+
+/// 演算子 1 つとバージョン 1 つ (`>= 2.7`)。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    pub op: Op,
+    pub version: String,
+}
+
+/// すべてを満たす必要がある制約の並び (`>= 2.7, < 4`)。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Requirement {
+    pub constraints: Vec<Constraint>,
+}
+
+/// 範囲の端。`inclusive` なら端の版自体も含む
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bound {
+    pub version: String,
+    pub inclusive: bool,
+}
+
+// ─────────────────────────────────────────────
+// パース
+// ─────────────────────────────────────────────
+
+/// 長いものから順に試す (`>=` を `>` と読まないため)
+const OPS: [(&str, Op); 7] = [
+    ("~>", Op::Pessimistic),
+    (">=", Op::Ge),
+    ("<=", Op::Le),
+    ("!=", Op::Ne),
+    (">", Op::Gt),
+    ("<", Op::Lt),
+    ("=", Op::Eq),
+];
+
+impl Constraint {
+    /// `~> 3.1` / `>=2.7` / `3.3.0` (演算子なしは `=`) を解釈する。
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (op, rest) = OPS
+            .iter()
+            .find_map(|(symbol, op)| s.strip_prefix(symbol).map(|rest| (*op, rest)))
+            .unwrap_or((Op::Eq, s));
+        let version = rest.trim();
+        if !version.starts_with(|c: char| c.is_ascii_digit())
+            || !version.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        {
+            bail!("Invalid version requirement '{}'", s);
+        }
+        Ok(Self { op, version: version.to_string() })
+    }
+
+    pub fn satisfied_by(&self, version: &str) -> bool {
+        let ord = compare(version, &self.version);
+        match self.op {
+            Op::Eq => ord.is_eq(),
+            Op::Ne => ord.is_ne(),
+            Op::Gt => ord.is_gt(),
+            Op::Ge => ord.is_ge(),
+            Op::Lt => ord.is_lt(),
+            Op::Le => ord.is_le(),
+            // RubyGems と同じく、上限はプレリリースを除いた版で比べる (`4.0.0.preview1` は `~> 3.1` を満たさない)
+            Op::Pessimistic => ord.is_ge() && compare(&release(version), &bump(&self.version)).is_lt(),
+        }
+    }
+
+    /// この制約が与える下限 (`!=` と `<` 系はなし)
+    pub fn lower(&self) -> Option<Bound> {
+        let inclusive = match self.op {
+            Op::Ge | Op::Eq | Op::Pessimistic => true,
+            Op::Gt => false,
+            Op::Ne | Op::Lt | Op::Le => return None,
+        };
+        Some(Bound { version: self.version.clone(), inclusive })
+    }
+
+    /// この制約が与える上限 (`!=` と `>` 系はなし)
+    pub fn upper(&self) -> Option<Bound> {
+        match self.op {
+            Op::Le | Op::Eq => Some(Bound { version: self.version.clone(), inclusive: true }),
+            Op::Lt => Some(Bound { version: self.version.clone(), inclusive: false }),
+            Op::Pessimistic => Some(Bound { version: bump(&self.version), inclusive: false }),
+            Op::Ne | Op::Gt | Op::Ge => None,
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = OPS.iter().find(|(_, op)| *op == self.op).map_or("=", |(symbol, _)| symbol);
+        write!(f, "{} {}", symbol, self.version)
+    }
+}
+
+impl Requirement {
+    /// `>= 2.7, < 4` のようにカンマで区切った要件を解釈する。
+    pub fn parse(s: &str) -> Result<Self> {
+        Self::from_parts(&s.split(',').collect::<Vec<_>>())
+    }
+
+    /// Gemfile の `ruby ">= 3.1", "< 3.4"` や gemspec の配列のように、別々に書かれた制約をまとめる。
+    pub fn from_parts(parts: &[&str]) -> Result<Self> {
+        let constraints = parts
+            .iter()
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| Constraint::parse(p))
+            .collect::<Result<Vec<_>>>()?;
+        if constraints.is_empty() {
+            bail!("Empty version requirement");
+        }
+        Ok(Self { constraints })
+    }
+
+    pub fn satisfied_by(&self, version: &str) -> bool {
+        self.constraints.iter().all(|c| c.satisfied_by(version))
+    }
+
+    /// `version` が満たさない制約
+    pub fn violations(&self, version: &str) -> Vec<&Constraint> {
+        self.constraints.iter().filter(|c| !c.satisfied_by(version)).collect()
+    }
+
+    /// 両方を満たす要件 (同じ制約は 1 つにまとめる)
+    pub fn intersect(&self, other: &Requirement) -> Requirement {
+        let mut constraints = self.constraints.clone();
+        for c in &other.constraints {
+            if !constraints.contains(c) {
+                constraints.push(c.clone());
+            }
+        }
+        Requirement { constraints }
+    }
+
+    /// もっとも厳しい下限
+    pub fn lower(&self) -> Option<Bound> {
+        self.constraints.iter().filter_map(Constraint::lower).reduce(tighter_lower)
+    }
+
+    /// 同時には満たせない制約の組。範囲は 1 次元なので、組ごとに調べれば全体が満たせるか分かる
+    pub fn conflict(&self) -> Option<(&Constraint, &Constraint)> {
+        let cs = &self.constraints;
+        (0..cs.len())
+            .flat_map(|i| (i + 1..cs.len()).map(move |j| (i, j)))
+            .find(|&(i, j)| !pair_satisfiable(&cs[i], &cs[j]))
+            .map(|(i, j)| (&cs[i], &cs[j]))
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.constraints.iter().map(Constraint::to_string).collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", if self.inclusive { ">=" } else { ">" }, self.version)
+    }
+}
+
+// ─────────────────────────────────────────────
+// 範囲の計算
+// ─────────────────────────────────────────────
+
+/// プレリリースを除いた数値部分 (`3.4.0.preview1` → `3.4.0`)
+fn release(version: &str) -> String {
+    let version = version.trim().trim_start_matches("ruby-");
+    let core = version.split('-').next().unwrap_or(version);
+    let numbers: Vec<&str> = core.split('.').take_while(|s| s.parse::<u64>().is_ok()).collect();
+    numbers.join(".")
+}
+
+/// `~>` の上限。最後の区切りを落として (1 つしかなければそのまま) 繰り上げる (`3.1.2` → `3.2`, `3` → `4`)
+fn bump(version: &str) -> String {
+    let mut numbers: Vec<u64> = release(version).split('.').filter_map(|s| s.parse().ok()).collect();
+    if numbers.len() > 1 {
+        numbers.pop();
+    }
+    if let Some(last) = numbers.last_mut() {
+        *last += 1;
+    }
+    numbers.iter().map(u64::to_string).collect::<Vec<_>>().join(".")
+}
+
+fn tighter_lower(a: Bound, b: Bound) -> Bound {
+    match compare(&a.version, &b.version) {
+        Ordering::Greater => a,
+        Ordering::Less => b,
+        Ordering::Equal => Bound { inclusive: a.inclusive && b.inclusive, ..a },
+    }
+}
+
+fn tighter_upper(a: Bound, b: Bound) -> Bound {
+    match compare(&a.version, &b.version) {
+        Ordering::Less => a,
+        Ordering::Greater => b,
+        Ordering::Equal => Bound { inclusive: a.inclusive && b.inclusive, ..a },
+    }
+}
+
+/// 下限と上限の間に版が残るか
+fn non_empty(lower: Option<&Bound>, upper: Option<&Bound>) -> bool {
+    let (Some(lower), Some(upper)) = (lower, upper) else { return true };
+    match compare(&lower.version, &upper.version) {
+        Ordering::Less => true,
+        Ordering::Equal => lower.inclusive && upper.inclusive,
+        Ordering::Greater => false,
+    }
+}
+
+fn pair_satisfiable(a: &Constraint, b: &Constraint) -> bool {
+    // `!=` が外せるのは、もう一方が同じ版ちょうどしか許さない場合だけ
+    match (a.op, b.op) {
+        (Op::Ne, Op::Ne) => return true,
+        (Op::Ne, _) => return b.op != Op::Eq || compare(&a.version, &b.version).is_ne(),
+        (_, Op::Ne) => return pair_satisfiable(b, a),
+        _ => {}
+    }
+    let lower = [a.lower(), b.lower()].into_iter().flatten().reduce(tighter_lower);
+    let upper = [a.upper(), b.upper()].into_iter().flatten().reduce(tighter_upper);
+    non_empty(lower.as_ref(), upper.as_ref())
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(s: &str) -> Requirement {
+        Requirement::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        let c = Constraint::parse(">=2.7").unwrap();
+        assert_eq!((c.op, c.version.as_str()), (Op::Ge, "2.7"));
+        assert_eq!(Constraint::parse("3.3.0").unwrap().op, Op::Eq);
+        assert_eq!(Constraint::parse("= 3.3.0").unwrap().op, Op::Eq);
+        assert_eq!(Constraint::parse("!= 3.2.1").unwrap().op, Op::Ne);
+        assert_eq!(Constraint::parse("> 3").unwrap().op, Op::Gt);
+        assert_eq!(Constraint::parse("<= 3.4").unwrap().op, Op::Le);
+        assert_eq!(Constraint::parse(" ~> 3.1 ").unwrap().op, Op::Pessimistic);
+        assert_eq!(Constraint::parse("< 3.4.dev").unwrap().version, "3.4.dev");
+        assert!(Constraint::parse(">= ").is_err());
+        assert!(Constraint::parse("=> 3.1").is_err());
+        assert!(Constraint::parse("latest").is_err());
+        assert!(Requirement::parse(" , ").is_err());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let r = req(">=2.7,<4, ~>3.1");
+        assert_eq!(r.to_string(), ">= 2.7, < 4, ~> 3.1");
+        assert_eq!(req(&r.to_string()), r);
+        assert_eq!(req("3.3.0").to_string(), "= 3.3.0");
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        assert!(req(">= 3.1").satisfied_by("3.1.0"));
+        assert!(req(">= 3.1").satisfied_by("3.3.6"));
+        assert!(!req(">= 3.1").satisfied_by("3.0.7"));
+        assert!(!req("> 3.1").satisfied_by("3.1"));
+        assert!(req("< 3.4").satisfied_by("3.3.9"));
+        assert!(!req("< 3.4").satisfied_by("3.4.0"));
+        assert!(req("<= 3.4").satisfied_by("3.4.0"));
+        assert!(req("= 3.3").satisfied_by("3.3.0"));
+        assert!(!req("!= 3.2.1").satisfied_by("3.2.1"));
+        assert!(req("!= 3.2.1").satisfied_by("3.2.2"));
+    }
+
+    #[test]
+    fn test_pessimistic_operator() {
+        // ~> 3.1 は >= 3.1, < 4
+        assert!(req("~> 3.1").satisfied_by("3.1.0"));
+        assert!(req("~> 3.1").satisfied_by("3.9.9"));
+        assert!(!req("~> 3.1").satisfied_by("4.0.0"));
+        assert!(!req("~> 3.1").satisfied_by("3.0.9"));
+        // ~> 3.1.2 は >= 3.1.2, < 3.2
+        assert!(req("~> 3.1.2").satisfied_by("3.1.7"));
+        assert!(!req("~> 3.1.2").satisfied_by("3.1.1"));
+        assert!(!req("~> 3.1.2").satisfied_by("3.2.0"));
+        // 区切りが 1 つだけなら繰り上げる
+        assert!(req("~> 3").satisfied_by("3.4.1"));
+        assert!(!req("~> 3").satisfied_by("4.0"));
+        // 上限はプレリリースを除いて比べる
+        assert!(!req("~> 3.1").satisfied_by("4.0.0.preview1"));
+        assert!(!req("~> 3.4.0").satisfied_by("3.4.0.preview1"));
+    }
+
+    #[test]
+    fn test_prerelease_ordering() {
+        // gemspec によくある `< 3.4.dev` は 3.4 のプレリリースも除く
+        assert!(req(">= 2.7, < 3.4.dev").satisfied_by("3.3.6"));
+        assert!(!req(">= 2.7, < 3.4.dev").satisfied_by("3.4.0"));
+        assert!(!req(">= 2.7, < 3.4.dev").satisfied_by("3.4.0-preview2"));
+        assert!(!req(">= 3.4").satisfied_by("3.4.0-rc1"));
+        assert!(req(">= 3.4.0.preview1").satisfied_by("3.4.0-rc1"));
+    }
+
+    #[test]
+    fn test_compound_requirements_and_violations() {
+        let r = req(">= 3.1, < 3.4, != 3.2.0");
+        assert!(r.satisfied_by("3.3.6"));
+        assert!(!r.satisfied_by("3.2.0"));
+        let violations: Vec<String> = r.violations("3.4.1").iter().map(|c| c.to_string()).collect();
+        assert_eq!(violations, vec!["< 3.4"]);
+        let violations: Vec<String> = r.violations("3.0.0").iter().map(|c| c.to_string()).collect();
+        assert_eq!(violations, vec![">= 3.1"]);
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let r = Requirement::from_parts(&[">= 3.1", "< 3.4"]).unwrap();
+        assert_eq!(r.to_string(), ">= 3.1, < 3.4");
+        assert!(Requirement::from_parts(&[]).is_err());
+    }
+
+    #[test]
+    fn test_bounds() {
+        let r = req("~> 3.1.2, >= 3.1.4");
+        assert_eq!(r.lower(), Some(Bound { version: "3.1.4".to_string(), inclusive: true }));
+        assert_eq!(r.constraints[0].upper(), Some(Bound { version: "3.2".to_string(), inclusive: false }));
+        assert_eq!(req(">= 3.1, > 3.1").lower().unwrap().to_string(), "> 3.1");
+        assert_eq!(Constraint::parse("~> 3").unwrap().upper().unwrap().version, "4");
+        assert_eq!(req("!= 3.0").lower(), None);
+        assert_eq!(Constraint::parse(">= 2.7").unwrap().upper(), None);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let gemfile = req("~> 3.2");
+        let gems = req(">= 3.1");
+        let both = gemfile.intersect(&gems).intersect(&req("~> 3.2"));
+        assert_eq!(both.to_string(), "~> 3.2, >= 3.1");
+        assert!(both.conflict().is_none());
+        assert!(both.satisfied_by("3.3.0"));
+        assert!(!both.satisfied_by("3.1.9"));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let r = req("~> 3.1.0").intersect(&req(">= 3.2"));
+        let (a, b) = r.conflict().unwrap();
+        assert_eq!((a.to_string(), b.to_string()), ("~> 3.1.0".to_string(), ">= 3.2".to_string()));
+        assert!(req(">= 3.4, < 3.4").conflict().is_some());
+        assert!(req(">= 3.4, <= 3.4").conflict().is_none());
+        assert!(req("> 3.4, <= 3.4").conflict().is_some());
+        assert!(req("= 3.3.0, != 3.3.0").conflict().is_some());
+        assert!(req("= 3.3.0, != 3.3.1").conflict().is_none());
+        assert!(req("!= 3.3.0, >= 3.3.0").conflict().is_none());
+        assert!(req("= 3.2.0, ~> 3.3").conflict().is_some());
+        assert!(req(">= 2.7, < 3.4.dev, ~> 3.3").conflict().is_none());
+        assert!(req(">= 3.4, < 3.4.dev").conflict().is_some());
+    }
+}
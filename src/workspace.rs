@@ -0,0 +1,171 @@
+//! `.arc/workspace.toml` — 1 つのリポジトリに置いた複数の arc プロジェクト (モノレポ) をまとめて扱う。
+//!
+//! ```toml
+//! members = ["services/*", "tools/cli"]
+//! ```
+//!
+//! `members` はワークスペースのルートからの相対パス。パスの各要素に `*` / `?` の glob を書ける。
+//! glob に一致しても `.flux/` のないディレクトリはメンバーにしない。明示したパスに `.flux/` がなければエラー。
+//! 各メンバーは自分の Signal ログを持つ。
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::signals::FLUX_DIR;
+use crate::state::glob_match;
+
+/// ワークスペースの定義ファイル (ルートからの相対パス)
+pub const WORKSPACE_FILE: &str = ".arc/workspace.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkspaceFile {
+    #[serde(default)]
+    members: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workspace {
+    pub root: PathBuf,
+    /// メンバーのディレクトリ (ルートからの相対パスの順)
+    pub members: Vec<PathBuf>,
+}
+
+impl Workspace {
+    /// `start` から親へ `.arc/workspace.toml` を探して読み込む。
+    pub fn find(start: &Path) -> Result<Self> {
+        let Some(root) = start.ancestors().find(|dir| dir.join(WORKSPACE_FILE).is_file()) else {
+            bail!("{} が見つかりません ({:?} とその親ディレクトリ)", WORKSPACE_FILE, start);
+        };
+        Self::load(root)
+    }
+
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(WORKSPACE_FILE);
+        let content = fs::read_to_string(&path).with_context(|| format!("{:?} を読み込めません", path))?;
+        let file: WorkspaceFile = toml::from_str(&content).with_context(|| format!("{:?} の形式が不正です", path))?;
+        let mut members = Vec::new();
+        for pattern in &file.members {
+            members.extend(expand_member(root, pattern)?);
+        }
+        members.sort();
+        members.dedup();
+        if members.is_empty() {
+            bail!("{:?} にメンバーがありません (members = [\"services/*\"] のように指定してください)", path);
+        }
+        Ok(Self { root: root.to_path_buf(), members })
+    }
+
+    /// メンバーの表示名 (ルートからの相対パス)
+    pub fn name(&self, member: &Path) -> String {
+        member.strip_prefix(&self.root).unwrap_or(member).display().to_string()
+    }
+
+    /// ルート自体が arc プロジェクトか (ワークスペースの操作を記録する)
+    pub fn root_is_project(&self) -> bool {
+        self.root.join(FLUX_DIR).is_dir()
+    }
+}
+
+/// `members` の 1 要素を、`.flux/` を持つディレクトリの一覧に展開する。
+fn expand_member(root: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let relative = Path::new(pattern);
+    if relative.is_absolute() || relative.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+        bail!("ワークスペースのメンバーはルートの中の相対パスで指定してください: {:?}", pattern);
+    }
+    if !pattern.contains(['*', '?']) {
+        let dir = root.join(relative);
+        if !dir.join(FLUX_DIR).is_dir() {
+            bail!("ワークスペースのメンバー {:?} は arc プロジェクトではありません ({:?} がありません)", pattern, dir.join(FLUX_DIR));
+        }
+        return Ok(vec![dir]);
+    }
+
+    let mut dirs = vec![root.to_path_buf()];
+    for segment in relative.iter().filter_map(|s| s.to_str()) {
+        let mut next = Vec::new();
+        for dir in &dirs {
+            if !segment.contains(['*', '?']) {
+                next.push(dir.join(segment));
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(name) = name.to_str() else { continue };
+                // `*` は隠しディレクトリに一致しない
+                if !name.starts_with('.') && entry.path().is_dir() && glob_match(segment.as_bytes(), name.as_bytes()) {
+                    next.push(entry.path());
+                }
+            }
+        }
+        dirs = next;
+    }
+    dirs.retain(|dir| dir.join(FLUX_DIR).is_dir());
+    Ok(dirs)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// services/{api,web,worker} (worker は arc プロジェクトではない) と tools/cli を持つワークスペース
+    pub(crate) fn fixture(name: &str, members: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        for member in ["services/api", "services/web", "tools/cli"] {
+            fs::create_dir_all(root.join(member).join(FLUX_DIR)).unwrap();
+        }
+        fs::create_dir_all(root.join("services/worker")).unwrap();
+        fs::create_dir_all(root.join("services/.hidden").join(FLUX_DIR)).unwrap();
+        fs::create_dir_all(root.join(".arc")).unwrap();
+        fs::write(root.join(WORKSPACE_FILE), format!("members = {}\n", members)).unwrap();
+        root
+    }
+
+    fn names(ws: &Workspace) -> Vec<String> {
+        ws.members.iter().map(|m| ws.name(m)).collect()
+    }
+
+    #[test]
+    fn test_glob_members_skip_non_projects() {
+        let root = fixture("arc_ws_glob_test", r#"["services/*", "tools/cli", "services/api"]"#);
+        let ws = Workspace::load(&root).unwrap();
+        assert_eq!(names(&ws), ["services/api", "services/web", "tools/cli"]);
+        assert!(!ws.root_is_project());
+
+        fs::write(root.join(WORKSPACE_FILE), "members = [\"*/c?i\", \"s*/w*\"]\n").unwrap();
+        assert_eq!(names(&Workspace::load(&root).unwrap()), ["services/web", "tools/cli"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_find_from_member_dir() {
+        let root = fixture("arc_ws_find_test", r#"["services/*"]"#);
+        let ws = Workspace::find(&root.join("services/api")).unwrap();
+        assert_eq!(ws.root, root);
+        assert!(Workspace::find(&std::env::temp_dir().join("arc_ws_find_missing")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_members() {
+        let root = fixture("arc_ws_invalid_test", r#"["services/worker"]"#);
+        let err = Workspace::load(&root).unwrap_err().to_string();
+        assert!(err.contains("services/worker") && err.contains("arc プロジェクトではありません"), "{}", err);
+
+        fs::write(root.join(WORKSPACE_FILE), "members = [\"../elsewhere\"]\n").unwrap();
+        assert!(Workspace::load(&root).unwrap_err().to_string().contains("相対パス"));
+        fs::write(root.join(WORKSPACE_FILE), "members = [\"nothing/*\"]\n").unwrap();
+        assert!(Workspace::load(&root).unwrap_err().to_string().contains("メンバーがありません"));
+        fs::write(root.join(WORKSPACE_FILE), "member = []\n").unwrap();
+        assert!(Workspace::load(&root).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
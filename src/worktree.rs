@@ -0,0 +1,255 @@
+//! git worktree 間での `ruby_runtime` (と Gem) の共有 (`[env] share_runtime = "per-repo"`)。
+//!
+//! worktree ごとに `.arc/env/ruby_runtime` を配置すると、同じプロジェクトなのにリンクの森と
+//! Gem のインストールが worktree の数だけ増える。per-repo のときは、リポジトリの git common dir
+//! (メインの worktree の `.git`) ごとの共有ディレクトリに 1 つだけ配置し、各 worktree の
+//! `.arc/env/ruby_runtime` はそこへのシンボリックリンクにする。
+//!
+//! ```text
+//! ~/.arc/worktrees/<common dir のハッシュ>/
+//!   ruby_runtime/<ruby id>/   ← 各 worktree の .arc/env/ruby_runtime のリンク先
+//!   ruby/                     ← share_gems のときの .arc/env/ruby のリンク先
+//! ```
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::blobs::sha256_hex;
+use crate::config::{EnvConfig, ShareRuntime};
+use crate::link::{self, LinkMode, LinkReport};
+use crate::perms;
+
+/// グローバルな共有ディレクトリの親 (`~/.arc/worktrees`)
+pub const SHARED_ROOT: &str = "worktrees";
+/// 共有ディレクトリのハッシュの長さ
+const KEY_LEN: usize = 16;
+
+// ─────────────────────────────────────────────
+// git worktree の検出
+// ─────────────────────────────────────────────
+
+/// `start` を含むリポジトリの git common dir (すべての worktree が共有する `.git`)。
+/// メインの worktree では `.git` ディレクトリそのもの、追加の worktree では `.git` ファイルの
+/// `gitdir:` の先にある `commondir` を辿る。git の管理下でなければ `None`。
+pub fn git_common_dir(start: &Path) -> Option<PathBuf> {
+    let dot_git = start.ancestors().map(|dir| dir.join(".git")).find(|p| fs::symlink_metadata(p).is_ok())?;
+    if dot_git.is_dir() {
+        return fs::canonicalize(dot_git).ok();
+    }
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let gitdir = content.lines().find_map(|l| l.strip_prefix("gitdir:"))?.trim();
+    let gitdir = dot_git.parent()?.join(gitdir);
+    let common = match fs::read_to_string(gitdir.join("commondir")) {
+        Ok(rel) => gitdir.join(rel.trim()),
+        Err(_) => gitdir,
+    };
+    fs::canonicalize(common).ok()
+}
+
+/// リポジトリのすべての worktree のルート (メインの worktree が先頭)。
+pub fn worktree_roots(common_dir: &Path) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = common_dir
+        .parent()
+        .filter(|_| common_dir.file_name().is_some_and(|n| n == ".git"))
+        .map(Path::to_path_buf)
+        .into_iter()
+        .collect();
+    if let Ok(entries) = fs::read_dir(common_dir.join("worktrees")) {
+        let mut linked: Vec<PathBuf> = entries
+            .flatten()
+            .filter_map(|e| fs::read_to_string(e.path().join("gitdir")).ok())
+            .filter_map(|gitdir| PathBuf::from(gitdir.trim()).parent().map(Path::to_path_buf))
+            .filter_map(|root| fs::canonicalize(root).ok())
+            .collect();
+        linked.sort();
+        roots.extend(linked);
+    }
+    roots
+}
+
+// ─────────────────────────────────────────────
+// 共有ディレクトリ
+// ─────────────────────────────────────────────
+
+/// `cwd` のプロジェクトが使う共有ディレクトリ。per-project、または per-repo でも git の管理下に
+/// なく `shared_dir` も指定されていなければ `None` (従来どおり worktree ごとに配置する)。
+pub fn shared_dir(config: &EnvConfig, cwd: &Path, arc_root: &Path) -> Option<PathBuf> {
+    if config.share_runtime != ShareRuntime::PerRepo {
+        return None;
+    }
+    if let Some(dir) = &config.shared_dir {
+        return Some(cwd.join(dir));
+    }
+    let common = git_common_dir(cwd)?;
+    let key = sha256_hex(common.to_string_lossy().as_bytes());
+    Some(arc_root.join(SHARED_ROOT).join(&key[..KEY_LEN]))
+}
+
+/// 共有された Ruby 実行環境の配置先。Ruby のバージョンごとに分ける。
+pub fn shared_runtime(shared: &Path, ruby_id: &str) -> PathBuf {
+    shared.join("ruby_runtime").join(ruby_id)
+}
+
+/// `dest` (`.arc/env/ruby_runtime`) に Ruby 実行環境を配置する。
+///
+/// `shared` がなければ従来どおり `cache_dir` から `dest` へ配置する。ある場合は共有先に
+/// まだなければそこへ配置し、`dest` は共有先へのシンボリックリンクにする。既に共有先に
+/// あれば (他の worktree が配置済み) 何も配置せず `None` を返す。
+pub fn place_runtime(cache_dir: &Path, dest: &Path, shared: Option<&Path>, mode: LinkMode) -> Result<Option<LinkReport>> {
+    let parent = dest.parent().context("ruby_runtime の親ディレクトリが取得できません")?;
+    perms::create_dir_all(parent)?;
+    let Some(shared) = shared else {
+        return Ok(Some(link::link_tree(cache_dir, dest, mode)?));
+    };
+
+    let report = if shared.exists() {
+        None
+    } else {
+        perms::create_dir_all(shared.parent().context("共有先の親ディレクトリが取得できません")?)?;
+        Some(link::link_tree(cache_dir, shared, mode)?)
+    };
+    std::os::unix::fs::symlink(shared, dest)
+        .with_context(|| format!("Failed to link {:?} to {:?}", dest, shared))?;
+    Ok(report)
+}
+
+/// `gem_dir` (`.arc/env/ruby`) を共有先の `ruby/` へのシンボリックリンクにする。
+/// 既に実体のディレクトリがある場合は移動せず `false` を返す。
+pub fn share_gem_dir(gem_dir: &Path, shared: &Path) -> Result<bool> {
+    if fs::symlink_metadata(gem_dir).is_ok() {
+        return Ok(fs::read_link(gem_dir).is_ok_and(|t| t == shared.join("ruby")));
+    }
+    perms::create_dir_all(&shared.join("ruby"))?;
+    perms::create_dir_all(gem_dir.parent().context("Gem ディレクトリの親が取得できません")?)?;
+    std::os::unix::fs::symlink(shared.join("ruby"), gem_dir)
+        .with_context(|| format!("Failed to link {:?}", gem_dir))?;
+    Ok(true)
+}
+
+/// `root` 以外の worktree のうち、`runtime` (共有された実行環境) を参照しているもの。
+pub fn siblings_sharing(root: &Path, runtime: &Path, env_dir_name: &str) -> Vec<PathBuf> {
+    let Some(common) = git_common_dir(root) else { return vec![] };
+    let Ok(runtime) = fs::canonicalize(runtime) else { return vec![] };
+    let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    worktree_roots(&common)
+        .into_iter()
+        .filter(|wt| *wt != root)
+        .filter(|wt| fs::canonicalize(wt.join(env_dir_name).join("ruby_runtime")).is_ok_and(|t| t == runtime))
+        .collect()
+}
+
+/// 共有された実行環境を削除する前の警告。共有している worktree がなければ `None`。
+pub fn sharing_warning(siblings: &[PathBuf]) -> Option<String> {
+    if siblings.is_empty() {
+        return None;
+    }
+    let mut warning = format!(
+        "⚠️  ruby_runtime is shared with {} other worktree(s); removing it breaks them until they run `arc bootstrap` again:",
+        siblings.len()
+    );
+    for sibling in siblings {
+        warning.push_str(&format!("\n     {}", sibling.display()));
+    }
+    Some(warning)
+}
+
+// ─────────────────────────────────────────────
+// テスト
+// ─────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::ARC_ENV_DIR;
+
+    /// メインの worktree `main` と、`git worktree add ../feature` 相当の `feature` を作る。
+    fn repo(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let main = root.join("main");
+        let feature = root.join("feature");
+        let admin = main.join(".git/worktrees/feature");
+        fs::create_dir_all(&admin).unwrap();
+        fs::create_dir_all(feature.join("app")).unwrap();
+        fs::write(admin.join("commondir"), "../..\n").unwrap();
+        fs::write(admin.join("gitdir"), format!("{}\n", feature.join(".git").display())).unwrap();
+        fs::write(feature.join(".git"), format!("gitdir: {}\n", admin.display())).unwrap();
+        let root = fs::canonicalize(&root).unwrap();
+        (root.clone(), root.join("main"), root.join("feature"))
+    }
+
+    fn env_config(share: ShareRuntime) -> EnvConfig {
+        EnvConfig { share_runtime: share, ..Default::default() }
+    }
+
+    #[test]
+    fn test_git_common_dir_across_worktrees() {
+        let (root, main, feature) = repo("arc_worktree_common_test");
+        let common = main.join(".git");
+        assert_eq!(git_common_dir(&main).as_deref(), Some(common.as_path()));
+        assert_eq!(git_common_dir(&feature.join("app")).as_deref(), Some(common.as_path()));
+        assert_eq!(worktree_roots(&common), [main.clone(), feature.clone()]);
+
+        let arc_root = root.join("home/.arc");
+        let per_repo = env_config(ShareRuntime::PerRepo);
+        let shared = shared_dir(&per_repo, &main, &arc_root).unwrap();
+        assert_eq!(shared_dir(&per_repo, &feature, &arc_root), Some(shared.clone()));
+        assert!(shared.starts_with(arc_root.join(SHARED_ROOT)));
+        assert_eq!(shared_dir(&env_config(ShareRuntime::PerProject), &main, &arc_root), None);
+        // git の管理下でなければ共有しない
+        assert_eq!(shared_dir(&per_repo, &root.join("elsewhere"), &arc_root), None);
+        let explicit = EnvConfig { shared_dir: Some("../shared".into()), ..per_repo };
+        assert_eq!(shared_dir(&explicit, &feature, &arc_root), Some(feature.join("../shared")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_second_worktree_links_shared_runtime() {
+        let (root, main, feature) = repo("arc_worktree_share_test");
+        let cache = root.join("cache/ruby-3.3.6");
+        fs::create_dir_all(cache.join("bin")).unwrap();
+        fs::write(cache.join("bin/ruby"), "#!/bin/sh\n").unwrap();
+        let shared = shared_runtime(&root.join("shared"), "ruby-3.3.6");
+        let dest = |wt: &Path| wt.join(ARC_ENV_DIR).join("ruby_runtime");
+
+        let first = place_runtime(&cache, &dest(&main), Some(&shared), LinkMode::Copy).unwrap();
+        assert_eq!(first.map(|r| r.total()), Some(1));
+        let second = place_runtime(&cache, &dest(&feature), Some(&shared), LinkMode::Copy).unwrap();
+        assert!(second.is_none(), "the second worktree must link, not copy");
+        for wt in [&main, &feature] {
+            assert_eq!(fs::read_link(dest(wt)).unwrap(), shared);
+            assert!(dest(wt).join("bin/ruby").exists());
+        }
+
+        // 共有先への Gem ディレクトリ
+        let gems = root.join("shared");
+        assert!(share_gem_dir(&main.join(ARC_ENV_DIR).join("ruby"), &gems).unwrap());
+        assert!(share_gem_dir(&feature.join(ARC_ENV_DIR).join("ruby"), &gems).unwrap());
+        fs::write(main.join(ARC_ENV_DIR).join("ruby/marker"), "").unwrap();
+        assert!(feature.join(ARC_ENV_DIR).join("ruby/marker").exists());
+        fs::create_dir_all(root.join("solo").join(ARC_ENV_DIR).join("ruby")).unwrap();
+        assert!(!share_gem_dir(&root.join("solo").join(ARC_ENV_DIR).join("ruby"), &gems).unwrap());
+
+        // 片方から削除しようとすると、もう片方が警告に挙がる
+        let siblings = siblings_sharing(&main, &dest(&main), ARC_ENV_DIR);
+        assert_eq!(siblings, [feature.as_path()]);
+        let warning = sharing_warning(&siblings).unwrap();
+        assert!(warning.contains("1 other worktree") && warning.contains(&feature.display().to_string()));
+        assert_eq!(siblings_sharing(&feature, &dest(&feature), ARC_ENV_DIR), [main.as_path()]);
+        assert_eq!(sharing_warning(&[]), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_per_project_places_tree() {
+        let root = std::env::temp_dir().join("arc_worktree_per_project_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("cache/bin")).unwrap();
+        fs::write(root.join("cache/bin/ruby"), "").unwrap();
+        let dest = root.join("proj").join(ARC_ENV_DIR).join("ruby_runtime");
+        place_runtime(&root.join("cache"), &dest, None, LinkMode::Copy).unwrap();
+        assert!(fs::symlink_metadata(&dest).unwrap().is_dir());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}